@@ -0,0 +1,191 @@
+//! Export of the symbol database as JSON, for tools without Rust bindings.
+//!
+//! The output is one object in the schema below. Within a `version`, fields
+//! may be added but are never removed or changed in meaning.
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "types": [{
+//!     "path": "/obj/item",            "/" for the root, which holds globals
+//!     "parent": "/obj",               the parent_type, or null for the root
+//!     "children": ["/obj/item/tool"], types whose parent_type this is
+//!     "location": LOCATION,
+//!     "docs": "...",                  or null if undocumented
+//!     "vars": [{
+//!       "name": "force",
+//!       "declared": true,             false if overriding a parent's var
+//!       "type": "/obj",               the declared type, or null
+//!       "static": false,
+//!       "const": false,
+//!       "tmp": false,
+//!       "value": "5",                 the constant default as DM source, or
+//!                                     null if it is not constant
+//!       "location": LOCATION
+//!     }],
+//!     "procs": [{
+//!       "name": "attack",
+//!       "kind": "proc",               "proc", "verb", or "override"
+//!       "parameters": [{
+//!         "name": "target",
+//!         "type": "/mob",             or null
+//!         "default": "null"           DM source, or null if none
+//!       }],
+//!       "docs": "...",
+//!       "location": LOCATION
+//!     }]
+//!   }]
+//! }
+//! ```
+//!
+//! Each `LOCATION` is `{"file": "code/item.dm", "line": 1, "column": 1}`,
+//! or null for builtins. A type or var appears once per type; a proc
+//! appears once for each definition on that type.
+use std::collections::BTreeMap;
+
+use dm::objtree::{ObjectTree, TypeRef, ProcKind};
+use dm::Location;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+pub struct Symbols {
+    version: u32,
+    types: Vec<Type>,
+}
+
+#[derive(Serialize)]
+struct SourceLocation {
+    file: String,
+    line: u32,
+    column: u16,
+}
+
+#[derive(Serialize)]
+struct Type {
+    path: String,
+    parent: Option<String>,
+    children: Vec<String>,
+    location: Option<SourceLocation>,
+    docs: Option<String>,
+    vars: Vec<Var>,
+    procs: Vec<Proc>,
+}
+
+#[derive(Serialize)]
+struct Var {
+    name: String,
+    declared: bool,
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    #[serde(rename = "static")]
+    is_static: bool,
+    #[serde(rename = "const")]
+    is_const: bool,
+    #[serde(rename = "tmp")]
+    is_tmp: bool,
+    value: Option<String>,
+    location: Option<SourceLocation>,
+}
+
+#[derive(Serialize)]
+struct Proc {
+    name: String,
+    kind: &'static str,
+    parameters: Vec<Parameter>,
+    docs: Option<String>,
+    location: Option<SourceLocation>,
+}
+
+#[derive(Serialize)]
+struct Parameter {
+    name: String,
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    default: Option<String>,
+}
+
+fn type_path(path: &[String]) -> Option<String> {
+    if path.is_empty() {
+        None
+    } else {
+        Some(format!("/{}", path.join("/")))
+    }
+}
+
+fn type_name(ty: &TypeRef) -> String {
+    if ty.is_root() {
+        "/".to_owned()
+    } else {
+        ty.get().path.clone()
+    }
+}
+
+/// Collect every type in the tree, the root first and the rest by path.
+pub fn export(context: &dm::Context, tree: &ObjectTree) -> Symbols {
+    let location = |location: Location| if location.is_builtins() {
+        None
+    } else {
+        Some(SourceLocation {
+            file: context.file_path(location.file).display().to_string().replace('\\', "/"),
+            line: location.line,
+            column: location.column,
+        })
+    };
+    let docs = |docs: &dm::docs::DocCollection| if docs.is_empty() {
+        None
+    } else {
+        Some(docs.text())
+    };
+
+    let mut all: Vec<TypeRef> = vec![tree.root()];
+    all.extend(tree.types.keys().filter_map(|path| tree.find(path)).filter(|ty| !ty.is_root()));
+
+    let mut children: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for ty in all.iter() {
+        if let Some(parent) = ty.parent_type() {
+            children.entry(&parent.get().path).or_insert_with(Vec::new).push(type_name(ty));
+        }
+    }
+
+    let types = all.iter().map(|ty| Type {
+        path: type_name(ty),
+        parent: ty.parent_type().map(|parent| type_name(&parent)),
+        children: children.get(ty.get().path.as_str()).cloned().unwrap_or_default(),
+        location: location(ty.get().location),
+        docs: docs(&ty.get().docs),
+        vars: ty.get().vars.iter().map(|(name, var)| {
+            let decl = var.declaration.as_ref();
+            Var {
+                name: name.clone(),
+                declared: decl.is_some(),
+                type_: decl.and_then(|decl| type_path(&decl.var_type.type_path)),
+                is_static: decl.map_or(false, |decl| decl.var_type.is_static),
+                is_const: decl.map_or(false, |decl| decl.var_type.is_const),
+                is_tmp: decl.map_or(false, |decl| decl.var_type.is_tmp),
+                value: var.value.constant.as_ref().map(|constant| constant.to_string()),
+                location: location(var.value.location),
+            }
+        }).collect(),
+        procs: ty.get().procs.iter().flat_map(|(name, proc)| proc.value.iter().map(move |value| (name, value))).map(|(name, value)| Proc {
+            name: name.clone(),
+            kind: match value.kind {
+                ProcKind::Proc => "proc",
+                ProcKind::Verb => "verb",
+                ProcKind::Override => "override",
+            },
+            parameters: value.parameters.iter().map(|param| Parameter {
+                name: param.name.clone(),
+                type_: type_path(&param.path),
+                default: param.default.as_ref().map(|expr| expr.to_string()),
+            }).collect(),
+            docs: docs(&value.docs),
+            location: location(value.location),
+        }).collect(),
+    }).collect();
+
+    Symbols {
+        version: SCHEMA_VERSION,
+        types,
+    }
+}
@@ -1,6 +1,7 @@
 //! CLI tools, including a map renderer, using the same backend as the editor.
 #![forbid(unsafe_code)]
 
+extern crate atty;
 extern crate rayon;
 extern crate structopt;
 #[macro_use] extern crate structopt_derive;
@@ -19,6 +20,8 @@ use std::sync::atomic::{AtomicIsize, Ordering};
 
 use structopt::StructOpt;
 
+mod export;
+
 use dm::objtree::ObjectTree;
 use dmm_tools::*;
 
@@ -34,7 +37,7 @@ fn main() {
         .get_matches());
 
     let mut context = Context::default();
-    context.dm_context.set_print_severity(Some(dm::Severity::Error));
+    context.dm_context = dm_context(&opt);
     rayon::ThreadPoolBuilder::new()
         .num_threads(opt.jobs)
         .build_global()
@@ -51,6 +54,8 @@ struct Context {
     dm_context: dm::Context,
     objtree: ObjectTree,
     icon_cache: icon_cache::IconCache,
+    defines: Option<dm::preprocessor::DefineMap>,
+    environment: Option<std::path::PathBuf>,
     exit_status: AtomicIsize,
     parallel: bool,
     procs: bool,
@@ -58,36 +63,69 @@ struct Context {
 
 impl Context {
     fn objtree(&mut self, opt: &Opt) {
-        let pathbuf;
-        let environment: &std::path::Path = match opt.environment {
-            Some(ref env) => env.as_ref(),
-            None => match dm::detect_environment_default() {
-                Ok(Some(found)) => {
-                    pathbuf = found;
-                    &pathbuf
-                }
-                _ => dm::DEFAULT_ENV.as_ref(),
-            },
-        };
-        println!("parsing {}", environment.display());
-
+        let environment = environment(opt);
         if let Some(parent) = environment.parent() {
             self.icon_cache.set_icons_root(&parent);
+            dm::config::Config::load(parent).apply(&self.dm_context);
         }
 
-        let pp = match dm::preprocessor::Preprocessor::new(&self.dm_context, environment.to_owned()) {
-            Ok(pp) => pp,
-            Err(e) => {
-                eprintln!("i/o error opening environment:\n{}", e);
-                std::process::exit(1);
-            }
-        };
-        let indents = dm::indents::IndentProcessor::new(&self.dm_context, pp);
-        let mut parser = dm::parser::Parser::new(&self.dm_context, indents);
-        if self.procs {
-            parser.enable_procs();
+        self.environment = Some(environment.clone());
+        let mut pp = preprocessor(&self.dm_context, environment);
+        {
+            let indents = dm::indents::IndentProcessor::new(&self.dm_context, &mut pp);
+            if self.parallel {
+                self.objtree = dm::parser::parse_parallel(&self.dm_context, indents, self.procs);
+            } else {
+                let mut parser = dm::parser::Parser::new(&self.dm_context, indents);
+                if self.procs {
+                    parser.enable_procs();
+                }
+                self.objtree = parser.parse_object_tree();
+            }
+        }
+        self.defines = Some(pp.defines().clone());
+    }
+}
+
+/// Create a parsing context configured by the command line.
+fn dm_context(opt: &Opt) -> dm::Context {
+    let mut context = dm::Context::default();
+    context.set_print_severity(Some(dm::Severity::Error));
+    context.set_encoding(opt.encoding);
+    for lint in opt.lints.iter() {
+        context.enable_lint(lint);
+    }
+    if let Some(version) = opt.byond_version {
+        context.set_byond_version(version);
+    }
+    context.set_color(match opt.color.as_str() {
+        "always" => true,
+        "never" => false,
+        _ => atty::is(atty::Stream::Stderr) && std::env::var_os("NO_COLOR").is_none() && match std::env::var("TERM") {
+            Ok(term) => term != "dumb",
+            Err(_) => false,
+        },
+    });
+    context
+}
+
+/// Find the environment given on the command line or detected nearby.
+fn environment(opt: &Opt) -> std::path::PathBuf {
+    let environment: std::path::PathBuf = match opt.environment {
+        Some(ref env) => env.into(),
+        None => dm::detect_environment_default().unwrap_or_else(|| dm::DEFAULT_ENV.into()),
+    };
+    eprintln!("parsing {}", environment.display());
+    environment
+}
+
+fn preprocessor(context: &dm::Context, environment: std::path::PathBuf) -> dm::preprocessor::Preprocessor {
+    match dm::preprocessor::Preprocessor::new(context, environment) {
+        Ok(pp) => pp,
+        Err(e) => {
+            eprintln!("i/o error opening environment:\n{}", e);
+            std::process::exit(1);
         }
-        self.objtree = parser.parse_object_tree();
     }
 }
 
@@ -106,10 +144,28 @@ struct Opt {
     verbose: bool,
 
     /// Set the number of threads to be used for parallel execution when
-    /// possible. A value of 0 will select automatically, and 1 will be serial.
+    /// possible, such as parsing after preprocessing. A value of 0 will
+    /// select automatically, and 1 will be serial.
     #[structopt(long="jobs", default_value="1")]
     jobs: usize,
 
+    /// The encoding of source files: latin1, utf8, or auto.
+    #[structopt(long="encoding", default_value="latin1")]
+    encoding: dm::lexer::Encoding,
+
+    /// Turn on an optional lint, such as "shadowed-src-vars". May be repeated.
+    #[structopt(long="lint")]
+    lints: Vec<String>,
+
+    /// Warn about builtins newer than this BYOND version, such as 513.
+    #[structopt(long="byond-version")]
+    byond_version: Option<u32>,
+
+    /// Whether to color diagnostics: auto, always, or never. Auto colors
+    /// them only when stderr is a terminal.
+    #[structopt(long="color", default_value="auto")]
+    color: String,
+
     #[structopt(subcommand)]
     command: Command,
 }
@@ -135,9 +191,157 @@ enum Command {
         /// Check proc bodies as well as the object tree.
         #[structopt(long="procs")]
         procs: bool,
+        /// Apply suggested fixes to the source files.
+        #[structopt(long="fix")]
+        fix: bool,
+        /// The output format, of "human" or "json".
+        #[structopt(long="format", default_value="human")]
+        format: String,
+        /// Check again whenever a file in the environment changes.
+        #[structopt(long="watch")]
+        watch: bool,
+        /// Check that resource files such as icons and sounds exist.
+        #[structopt(long="resources")]
+        resources: bool,
+        /// With --resources, also check that each icon_state is in its icon.
+        #[structopt(long="icon-states")]
+        icon_states: bool,
+    },
+    /// Show the type hierarchy of the environment.
+    #[structopt(name = "objtree")]
+    Objtree {
+        /// Only show types at or beneath this path.
+        #[structopt(default_value="")]
+        prefix: String,
+        /// Include var declarations and overrides.
+        #[structopt(long="vars")]
+        vars: bool,
+        /// Include proc declarations and overrides.
+        #[structopt(long="procs")]
+        procs: bool,
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+    },
+    /// List every type which declares or overrides a var, with its value.
+    #[structopt(name = "var-overrides")]
+    VarOverrides {
+        /// The name of the var, such as `layer`.
+        name: String,
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+    },
+    /// Evaluate constant expressions against the environment.
+    ///
+    /// If no expressions are given, they are read from standard input.
+    #[structopt(name = "eval")]
+    Eval {
+        /// The type against which to resolve variable names.
+        #[structopt(short="t", long="type", default_value="/")]
+        type_: String,
+        /// The expressions to evaluate.
+        expressions: Vec<String>,
+    },
+    /// Report string literals which are repeated throughout the environment.
+    #[structopt(name = "strings")]
+    Strings {
+        /// The number of duplicated strings to show.
+        #[structopt(short="n", long="limit", default_value="20")]
+        limit: usize,
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+    },
+    /// Join profiler output against the environment to locate hot procs.
+    ///
+    /// Accepts the CSV or JSON export of the BYOND profiler.
+    #[structopt(name = "hot-procs")]
+    HotProcs {
+        /// The exported profile.
+        profile: String,
+        /// The number of procs to show.
+        #[structopt(short="n", long="limit", default_value="20")]
+        limit: usize,
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+    },
+    /// Map the call stacks of runtime errors in a log to their source.
+    ///
+    /// Reads the runtime errors written to `world.log`, such as by a server
+    /// started with `-logself`.
+    #[structopt(name = "explain-runtime")]
+    ExplainRuntime {
+        /// The log file.
+        log: String,
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+    },
+    /// Write every type, var, and proc in the environment to a JSON file.
+    ///
+    /// The schema is documented in `export.rs` and versioned for use by
+    /// other tools.
+    #[structopt(name = "export-symbols")]
+    ExportSymbols {
+        /// The file to write, or `-` for standard output.
+        out: String,
     },
-    /// Build minimaps of the specified maps.
-    #[structopt(name = "minimap")]
+    /// Report the most complex procs in the environment.
+    #[structopt(name = "metrics")]
+    Metrics {
+        /// The number of procs to show.
+        #[structopt(short="n", long="limit", default_value="20")]
+        limit: usize,
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+    },
+    /// Report procs which are never called.
+    ///
+    /// Procs invoked only by reflection can be excluded with
+    /// `set SpacemanDMM_called_by_reflection = TRUE`.
+    #[structopt(name = "unused-procs")]
+    UnusedProcs {
+        /// Additional procs called from outside the environment, either a
+        /// proc name or a type and proc such as `/world/New`.
+        #[structopt(long="root")]
+        roots: Vec<String>,
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+    },
+    /// Search proc bodies for expressions matching a structural pattern,
+    /// such as `call("addtimer", _, CONST)` or `assign("health") in /mob`.
+    #[structopt(name = "grep")]
+    Grep {
+        /// The pattern to search for.
+        pattern: String,
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+    },
+    /// List groups of procs whose bodies are the same up to local names.
+    #[structopt(name = "duplicate-procs")]
+    DuplicateProcs {
+        /// Skip bodies with fewer statements than this.
+        #[structopt(long="min-statements", default_value="5")]
+        min_statements: usize,
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+    },
+    /// Compare the types, vars, and procs of two versions of an environment.
+    #[structopt(name = "ast-diff")]
+    AstDiff {
+        /// The environment before the changes.
+        old: String,
+        /// The environment after the changes.
+        new: String,
+    },
+    /// Build minimaps of the specified maps, one PNG per z-level.
+    #[structopt(name = "minimap", alias = "render")]
     Minimap {
         /// The output directory.
         #[structopt(short="o", default_value="data/minimaps")]
@@ -171,6 +375,10 @@ enum Command {
         files: Vec<String>,
     },
     /// Lint and automatically fix the specified maps.
+    ///
+    /// Also reports prefabs with unknown types or vars, vars set to their
+    /// default values, and duplicated atoms, which are not fixed
+    /// automatically and cause a nonzero exit status.
     #[structopt(name = "lint-maps")]
     LintMaps {
         /// Only report and do not save out changes.
@@ -190,6 +398,53 @@ enum Command {
         left: String,
         right: String,
     },
+    /// Find tiles containing objects of a given type or with given vars.
+    #[structopt(name="find")]
+    Find {
+        /// The type to search for, including subtypes.
+        #[structopt(short="t", long="type")]
+        type_: Option<String>,
+        /// Do not match subtypes of the given type.
+        #[structopt(long="exact")]
+        exact: bool,
+        /// A var constraint such as `pixel_x != 0`. May be repeated.
+        #[structopt(short="w", long="where")]
+        constraints: Vec<search::VarConstraint>,
+        /// The list of maps to search.
+        files: Vec<String>,
+    },
+    /// Perform a three-way merge of map files.
+    ///
+    /// Usable as a git merge driver with `dmm-tools merge-maps %O %A %B`.
+    /// Conflicting tiles keep the contents of the current version and are
+    /// listed in the output, and the exit status is nonzero.
+    #[structopt(name="merge-maps")]
+    MergeMaps {
+        /// The common ancestor of both versions.
+        base: String,
+        /// The current version, which is overwritten unless -o is given.
+        ours: String,
+        /// The other version being merged in.
+        theirs: String,
+        /// Write the merged map here instead of over the current version.
+        #[structopt(short="o", long="output")]
+        output: Option<String>,
+    },
+    /// Count the types, area coverage, and power networks of maps, in total
+    /// and for each z-level.
+    #[structopt(name="stats")]
+    Stats {
+        /// Count each type along with its subtypes.
+        #[structopt(long="grouped")]
+        grouped: bool,
+
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+
+        /// The list of maps to count.
+        files: Vec<String>,
+    },
     /// Show metadata information about the map.
     #[structopt(name="map-info")]
     MapInfo {
@@ -240,37 +495,720 @@ fn run(opt: &Opt, command: &Command, context: &mut Context) {
             }
         },
         // --------------------------------------------------------------------
-        Command::Check { ref severity, procs } => {
+        Command::Check { ref severity, procs, fix, ref format, watch, resources, icon_states } => {
             let severity = match severity.as_str() {
                 "error" => dm::Severity::Error,
                 "warning" => dm::Severity::Warning,
                 "info" => dm::Severity::Info,
                 _ => dm::Severity::Hint,
             };
-            context.dm_context.set_print_severity(Some(severity));
-            context.procs = procs;
+            let json = match format.as_str() {
+                "human" => false,
+                "json" => true,
+                other => {
+                    eprintln!("unknown format {:?}, expected \"human\" or \"json\"", other);
+                    *context.exit_status.get_mut() = 2;
+                    return;
+                }
+            };
+            loop {
+                context.dm_context.set_print_severity(if json { None } else { Some(severity) });
+                context.procs = procs;
+                context.objtree(opt);
+
+                if resources {
+                    let root = context.environment.as_ref()
+                        .and_then(|env| env.parent())
+                        .map_or_else(Default::default, |parent| parent.to_owned());
+                    let file_dirs = context.defines.as_ref()
+                        .map_or_else(Vec::new, dm::resources::file_dirs);
+                    let mut checker = dm::resources::ResourceChecker::new(&root, &file_dirs);
+                    checker.check_icon_states(icon_states);
+                    checker.check(&context.dm_context, &context.objtree);
+                }
+
+                // Only error-severity diagnostics fail the check, regardless of
+                // which severities were requested for output.
+                let failed = context.dm_context.has_errors_at(dm::Severity::Error);
+                *context.exit_status.get_mut() = failed as isize;
+
+                if json {
+                    #[derive(Serialize)]
+                    struct Diagnostic<'a> {
+                        file: std::path::PathBuf,
+                        line: u32,
+                        column: u16,
+                        severity: String,
+                        description: &'a str,
+                    }
+
+                    let errors = context.dm_context.errors();
+                    let report: Vec<_> = errors.iter()
+                        .filter(|e| e.severity() <= severity)
+                        .map(|e| {
+                            let location = e.location();
+                            Diagnostic {
+                                file: context.dm_context.file_path(location.file),
+                                line: location.line,
+                                column: location.column,
+                                severity: e.severity().to_string(),
+                                description: e.description(),
+                            }
+                        })
+                        .collect();
+                    output_json(&report);
+                }
+
+                if fix {
+                    let errors = context.dm_context.errors();
+                    let mut by_file: HashMap<dm::FileId, Vec<&dm::Fix>> = HashMap::new();
+                    for error in errors.iter().filter(|e| e.severity() <= severity) {
+                        for each in error.fixes() {
+                            by_file.entry(each.replacement_range.start.file)
+                                .or_insert_with(Vec::new)
+                                .push(each);
+                        }
+                    }
+                    let mut total = 0;
+                    for (file, fixes) in by_file {
+                        let path = context.dm_context.file_path(file);
+                        let source = match std::fs::read(&path) {
+                            Ok(source) => source,
+                            Err(e) => {
+                                eprintln!("{}: {}", path.display(), e);
+                                continue;
+                            }
+                        };
+                        let (output, applied) = dm::apply_fixes(&source, &fixes);
+                        if applied == 0 {
+                            continue;
+                        }
+                        if let Err(e) = std::fs::write(&path, output) {
+                            eprintln!("{}: {}", path.display(), e);
+                            continue;
+                        }
+                        eprintln!("{}: applied {} fixes", path.display(), applied);
+                        total += applied;
+                    }
+                    eprintln!("applied {} fixes in total", total);
+                }
+
+                if !watch {
+                    break;
+                }
+                let changed = match dm::watch::Watch::new(&context.dm_context, std::time::Duration::from_millis(200)) {
+                    Ok(watch) => {
+                        eprintln!("watching for changes...");
+                        watch.wait()
+                    }
+                    Err(e) => {
+                        eprintln!("unable to watch for changes: {}", e);
+                        *context.exit_status.get_mut() = 2;
+                        return;
+                    }
+                };
+                let changed = match changed {
+                    Some(changed) => changed,
+                    None => break,
+                };
+                for &file in changed.iter() {
+                    eprintln!("changed: {}", context.dm_context.file_path(file).display());
+                }
+                context.dm_context = dm_context(opt);
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::Objtree { ref prefix, vars, procs, json } => {
+            context.objtree(opt);
+            let root = if prefix.is_empty() || prefix == "/" {
+                context.objtree.root()
+            } else {
+                match context.objtree.find(prefix.trim_right_matches('/')) {
+                    Some(ty) => ty,
+                    None => {
+                        eprintln!("no such type: {}", prefix);
+                        *context.exit_status.get_mut() = 1;
+                        return;
+                    }
+                }
+            };
+
+            #[derive(Serialize)]
+            struct Type {
+                path: String,
+                #[serde(skip_serializing_if="Option::is_none")]
+                parent_type: Option<String>,
+                #[serde(skip_serializing_if="Vec::is_empty")]
+                vars: Vec<Var>,
+                #[serde(skip_serializing_if="Vec::is_empty")]
+                procs: Vec<Proc>,
+                children: Vec<Type>,
+            }
+
+            #[derive(Serialize)]
+            struct Var {
+                name: String,
+                declaration: Option<String>,
+                value: Option<String>,
+            }
+
+            #[derive(Serialize)]
+            struct Proc {
+                name: String,
+                declaration: Option<&'static str>,
+                parameters: Vec<String>,
+            }
+
+            fn build(ty: dm::objtree::TypeRef, vars: bool, procs: bool) -> Type {
+                let mut children = ty.children();
+                children.sort_by(|a, b| a.name.cmp(&b.name));
+                Type {
+                    path: ty.pretty_path().to_owned(),
+                    parent_type: match (ty.parent_type(), ty.parent_path()) {
+                        (Some(parent), Some(path)) if parent.path != path.path => Some(parent.pretty_path().to_owned()),
+                        _ => None,
+                    },
+                    vars: if vars {
+                        ty.vars.iter().map(|(name, var)| Var {
+                            name: name.clone(),
+                            declaration: var.declaration.as_ref().map(|d| d.var_type.to_string()),
+                            value: var.value.constant.as_ref().map(|c| c.to_string()),
+                        }).collect()
+                    } else {
+                        Vec::new()
+                    },
+                    procs: if procs {
+                        ty.procs.iter().map(|(name, proc)| Proc {
+                            name: name.clone(),
+                            declaration: proc.declaration.as_ref().map(|d| if d.is_verb { "verb" } else { "proc" }),
+                            parameters: proc.value.last()
+                                .map_or(Vec::new(), |v| v.parameters.iter().map(|p| p.to_string()).collect()),
+                        }).collect()
+                    } else {
+                        Vec::new()
+                    },
+                    children: children.into_iter().map(|c| build(c, vars, procs)).collect(),
+                }
+            }
+
+            fn print(ty: &Type, depth: usize) {
+                let indent = "  ".repeat(depth);
+                match ty.parent_type {
+                    Some(ref parent) => println!("{}{} (parent_type = {})", indent, ty.path, parent),
+                    None => println!("{}{}", indent, ty.path),
+                }
+                for var in ty.vars.iter() {
+                    let decl = match var.declaration {
+                        Some(ref decl) => format!("{}/", decl),
+                        None => String::new(),
+                    };
+                    match var.value {
+                        Some(ref value) => println!("{}  {}{} = {}", indent, decl, var.name, value),
+                        None => println!("{}  {}{}", indent, decl, var.name),
+                    }
+                }
+                for proc in ty.procs.iter() {
+                    let decl = match proc.declaration {
+                        Some(kind) => format!("{}/", kind),
+                        None => String::new(),
+                    };
+                    println!("{}  {}{}({})", indent, decl, proc.name, proc.parameters.join(", "));
+                }
+                for child in ty.children.iter() {
+                    print(child, depth + 1);
+                }
+            }
+
+            let tree = build(root, vars, procs);
+            if json {
+                output_json(&tree);
+            } else {
+                print(&tree, 0);
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::VarOverrides { ref name, json } => {
+            context.objtree(opt);
+            let overrides = context.objtree.var_overrides(name);
+            if overrides.is_empty() {
+                eprintln!("no type sets var {}", name);
+                *context.exit_status.get_mut() = 1;
+                return;
+            }
+
+            #[derive(Serialize)]
+            struct Override {
+                path: String,
+                declaration: bool,
+                value: Option<String>,
+            }
+
+            let overrides: Vec<Override> = overrides.iter().map(|o| Override {
+                path: o.ty.pretty_path().to_owned(),
+                declaration: o.is_declaration(),
+                value: match o.constant() {
+                    Some(constant) => Some(constant.to_string()),
+                    None => o.var.value.expression.as_ref().map(|expr| expr.to_string()),
+                },
+            }).collect();
+
+            if json {
+                output_json(&overrides);
+            } else {
+                for each in overrides.iter() {
+                    println!("{}: {} = {}{}",
+                        each.path,
+                        name,
+                        each.value.as_ref().map_or("null", |v| v.as_str()),
+                        if each.declaration { " (declared)" } else { "" });
+                }
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::Eval { ref type_, ref expressions } => {
+            context.objtree(opt);
+            // report errors in the snippets, not the environment
+            let eval_context = dm::Context::default();
+            let Context { ref mut objtree, ref defines, ref mut exit_status, .. } = *context;
+            let mut eval = |expression: &str| {
+                match dm::constants::evaluate_str(&eval_context, objtree, type_, defines.as_ref(), expression) {
+                    Ok(constant) => println!("{}", constant),
+                    Err(e) => {
+                        println!("error: {}", e.description());
+                        *exit_status.get_mut() += 1;
+                    }
+                }
+            };
+            if expressions.is_empty() {
+                use std::io::BufRead;
+                let stdin = std::io::stdin();
+                for line in stdin.lock().lines() {
+                    let line = line.expect("error reading stdin");
+                    if !line.trim().is_empty() {
+                        eval(&line);
+                    }
+                }
+            } else {
+                for expression in expressions {
+                    eval(expression);
+                }
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::Strings { limit, json } => {
+            let table = dm::strings::StringTable::from_preprocessor(&mut preprocessor(&context.dm_context, environment(opt)));
+            let duplicates = table.duplicates();
+            let near_duplicates = table.near_duplicates();
+            let savings: usize = duplicates.iter().map(|dupe| dupe.savings()).sum();
+
+            if json {
+                #[derive(Serialize)]
+                struct Duplicate<'a> {
+                    text: &'a str,
+                    count: usize,
+                    savings: usize,
+                    locations: Vec<String>,
+                }
+
+                #[derive(Serialize)]
+                struct Report<'a> {
+                    total: usize,
+                    distinct: usize,
+                    savings: usize,
+                    duplicates: Vec<Duplicate<'a>>,
+                    near_duplicates: &'a [Vec<&'a str>],
+                }
+
+                let dm_context = &context.dm_context;
+                output_json(&Report {
+                    total: table.total(),
+                    distinct: table.len(),
+                    savings,
+                    duplicates: duplicates.iter().take(limit).map(|dupe| Duplicate {
+                        text: dupe.text,
+                        count: dupe.locations.len(),
+                        savings: dupe.savings(),
+                        locations: dupe.locations.iter().map(|loc| format!(
+                            "{}:{}:{}", dm_context.file_path(loc.file).display(), loc.line, loc.column,
+                        )).collect(),
+                    }).collect(),
+                    near_duplicates: &near_duplicates,
+                });
+                return;
+            }
+
+            println!("{} string literals, {} distinct", table.total(), table.len());
+            println!("{} repeated, saving an estimated {} bytes if each were defined once", duplicates.len(), savings);
+            for dupe in duplicates.iter().take(limit) {
+                let first = dupe.locations[0];
+                println!(
+                    "{:>6} bytes  {:>4}x  {:?}  (first at {}:{})",
+                    dupe.savings(),
+                    dupe.locations.len(),
+                    dupe.text,
+                    context.dm_context.file_path(first.file).display(),
+                    first.line,
+                );
+            }
+            if !near_duplicates.is_empty() {
+                println!("{} groups of near-duplicates", near_duplicates.len());
+                for group in near_duplicates.iter().take(limit) {
+                    println!("    {:?}", group);
+                }
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::HotProcs { ref profile, limit, json } => {
+            let entries = match read_profile(profile.as_ref()) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    eprintln!("failed to read {}:\n{}", profile, e);
+                    *context.exit_status.get_mut() = 2;
+                    return;
+                }
+            };
+            context.procs = true;
             context.objtree(opt);
-            *context.exit_status.get_mut() = context
-                .dm_context
-                .errors()
-                .iter()
-                .filter(|e| e.severity() <= severity)
-                .count() as isize;
+
+            let hot = dm::profile::hot_procs(&context.objtree, &entries);
+            let dm_context = &context.dm_context;
+            let describe_location = |each: &dm::profile::HotProc| each.proc.map(|proc| format!(
+                "{}:{}",
+                dm_context.file_path(proc.location.file).display(),
+                proc.location.line,
+            ));
+
+            if json {
+                #[derive(Serialize)]
+                struct HotProc<'a> {
+                    name: &'a str,
+                    self_cpu: f64,
+                    total_cpu: f64,
+                    calls: u64,
+                    location: Option<String>,
+                    sleeps: &'a [&'a str],
+                    suspicious: bool,
+                }
+
+                let report: Vec<_> = hot.iter().take(limit).map(|each| HotProc {
+                    name: &each.entry.name,
+                    self_cpu: each.entry.self_cpu,
+                    total_cpu: each.entry.total_cpu,
+                    calls: each.entry.calls,
+                    location: describe_location(each),
+                    sleeps: &each.sleeps,
+                    suspicious: each.is_suspicious(),
+                }).collect();
+                output_json(&report);
+                return;
+            }
+
+            for each in hot.iter().take(limit) {
+                println!(
+                    "{:>10.3}s self {:>10.3}s total {:>8} calls  {}",
+                    each.entry.self_cpu,
+                    each.entry.total_cpu,
+                    each.entry.calls,
+                    each.entry.name,
+                );
+                match describe_location(each) {
+                    Some(location) => println!("    defined at {}", location),
+                    None => println!("    not found in the environment"),
+                }
+                if each.is_suspicious() {
+                    println!("    may sleep by calling {}", each.sleeps.join(", "));
+                }
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::ExplainRuntime { ref log, json } => {
+            let text = match std::fs::read_to_string(log) {
+                Ok(text) => text,
+                Err(e) => {
+                    eprintln!("failed to read {}:\n{}", log, e);
+                    *context.exit_status.get_mut() = 2;
+                    return;
+                }
+            };
+            let errors = dm::runtime::parse_log(&text);
+            context.objtree(opt);
+
+            let dm_context = &context.dm_context;
+            let describe_location = |each: &dm::runtime::ResolvedFrame| each.location.map(|location| format!(
+                "{}:{}",
+                dm_context.file_path(location.file).display(),
+                location.line,
+            ));
+
+            if json {
+                #[derive(Serialize)]
+                struct Frame<'a> {
+                    text: &'a str,
+                    proc: Option<String>,
+                    location: Option<String>,
+                }
+
+                #[derive(Serialize)]
+                struct Runtime<'a> {
+                    message: &'a str,
+                    frames: Vec<Frame<'a>>,
+                }
+
+                let report: Vec<_> = errors.iter().map(|error| Runtime {
+                    message: &error.message,
+                    frames: dm::runtime::resolve(dm_context, &context.objtree, error).iter().map(|each| Frame {
+                        text: &each.frame.text,
+                        proc: each.ty.map(|ty| format!("{}/proc/{}", ty.get().path, each.frame.proc_name)),
+                        location: describe_location(each),
+                    }).collect(),
+                }).collect();
+                output_json(&report);
+                return;
+            }
+
+            for error in errors.iter() {
+                println!("runtime error: {}", error.message);
+                for each in dm::runtime::resolve(dm_context, &context.objtree, error) {
+                    println!("    {}", each.frame.text);
+                    match (describe_location(&each), each.ty) {
+                        (Some(location), _) => println!("        at {}", location),
+                        (None, Some(_)) => println!("        builtin"),
+                        (None, None) => println!("        not found in the environment"),
+                    }
+                }
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::ExportSymbols { ref out } => {
+            context.objtree(opt);
+
+            let symbols = export::export(&context.dm_context, &context.objtree);
+            if out == "-" {
+                output_json(&symbols);
+                return;
+            }
+            let result = std::fs::File::create(out)
+                .map_err(serde_json::Error::io)
+                .and_then(|file| serde_json::to_writer(std::io::BufWriter::new(file), &symbols));
+            if let Err(e) = result {
+                eprintln!("failed to write {}:\n{}", out, e);
+                *context.exit_status.get_mut() = 2;
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::Metrics { limit, json } => {
+            context.procs = true;
+            context.objtree(opt);
+
+            let reports = dm::metrics::measure_all(&context.objtree);
+            if json {
+                #[derive(Serialize)]
+                struct Metrics {
+                    path: String,
+                    file: std::path::PathBuf,
+                    line: u32,
+                    statements: usize,
+                    complexity: usize,
+                    max_depth: usize,
+                    sleeps: usize,
+                }
+
+                let report: Vec<_> = reports.iter().take(limit).map(|each| Metrics {
+                    path: each.path(),
+                    file: context.dm_context.file_path(each.proc.location.file),
+                    line: each.proc.location.line,
+                    statements: each.metrics.statements,
+                    complexity: each.metrics.complexity,
+                    max_depth: each.metrics.max_depth,
+                    sleeps: each.metrics.sleeps,
+                }).collect();
+                output_json(&report);
+                return;
+            }
+
+            println!("complexity  statements  depth  sleeps  proc");
+            for each in reports.iter().take(limit) {
+                println!(
+                    "{:>10}  {:>10}  {:>5}  {:>6}  {} ({}:{})",
+                    each.metrics.complexity,
+                    each.metrics.statements,
+                    each.metrics.max_depth,
+                    each.metrics.sleeps,
+                    each.path(),
+                    context.dm_context.file_path(each.proc.location.file).display(),
+                    each.proc.location.line,
+                );
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::AstDiff { ref old, ref new } => {
+            let parse = |environment: &str| {
+                let dm_context = dm_context(opt);
+                eprintln!("parsing {}", environment);
+                let pp = preprocessor(&dm_context, environment.into());
+                let indents = dm::indents::IndentProcessor::new(&dm_context, pp);
+                let mut parser = dm::parser::Parser::new(&dm_context, indents);
+                parser.enable_procs();
+                parser.parse_object_tree()
+            };
+            let (old, new) = (parse(old), parse(new));
+            for change in dm::astdiff::compare(&old, &new) {
+                println!("{}", change);
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::UnusedProcs { ref roots, json } => {
+            context.procs = true;
+            context.objtree(opt);
+
+            let mut all_roots: Vec<&str> = dm::dead_code::DEFAULT_ROOTS.to_vec();
+            all_roots.extend(roots.iter().map(|root| root.as_str()));
+            let unused = dm::dead_code::unused_procs(&context.objtree, &all_roots);
+
+            if json {
+                #[derive(Serialize)]
+                struct Unused {
+                    path: String,
+                    file: std::path::PathBuf,
+                    line: u32,
+                }
+
+                let report: Vec<_> = unused.iter().map(|each| Unused {
+                    path: format!("{}/proc/{}", each.ty.get().path, each.name),
+                    file: context.dm_context.file_path(each.proc.location.file),
+                    line: each.proc.location.line,
+                }).collect();
+                output_json(&report);
+                return;
+            }
+
+            for each in unused.iter() {
+                println!(
+                    "{}:{}: {}/proc/{} is never called",
+                    context.dm_context.file_path(each.proc.location.file).display(),
+                    each.proc.location.line,
+                    each.ty.get().path,
+                    each.name,
+                );
+            }
+            println!("{} unused procs", unused.len());
+        },
+        // --------------------------------------------------------------------
+        Command::DuplicateProcs { min_statements, json } => {
+            context.procs = true;
+            context.objtree(opt);
+            let groups = dm::duplicates::duplicate_procs(&context.dm_context, &context.objtree, min_statements);
+
+            if json {
+                #[derive(Serialize)]
+                struct Group {
+                    statements: usize,
+                    procs: Vec<Proc>,
+                }
+
+                #[derive(Serialize)]
+                struct Proc {
+                    path: String,
+                    file: std::path::PathBuf,
+                    line: u32,
+                }
+
+                let report: Vec<_> = groups.iter().map(|group| Group {
+                    statements: group.statements,
+                    procs: group.procs.iter().map(|each| Proc {
+                        path: format!("{}/proc/{}", each.ty.get().path, each.name),
+                        file: context.dm_context.file_path(each.proc.location.file),
+                        line: each.proc.location.line,
+                    }).collect(),
+                }).collect();
+                output_json(&report);
+                return;
+            }
+
+            for group in groups.iter() {
+                println!("{} procs with the same {}-statement body:", group.procs.len(), group.statements);
+                for each in group.procs.iter() {
+                    println!(
+                        "    {}:{}: {}/proc/{}",
+                        context.dm_context.file_path(each.proc.location.file).display(),
+                        each.proc.location.line,
+                        each.ty.get().path,
+                        each.name,
+                    );
+                }
+            }
+            println!("{} groups of duplicate procs", groups.len());
+        },
+        // --------------------------------------------------------------------
+        Command::Grep { ref pattern, json } => {
+            let pattern = match dm::search::Pattern::parse(pattern) {
+                Ok(pattern) => pattern,
+                Err(err) => {
+                    eprintln!("invalid pattern at column {}: {}", err.location().column, err.description());
+                    *context.exit_status.get_mut() = 1;
+                    return;
+                }
+            };
+            context.procs = true;
+            context.objtree(opt);
+            if let Some(scope) = pattern.scope() {
+                if context.objtree.find(scope).is_none() {
+                    eprintln!("no such type: {}", scope);
+                    *context.exit_status.get_mut() = 1;
+                    return;
+                }
+            }
+
+            let matches = pattern.search(&context.dm_context, &context.objtree);
+            if json {
+                #[derive(Serialize)]
+                struct Found {
+                    proc: String,
+                    file: std::path::PathBuf,
+                    line: u32,
+                    text: String,
+                }
+
+                let report: Vec<_> = matches.iter().map(|each| Found {
+                    proc: format!("{}/proc/{}", each.ty.get().path, each.proc),
+                    file: context.dm_context.file_path(each.location.file),
+                    line: each.location.line,
+                    text: each.text.clone(),
+                }).collect();
+                output_json(&report);
+                return;
+            }
+
+            for each in matches.iter() {
+                println!(
+                    "{}:{}: {}/proc/{}: {}",
+                    context.dm_context.file_path(each.location.file).display(),
+                    each.location.line,
+                    each.ty.get().path,
+                    each.proc,
+                    each.text,
+                );
+            }
+            println!("{} matches", matches.len());
         },
         // --------------------------------------------------------------------
         Command::Minimap {
             ref output, min, max, ref enable, ref disable, ref files,
             pngcrush, optipng,
         } => {
+            let unknown: Vec<&str> = dmm_tools::render_passes::unknown_names(enable)
+                .into_iter()
+                .chain(dmm_tools::render_passes::unknown_names(disable))
+                .collect();
+            if !unknown.is_empty() {
+                eprintln!("unknown render passes: {}", unknown.join(", "));
+                eprintln!("see `list-passes` for the available passes");
+                *context.exit_status.get_mut() = 2;
+                return;
+            }
+
             context.objtree(opt);
-            if context
-                .dm_context
-                .errors()
-                .iter()
-                .filter(|e| e.severity() <= dm::Severity::Error)
-                .next()
-                .is_some()
-            {
+            if context.dm_context.has_errors_at(dm::Severity::Error) {
                 println!("there were some parsing errors; render may be inaccurate")
             }
             let Context {
@@ -325,8 +1263,8 @@ fn run(opt: &Opt, command: &Command, context: &mut Context) {
                         objtree: &objtree,
                         map: &map,
                         grid: map.z_level(z),
-                        min: (min.x - 1, min.y - 1),
-                        max: (max.x - 1, max.y - 1),
+                        min: coords::Coord2::new(min.x as i32, min.y as i32),
+                        max: coords::Coord2::new(max.x as i32, max.y as i32),
                         render_passes: &render_passes,
                     };
                     let image = minimap::generate(minimap_context, icon_cache).unwrap();
@@ -394,6 +1332,14 @@ fn run(opt: &Opt, command: &Command, context: &mut Context) {
                 println!("{}", path.display());
                 let mut map = dmm::Map::from_file(path).unwrap();
 
+                let problems = lint::validate(&context.objtree, &map);
+                for problem in problems.iter() {
+                    println!("    {}", problem);
+                }
+                if !problems.is_empty() {
+                    *context.exit_status.get_mut() = 1;
+                }
+
                 let linted = lint::check(&context.objtree, &mut map);
                 print!("{}", linted);
                 if !dry_run && (linted.any() || reformat) {
@@ -434,6 +1380,159 @@ fn run(opt: &Opt, command: &Command, context: &mut Context) {
             }
         },
         // --------------------------------------------------------------------
+        Command::Find {
+            ref type_, exact, ref constraints, ref files,
+        } => {
+            if type_.is_none() && constraints.is_empty() {
+                eprintln!("at least one of --type or --where is required");
+                *context.exit_status.get_mut() = 2;
+                return;
+            }
+            context.objtree(opt);
+
+            let query = search::Query {
+                path: type_.clone(),
+                exact,
+                constraints: constraints.clone(),
+            };
+            for path in files.iter() {
+                let path: &std::path::Path = path.as_ref();
+                let map = match dmm::Map::from_file(path) {
+                    Ok(map) => map,
+                    Err(e) => {
+                        eprintln!("{}: {}", path.display(), e.description());
+                        *context.exit_status.get_mut() = 2;
+                        continue;
+                    }
+                };
+                for coord in search::find(&context.objtree, &map, &query) {
+                    println!("{}: {}", path.display(), coord);
+                }
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::MergeMaps {
+            ref base, ref ours, ref theirs, ref output,
+        } => {
+            let load = |path: &str| match dmm::Map::from_file(path.as_ref()) {
+                Ok(map) => Some(map),
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    None
+                }
+            };
+            let (base_map, our_map, their_map) = match (load(base), load(ours), load(theirs)) {
+                (Some(b), Some(o), Some(t)) => (b, o, t),
+                _ => {
+                    *context.exit_status.get_mut() = 2;
+                    return;
+                }
+            };
+
+            let merged = match merge::merge(&base_map, &our_map, &their_map) {
+                Ok(merged) => merged,
+                Err(e) => {
+                    eprintln!("{}", e.description());
+                    *context.exit_status.get_mut() = 2;
+                    return;
+                }
+            };
+            for conflict in merged.conflicts.iter() {
+                println!("conflict at {}", conflict.coord);
+                println!("    base:   {}", FormatTile(&conflict.base));
+                println!("    ours:   {}", FormatTile(&conflict.ours));
+                println!("    theirs: {}", FormatTile(&conflict.theirs));
+            }
+
+            let output: &std::path::Path = output.as_ref().unwrap_or(ours).as_ref();
+            if let Err(e) = merged.map.to_file(output) {
+                eprintln!("{}: {}", output.display(), e);
+                *context.exit_status.get_mut() = 2;
+                return;
+            }
+            if !merged.is_clean() {
+                println!("{} conflicts, keeping {} for those tiles", merged.conflicts.len(), ours);
+                *context.exit_status.get_mut() = 1;
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::Stats {
+            grouped, json, ref files,
+        } => {
+            use std::collections::BTreeMap;
+            use dmm_tools::census::Census;
+
+            context.objtree(opt);
+
+            #[derive(Serialize)]
+            struct Stats {
+                tiles: usize,
+                types: BTreeMap<String, usize>,
+                areas: BTreeMap<String, usize>,
+                cables: usize,
+                powernets: usize,
+            }
+
+            #[derive(Serialize)]
+            struct MapStats {
+                total: Stats,
+                z_levels: Vec<Stats>,
+            }
+
+            let objtree = &context.objtree;
+            let stats = |census: &Census| Stats {
+                tiles: census.tiles,
+                types: if grouped { census.grouped(objtree) } else { census.types.clone() },
+                areas: census.areas.clone(),
+                cables: census.cables,
+                powernets: census.powernets,
+            };
+
+            let mut report = BTreeMap::new();
+            for path in files.iter() {
+                let map = match dmm::Map::from_file(path.as_ref()) {
+                    Ok(map) => map,
+                    Err(e) => {
+                        eprintln!("Failed to load {}:\n{}", path, e);
+                        context.exit_status.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+                let z_levels = Census::of_map(objtree, &map);
+                let mut total = Census::default();
+                for each in z_levels.iter() {
+                    total.merge(each);
+                }
+
+                let map_stats = MapStats {
+                    total: stats(&total),
+                    z_levels: z_levels.iter().map(&stats).collect(),
+                };
+                if json {
+                    report.insert(path.clone(), map_stats);
+                    continue;
+                }
+
+                println!("{}", path);
+                let sections = Some(("total".to_owned(), &map_stats.total)).into_iter()
+                    .chain(map_stats.z_levels.iter().enumerate().map(|(z, each)| (format!("z={}", z + 1), each)));
+                for (name, each) in sections {
+                    println!("    {}: {} tiles, {} cables in {} powernets", name, each.tiles, each.cables, each.powernets);
+                    let mut types: Vec<_> = each.types.iter().collect();
+                    types.sort_by(|a, b| b.1.cmp(a.1));
+                    for (path, count) in types {
+                        println!("        {:>8}  {}", count, path);
+                    }
+                    for (path, tiles) in each.areas.iter() {
+                        println!("        {:>8}  {} (tiles)", tiles, path);
+                    }
+                }
+            }
+            if json {
+                output_json(&report);
+            }
+        },
+        // --------------------------------------------------------------------
         Command::MapInfo {
             json, ref files,
         } => {
@@ -511,6 +1610,20 @@ impl std::str::FromStr for CoordArg {
     }
 }
 
+struct FormatTile<'a>(&'a [dmm::Prefab]);
+
+impl<'a> fmt::Display for FormatTile<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, fab) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", fab)?;
+        }
+        Ok(())
+    }
+}
+
 fn clamp(val: usize, min: usize, max: usize) -> usize {
     if val < min {
         min
@@ -521,6 +1634,12 @@ fn clamp(val: usize, min: usize, max: usize) -> usize {
     }
 }
 
+/// Read the CSV or JSON export of the BYOND profiler.
+fn read_profile(path: &Path) -> Result<Vec<dm::profile::ProfileEntry>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    dm::profile::parse(&text)
+}
+
 fn output_json<T: serde::Serialize>(t: &T) {
     let stdout = std::io::stdout();
     serde_json::to_writer(stdout.lock(), t).unwrap();
@@ -64,10 +64,10 @@ fn main() -> Result<(), Box<std::error::Error>> {
     });
 
     // parse environment
-    let environment = match dm::detect_environment_default()? {
+    let environment = match dm::detect_environment_default() {
         Some(env) => env,
         None => {
-            eprintln!("Unable to find a .dme file in this directory");
+            eprintln!("Unable to find a .dme file in this directory or its parents");
             return Ok(());
         }
     };
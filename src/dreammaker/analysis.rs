@@ -0,0 +1,1386 @@
+//! Checks run over the finished object tree and parsed proc bodies.
+//!
+//! These run after constant evaluation, when every type, var, and proc in
+//! the environment is known.
+
+use std::fmt;
+
+use super::{Context, DMError, FileId, Location, Severity, Phase};
+use super::ast::*;
+use super::builtins;
+use super::constants::{self, Color, Constant};
+use super::strings::MacroTarget;
+use super::objtree::{ObjectTree, TypeRef, ProcValue, ProcDeclaration, VarDeclaration, Code, Body};
+use super::visit::{Visitor, walk_expression, walk_follow, walk_statement, walk_term};
+
+/// A check supplied by a codebase, such as one enforcing its own
+/// conventions, which runs alongside the builtin checks.
+///
+/// Lints are registered with `Context::register_lint`. Each hook is called
+/// once per matching item and does nothing by default.
+pub trait Lint: Send + Sync {
+    /// The name of this lint.
+    fn name(&self) -> &str;
+
+    /// Check a type.
+    fn check_type(&self, _cx: &LintContext, _ty: TypeRef) {}
+
+    /// Check a proc definition whose body was parsed.
+    fn check_proc(&self, _cx: &ProcLintContext, _block: &[Spanned<Statement>]) {}
+
+    /// Check a statement in a proc body, including nested statements.
+    fn check_statement(&self, _cx: &ProcLintContext, _statement: &Statement) {}
+
+    /// Check an expression in a proc body, including subexpressions.
+    fn check_expression(&self, _cx: &ProcLintContext, _expr: &Expression) {}
+}
+
+impl fmt::Debug for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Lint({:?})", self.name())
+    }
+}
+
+/// What a `Lint` can see of the environment.
+pub struct LintContext<'a> {
+    pub context: &'a Context,
+    pub tree: &'a ObjectTree,
+}
+
+impl<'a> LintContext<'a> {
+    /// Report a warning at the given location.
+    pub fn warning<S: Into<String>>(&self, location: Location, message: S) {
+        self.context.register_error(DMError::new(location, message).set_severity(Severity::Warning));
+    }
+}
+
+/// What a `Lint` can see of the environment while checking a proc.
+pub struct ProcLintContext<'a> {
+    pub context: &'a Context,
+    pub tree: &'a ObjectTree,
+    /// The type on which the proc is defined.
+    pub ty: TypeRef<'a>,
+    pub proc_name: &'a str,
+    pub proc: &'a ProcValue,
+}
+
+impl<'a> ProcLintContext<'a> {
+    /// Report a warning about this proc, in the same form as the builtin
+    /// checks.
+    pub fn warning<S: AsRef<str>>(&self, message: S) {
+        self.context.register_error(DMError::new(
+            self.proc.location,
+            format!("{}/proc/{}: {}", self.ty.path, self.proc_name, message.as_ref()),
+        ).set_severity(Severity::Warning));
+    }
+}
+
+/// Run the lints registered with the context over a finalized object tree,
+/// or over what is declared in one file of it.
+fn run_lints(context: &Context, tree: &ObjectTree, only: Option<FileId>) {
+    let lints = context.lints();
+    if lints.is_empty() {
+        return;
+    }
+    let cx = LintContext { context, tree };
+    for idx in tree.graph.node_indices() {
+        if context.is_cancelled() {
+            return;
+        }
+        let ty = TypeRef::new(tree, idx);
+        if declares_in(ty, only) {
+            for lint in lints.iter() {
+                lint.check_type(&cx, ty);
+            }
+        }
+        for (name, proc) in ty.get().procs.iter() {
+            for value in proc.value.iter() {
+                let block = match body_to_check(context, value, only) {
+                    Some(block) => block,
+                    None => continue,
+                };
+                let proc_cx = ProcLintContext { context, tree, ty, proc_name: name, proc: value };
+                for lint in lints.iter() {
+                    lint.check_proc(&proc_cx, &block);
+                    each_statement(&block, &mut |statement| lint.check_statement(&proc_cx, statement));
+                    each_expr(&block, true, &mut |expr| lint.check_expression(&proc_cx, expr));
+                }
+            }
+        }
+    }
+}
+
+/// Run all checks on a finalized object tree.
+pub(crate) fn check_all(context: &Context, tree: &ObjectTree) {
+    let total = tree.graph.node_count();
+    for (done, idx) in tree.graph.node_indices().enumerate() {
+        if context.is_cancelled() {
+            return;
+        }
+        context.report_progress(Phase::Analyzing, done, total);
+        check_type(context, tree, TypeRef::new(tree, idx), None);
+    }
+    context.report_progress(Phase::Analyzing, total, total);
+    run_lints(context, tree, None);
+}
+
+/// Run all checks on the vars and procs declared in one file, parsing lazily
+/// collected proc bodies as needed.
+///
+/// This revalidates a file which has changed without checking the rest of
+/// the environment.
+pub fn check_file(context: &Context, tree: &ObjectTree, file: FileId) {
+    for idx in tree.graph.node_indices() {
+        if context.is_cancelled() {
+            return;
+        }
+        check_type(context, tree, TypeRef::new(tree, idx), Some(file));
+    }
+    run_lints(context, tree, Some(file));
+}
+
+fn check_type(context: &Context, tree: &ObjectTree, ty: TypeRef, only: Option<FileId>) {
+    if declares_in(ty, only) {
+        check_const_overrides(context, ty);
+        if context.lint_enabled(LINT_MALFORMED_COLORS) {
+            check_color_vars(context, ty);
+        }
+        if context.lint_enabled(LINT_TEXT_MACROS) {
+            check_var_text_macros(context, ty);
+        }
+    }
+
+    for (name, proc) in ty.get().procs.iter() {
+        for value in proc.value.iter() {
+            if let Some(block) = body_to_check(context, value, only) {
+                let mut checker = ProcChecker::new(context, tree, ty, name, value);
+                checker.visit_block(&block);
+                checker.check_gotos();
+            }
+        }
+    }
+}
+
+/// Whether a type sets any vars in the given file, or in any file.
+fn declares_in(ty: TypeRef, only: Option<FileId>) -> bool {
+    match only {
+        None => true,
+        Some(file) => ty.get().location.file == file
+            || ty.get().vars.values().any(|var| var.value.location.file == file),
+    }
+}
+
+/// The body of a proc to check. Lazily collected bodies are only parsed for
+/// checking a single file.
+fn body_to_check<'a>(context: &Context, proc: &'a ProcValue, only: Option<FileId>) -> Option<Body<'a>> {
+    match (&proc.code, only) {
+        (&Code::Present(_), None) => proc.body(context),
+        (_, Some(file)) if proc.location.file == file => proc.body(context),
+        _ => None,
+    }
+}
+
+/// Infer the types of the local vars in a proc body which are declared
+/// without one, as in `var/x = new /obj/item`.
+///
+/// Returns the location following each var's name and the path of its
+/// type, such as `/obj/item` or `/list/obj/item` for a list of them.
+pub fn inferred_local_types(tree: &ObjectTree, ty: TypeRef, proc_name: &str, proc: &ProcValue, block: &[Spanned<Statement>]) -> Vec<(Location, String)> {
+    // warnings are reported when the environment is checked instead
+    let context = Context::default();
+    let mut checker = ProcChecker::new(&context, tree, ty, proc_name, proc);
+    checker.visit_block(block);
+    checker.inferred.into_iter().map(|(location, ty)| {
+        let path = match ty {
+            StaticType::Type(ty) => ty.get().path.clone(),
+            StaticType::List(depth, ty) => format!("{}{}", "/list".repeat(depth), ty.get().path),
+            StaticType::Unknown => String::new(),
+        };
+        (location, path)
+    }).collect()
+}
+
+/// Find the calls in a proc body to builtins which may sleep, such as
+/// `sleep()` or `input()`, in source order.
+pub fn sleeping_calls<'a>(tree: &ObjectTree, block: &'a [Spanned<Statement>]) -> Vec<&'a str> {
+    let mut result = Vec::new();
+    // spawned blocks run separately, so they cannot sleep their caller
+    each_expr(block, false, &mut |expr| match *expr {
+        Expression::Base { term: Term::Input { .. }, .. } => result.push("input"),
+        Expression::Base { term: Term::Call(ref name, _), .. } => {
+            let is_builtin = tree.root().get_proc(name)
+                .map_or(false, |proc| proc.location.file == FileId::builtins());
+            if is_builtin && tree.builtin_signature(name).map_or(false, |sig| sig.sleeps) {
+                result.push(name);
+            }
+        }
+        _ => {}
+    });
+    result
+}
+
+/// Call `f` on every statement in a block, including nested statements.
+fn each_statement<'a>(block: &'a [Spanned<Statement>], f: &mut FnMut(&'a Statement)) {
+    struct Statements<'f, 'a: 'f> {
+        f: &'f mut FnMut(&'a Statement),
+    }
+
+    impl<'f, 'a> Visitor<'a> for Statements<'f, 'a> {
+        fn visit_statement(&mut self, statement: &'a Statement) {
+            (self.f)(statement);
+            walk_statement(self, statement);
+        }
+
+        fn visit_expression(&mut self, _: &'a Expression) {}
+    }
+
+    Statements { f }.visit_block(block);
+}
+
+/// Calls a function on expressions, used by `each_expr` and `each_subexpr`.
+struct Expressions<'f, 'a: 'f> {
+    spawned: bool,
+    f: &'f mut FnMut(&'a Expression),
+}
+
+impl<'f, 'a> Visitor<'a> for Expressions<'f, 'a> {
+    fn visit_statement(&mut self, statement: &'a Statement) {
+        match *statement {
+            Statement::Spawn(ref delay, _) if !self.spawned => if let Some(ref delay) = *delay {
+                self.visit_expression(delay);
+            },
+            _ => walk_statement(self, statement),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &'a Expression) {
+        (self.f)(expr);
+        walk_expression(self, expr);
+    }
+}
+
+/// Call `f` on every expression in a block, including subexpressions and
+/// nested blocks. The bodies of `spawn` are included only if `spawned` is
+/// set, as they do not run as part of the block itself.
+pub(crate) fn each_expr<'a>(block: &'a [Spanned<Statement>], spawned: bool, f: &mut FnMut(&'a Expression)) {
+    Expressions { spawned, f }.visit_block(block);
+}
+
+/// Call `f` on an expression and each of its subexpressions.
+pub(crate) fn each_subexpr<'a>(expr: &'a Expression, f: &mut FnMut(&'a Expression)) {
+    Expressions { spawned: true, f }.visit_expression(expr);
+}
+
+/// Builtin `var/const`s which the compiler still lets types override.
+const OVERRIDABLE_CONSTS: &[&str] = &["parent_type"];
+
+/// Report types which change the value of an inherited `var/const`.
+fn check_const_overrides(context: &Context, ty: TypeRef) {
+    for (name, var) in ty.get().vars.iter() {
+        if var.declaration.is_some() || var.value.expression.is_none() {
+            continue;
+        }
+        if var.value.location.is_builtins() || OVERRIDABLE_CONSTS.contains(&name.as_str()) {
+            continue;
+        }
+        if let Some(decl) = ty.get_declaration(name) {
+            if decl.var_type.is_const {
+                context.register_error(DMError::new(
+                    var.value.location,
+                    format!("cannot override const var '{}' on {}", name, ty.pretty_path()),
+                ));
+            }
+        }
+    }
+}
+
+/// Report `color` vars set to a string which looks like a hex color but is
+/// not one.
+fn check_color_vars(context: &Context, ty: TypeRef) {
+    if let Some(var) = ty.get().vars.get("color") {
+        if let Some(text) = var.value.constant.as_ref().and_then(malformed_color) {
+            context.register_error(DMError::new(
+                var.value.location,
+                format!("malformed color \"{}\" on {}", text, ty.pretty_path()),
+            ).set_severity(Severity::Warning));
+        }
+    }
+}
+
+/// The text of a constant which starts with `#` but is not a valid color.
+fn malformed_color(constant: &Constant) -> Option<&str> {
+    match constant.as_str() {
+        Some(text) if text.starts_with('#') && Color::parse(text).is_none() => Some(text),
+        _ => None,
+    }
+}
+
+/// Whether an assignment target is a `color` var, as in `color` or
+/// `x.color`.
+fn is_color_var(lhs: &Expression) -> bool {
+    match *lhs {
+        Expression::Base { term: Term::Ident(ref name), ref follow, .. } => match follow.last() {
+            None => name == "color",
+            Some(&Follow::Field(_, ref field)) => field == "color",
+            Some(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// Report text macros which BYOND ignores in the strings of var values.
+fn check_var_text_macros(context: &Context, ty: TypeRef) {
+    for var in ty.get().vars.values() {
+        if var.value.location.file == FileId::builtins() {
+            continue;
+        }
+        if let Some(ref expr) = var.value.expression {
+            each_subexpr(expr, &mut |expr: &Expression| {
+                let format = match *expr {
+                    Expression::Base { ref term, .. } => term.string_format(),
+                    _ => None,
+                };
+                for each in format.iter().flat_map(|format| format.macro_uses()) {
+                    if let Some(reason) = each.ignored_reason() {
+                        context.register_error(DMError::new(
+                            var.value.location,
+                            format!("{} on {}", reason, ty.pretty_path()),
+                        ).set_severity(Severity::Warning));
+                    }
+                }
+            });
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Proc body checks
+
+/// Warns when a local var hides a var of `src` with the same name.
+pub const LINT_SHADOWED_SRC_VARS: &str = "shadowed-src-vars";
+/// Warns when a local var hides a global var with the same name.
+pub const LINT_SHADOWED_GLOBAL_VARS: &str = "shadowed-global-vars";
+/// Warns when an unqualified call matches neither a proc of `src` nor a global proc.
+pub const LINT_UNDEFINED_PROCS: &str = "undefined-procs";
+/// Warns when `del()` is used on a datum rather than the codebase's
+/// preferred deletion proc.
+pub const LINT_HARD_DELETES: &str = "hard-deletes";
+/// Warns when statements follow `del(src)`, which ends the proc.
+pub const LINT_CODE_AFTER_DEL_SRC: &str = "code-after-del-src";
+/// The lint option naming the preferred deletion proc, `qdel` by default.
+pub const OPTION_DEL_REPLACEMENT: &str = "del_replacement";
+/// Warns when `x.foo` names a var or proc which the declared type of `x`
+/// lacks, taking `istype()` checks into account.
+pub const LINT_UNDEFINED_FIELDS: &str = "undefined-fields";
+/// Warns when a `color` var is set to a string starting with `#` which is
+/// not a valid `#rgb`, `#rgba`, `#rrggbb`, or `#rrggbbaa` color.
+pub const LINT_MALFORMED_COLORS: &str = "malformed-colors";
+/// Warns when a text macro such as `\the` or `\improper` is placed where
+/// BYOND ignores it, or applies to an expression of the wrong kind.
+pub const LINT_TEXT_MACROS: &str = "text-macros";
+/// Warns when an assignment such as `if(x = 1)` is used as a condition.
+pub const LINT_ASSIGNMENT_CONDITIONS: &str = "assignment-conditions";
+
+/// Vars which every proc has without declaring them.
+const IMPLICIT_VARS: &[&str] = &["src", "usr", "args", "."];
+
+/// What an unqualified var name in a proc body refers to.
+enum Binding<'a> {
+    Local { is_const: bool },
+    Parameter,
+    Implicit,
+    Src(&'a VarDeclaration),
+    Global(&'a VarDeclaration),
+    Unknown,
+}
+
+/// What is known about the value of a var or expression.
+#[derive(Copy, Clone)]
+enum StaticType<'a> {
+    /// Nothing is known.
+    Unknown,
+    /// An instance of the given type.
+    Type(TypeRef<'a>),
+    /// A list of instances of the given type, nested some number of times,
+    /// as declared by `var/list/obj/L`.
+    List(usize, TypeRef<'a>),
+}
+
+impl<'a> StaticType<'a> {
+    /// The type of an element of this list, if it is a typed list.
+    fn element(self) -> StaticType<'a> {
+        match self {
+            StaticType::List(1, ty) => StaticType::Type(ty),
+            StaticType::List(depth, ty) => StaticType::List(depth - 1, ty),
+            _ => StaticType::Unknown,
+        }
+    }
+}
+
+struct ProcChecker<'a> {
+    context: &'a Context,
+    tree: &'a ObjectTree,
+    root: TypeRef<'a>,
+    ty: TypeRef<'a>,
+    proc_name: &'a str,
+    proc: &'a ProcValue,
+    /// Local vars in scope, innermost block last, with whether each is const
+    /// and its declared type.
+    locals: Vec<Vec<(&'a str, bool, StaticType<'a>)>>,
+    /// Vars known to be of a more specific type than declared, such as
+    /// within `if(istype(x, /obj))`, innermost block last.
+    /// Vars known to be non-null are recorded without a type.
+    narrowed: Vec<Vec<(&'a str, Option<TypeRef<'a>>)>>,
+    /// Labels defined anywhere in the proc.
+    labels: Vec<&'a str>,
+    /// Targets of `goto` statements, checked once the whole proc is seen.
+    gotos: Vec<&'a str>,
+    /// Where the innermost statement being checked starts, which is more
+    /// precise than the location of the proc.
+    statement_at: Option<Location>,
+    /// Where the innermost string expression being checked starts, which is
+    /// more precise still.
+    embedded_at: Option<Location>,
+    /// The types of locals declared without one, where they can be told
+    /// from the value.
+    inferred: Vec<(Location, StaticType<'a>)>,
+}
+
+impl<'a> ProcChecker<'a> {
+    fn new(context: &'a Context, tree: &'a ObjectTree, ty: TypeRef<'a>, proc_name: &'a str, proc: &'a ProcValue) -> ProcChecker<'a> {
+        ProcChecker {
+            context,
+            tree,
+            root: tree.root(),
+            ty,
+            proc_name,
+            proc,
+            locals: Vec::new(),
+            narrowed: Vec::new(),
+            labels: Vec::new(),
+            gotos: Vec::new(),
+            statement_at: None,
+            embedded_at: None,
+            inferred: Vec::new(),
+        }
+    }
+
+    fn warning(&self, message: String) {
+        self.context.register_error(self.make_warning(message));
+    }
+
+    /// Build a warning about this proc, to have notes added before it is
+    /// registered.
+    fn make_warning(&self, message: String) -> DMError {
+        DMError::new(
+            self.embedded_at.or(self.statement_at).unwrap_or(self.proc.location),
+            format!("{}/proc/{}: {}", self.ty.path, self.proc_name, message),
+        ).set_severity(Severity::Warning)
+    }
+
+    fn declare(&mut self, name: &'a str, var_type: &VarType) {
+        if self.context.lint_enabled(LINT_SHADOWED_GLOBAL_VARS) {
+            if let Some(decl) = self.root.get().vars.get(name).and_then(|var| var.declaration.as_ref()) {
+                if decl.location.file != FileId::builtins() {
+                    self.context.register_error(self.make_warning(format!("local var '{}' shadows global var", name))
+                        .with_note(decl.location, "global var declared here"));
+                }
+            }
+        }
+        if self.context.lint_enabled(LINT_SHADOWED_SRC_VARS) {
+            if let Some(decl) = self.src_var(name) {
+                let mut error = self.make_warning(format!("local var '{}' shadows src.{}", name, name));
+                if !decl.location.is_builtins() {
+                    error = error.with_note(decl.location, "src var declared here");
+                }
+                self.context.register_error(error);
+            }
+        }
+        let ty = self.type_of_path(&var_type.type_path);
+        self.locals.last_mut()
+            .expect("declaration outside of any block")
+            .push((name, var_type.is_const, ty));
+    }
+
+    /// Find the type named by a declared type path, such as `obj` or
+    /// `list/obj`.
+    fn type_of_path(&self, path: &[String]) -> StaticType<'a> {
+        let depth = path.iter().take_while(|each| *each == "list").count();
+        if depth == path.len() {
+            return StaticType::Unknown;
+        }
+        match self.tree.type_by_path(&path[depth..]) {
+            Some(ty) if depth == 0 => StaticType::Type(ty),
+            Some(ty) => StaticType::List(depth, ty),
+            None => StaticType::Unknown,
+        }
+    }
+
+    /// Find the type named by an absolute prefab such as `/obj/item`.
+    fn type_of_prefab(&self, prefab: &Prefab) -> Option<TypeRef<'a>> {
+        match prefab.path.first() {
+            Some(&(PathOp::Slash, _)) => {}
+            _ => return None,
+        }
+        if prefab.path.iter().any(|&(op, _)| op != PathOp::Slash) {
+            return None;
+        }
+        self.tree.type_by_path(prefab.path.iter().map(|&(_, ref name)| name))
+    }
+
+    /// Determine the static type of an unqualified var.
+    fn static_type(&self, name: &str) -> StaticType<'a> {
+        if let Some(ty) = self.narrowed.iter().flatten().rev().filter(|&&(var, _)| var == name).filter_map(|&(_, ty)| ty).next() {
+            return StaticType::Type(ty);
+        }
+        for scope in self.locals.iter().rev() {
+            if let Some(&(_, _, ty)) = scope.iter().rev().find(|&&(local, _, _)| local == name) {
+                return ty;
+            }
+        }
+        if let Some(param) = self.proc.parameters.iter().find(|param| param.name == name) {
+            return self.type_of_path(&param.path);
+        }
+        match name {
+            "src" if !self.ty.is_root() => StaticType::Type(self.ty),
+            "usr" => self.tree.find("/mob").map_or(StaticType::Unknown, StaticType::Type),
+            _ => match self.resolve_var(name) {
+                Binding::Src(decl) |
+                Binding::Global(decl) => self.type_of_path(&decl.var_type.type_path),
+                _ => StaticType::Unknown,
+            },
+        }
+    }
+
+    /// Determine the static type of an expression, if it is a var followed
+    /// by fields and indexes or a `new` instance.
+    fn expr_type(&self, expr: &Expression) -> StaticType<'a> {
+        match *expr {
+            Expression::Base { ref unary, term: Term::Ident(ref name), ref follow } if unary.is_empty() => {
+                self.follow_type(name, follow, false)
+            }
+            Expression::Base { ref unary, term: Term::Expr(ref inner), ref follow } if unary.is_empty() && follow.is_empty() => {
+                self.expr_type(inner)
+            }
+            Expression::Base { ref unary, term: Term::New { type_: NewType::Prefab(ref prefab), .. }, ref follow } if unary.is_empty() && follow.is_empty() => {
+                self.type_of_prefab(prefab).map_or(StaticType::Unknown, StaticType::Type)
+            }
+            Expression::Base { ref unary, term: Term::Call(ref name, _), ref follow } if unary.is_empty() && follow.is_empty() => {
+                match self.resolve_proc(name) {
+                    Some(proc) if proc.location.is_builtins() => {
+                        self.tree.builtin_return_type(name).map_or(StaticType::Unknown, StaticType::Type)
+                    }
+                    _ => StaticType::Unknown,
+                }
+            }
+            _ => StaticType::Unknown,
+        }
+    }
+
+    /// Collect the vars which `cond` being true proves to be of some type,
+    /// or to be non-null.
+    fn narrowing(&self, cond: &'a Expression, out: &mut Vec<(&'a str, Option<TypeRef<'a>>)>) {
+        match *cond {
+            Expression::BinaryOp { op: BinaryOp::And, ref lhs, ref rhs } => {
+                self.narrowing(lhs, out);
+                self.narrowing(rhs, out);
+            }
+            Expression::Base { ref unary, ref term, ref follow } if follow.is_empty() => match (&unary[..], term) {
+                (&[], &Term::Expr(ref inner)) => self.narrowing(inner, out),
+                (&[], _) => self.narrow_call(term, out),
+                (&[UnaryOp::Not], &Term::Expr(ref inner)) => self.negative_narrowing(inner, out),
+                (&[UnaryOp::Not], _) => self.narrow_isnull(term, out),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Collect the vars which `cond` being false proves to be of some type,
+    /// as in `if(!istype(x, /obj)) return`, or to be non-null.
+    fn negative_narrowing(&self, cond: &'a Expression, out: &mut Vec<(&'a str, Option<TypeRef<'a>>)>) {
+        match *cond {
+            Expression::BinaryOp { op: BinaryOp::Or, ref lhs, ref rhs } => {
+                self.negative_narrowing(lhs, out);
+                self.negative_narrowing(rhs, out);
+            }
+            Expression::Base { ref unary, ref term, ref follow } if follow.is_empty() => match (&unary[..], term) {
+                (&[], &Term::Expr(ref inner)) => self.negative_narrowing(inner, out),
+                (&[], _) => self.narrow_isnull(term, out),
+                (&[UnaryOp::Not], &Term::Expr(ref inner)) => self.narrowing(inner, out),
+                (&[UnaryOp::Not], _) => self.narrow_call(term, out),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Narrow the var checked by `istype(x, /path)`.
+    fn narrow_call(&self, term: &'a Term, out: &mut Vec<(&'a str, Option<TypeRef<'a>>)>) {
+        if let Term::Call(ref name, ref args) = *term {
+            if name != "istype" || args.len() != 2 {
+                return;
+            }
+            if let (Some(&Term::Ident(ref var)), Some(&Term::Prefab(ref prefab))) = (args[0].as_term(), args[1].as_term()) {
+                if let Some(ty) = self.type_of_prefab(prefab) {
+                    out.push((var, Some(ty)));
+                }
+            }
+        }
+    }
+
+    /// Mark the var checked by `isnull(x)` as non-null, for when the call
+    /// is known to be false.
+    fn narrow_isnull(&self, term: &'a Term, out: &mut Vec<(&'a str, Option<TypeRef<'a>>)>) {
+        if let Term::Call(ref name, ref args) = *term {
+            if name != "isnull" || args.len() != 1 {
+                return;
+            }
+            if let Some(&Term::Ident(ref var)) = args[0].as_term() {
+                out.push((var, None));
+            }
+        }
+    }
+
+    /// Determine the static type of `name` followed by fields and indexes.
+    ///
+    /// If `report` is set, warns of deprecated fields and procs, and of
+    /// missing ones if the `undefined-fields` lint is enabled.
+    fn follow_type(&self, name: &str, follow: &[Follow], report: bool) -> StaticType<'a> {
+        let undefined = report && self.context.lint_enabled(LINT_UNDEFINED_FIELDS);
+        let mut current = self.static_type(name);
+        for each in follow.iter() {
+            current = match (each, current) {
+                (&Follow::Index(_), list) |
+                (&Follow::SafeIndex(_), list) => list.element(),
+                // ':' is how DM code opts out of this check
+                (&Follow::Field(IndexKind::Dot, ref field), StaticType::Type(ty)) |
+                (&Follow::Field(IndexKind::SafeDot, ref field), StaticType::Type(ty)) => {
+                    match ty.get_declaration(field) {
+                        Some(decl) => {
+                            if report {
+                                self.check_deprecated("var", field, decl.deprecated.as_ref());
+                            }
+                            self.type_of_path(&decl.var_type.type_path)
+                        }
+                        None => {
+                            if undefined {
+                                self.warning(format!("undefined field '{}' on {}", field, ty.pretty_path()));
+                            }
+                            return StaticType::Unknown;
+                        }
+                    }
+                }
+                (&Follow::Call(IndexKind::Dot, ref field, _), StaticType::Type(ty)) |
+                (&Follow::Call(IndexKind::SafeDot, ref field, _), StaticType::Type(ty)) => {
+                    if report {
+                        if let Some(decl) = ty.get_proc_declaration(field) {
+                            self.check_deprecated("proc", field, decl.deprecated.as_ref());
+                        }
+                    }
+                    if undefined && ty.get_proc(field).is_none() {
+                        self.warning(format!("undefined proc '{}' on {}", field, ty.pretty_path()));
+                    }
+                    return StaticType::Unknown;
+                }
+                (&Follow::Call(_, ref proc_name, ref args), list @ StaticType::List(..)) => {
+                    if undefined && proc_name == "Add" {
+                        for arg in args.iter() {
+                            self.check_list_add(list, arg);
+                        }
+                    }
+                    return StaticType::Unknown;
+                }
+                _ => return StaticType::Unknown,
+            };
+        }
+        current
+    }
+
+    /// Warn if a value added to a typed list can never be of its element
+    /// type.
+    fn check_list_add(&self, list: StaticType<'a>, value: &Expression) {
+        let (element, value) = match (list.element(), self.expr_type(value)) {
+            (StaticType::Type(element), StaticType::Type(value)) => (element, value),
+            _ => return,
+        };
+        if !value.is_subtype_of(element.get()) && !element.is_subtype_of(value.get()) {
+            self.warning(format!("adding {} to a list of {}", value.pretty_path(), element.pretty_path()));
+        }
+    }
+
+    /// Look up a var declared on the type of `src`, if there is one.
+    fn src_var(&self, name: &str) -> Option<&'a VarDeclaration> {
+        if self.ty.is_root() {
+            None
+        } else {
+            self.ty.get_declaration(name)
+        }
+    }
+
+    /// Resolve an unqualified var name as DM does: locals, then parameters,
+    /// then the implicit proc vars, then vars of `src`, then globals.
+    fn resolve_var(&self, name: &str) -> Binding<'a> {
+        for scope in self.locals.iter().rev() {
+            if let Some(&(_, is_const, _)) = scope.iter().rev().find(|&&(local, _, _)| local == name) {
+                return Binding::Local { is_const };
+            }
+        }
+        if self.proc.parameters.iter().any(|param| param.name == name) {
+            return Binding::Parameter;
+        }
+        if IMPLICIT_VARS.contains(&name) {
+            return Binding::Implicit;
+        }
+        if let Some(decl) = self.src_var(name) {
+            return Binding::Src(decl);
+        }
+        match self.root.get_declaration(name) {
+            Some(decl) => Binding::Global(decl),
+            None => Binding::Unknown,
+        }
+    }
+
+    /// Resolve an unqualified proc name: procs of `src`, then global procs.
+    fn resolve_proc(&self, name: &str) -> Option<&'a ProcValue> {
+        if !self.ty.is_root() {
+            if let Some(proc) = self.ty.get_proc(name) {
+                return Some(proc);
+            }
+        }
+        self.root.get_proc(name)
+    }
+
+    /// Resolve the declaration of an unqualified proc name, as with
+    /// `resolve_proc`.
+    fn resolve_proc_declaration(&self, name: &str) -> Option<&'a ProcDeclaration> {
+        if !self.ty.is_root() {
+            if let Some(decl) = self.ty.get_proc_declaration(name) {
+                return Some(decl);
+            }
+        }
+        self.root.get_proc_declaration(name)
+    }
+
+    fn assign(&mut self, name: &str) {
+        let is_const = match self.resolve_var(name) {
+            Binding::Local { is_const } => is_const,
+            Binding::Src(decl) |
+            Binding::Global(decl) => decl.var_type.is_const,
+            Binding::Parameter |
+            Binding::Implicit |
+            Binding::Unknown => false,
+        };
+        if is_const {
+            self.warning(format!("cannot assign to const var '{}'", name));
+        }
+        // whatever is assigned need not match an earlier istype()
+        for scope in self.narrowed.iter_mut() {
+            scope.retain(|&(var, _)| var != name);
+        }
+    }
+
+    /// Warn if a builtin is newer than the targeted BYOND version.
+    fn check_builtin_version(&self, path: &str) {
+        if let (Some(target), Some(since)) = (self.context.byond_version(), builtins::introduced_in(path)) {
+            if since > target {
+                self.warning(format!("{} requires BYOND {}, but {} is targeted", path, since, target));
+            }
+        }
+    }
+
+    /// Warn of a use of a deprecated var or proc, with its note if any.
+    fn check_deprecated(&self, kind: &str, name: &str, deprecated: Option<&String>) {
+        match deprecated.map(|note| note.as_str()) {
+            None => {}
+            Some("") => self.warning(format!("use of deprecated {} '{}'", kind, name)),
+            Some(note) => self.warning(format!("use of deprecated {} '{}': {}", kind, name, note)),
+        }
+    }
+
+    fn check_var_version(&self, name: &str) {
+        let decl = match self.resolve_var(name) {
+            Binding::Src(decl) |
+            Binding::Global(decl) => decl,
+            _ => return,
+        };
+        self.check_deprecated("var", name, decl.deprecated.as_ref());
+        if decl.location.file != FileId::builtins() {
+            return;
+        }
+        // find the type which declares it
+        let mut current = Some(if self.ty.get_declaration(name).is_some() { self.ty } else { self.root });
+        while let Some(ty) = current {
+            if ty.get().vars.get(name).map_or(false, |var| var.declaration.is_some()) {
+                self.check_builtin_version(&format!("{}/var/{}", ty.path, name));
+                return;
+            }
+            current = ty.parent_type();
+        }
+    }
+
+    fn check_prefab_version(&self, prefab: &Prefab) {
+        let mut path = String::new();
+        for &(op, ref name) in prefab.path.iter() {
+            path.push_str(&op.to_string());
+            path.push_str(name);
+            self.check_builtin_version(&path);
+        }
+    }
+
+    /// Check the argument count and any literal arguments of a builtin call.
+    fn check_builtin_call(&self, name: &str, signature: &builtins::BuiltinSignature, args: &[Expression]) {
+        if has_arglist(args) {
+            return;
+        }
+
+        let plural = |n| if n == 1 { "" } else { "s" };
+        if args.len() < signature.min_args {
+            self.warning(format!("'{}' takes at least {} argument{}, got {}",
+                name, signature.min_args, plural(signature.min_args), args.len()));
+        }
+        if let Some(max) = signature.max_args {
+            if args.len() > max {
+                self.warning(format!("'{}' takes at most {} argument{}, got {}",
+                    name, max, plural(max), args.len()));
+            }
+        }
+
+        for (i, (arg, &expected)) in args.iter().zip(signature.args.iter()).enumerate() {
+            let found = match arg.as_term().and_then(literal_kind) {
+                Some(found) => found,
+                None => continue,
+            };
+            if expected != builtins::ArgKind::Any && found != expected {
+                self.warning(format!("'{}' argument {} should be {}, got {}",
+                    name, i + 1, expected.name(), found.name()));
+            }
+        }
+    }
+
+    /// Check a call to a function in a native library against the functions
+    /// declared for that library.
+    fn check_external_call(&self, first: &[Expression], second: &[Expression]) {
+        let custom = self.context.custom_builtins();
+        let declared = &custom.external;
+        if declared.is_empty() || first.len() != 2 {
+            return;
+        }
+        // only functions named by a literal can be checked
+        let name = match first[1].as_term() {
+            Some(&Term::String(ref name)) => name,
+            _ => return,
+        };
+        let library = match first[0].as_term() {
+            Some(&Term::String(ref path)) => Some(builtins::library_name(path)),
+            _ => None,
+        };
+
+        let mut candidates = declared.iter()
+            .filter(|func| library.map_or(true, |library| builtins::library_name(&func.library) == library))
+            .peekable();
+        if candidates.peek().is_none() {
+            // a library the settings say nothing about
+            return;
+        }
+        match candidates.find(|func| func.name == *name) {
+            None => self.warning(format!("unknown external function '{}'", name)),
+            Some(func) => if !has_arglist(second) && second.len() != func.args {
+                let plural = if func.args == 1 { "" } else { "s" };
+                self.warning(format!("external function '{}' takes {} argument{}, got {}",
+                    name, func.args, plural, second.len()));
+            },
+        }
+    }
+
+    fn check_gotos(&self) {
+        for target in self.gotos.iter() {
+            if !self.labels.contains(target) {
+                self.warning(format!("goto targets missing label '{}'", target));
+            }
+        }
+    }
+
+    /// Check an expression whose truth decides control flow.
+    fn condition(&mut self, cond: &'a Expression) {
+        self.check_assignment_condition(cond);
+        self.visit_expression(cond);
+    }
+
+    /// Warn about `if(x = 1)`, which is almost always a typo for `==`.
+    fn check_assignment_condition(&self, cond: &Expression) {
+        if !self.context.lint_enabled(LINT_ASSIGNMENT_CONDITIONS) {
+            return;
+        }
+        if let Expression::AssignOp { op: AssignOp::Assign, .. } = *cond {
+            self.warning("assignment used as a condition, did you mean '=='?".to_owned());
+        }
+    }
+
+    /// Warn about `del()` of a value known to be a datum.
+    fn check_hard_delete(&self, arg: &Expression) {
+        let datum = match self.tree.find("/datum") {
+            Some(datum) => datum,
+            None => return,
+        };
+        let ty = match self.expr_type(arg) {
+            StaticType::Type(ty) => ty,
+            _ => return,
+        };
+        if ty.is_subtype_of(datum.get()) {
+            let replacement = self.context.lint_option(OPTION_DEL_REPLACEMENT).unwrap_or_else(|| "qdel".to_owned());
+            self.warning(format!("del() of {}, use {}() instead", ty.pretty_path(), replacement));
+        }
+    }
+
+    /// Warn about `istype(x, /A) || istype(x, /A/B)`, where the second check
+    /// is subsumed by the first.
+    fn check_redundant_istype(&self, disjuncts: &[&'a Expression]) {
+        let mut checks = Vec::new();
+        for each in disjuncts.iter() {
+            if let Some(&Term::Call(ref name, ref args)) = each.as_term() {
+                if name == "istype" && args.len() == 2 {
+                    if let (Some(&Term::Ident(ref var)), Some(&Term::Prefab(ref prefab))) = (args[0].as_term(), args[1].as_term()) {
+                        if let Some(ty) = self.type_of_prefab(prefab) {
+                            checks.push((var.as_str(), ty));
+                        }
+                    }
+                }
+            }
+        }
+        for (i, &(var, ty)) in checks.iter().enumerate() {
+            let subsumed_by = checks.iter().enumerate().find(|&(j, &(other_var, other_ty))| {
+                j != i && other_var == var && ty.is_subtype_of(other_ty.get())
+                    // of two identical checks, report only the second
+                    && (j < i || !other_ty.is_subtype_of(ty.get()))
+            });
+            if let Some((_, &(_, other_ty))) = subsumed_by {
+                self.warning(format!(
+                    "istype({}, {}) is redundant with istype({}, {})",
+                    var, ty.pretty_path(), var, other_ty.pretty_path(),
+                ));
+            }
+        }
+    }
+
+    fn var(&mut self, var: &'a VarStatement) {
+        if let Some(ref value) = var.value {
+            self.visit_expression(value);
+            if var.var_type.type_path.is_empty() {
+                match self.expr_type(value) {
+                    StaticType::Unknown => {}
+                    known => self.inferred.push((var.location, known)),
+                }
+            }
+        }
+        self.declare(&var.name, &var.var_type);
+    }
+
+    /// Check an argument list, where `name = value` is a named argument or
+    /// association rather than an assignment.
+    fn arguments(&mut self, args: &'a [Expression]) {
+        for arg in args.iter() {
+            match *arg {
+                Expression::AssignOp { op: AssignOp::Assign, ref lhs, ref rhs } => {
+                    if lhs.as_term().is_none() {
+                        self.visit_expression(lhs);
+                    }
+                    self.visit_expression(rhs);
+                }
+                ref other => self.visit_expression(other),
+            }
+        }
+    }
+
+    /// Check the text macros of a string, given its embedded expressions.
+    fn check_text_macros(&self, term: &Term, parts: &[(Option<Expression>, String, Location)]) {
+        let format = match term.string_format() {
+            Some(format) => format,
+            None => return,
+        };
+        for each in format.macro_uses() {
+            if let Some(reason) = each.ignored_reason() {
+                self.warning(reason);
+                continue;
+            }
+            let target = match each.target {
+                MacroTarget::Embed(idx) => match parts.get(idx) {
+                    Some(&(Some(ref expr), _, _)) => expr,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            let literal = match *target {
+                Expression::Base { ref unary, ref term, ref follow } if follow.is_empty() => {
+                    if unary.is_empty() { literal_kind(term) } else { Some(builtins::ArgKind::Num) }
+                }
+                _ => None,
+            };
+            let wrong = match (literal, self.expr_type(target)) {
+                (Some(builtins::ArgKind::Num), _) if !each.wants_number() => "a number".to_owned(),
+                (Some(builtins::ArgKind::Text), _) => "text".to_owned(),
+                (_, StaticType::Type(ty)) if each.wants_number() => format!("{}, which is not a number", ty.pretty_path()),
+                (_, StaticType::Type(ty)) if !self.is_atom(ty) && each.name != "ref" => {
+                    format!("{}, which is not an atom", ty.pretty_path())
+                }
+                _ => continue,
+            };
+            self.warning(format!("\\{} applied to {}", each.name, wrong));
+        }
+    }
+
+    fn is_atom(&self, ty: TypeRef) -> bool {
+        self.tree.find("/atom").map_or(true, |atom| ty.is_subtype_of(atom.get()))
+    }
+}
+
+impl<'a> Visitor<'a> for ProcChecker<'a> {
+    fn visit_block(&mut self, block: &'a [Spanned<Statement>]) {
+        self.locals.push(Vec::new());
+        self.narrowed.push(Vec::new());
+        let outer = self.statement_at;
+        for (i, statement) in block.iter().enumerate() {
+            self.statement_at = Some(statement.location);
+            if is_del_src(&statement.elem) && i + 1 < block.len() && self.context.lint_enabled(LINT_CODE_AFTER_DEL_SRC) {
+                self.warning("del(src) ends the proc, so the statements after it never run".to_owned());
+            }
+            self.visit_statement(&statement.elem);
+        }
+        self.statement_at = outer;
+        self.narrowed.pop();
+        self.locals.pop();
+    }
+
+    fn visit_statement(&mut self, statement: &'a Statement) {
+        match *statement {
+            Statement::While(ref cond, _) |
+            Statement::DoWhile(_, ref cond) => {
+                self.check_assignment_condition(cond);
+                walk_statement(self, statement);
+            }
+            Statement::If(ref arms, ref else_arm) => {
+                for (i, &(ref cond, ref block)) in arms.iter().enumerate() {
+                    if arms[..i].iter().any(|&(ref prev, _)| prev == cond) {
+                        self.warning("duplicate condition in if/else if chain".to_owned());
+                    }
+                    self.condition(cond);
+                    let mut narrowed = Vec::new();
+                    self.narrowing(cond, &mut narrowed);
+                    self.narrowed.push(narrowed);
+                    self.visit_block(block);
+                    self.narrowed.pop();
+                }
+                if let Some(ref block) = *else_arm {
+                    self.visit_block(block);
+                }
+                // `if(!istype(x, /obj)) return` narrows `x` for the rest of
+                // the enclosing block
+                if arms.len() == 1 && else_arm.is_none() && always_exits(&arms[0].1) {
+                    let mut narrowed = Vec::new();
+                    self.negative_narrowing(&arms[0].0, &mut narrowed);
+                    if let Some(scope) = self.narrowed.last_mut() {
+                        scope.extend(narrowed);
+                    }
+                }
+            }
+            Statement::ForLoop { ref test, .. } => {
+                self.locals.push(Vec::new());
+                if let Some(ref test) = *test {
+                    self.check_assignment_condition(test);
+                }
+                walk_statement(self, statement);
+                self.locals.pop();
+            }
+            Statement::ForList { ref var_type, ref name, ref in_list, ref block, .. } => {
+                self.locals.push(Vec::new());
+                if let Some(ref in_list) = *in_list {
+                    self.visit_expression(in_list);
+                }
+                match *var_type {
+                    Some(ref var_type) => {
+                        self.declare(name, var_type);
+                        // `for(var/x in L)` takes the element type of `L`
+                        if let (true, &Some(ref in_list)) = (var_type.type_path.is_empty(), in_list) {
+                            let element = self.expr_type(in_list).element();
+                            if let Some(local) = self.locals.last_mut().and_then(|scope| scope.last_mut()) {
+                                local.2 = element;
+                            }
+                        }
+                    }
+                    None => self.assign(name),
+                }
+                self.visit_block(block);
+                self.locals.pop();
+            }
+            Statement::ForRange { ref var_type, ref name, ref start, ref end, ref step, ref block } => {
+                self.locals.push(Vec::new());
+                self.visit_expression(start);
+                self.visit_expression(end);
+                if let Some(ref step) = *step {
+                    self.visit_expression(step);
+                }
+                match *var_type {
+                    Some(ref var_type) => self.declare(name, var_type),
+                    None => self.assign(name),
+                }
+                self.visit_block(block);
+                self.locals.pop();
+            }
+            Statement::Var(ref var) => self.var(var),
+            Statement::Vars(ref vars) => for var in vars.iter() {
+                self.var(var);
+            },
+            Statement::Setting(..) => {}
+            Statement::TryCatch { ref try_block, ref catch_param, ref catch_block } => {
+                self.visit_block(try_block);
+                self.locals.push(Vec::new());
+                match *catch_param {
+                    Some((Some(ref var_type), ref name)) => self.declare(name, var_type),
+                    Some((None, ref name)) => self.assign(name),
+                    None => {}
+                }
+                self.visit_block(catch_block);
+                self.locals.pop();
+            }
+            Statement::Label(ref name, _) => {
+                self.labels.push(name);
+                walk_statement(self, statement);
+            }
+            Statement::Goto(ref target) => self.gotos.push(target),
+            _ => walk_statement(self, statement),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &'a Expression) {
+        match *expr {
+            Expression::Base { ref unary, ref term, ref follow } => {
+                let modifies = unary.iter().any(|op| match *op {
+                    UnaryOp::PreIncr | UnaryOp::PreDecr | UnaryOp::PostIncr | UnaryOp::PostDecr => true,
+                    _ => false,
+                });
+                if modifies && follow.is_empty() {
+                    if let Term::Ident(ref name) = *term {
+                        self.assign(name);
+                    }
+                }
+                self.visit_term(term);
+                if let Term::Ident(ref name) = *term {
+                    self.follow_type(name, follow, true);
+                }
+                // only the term itself can be proven non-null; any field,
+                // call, or index may produce null, as may a short-circuited
+                // null-safe chain
+                let mut non_null = term_is_non_null(term) || match *term {
+                    Term::Ident(ref name) => self.narrowed.iter().flatten().any(|&(var, _)| var == name),
+                    _ => false,
+                };
+                for each in follow.iter() {
+                    if non_null && each.is_null_safe() {
+                        self.warning(format!("redundant '{}' on a value which is never null", safe_operator(each)));
+                    }
+                    non_null = false;
+                    self.visit_follow(each);
+                }
+            }
+            Expression::BinaryOp { op: BinaryOp::Or, .. } => {
+                // check the whole chain at once so that `a || b || c` is
+                // checked only once
+                let mut disjuncts = Vec::new();
+                flatten_or(expr, &mut disjuncts);
+                self.check_redundant_istype(&disjuncts);
+                for each in disjuncts {
+                    self.condition(each);
+                }
+            }
+            Expression::BinaryOp { op: BinaryOp::And, ref lhs, ref rhs } => {
+                self.check_assignment_condition(lhs);
+                self.check_assignment_condition(rhs);
+                walk_expression(self, expr);
+            }
+            Expression::AssignOp { op, ref lhs, ref rhs } => {
+                if let Some(&Term::Ident(ref name)) = lhs.as_term() {
+                    self.assign(name);
+                }
+                if op == AssignOp::AddAssign && self.context.lint_enabled(LINT_UNDEFINED_FIELDS) {
+                    let list = self.expr_type(lhs);
+                    self.check_list_add(list, rhs);
+                }
+                if op == AssignOp::Assign && is_color_var(lhs) && self.context.lint_enabled(LINT_MALFORMED_COLORS) {
+                    if let Ok(constant) = constants::simple_evaluate(self.proc.location, (**rhs).clone()) {
+                        if let Some(text) = malformed_color(&constant) {
+                            self.warning(format!("malformed color \"{}\"", text));
+                        }
+                    }
+                }
+                walk_expression(self, expr);
+            }
+            _ => walk_expression(self, expr),
+        }
+    }
+
+    fn visit_term(&mut self, term: &'a Term) {
+        match *term {
+            Term::New { ref type_, ref args } => {
+                if let NewType::Prefab(ref prefab) = *type_ {
+                    self.check_prefab_version(prefab);
+                }
+                if let Some(ref args) = *args {
+                    self.arguments(args);
+                }
+            }
+            Term::Call(ref name, ref args) => {
+                if name == "del" && args.len() == 1 && self.context.lint_enabled(LINT_HARD_DELETES) {
+                    self.check_hard_delete(&args[0]);
+                }
+                let deprecated = self.resolve_proc_declaration(name).and_then(|decl| decl.deprecated.as_ref());
+                self.check_deprecated("proc", name, deprecated);
+                match self.resolve_proc(name) {
+                    Some(proc) => if proc.location.file == FileId::builtins() {
+                        self.check_builtin_version(&format!("/proc/{}", name));
+                        if let Some(signature) = self.tree.builtin_signature(name) {
+                            self.check_builtin_call(name, &signature, args);
+                        }
+                    },
+                    None => if self.context.lint_enabled(LINT_UNDEFINED_PROCS) {
+                        self.warning(format!("undefined proc '{}'", name));
+                    },
+                }
+                self.arguments(args);
+            }
+            Term::List(ref args) |
+            Term::ParentCall(ref args) |
+            Term::SelfCall(ref args) => self.arguments(args),
+            Term::Input { ref args, ref in_list, .. } |
+            Term::Locate { ref args, ref in_list } => {
+                self.arguments(args);
+                if let Some(ref in_list) = *in_list {
+                    self.visit_expression(in_list);
+                }
+            }
+            Term::Prefab(ref prefab) => {
+                self.check_prefab_version(prefab);
+                walk_term(self, term);
+            }
+            Term::DynamicCall(ref first, ref second) => {
+                // `call("library", "function")` is the older form of `call_ext`
+                if let Some(&Term::String(_)) = first.first().and_then(Expression::as_term) {
+                    self.check_external_call(first, second);
+                }
+                self.arguments(first);
+                self.arguments(second);
+            }
+            Term::ExternalCall(ref first, ref second) => {
+                self.check_builtin_version("/proc/call_ext");
+                self.check_external_call(first, second);
+                self.arguments(first);
+                self.arguments(second);
+            }
+            Term::InterpString(_, ref parts) => {
+                if self.context.lint_enabled(LINT_TEXT_MACROS) {
+                    self.check_text_macros(term, parts);
+                }
+                let outer = self.embedded_at;
+                for &(ref expr, _, location) in parts.iter() {
+                    if let Some(ref expr) = *expr {
+                        self.embedded_at = Some(location);
+                        self.visit_expression(expr);
+                    }
+                }
+                self.embedded_at = outer;
+            }
+            Term::String(_) => if self.context.lint_enabled(LINT_TEXT_MACROS) {
+                self.check_text_macros(term, &[]);
+            },
+            Term::Ident(ref name) => self.check_var_version(name),
+            _ => walk_term(self, term),
+        }
+    }
+
+    fn visit_follow(&mut self, follow: &'a Follow) {
+        match *follow {
+            Follow::Call(_, _, ref args) => self.arguments(args),
+            _ => walk_follow(self, follow),
+        }
+    }
+}
+
+/// Check whether a statement is `del(src)`, which ends the proc.
+fn is_del_src(statement: &Statement) -> bool {
+    if let Statement::Expr(ref expr) = *statement {
+        if let Some(&Term::Call(ref name, ref args)) = expr.as_term() {
+            return name == "del" && args.len() == 1 && match args[0].as_term() {
+                Some(&Term::Ident(ref arg)) => arg == "src",
+                _ => false,
+            };
+        }
+    }
+    false
+}
+
+/// Collect the operands of a chain of `||` operators, left to right.
+fn flatten_or<'a>(expr: &'a Expression, out: &mut Vec<&'a Expression>) {
+    match *expr {
+        Expression::BinaryOp { op: BinaryOp::Or, ref lhs, ref rhs } => {
+            flatten_or(lhs, out);
+            flatten_or(rhs, out);
+        }
+        _ => out.push(expr),
+    }
+}
+
+/// Check whether a block ends by leaving its enclosing block.
+fn always_exits(block: &[Spanned<Statement>]) -> bool {
+    match block.last().map(|statement| &statement.elem) {
+        Some(&Statement::Return(_)) |
+        Some(&Statement::Throw(_)) |
+        Some(&Statement::Continue(_)) |
+        Some(&Statement::Break(_)) |
+        Some(&Statement::Goto(_)) => true,
+        _ => false,
+    }
+}
+
+/// Whether the arguments of a call include `arglist()`, which supplies an
+/// unknown number of them.
+fn has_arglist(args: &[Expression]) -> bool {
+    args.iter().any(|arg| match arg.as_term() {
+        Some(&Term::Call(ref callee, _)) => callee == "arglist",
+        _ => false,
+    })
+}
+
+/// Determine the kind of a literal argument, if it is one.
+fn literal_kind(term: &Term) -> Option<builtins::ArgKind> {
+    match *term {
+        Term::Int(_) |
+        Term::Float(_) => Some(builtins::ArgKind::Num),
+        Term::String(_) |
+        Term::InterpString(..) => Some(builtins::ArgKind::Text),
+        Term::Prefab(_) => Some(builtins::ArgKind::Path),
+        Term::List(_) => Some(builtins::ArgKind::List),
+        _ => None,
+    }
+}
+
+/// Check whether a term can be proven to never evaluate to null.
+fn term_is_non_null(term: &Term) -> bool {
+    match *term {
+        Term::Int(_) |
+        Term::Float(_) |
+        Term::String(_) |
+        Term::InterpString(..) |
+        Term::Resource(_) |
+        Term::New { .. } |
+        Term::List(_) => true,
+        Term::Ident(ref name) => name == "world",
+        _ => false,
+    }
+}
+
+fn safe_operator(follow: &Follow) -> String {
+    match *follow {
+        Follow::Field(kind, _) |
+        Follow::Call(kind, _, _) => kind.to_string(),
+        _ => "?[".to_owned(),
+    }
+}
@@ -1,8 +1,10 @@
 //! Data structures for the parser to output mappings from input ranges to AST
 //! elements at those positions.
 
+use std::ops::Range;
+
 use interval_tree::{IntervalTree, RangePairIter, RangeInclusive, range};
-use super::Location;
+use super::{Location, FileId};
 use super::ast::*;
 
 pub type Iter<'a> = RangePairIter<'a, Location, Annotation>;
@@ -27,6 +29,12 @@ pub enum Annotation {
     ReturnVal,  // .
     InSequence(usize),  // where in TreePath or TypePath is this ident
 
+    // spans of whole expressions
+    Prefab(Prefab),  // the type is resolved with ObjectTree::type_by_path
+    ProcCall(Vec<String>, String),  // Vec empty for unscoped call
+    Interpolation(usize),  // which embedded expression of a string this is
+    MacroUse(String, Location),  // the location is that of the #define
+
     // error annotations, mostly for autocompletion
     ScopedMissingIdent(Vec<String>),  // when a . is followed by a non-ident
     IncompleteTypePath(TypePath, PathOp),
@@ -34,8 +42,25 @@ pub enum Annotation {
 
     ProcArguments(Vec<String>, String, usize),  // Vec empty for unscoped call
     ProcArgument(usize),  // where in the prog arguments we are
+    ProcParameter(String),  // a parameter in a proc's header
 }
 
+/// The semantic classification of an identifier, for syntax highlighting.
+///
+/// Earlier variants take priority when two annotations claim the same text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TokenClass {
+    Macro,
+    Keyword,
+    Parameter,
+    Proc,
+    Var,
+    Type,
+}
+
+const IMPLICIT_VARS: &[&str] = &["src", "usr", "args", "world", "global"];
+const VAR_MODIFIERS: &[&str] = &["static", "global", "const", "tmp", "final"];
+
 pub struct AnnotationTree {
     tree: IntervalTree<Location, Annotation>,
     len: usize,
@@ -64,6 +89,55 @@ impl AnnotationTree {
         self.tree.iter()
     }
 
+    /// Estimate the memory held by this tree, counting one tree node per
+    /// annotation along with the strings and paths each annotation owns.
+    pub fn memory_estimate(&self) -> usize {
+        use std::mem::size_of;
+
+        // a node holds its range, the maximum end beneath it, its height,
+        // two child links, and the list of annotations sharing its range
+        let node = 3 * size_of::<Location>() + 3 * size_of::<usize>() + size_of::<Vec<Annotation>>();
+        let strings = |strings: &Vec<String>| {
+            strings.capacity() * size_of::<String>() + strings.iter().map(|s| s.capacity()).sum::<usize>()
+        };
+        let type_path = |path: &TypePath| {
+            path.capacity() * size_of::<(PathOp, String)>() + path.iter().map(|&(_, ref s)| s.capacity()).sum::<usize>()
+        };
+
+        let mut total = self.len * (node + size_of::<Annotation>());
+        for (_, annotation) in self.iter() {
+            total += match *annotation {
+                Annotation::TreeBlock(ref path) |
+                Annotation::TreePath(_, ref path) |
+                Annotation::Variable(ref path) |
+                Annotation::ProcHeader(ref path, _) |
+                Annotation::ProcBody(ref path, _) |
+                Annotation::ScopedMissingIdent(ref path) |
+                Annotation::IncompleteTreePath(_, ref path) => strings(path),
+                Annotation::TypePath(ref path) |
+                Annotation::IncompleteTypePath(ref path, _) => type_path(path),
+                Annotation::LocalVarScope(ref var_type, ref name) => strings(&var_type.type_path) + name.capacity(),
+                Annotation::UnscopedCall(ref name) |
+                Annotation::UnscopedVar(ref name) |
+                Annotation::MacroUse(ref name, _) |
+                Annotation::ProcParameter(ref name) => name.capacity(),
+                Annotation::ScopedCall(ref path, ref name) |
+                Annotation::ScopedVar(ref path, ref name) |
+                Annotation::ProcCall(ref path, ref name) |
+                Annotation::ProcArguments(ref path, ref name, _) => strings(path) + name.capacity(),
+                Annotation::Prefab(ref prefab) => type_path(&prefab.path) + prefab.vars.iter().map(|(name, value)| {
+                    size_of::<(String, Expression)>() + name.capacity() + super::memory::expression(value)
+                }).sum::<usize>(),
+                Annotation::ParentCall |
+                Annotation::ReturnVal |
+                Annotation::InSequence(_) |
+                Annotation::Interpolation(_) |
+                Annotation::ProcArgument(_) => 0,
+            };
+        }
+        total
+    }
+
     pub fn get_location(&self, loc: Location) -> Iter {
         self.tree.range(range(loc.pred(), loc))
     }
@@ -75,4 +149,182 @@ impl AnnotationTree {
     pub fn get_range_raw(&self, place: RangeInclusive<Location>) -> Iter {
         self.tree.range(place)
     }
+
+    /// Find the innermost call whose argument list contains the given
+    /// location, returning its scope, the proc name, and which argument the
+    /// location is in.
+    pub fn call_arguments_at(&self, loc: Location) -> Option<(&[String], &str, usize)> {
+        let mut call: Option<(Location, &[String], &str, usize)> = None;
+        for (span, annotation) in self.get_location(loc) {
+            if let Annotation::ProcArguments(ref priors, ref proc_name, count) = *annotation {
+                if call.map_or(true, |(start, ..)| start <= span.start) {
+                    call = Some((span.start, &priors[..], &proc_name[..], count));
+                }
+            }
+        }
+        let (call_start, priors, proc_name, count) = call?;
+
+        // past the last argument means starting a new one
+        let mut index = (call_start, count);
+        for (span, annotation) in self.get_location(loc) {
+            if let Annotation::ProcArgument(i) = *annotation {
+                if span.start >= index.0 {
+                    index = (span.start, i);
+                }
+            }
+        }
+        Some((priors, proc_name, index.1))
+    }
+
+    /// Classify the identifiers in the given file, sorted by position and
+    /// without overlaps, as LSP semantic tokens require.
+    pub fn semantic_tokens(&self, file: FileId) -> Vec<(Range<Location>, TokenClass)> {
+        let mut tokens = Vec::new();
+        for (span, annotation) in self.tree.iter() {
+            if span.start.file != file {
+                continue;
+            }
+            let (len, class) = match *annotation {
+                Annotation::MacroUse(ref name, _) => (name.len(), TokenClass::Macro),
+                Annotation::ParentCall => (2, TokenClass::Keyword),
+                Annotation::ReturnVal => (1, TokenClass::Keyword),
+                Annotation::UnscopedCall(ref name) |
+                Annotation::ScopedCall(_, ref name) => (name.len(), TokenClass::Proc),
+                Annotation::ScopedVar(_, ref name) => (name.len(), TokenClass::Var),
+                Annotation::UnscopedVar(ref name) => (name.len(), self.classify_var(span.start, name)),
+                Annotation::InSequence(idx) => match self.classify_path_ident(&span, idx) {
+                    Some(each) => each,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let end = span.start.add_columns(len);
+            tokens.push((span.start..end, class));
+        }
+
+        tokens.sort_by_key(|&(ref range, class)| (range.start, class));
+        let mut result: Vec<(Range<Location>, TokenClass)> = Vec::with_capacity(tokens.len());
+        for (range, class) in tokens {
+            if result.last().map_or(true, |&(ref last, _)| last.end <= range.start) {
+                result.push((range, class));
+            }
+        }
+        result
+    }
+
+    fn classify_var(&self, loc: Location, name: &str) -> TokenClass {
+        let mut body = None;
+        for (span, annotation) in self.get_location(loc) {
+            match *annotation {
+                Annotation::LocalVarScope(_, ref local) if local == name => return TokenClass::Var,
+                Annotation::ProcBody(ref path, idx) => body = Some((span.start, path, idx)),
+                _ => {}
+            }
+        }
+        if IMPLICIT_VARS.contains(&name) {
+            return TokenClass::Keyword;
+        }
+
+        // the header ends where the body begins
+        if let Some((body_start, path, idx)) = body {
+            for (span, annotation) in self.get_location(body_start) {
+                if let Annotation::ProcHeader(ref header_path, header_idx) = *annotation {
+                    if header_path != path || header_idx != idx {
+                        continue;
+                    }
+                    for (_, annotation) in self.get_range_raw(span) {
+                        if let Annotation::ProcParameter(ref param) = *annotation {
+                            if param == name {
+                                return TokenClass::Parameter;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        TokenClass::Var
+    }
+
+    fn classify_path_ident(&self, span: &RangeInclusive<Location>, idx: usize) -> Option<(usize, TokenClass)> {
+        // find the innermost path containing this ident, and its context
+        let mut path: Option<(Location, &Annotation)> = None;
+        let mut block: Option<(Location, &[String])> = None;
+        let mut in_parameter = false;
+        let mut in_proc_body = false;
+        for (outer, annotation) in self.get_range_raw(span.clone()) {
+            if outer.start > span.start || outer.end < span.end {
+                continue;
+            }
+            match *annotation {
+                Annotation::TreePath(..) |
+                Annotation::TypePath(_) => if path.map_or(true, |(start, _)| start <= outer.start) {
+                    path = Some((outer.start, annotation));
+                },
+                Annotation::TreeBlock(ref parts) => if block.map_or(true, |(start, _)| start <= outer.start) {
+                    block = Some((outer.start, &parts[..]));
+                },
+                Annotation::ProcParameter(_) => in_parameter = true,
+                Annotation::ProcBody(..) => in_proc_body = true,
+                _ => {}
+            }
+        }
+
+        match *path?.1 {
+            Annotation::TypePath(ref parts) => {
+                let name = &parts.get(idx)?.1;
+                let class = if name == "proc" || name == "verb" {
+                    TokenClass::Keyword
+                } else if idx > 0 && (parts[idx - 1].1 == "proc" || parts[idx - 1].1 == "verb") {
+                    TokenClass::Proc
+                } else {
+                    TokenClass::Type
+                };
+                Some((name.len(), class))
+            }
+            Annotation::TreePath(absolute, ref parts) => {
+                let name = parts.get(idx)?;
+                // parameters and local var declarations end with their name
+                if in_parameter || in_proc_body {
+                    let class = if idx + 1 == parts.len() {
+                        if in_parameter { TokenClass::Parameter } else { TokenClass::Var }
+                    } else if name == "var" || VAR_MODIFIERS.contains(&&name[..]) {
+                        TokenClass::Keyword
+                    } else {
+                        TokenClass::Type
+                    };
+                    return Some((name.len(), class));
+                }
+
+                let mut full: Vec<&str> = Vec::new();
+                if !absolute {
+                    if let Some((_, parents)) = block {
+                        full.extend(parents.iter().map(|s| &s[..]));
+                    }
+                }
+                let idx = full.len() + idx;
+                full.extend(parts.iter().map(|s| &s[..]));
+                Some((name.len(), classify_tree_path(&full, idx)))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn classify_tree_path(parts: &[&str], idx: usize) -> TokenClass {
+    let name = parts[idx];
+    if name == "proc" || name == "verb" || name == "var" {
+        TokenClass::Keyword
+    } else if idx > 0 && (parts[idx - 1] == "proc" || parts[idx - 1] == "verb") {
+        TokenClass::Proc
+    } else if parts[..idx].contains(&"var") {
+        if idx + 1 == parts.len() {
+            TokenClass::Var
+        } else if VAR_MODIFIERS.contains(&name) {
+            TokenClass::Keyword
+        } else {
+            TokenClass::Type
+        }
+    } else {
+        TokenClass::Type
+    }
 }
@@ -10,6 +10,7 @@ use error::Location;
 
 /// The unary operators, both prefix and postfix.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum UnaryOp {
     Neg,
     Not,
@@ -53,6 +54,7 @@ impl<'a, T: fmt::Display> fmt::Display for Around<'a, T> {
 ///
 /// Which path operator is used typically only matters at the start of a path.
 #[derive(Copy, Clone, Hash, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum PathOp {
     /// `/` for absolute pathing.
     Slash,
@@ -80,6 +82,7 @@ pub type TypePath = Vec<(PathOp, String)>;
 
 /// The binary operators.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -87,6 +90,7 @@ pub enum BinaryOp {
     Div,
     Pow,
     Mod,
+    FloatMod,
     Eq,
     NotEq,
     Less,
@@ -116,6 +120,7 @@ impl fmt::Display for BinaryOp {
             Div => "/",
             Pow => "**",
             Mod => "%",
+            FloatMod => "%%",
             Eq => "==",
             NotEq => "!=",
             Less => "<",
@@ -137,8 +142,20 @@ impl fmt::Display for BinaryOp {
     }
 }
 
+impl BinaryOp {
+    /// Check whether a chain of this operator groups from the left, so that
+    /// `a op b op c` means `(a op b) op c`.
+    pub fn is_left_associative(self) -> bool {
+        match self {
+            BinaryOp::In | BinaryOp::To => false,
+            _ => true,
+        }
+    }
+}
+
 /// The assignment operators, including augmented assignment.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum AssignOp {
     Assign,
     AddAssign,
@@ -146,11 +163,14 @@ pub enum AssignOp {
     MulAssign,
     DivAssign,
     ModAssign,
+    FloatModAssign,
     BitAndAssign,
     BitOrAssign,
     BitXorAssign,
     LShiftAssign,
     RShiftAssign,
+    /// `:=`, which assigns into the value of the left-hand side.
+    AssignInto,
 }
 
 impl fmt::Display for AssignOp {
@@ -163,11 +183,13 @@ impl fmt::Display for AssignOp {
             MulAssign => "*=",
             DivAssign => "/=",
             ModAssign => "%=",
+            FloatModAssign => "%%=",
             BitAndAssign => "&=",
             BitXorAssign => "^=",
             BitOrAssign => "|=",
             LShiftAssign => "<<=",
             RShiftAssign => ">>=",
+            AssignInto => ":=",
         })
     }
 }
@@ -200,6 +222,8 @@ augmented! {
     Sub = SubAssign;
     Mul = MulAssign;
     Div = DivAssign;
+    Mod = ModAssign;
+    FloatMod = FloatModAssign;
     BitAnd = BitAndAssign;
     BitOr = BitOrAssign;
     BitXor = BitXorAssign;
@@ -209,8 +233,14 @@ augmented! {
 
 /// A path optionally followed by a set of variables.
 #[derive(Clone, Hash, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "E: ::serde::Serialize",
+    deserialize = "E: ::serde::Deserialize<'de>",
+)))]
 pub struct Prefab<E=Expression> {
     pub path: TypePath,
+    #[cfg_attr(feature = "serde", serde(with = "::serde_helpers::linked_hash_map"))]
     pub vars: LinkedHashMap<String, E>,
 }
 
@@ -246,6 +276,7 @@ impl<E: fmt::Display> fmt::Display for Prefab<E> {
 
 /// The different forms of the `new` command.
 #[derive(Clone, Hash, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum NewType<E=Expression> {
     /// Implicit type, taken from context.
     Implicit,
@@ -267,6 +298,7 @@ impl<E: fmt::Display> fmt::Display for NewType<E> {
 
 /// The structure of an expression, a tree of terms and operators.
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum Expression {
     /// An expression containing a term directly. The term is evaluated first,
     /// then its follows, then its unary operators in reverse order.
@@ -345,8 +377,72 @@ impl From<Term> for Expression {
     }
 }
 
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expression::Base { ref unary, ref term, ref follow } => {
+                let mut text = term.to_string();
+                for each in follow.iter() {
+                    text.push_str(&each.to_string());
+                }
+                for op in unary.iter().rev() {
+                    text = op.around(&text).to_string();
+                }
+                f.write_str(&text)
+            }
+            Expression::BinaryOp { op, ref lhs, ref rhs } => {
+                // chains of the same operator read naturally without parens
+                match **lhs {
+                    Expression::BinaryOp { op: lhs_op, .. } if lhs_op == op && op.is_left_associative() => write!(f, "{}", lhs)?,
+                    _ => Operand(lhs).fmt(f)?,
+                }
+                write!(f, " {} ", op)?;
+                Operand(rhs).fmt(f)
+            }
+            Expression::AssignOp { op, ref lhs, ref rhs } => {
+                write!(f, "{} {} ", Operand(lhs), op)?;
+                Operand(rhs).fmt(f)
+            }
+            Expression::TernaryOp { ref cond, ref if_, ref else_ } => {
+                write!(f, "{} ? {} : {}", Operand(cond), Operand(if_), Operand(else_))
+            }
+        }
+    }
+}
+
+/// Formatting helper which parenthesizes an expression if it is not a
+/// single term, so that it keeps its meaning as an operand.
+struct Operand<'a>(&'a Expression);
+
+impl<'a> fmt::Display for Operand<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.0 {
+            Expression::Base { .. } => self.0.fmt(f),
+            _ => write!(f, "({})", self.0),
+        }
+    }
+}
+
+/// Formatting helper for a comma-separated argument list.
+struct Arguments<'a>(&'a [Expression]);
+
+impl<'a> fmt::Display for Arguments<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for each in self.0.iter() {
+            if !first {
+                f.write_str(", ")?;
+            }
+            first = false;
+            write!(f, "{}", each)?;
+        }
+        Ok(())
+    }
+}
+
 /// The structure of a term, the basic building block of the AST.
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum Term {
     /// The literal `null`.
     Null,
@@ -394,8 +490,13 @@ pub enum Term {
     Expr(Box<Expression>),
     /// A use of the `call()()` primitive.
     DynamicCall(Vec<Expression>, Vec<Expression>),
-    /// An interpolated string, alternating string/expr/string/expr.
-    InterpString(String, Vec<(Option<Expression>, String)>),
+    /// A use of the `call_ext()()` primitive, which calls a function in a
+    /// native library.
+    ExternalCall(Vec<Expression>, Vec<Expression>),
+    /// An interpolated string, alternating string/expr/string/expr. Each
+    /// embedded expression is followed by the text after it and by where it
+    /// starts inside the string literal.
+    InterpString(String, Vec<(Option<Expression>, String, Location)>),
 }
 
 impl From<Expression> for Term {
@@ -414,8 +515,85 @@ impl From<Expression> for Term {
     }
 }
 
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Term::Null => f.write_str("null"),
+            Term::New { ref type_, ref args } => {
+                write!(f, "new{}", type_)?;
+                if let Some(ref args) = *args {
+                    write!(f, "({})", Arguments(args))?;
+                }
+                Ok(())
+            }
+            Term::List(ref args) => write!(f, "list({})", Arguments(args)),
+            Term::Input { ref args, input_type, ref in_list } => {
+                write!(f, "input({})", Arguments(args))?;
+                if !input_type.is_empty() {
+                    write!(f, " as {}", input_type)?;
+                }
+                if let Some(ref in_list) = *in_list {
+                    write!(f, " in {}", Operand(in_list))?;
+                }
+                Ok(())
+            }
+            Term::Locate { ref args, ref in_list } => {
+                write!(f, "locate({})", Arguments(args))?;
+                if let Some(ref in_list) = *in_list {
+                    write!(f, " in {}", Operand(in_list))?;
+                }
+                Ok(())
+            }
+            Term::Pick(ref args) => {
+                f.write_str("pick(")?;
+                let mut first = true;
+                for &(ref weight, ref value) in args.iter() {
+                    if !first {
+                        f.write_str(", ")?;
+                    }
+                    first = false;
+                    if let Some(ref weight) = *weight {
+                        write!(f, "{}; ", weight)?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str(")")
+            }
+            Term::Call(ref name, ref args) => write!(f, "{}({})", name, Arguments(args)),
+            Term::ParentCall(ref args) => write!(f, "..({})", Arguments(args)),
+            Term::SelfCall(ref args) => write!(f, ".({})", Arguments(args)),
+            Term::Prefab(ref prefab) => prefab.fmt(f),
+            Term::Ident(ref name) => f.write_str(name),
+            Term::String(ref text) => ::lexer::Quote(text).fmt(f),
+            Term::Resource(ref path) => write!(f, "'{}'", path),
+            Term::Int(value) => write!(f, "{}", value),
+            Term::Float(value) => if value.is_finite() {
+                write!(f, "{}", value)
+            } else {
+                ::lexer::FormatFloat(value).fmt(f)
+            },
+            Term::Expr(ref expr) => write!(f, "({})", expr),
+            Term::DynamicCall(ref first, ref second) => write!(f, "call({})({})", Arguments(first), Arguments(second)),
+            Term::ExternalCall(ref first, ref second) => write!(f, "call_ext({})({})", Arguments(first), Arguments(second)),
+            Term::InterpString(ref begin, ref parts) => {
+                let mut text = begin.clone();
+                for &(ref expr, ref part, _) in parts.iter() {
+                    text.push('[');
+                    if let Some(ref expr) = *expr {
+                        text.push_str(&expr.to_string());
+                    }
+                    text.push(']');
+                    text.push_str(part);
+                }
+                ::lexer::Quote(&text).fmt(f)
+            }
+        }
+    }
+}
+
 /// The possible kinds of index operators, for both fields and methods.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum IndexKind {
     /// `a.b`
     Dot,
@@ -451,17 +629,50 @@ impl fmt::Display for IndexKind {
 
 /// An expression part which is applied to a term or another follow.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum Follow {
     /// Index the value by an expression.
     Index(Box<Expression>),
+    /// Index the value by an expression, unless it is null: `a?[b]`.
+    SafeIndex(Box<Expression>),
     /// Access a field of the value.
     Field(IndexKind, String),
     /// Call a method of the value.
     Call(IndexKind, String, Vec<Expression>),
 }
 
+impl Follow {
+    /// Check whether this follow evaluates to null rather than failing when
+    /// applied to null.
+    ///
+    /// Once one null-safe follow appears, the rest of the chain is skipped
+    /// when it short-circuits, so the value of the whole chain may be null.
+    pub fn is_null_safe(&self) -> bool {
+        match *self {
+            Follow::SafeIndex(_) |
+            Follow::Field(IndexKind::SafeDot, _) |
+            Follow::Field(IndexKind::SafeColon, _) |
+            Follow::Call(IndexKind::SafeDot, _, _) |
+            Follow::Call(IndexKind::SafeColon, _, _) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Follow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Follow::Index(ref expr) => write!(f, "[{}]", expr),
+            Follow::SafeIndex(ref expr) => write!(f, "?[{}]", expr),
+            Follow::Field(kind, ref name) => write!(f, "{}{}", kind, name),
+            Follow::Call(kind, ref name, ref args) => write!(f, "{}{}({})", kind, name, Arguments(args)),
+        }
+    }
+}
+
 /// A parameter declaration in the header of a proc.
 #[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct Parameter {
     pub path: Vec<String>,
     pub name: String,
@@ -518,6 +729,21 @@ macro_rules! type_table {
                 Ok(())
             }
         }
+
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_u32(self.bits())
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let bits: u32 = ::serde::Deserialize::deserialize(deserializer)?;
+                Ok($name::from_bits_truncate(bits))
+            }
+        }
     }
 }
 
@@ -547,15 +773,61 @@ type_table! {
 }
 
 /// A type which may be ascribed to a `var`.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct VarType {
+    /// Declared `var/static` or `var/global`, which are synonyms.
     pub is_static: bool,
+    /// Declared `var/const`.
     pub is_const: bool,
+    /// Declared `var/tmp`.
     pub is_tmp: bool,
+    /// The declared type, with any modifiers removed.
     pub type_path: TreePath,
 }
 
 impl VarType {
+    /// Check whether a path element is a modifier like `static` rather than
+    /// part of the declared type.
+    pub fn is_modifier(element: &str) -> bool {
+        match element {
+            "global" | "static" | "const" | "tmp" => true,
+            _ => false,
+        }
+    }
+
+    /// Apply a modifier to this type, returning `false` if the element is
+    /// not a modifier.
+    pub fn apply_modifier(&mut self, element: &str) -> bool {
+        match element {
+            "global" | "static" => self.is_static = true,
+            "const" => self.is_const = true,
+            "tmp" => self.is_tmp = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// The number of times `list` is nested in the declared type.
+    ///
+    /// `var/list/L` has a depth of 1 and `var/list/list/L` a depth of 2.
+    pub fn list_depth(&self) -> usize {
+        self.type_path.iter().take_while(|each| *each == "list").count()
+    }
+
+    /// Check whether this var was declared as a list.
+    #[inline]
+    pub fn is_list(&self) -> bool {
+        self.list_depth() > 0
+    }
+
+    /// The declared type with any `list` prefixes removed.
+    ///
+    /// For `var/list/obj/L`, this is `obj`.
+    pub fn element_type(&self) -> &[String] {
+        &self.type_path[self.list_depth()..]
+    }
+
     #[inline]
     pub fn is_const_evaluable(&self) -> bool {
         self.is_const || (!self.is_static && !self.is_tmp)
@@ -569,29 +841,18 @@ impl VarType {
 
 impl FromIterator<String> for VarType {
     fn from_iter<T: IntoIterator<Item=String>>(iter: T) -> Self {
-        let (mut is_static, mut is_const, mut is_tmp) = (false, false, false);
+        let mut var_type = VarType {
+            is_static: false,
+            is_const: false,
+            is_tmp: false,
+            type_path: Vec::new(),
+        };
         let type_path = iter
             .into_iter()
-            .skip_while(|p| {
-                if p == "global" || p == "static" {
-                    is_static = true;
-                    true
-                } else if p == "const" {
-                    is_const = true;
-                    true
-                } else if p == "tmp" {
-                    is_tmp = true;
-                    true
-                } else {
-                    false
-                }
-            }).collect();
-        VarType {
-            is_static,
-            is_const,
-            is_tmp,
-            type_path,
-        }
+            .skip_while(|p| var_type.apply_modifier(p))
+            .collect();
+        var_type.type_path = type_path;
+        var_type
     }
 }
 
@@ -614,20 +875,38 @@ impl fmt::Display for VarType {
     }
 }
 
+/// A node along with the location where it begins.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Spanned<T> {
+    pub location: Location,
+    pub elem: T,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(location: Location, elem: T) -> Spanned<T> {
+        Spanned { location, elem }
+    }
+}
+
+/// A sequence of statements, such as a proc body.
+pub type Block = Vec<Spanned<Statement>>;
+
 /// A statement in a proc body.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum Statement {
     Expr(Expression),
     Return(Option<Expression>),
     Throw(Expression),
-    While(Expression, Vec<Statement>),
-    DoWhile(Vec<Statement>, Expression),
-    If(Vec<(Expression, Vec<Statement>)>, Option<Vec<Statement>>),
+    While(Expression, Block),
+    DoWhile(Block, Expression),
+    If(Vec<(Expression, Block)>, Option<Block>),
     ForLoop {
         init: Option<Box<Statement>>,
         test: Option<Expression>,
         inc: Option<Box<Statement>>,
-        block: Vec<Statement>,
+        block: Block,
     },
     ForList {
         var_type: Option<VarType>,
@@ -636,7 +915,7 @@ pub enum Statement {
         input_type: InputType,
         /// Defaults to 'world'.
         in_list: Option<Expression>,
-        block: Vec<Statement>,
+        block: Block,
     },
     ForRange {
         var_type: Option<VarType>,
@@ -644,37 +923,45 @@ pub enum Statement {
         start: Expression,
         end: Expression,
         step: Option<Expression>,
-        block: Vec<Statement>,
+        block: Block,
     },
     Var(VarStatement),
     Vars(Vec<VarStatement>),
     Setting(String, SettingMode, Expression),
-    Spawn(Option<Expression>, Vec<Statement>),
-    Switch(Expression, Vec<(Vec<Case>, Vec<Statement>)>, Option<Vec<Statement>>),
+    Spawn(Option<Expression>, Block),
+    Switch(Expression, Vec<(Vec<Case>, Block)>, Option<Block>),
     TryCatch {
-        try_block: Vec<Statement>,
-        catch_params: Vec<TreePath>,
-        catch_block: Vec<Statement>,
+        try_block: Block,
+        /// The variable which receives the thrown value, if any. The type
+        /// is present if declared in place, as in `catch(var/exception/e)`.
+        catch_param: Option<(Option<VarType>, String)>,
+        catch_block: Block,
     },
     Continue(Option<String>),
     Break(Option<String>),
-    Label(String, Vec<Statement>),
+    Label(String, Block),
+    Goto(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct VarStatement {
     pub var_type: VarType,
     pub name: String,
     pub value: Option<Expression>,
+    /// The location of the token following the name.
+    pub location: Location,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum SettingMode {
     Assign,
     In,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum Case {
     Exact(Expression),
     Range(Expression, Expression),
@@ -0,0 +1,289 @@
+//! Structural comparison of two object trees.
+//!
+//! Types are matched by path, and vars and procs by name. Only vars and procs
+//! which a type itself declares or overrides are compared, so a change to a
+//! parent's default is reported once rather than on every subtype. Source
+//! locations are ignored, so moving code between files is not a change.
+use std::fmt;
+
+use ast::{Parameter, Statement, Spanned, Block, Expression, Term};
+use constants::Constant;
+use objtree::{ObjectTree, TypeRef, TypeVar, ProcValue, Code};
+use visit::{VisitorMut, walk_block_mut, walk_statement_mut, walk_term_mut};
+use Location;
+
+/// A difference between two object trees.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    TypeAdded {
+        path: String,
+    },
+    TypeRemoved {
+        path: String,
+    },
+    ParentChanged {
+        path: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    VarAdded {
+        path: String,
+        name: String,
+        value: Option<Constant>,
+    },
+    VarRemoved {
+        path: String,
+        name: String,
+        value: Option<Constant>,
+    },
+    VarChanged {
+        path: String,
+        name: String,
+        old: Option<Constant>,
+        new: Option<Constant>,
+    },
+    ProcAdded {
+        path: String,
+        name: String,
+    },
+    ProcRemoved {
+        path: String,
+        name: String,
+    },
+    /// A proc's parameters or body changed.
+    ProcChanged {
+        path: String,
+        name: String,
+    },
+}
+
+impl Change {
+    /// The path of the type this change concerns, which is empty for global
+    /// vars and procs.
+    pub fn path(&self) -> &str {
+        match *self {
+            Change::TypeAdded { ref path } |
+            Change::TypeRemoved { ref path } |
+            Change::ParentChanged { ref path, .. } |
+            Change::VarAdded { ref path, .. } |
+            Change::VarRemoved { ref path, .. } |
+            Change::VarChanged { ref path, .. } |
+            Change::ProcAdded { ref path, .. } |
+            Change::ProcRemoved { ref path, .. } |
+            Change::ProcChanged { ref path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::TypeAdded { ref path } => write!(f, "+ {}", path),
+            Change::TypeRemoved { ref path } => write!(f, "- {}", path),
+            Change::ParentChanged { ref path, ref old, ref new } => write!(f, "~ {}: parent_type {} -> {}",
+                path, Value(old.as_ref()), Value(new.as_ref())),
+            Change::VarAdded { ref path, ref name, ref value } => write!(f, "+ {}/var/{} = {}",
+                path, name, Value(value.as_ref())),
+            Change::VarRemoved { ref path, ref name, ref value } => write!(f, "- {}/var/{} = {}",
+                path, name, Value(value.as_ref())),
+            Change::VarChanged { ref path, ref name, ref old, ref new } => write!(f, "~ {}/var/{}: {} -> {}",
+                path, name, Value(old.as_ref()), Value(new.as_ref())),
+            Change::ProcAdded { ref path, ref name } => write!(f, "+ {}/proc/{}", path, name),
+            Change::ProcRemoved { ref path, ref name } => write!(f, "- {}/proc/{}", path, name),
+            Change::ProcChanged { ref path, ref name } => write!(f, "~ {}/proc/{}", path, name),
+        }
+    }
+}
+
+/// Displays a value which may not be known, such as a var whose default is
+/// not a constant expression.
+struct Value<T>(Option<T>);
+
+impl<T: fmt::Display> fmt::Display for Value<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(ref value) => write!(f, "{}", value),
+            None => f.write_str("(unknown)"),
+        }
+    }
+}
+
+/// Compare two object trees, such as two versions of the same environment.
+///
+/// Changes are sorted by type path, with the changes to each type in the
+/// order its vars and procs are declared.
+pub fn compare(old: &ObjectTree, new: &ObjectTree) -> Vec<Change> {
+    let mut changes = Vec::new();
+    compare_type(&mut changes, old.root(), new.root());
+    for (path, &idx) in old.types.iter() {
+        match new.types.get(path) {
+            Some(&new_idx) => compare_type(&mut changes, TypeRef::new(old, idx), TypeRef::new(new, new_idx)),
+            None => changes.push(Change::TypeRemoved { path: path.clone() }),
+        }
+    }
+    for path in new.types.keys() {
+        if !old.types.contains_key(path) {
+            changes.push(Change::TypeAdded { path: path.clone() });
+        }
+    }
+    // stable, so each type's own changes keep their order
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    changes
+}
+
+fn compare_type(changes: &mut Vec<Change>, old: TypeRef, new: TypeRef) {
+    let path = &old.get().path;
+
+    let old_parent = old.parent_type().map(|ty| ty.get().path.clone());
+    let new_parent = new.parent_type().map(|ty| ty.get().path.clone());
+    if old_parent != new_parent {
+        changes.push(Change::ParentChanged {
+            path: path.clone(),
+            old: old_parent,
+            new: new_parent,
+        });
+    }
+
+    for (name, old_var) in old.get().vars.iter() {
+        match new.get().vars.get(name) {
+            Some(new_var) => if !same_var(old_var, new_var) {
+                changes.push(Change::VarChanged {
+                    path: path.clone(),
+                    name: name.clone(),
+                    old: old_var.value.constant.clone(),
+                    new: new_var.value.constant.clone(),
+                });
+            },
+            None => changes.push(Change::VarRemoved {
+                path: path.clone(),
+                name: name.clone(),
+                value: old_var.value.constant.clone(),
+            }),
+        }
+    }
+    for (name, new_var) in new.get().vars.iter() {
+        if !old.get().vars.contains_key(name) {
+            changes.push(Change::VarAdded {
+                path: path.clone(),
+                name: name.clone(),
+                value: new_var.value.constant.clone(),
+            });
+        }
+    }
+
+    for (name, old_proc) in old.get().procs.iter() {
+        match new.get().procs.get(name) {
+            Some(new_proc) => if !same_proc(old_proc.value.last(), new_proc.value.last()) {
+                changes.push(Change::ProcChanged {
+                    path: path.clone(),
+                    name: name.clone(),
+                });
+            },
+            None => changes.push(Change::ProcRemoved {
+                path: path.clone(),
+                name: name.clone(),
+            }),
+        }
+    }
+    for name in new.get().procs.keys() {
+        if !old.get().procs.contains_key(name) {
+            changes.push(Change::ProcAdded {
+                path: path.clone(),
+                name: name.clone(),
+            });
+        }
+    }
+}
+
+fn same_var(old: &TypeVar, new: &TypeVar) -> bool {
+    // fall back to the expression when the value is not a constant
+    old.value.constant == new.value.constant
+        && (old.value.constant.is_some() || same_expr(&old.value.expression, &new.value.expression))
+        && old.declaration.as_ref().map(|d| &d.var_type) == new.declaration.as_ref().map(|d| &d.var_type)
+}
+
+fn same_proc(old: Option<&ProcValue>, new: Option<&ProcValue>) -> bool {
+    let (old, new) = match (old, new) {
+        (Some(old), Some(new)) => (old, new),
+        (None, None) => return true,
+        _ => return false,
+    };
+    if old.parameters.len() != new.parameters.len()
+        || old.parameters.iter().zip(new.parameters.iter()).any(|(a, b)| !same_parameter(a, b))
+    {
+        return false;
+    }
+    match (&old.code, &new.code) {
+        (&Code::Present(ref a), &Code::Present(ref b)) => same_block(a, b),
+        (&Code::Lazy(ref a), &Code::Lazy(ref b)) => {
+            a.tokens().len() == b.tokens().len()
+                && a.tokens().iter().zip(b.tokens().iter()).all(|(a, b)| a.token == b.token)
+        }
+        (&Code::Invalid, &Code::Invalid) | (&Code::Disabled, &Code::Disabled) => true,
+        _ => false,
+    }
+}
+
+fn same_parameter(old: &Parameter, new: &Parameter) -> bool {
+    old.path == new.path
+        && old.name == new.name
+        && same_expr(&old.default, &new.default)
+        && old.input_type == new.input_type
+        && same_expr(&old.in_list, &new.in_list)
+}
+
+fn same_block(old: &[Spanned<Statement>], new: &[Spanned<Statement>]) -> bool {
+    if old.len() != new.len() {
+        return false;
+    }
+    let (mut old, mut new) = (old.to_vec(), new.to_vec());
+    StripLocations.visit_block(&mut old);
+    StripLocations.visit_block(&mut new);
+    old == new
+}
+
+fn same_expr(old: &Option<Expression>, new: &Option<Expression>) -> bool {
+    match (old, new) {
+        (&Some(ref old), &Some(ref new)) => {
+            let (mut old, mut new) = (old.clone(), new.clone());
+            StripLocations.visit_expression(&mut old);
+            StripLocations.visit_expression(&mut new);
+            old == new
+        }
+        (&None, &None) => true,
+        _ => false,
+    }
+}
+
+/// Clears the locations of local var declarations and of embedded
+/// expressions in strings.
+struct StripLocations;
+
+impl VisitorMut for StripLocations {
+    fn visit_block(&mut self, block: &mut Block) {
+        for statement in block.iter_mut() {
+            statement.location = Location::default();
+        }
+        walk_block_mut(self, block);
+    }
+
+    fn visit_statement(&mut self, statement: &mut Statement) {
+        match *statement {
+            Statement::Var(ref mut var) => var.location = Location::default(),
+            Statement::Vars(ref mut vars) => for var in vars.iter_mut() {
+                var.location = Location::default();
+            },
+            _ => {}
+        }
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_term(&mut self, term: &mut Term) {
+        if let Term::InterpString(_, ref mut parts) = *term {
+            for part in parts.iter_mut() {
+                part.2 = Location::default();
+            }
+        }
+        walk_term_mut(self, term);
+    }
+}
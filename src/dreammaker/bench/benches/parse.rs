@@ -0,0 +1,101 @@
+//! Lexer and parser throughput benchmarks, run with `cargo bench` from
+//! this directory.
+//!
+//! By default these run over synthetic DM generated below. Set
+//! `DM_BENCH_ENVIRONMENT` to the path of a `.dme` file to also benchmark
+//! parsing a real codebase.
+#[macro_use] extern crate criterion;
+extern crate dreammaker as dm;
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use criterion::{Benchmark, Criterion, Throughput};
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+
+/// Generate an environment with a spread of types, vars, and procs.
+fn synthetic(types: usize) -> String {
+    let mut code = String::new();
+    for i in 0..types {
+        let _ = write!(code, r#"
+/obj/item/thing{i}
+    name = "thing {i}"
+    desc = "A thing with [{i}] in it."
+    var/list/contents_cache = list()
+    var/const/MAX_{i} = {i} * 2 + 1
+
+/obj/item/thing{i}/proc/process(mob/user, amount = 1)
+    if(!user || amount <= 0)
+        return 0
+    for(var/obj/item/I in contents_cache)
+        if(istype(I, /obj/item/thing{i}) && I.name != name)
+            amount += length(I.name) * MAX_{i}
+        else
+            contents_cache -= I
+    switch(amount)
+        if(0 to 10)
+            user << "small [{i}]"
+        if(11 to 100)
+            user << "medium"
+        else
+            spawn(10)
+                process(user, amount / 2)
+    return ..() ? amount : null
+"#, i = i);
+    }
+    code
+}
+
+fn parse(code: &str) -> dm::objtree::ObjectTree {
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.parse_object_tree()
+}
+
+fn synthetic_benchmarks(c: &mut Criterion) {
+    let code = synthetic(200);
+    let len = code.len() as u32;
+
+    let lex_code = code.clone();
+    c.bench("lexer", Benchmark::new("synthetic", move |b| b.iter(|| {
+        let context = dm::Context::default();
+        Lexer::new(&context, Default::default(), lex_code.bytes().map(Ok)).count()
+    })).throughput(Throughput::Bytes(len)));
+
+    c.bench("parser", Benchmark::new("synthetic", move |b| b.iter(|| {
+        parse(&code)
+    })).throughput(Throughput::Bytes(len)));
+}
+
+fn environment_benchmarks(c: &mut Criterion) {
+    let dme = match ::std::env::var_os("DM_BENCH_ENVIRONMENT") {
+        Some(path) => PathBuf::from(path),
+        None => return,
+    };
+
+    // measure the size of the environment once, to report throughput
+    let context = dm::Context::default();
+    context.parse_environment(&dme).expect("i/o error opening environment");
+    let throughput = context.throughput();
+    println!(
+        "{}: {} bytes, {} tokens, {:.0} bytes/s, {:.0} tokens/s",
+        dme.display(),
+        throughput.bytes,
+        throughput.tokens,
+        throughput.bytes_per_second(),
+        throughput.tokens_per_second(),
+    );
+
+    c.bench("environment", Benchmark::new("parse", move |b| b.iter(|| {
+        let context = dm::Context::default();
+        context.parse_environment(&dme).expect("i/o error opening environment")
+    })).sample_size(10).throughput(Throughput::Bytes(throughput.bytes as u32)));
+}
+
+criterion_group!(benches, synthetic_benchmarks, environment_benchmarks);
+criterion_main!(benches);
@@ -2,11 +2,236 @@
 
 use super::objtree::*;
 use super::ast::*;
-use super::{Location, FileId, DMError};
+use super::{Location, FileId, DMError, Context};
 use super::preprocessor::{DefineMap, Define};
 
+/// The BYOND version which the unversioned builtins below correspond to.
+pub const BASE_BYOND_VERSION: u32 = 512;
+
+/// Builtins added since `BASE_BYOND_VERSION`, with the version which added
+/// each.
+const VERSIONED_BUILTINS: &[(&str, u32)] = &[
+    ("/proc/clamp", 513),
+    ("/proc/copytext_char", 513),
+    ("/proc/findlasttext_char", 513),
+    ("/proc/findlasttextEx_char", 513),
+    ("/proc/findtext_char", 513),
+    ("/proc/findtextEx_char", 513),
+    ("/proc/length_char", 513),
+    ("/proc/nonspantext_char", 513),
+    ("/proc/replacetext_char", 513),
+    ("/proc/replacetextEx_char", 513),
+    ("/proc/spantext_char", 513),
+    ("/proc/splittext_char", 513),
+    ("/proc/text2ascii_char", 513),
+    ("/atom/var/render_source", 513),
+    ("/atom/var/render_target", 513),
+    ("/proc/generator", 514),
+    ("/proc/gradient", 514),
+    ("/particles", 514),
+    ("/atom/var/particles", 514),
+    ("/world/var/movement_mode", 514),
+    ("/proc/nameof", 515),
+    ("/proc/refcount", 515),
+    ("/proc/trimtext", 515),
+    ("/proc/call_ext", 515),
+];
+
+/// Look up the BYOND version which added a builtin type, proc, or var, if it
+/// is newer than `BASE_BYOND_VERSION`.
+pub fn introduced_in(path: &str) -> Option<u32> {
+    VERSIONED_BUILTINS.iter().find(|&&(each, _)| each == path).map(|&(_, version)| version)
+}
+
+/// The kind of value a builtin proc expects for an argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    Any,
+    Num,
+    Text,
+    Path,
+    List,
+}
+
+impl ArgKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ArgKind::Any => "anything",
+            ArgKind::Num => "a number",
+            ArgKind::Text => "text",
+            ArgKind::Path => "a type path",
+            ArgKind::List => "a list",
+        }
+    }
+}
+
+/// The calling convention and effects of a builtin global proc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuiltinSignature {
+    pub min_args: usize,
+    /// `None` if any number of arguments may follow.
+    pub max_args: Option<usize>,
+    /// The kind of each leading argument; any after these may be anything.
+    pub args: &'static [ArgKind],
+    /// Whether the result depends only on the arguments, without side effects.
+    pub is_pure: bool,
+    /// Whether calling the proc may sleep.
+    pub sleeps: bool,
+}
+
+fn pure_proc(min_args: usize, max_args: Option<usize>, args: &'static [ArgKind]) -> BuiltinSignature {
+    BuiltinSignature { min_args, max_args, args, is_pure: true, sleeps: false }
+}
+
+fn impure_proc(min_args: usize, max_args: Option<usize>, args: &'static [ArgKind]) -> BuiltinSignature {
+    BuiltinSignature { min_args, max_args, args, is_pure: false, sleeps: false }
+}
+
+fn sleeping_proc(min_args: usize, max_args: Option<usize>, args: &'static [ArgKind]) -> BuiltinSignature {
+    BuiltinSignature { min_args, max_args, args, is_pure: false, sleeps: true }
+}
+
+/// Look up the signature of a builtin global proc, for those which are known.
+pub fn builtin_signature(name: &str) -> Option<BuiltinSignature> {
+    use self::ArgKind::*;
+    Some(match name {
+        "abs" | "arccos" | "arcsin" | "cos" | "sin" | "sqrt" => pure_proc(1, Some(1), &[Num]),
+        "clamp" => pure_proc(3, Some(3), &[Num, Num, Num]),
+        "log" | "round" => pure_proc(1, Some(2), &[Num, Num]),
+        "max" | "min" => pure_proc(1, None, &[]),
+        "turn" => pure_proc(2, Some(2), &[Any, Num]),
+        "ascii2text" => pure_proc(1, Some(1), &[Num]),
+        "num2text" => pure_proc(1, Some(3), &[Num, Num, Num]),
+        "text2num" | "text2ascii" => pure_proc(1, Some(2), &[Text, Num]),
+        "text2path" | "ckey" | "ckeyEx" | "lowertext" | "uppertext" | "html_encode" |
+        "html_decode" | "url_decode" | "params2list" | "json_decode" => pure_proc(1, Some(1), &[Text]),
+        "url_encode" => pure_proc(1, Some(2), &[Text, Num]),
+        "copytext" | "copytext_char" => pure_proc(1, Some(3), &[Text, Num, Num]),
+        "findtext" | "findtextEx" | "findlasttext" | "findlasttextEx" => pure_proc(2, Some(4), &[Text, Any, Num, Num]),
+        "replacetext" | "replacetextEx" => pure_proc(3, Some(5), &[Text, Any, Any, Num, Num]),
+        "splittext" => pure_proc(2, Some(5), &[Text, Any, Num, Num, Num]),
+        "jointext" => pure_proc(2, Some(4), &[List, Text, Num, Num]),
+        "length" | "isnull" | "isnum" | "istext" | "isfile" | "isicon" => pure_proc(1, Some(1), &[]),
+        "isarea" | "isloc" | "ismob" | "isobj" | "isturf" => pure_proc(1, None, &[]),
+        "ispath" | "istype" => pure_proc(1, Some(2), &[Any, Path]),
+        "typesof" => pure_proc(1, None, &[]),
+        "arglist" | "list2params" => pure_proc(1, Some(1), &[List]),
+        "json_encode" => pure_proc(1, Some(2), &[Any, Num]),
+        "del" => impure_proc(1, Some(1), &[]),
+        "get_dir" | "get_dist" | "flick" => impure_proc(2, Some(2), &[]),
+        "locate" => impure_proc(0, Some(3), &[]),
+        "prob" | "rand_seed" => impure_proc(1, Some(1), &[Num]),
+        "rand" => impure_proc(0, Some(2), &[Num, Num]),
+        "sleep" => sleeping_proc(0, Some(1), &[Num]),
+        "alert" => sleeping_proc(1, Some(6), &[]),
+        "shell" => sleeping_proc(0, Some(1), &[Text]),
+        "winexists" => sleeping_proc(2, Some(2), &[Any, Text]),
+        "winget" => sleeping_proc(3, Some(3), &[Any, Text, Text]),
+        _ => return None,
+    })
+}
+
+/// A native proc which a codebase adds to the builtins, such as one provided
+/// by an auxtools or rust-g library, as declared in its settings.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct CustomProc {
+    /// The path of the proc, such as `/proc/rustg_file_read`.
+    pub path: String,
+    /// The names of the parameters.
+    pub params: Vec<String>,
+    /// The fewest arguments which may be passed, if not all of them.
+    pub min_args: Option<usize>,
+    /// The type path of the value returned, if one is declared.
+    pub return_type: Option<String>,
+    /// Whether calling the proc may sleep.
+    pub sleeps: bool,
+}
+
+impl CustomProc {
+    pub fn signature(&self) -> BuiltinSignature {
+        BuiltinSignature {
+            min_args: self.min_args.unwrap_or(self.params.len()),
+            max_args: Some(self.params.len()),
+            args: &[],
+            is_pure: false,
+            sleeps: self.sleeps,
+        }
+    }
+}
+
+/// A function in a native library, called through `call_ext()()`.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ExternalFunction {
+    /// The library, such as `rust_g`, with or without its extension.
+    pub library: String,
+    /// The exported name of the function.
+    pub name: String,
+    /// The number of arguments the function expects.
+    pub args: usize,
+}
+
+/// The name of a native library without its directory or extension, so that
+/// `rust_g`, `rust_g.dll`, and `bin/librust_g.so` are all the same.
+pub fn library_name(path: &str) -> &str {
+    let file = path.rsplit(|c: char| c == '/' || c == '\\').next().unwrap_or(path);
+    if file.ends_with(".so") && file.starts_with("lib") {
+        &file[3..file.len() - 3]
+    } else {
+        match file.rfind('.') {
+            Some(idx) => &file[..idx],
+            None => file,
+        }
+    }
+}
+
+/// The natives which a codebase adds to the builtins.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct CustomBuiltins {
+    pub procs: Vec<CustomProc>,
+    /// The paths of vars, such as `/datum/var/native_id`.
+    pub vars: Vec<String>,
+    /// The functions of native libraries. Calls to a library with any
+    /// functions declared here are checked against them.
+    pub external: Vec<ExternalFunction>,
+}
+
+/// Split a configured path such as `/proc/foo` into its elements.
+pub fn path_elements(path: &str) -> Vec<&str> {
+    path.split('/').filter(|each| !each.is_empty()).collect()
+}
+
+/// Register a codebase's natives into an object tree which already has the
+/// builtins, reporting any which are malformed.
+pub fn register_custom_builtins(tree: &mut ObjectTree, context: &Context) {
+    let location = Location {
+        file: FileId::builtins(),
+        line: 1,
+        column: 1,
+    };
+    let custom = context.custom_builtins();
+    for path in custom.vars.iter() {
+        let elems = path_elements(path);
+        if let Err(e) = tree.add_entry(location, elems.iter().cloned(), elems.len() + 1, Default::default()) {
+            context.register_error(DMError::new(location, format!("bad custom builtin {}: {}", path, e.description())));
+        }
+    }
+    for proc in custom.procs.iter() {
+        let elems = path_elements(&proc.path);
+        let params = proc.params.iter().map(|name| Parameter {
+            name: name.clone(),
+            .. Default::default()
+        }).collect();
+        if let Err(e) = tree.add_proc(location, elems.iter().cloned(), elems.len() + 1, params) {
+            context.register_error(DMError::new(location, format!("bad custom builtin {}: {}", proc.path, e.description())));
+        }
+    }
+}
+
 /// Register BYOND builtin macros to the given define map.
-pub fn default_defines(defines: &mut DefineMap) {
+pub fn default_defines(defines: &mut DefineMap, version: u32) {
     use super::lexer::Token::*;
     let location = Location {
         file: FileId::builtins(),
@@ -24,7 +249,7 @@ pub fn default_defines(defines: &mut DefineMap) {
         }
     }
     c! {
-        DM_VERSION = Int(512);
+        DM_VERSION = Int(version as i32);
 
         // eye and sight
         SEEINVIS = Int(2);
@@ -265,6 +490,7 @@ pub fn register_builtins(tree: &mut ObjectTree) -> Result<(), DMError> {
         proc/cmptextEx(T1,T2/*,...*/);
         proc/copytext(T,Start/*=1*/,End/*=0*/);
         proc/cos(X);
+        proc/del(Object);
         proc/fcopy(Src,Dst);
         proc/fcopy_rsc(File);
         proc/fdel(File);
@@ -752,6 +978,35 @@ pub fn register_builtins(tree: &mut ObjectTree) -> Result<(), DMError> {
         image/var/override;
         image/var/transform;
         mutable_appearance/parent_type = path!(/image);
+
+        // added in 513, see VERSIONED_BUILTINS
+        proc/clamp(Number, Low, High);
+        proc/copytext_char(T,Start=1,End=0);
+        proc/findlasttext_char(Haystack,Needle,Start=0,End=1);
+        proc/findlasttextEx_char(Haystack,Needle,Start=0,End=1);
+        proc/findtext_char(Haystack,Needle,Start=1,End=0);
+        proc/findtextEx_char(Haystack,Needle,Start=1,End=0);
+        proc/length_char(E);
+        proc/nonspantext_char(Haystack,Needles,Start=1);
+        proc/replacetext_char(Haystack,Needle,Replacement,Start=1,End=0);
+        proc/replacetextEx_char(Haystack,Needle,Replacement,Start=1,End=0);
+        proc/spantext_char(Haystack,Needles,Start=1);
+        proc/splittext_char(Text,Delimiter,Start=1,End=0,include_delimiters=0);
+        proc/text2ascii_char(T,pos=1);
+        atom/var/render_source;
+        atom/var/render_target;
+
+        // added in 514
+        proc/generator(type, A, B, rand);
+        proc/gradient(Gradient, index);
+        particles;
+        atom/var/particles;
+        world/var/movement_mode;
+
+        // added in 515
+        proc/nameof(Var);  // special form
+        proc/refcount(Object);
+        proc/trimtext(Text);
     };
 
     Ok(())
@@ -0,0 +1,108 @@
+//! A C ABI over the parser, for map editors and game tools not written in
+//! Rust.
+//!
+//! Build a shared library with:
+//!
+//! ```text
+//! cargo rustc -p dreammaker --release --features capi -- --crate-type cdylib
+//! ```
+//!
+//! A C header for the exported functions:
+//!
+//! ```c
+//! typedef struct SdmmEnvironment SdmmEnvironment;
+//!
+//! SdmmEnvironment *sdmm_parse_environment(const char *dme);
+//! char *sdmm_get_errors(const SdmmEnvironment *env);
+//! void sdmm_free(SdmmEnvironment *env);
+//! void sdmm_free_string(char *string);
+//! ```
+//!
+//! Strings are UTF-8 and NUL-terminated. Structured results are returned as
+//! JSON strings, which the caller releases with `sdmm_free_string`. No
+//! function unwinds into the caller; a panic is reported as a null return.
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use objtree::ObjectTree;
+use Context;
+
+/// A parsed environment, opaque to C.
+pub struct SdmmEnvironment {
+    context: Context,
+    tree: ObjectTree,
+}
+
+fn to_c_string(string: String) -> *mut c_char {
+    // JSON output escapes NUL, so this only fails on a bug
+    CString::new(string).map(CString::into_raw).unwrap_or(ptr::null_mut())
+}
+
+/// Parse the `.dme` file at the given path, including everything it
+/// includes.
+///
+/// Returns null if the path is null or not UTF-8, if the file cannot be
+/// read, or if parsing panics. Problems in the code itself do not cause a
+/// null return; retrieve them with `sdmm_get_errors`. The environment must
+/// be released with `sdmm_free`.
+#[no_mangle]
+pub unsafe extern "C" fn sdmm_parse_environment(dme: *const c_char) -> *mut SdmmEnvironment {
+    if dme.is_null() {
+        return ptr::null_mut();
+    }
+    let dme = match CStr::from_ptr(dme).to_str() {
+        Ok(dme) => dme.to_owned(),
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let result = panic::catch_unwind(|| {
+        let context = Context::default();
+        let tree = context.parse_environment(Path::new(&dme)).ok()?;
+        Some(SdmmEnvironment { context, tree })
+    });
+    match result {
+        Ok(Some(env)) => Box::into_raw(Box::new(env)),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Get the diagnostics produced while parsing an environment.
+///
+/// Returns a JSON array of objects with `file`, `line`, `column`,
+/// `end_line`, `end_column`, `severity` (one of `error`, `warning`,
+/// `info`, or `hint`), `message`, and `notes`, or null if the environment
+/// is null. Each note has a `file`, `line`, `column`, and `message`. The string must be
+/// released with `sdmm_free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn sdmm_get_errors(env: *const SdmmEnvironment) -> *mut c_char {
+    let env = match env.as_ref() {
+        Some(env) => env,
+        None => return ptr::null_mut(),
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| env.context.errors_json()));
+    match result {
+        Ok(json) => to_c_string(json),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Release an environment returned by `sdmm_parse_environment`. Null is
+/// ignored.
+#[no_mangle]
+pub unsafe extern "C" fn sdmm_free(env: *mut SdmmEnvironment) {
+    if !env.is_null() {
+        drop(Box::from_raw(env));
+    }
+}
+
+/// Release a string returned by any other function. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn sdmm_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
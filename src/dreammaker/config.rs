@@ -0,0 +1,131 @@
+//! Per-codebase settings, read from `SpacemanDMM.toml` beside the environment.
+//!
+//! ```toml
+//! [lints]
+//! enable = ["hard-deletes"]
+//!
+//! [lints.options]
+//! del_replacement = "qdel"
+//!
+//! [builtins]
+//! vars = ["/datum/var/native_id"]
+//!
+//! [[builtins.procs]]
+//! path = "/proc/rustg_file_read"
+//! params = ["fname"]
+//! return_type = "/list"
+//!
+//! [[builtins.external]]
+//! library = "rust_g"
+//! name = "file_read"
+//! args = 1
+//! ```
+//!
+//! Tools may read their own sections from the same file with `load_file`.
+use std::collections::HashMap;
+use std::path::Path;
+use std::fs;
+
+use serde::de::DeserializeOwned;
+
+use {Context, CustomBuiltins, CustomProc};
+
+pub const FILE_NAME: &str = "SpacemanDMM.toml";
+
+#[derive(::serde::Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub lints: Lints,
+    pub builtins: Builtins,
+}
+
+#[derive(::serde::Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Lints {
+    /// Optional lints to turn on.
+    pub enable: Vec<String>,
+    /// Codebase-specific lint settings.
+    pub options: HashMap<String, String>,
+}
+
+#[derive(::serde::Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Builtins {
+    /// Native procs which the codebase adds, such as through auxtools or
+    /// rust-g.
+    pub procs: Vec<BuiltinProc>,
+    /// Native vars which the codebase adds, by path.
+    pub vars: Vec<String>,
+    /// Functions of native libraries called through `call_ext()()`.
+    pub external: Vec<ExternalFunction>,
+}
+
+#[derive(::serde::Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct ExternalFunction {
+    pub library: String,
+    pub name: String,
+    pub args: usize,
+}
+
+#[derive(::serde::Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct BuiltinProc {
+    pub path: String,
+    pub params: Vec<String>,
+    /// The fewest arguments which may be passed, if not all of them.
+    pub min_args: Option<usize>,
+    /// The type path of the value returned.
+    pub return_type: Option<String>,
+    pub sleeps: bool,
+}
+
+/// Read settings of any shape from the file in the given directory. Missing
+/// files and sections produce the defaults.
+pub fn load_file<T: DeserializeOwned + Default>(dir: &Path) -> T {
+    let path = dir.join(FILE_NAME);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => return T::default(),
+    };
+    match ::toml::from_str(&contents) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error reading {}:\n{}", path.display(), e);
+            T::default()
+        }
+    }
+}
+
+impl Config {
+    /// Read the settings in the given directory, if there are any.
+    pub fn load(dir: &Path) -> Config {
+        load_file(dir)
+    }
+
+    /// Apply these settings to a context. This must happen before parsing
+    /// for the custom builtins to be registered.
+    pub fn apply(&self, context: &Context) {
+        for lint in self.lints.enable.iter() {
+            context.enable_lint(lint);
+        }
+        for (name, value) in self.lints.options.iter() {
+            context.set_lint_option(name, value);
+        }
+        context.set_custom_builtins(CustomBuiltins {
+            procs: self.builtins.procs.iter().map(|proc| CustomProc {
+                path: proc.path.clone(),
+                params: proc.params.clone(),
+                min_args: proc.min_args,
+                return_type: proc.return_type.clone(),
+                sleeps: proc.sleeps,
+            }).collect(),
+            vars: self.builtins.vars.clone(),
+            external: self.builtins.external.iter().map(|func| ::ExternalFunction {
+                library: func.library.clone(),
+                name: func.name.clone(),
+                args: func.args,
+            }).collect(),
+        });
+    }
+}
@@ -1,20 +1,23 @@
 //! The constant folder/evaluator, used by the preprocessor and object tree.
 use std::fmt;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use linked_hash_map::LinkedHashMap;
 use noisy_float::prelude::*;
 
-use super::{DMError, Location, HasLocation, Context};
+use super::{DMError, Location, HasLocation, Context, Severity, Phase};
 use super::objtree::*;
 use super::ast::*;
-use super::preprocessor::DefineMap;
+use super::lexer::{Lexer, LocatedToken};
+use super::preprocessor::{DefineMap, Preprocessor};
 
 /// A DM constant, usually a literal or simple combination of other constants.
 ///
 /// This is intended to represent the degree to which constants are evaluated
 /// before being displayed in DreamMaker.
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum Constant {
     /// The literal `null`.
     Null(Option<TreePath>),
@@ -38,11 +41,12 @@ pub enum Constant {
     /// An integer literal.
     Int(i32),
     /// A floating-point literal.
-    Float(N32),
+    Float(#[cfg_attr(feature = "serde", serde(with = "::serde_helpers::n32"))] N32),
 }
 
 /// The constant functions which are represented as-is.
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum ConstFn {
     /// The `icon()` type constructor.
     Icon,
@@ -145,6 +149,21 @@ impl Constant {
             _ => None,
         }
     }
+
+    /// Interpret this value as a single color, as by `rgb()` or `"#hex"`.
+    pub fn to_color(&self) -> Option<Color> {
+        self.as_str().and_then(Color::parse)
+    }
+
+    /// Interpret this value as a `color` var, which may be a color matrix.
+    pub fn to_color_matrix(&self) -> Option<ColorMatrix> {
+        ColorMatrix::from_constant(self)
+    }
+
+    /// Interpret this value as a `transform` var.
+    pub fn to_matrix(&self) -> Option<Matrix> {
+        Matrix::from_constant(self)
+    }
 }
 
 impl Default for Constant {
@@ -253,14 +272,276 @@ impl fmt::Display for ConstFn {
     }
 }
 
+// ----------------------------------------------------------------------------
+// Colors and matrices
+
+/// The colors which BYOND accepts by name.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("black", 0x000000),
+    ("silver", 0xc0c0c0),
+    ("gray", 0x808080),
+    ("grey", 0x808080),
+    ("white", 0xffffff),
+    ("maroon", 0x800000),
+    ("red", 0xff0000),
+    ("purple", 0x800080),
+    ("fuchsia", 0xff00ff),
+    ("magenta", 0xff00ff),
+    ("green", 0x00c000),
+    ("lime", 0x00ff00),
+    ("olive", 0x808000),
+    ("gold", 0x808000),
+    ("yellow", 0xffff00),
+    ("navy", 0x000080),
+    ("blue", 0x0000ff),
+    ("teal", 0x008080),
+    ("aqua", 0x00ffff),
+    ("cyan", 0x00ffff),
+];
+
+/// A color with an alpha channel, as in the `color` var.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color { r: 255, g: 255, b: 255, a: 255 };
+
+    /// Parse a color of the form `#rgb`, `#rgba`, `#rrggbb`, or `#rrggbbaa`,
+    /// or one of the colors BYOND knows by name.
+    pub fn parse(text: &str) -> Option<Color> {
+        if !text.starts_with('#') {
+            return NAMED_COLORS.iter()
+                .find(|&&(name, _)| name.eq_ignore_ascii_case(text))
+                .map(|&(_, rgb)| Color::from_rgb(rgb));
+        }
+        let digits = &text[1..];
+        if !digits.chars().all(|c| c.is_digit(16)) {
+            return None;
+        }
+        let value = u32::from_str_radix(digits, 16).ok()?;
+        let short = |shift: u32| (0x11 * ((value >> shift) & 0xf)) as u8;
+        match digits.len() {
+            3 => Some(Color { r: short(8), g: short(4), b: short(0), a: 255 }),
+            4 => Some(Color { r: short(12), g: short(8), b: short(4), a: short(0) }),
+            6 => Some(Color::from_rgb(value)),
+            8 => Some(Color {
+                r: (value >> 24) as u8,
+                g: (value >> 16) as u8,
+                b: (value >> 8) as u8,
+                a: value as u8,
+            }),
+            _ => None,
+        }
+    }
+
+    fn from_rgb(rgb: u32) -> Color {
+        Color { r: (rgb >> 16) as u8, g: (rgb >> 8) as u8, b: rgb as u8, a: 255 }
+    }
+
+    pub fn to_array(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+/// A color matrix, as in the `color` var, in rows of red, green, blue,
+/// alpha, and constant terms.
+///
+/// Each output channel is the sum over input channels of the input times
+/// the entry in that input's row, plus the constant row, with channels
+/// ranging from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorMatrix(pub [[f32; 4]; 5]);
+
+impl ColorMatrix {
+    pub const IDENTITY: ColorMatrix = ColorMatrix([
+        [1., 0., 0., 0.],
+        [0., 1., 0., 0.],
+        [0., 0., 1., 0.],
+        [0., 0., 0., 1.],
+        [0., 0., 0., 0.],
+    ]);
+
+    /// Interpret a `color` value, which is a color string, a list of 9, 12,
+    /// 16, or 20 numbers, or a list of 3 to 5 color strings or nulls each
+    /// giving a row.
+    pub fn from_constant(constant: &Constant) -> Option<ColorMatrix> {
+        let list = match *constant {
+            Constant::String(ref text) => return Color::parse(text).map(ColorMatrix::from),
+            Constant::Null(_) => return Some(ColorMatrix::IDENTITY),
+            Constant::List(ref list) => list,
+            _ => return None,
+        };
+
+        let mut result = ColorMatrix::IDENTITY;
+        if list.iter().all(|&(ref key, _)| key.to_float().is_some()) {
+            let numbers: Vec<f32> = list.iter().filter_map(|&(ref key, _)| key.to_float()).collect();
+            // how many entries each row has, and how many rows there are
+            let (width, height) = match numbers.len() {
+                9 => (3, 3),
+                12 => (3, 4),
+                16 => (4, 4),
+                20 => (4, 5),
+                _ => return None,
+            };
+            for (i, &number) in numbers.iter().enumerate() {
+                let (row, column) = (i / width, i % width);
+                // the constant row follows the alpha row even without alpha
+                let row = if height == 4 && width == 3 && row == 3 { 4 } else { row };
+                result.0[row][column] = number;
+            }
+            return Some(result);
+        }
+
+        if list.len() < 3 || list.len() > 5 {
+            return None;
+        }
+        for (row, &(ref key, _)) in list.iter().enumerate() {
+            match *key {
+                Constant::Null(_) => {}
+                Constant::String(ref text) => {
+                    let color = Color::parse(text)?;
+                    result.0[row] = [
+                        color.r as f32 / 255.,
+                        color.g as f32 / 255.,
+                        color.b as f32 / 255.,
+                        if row == 3 { color.a as f32 / 255. } else { 0. },
+                    ];
+                }
+                _ => return None,
+            }
+        }
+        Some(result)
+    }
+
+    /// Apply this matrix to a color whose channels range from 0 to 1.
+    pub fn apply(&self, rgba: [f32; 4]) -> [f32; 4] {
+        let mut result = self.0[4];
+        for (input, row) in rgba.iter().zip(self.0.iter()) {
+            for (output, &factor) in result.iter_mut().zip(row.iter()) {
+                *output += input * factor;
+            }
+        }
+        for output in result.iter_mut() {
+            *output = output.max(0.).min(1.);
+        }
+        result
+    }
+}
+
+impl From<Color> for ColorMatrix {
+    /// A plain color multiplies each channel by that channel of the color.
+    fn from(color: Color) -> ColorMatrix {
+        let mut result = ColorMatrix::IDENTITY;
+        result.0[0][0] = color.r as f32 / 255.;
+        result.0[1][1] = color.g as f32 / 255.;
+        result.0[2][2] = color.b as f32 / 255.;
+        result.0[3][3] = color.a as f32 / 255.;
+        result
+    }
+}
+
+/// The `MATRIX_ROTATE` flag to `matrix()`.
+pub const MATRIX_ROTATE: i32 = 5;
+/// The `MATRIX_SCALE` flag to `matrix()`.
+pub const MATRIX_SCALE: i32 = 6;
+/// The `MATRIX_TRANSLATE` flag to `matrix()`.
+pub const MATRIX_TRANSLATE: i32 = 7;
+
+/// A 2D transformation, as in the `transform` var, mapping `(x, y)` to
+/// `(a*x + b*y + c, d*x + e*y + f)` with `y` counting up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Matrix {
+    pub const IDENTITY: Matrix = Matrix { a: 1., b: 0., c: 0., d: 0., e: 1., f: 0. };
+
+    /// Interpret a `transform` value: null, or a constant `matrix()` call
+    /// which is empty, copies another, has all six entries, or rotates,
+    /// scales, or translates.
+    pub fn from_constant(constant: &Constant) -> Option<Matrix> {
+        let args = match *constant {
+            Constant::Null(_) => return Some(Matrix::IDENTITY),
+            Constant::Call(ConstFn::Matrix, ref args) => args,
+            _ => return None,
+        };
+        if args.iter().any(|&(_, ref value)| value.is_some()) {
+            return None;  // named arguments
+        }
+        let arg = |i: usize| args[i].0.to_float();
+        match args.len() {
+            0 => Some(Matrix::IDENTITY),
+            1 => Matrix::from_constant(&args[0].0),
+            2 if args[1].0.to_int() == Some(MATRIX_ROTATE) => {
+                let (sin, cos) = arg(0)?.to_radians().sin_cos();
+                Some(Matrix { a: cos, b: sin, c: 0., d: -sin, e: cos, f: 0. })
+            }
+            3 if args[2].0.to_int() == Some(MATRIX_SCALE) => {
+                Some(Matrix { a: arg(0)?, e: arg(1)?, ..Matrix::IDENTITY })
+            }
+            3 if args[2].0.to_int() == Some(MATRIX_TRANSLATE) => {
+                Some(Matrix { c: arg(0)?, f: arg(1)?, ..Matrix::IDENTITY })
+            }
+            6 => Some(Matrix {
+                a: arg(0)?,
+                b: arg(1)?,
+                c: arg(2)?,
+                d: arg(3)?,
+                e: arg(4)?,
+                f: arg(5)?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Transform a point.
+    pub fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (self.a * x + self.b * y + self.c, self.d * x + self.e * y + self.f)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Matrix::IDENTITY
+    }
+}
+
 // ----------------------------------------------------------------------------
 // The constant evaluator
 
+/// The magnitude beyond which not every integer is exactly representable,
+/// as BYOND stores numbers as 32-bit floats.
+pub const MAX_EXACT_INT: i64 = 1 << 24;
+
 /// Evaluate all the type-level variables in an object tree into constants.
 pub(crate) fn evaluate_all(context: &Context, tree: &mut ObjectTree, sloppy: bool) {
     let mut been_sloppy = false;
 
-    for ty in tree.graph.node_indices() {
+    let total = tree.graph.node_count();
+    for (done, ty) in tree.graph.node_indices().enumerate() {
+        if context.is_cancelled() {
+            return;
+        }
+        context.report_progress(Phase::Evaluating, done, total);
         let keys: Vec<String> = tree.graph.node_weight(ty).unwrap().vars.keys().cloned().collect();
         for key in keys {
             if !tree
@@ -274,7 +555,7 @@ pub(crate) fn evaluate_all(context: &Context, tree: &mut ObjectTree, sloppy: boo
             {
                 continue;  // skip non-constant-evaluable vars
             }
-            match constant_ident_lookup(tree, ty, &key, false) {
+            match constant_ident_lookup(Some(context), tree, ty, &key, false) {
                 Err(err) => context.register_error(err),
                 Ok(ConstLookup::Found(_, _)) => {}
                 Ok(ConstLookup::Continue(_)) => {
@@ -301,25 +582,77 @@ pub(crate) fn evaluate_all(context: &Context, tree: &mut ObjectTree, sloppy: boo
             }
         }
     }
+    context.report_progress(Phase::Evaluating, total, total);
 }
 
 /// Evaluate an expression in the absence of any surrounding context.
 pub fn simple_evaluate(location: Location, expr: Expression) -> Result<Constant, DMError> {
     ConstantFolder {
+        context: None,
         tree: None,
         location,
         ty: NodeIndex::new(0),
         defines: None,
+        initial: false,
     }.expr(expr, None)
 }
 
 /// Evaluate an expression in the preprocessor, with `defined()` available.
 pub fn preprocessor_evaluate(location: Location, expr: Expression, defines: &DefineMap) -> Result<Constant, DMError> {
     ConstantFolder {
+        context: None,
         tree: None,
         location,
         ty: NodeIndex::new(0),
         defines: Some(defines),
+        initial: false,
+    }.expr(expr, None)
+}
+
+/// Evaluate a snippet of DM source as a constant expression.
+///
+/// Identifiers are resolved against the initial values of vars on the type at
+/// `type_path`, and `initial()` may be used. If defines are provided, they
+/// are expanded first.
+pub fn evaluate_str(
+    context: &Context,
+    tree: &mut ObjectTree,
+    type_path: &str,
+    defines: Option<&DefineMap>,
+    source: &str,
+) -> Result<Constant, DMError> {
+    let location = Location::default();
+    let ty = if type_path.is_empty() || type_path == "/" {
+        NodeIndex::new(0)
+    } else {
+        match tree.types.get(type_path) {
+            Some(&idx) => idx,
+            None => return Err(DMError::new(location, format!("unknown typepath {}", type_path))),
+        }
+    };
+
+    let tokens: Vec<LocatedToken> = match defines {
+        Some(defines) => {
+            let mut preprocessor = Preprocessor::from_defines(context, defines.clone());
+            preprocessor.push_file(PathBuf::from("(eval)"), io::Cursor::new(source.as_bytes().to_owned()));
+            preprocessor.collect()
+        }
+        None => Lexer::new(context, Default::default(), source.bytes().map(Ok)).collect(),
+    };
+
+    let expr = {
+        let mut parser = ::parser::Parser::new(context, tokens.into_iter());
+        parser.set_fallback_location(location);
+        let expr = parser.expression();
+        parser.require(expr)?
+    };
+    ConstantFolder {
+        context: Some(context),
+        tree: Some(tree),
+        defines: None,
+        location,
+        ty,
+        initial: true,
     }.expr(expr, None)
 }
 
@@ -329,6 +662,7 @@ enum ConstLookup {
 }
 
 fn constant_ident_lookup(
+    context: Option<&Context>,
     tree: &mut ObjectTree,
     ty: NodeIndex,
     ident: &str,
@@ -386,10 +720,12 @@ fn constant_ident_lookup(
     };
     // evaluate full_value
     let value = ConstantFolder {
+        context,
         tree: Some(tree),
         defines: None,
         location,
         ty,
+        initial: false,
     }.expr(expr, if type_hint.is_empty() { None } else { Some(&type_hint) })?;
     // and store it into 'value', then return it
     let var = tree.graph.node_weight_mut(ty).unwrap().vars.get_mut(ident).unwrap();
@@ -399,10 +735,14 @@ fn constant_ident_lookup(
 }
 
 struct ConstantFolder<'a> {
+    /// Where to report warnings, if anywhere.
+    context: Option<&'a Context>,
     tree: Option<&'a mut ObjectTree>,
     defines: Option<&'a DefineMap>,
     location: Location,
     ty: NodeIndex,
+    /// Whether `initial()` calls may be folded, as in `evaluate_str`.
+    initial: bool,
 }
 
 impl<'a> HasLocation for ConstantFolder<'a> {
@@ -515,9 +855,68 @@ impl<'a> ConstantFolder<'a> {
         })
     }
 
+    fn warning<S: Into<String>>(&self, message: S) {
+        if let Some(context) = self.context {
+            context.register_error(self.error(message).set_severity(Severity::Warning));
+        }
+    }
+
+    /// Warn if the result of integer arithmetic is not exactly representable.
+    fn check_exact(&self, lhs: i32, op: BinaryOp, rhs: i32, result: i64) {
+        if result.abs() > MAX_EXACT_INT {
+            self.warning(format!(
+                "precision loss in constant {} {} {}: {} exceeds {} in magnitude",
+                lhs, op, rhs, result, MAX_EXACT_INT,
+            ));
+        }
+    }
+
+    /// Warn if an operand or result of a bitwise operation does not fit in
+    /// the 24 bits which BYOND uses.
+    fn check_bitwise(&self, lhs: i32, op: BinaryOp, rhs: i32) {
+        let fits = |value: i64| value >= 0 && value < MAX_EXACT_INT;
+        let value = if !fits(i64::from(lhs)) {
+            i64::from(lhs)
+        } else if !fits(i64::from(rhs)) {
+            i64::from(rhs)
+        } else if op == BinaryOp::LShift && !fits(i64::from(lhs) << rhs.min(40)) {
+            i64::from(lhs) << rhs.min(40)
+        } else {
+            return;
+        };
+        self.warning(format!(
+            "bitwise operation {} {} {} truncates {} to 24 bits",
+            lhs, op, rhs, value,
+        ));
+    }
+
     fn binary(&mut self, mut lhs: Constant, mut rhs: Constant, op: BinaryOp) -> Result<Constant, DMError> {
         use self::Constant::*;
 
+        // integer arithmetic is widened to detect overflow, which would
+        // otherwise wrap or panic
+        if let (&Int(l), &Int(r)) = (&lhs, &rhs) {
+            let wide = match op {
+                BinaryOp::Add => Some(i64::from(l) + i64::from(r)),
+                BinaryOp::Sub => Some(i64::from(l) - i64::from(r)),
+                BinaryOp::Mul => Some(i64::from(l) * i64::from(r)),
+                _ => None,
+            };
+            if let Some(wide) = wide {
+                self.check_exact(l, op, r, wide);
+                return Ok(if wide as i32 as i64 == wide {
+                    Int(wide as i32)
+                } else {
+                    Constant::from(wide as f32)
+                });
+            }
+            match op {
+                BinaryOp::BitOr | BinaryOp::BitAnd | BinaryOp::BitXor |
+                BinaryOp::LShift | BinaryOp::RShift => self.check_bitwise(l, op, r),
+                _ => {}
+            }
+        }
+
         macro_rules! numeric {
             ($name:ident $oper:tt) => {
                 match (op, lhs, rhs) {
@@ -549,6 +948,14 @@ impl<'a> ConstantFolder<'a> {
             (BinaryOp::Pow, Int(lhs), Float(rhs)) => return Ok(Constant::from((lhs as f32).powf(rhs.raw()))),
             (BinaryOp::Pow, Float(lhs), Int(rhs)) => return Ok(Constant::from(lhs.powi(rhs))),
             (BinaryOp::Pow, Float(lhs), Float(rhs)) => return Ok(Constant::from(lhs.powf(rhs))),
+            (BinaryOp::FloatMod, lhs_, rhs_) => match (lhs_.to_float(), rhs_.to_float()) {
+                // the result takes the sign of the divisor
+                (Some(lhs), Some(rhs)) if rhs != 0. => return Ok(Constant::from(lhs - rhs * (lhs / rhs).floor())),
+                _ => {
+                    lhs = lhs_;
+                    rhs = rhs_;
+                }
+            },
             (_, lhs_, rhs_) => {
                 lhs = lhs_;
                 rhs = rhs_;
@@ -609,15 +1016,23 @@ impl<'a> ConstantFolder<'a> {
                     let mut result = String::with_capacity(7);
                     result.push_str("#");
                     for each in args {
-                        if let Constant::Int(i) = self.expr(each, None)? {
-                            let clamped = ::std::cmp::max(::std::cmp::min(i, 255), 0);
-                            let _ = write!(result, "{:02x}", clamped);
-                        } else {
-                            return Err(self.error("malformed rgb() call"));
+                        match self.expr(each, None)?.to_float() {
+                            Some(value) => {
+                                let clamped = value.round().max(0.).min(255.);
+                                let _ = write!(result, "{:02x}", clamped as u8);
+                            }
+                            None => return Err(self.error("malformed rgb() call")),
                         }
                     }
                     Constant::String(result)
                 },
+                "initial" if self.initial => {
+                    if args.len() != 1 {
+                        return Err(self.error("malformed initial() call"));
+                    }
+                    let arg = args.into_iter().next().unwrap();
+                    self.expr(arg, type_hint)?
+                }
                 "defined" if self.defines.is_some() => {
                     let defines = self.defines.unwrap();  // annoying, but keeps the match clean
                     if args.len() != 1 {
@@ -640,6 +1055,7 @@ impl<'a> ConstantFolder<'a> {
             Term::String(v) => Constant::String(v),
             Term::Resource(v) => Constant::Resource(v),
             Term::Int(v) => Constant::Int(v),
+            Term::Float(v) if v.is_nan() => return Err(self.error("NaN is not a valid constant")),
             Term::Float(v) => Constant::from(v),
             Term::Expr(expr) => self.expr(*expr, type_hint)?,
             _ => return Err(self.error(format!("non-constant expression"))),
@@ -670,8 +1086,9 @@ impl<'a> ConstantFolder<'a> {
             if self.tree.is_none() {
                 return Err(self.error("cannot reference variables in this context"));
             }
+            let context = self.context;
             let tree = self.tree.as_mut().unwrap();
-            match constant_ident_lookup(tree, ty, &ident, must_be_static)
+            match constant_ident_lookup(context, tree, ty, &ident, must_be_static)
                 .map_err(|e| DMError::new(location, e.into_description()))?
             {
                 ConstLookup::Found(_, v) => return Ok(v),
@@ -0,0 +1,158 @@
+//! Detection of procs which are never called.
+//!
+//! DM dispatches procs by name, so a proc counts as referenced when its name
+//! is called anywhere, on any type, or appears in a proc path such as
+//! `.proc/foo` or as a string such as `call(src, "foo")()`. This errs on the
+//! side of reporting too little.
+//!
+//! Procs which are only invoked by reflection can be excluded with
+//! `set SpacemanDMM_called_by_reflection = TRUE`.
+use std::collections::HashSet;
+
+use ast::*;
+use objtree::{ObjectTree, TypeRef, ProcValue, ProcKind, Code};
+use analysis;
+use FileId;
+
+/// The directive marking a proc as invoked by reflection.
+pub const REFLECTION_DIRECTIVE: &str = "SpacemanDMM_called_by_reflection";
+
+/// Procs which are called by BYOND or by common libraries rather than by
+/// the environment itself.
+///
+/// A root is either a proc name, matching that proc on every type, or a
+/// type path and proc name such as `/world/New`, matching that type and its
+/// subtypes.
+pub const DEFAULT_ROOTS: &[&str] = &[
+    "/world/New",
+    "/world/Del",
+    "/world/Topic",
+    "/world/Reboot",
+    "Topic",
+    "ui_act",
+    "ui_data",
+    "ui_static_data",
+    "ui_interact",
+];
+
+/// A proc declaration which nothing refers to.
+#[derive(Debug, Clone)]
+pub struct UnusedProc<'a> {
+    pub ty: TypeRef<'a>,
+    pub name: &'a str,
+    pub proc: &'a ProcValue,
+}
+
+/// Find the procs declared in the environment which are never referenced.
+pub fn unused_procs<'a, S: AsRef<str>>(tree: &'a ObjectTree, roots: &[S]) -> Vec<UnusedProc<'a>> {
+    let referenced = referenced_names(tree);
+    let mut reflected = HashSet::new();
+    for ty in types(tree) {
+        for (name, proc) in ty.get().procs.iter() {
+            if proc.value.iter().any(|value| value.settings.directive_enabled(REFLECTION_DIRECTIVE)) {
+                reflected.insert(name.as_str());
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    for ty in types(tree) {
+        for (name, proc) in ty.get().procs.iter() {
+            // only report where the proc is declared, not each override
+            let declaration = match proc.value.first() {
+                Some(value) if value.kind == ProcKind::Proc => value,
+                _ => continue,
+            };
+            if declaration.location.file == FileId::builtins()
+                || referenced.contains(name.as_str())
+                || reflected.contains(name.as_str())
+                || is_root(ty, name, roots)
+            {
+                continue;
+            }
+            result.push(UnusedProc { ty, name, proc: declaration });
+        }
+    }
+    result.sort_by_key(|each| each.proc.location);
+    result
+}
+
+fn types(tree: &ObjectTree) -> impl Iterator<Item=TypeRef> {
+    tree.graph.node_indices().map(move |idx| TypeRef::new(tree, idx))
+}
+
+/// Check whether a proc matches any of the configured roots.
+fn is_root<S: AsRef<str>>(ty: TypeRef, name: &str, roots: &[S]) -> bool {
+    roots.iter().any(|root| {
+        let root = root.as_ref();
+        match root.rfind('/') {
+            Some(idx) => {
+                let (path, proc_name) = (&root[..idx], &root[idx + 1..]);
+                proc_name == name && is_subtype_path(ty, path)
+            }
+            None => root == name,
+        }
+    })
+}
+
+fn is_subtype_path(ty: TypeRef, path: &str) -> bool {
+    let mut current = Some(ty);
+    while let Some(each) = current {
+        if each.get().path == path {
+            return true;
+        }
+        current = each.parent_type();
+    }
+    false
+}
+
+/// Collect every name by which a proc may be referred to.
+fn referenced_names<'a>(tree: &'a ObjectTree) -> HashSet<&'a str> {
+    let mut names = HashSet::new();
+    {
+        let mut visit = |expr: &'a Expression| match *expr {
+            Expression::Base { ref term, ref follow, .. } => {
+                match *term {
+                    Term::Call(ref name, _) => { names.insert(name.as_str()); }
+                    Term::String(ref text) => { names.insert(text.as_str()); }
+                    Term::Prefab(ref prefab) => if let Some(name) = proc_path_name(prefab) {
+                        names.insert(name);
+                    },
+                    _ => {}
+                }
+                for each in follow.iter() {
+                    if let Follow::Call(_, ref name, _) = *each {
+                        names.insert(name.as_str());
+                    }
+                }
+            }
+            _ => {}
+        };
+
+        for ty in types(tree) {
+            for (_, var) in ty.get().vars.iter() {
+                if let Some(ref expr) = var.value.expression {
+                    analysis::each_subexpr(expr, &mut visit);
+                }
+            }
+            for (_, proc) in ty.get().procs.iter() {
+                for value in proc.value.iter() {
+                    if let Code::Present(ref block) = value.code {
+                        analysis::each_expr(block, true, &mut visit);
+                    }
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Find the proc named by a path such as `.proc/foo` or `/mob/verb/bar`.
+fn proc_path_name(prefab: &Prefab) -> Option<&str> {
+    let len = prefab.path.len();
+    if len >= 2 && (prefab.path[len - 2].1 == "proc" || prefab.path[len - 2].1 == "verb") {
+        Some(&prefab.path[len - 1].1)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,537 @@
+//! A Debug Adapter Protocol server for runtime debuggers.
+//!
+//! The server speaks DAP to an editor on one side and drives a `Backend` on
+//! the other, such as a native extension loaded into a running server in the
+//! style of auxtools. The backend deals only in procs and bytecode offsets;
+//! the object tree maps these to and from lines of the source.
+//!
+//! * https://microsoft.github.io/debug-adapter-protocol/specification
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+
+use objtree::{ObjectTree, TypeRef, ProcValue};
+use {Context, FileId};
+
+/// Frame IDs are unique across threads by combining the two.
+const FRAMES_PER_THREAD: i64 = 1 << 16;
+
+/// One definition of a proc, as the backend refers to it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProcRef {
+    /// The path of the type, such as `/mob`, or empty for global procs.
+    pub type_path: String,
+    /// The name of the proc.
+    pub name: String,
+    /// Which of the type's definitions of the proc, in the order they
+    /// appear in the environment.
+    pub override_id: usize,
+}
+
+impl fmt::Display for ProcRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/proc/{}", self.type_path, self.name)
+    }
+}
+
+/// A frame of a suspended thread's call stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub proc: ProcRef,
+    /// The bytecode offset being executed.
+    pub offset: u32,
+}
+
+/// A named group of variables, such as a frame's arguments or locals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    pub name: String,
+    /// The reference with which to request the variables.
+    pub variables: i64,
+}
+
+/// A variable and its value, formatted for display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub value: String,
+    /// The reference with which to request the variable's contents, such as
+    /// the vars of a datum or the entries of a list, or 0 if it has none.
+    pub variables: i64,
+}
+
+/// How to resume a suspended thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    StepOver,
+    StepInto,
+    StepOut,
+}
+
+/// Why a thread was suspended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Breakpoint,
+    Step,
+    Pause,
+    /// A runtime error, with its message.
+    Exception(String),
+}
+
+/// Something which happened while the debuggee was running.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendEvent {
+    Stopped { thread: i64, reason: StopReason },
+    Output(String),
+    Terminated,
+}
+
+/// A running server which can be debugged.
+pub trait Backend {
+    /// Connect to the debuggee, given the arguments of the editor's `attach`
+    /// or `launch` request. Events are sent through `events` from then on,
+    /// from any thread.
+    fn attach(&mut self, events: EventSink, arguments: &Value) -> Result<(), String>;
+
+    /// Disconnect from the debuggee, leaving it running.
+    fn detach(&mut self);
+
+    /// The offset at which a line of a proc begins, from the debug
+    /// information of the compiled environment.
+    fn offset_of_line(&mut self, proc: &ProcRef, line: u32) -> Option<u32>;
+
+    /// The line containing an offset of a proc.
+    fn line_of_offset(&mut self, proc: &ProcRef, offset: u32) -> Option<u32>;
+
+    fn set_breakpoint(&mut self, proc: &ProcRef, offset: u32) -> Result<(), String>;
+
+    fn clear_breakpoint(&mut self, proc: &ProcRef, offset: u32);
+
+    /// The IDs and names of the suspended threads.
+    fn threads(&mut self) -> Vec<(i64, String)>;
+
+    /// The call stack of a suspended thread, innermost first.
+    fn stack_frames(&mut self, thread: i64) -> Vec<StackFrame>;
+
+    /// The groups of variables visible in a frame of a suspended thread.
+    fn scopes(&mut self, thread: i64, frame: usize) -> Vec<Scope>;
+
+    /// The variables of a scope, or the contents of a variable.
+    fn variables(&mut self, reference: i64) -> Vec<Variable>;
+
+    fn resume(&mut self, thread: i64, how: Resume);
+
+    fn pause(&mut self, thread: i64);
+}
+
+/// Sends events from the backend to the editor.
+#[derive(Clone)]
+pub struct EventSink {
+    output: Output,
+}
+
+impl EventSink {
+    pub fn send(&self, event: BackendEvent) {
+        match event {
+            BackendEvent::Stopped { thread, reason } => {
+                let (reason, text) = match reason {
+                    StopReason::Breakpoint => ("breakpoint", None),
+                    StopReason::Step => ("step", None),
+                    StopReason::Pause => ("pause", None),
+                    StopReason::Exception(message) => ("exception", Some(message)),
+                };
+                let mut body = json!({
+                    "reason": reason,
+                    "threadId": thread,
+                    "allThreadsStopped": true,
+                });
+                if let Some(text) = text {
+                    body["text"] = Value::String(text);
+                }
+                self.output.event("stopped", body);
+            }
+            BackendEvent::Output(text) => self.output.event("output", json!({
+                "category": "console",
+                "output": text,
+            })),
+            BackendEvent::Terminated => self.output.event("terminated", Value::Null),
+        }
+    }
+}
+
+/// The framed message stream to the editor, shared with the event sink.
+#[derive(Clone)]
+struct Output {
+    writer: Arc<Mutex<Box<Write + Send>>>,
+    seq: Arc<AtomicUsize>,
+}
+
+impl Output {
+    fn send(&self, mut message: Value) {
+        let mut writer = self.writer.lock().unwrap();
+        message["seq"] = json!(self.seq.fetch_add(1, Ordering::SeqCst) + 1);
+        let text = message.to_string();
+        // an editor which has gone away is noticed when reading instead
+        let _ = write!(writer, "Content-Length: {}\r\n\r\n{}", text.len(), text)
+            .and_then(|_| writer.flush());
+    }
+
+    fn event(&self, event: &str, body: Value) {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message);
+    }
+}
+
+/// Read one framed message, or `None` at the end of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        let mut parts = line.splitn(2, ':');
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[derive(::serde::Deserialize)]
+struct Request {
+    seq: i64,
+    command: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(::serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetBreakpointsArguments {
+    source: Source,
+    #[serde(default)]
+    breakpoints: Vec<SourceBreakpoint>,
+}
+
+#[derive(::serde::Deserialize)]
+struct Source {
+    path: Option<String>,
+}
+
+#[derive(::serde::Deserialize)]
+struct SourceBreakpoint {
+    line: u32,
+}
+
+#[derive(::serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ThreadArguments {
+    thread_id: i64,
+}
+
+#[derive(::serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScopesArguments {
+    frame_id: i64,
+}
+
+#[derive(::serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VariablesArguments {
+    variables_reference: i64,
+}
+
+fn arguments<T: DeserializeOwned>(value: Value) -> Result<T, String> {
+    serde_json::from_value(value).map_err(|e| format!("bad arguments: {}", e))
+}
+
+/// A debug adapter for one editor session.
+pub struct Debugger<'a, B> {
+    context: &'a Context,
+    tree: &'a ObjectTree,
+    /// The directory containing the environment, against which the paths of
+    /// its files are relative.
+    root: PathBuf,
+    backend: B,
+    output: Output,
+    /// The breakpoints set in each file, to be cleared when the editor
+    /// sends that file's breakpoints again.
+    breakpoints: HashMap<FileId, Vec<(ProcRef, u32)>>,
+}
+
+impl<'a, B: Backend> Debugger<'a, B> {
+    pub fn new<W: Write + Send + 'static>(
+        context: &'a Context,
+        tree: &'a ObjectTree,
+        root: PathBuf,
+        backend: B,
+        output: W,
+    ) -> Debugger<'a, B> {
+        Debugger {
+            context,
+            tree,
+            root,
+            backend,
+            output: Output {
+                writer: Arc::new(Mutex::new(Box::new(output))),
+                seq: Arc::new(AtomicUsize::new(0)),
+            },
+            breakpoints: HashMap::new(),
+        }
+    }
+
+    pub fn backend(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Handle messages from the editor until it disconnects.
+    pub fn run<R: BufRead>(&mut self, mut input: R) -> io::Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            if !self.handle(&message) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle one message from the editor, returning `false` once it has
+    /// disconnected.
+    pub fn handle(&mut self, message: &str) -> bool {
+        let request: Request = match serde_json::from_str(message) {
+            Ok(request) => request,
+            Err(e) => {
+                self.output.event("output", json!({
+                    "category": "stderr",
+                    "output": format!("malformed request: {}\n", e),
+                }));
+                return true;
+            }
+        };
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+        });
+        let result = self.dispatch(&request.command, request.arguments);
+        let success = result.is_ok();
+        match result {
+            Ok(body) => {
+                response["success"] = Value::Bool(true);
+                if !body.is_null() {
+                    response["body"] = body;
+                }
+            }
+            Err(message) => {
+                response["success"] = Value::Bool(false);
+                response["message"] = Value::String(message);
+            }
+        }
+        self.output.send(response);
+
+        match request.command.as_str() {
+            // configuration requests such as breakpoints may now be sent
+            "initialize" if success => self.output.event("initialized", Value::Null),
+            "disconnect" => return false,
+            _ => {}
+        }
+        true
+    }
+
+    fn dispatch(&mut self, command: &str, args: Value) -> Result<Value, String> {
+        match command {
+            "initialize" => Ok(json!({ "supportsConfigurationDoneRequest": true })),
+            "attach" | "launch" => {
+                let events = EventSink { output: self.output.clone() };
+                self.backend.attach(events, &args)?;
+                Ok(Value::Null)
+            }
+            "configurationDone" => Ok(Value::Null),
+            "disconnect" => {
+                self.breakpoints.clear();
+                self.backend.detach();
+                Ok(Value::Null)
+            }
+            "setBreakpoints" => self.set_breakpoints(arguments(args)?),
+            "threads" => {
+                let threads: Vec<_> = self.backend.threads().into_iter()
+                    .map(|(id, name)| json!({ "id": id, "name": name }))
+                    .collect();
+                Ok(json!({ "threads": threads }))
+            }
+            "stackTrace" => {
+                let args: ThreadArguments = arguments(args)?;
+                Ok(self.stack_trace(args.thread_id))
+            }
+            "scopes" => {
+                let args: ScopesArguments = arguments(args)?;
+                let thread = args.frame_id / FRAMES_PER_THREAD;
+                let frame = (args.frame_id % FRAMES_PER_THREAD) as usize;
+                let scopes: Vec<_> = self.backend.scopes(thread, frame).into_iter().map(|scope| json!({
+                    "name": scope.name,
+                    "variablesReference": scope.variables,
+                    "expensive": false,
+                })).collect();
+                Ok(json!({ "scopes": scopes }))
+            }
+            "variables" => {
+                let args: VariablesArguments = arguments(args)?;
+                let variables: Vec<_> = self.backend.variables(args.variables_reference).into_iter().map(|var| json!({
+                    "name": var.name,
+                    "value": var.value,
+                    "variablesReference": var.variables,
+                })).collect();
+                Ok(json!({ "variables": variables }))
+            }
+            "continue" | "next" | "stepIn" | "stepOut" => {
+                let args: ThreadArguments = arguments(args)?;
+                let how = match command {
+                    "continue" => Resume::Continue,
+                    "next" => Resume::StepOver,
+                    "stepIn" => Resume::StepInto,
+                    _ => Resume::StepOut,
+                };
+                self.backend.resume(args.thread_id, how);
+                if how == Resume::Continue {
+                    Ok(json!({ "allThreadsContinued": true }))
+                } else {
+                    Ok(Value::Null)
+                }
+            }
+            "pause" => {
+                let args: ThreadArguments = arguments(args)?;
+                self.backend.pause(args.thread_id);
+                Ok(Value::Null)
+            }
+            _ => Err(format!("unsupported command '{}'", command)),
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: SetBreakpointsArguments) -> Result<Value, String> {
+        let file = args.source.path.as_ref().and_then(|path| self.file_id(Path::new(path)));
+        if let Some(file) = file {
+            for (proc, offset) in self.breakpoints.remove(&file).unwrap_or_default() {
+                self.backend.clear_breakpoint(&proc, offset);
+            }
+        }
+
+        let mut set = Vec::new();
+        let mut result = Vec::new();
+        for breakpoint in args.breakpoints {
+            let outcome = match file {
+                Some(file) => self.set_breakpoint(file, breakpoint.line),
+                None => Err("file is not part of the environment".to_owned()),
+            };
+            match outcome {
+                Ok(entry) => {
+                    set.push(entry);
+                    result.push(json!({ "verified": true, "line": breakpoint.line }));
+                }
+                Err(message) => result.push(json!({
+                    "verified": false,
+                    "line": breakpoint.line,
+                    "message": message,
+                })),
+            }
+        }
+        if let Some(file) = file {
+            self.breakpoints.insert(file, set);
+        }
+        Ok(json!({ "breakpoints": result }))
+    }
+
+    fn set_breakpoint(&mut self, file: FileId, line: u32) -> Result<(ProcRef, u32), String> {
+        let proc = self.proc_at(file, line).ok_or("no proc at this line")?;
+        let offset = self.backend.offset_of_line(&proc, line).ok_or("no code at this line")?;
+        self.backend.set_breakpoint(&proc, offset)?;
+        Ok((proc, offset))
+    }
+
+    fn stack_trace(&mut self, thread: i64) -> Value {
+        let frames = self.backend.stack_frames(thread);
+        let mut result = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            let mut entry = json!({
+                "id": thread * FRAMES_PER_THREAD + i as i64,
+                "name": frame.proc.to_string(),
+                "line": 0,
+                "column": 0,
+            });
+            if let Some(value) = self.proc_value(&frame.proc) {
+                if !value.location.is_builtins() {
+                    let path = self.root.join(self.context.file_path(value.location.file));
+                    let line = self.backend.line_of_offset(&frame.proc, frame.offset)
+                        .unwrap_or(value.location.line);
+                    entry["source"] = json!({
+                        "name": path.file_name().map(|name| name.to_string_lossy().into_owned()),
+                        "path": path.display().to_string(),
+                    });
+                    entry["line"] = json!(line);
+                    entry["column"] = json!(1);
+                }
+            }
+            result.push(entry);
+        }
+        json!({ "stackFrames": result, "totalFrames": frames.len() })
+    }
+
+    /// Find an environment file from the path the editor knows it by.
+    pub fn file_id(&self, path: &Path) -> Option<FileId> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.context.get_file(relative)
+    }
+
+    /// Find the proc containing a line: the last one defined at or before
+    /// it in the same file.
+    pub fn proc_at(&self, file: FileId, line: u32) -> Option<ProcRef> {
+        let mut best: Option<(u32, ProcRef)> = None;
+        for idx in self.tree.graph.node_indices() {
+            let ty = TypeRef::new(self.tree, idx);
+            for (name, proc) in ty.get().procs.iter() {
+                for (i, value) in proc.value.iter().enumerate() {
+                    let at = value.location;
+                    if at.file == file && at.line <= line && best.as_ref().map_or(true, |&(prev, _)| at.line > prev) {
+                        best = Some((at.line, ProcRef {
+                            type_path: ty.get().path.clone(),
+                            name: name.clone(),
+                            override_id: i,
+                        }));
+                    }
+                }
+            }
+        }
+        best.map(|(_, proc)| proc)
+    }
+
+    /// Look up the definition the backend refers to.
+    pub fn proc_value(&self, proc: &ProcRef) -> Option<&'a ProcValue> {
+        let ty = if proc.type_path.is_empty() {
+            self.tree.root()
+        } else {
+            self.tree.find(&proc.type_path)?
+        };
+        ty.get().procs.get(&proc.name)?.value.get(proc.override_id)
+    }
+}
@@ -0,0 +1,353 @@
+//! Parsing of interface (`.dmf`) and client script (`.dms`) files.
+//!
+//! A skin file is a list of sections, each a macro set, a menu, or a window,
+//! containing `elem` entries which each have a list of attributes:
+//!
+//! ```text
+//! window "mainwindow"
+//!     elem "mainwindow"
+//!         type = MAIN
+//!         size = 640x480
+//! ```
+//!
+//! Client scripts are read only as far as their `macro` blocks and `<STYLE>`
+//! sheets; anything else is skipped.
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use super::{Context, DMError, Location, FileId, Severity};
+
+/// The kinds of section in a skin file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    Macro,
+    Menu,
+    Window,
+}
+
+impl SectionKind {
+    fn from_keyword(keyword: &str) -> Option<SectionKind> {
+        match keyword {
+            "macro" => Some(SectionKind::Macro),
+            "menu" => Some(SectionKind::Menu),
+            "window" => Some(SectionKind::Window),
+            _ => None,
+        }
+    }
+
+    /// The keyword which introduces this kind of section.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            SectionKind::Macro => "macro",
+            SectionKind::Menu => "menu",
+            SectionKind::Window => "window",
+        }
+    }
+}
+
+/// The value of an element attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A quoted string, with escapes resolved.
+    String(String),
+    /// A resource in single quotes, such as an icon.
+    Resource(String),
+    /// Anything unquoted, such as `true`, `640x480`, or `#ffffff`.
+    Bare(String),
+}
+
+impl Value {
+    /// The text of this value, without quotes.
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Value::String(ref s) |
+            Value::Resource(ref s) |
+            Value::Bare(ref s) => s,
+        }
+    }
+}
+
+/// An `elem` entry: a control, a macro, or a menu item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    /// The element's name. Macros and menu items are usually unnamed.
+    pub name: Option<String>,
+    pub location: Location,
+    /// The attributes in the order they appear.
+    pub attributes: Vec<(String, Value)>,
+}
+
+impl Element {
+    /// Look up an attribute by name.
+    pub fn get(&self, attribute: &str) -> Option<&Value> {
+        self.attributes.iter().find(|&&(ref name, _)| name == attribute).map(|&(_, ref value)| value)
+    }
+
+    /// The `type` attribute of a control, such as `MAIN` or `BROWSER`.
+    pub fn control_type(&self) -> Option<&str> {
+        self.get("type").map(Value::as_str)
+    }
+}
+
+/// A macro set, menu, or window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub kind: SectionKind,
+    pub name: String,
+    pub location: Location,
+    pub elements: Vec<Element>,
+}
+
+impl Section {
+    /// The controls of a window, not counting the element describing the
+    /// window itself.
+    pub fn controls(&self) -> impl Iterator<Item=&Element> {
+        let name = &self.name;
+        self.elements.iter().filter(move |elem| elem.name.as_ref() != Some(name))
+    }
+}
+
+/// A parsed `.dmf` interface file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Skin {
+    pub sections: Vec<Section>,
+}
+
+impl Skin {
+    /// Read and parse a skin file, registering it with the context.
+    pub fn from_file(context: &Context, path: &Path) -> io::Result<Skin> {
+        let text = read_file(path)?;
+        let file = context.register_file(path);
+        Ok(Skin::parse(context, file, &text))
+    }
+
+    /// Parse the text of a skin file. Malformed lines are reported to the
+    /// context and skipped.
+    pub fn parse(context: &Context, file: FileId, text: &str) -> Skin {
+        let mut skin = Skin::default();
+        // whether lines belong to a section which failed to parse
+        let mut skipping = false;
+
+        for (location, line) in lines(file, text) {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            let (keyword, rest) = split_word(trimmed);
+            if keyword == "elem" {
+                if skipping {
+                    continue;
+                }
+                let section = match skin.sections.last_mut() {
+                    Some(section) => section,
+                    None => {
+                        context.register_error(DMError::new(location, "elem outside of any section"));
+                        continue;
+                    }
+                };
+                let name = if rest.is_empty() {
+                    None
+                } else {
+                    match parse_value(rest) {
+                        Some(Value::String(name)) => Some(name),
+                        _ => {
+                            context.register_error(DMError::new(location, "expected a quoted elem name"));
+                            None
+                        }
+                    }
+                };
+                section.elements.push(Element { name, location, attributes: Vec::new() });
+            } else if let (Some(kind), true) = (SectionKind::from_keyword(keyword), rest.starts_with('"')) {
+                match parse_value(rest) {
+                    Some(Value::String(name)) => {
+                        skipping = false;
+                        skin.sections.push(Section { kind, name, location, elements: Vec::new() });
+                    }
+                    _ => {
+                        skipping = true;
+                        context.register_error(DMError::new(location, format!("malformed {} name", keyword)));
+                    }
+                }
+            } else if let Some(eq) = trimmed.find('=') {
+                if skipping {
+                    continue;
+                }
+                let element = match skin.sections.last_mut().and_then(|s| s.elements.last_mut()) {
+                    Some(element) => element,
+                    None => {
+                        context.register_error(DMError::new(location, "attribute outside of any elem"));
+                        continue;
+                    }
+                };
+                let value = trimmed[eq + 1..].trim();
+                match parse_value(value) {
+                    Some(value) => element.attributes.push((trimmed[..eq].trim().to_owned(), value)),
+                    None => context.register_error(DMError::new(location, format!("malformed value: {}", value))),
+                }
+            } else {
+                skipping = true;
+                context.register_error(DMError::new(location, format!("unknown section: {}", trimmed)));
+            }
+        }
+        skin
+    }
+
+    /// The sections of a given kind.
+    pub fn sections_of(&self, kind: SectionKind) -> impl Iterator<Item=&Section> {
+        self.sections.iter().filter(move |section| section.kind == kind)
+    }
+
+    /// The windows, including panes embedded in other windows.
+    pub fn windows(&self) -> impl Iterator<Item=&Section> {
+        self.sections_of(SectionKind::Window)
+    }
+
+    /// Find a control by name in any window.
+    pub fn find_control(&self, name: &str) -> Option<&Element> {
+        self.windows()
+            .flat_map(|window| window.elements.iter())
+            .find(|elem| elem.name.as_ref().map_or(false, |n| n == name))
+    }
+}
+
+/// A key binding in a client script's `macro` block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptMacro {
+    /// The key combination, such as `CTRL+Z`.
+    pub key: String,
+    /// The command to run.
+    pub command: String,
+    pub location: Location,
+}
+
+/// A parsed `.dms` client script.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Script {
+    pub macros: Vec<ScriptMacro>,
+    /// The contents of each `<STYLE>` sheet.
+    pub styles: Vec<(Location, String)>,
+}
+
+impl Script {
+    /// Read and parse a client script, registering it with the context.
+    pub fn from_file(context: &Context, path: &Path) -> io::Result<Script> {
+        let text = read_file(path)?;
+        let file = context.register_file(path);
+        Ok(Script::parse(context, file, &text))
+    }
+
+    /// Parse the text of a client script.
+    pub fn parse(context: &Context, file: FileId, text: &str) -> Script {
+        let mut script = Script::default();
+        let mut in_macro = false;
+        let mut style: Option<(Location, String)> = None;
+
+        for (location, line) in lines(file, text) {
+            if let Some((start, mut sheet)) = style.take() {
+                match find_ignore_case(line, "</style>") {
+                    Some(end) => script.styles.push((start, sheet + &line[..end])),
+                    None => {
+                        sheet.push_str(line);
+                        sheet.push('\n');
+                        style = Some((start, sheet));
+                    }
+                }
+                continue;
+            }
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with("//") || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if in_macro && line.starts_with(|c: char| c.is_whitespace()) {
+                let (key, rest) = split_word(trimmed);
+                let (word, after) = split_word(rest);
+                let command = if word == "return" { after } else { rest };
+                let command = match parse_value(command) {
+                    Some(Value::String(command)) => command,
+                    _ => command.to_owned(),
+                };
+                script.macros.push(ScriptMacro { key: key.to_owned(), command, location });
+                continue;
+            }
+
+            in_macro = false;
+            if let Some(start) = find_ignore_case(trimmed, "<style>") {
+                let sheet = &trimmed[start + "<style>".len()..];
+                match find_ignore_case(sheet, "</style>") {
+                    Some(end) => script.styles.push((location, sheet[..end].to_owned())),
+                    None => style = Some((location, format!("{}\n", sheet))),
+                }
+            } else if split_word(trimmed).0 == "macro" {
+                in_macro = true;
+            } else {
+                context.register_error(DMError::new(location, "unrecognized line in client script")
+                    .set_severity(Severity::Hint));
+            }
+        }
+
+        if let Some((start, _)) = style {
+            context.register_error(DMError::new(start, "unterminated <STYLE>"));
+        }
+        script
+    }
+}
+
+fn read_file(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+fn lines(file: FileId, text: &str) -> impl Iterator<Item=(Location, &str)> {
+    text.lines().enumerate().map(move |(i, line)| (Location {
+        file,
+        line: i as u32 + 1,
+        column: 1,
+    }, line))
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(idx) => (&text[..idx], text[idx..].trim_left()),
+        None => (text, ""),
+    }
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.to_ascii_lowercase().find(needle)
+}
+
+/// Parse an attribute value, or `None` if its quotes are unbalanced.
+fn parse_value(text: &str) -> Option<Value> {
+    if text.starts_with('"') {
+        let mut result = String::new();
+        let mut chars = text[1..].chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => return if chars.as_str().trim().is_empty() {
+                    Some(Value::String(result))
+                } else {
+                    None
+                },
+                '\\' => match chars.next()? {
+                    'n' => result.push('\n'),
+                    other => result.push(other),
+                },
+                other => result.push(other),
+            }
+        }
+        None
+    } else if text.starts_with('\'') {
+        if text.len() >= 2 && text.ends_with('\'') {
+            Some(Value::Resource(text[1..text.len() - 1].replace("\\\\", "\\")))
+        } else {
+            None
+        }
+    } else {
+        Some(Value::Bare(text.to_owned()))
+    }
+}
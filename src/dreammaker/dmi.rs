@@ -1,8 +1,9 @@
 use std::io;
+use std::fmt;
 use std::path::Path;
 use std::collections::BTreeMap;
 
-use lodepng::ffi::{State as PngState};
+use lodepng::ffi::{State as PngState, ColorType};
 
 const VERSION: &str = "4.0";
 
@@ -99,12 +100,54 @@ impl Frames {
     }
 }
 
+// ----------------------------------------------------------------------------
+// Metadata writer
+
+/// Formats the metadata as it appears in a `Description` text chunk.
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# BEGIN DMI")?;
+        writeln!(f, "version = {}", VERSION)?;
+        writeln!(f, "\twidth = {}", self.width)?;
+        writeln!(f, "\theight = {}", self.height)?;
+        for state in self.states.iter() {
+            writeln!(f, "state = \"{}\"", state.name)?;
+            writeln!(f, "\tdirs = {}", state.dirs.len())?;
+            writeln!(f, "\tframes = {}", state.frames.len())?;
+            if let Frames::Delays(ref delays) = state.frames {
+                write!(f, "\tdelay = ")?;
+                for (i, delay) in delays.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", delay)?;
+                }
+                writeln!(f)?;
+            }
+            if state.loop_ != 0 {
+                writeln!(f, "\tloop = {}", state.loop_)?;
+            }
+            if state.rewind {
+                writeln!(f, "\trewind = 1")?;
+            }
+            if state.movement {
+                writeln!(f, "\tmovement = 1")?;
+            }
+        }
+        writeln!(f, "# END DMI")
+    }
+}
+
 // ----------------------------------------------------------------------------
 // Metadata parser
 
 fn read_metadata(path: &Path) -> io::Result<String> {
     let path = &::fix_case(path);
     let mut decoder = PngState::new();
+    // when the requested color mode matches the file's, lodepng compares
+    // their palettes by slicing null pointers, so ask for 16-bit output
+    decoder.info_raw.colortype = ColorType::RGBA;
+    decoder.info_raw.set_bitdepth(16);
     decoder.remember_unknown_chunks(false);
     match decoder.decode_file(path) {
         Ok(_) => {}
@@ -4,6 +4,7 @@ use std::fmt;
 
 /// A collection of documentation comments targeting the same item.
 #[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct DocCollection {
     elems: Vec<DocComment>,
 }
@@ -24,6 +25,22 @@ impl DocCollection {
         self.elems.iter().all(|c| c.is_empty())
     }
 
+    /// Estimate the heap memory owned by this collection.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.elems.capacity() * ::std::mem::size_of::<DocComment>()
+            + self.elems.iter().map(|c| c.text.capacity()).sum::<usize>()
+    }
+
+    /// The note following a `@deprecated` tag in this collection, if there
+    /// is one.
+    pub fn deprecation(&self) -> Option<String> {
+        const TAG: &str = "@deprecated";
+        self.text().lines()
+            .map(str::trim)
+            .find(|line| line.starts_with(TAG))
+            .map(|line| line[TAG.len()..].trim().to_owned())
+    }
+
     /// Render this collection to a single Markdown document.
     pub fn text(&self) -> String {
         let mut output = String::new();
@@ -60,6 +77,7 @@ impl DocCollection {
 
 /// A documentation comment.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct DocComment {
     pub kind: CommentKind,
     pub target: DocTarget,
@@ -95,6 +113,7 @@ impl fmt::Display for DocComment {
 
 /// The possible documentation comment kinds.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum CommentKind {
     /// A block `/** */` comment.
     Block,
@@ -197,6 +216,7 @@ fn is_empty(text: &str, ignore_char: char) -> bool {
 
 /// The possible items that a documentation comment may target.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum DocTarget {
     /// Starting with `*` or `/`, referring to the following item.
     FollowingItem,
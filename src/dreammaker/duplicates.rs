@@ -0,0 +1,143 @@
+//! Detection of copy-pasted procs.
+//!
+//! Each proc body is normalized by renaming its parameters and local vars in
+//! the order they are declared, then printed and hashed. Bodies which differ
+//! only in the names of their locals, in comments, or in whitespace hash the
+//! same, and are reported together.
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use ast::*;
+use visit::*;
+use objtree::{ObjectTree, TypeRef, ProcValue};
+use rewrite::format_block;
+use {Context, FileId};
+
+/// One of a group of procs with the same normalized body.
+#[derive(Debug, Clone)]
+pub struct DuplicateProc<'a> {
+    pub ty: TypeRef<'a>,
+    pub name: &'a str,
+    pub proc: &'a ProcValue,
+}
+
+/// A set of procs whose bodies are the same up to local names.
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup<'a> {
+    /// The number of statements in each body, counting nested statements.
+    pub statements: usize,
+    /// The procs, sorted by location.
+    pub procs: Vec<DuplicateProc<'a>>,
+}
+
+/// Hash a proc body after normalizing the names of its parameters and
+/// locals.
+pub fn body_hash(parameters: &[Parameter], block: &[Spanned<Statement>]) -> u64 {
+    let mut normalizer = Normalizer::default();
+    for param in parameters.iter() {
+        normalizer.rename(&param.name);
+    }
+    let mut block = block.to_vec();
+    normalizer.visit_block(&mut block);
+
+    let mut hasher = DefaultHasher::new();
+    parameters.len().hash(&mut hasher);
+    format_block(&block, "\t", 0).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Find groups of procs in the environment whose bodies are the same up to
+/// local names, skipping bodies of fewer than `min_statements` statements.
+///
+/// Groups are sorted by the location of their first proc.
+pub fn duplicate_procs<'a>(context: &Context, tree: &'a ObjectTree, min_statements: usize) -> Vec<DuplicateGroup<'a>> {
+    let mut by_hash: HashMap<u64, DuplicateGroup<'a>> = HashMap::new();
+    for idx in tree.graph.node_indices() {
+        let ty = TypeRef::new(tree, idx);
+        for (name, proc) in ty.get().procs.iter() {
+            for value in proc.value.iter() {
+                if value.location.file == FileId::builtins() {
+                    continue;
+                }
+                let body = match value.body(context) {
+                    Some(body) => body,
+                    None => continue,
+                };
+                let statements = count_statements(&body);
+                if statements < min_statements {
+                    continue;
+                }
+                by_hash.entry(body_hash(&value.parameters, &body))
+                    .or_insert_with(|| DuplicateGroup { statements, procs: Vec::new() })
+                    .procs
+                    .push(DuplicateProc { ty, name, proc: value });
+            }
+        }
+    }
+
+    let mut groups: Vec<_> = by_hash.into_iter()
+        .map(|(_, group)| group)
+        .filter(|group| group.procs.len() > 1)
+        .collect();
+    for group in groups.iter_mut() {
+        group.procs.sort_by_key(|each| each.proc.location);
+    }
+    groups.sort_by_key(|group| group.procs[0].proc.location);
+    groups
+}
+
+/// Count the statements in a block, including those nested in others.
+pub fn count_statements(block: &[Spanned<Statement>]) -> usize {
+    struct Counter(usize);
+
+    impl<'ast> Visitor<'ast> for Counter {
+        fn visit_statement(&mut self, statement: &'ast Statement) {
+            self.0 += 1;
+            walk_statement(self, statement);
+        }
+    }
+
+    let mut counter = Counter(0);
+    counter.visit_block(block);
+    counter.0
+}
+
+/// Renames parameters and locals to `_1`, `_2`, and so on, in the order
+/// they are declared.
+#[derive(Default)]
+struct Normalizer {
+    names: HashMap<String, String>,
+}
+
+impl Normalizer {
+    fn rename(&mut self, name: &str) -> String {
+        let next = format!("_{}", self.names.len() + 1);
+        self.names.entry(name.to_owned()).or_insert(next).clone()
+    }
+}
+
+impl VisitorMut for Normalizer {
+    fn visit_statement(&mut self, statement: &mut Statement) {
+        match *statement {
+            Statement::Var(ref mut var) => var.name = self.rename(&var.name),
+            Statement::Vars(ref mut vars) => for var in vars.iter_mut() {
+                var.name = self.rename(&var.name);
+            },
+            Statement::ForList { ref mut name, .. } |
+            Statement::ForRange { ref mut name, .. } => *name = self.rename(name),
+            Statement::TryCatch { catch_param: Some((_, ref mut name)), .. } => *name = self.rename(name),
+            _ => {}
+        }
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_term(&mut self, term: &mut Term) {
+        if let Term::Ident(ref mut name) = *term {
+            if let Some(renamed) = self.names.get(name.as_str()) {
+                *name = renamed.clone();
+            }
+        }
+        walk_term_mut(self, term);
+    }
+}
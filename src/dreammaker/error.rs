@@ -1,12 +1,21 @@
 //! Error, warning, and other diagnostics handling.
 
 use std::{fmt, error, io};
+use std::ops::Range;
 use std::path::{PathBuf, Path};
-use std::cell::{RefCell, Ref};
-use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet};
+
+use lexer::Encoding;
+use analysis::Lint;
+use builtins::CustomBuiltins;
+use files::{FileProvider, NativeFiles};
 
 /// An identifier referring to a loaded file.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct FileId(u16);
 
 const FILEID_BUILTINS: FileId = FileId(0x0000);
@@ -28,38 +37,142 @@ impl FileId {
 }
 
 /// A diagnostics context, tracking loaded files and any observed errors.
+///
+/// May be shared between threads.
 #[derive(Debug, Default)]
 pub struct Context {
     /// The list of loaded files.
-    files: RefCell<Vec<PathBuf>>,
+    files: Mutex<Vec<PathBuf>>,
     /// Reverse mapping from paths to file numbers.
-    reverse_files: RefCell<HashMap<PathBuf, FileId>>,
+    reverse_files: Mutex<HashMap<PathBuf, FileId>>,
     /// A list of errors, warnings, and other diagnostics generated.
-    errors: RefCell<Vec<DMError>>,
+    errors: Mutex<Vec<DMError>>,
     /// Severity at and above which errors will be printed immediately.
     print_severity: Option<Severity>,
+    /// Whether printed diagnostics are colored for a terminal.
+    color: bool,
+    /// The encoding used to decode source files.
+    encoding: Encoding,
+    /// Optional lints which have been turned on.
+    enabled_lints: Mutex<HashSet<String>>,
+    /// Codebase-specific settings for lints, such as preferred proc names.
+    lint_options: Mutex<HashMap<String, String>>,
+    /// Lints supplied by the codebase.
+    lints: Vec<Box<Lint>>,
+    /// Native procs and vars added to the builtins by the codebase.
+    custom_builtins: Mutex<Arc<CustomBuiltins>>,
+    /// Where source files are read from, if not the filesystem.
+    file_provider: Option<Box<FileProvider>>,
+    /// Contents which replace those of the file provider.
+    file_contents: Mutex<HashMap<PathBuf, Vec<u8>>>,
+    /// The BYOND version being targeted, if not the latest.
+    byond_version: Option<u32>,
+    /// How deeply the parser may nest, if not the default.
+    nesting_limit: Option<usize>,
+    /// Totals for how much source text has been lexed.
+    lexed: Mutex<LexedTotals>,
+    /// Set from outside to abandon work in progress.
+    cancel: Option<Arc<AtomicBool>>,
+    /// Called as parsing and analysis progress.
+    progress: Option<ProgressHandler>,
+}
+
+/// A stage of loading an environment, as reported to a progress handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Files are being preprocessed and parsed. Counts files, where the
+    /// total is the number of `#include`s in the environment file, or more
+    /// once nested includes are found.
+    Parsing,
+    /// Constant vars are being evaluated. Counts types.
+    Evaluating,
+    /// Procs are being checked. Counts types.
+    Analyzing,
+}
+
+/// The clock is unavailable on `wasm32-unknown-unknown`, where throughput
+/// goes unmeasured.
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now() -> Option<Instant> {
+    None
+}
+
+struct ProgressHandler(Box<Fn(Phase, usize, usize) + Send + Sync>);
+
+impl fmt::Debug for ProgressHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressHandler")
+    }
+}
+
+#[derive(Debug, Default)]
+struct LexedTotals {
+    bytes: u64,
+    tokens: u64,
+    started: Option<Instant>,
+    finished: Option<Instant>,
+}
+
+/// How much source text has been lexed, and how quickly.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Throughput {
+    /// The number of bytes read from source files.
+    pub bytes: u64,
+    /// The number of tokens produced from those bytes.
+    pub tokens: u64,
+    /// The time from when the first lexer was created until the last one
+    /// finished, which includes the preprocessing and parsing done meanwhile.
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    fn seconds(&self) -> f64 {
+        self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 * 1e-9
+    }
+
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.seconds()
+    }
+
+    pub fn tokens_per_second(&self) -> f64 {
+        self.tokens as f64 / self.seconds()
+    }
 }
 
+/// The default for how deeply blocks and expressions may nest.
+pub const DEFAULT_NESTING_LIMIT: usize = 128;
+
 impl Context {
     /// Add a new file to the context and return its index.
     pub fn register_file(&self, path: &Path) -> FileId {
-        if let Some(id) = self.reverse_files.borrow().get(path).cloned() {
+        let mut reverse_files = self.reverse_files.lock().unwrap();
+        if let Some(id) = reverse_files.get(path).cloned() {
             return id;
         }
-        let mut files = self.files.borrow_mut();
+        let mut files = self.files.lock().unwrap();
         if files.len() > FILEID_MAX.0 as usize {
             panic!("file limit of {} exceeded", FILEID_MAX.0);
         }
         let len = files.len() as u16;
         files.push(path.to_owned());
         let id = FileId(len + FILEID_MIN.0);
-        self.reverse_files.borrow_mut().insert(path.to_owned(), id);
+        reverse_files.insert(path.to_owned(), id);
         id
     }
 
     /// Look up a file's ID by its path, without inserting it.
     pub fn get_file(&self, path: &Path) -> Option<FileId> {
-        self.reverse_files.borrow().get(path).cloned()
+        self.reverse_files.lock().unwrap().get(path).cloned()
+    }
+
+    /// Get the paths of all registered files, in the order they were added.
+    pub fn file_list(&self) -> Vec<PathBuf> {
+        self.files.lock().unwrap().clone()
     }
 
     /// Look up a file path by its index returned from `register_file`.
@@ -68,7 +181,7 @@ impl Context {
             return "(builtins)".into();
         }
         let idx = (file.0 - FILEID_MIN.0) as usize;
-        let files = self.files.borrow();
+        let files = self.files.lock().unwrap();
         if idx > files.len() {
             "(unknown)".into()
         } else {
@@ -85,12 +198,27 @@ impl Context {
                     .expect("error writing to stderr");
             }
         }
-        self.errors.borrow_mut().push(error);
+        self.errors.lock().unwrap().push(error);
+    }
+
+    /// Get a copy of the list of diagnostics generated so far.
+    pub fn errors(&self) -> Vec<DMError> {
+        self.errors.lock().unwrap().clone()
     }
 
-    /// Access the list of diagnostics generated so far.
-    pub fn errors(&self) -> Ref<[DMError]> {
-        Ref::map(self.errors.borrow(), |x| &**x)
+    /// Check whether any diagnostic at `severity` or worse was generated.
+    pub fn has_errors_at(&self, severity: Severity) -> bool {
+        self.errors.lock().unwrap().iter().any(|error| error.severity <= severity)
+    }
+
+    /// Remove and return the diagnostics generated so far.
+    pub fn take_errors(&self) -> Vec<DMError> {
+        ::std::mem::replace(&mut *self.errors.lock().unwrap(), Vec::new())
+    }
+
+    /// Color printed diagnostics with ANSI escapes.
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
     }
 
     /// Set a severity at and above which errors will be printed immediately.
@@ -98,16 +226,275 @@ impl Context {
         self.print_severity = print_severity;
     }
 
-    /// Pretty-print a `DMError` to the given output.
+    /// Set the encoding used to decode source files.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Get the encoding used to decode source files.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Target a specific BYOND version, warning about newer builtins.
+    pub fn set_byond_version(&mut self, version: u32) {
+        self.byond_version = Some(version);
+    }
+
+    /// Get the targeted BYOND version, if one was set.
+    pub fn byond_version(&self) -> Option<u32> {
+        self.byond_version
+    }
+
+    /// Set how deeply blocks and expressions may nest before the parser
+    /// gives up on them, to avoid overflowing the stack.
+    pub fn set_nesting_limit(&mut self, limit: usize) {
+        self.nesting_limit = Some(limit);
+    }
+
+    /// Get how deeply blocks and expressions may nest.
+    pub fn nesting_limit(&self) -> usize {
+        self.nesting_limit.unwrap_or(DEFAULT_NESTING_LIMIT)
+    }
+
+    /// Set a flag which, once raised, causes preprocessing, parsing, and
+    /// analysis using this context to stop early.
+    ///
+    /// The results of cancelled work are incomplete and should be discarded.
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = Some(flag);
+    }
+
+    /// Check whether the cancel flag has been raised.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().map_or(false, |flag| flag.load(Ordering::Relaxed))
+    }
+
+    /// Set a function to be called with the phase, the amount of work done,
+    /// and the total amount of work as an environment is loaded.
+    ///
+    /// Within a phase the handler is called at most about a hundred times,
+    /// and is always called once the phase is complete.
+    pub fn set_progress_handler<F>(&mut self, handler: F)
+        where F: Fn(Phase, usize, usize) + Send + Sync + 'static
+    {
+        self.progress = Some(ProgressHandler(Box::new(handler)));
+    }
+
+    pub(crate) fn report_progress(&self, phase: Phase, done: usize, total: usize) {
+        if let Some(ref progress) = self.progress {
+            if done == total || done % ::std::cmp::max(total / 100, 1) == 0 {
+                (progress.0)(phase, done, total);
+            }
+        }
+    }
+
+    pub(crate) fn lexing_started(&self) {
+        let mut lexed = self.lexed.lock().unwrap();
+        if lexed.started.is_none() {
+            lexed.started = now();
+        }
+    }
+
+    pub(crate) fn record_lexed(&self, bytes: u64, tokens: u64) {
+        let mut lexed = self.lexed.lock().unwrap();
+        lexed.bytes += bytes;
+        lexed.tokens += tokens;
+        lexed.finished = now();
+    }
+
+    /// Get how much source text has been lexed so far, and how quickly.
+    pub fn throughput(&self) -> Throughput {
+        let lexed = self.lexed.lock().unwrap();
+        Throughput {
+            bytes: lexed.bytes,
+            tokens: lexed.tokens,
+            elapsed: match (lexed.started, lexed.finished) {
+                (Some(started), Some(finished)) => finished - started,
+                _ => Duration::default(),
+            },
+        }
+    }
+
+    /// Turn on an optional lint by name.
+    pub fn enable_lint(&self, name: &str) {
+        self.enabled_lints.lock().unwrap().insert(name.to_owned());
+    }
+
+    /// Check whether an optional lint has been turned on.
+    pub fn lint_enabled(&self, name: &str) -> bool {
+        self.enabled_lints.lock().unwrap().contains(name)
+    }
+
+    /// Set a codebase-specific lint setting.
+    pub fn set_lint_option(&self, name: &str, value: &str) {
+        self.lint_options.lock().unwrap().insert(name.to_owned(), value.to_owned());
+    }
+
+    /// Get a codebase-specific lint setting, if it was set.
+    pub fn lint_option(&self, name: &str) -> Option<String> {
+        self.lint_options.lock().unwrap().get(name).cloned()
+    }
+
+    /// Add a lint to be run over the object tree once it is finished.
+    pub fn register_lint(&mut self, lint: Box<Lint>) {
+        self.lints.push(lint);
+    }
+
+    /// Get the lints supplied by the codebase.
+    pub fn lints(&self) -> &[Box<Lint>] {
+        &self.lints
+    }
+
+    /// Declare native procs and vars which the codebase adds to the
+    /// builtins, to be registered before parsing.
+    pub fn set_custom_builtins(&self, custom: CustomBuiltins) {
+        *self.custom_builtins.lock().unwrap() = Arc::new(custom);
+    }
+
+    /// Get the natives which the codebase adds to the builtins.
+    pub fn custom_builtins(&self) -> Arc<CustomBuiltins> {
+        self.custom_builtins.lock().unwrap().clone()
+    }
+
+    /// Read source files from somewhere other than the filesystem.
+    pub fn set_file_provider<P: FileProvider + 'static>(&mut self, provider: P) {
+        self.file_provider = Some(Box::new(provider));
+    }
+
+    /// Get where source files are read from.
+    pub fn file_provider(&self) -> &FileProvider {
+        static NATIVE: NativeFiles = NativeFiles;
+        match self.file_provider {
+            Some(ref provider) => &**provider,
+            None => &NATIVE,
+        }
+    }
+
+    /// Override the contents of a file, such as with an unsaved editor
+    /// buffer, until cleared.
+    ///
+    /// Takes effect from the next parse. A file which was already parsed
+    /// keeps its `FileId`, so locations from earlier parses stay valid.
+    pub fn set_file_contents(&self, path: &Path, contents: Vec<u8>) {
+        self.file_contents.lock().unwrap().insert(path.to_owned(), contents);
+    }
+
+    /// Return to reading a file from the file provider.
+    pub fn clear_file_contents(&self, path: &Path) {
+        self.file_contents.lock().unwrap().remove(path);
+    }
+
+    /// Check whether a source file exists, counting overridden contents.
+    pub(crate) fn file_exists(&self, path: &Path) -> bool {
+        self.file_contents.lock().unwrap().contains_key(path) || self.file_provider().exists(path)
+    }
+
+    /// Open a source file, preferring overridden contents.
+    pub(crate) fn open_file(&self, path: &Path) -> io::Result<Box<io::Read>> {
+        if let Some(contents) = self.file_contents.lock().unwrap().get(path) {
+            return Ok(Box::new(io::Cursor::new(contents.clone())));
+        }
+        self.file_provider().open(path)
+    }
+
+    /// Describe the diagnostics generated so far as a JSON array.
+    #[cfg(any(feature = "capi", feature = "wasm"))]
+    pub(crate) fn errors_json(&self) -> String {
+        let errors: Vec<::serde_json::Value> = self.errors.lock().unwrap().iter().map(|error| json!({
+            "file": self.file_path(error.location.file).display().to_string(),
+            "line": error.location.line,
+            "column": error.location.column,
+            "end_line": error.span().end.line,
+            "end_column": error.span().end.column,
+            "severity": error.severity.to_string(),
+            "message": error.description,
+            "notes": error.notes.iter().map(|note| json!({
+                "file": self.file_path(note.location.file).display().to_string(),
+                "line": note.location.line,
+                "column": note.location.column,
+                "message": note.description,
+            })).collect::<Vec<_>>(),
+        })).collect();
+        ::serde_json::Value::Array(errors).to_string()
+    }
+
+    /// Pretty-print a `DMError` to the given output, underlining the
+    /// offending code in the line of source it is on.
+    ///
+    /// The source is left out if its file cannot be read.
     pub fn pretty_print_error<W: io::Write>(&self, w: &mut W, error: &DMError) -> io::Result<()> {
+        let esc = |code: &'static str| if self.color { code } else { "" };
+        let color = esc(severity_color(error.severity));
+        let gutter = esc("\x1b[1;34m");
+        let bold = esc("\x1b[1m");
+        let reset = esc("\x1b[0m");
+
+        let location = error.location;
+        let number = location.line.to_string();
+        let pad = " ".repeat(number.len());
+        writeln!(w, "{}{}{}: {}{}{}", color, error.severity, reset, bold, error.description, reset)?;
         writeln!(
             w,
-            "{}, line {}, column {}:",
-            self.file_path(error.location.file).display(),
-            error.location.line,
-            error.location.column,
+            "{}{}-->{} {}:{}:{}",
+            pad,
+            gutter,
+            reset,
+            self.file_path(location.file).display(),
+            location.line,
+            location.column,
         )?;
-        writeln!(w, "{}: {}\n", error.severity, error.description)
+
+        if let Some(source) = self.source_line(location) {
+            let column = location.column as usize;
+            let end = match error.end {
+                Some(end) if end.file == location.file
+                    && end.line == location.line
+                    && end.column as usize > column => end.column as usize,
+                _ => column + 1,
+            };
+            // copy tabs so that the underline lines up however wide they are
+            let indent: String = source.iter()
+                .take(column.saturating_sub(1))
+                .map(|&b| if b == b'\t' { '\t' } else { ' ' })
+                .collect();
+            writeln!(w, "{} {}|{}", pad, gutter, reset)?;
+            writeln!(w, "{}{} |{} {}", gutter, number, reset, String::from_utf8_lossy(&source))?;
+            writeln!(w, "{} {}|{} {}{}{}{}", pad, gutter, reset, indent, color, "^".repeat(end - column), reset)?;
+        }
+
+        for note in error.notes.iter() {
+            writeln!(
+                w,
+                "{} {}={} {}note{}: {}:{}:{}: {}",
+                pad,
+                gutter,
+                reset,
+                bold,
+                reset,
+                self.file_path(note.location.file).display(),
+                note.location.line,
+                note.location.column,
+                note.description,
+            )?;
+        }
+        writeln!(w)
+    }
+
+    /// Read the line of source containing a location, without its line
+    /// ending.
+    fn source_line(&self, location: Location) -> Option<Vec<u8>> {
+        use std::io::BufRead;
+
+        if location.is_builtins() || location.line == 0 {
+            return None;
+        }
+        let read = self.open_file(&self.file_path(location.file)).ok()?;
+        let mut line = io::BufReader::new(read).split(b'\n').nth(location.line as usize - 1)?.ok()?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Some(line)
     }
 
     /// Pretty-print all registered diagnostics to standard error.
@@ -116,7 +503,7 @@ impl Context {
     pub fn print_all_errors(&self, min_severity: Severity) -> bool {
         let stderr = io::stderr();
         let stderr = &mut stderr.lock();
-        let errors = self.errors();
+        let errors = self.errors.lock().unwrap();
         let mut printed = false;
         for err in errors.iter() {
             if err.severity <= min_severity {
@@ -142,6 +529,7 @@ impl Context {
 
 /// File, line, and column information for an error.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct Location {
     /// The index into the file table.
     pub file: FileId,
@@ -177,6 +565,14 @@ impl Location {
     pub fn is_builtins(self) -> bool {
         self.file == FileId::builtins()
     }
+
+    /// Return the location `n` columns to the right, such as the end of a
+    /// token which starts here and is `n` bytes long.
+    pub fn add_columns(mut self, n: usize) -> Location {
+        let n = ::std::cmp::min(n, u16::max_value() as usize) as u16;
+        self.column = self.column.saturating_add(n);
+        self
+    }
 }
 
 /// A trait for types which may yield location information.
@@ -221,6 +617,16 @@ impl Default for Severity {
     }
 }
 
+/// The ANSI escape to color a severity by.
+fn severity_color(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "\x1b[1;31m",
+        Severity::Warning => "\x1b[1;33m",
+        Severity::Info => "\x1b[1;36m",
+        Severity::Hint => "\x1b[1;32m",
+    }
+}
+
 impl fmt::Display for Severity {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -233,12 +639,23 @@ impl fmt::Display for Severity {
 }
 
 /// An error produced during DM parsing, with location information.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DMError {
     location: Location,
+    end: Option<Location>,
     severity: Severity,
     description: String,
-    cause: Option<Box<error::Error + Send + Sync>>,
+    cause: Option<Arc<error::Error + Send + Sync>>,
+    fixes: Vec<Fix>,
+    notes: Vec<DiagnosticNote>,
+}
+
+/// A secondary location relevant to a diagnostic, such as a previous
+/// definition of the same thing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticNote {
+    pub location: Location,
+    pub description: String,
 }
 
 #[allow(unused_variables)]
@@ -246,14 +663,17 @@ impl DMError {
     pub fn new<S: Into<String>>(location: Location, desc: S) -> DMError {
         DMError {
             location,
+            end: None,
             severity: Default::default(),
             description: desc.into(),
             cause: None,
+            fixes: Vec::new(),
+            notes: Vec::new(),
         }
     }
 
     pub fn set_cause<E: error::Error + Send + Sync + 'static>(mut self, cause: E) -> DMError {
-        self.cause = Some(Box::new(cause));
+        self.cause = Some(Arc::new(cause));
         self
     }
 
@@ -262,11 +682,44 @@ impl DMError {
         self
     }
 
+    /// Mark where the offending code ends, exclusive, so that all of it can
+    /// be underlined rather than just its start.
+    pub fn set_end(mut self, end: Location) -> DMError {
+        self.end = Some(end);
+        self
+    }
+
+    /// Point out another location relevant to this diagnostic.
+    pub fn with_note<S: Into<String>>(mut self, location: Location, desc: S) -> DMError {
+        self.notes.push(DiagnosticNote {
+            location,
+            description: desc.into(),
+        });
+        self
+    }
+
+    /// Attach a machine-applicable fix to this diagnostic.
+    pub fn add_fix(mut self, fix: Fix) -> DMError {
+        self.fixes.push(fix);
+        self
+    }
+
     /// Get the location in the code at which this error was observed.
     pub fn location(&self) -> Location {
         self.location
     }
 
+    /// Get where the offending code ends, exclusive, if known.
+    pub fn end(&self) -> Option<Location> {
+        self.end
+    }
+
+    /// Get the span of the offending code, which is empty if its end is not
+    /// known.
+    pub fn span(&self) -> Range<Location> {
+        self.location..self.end.unwrap_or(self.location)
+    }
+
     /// Get the severity of this diagnostic.
     pub fn severity(&self) -> Severity {
         self.severity
@@ -277,6 +730,16 @@ impl DMError {
         &self.description
     }
 
+    /// Get the other locations relevant to this diagnostic.
+    pub fn notes(&self) -> &[DiagnosticNote] {
+        &self.notes
+    }
+
+    /// Get the suggested fixes attached to this error.
+    pub fn fixes(&self) -> &[Fix] {
+        &self.fixes
+    }
+
     /// Deconstruct this error, returning only the description.
     pub fn into_description(self) -> String {
         self.description
@@ -298,3 +761,79 @@ impl error::Error for DMError {
         self.cause.as_ref().map(|x| &**x as &error::Error)
     }
 }
+
+// ----------------------------------------------------------------------------
+// Suggested fixes
+
+/// A machine-applicable replacement which resolves a diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    /// The source range to replace, exclusive of the end. Both ends must lie
+    /// in the same file.
+    pub replacement_range: Range<Location>,
+    /// The text to put in place of the range.
+    pub replacement_text: String,
+    /// A short description of the change.
+    pub description: String,
+}
+
+impl Fix {
+    pub fn new<S: Into<String>, D: Into<String>>(range: Range<Location>, text: S, desc: D) -> Fix {
+        Fix {
+            replacement_range: range,
+            replacement_text: text.into(),
+            description: desc.into(),
+        }
+    }
+
+    /// Replace the single character at the given location.
+    pub fn replace_char<S: Into<String>, D: Into<String>>(location: Location, text: S, desc: D) -> Fix {
+        Fix::new(location..location.add_columns(1), text, desc)
+    }
+}
+
+/// Apply fixes to the contents of a single file.
+///
+/// Fixes which overlap one already applied, or which fall outside the file,
+/// are skipped. Returns the new contents and the number of fixes applied.
+pub fn apply_fixes(source: &[u8], fixes: &[&Fix]) -> (Vec<u8>, usize) {
+    let mut line_starts = vec![0];
+    for (i, &ch) in source.iter().enumerate() {
+        if ch == b'\n' {
+            line_starts.push(i + 1);
+        }
+    }
+    let offset = |loc: Location| -> Option<usize> {
+        let start = *line_starts.get((loc.line as usize).checked_sub(1)?)?;
+        let offset = start + (loc.column as usize).checked_sub(1)?;
+        if offset <= source.len() { Some(offset) } else { None }
+    };
+
+    let mut ranges: Vec<(usize, usize, &str)> = fixes.iter()
+        .filter_map(|fix| {
+            let start = offset(fix.replacement_range.start)?;
+            let end = offset(fix.replacement_range.end)?;
+            if start <= end {
+                Some((start, end, fix.replacement_text.as_str()))
+            } else {
+                None
+            }
+        })
+        .collect();
+    ranges.sort_by_key(|&(start, end, _)| (start, end));
+
+    let mut output = Vec::with_capacity(source.len());
+    let mut pos = 0;
+    let mut applied = 0;
+    for (start, end, text) in ranges {
+        if start < pos {
+            continue;
+        }
+        output.extend_from_slice(&source[pos..start]);
+        output.extend_from_slice(text.as_bytes());
+        pos = end;
+        applied += 1;
+    }
+    output.extend_from_slice(&source[pos..]);
+    (output, applied)
+}
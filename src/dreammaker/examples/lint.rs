@@ -1,5 +1,6 @@
-//! A very simple CLI binary which finds a `.dme` file in the current directory
-//! and prints all non-hint diagnostics from parsing the object tree and procs.
+//! A very simple CLI binary which finds a `.dme` file in the current directory,
+//! or a parent, and prints all non-hint diagnostics from parsing the object
+//! tree and procs.
 
 extern crate dreammaker as dm;
 
@@ -7,7 +8,6 @@ fn main() {
     let mut context = dm::Context::default();
     context.set_print_severity(Some(dm::Severity::Info));
     let env = dm::detect_environment_default()
-        .expect("error detecting .dme")
         .expect("no .dme found");
     let pp = dm::preprocessor::Preprocessor::new(&context, env)
         .expect("i/o error opening .dme");
@@ -0,0 +1,87 @@
+//! Access to source files, so environments need not live on disk.
+//!
+//! The preprocessor reads the `.dme` and everything it includes through the
+//! `Context`'s `FileProvider`, which is the real filesystem by default, as
+//! does the cache when checking whether files have changed. Web
+//! builds, which have no filesystem, supply the files in memory instead.
+//! Other resources, such as icons and interface files, are still read from
+//! disk.
+//!
+//! Unsaved editor buffers are laid over the provider with
+//! `Context::set_file_contents`.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of file contents by path.
+pub trait FileProvider: fmt::Debug + Send + Sync {
+    /// Check whether a file exists at the given path.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Open the file at the given path for reading.
+    fn open(&self, path: &Path) -> io::Result<Box<Read>>;
+
+    /// Get when the file at the given path was last modified.
+    fn modified(&self, path: &Path) -> io::Result<SystemTime>;
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()))
+}
+
+/// Reads files from the real filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeFiles;
+
+impl FileProvider for NativeFiles {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<Read>> {
+        Ok(Box::new(io::BufReader::new(File::open(path)?)))
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        fs::metadata(path)?.modified()
+    }
+}
+
+/// Serves files from memory, such as those uploaded to a web page.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFiles {
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFiles {
+    /// Add a file, replacing any already at that path.
+    pub fn insert<P: Into<PathBuf>, C: Into<Vec<u8>>>(&mut self, path: P, contents: C) {
+        self.files.insert(path.into(), contents.into());
+    }
+}
+
+impl FileProvider for MemoryFiles {
+    fn exists(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<Read>> {
+        match self.files.get(path) {
+            Some(contents) => Ok(Box::new(io::Cursor::new(contents.clone()))),
+            None => Err(not_found(path)),
+        }
+    }
+
+    /// Memory files never change once added, so all are reported as
+    /// modified at the epoch.
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        if self.files.contains_key(path) {
+            Ok(UNIX_EPOCH)
+        } else {
+            Err(not_found(path))
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Whitespace-only formatting of source files.
+//!
+//! Unlike the printer in `rewrite`, which prints proc bodies back out from
+//! the syntax tree, formatting keeps every token and comment as written and
+//! changes only whitespace. Indentation is re-emitted in the configured style
+//! at the depth the indentation processor reads from it, and trailing
+//! whitespace is removed. Lines which continue a bracketed expression or lie
+//! inside braces, multi-line strings, or block comments are left alone, as
+//! are preprocessor directives.
+use std::ops::Range;
+
+use super::{Context, FileId, Fix, Location};
+use super::lexer::{Lexer, Punctuation, Token};
+
+/// The whitespace used for one level of indentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    /// The given number of spaces.
+    Spaces(usize),
+}
+
+impl IndentStyle {
+    fn unit(self) -> String {
+        match self {
+            IndentStyle::Tabs => "\t".to_owned(),
+            IndentStyle::Spaces(width) => " ".repeat(width),
+        }
+    }
+}
+
+impl Default for IndentStyle {
+    fn default() -> IndentStyle {
+        IndentStyle::Tabs
+    }
+}
+
+/// Settings for `format_lines`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub indent: IndentStyle,
+    pub trim_trailing_whitespace: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions {
+            indent: IndentStyle::Tabs,
+            trim_trailing_whitespace: true,
+        }
+    }
+}
+
+/// How a line's leading whitespace is treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    /// Whitespace which is not indentation, or a line which starts inside a
+    /// token or comment.
+    Untouched,
+    /// Code at the head of a line, with the number of whitespace characters
+    /// before it.
+    Code(usize),
+    /// A blank line or one holding only a comment.
+    Comment(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LineInfo {
+    kind: LineKind,
+    /// Whether the line ends outside of any string or block comment.
+    ends_in_code: bool,
+}
+
+/// Format an entire file, returning an edit for each line which changed.
+pub fn format_file(file: FileId, source: &str, options: &FormatOptions) -> Vec<Fix> {
+    format_lines(file, source, options, 1..u32::max_value())
+}
+
+/// Format the given one-based lines of a file, returning an edit for each
+/// line which changed.
+///
+/// The whole file is read to learn the indentation of the lines in range.
+pub fn format_lines(file: FileId, source: &str, options: &FormatOptions, lines: Range<u32>) -> Vec<Fix> {
+    let info = classify_lines(file, source);
+    let unit = options.indent.unit();
+
+    let mut fixes = Vec::new();
+    // as in the indentation processor, the width of one indent and the
+    // current number of indents
+    let mut current: Option<(usize, usize)> = None;
+    for (idx, (text, each)) in source.split('\n').zip(info.iter()).enumerate() {
+        let line = idx as u32 + 1;
+        let text = text.trim_right_matches('\r');
+        let leading = text.len() - text.trim_left_matches(|c| c == ' ' || c == '\t').len();
+        let blank = leading == text.len();
+
+        let depth = match each.kind {
+            LineKind::Untouched => None,
+            LineKind::Code(0) => {
+                current = None;
+                Some(0)
+            }
+            LineKind::Code(width) => {
+                let depth = match current {
+                    None => 1,
+                    Some((per_indent, _)) => width / per_indent,
+                };
+                current = Some((current.map_or(width, |(per_indent, _)| per_indent), depth));
+                Some(depth)
+            }
+            LineKind::Comment(_) if blank => Some(0),
+            LineKind::Comment(width) => Some(match current {
+                Some((per_indent, _)) => width / per_indent,
+                None => if width > 0 { 1 } else { 0 },
+            }),
+        };
+        if line < lines.start || line >= lines.end {
+            continue;
+        }
+
+        let at = |column: usize| Location { file, line, column: column as u16 + 1 };
+        if let Some(depth) = depth {
+            let indent = unit.repeat(depth);
+            if text[..leading] != indent[..] {
+                fixes.push(Fix::new(at(0)..at(leading), indent, "reindent"));
+            }
+        }
+        if options.trim_trailing_whitespace && each.ends_in_code && !blank {
+            let trimmed = text.trim_right_matches(|c| c == ' ' || c == '\t').len();
+            if trimmed < text.len() {
+                fixes.push(Fix::new(at(trimmed)..at(text.len()), "", "remove trailing whitespace"));
+            }
+        }
+    }
+    fixes
+}
+
+/// Lex a file to find which lines begin with indentation and which end
+/// outside of strings and comments.
+fn classify_lines(file: FileId, source: &str) -> Vec<LineInfo> {
+    let mut info = vec![LineInfo { kind: LineKind::Untouched, ends_in_code: false }; source.split('\n').count()];
+    // lexing errors are reported when the file is parsed instead
+    let context = Context::default();
+    let mut parens = 0usize;
+    let mut braces = 0usize;
+    // the line whose leading whitespace is being read, and its length
+    let mut head = Some((1u32, 0usize));
+
+    for token in Lexer::new(&context, file, source.bytes().map(Ok)) {
+        match token.token {
+            Token::Punct(Punctuation::Tab) |
+            Token::Punct(Punctuation::Space) => {
+                if let Some((_, ref mut width)) = head {
+                    *width += 1;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if let Some((line, width)) = head.take() {
+            if let Some(each) = info.get_mut(line as usize - 1) {
+                each.kind = match token.token {
+                    _ if token.location.line != line => LineKind::Untouched,
+                    Token::Punct(Punctuation::Newline) |
+                    Token::DocComment(_) => LineKind::Comment(width),
+                    Token::Punct(Punctuation::Hash) => LineKind::Untouched,
+                    _ if parens == 0 && braces == 0 => LineKind::Code(width),
+                    _ => LineKind::Untouched,
+                };
+            }
+        }
+        match token.token {
+            Token::Punct(Punctuation::Newline) => {
+                if let Some(each) = info.get_mut((token.location.line as usize).wrapping_sub(1)) {
+                    each.ends_in_code = true;
+                }
+                if parens == 0 {
+                    head = Some((token.location.line + 1, 0));
+                }
+            }
+            Token::Punct(Punctuation::LParen) |
+            Token::Punct(Punctuation::LBracket) |
+            Token::Punct(Punctuation::SafeLBracket) => parens += 1,
+            Token::Punct(Punctuation::RParen) |
+            Token::Punct(Punctuation::RBracket) => parens = parens.saturating_sub(1),
+            Token::Punct(Punctuation::LBrace) => braces += 1,
+            Token::Punct(Punctuation::RBrace) => braces = braces.saturating_sub(1),
+            _ => {}
+        }
+    }
+    info
+}
@@ -0,0 +1,10 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+
+fuzz_target!(|data: &[u8]| {
+    let context = dm::Context::default();
+    for _ in Lexer::new(&context, Default::default(), data.iter().cloned().map(Ok)) {}
+});
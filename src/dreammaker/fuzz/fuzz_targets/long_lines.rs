@@ -0,0 +1,33 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+
+// Pad every line past the column limit, so that locations saturate.
+fuzz_target!(|data: &[u8]| {
+    let mut pad = b"/*".to_vec();
+    pad.extend(::std::iter::repeat(b' ').take(65536));
+    pad.extend_from_slice(b"*/");
+
+    let mut input = pad.clone();
+    for &b in data {
+        input.push(b);
+        if b == b'\n' {
+            input.extend_from_slice(&pad);
+        }
+    }
+
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), input.into_iter().map(Ok));
+    let mut annotations = dm::annotation::AnnotationTree::default();
+    {
+        let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+        parser.enable_procs();
+        parser.annotate_to(&mut annotations);
+        parser.run();
+    }
+    annotations.semantic_tokens(Default::default());
+});
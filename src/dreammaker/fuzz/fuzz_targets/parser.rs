@@ -0,0 +1,15 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+
+fuzz_target!(|data: &[u8]| {
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), data.iter().cloned().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.parse_object_tree();
+});
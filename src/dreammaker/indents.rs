@@ -1,9 +1,13 @@
 //! The indentation processor.
 use std::collections::VecDeque;
 
-use super::{Location, HasLocation, Context};
+use super::{Location, HasLocation, Context, FileId, Severity};
 use super::lexer::{LocatedToken, Token, Punctuation};
 
+/// Warns when a line's indentation mixes tabs and spaces, or uses different
+/// whitespace than earlier lines of the same file.
+pub const LINT_MIXED_INDENTATION: &str = "mixed-indentation";
+
 /// Eliminates blank lines, parses and validates indentation, braces, and semicolons.
 ///
 /// After processing, no Newline, Tab, or Space tokens remain.
@@ -19,6 +23,11 @@ pub struct IndentProcessor<'ctx, I> {
     current: Option<(usize, usize)>,
     // The number of spaces/tabs accumulated on the current line. None when not at line head.
     current_spaces: Option<usize>,
+    // Which whitespace characters appeared in the current line's indentation.
+    line_tabs: bool,
+    line_spaces: bool,
+    // The whitespace used by the first indented line of the current file.
+    indent_style: Option<(FileId, Punctuation)>,
     parentheses: usize,
     eof_yielded: bool,
 }
@@ -41,6 +50,9 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
             output: VecDeque::new(),
             current: None,
             current_spaces: None,
+            line_tabs: false,
+            line_spaces: false,
+            indent_style: None,
             parentheses: 0,
             eof_yielded: false,
         }
@@ -66,12 +78,51 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
         self.push_eol(Token::Punct(Punctuation::Semicolon));
     }
 
+    fn describe_whitespace(&self, count: usize) -> String {
+        let unit = match (self.line_tabs, self.line_spaces) {
+            (true, false) => "tab",
+            (false, true) => "space",
+            _ => "whitespace character",
+        };
+        format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+    }
+
+    /// Warn if the current line's indentation mixes tabs and spaces, or
+    /// differs from the style used earlier in the file.
+    fn check_whitespace(&mut self) {
+        if !self.context.lint_enabled(LINT_MIXED_INDENTATION) {
+            return;
+        }
+        let style = match (self.line_tabs, self.line_spaces) {
+            (true, false) => Punctuation::Tab,
+            (false, true) => Punctuation::Space,
+            _ => {
+                self.context.register_error(self.error("indentation mixes tabs and spaces")
+                    .set_severity(Severity::Warning));
+                return;
+            }
+        };
+        let file = self.last_input_loc.file;
+        match self.indent_style {
+            Some((style_file, expected)) if style_file == file => if style != expected {
+                let name = |p| if p == Punctuation::Tab { "tabs" } else { "spaces" };
+                self.context.register_error(self.error(format!(
+                    "indented with {}, but earlier lines are indented with {}",
+                    name(style), name(expected),
+                )).set_severity(Severity::Warning));
+            },
+            _ => self.indent_style = Some((file, style)),
+        }
+    }
+
     fn real_next(&mut self, read: Token) {
         // handle whitespace
         match read {
             Token::Punct(Punctuation::Newline) => {
                 if self.parentheses == 0 {
                     self.current_spaces = Some(0);
+                    self.line_tabs = false;
+                    self.line_spaces = false;
                 }
                 // semicolons are placed by the first token on the next line
                 if self.eol_location.is_none() {
@@ -79,10 +130,15 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
                 }
                 return;
             }
-            Token::Punct(Punctuation::Tab) |
-            Token::Punct(Punctuation::Space) => {
+            Token::Punct(p @ Punctuation::Tab) |
+            Token::Punct(p @ Punctuation::Space) => {
                 if let Some(spaces) = self.current_spaces.as_mut() {
                     *spaces += 1;
+                    if p == Punctuation::Tab {
+                        self.line_tabs = true;
+                    } else {
+                        self.line_spaces = true;
+                    }
                 }
                 return;
             }
@@ -100,6 +156,9 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
 
         // handle indentation
         if let Some(spaces) = self.current_spaces.take() {
+            if spaces > 0 {
+                self.check_whitespace();
+            }
             let (indents, new_indents);
             match self.current {
                 None => {
@@ -122,9 +181,15 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
                             // Register the error, but cross our fingers and
                             // hope that truncating division will approximate
                             // a sane situation.
+                            let mut expected: Vec<_> = (0..indents + 2)
+                                .map(|i| (i * spaces_per_indent).to_string())
+                                .collect();
+                            let last = expected.pop().unwrap();
                             self.context.register_error(self.error(format!(
-                                "inconsistent indentation: {} % {} != 0",
-                                spaces, spaces_per_indent
+                                "inconsistent indentation: found {}, expected {}, or {}",
+                                self.describe_whitespace(spaces),
+                                expected.join(", "),
+                                last,
                             )));
                         }
                         new_indents = spaces / spaces_per_indent;
@@ -138,9 +203,11 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
                 self.push_eol(Token::Punct(Punctuation::LBrace));
             } else if indents < new_indents {
                 // multiple indent is an error, register it but let it work
+                let (spaces_per_indent, _) = self.current.unwrap_or((1, 0));
                 self.context.register_error(self.error(format!(
-                    "inconsistent multiple indentation: {} > 1",
-                    new_indents - indents,
+                    "inconsistent multiple indentation: found {}, expected at most {}",
+                    self.describe_whitespace(new_indents * spaces_per_indent),
+                    (indents + 1) * spaces_per_indent,
                 )));
                 for _ in indents..new_indents {
                     self.push_eol(Token::Punct(Punctuation::LBrace));
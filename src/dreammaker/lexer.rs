@@ -3,6 +3,7 @@ use std::io;
 use std::str::FromStr;
 use std::fmt;
 use std::borrow::Cow;
+use std::ops::Range;
 
 use super::{DMError, Location, HasLocation, FileId, Context, Severity};
 use super::docs::*;
@@ -74,6 +75,8 @@ table! {
     "#",   Hash;
     "##",  TokenPaste;
     "%",   Mod;
+    "%%",  FloatMod;
+    "%%=", FloatModAssign;
     "%=",  ModAssign;
     "&",   BitAnd;
     "&&",  And;
@@ -99,6 +102,7 @@ table! {
     "//",  LineComment;
     "/=",  DivAssign;
     ":",   Colon -> CloseColon;
+    ":=",  AssignInto;
     ";",   Semicolon;
     "<",   Less;
     "<<",  LShift;
@@ -114,6 +118,7 @@ table! {
     "?",   QuestionMark;
     "?.",  SafeDot;
     "?:",  SafeColon;
+    "?[",  SafeLBracket;
     "[",   LBracket;
     "]",   RBracket;
     "^",   BitXor;
@@ -145,18 +150,18 @@ static SPEEDY_TABLE: [(usize, usize); 127] = [
     (0, 0), (0, 1), (1, 2), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (2, 3), (3, 5), (5, 6), (6, 8), (0, 0), (8, 10), (10, 13), (13, 14),
-    (14, 15), (15, 16), (16, 19), (19, 22), (22, 23), (23, 26), (26, 29), (29, 33),
+    (2, 3), (3, 5), (5, 6), (6, 8), (0, 0), (8, 12), (12, 15), (15, 16),
+    (16, 17), (17, 18), (18, 21), (21, 24), (24, 25), (25, 28), (28, 31), (31, 35),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (0, 0), (0, 0), (33, 34), (34, 35), (35, 40), (40, 42), (42, 46), (46, 49),
+    (0, 0), (0, 0), (35, 37), (37, 38), (38, 43), (43, 45), (45, 49), (49, 53),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (0, 0), (0, 0), (0, 0), (49, 50), (0, 0), (50, 51), (51, 53), (0, 0),
+    (0, 0), (0, 0), (0, 0), (53, 54), (0, 0), (54, 55), (55, 57), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (0, 0), (0, 0), (0, 0), (53, 55), (55, 58), (58, 59), (59, 62)];
+    (0, 0), (0, 0), (0, 0), (57, 59), (59, 62), (62, 63), (63, 66)];
 
 #[test]
 fn make_speedy_table() {
@@ -267,6 +272,8 @@ impl Token {
                 Eq |
                 NotEq |
                 Mod |
+                FloatMod |
+                FloatModAssign |
                 And |
                 BitAndAssign |
                 Mul |
@@ -278,6 +285,7 @@ impl Token {
                 SubAssign |
                 DivAssign |
                 Colon |
+                AssignInto |
                 Less |
                 LShift |
                 LShiftAssign |
@@ -312,6 +320,16 @@ impl Token {
         }
     }
 
+    /// Get the length of this token as written, if it is known from the
+    /// token alone.
+    pub fn source_len(&self) -> Option<usize> {
+        match *self {
+            Token::Punct(p) if !self.is_whitespace() => Some(p.value().len()),
+            Token::Ident(ref i, _) => Some(i.len()),
+            _ => None,
+        }
+    }
+
     /// Check whether this token is whitespace.
     pub fn is_whitespace(&self) -> bool {
         match *self {
@@ -368,8 +386,11 @@ impl fmt::Display for FormatFloat {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let n = self.0;
         if n.is_nan() {
-            // Not sure what is actually correct here.
-            f.write_str("1.#NaN")
+            if n.is_sign_negative() {
+                f.write_str("-1.#IND")
+            } else {
+                f.write_str("1.#IND")
+            }
         } else if n.is_infinite() {
             if n.is_sign_negative() {
                 f.write_str("-1.#INF")
@@ -417,6 +438,11 @@ fn is_ident(ch: u8) -> bool {
     (ch >= b'a' && ch <= b'z') || (ch >= b'A' && ch <= b'Z') || ch == b'_'
 }
 
+/// An error spanning the number literal `text`, which starts at `start`.
+fn number_error(start: Location, text: &str, message: String) -> DMError {
+    DMError::new(start, message).set_end(start.add_columns(text.len()))
+}
+
 /// Convert the input bytes to a `String` assuming Latin-1 encoding.
 pub fn from_latin1(mut bytes: Vec<u8>) -> String {
     let non_ascii = bytes.iter().filter(|&&i| i > 0x7f).count();
@@ -451,6 +477,77 @@ pub fn from_latin1_borrowed(bytes: &[u8]) -> Cow<str> {
     Cow::Owned(output)
 }
 
+/// The character encoding used to decode source text.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Every byte is one character. Matches the behavior of BYOND.
+    Latin1,
+    /// UTF-8, with invalid sequences replaced.
+    Utf8,
+    /// UTF-8 where valid, and Latin-1 otherwise. A byte order mark at the
+    /// start of a file selects UTF-8 for that file.
+    Autodetect,
+}
+
+impl Default for Encoding {
+    fn default() -> Encoding {
+        Encoding::Latin1
+    }
+}
+
+impl Encoding {
+    /// Convert the input bytes to a `String` using this encoding.
+    pub fn decode(self, bytes: Vec<u8>) -> String {
+        match self {
+            Encoding::Latin1 => from_latin1(bytes),
+            Encoding::Utf8 => match String::from_utf8(bytes) {
+                Ok(v) => v,
+                Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+            },
+            Encoding::Autodetect => match String::from_utf8(bytes) {
+                Ok(v) => v,
+                Err(e) => from_latin1(e.into_bytes()),
+            },
+        }
+    }
+
+    /// Convert the input bytes to a string using this encoding, borrowing
+    /// if possible.
+    pub fn decode_borrowed(self, bytes: &[u8]) -> Cow<str> {
+        match self {
+            Encoding::Latin1 => from_latin1_borrowed(bytes),
+            Encoding::Utf8 => String::from_utf8_lossy(bytes),
+            Encoding::Autodetect => match ::std::str::from_utf8(bytes) {
+                Ok(v) => Cow::Borrowed(v),
+                Err(_) => from_latin1_borrowed(bytes),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Encoding::Latin1 => "latin1",
+            Encoding::Utf8 => "utf8",
+            Encoding::Autodetect => "auto",
+        })
+    }
+}
+
+impl ::std::str::FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Encoding, String> {
+        match s {
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "utf8" | "utf-8" => Ok(Encoding::Utf8),
+            "auto" | "autodetect" => Ok(Encoding::Autodetect),
+            _ => Err(format!("unknown encoding {:?}", s)),
+        }
+    }
+}
+
 // Used to track nested string interpolations and know when they end.
 #[derive(Debug)]
 struct Interpolation {
@@ -472,12 +569,16 @@ enum Directive {
 /// All characters, including tabs, are considered to occupy one column
 /// regardless of position.
 ///
-/// `io::Error`s are converted to `DMError`s which include the location.
+/// `io::Error`s are converted to `DMError`s which include the location. Line
+/// and column numbers stop at their limits instead of overflowing.
 pub struct LocationTracker<I> {
     inner: I,
     /// The location of the last character returned by `next()`.
     location: Location,
     at_line_end: bool,
+    line_saturated: bool,
+    column_saturated: bool,
+    overflow: Option<DMError>,
 }
 
 impl<I> LocationTracker<I> {
@@ -490,8 +591,19 @@ impl<I> LocationTracker<I> {
                 column: 0,
             },
             at_line_end: true,
+            line_saturated: false,
+            column_saturated: false,
+            overflow: None,
         }
     }
+
+    /// Take the error describing the first time a line or column number
+    /// exceeded its limit, if that has happened since the last call.
+    ///
+    /// Past the limit, locations stop advancing rather than wrapping around.
+    pub fn take_overflow(&mut self) -> Option<DMError> {
+        self.overflow.take()
+    }
 }
 
 impl<I> fmt::Debug for LocationTracker<I> {
@@ -500,6 +612,7 @@ impl<I> fmt::Debug for LocationTracker<I> {
             // inner omitted
             .field("location", &self.location)
             .field("at_line_end", &self.at_line_end)
+            .field("overflow", &self.overflow)
             .finish()
     }
 }
@@ -518,7 +631,12 @@ impl<I: Iterator<Item=io::Result<u8>>> Iterator for LocationTracker<I> {
             self.at_line_end = false;
             match self.location.line.checked_add(1) {
                 Some(new) => self.location.line = new,
-                None => panic!("per-file line limit of {} exceeded", self.location.line),
+                None => if !self.line_saturated {
+                    self.line_saturated = true;
+                    self.overflow = Some(DMError::new(self.location, format!(
+                        "per-file line limit of {} exceeded, later locations are inaccurate",
+                        self.location.line)));
+                },
             }
             self.location.column = 0;
         }
@@ -531,7 +649,12 @@ impl<I: Iterator<Item=io::Result<u8>>> Iterator for LocationTracker<I> {
                 }
                 match self.location.column.checked_add(1) {
                     Some(new) => self.location.column = new,
-                    None => panic!("per-line column limit of {} exceeded", self.location.column),
+                    None => if !self.column_saturated {
+                        self.column_saturated = true;
+                        self.overflow = Some(DMError::new(self.location, format!(
+                            "per-line column limit of {} exceeded, later locations are inaccurate",
+                            self.location.column)));
+                    },
                 }
                 Some(Ok(ch))
             }
@@ -540,16 +663,55 @@ impl<I: Iterator<Item=io::Result<u8>>> Iterator for LocationTracker<I> {
     }
 }
 
+/// The states of the numeric literal state machine.
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum NumberState {
+    /// A leading `0`, which may begin a hex literal.
+    Zero,
+    /// The digits of a decimal or octal integer.
+    Integer,
+    /// The digits of a hexadecimal integer, after `0x`.
+    Hex,
+    /// The digits after a decimal point.
+    Fraction,
+    /// Just after the `e` of an exponent.
+    ExponentStart,
+    /// Just after the sign of an exponent.
+    ExponentSign,
+    /// The digits of an exponent.
+    Exponent,
+}
+
+impl NumberState {
+    fn expectation(self) -> &'static str {
+        match self {
+            NumberState::Zero | NumberState::Integer => "expected digit, '.', or exponent",
+            NumberState::Hex => "expected hexadecimal digit",
+            NumberState::Fraction => "expected digit, exponent, or '#'",
+            NumberState::ExponentStart => "expected sign or digit in exponent",
+            NumberState::ExponentSign | NumberState::Exponent => "expected digit in exponent",
+        }
+    }
+}
+
 /// The lexer, which serves as a source of tokens through iteration.
 pub struct Lexer<'ctx, I> {
     context: &'ctx Context,
     input: LocationTracker<I>,
-    next: Option<u8>,
+    /// Characters which were put back, the most recent last.
+    next: Vec<u8>,
     final_newline: bool,
     at_line_head: bool,
     close_allowed: bool,
     directive: Directive,
     interp_stack: Vec<Interpolation>,
+    capture: Option<Vec<u8>>,
+    token_start: usize,
+    encoding: Encoding,
+    at_file_start: bool,
+    bytes_read: u64,
+    tokens_read: u64,
+    stats_recorded: bool,
 }
 
 impl<'ctx, I> fmt::Debug for Lexer<'ctx, I> {
@@ -562,6 +724,7 @@ impl<'ctx, I> fmt::Debug for Lexer<'ctx, I> {
             .field("at_line_head", &self.at_line_head)
             .field("directive", &self.directive)
             .field("interp_stack", &self.interp_stack)
+            .field("encoding", &self.encoding)
             .finish()
     }
 }
@@ -583,25 +746,52 @@ impl<'ctx, R: io::Read> Lexer<'ctx, io::Bytes<R>> {
 impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
     /// Create a new lexer from a byte stream.
     pub fn new(context: &'ctx Context, file_number: FileId, input: I) -> Lexer<I> {
+        context.lexing_started();
         Lexer {
             context,
             input: LocationTracker::new(file_number, input),
-            next: None,
+            next: Vec::new(),
             final_newline: false,
             at_line_head: true,
             close_allowed: true,
             directive: Directive::None,
             interp_stack: Vec::new(),
+            capture: None,
+            token_start: 0,
+            encoding: context.encoding(),
+            at_file_start: true,
+            bytes_read: 0,
+            tokens_read: 0,
+            stats_recorded: false,
         }
     }
 
+    /// The encoding this lexer is decoding source text with.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Convert this lexer into one which preserves all source text.
+    ///
+    /// Must be called before any tokens have been read.
+    pub fn lossless(mut self) -> LosslessLexer<'ctx, I> {
+        self.capture = Some(Vec::new());
+        LosslessLexer { inner: self }
+    }
+
     fn next(&mut self) -> Option<u8> {
-        if let Some(next) = self.next.take() {
+        if let Some(next) = self.next.pop() {
+            if let Some(ref mut capture) = self.capture {
+                capture.push(next);
+            }
             return Some(next);
         }
 
         let previous_loc = self.location();
         let result = self.input.next();
+        if let Some(err) = self.input.take_overflow() {
+            self.context.register_error(err);
+        }
         if self.location().line > previous_loc.line {
             self.at_line_head = true;
             self.directive = Directive::None;
@@ -609,9 +799,13 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
         match result {
             None => None,
             Some(Ok(ch)) => {
+                self.bytes_read += 1;
                 if ch != b'\t' && ch != b' ' {
                     self.at_line_head = false;
                 }
+                if let Some(ref mut capture) = self.capture {
+                    capture.push(ch);
+                }
                 Some(ch)
             }
             Some(Err(err)) => {
@@ -623,15 +817,18 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
     }
 
     fn put_back(&mut self, val: Option<u8>) {
-        if self.next.is_some() {
-            panic!("cannot put_back twice");
+        if let Some(val) = val {
+            if let Some(ref mut capture) = self.capture {
+                capture.pop();
+            }
+            self.next.push(val);
         }
-        self.next = val;
     }
 
     fn skip_block_comments(&mut self) -> Option<Token> {
         let mut depth = 1;
         let mut buffer = [0, 0];
+        let mut text = Vec::new();
 
         // read the first character and check for being a comment
         let mut comment = None;
@@ -662,18 +859,21 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
                 }
             }
 
-            if buffer[0] != 0 {
-                if let Some(ref mut comment) = comment {
-                    comment.text.push(buffer[0] as char);
-                }
+            if buffer[0] != 0 && comment.is_some() {
+                text.push(buffer[0]);
             }
         }
 
-        comment.map(Token::DocComment)
+        let encoding = self.encoding;
+        comment.map(|mut comment| {
+            comment.text = encoding.decode(text);
+            Token::DocComment(comment)
+        })
     }
 
     fn skip_line_comment(&mut self) -> Option<Token> {
         let mut backslash = false;
+        let mut text = Vec::new();
 
         // read the first character and check for being a comment
         let mut comment = None;
@@ -689,10 +889,8 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
         }
 
         while let Some(ch) = self.next() {
-            if ch != b'\r' && ch != b'\n' {
-                if let Some(ref mut comment) = comment {
-                    comment.text.push(ch as char);
-                }
+            if ch != b'\r' && ch != b'\n' && comment.is_some() {
+                text.push(ch);
             }
 
             if ch == b'\r' {
@@ -707,98 +905,179 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
             }
         }
 
-        comment.map(Token::DocComment)
+        let encoding = self.encoding;
+        comment.map(|mut comment| {
+            comment.text = encoding.decode(text);
+            Token::DocComment(comment)
+        })
     }
 
-    fn read_number_inner(&mut self, first: u8) -> (bool, u32, Cow<'static, str>) {
-        let mut integer = true;
-        let mut exponent = false;
-        let mut radix = 10;
+    fn read_number(&mut self, first: u8) -> Token {
+        use self::NumberState::*;
+
+        let start = self.location();
         let mut buf = String::new();
         buf.push(first as char);
-
-        if first == b'.' {
-            integer = false;
-        } else if first == b'0' {
-            radix = 8;  // hate. let me tell you...
-            match self.next() {
-                Some(b'x') => radix = 16,
-                ch => self.put_back(ch),
-            }
-        }
+        let mut state = match first {
+            b'0' => Zero,
+            b'.' => Fraction,
+            _ => Integer,
+        };
         loop {
-            match self.next() {
-                Some(b'_') => {},
-                Some(ch) if ch == b'.' || ch == b'e' || ch == b'E' => {
-                    integer = false;
-                    exponent |= ch == b'e' || ch == b'E';
-                    buf.push(ch as char);
-                }
-                Some(ch) if (ch == b'+' || ch == b'-') && exponent => {
-                    buf.push(ch as char);
-                }
-                Some(b'#') if !integer => {
-                    buf.push('#');  // Keep pushing to `buf` in case of error.
-                    for &expect in b"INF" {
+            let ch = self.next();
+            let next = match (state, ch) {
+                (_, Some(b'_')) => continue,
+                (Zero, Some(b'x')) | (Zero, Some(b'X')) => Hex,
+                (Zero, Some(b'0'...b'9')) | (Integer, Some(b'0'...b'9')) => Integer,
+                (Zero, Some(b'.')) | (Integer, Some(b'.')) => Fraction,
+                (Zero, Some(b'e')) | (Zero, Some(b'E')) |
+                (Integer, Some(b'e')) | (Integer, Some(b'E')) |
+                (Fraction, Some(b'e')) | (Fraction, Some(b'E')) => ExponentStart,
+                (Hex, Some(b'0'...b'9')) | (Hex, Some(b'a'...b'f')) | (Hex, Some(b'A'...b'F')) => Hex,
+                (Fraction, Some(b'0'...b'9')) => Fraction,
+                (Fraction, Some(b'#')) => return self.read_special_float(start, buf),
+                (ExponentStart, Some(b'+')) | (ExponentStart, Some(b'-')) => ExponentSign,
+                (ExponentStart, Some(b'0'...b'9')) |
+                (ExponentSign, Some(b'0'...b'9')) |
+                (Exponent, Some(b'0'...b'9')) => Exponent,
+                (_, Some(ch)) if is_ident(ch) || is_digit(ch) || ch == b'.' => {
+                    // Consume the rest of the malformed literal so that it
+                    // doesn't produce a cascade of further errors.
+                    let mut rest = String::new();
+                    rest.push(ch as char);
+                    loop {
                         match self.next() {
-                            Some(ch) if ch == expect => continue,
-                            Some(ch) => buf.push(ch as char),
-                            None => {}
+                            Some(ch) if is_ident(ch) || is_digit(ch) || ch == b'.' => rest.push(ch as char),
+                            ch => {
+                                self.put_back(ch);
+                                break;
+                            }
                         }
-                        // Not what we expected, throw it up the line so that
-                        // f32::from_str will error.
-                        return (false, 10, buf.into());
                     }
-                    // Got "1.#INF", change it to "inf" for read_number.
-                    return (false, 10, "inf".into());
+                    self.context.register_error(DMError::new(start, format!(
+                        "malformed number \"{}{}\": unexpected '{}' {}",
+                        buf, rest, ch as char, state.expectation()))
+                        .set_end(start.add_columns(buf.len() + rest.len())));
+                    return self.finish_number(start, state, buf, false);
                 }
-                Some(ch) if (ch as char).is_digit(::std::cmp::max(radix, 10)) => {
-                    exponent = false;
-                    buf.push(ch as char);
-                }
-                ch => {
+                (_, ch) => {
                     self.put_back(ch);
-                    return (integer, radix, buf.into());
+                    return self.finish_number(start, state, buf, true);
                 }
-            }
+            };
+            state = next;
+            buf.push(ch.unwrap() as char);
         }
     }
 
-    fn read_number(&mut self, first: u8) -> Token {
-        let (integer, radix, buf) = self.read_number_inner(first);
-        if integer {
-            let original_error = match i32::from_str_radix(&buf, radix) {
-                Ok(val) => return Token::Int(val),
-                Err(e) => e,
-            };
-            // Try to parse it as a float instead - this will catch numbers
-            // that are formatted like integers but are out of the range of our
-            // integer type.
-            if radix == 10 {
-                if let Ok(val) = f32::from_str(&buf) {
-                    let val_str = val.to_string();
-                    if val_str != buf {
-                        self.context.register_error(self.error(
-                            format!("precision loss of integer constant: \"{}\" to {}", buf, val)
-                        ).set_severity(Severity::Warning));
+    fn finish_number(&self, start: Location, state: NumberState, mut buf: String, report: bool) -> Token {
+        use self::NumberState::*;
+
+        let (radix, prefix) = match state {
+            Zero | Integer if buf.len() > 1 && buf.starts_with('0') => (8, 0),  // hate. let me tell you...
+            Zero | Integer => (10, 0),
+            Hex => (16, 2),
+            Fraction | Exponent => {
+                return match f32::from_str(&buf) {
+                    Ok(val) => Token::Float(val),
+                    Err(e) => {
+                        if report {
+                            self.context.register_error(number_error(start, &buf,
+                                format!("bad float \"{}\": {}", buf, e)));
+                        }
+                        Token::Float(0.0)  // fallback
                     }
-                    return Token::Float(val)
+                };
+            }
+            ExponentStart | ExponentSign => {
+                if report {
+                    self.context.register_error(number_error(start, &buf,
+                        format!("malformed number \"{}\": {}", buf, state.expectation())));
                 }
+                // use the mantissa alone
+                let end = buf.rfind(|c| c == 'e' || c == 'E').unwrap_or(buf.len());
+                buf.truncate(end);
+                return Token::Float(f32::from_str(&buf).unwrap_or(0.0));
+            }
+        };
+
+        let digits = &buf[prefix..];
+        if digits.is_empty() {
+            if report {
+                self.context.register_error(number_error(start, &buf,
+                    format!("malformed number \"{}\": {}", buf, state.expectation())));
             }
-            self.context.register_error(self.error(
+            return Token::Int(0);
+        }
+        let original_error = match i32::from_str_radix(digits, radix) {
+            Ok(val) => return Token::Int(val),
+            Err(e) => e,
+        };
+        // Try to parse it as a float instead - this will catch numbers
+        // that are formatted like integers but are out of the range of our
+        // integer type.
+        if radix == 10 {
+            if let Ok(val) = f32::from_str(&buf) {
+                let val_str = val.to_string();
+                if val_str != buf {
+                    self.context.register_error(number_error(start, &buf,
+                        format!("precision loss of integer constant: \"{}\" to {}", buf, val)
+                    ).set_severity(Severity::Warning));
+                }
+                return Token::Float(val)
+            }
+        }
+        if report {
+            self.context.register_error(number_error(start, &buf,
                 format!("bad base-{} integer \"{}\": {}", radix, buf, original_error)));
-            Token::Int(0)  // fallback
-        } else {
-            // ignore radix
-            match f32::from_str(&buf) {
-                Ok(val) => Token::Float(val),
-                Err(e) => {
-                    self.context.register_error(self.error(
-                        format!("bad float \"{}\": {}", buf, e)));
-                    Token::Float(0.0)  // fallback
+        }
+        Token::Int(0)  // fallback
+    }
+
+    /// Read the remainder of `1.#INF` or `1.#IND` after the `#`.
+    fn read_special_float(&mut self, start: Location, mut buf: String) -> Token {
+        buf.push('#');
+        let mut word = String::new();
+        loop {
+            match self.next() {
+                Some(ch) if is_ident(ch) || is_digit(ch) => word.push(ch as char),
+                ch => {
+                    self.put_back(ch);
+                    break;
                 }
             }
         }
+        match word.as_str() {
+            "INF" => Token::Float(::std::f32::INFINITY),
+            "IND" | "QNAN" => Token::Float(::std::f32::NAN),
+            _ => {
+                buf.push_str(&word);
+                self.context.register_error(number_error(start, &buf, format!(
+                    "malformed number \"{}\": expected INF or IND after '#'", buf)));
+                Token::Float(0.0)  // fallback
+            }
+        }
+    }
+
+    /// Skip the remainder of a UTF-8 byte order mark after its first byte.
+    ///
+    /// Anything else is put back to be lexed as usual.
+    fn skip_bom(&mut self) -> bool {
+        let second = self.next();
+        if second != Some(0xbb) {
+            self.put_back(second);
+            return false;
+        }
+        let third = self.next();
+        if third != Some(0xbf) {
+            self.put_back(third);
+            self.put_back(second);
+            return false;
+        }
+        if self.encoding == Encoding::Autodetect {
+            self.encoding = Encoding::Utf8;
+        }
+        true
     }
 
     fn read_ident(&mut self, first: u8) -> String {
@@ -814,7 +1093,7 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
                 }
             }
         }
-        from_latin1(ident)
+        self.encoding.decode(ident)
     }
 
     fn read_resource(&mut self) -> String {
@@ -825,12 +1104,13 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
                 Some(b'\'') => break,
                 Some(ch) => buf.push(ch),
                 None => {
-                    self.context.register_error(DMError::new(start_loc, "unterminated resource literal"));
+                    self.context.register_error(DMError::new(start_loc, "unterminated resource literal")
+                        .set_end(self.location().add_columns(1)));
                     break;
                 }
             }
         }
-        from_latin1(buf)
+        self.encoding.decode(buf)
     }
 
     fn read_string(&mut self, end: &'static [u8], interp_closed: bool) -> Token {
@@ -844,7 +1124,8 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
             let ch = match self.next() {
                 Some(ch) => ch,
                 None => {
-                    self.context.register_error(DMError::new(start_loc, "unterminated string literal"));
+                    self.context.register_error(DMError::new(start_loc, "unterminated string literal")
+                        .set_end(self.location().add_columns(1)));
                     break;
                 }
             };
@@ -892,7 +1173,7 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
             }
         }
 
-        let string = from_latin1(buf);
+        let string = self.encoding.decode(buf);
         match (interp_opened, interp_closed) {
             (true, true) => Token::InterpStringPart(string),
             (true, false) => Token::InterpStringBegin(string),
@@ -960,25 +1241,29 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
     }
 }
 
-impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
-    type Item = LocatedToken;
-
-    fn next(&mut self) -> Option<LocatedToken> {
+impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
+    fn next_token(&mut self) -> Option<LocatedToken> {
         use self::Token::*;
         use self::Punctuation::*;
         let mut skip_newlines = false;
         let mut found_illegal = false;
         loop {
             let first = match self.skip_ws(skip_newlines) {
-                Some(t) => t,
+                Some(t) => {
+                    if let Some(ref capture) = self.capture {
+                        self.token_start = capture.len() - 1;
+                    }
+                    t
+                }
                 None => {
+                    if let Some(ref capture) = self.capture {
+                        self.token_start = capture.len();
+                    }
                     // always end with a newline
                     if !self.final_newline {
                         self.final_newline = true;
-                        let mut location = self.location();
-                        location.column += 1;
                         return Some(LocatedToken {
-                            location: location,
+                            location: self.location().add_columns(1),
                             token: Token::Punct(Punctuation::Newline),
                         });
                     } else {
@@ -988,6 +1273,13 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
             };
             skip_newlines = false;
 
+            if self.at_file_start {
+                self.at_file_start = false;
+                if first == 0xef && self.skip_bom() {
+                    continue;
+                }
+            }
+
             let loc = self.location();
             let locate = |token| LocatedToken::new(loc, token);
 
@@ -1021,11 +1313,12 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
                 Some(SingleQuote) => Some(locate(Resource(self.read_resource()))),
                 Some(DoubleQuote) => Some(locate(self.read_string(b"\"", false))),
                 Some(BlockString) => Some(locate(self.read_string(b"\"}", false))),
-                Some(LBracket) => {
+                Some(p @ LBracket) |
+                Some(p @ SafeLBracket) => {
                     if let Some(interp) = self.interp_stack.last_mut() {
                         interp.bracket_depth += 1;
                     }
-                    Some(locate(Punct(LBracket)))
+                    Some(locate(Punct(p)))
                 }
                 Some(RBracket) => {
                     if let Some(mut interp) = self.interp_stack.pop() {
@@ -1084,3 +1377,114 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
         }
     }
 }
+
+impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
+    type Item = LocatedToken;
+
+    fn next(&mut self) -> Option<LocatedToken> {
+        let result = self.next_token();
+        match result {
+            Some(_) => self.tokens_read += 1,
+            None => if !self.stats_recorded {
+                self.stats_recorded = true;
+                self.context.record_lexed(self.bytes_read, self.tokens_read);
+            },
+        }
+        result
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Lossless lexing
+
+/// A token along with the exact source text it was lexed from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawToken {
+    pub location: Location,
+    pub token: Token,
+    /// The whitespace, comments, and line continuations preceding this token.
+    pub leading: Vec<u8>,
+    /// The source text of the token itself.
+    pub text: Vec<u8>,
+}
+
+impl RawToken {
+    /// Write the leading trivia and text of this token.
+    pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.leading)?;
+        w.write_all(&self.text)
+    }
+}
+
+/// A lexer which yields `RawToken`s, from which the original source can be
+/// re-emitted byte-for-byte.
+///
+/// Created by `Lexer::lossless`.
+pub struct LosslessLexer<'ctx, I> {
+    inner: Lexer<'ctx, I>,
+}
+
+impl<'ctx, I> fmt::Debug for LosslessLexer<'ctx, I> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("LosslessLexer")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<'ctx, I: Iterator<Item=io::Result<u8>>> HasLocation for LosslessLexer<'ctx, I> {
+    #[inline]
+    fn location(&self) -> Location {
+        self.inner.location()
+    }
+}
+
+impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for LosslessLexer<'ctx, I> {
+    type Item = RawToken;
+
+    fn next(&mut self) -> Option<RawToken> {
+        let token = match Iterator::next(&mut self.inner) {
+            Some(token) => token,
+            None => return None,
+        };
+        let mut leading = match self.inner.capture {
+            Some(ref mut capture) => ::std::mem::replace(capture, Vec::new()),
+            None => Vec::new(),
+        };
+        let text = leading.split_off(self.inner.token_start);
+        Some(RawToken {
+            location: token.location,
+            token: token.token,
+            leading,
+            text,
+        })
+    }
+}
+
+/// Re-emit the source text of a sequence of raw tokens, replacing the given
+/// ranges of tokens with new text.
+///
+/// Edits must be sorted and must not overlap. The leading trivia of the first
+/// token in each replaced range is kept; an empty range inserts its text
+/// before the trivia of the token at that index.
+pub fn reemit<W: io::Write>(w: &mut W, tokens: &[RawToken], edits: &[(Range<usize>, String)]) -> io::Result<()> {
+    let mut pos = 0;
+    for &(ref range, ref text) in edits {
+        if range.start < pos || range.end < range.start || range.end > tokens.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "bad token edit range {}..{}", range.start, range.end)));
+        }
+        for token in tokens[pos..range.start].iter() {
+            token.write_to(w)?;
+        }
+        if range.start < range.end {
+            w.write_all(&tokens[range.start].leading)?;
+        }
+        w.write_all(text.as_bytes())?;
+        pos = range.end;
+    }
+    for token in tokens[pos..].iter() {
+        token.write_to(w)?;
+    }
+    Ok(())
+}
@@ -1,5 +1,13 @@
 //! Parsing suite for DreamMaker, the language of the BYOND game engine.
-#![forbid(unsafe_code)]
+//!
+//! No input, however malformed, should cause the lexer, preprocessor, or
+//! parser to panic; problems are reported as `DMError`s on the `Context`
+//! instead. The `fuzz` directory holds `cargo fuzz` targets which check this.
+//!
+//! Unsafe code is forbidden outside of the C ABI in `capi` and the
+//! JavaScript bindings in `wasm`.
+#![cfg_attr(not(any(feature = "capi", feature = "wasm")), forbid(unsafe_code))]
+#![cfg_attr(any(feature = "capi", feature = "wasm"), deny(unsafe_code))]
 
 extern crate petgraph;
 extern crate linked_hash_map;
@@ -7,9 +15,15 @@ extern crate interval_tree;
 extern crate lodepng;
 #[macro_use] extern crate bitflags;
 extern crate noisy_float;
+#[cfg(not(target_arch = "wasm32"))] extern crate rayon;
+#[cfg(feature = "serde")] extern crate serde;
+#[cfg(feature = "config")] extern crate toml;
+#[cfg(feature = "notify")] extern crate notify;
+#[cfg(any(feature = "debugger", feature = "capi", feature = "wasm", feature = "profile"))] #[macro_use] extern crate serde_json;
+#[cfg(feature = "wasm")] extern crate wasm_bindgen;
 
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::borrow::Cow;
 
 #[allow(unused_macros)]
@@ -26,6 +40,7 @@ mod error;
 pub use error::*;
 
 // roughly in order of stage
+pub mod files;
 pub mod docs;
 pub mod lexer;
 pub mod preprocessor;
@@ -33,10 +48,42 @@ pub mod indents;
 pub mod parser;
 pub mod annotation;
 pub mod ast;
+pub mod visit;
+pub mod rewrite;
+pub mod formatting;
+pub mod strings;
 pub mod objtree;
+pub mod analysis;
+pub mod profile;
+pub mod runtime;
+pub mod metrics;
+pub mod memory;
+pub mod dead_code;
+pub mod duplicates;
+pub mod astdiff;
+pub mod search;
+pub mod references;
 mod builtins;
+pub use builtins::{BuiltinSignature, ArgKind, CustomBuiltins, CustomProc, ExternalFunction};
+pub mod opcodes;
 pub mod constants;
 pub mod dmi;
+pub mod dmf;
+pub mod resources;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "notify")]
+pub mod watch;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+#[cfg(feature = "capi")]
+#[allow(unsafe_code)]
+pub mod capi;
+#[cfg(feature = "wasm")]
+#[allow(unsafe_code)]
+pub mod wasm;
+#[cfg(feature = "serde")]
+mod serde_helpers;
 
 impl Context {
     /// Run the parsing suite on a given `.dme` file, producing an object tree.
@@ -164,29 +211,66 @@ pub fn fix_case(path: &Path) -> Cow<Path> {
 
 pub const DEFAULT_ENV: &str = "tgstation.dme";
 
-/// Autodetect any `.dme` file in the current folder, or fall back to default.
+/// Find the `.dme` file governing `start_dir`, searching that directory and
+/// then each of its parents in turn.
 ///
-/// If multiple environments exist, the first non-default is preferred.
-pub fn detect_environment(root: &Path, default: &str) -> std::io::Result<Option<std::path::PathBuf>> {
-    let mut result = None;
-    for entry in std::fs::read_dir(root)? {
-        if let Ok(entry) = entry {
+/// If a directory contains multiple environments, the first non-default is
+/// preferred.
+pub fn detect_environment(start_dir: &Path) -> Option<PathBuf> {
+    let mut current = Some(start_dir);
+    while let Some(dir) = current {
+        if let Some(found) = environment_in(dir, DEFAULT_ENV) {
+            return Some(found);
+        }
+        current = dir.parent();
+    }
+    None
+}
+
+/// Autodetect the `.dme` file governing the current directory.
+pub fn detect_environment_default() -> Option<PathBuf> {
+    std::env::current_dir().ok().and_then(|dir| detect_environment(&dir))
+}
+
+/// List every `.dme` file within a workspace, in sorted order.
+///
+/// Hidden directories are skipped, and symbolic links are not followed.
+pub fn list_environments(root: &Path) -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    let mut stack = vec![root.to_owned()];
+    while let Some(dir) = stack.pop() {
+        let read_dir = match std::fs::read_dir(&dir) {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        for entry in read_dir.filter_map(Result::ok) {
             let name = entry.file_name();
-            let (dme, default) = {
-                let utf8_name = name.to_string_lossy();
-                (utf8_name.ends_with(".dme"), utf8_name == default)
-            };
-            if dme {
-                result = Some(entry.path());
-                if !default {
-                    break;
-                }
+            let name = name.to_string_lossy();
+            match entry.file_type() {
+                Ok(ref t) if t.is_dir() && !name.starts_with('.') => stack.push(entry.path()),
+                Ok(ref t) if t.is_file() && name.ends_with(".dme") => result.push(entry.path()),
+                _ => {}
             }
         }
     }
-    Ok(result)
+    result.sort();
+    result
 }
 
-pub fn detect_environment_default() -> std::io::Result<Option<std::path::PathBuf>> {
-    detect_environment("".as_ref(), DEFAULT_ENV)
+fn environment_in(dir: &Path, default: &str) -> Option<PathBuf> {
+    let mut result = None;
+    for entry in std::fs::read_dir(dir).ok()?.filter_map(Result::ok) {
+        let name = entry.file_name();
+        let (dme, default) = {
+            let utf8_name = name.to_string_lossy();
+            (utf8_name.ends_with(".dme"), utf8_name == default)
+        };
+        if dme {
+            result = Some(entry.path());
+            if !default {
+                break;
+            }
+        }
+    }
+    result
 }
@@ -0,0 +1,389 @@
+//! Estimates of the memory held by a parsed environment.
+//!
+//! Sizes are estimated by walking the data structures and adding up the
+//! inline size of each value and the heap allocations it owns. Allocator
+//! overhead, hash table slack, and the sizes of interval tree and graph
+//! internals are approximated, so the figures are a guide to where memory
+//! goes rather than an exact account.
+use std::fmt;
+use std::mem::size_of;
+
+use linked_hash_map::LinkedHashMap;
+use petgraph::graph::NodeIndex;
+
+use super::annotation::AnnotationTree;
+use super::ast::*;
+use super::constants::Constant;
+use super::docs::DocCollection;
+use super::lexer::LocatedToken;
+use super::objtree::*;
+use super::Location;
+use super::visit::{Visitor, walk_block, walk_statement, walk_expression, walk_term, walk_follow};
+
+/// Estimated bytes used by each part of a parsed environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryReport {
+    /// Type nodes, their names and paths, and the path lookup table.
+    pub types: usize,
+    /// Var declarations and values, including initializer expressions and
+    /// their evaluated constants.
+    pub vars: usize,
+    /// Proc declarations, overrides, parameters, and settings.
+    pub procs: usize,
+    /// The statements and expressions of proc bodies.
+    pub proc_bodies: usize,
+    /// Documentation comments on types, vars, and procs.
+    pub docs: usize,
+    /// Annotation trees, if any were added with `add_annotations`.
+    pub annotations: usize,
+}
+
+impl MemoryReport {
+    /// Estimate the memory used by an object tree.
+    pub fn of_tree(tree: &ObjectTree) -> MemoryReport {
+        let mut report = MemoryReport::default();
+
+        report.types += tree.types.len() * (size_of::<String>() + size_of::<NodeIndex>());
+        report.types += tree.types.keys().map(|key| key.capacity()).sum::<usize>();
+        // each edge holds its weight, two node indices, and two next links
+        report.types += tree.graph.edge_count() * 4 * size_of::<NodeIndex>();
+
+        for idx in tree.graph.node_indices() {
+            let ty = &tree.graph[idx];
+            report.types += size_of::<Type>() + ty.name.capacity() + ty.path.capacity();
+            report.docs += docs(&ty.docs);
+
+            report.vars += map_entries(&ty.vars);
+            for (name, var) in ty.vars.iter() {
+                report.vars += name.capacity();
+                if let Some(ref decl) = var.declaration {
+                    report.vars += var_type(&decl.var_type);
+                }
+                if let Some(ref expr) = var.value.expression {
+                    report.vars += expression(expr);
+                }
+                if let Some(ref constant) = var.value.constant {
+                    report.vars += self::constant(constant);
+                }
+                report.docs += docs(&var.value.docs);
+            }
+
+            report.procs += map_entries(&ty.procs);
+            for (name, proc) in ty.procs.iter() {
+                report.procs += name.capacity();
+                report.procs += proc.value.capacity() * size_of::<ProcValue>();
+                for value in proc.value.iter() {
+                    report.procs += parameters(&value.parameters);
+                    report.procs += settings(&value.settings);
+                    report.docs += docs(&value.docs);
+                    match value.code {
+                        Code::Present(ref block) => report.proc_bodies += statements(block),
+                        // the text of identifiers and literals is not counted
+                        Code::Lazy(ref lazy) => report.proc_bodies += lazy.tokens().len() * size_of::<LocatedToken>(),
+                        Code::Invalid | Code::Disabled => {}
+                    }
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Add the estimated size of an annotation tree to the report.
+    pub fn add_annotations(&mut self, annotations: &AnnotationTree) {
+        self.annotations += annotations.memory_estimate();
+    }
+
+    /// The estimated total across all parts.
+    pub fn total(&self) -> usize {
+        self.types + self.vars + self.procs + self.proc_bodies + self.docs + self.annotations
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts = [
+            ("types", self.types),
+            ("vars", self.vars),
+            ("procs", self.procs),
+            ("proc bodies", self.proc_bodies),
+            ("docs", self.docs),
+            ("annotations", self.annotations),
+        ];
+        for &(name, bytes) in parts.iter() {
+            writeln!(f, "{:>12}: {}", name, Bytes(bytes))?;
+        }
+        write!(f, "{:>12}: {}", "total", Bytes(self.total()))
+    }
+}
+
+/// A byte count, displayed in the largest fitting binary unit.
+struct Bytes(usize);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut value = self.0 as f64 / 1024.;
+        let mut unit = 0;
+        while value >= 1024. && unit + 1 < UNITS.len() {
+            value /= 1024.;
+            unit += 1;
+        }
+        write!(f, "{:.1} {}", value, UNITS[unit])
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Helpers
+
+/// Estimate the heap memory owned by a block of statements.
+pub(crate) fn statements(block: &[Spanned<Statement>]) -> usize {
+    let mut size = AstSize(0);
+    size.visit_block(block);
+    size.0
+}
+
+/// Estimate the heap memory owned by an expression.
+pub(crate) fn expression(expr: &Expression) -> usize {
+    let mut size = AstSize(0);
+    size.visit_expression(expr);
+    size.0
+}
+
+/// The per-entry cost of a linked hash map: the key and value, plus the
+/// list links and the table slot pointing to the entry.
+fn map_entries<K, V>(map: &LinkedHashMap<K, V>) -> usize
+    where K: ::std::hash::Hash + Eq
+{
+    map.len() * (size_of::<K>() + size_of::<V>() + 3 * size_of::<usize>())
+}
+
+fn strings(strings: &Vec<String>) -> usize {
+    strings.capacity() * size_of::<String>() + strings.iter().map(|each| each.capacity()).sum::<usize>()
+}
+
+fn option_string(string: &Option<String>) -> usize {
+    string.as_ref().map_or(0, |each| each.capacity())
+}
+
+fn var_type(var_type: &VarType) -> usize {
+    strings(&var_type.type_path)
+}
+
+fn type_path(path: &TypePath) -> usize {
+    path.capacity() * size_of::<(PathOp, String)>() + path.iter().map(|&(_, ref each)| each.capacity()).sum::<usize>()
+}
+
+fn docs(docs: &DocCollection) -> usize {
+    docs.heap_size()
+}
+
+fn parameters(parameters: &Vec<Parameter>) -> usize {
+    let mut size = parameters.capacity() * size_of::<Parameter>();
+    for param in parameters.iter() {
+        size += strings(&param.path) + param.name.capacity();
+        if let Some(ref default) = param.default {
+            size += expression(default);
+        }
+        if let Some(ref in_list) = param.in_list {
+            size += expression(in_list);
+        }
+    }
+    size
+}
+
+fn settings(settings: &ProcSettings) -> usize {
+    let mut size = option_string(&settings.name) + option_string(&settings.desc);
+    if let Some(ref category) = settings.category {
+        size += option_string(category);
+    }
+    if let Some((_, ref src)) = settings.src {
+        size += expression(src);
+    }
+    size
+}
+
+fn constant(constant: &Constant) -> usize {
+    let pairs = |pairs: &Vec<(Constant, Option<Constant>)>| {
+        let mut size = pairs.capacity() * size_of::<(Constant, Option<Constant>)>();
+        for &(ref key, ref value) in pairs.iter() {
+            size += self::constant(key);
+            if let Some(ref value) = *value {
+                size += self::constant(value);
+            }
+        }
+        size
+    };
+    let prefab = |prefab: &Prefab<Constant>| {
+        let mut size = type_path(&prefab.path) + map_entries(&prefab.vars);
+        for (name, value) in prefab.vars.iter() {
+            size += name.capacity() + self::constant(value);
+        }
+        size
+    };
+
+    match *constant {
+        Constant::Null(Some(ref path)) => strings(path),
+        Constant::New { ref type_, ref args } => {
+            let mut size = match *type_ {
+                NewType::Ident(ref name) => name.capacity(),
+                NewType::Prefab(ref inner) => prefab(inner),
+                NewType::Implicit => 0,
+            };
+            if let Some(ref args) = *args {
+                size += pairs(args);
+            }
+            size
+        }
+        Constant::List(ref args) |
+        Constant::Call(_, ref args) => pairs(args),
+        Constant::Prefab(ref inner) => prefab(inner),
+        Constant::String(ref text) |
+        Constant::Resource(ref text) => text.capacity(),
+        Constant::Null(None) |
+        Constant::Int(_) |
+        Constant::Float(_) => 0,
+    }
+}
+
+/// Adds up the heap memory owned by AST nodes. Each node's inline size is
+/// counted by whatever owns it: a `Vec` or `Box` for nested nodes.
+struct AstSize(usize);
+
+impl AstSize {
+    fn expressions(&mut self, exprs: &Vec<Expression>) {
+        self.0 += exprs.capacity() * size_of::<Expression>();
+    }
+
+    fn prefab(&mut self, prefab: &Prefab) {
+        self.0 += type_path(&prefab.path) + map_entries(&prefab.vars);
+        self.0 += prefab.vars.keys().map(|key| key.capacity()).sum::<usize>();
+    }
+}
+
+impl<'ast> Visitor<'ast> for AstSize {
+    fn visit_block(&mut self, block: &'ast [Spanned<Statement>]) {
+        self.0 += block.len() * size_of::<Spanned<Statement>>();
+        walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, statement: &'ast Statement) {
+        match *statement {
+            Statement::If(ref arms, _) => {
+                self.0 += arms.capacity() * size_of::<(Expression, Block)>();
+            }
+            Statement::ForLoop { ref init, ref inc, .. } => {
+                self.0 += init.iter().chain(inc.iter()).count() * size_of::<Statement>();
+            }
+            Statement::ForList { ref var_type, ref name, .. } |
+            Statement::ForRange { ref var_type, ref name, .. } => {
+                self.0 += name.capacity() + var_type.as_ref().map_or(0, self::var_type);
+            }
+            Statement::Var(ref var) => {
+                self.0 += var.name.capacity() + self::var_type(&var.var_type);
+            }
+            Statement::Vars(ref vars) => {
+                self.0 += vars.capacity() * size_of::<VarStatement>();
+                for var in vars.iter() {
+                    self.0 += var.name.capacity() + self::var_type(&var.var_type);
+                }
+            }
+            Statement::Setting(ref name, ..) |
+            Statement::Label(ref name, _) |
+            Statement::Goto(ref name) => self.0 += name.capacity(),
+            Statement::Continue(ref label) |
+            Statement::Break(ref label) => self.0 += option_string(label),
+            Statement::Switch(_, ref cases, _) => {
+                self.0 += cases.capacity() * size_of::<(Vec<Case>, Block)>();
+                for &(ref case, _) in cases.iter() {
+                    self.0 += case.capacity() * size_of::<Case>();
+                }
+            }
+            Statement::TryCatch { catch_param: Some((ref var_type, ref name)), .. } => {
+                self.0 += name.capacity() + var_type.as_ref().map_or(0, self::var_type);
+            }
+            _ => {}
+        }
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        match *expr {
+            Expression::Base { ref unary, ref follow, .. } => {
+                self.0 += unary.capacity() * size_of::<UnaryOp>();
+                self.0 += follow.capacity() * size_of::<Follow>();
+            }
+            Expression::BinaryOp { .. } |
+            Expression::AssignOp { .. } => self.0 += 2 * size_of::<Expression>(),
+            Expression::TernaryOp { .. } => self.0 += 3 * size_of::<Expression>(),
+        }
+        walk_expression(self, expr);
+    }
+
+    fn visit_term(&mut self, term: &'ast Term) {
+        match *term {
+            Term::New { ref type_, ref args } => {
+                match *type_ {
+                    NewType::Ident(ref name) => self.0 += name.capacity(),
+                    NewType::Prefab(ref prefab) => self.prefab(prefab),
+                    NewType::Implicit => {}
+                }
+                if let Some(ref args) = *args {
+                    self.expressions(args);
+                }
+            }
+            Term::List(ref args) |
+            Term::ParentCall(ref args) |
+            Term::SelfCall(ref args) => self.expressions(args),
+            Term::Call(ref name, ref args) => {
+                self.0 += name.capacity();
+                self.expressions(args);
+            }
+            Term::Input { ref args, ref in_list, .. } |
+            Term::Locate { ref args, ref in_list } => {
+                self.expressions(args);
+                if in_list.is_some() {
+                    self.0 += size_of::<Expression>();
+                }
+            }
+            Term::Pick(ref args) => {
+                self.0 += args.capacity() * size_of::<(Option<Expression>, Expression)>();
+            }
+            Term::Prefab(ref prefab) => self.prefab(prefab),
+            Term::Ident(ref text) |
+            Term::String(ref text) |
+            Term::Resource(ref text) => self.0 += text.capacity(),
+            Term::Expr(_) => self.0 += size_of::<Expression>(),
+            Term::DynamicCall(ref first, ref second) |
+            Term::ExternalCall(ref first, ref second) => {
+                self.expressions(first);
+                self.expressions(second);
+            }
+            Term::InterpString(ref first, ref parts) => {
+                self.0 += first.capacity();
+                self.0 += parts.capacity() * size_of::<(Option<Expression>, String, Location)>();
+                self.0 += parts.iter().map(|&(_, ref text, _)| text.capacity()).sum::<usize>();
+            }
+            Term::Null |
+            Term::Int(_) |
+            Term::Float(_) => {}
+        }
+        walk_term(self, term);
+    }
+
+    fn visit_follow(&mut self, follow: &'ast Follow) {
+        match *follow {
+            Follow::Index(_) |
+            Follow::SafeIndex(_) => self.0 += size_of::<Expression>(),
+            Follow::Field(_, ref name) => self.0 += name.capacity(),
+            Follow::Call(_, ref name, ref args) => {
+                self.0 += name.capacity();
+                self.expressions(args);
+            }
+        }
+        walk_follow(self, follow);
+    }
+}
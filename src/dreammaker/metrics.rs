@@ -0,0 +1,118 @@
+//! Complexity metrics for proc bodies, computed from the AST.
+//!
+//! These are meant for finding procs which are due for a refactor, and are
+//! approximate: in particular, `goto` is not counted as a decision point.
+use ast::*;
+use objtree::{ObjectTree, TypeRef, ProcValue, Code};
+use analysis;
+use visit::*;
+
+/// Measurements of a single proc body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProcMetrics {
+    /// The number of statements, including those in nested blocks.
+    pub statements: usize,
+    /// The cyclomatic complexity: one more than the number of decision
+    /// points, such as conditions, loops, cases, `&&`, `||`, and `? :`.
+    pub complexity: usize,
+    /// The deepest nesting of blocks, where a body without any nested
+    /// blocks has a depth of zero.
+    pub max_depth: usize,
+    /// The number of calls to builtins which may sleep, such as `sleep()`.
+    pub sleeps: usize,
+}
+
+impl ProcMetrics {
+    /// Measure a proc body.
+    pub fn measure(tree: &ObjectTree, block: &[Spanned<Statement>]) -> ProcMetrics {
+        let mut measure = Measure {
+            metrics: ProcMetrics {
+                complexity: 1,
+                sleeps: analysis::sleeping_calls(tree, block).len(),
+                ..ProcMetrics::default()
+            },
+            depth: 0,
+        };
+        measure.metrics.statements += block.len();
+        walk_block(&mut measure, block);
+        measure.metrics
+    }
+}
+
+struct Measure {
+    metrics: ProcMetrics,
+    depth: usize,
+}
+
+impl<'ast> Visitor<'ast> for Measure {
+    fn visit_block(&mut self, block: &'ast [Spanned<Statement>]) {
+        self.metrics.statements += block.len();
+        self.depth += 1;
+        self.metrics.max_depth = ::std::cmp::max(self.metrics.max_depth, self.depth);
+        walk_block(self, block);
+        self.depth -= 1;
+    }
+
+    fn visit_statement(&mut self, statement: &'ast Statement) {
+        self.metrics.complexity += match *statement {
+            Statement::If(ref arms, _) => arms.len(),
+            Statement::Switch(_, ref cases, _) => cases.len(),
+            Statement::While(..) |
+            Statement::DoWhile(..) |
+            Statement::ForLoop { .. } |
+            Statement::ForList { .. } |
+            Statement::ForRange { .. } |
+            Statement::TryCatch { .. } => 1,
+            _ => 0,
+        };
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        match *expr {
+            Expression::BinaryOp { op: BinaryOp::And, .. } |
+            Expression::BinaryOp { op: BinaryOp::Or, .. } |
+            Expression::TernaryOp { .. } => self.metrics.complexity += 1,
+            _ => {}
+        }
+        walk_expression(self, expr);
+    }
+}
+
+/// The metrics of one proc definition.
+#[derive(Debug, Clone)]
+pub struct ProcReport<'a> {
+    pub ty: TypeRef<'a>,
+    pub name: &'a str,
+    pub proc: &'a ProcValue,
+    pub metrics: ProcMetrics,
+}
+
+impl<'a> ProcReport<'a> {
+    /// The full path of the proc, such as `/mob/proc/Life`.
+    pub fn path(&self) -> String {
+        format!("{}/proc/{}", self.ty.get().path, self.name)
+    }
+}
+
+/// Measure every proc in the tree whose body was parsed, most complex first.
+pub fn measure_all(tree: &ObjectTree) -> Vec<ProcReport> {
+    let mut result = Vec::new();
+    for ty in tree.graph.node_indices().map(|idx| TypeRef::new(tree, idx)) {
+        for (name, proc) in ty.get().procs.iter() {
+            for value in proc.value.iter() {
+                if let Code::Present(ref block) = value.code {
+                    result.push(ProcReport {
+                        ty,
+                        name,
+                        proc: value,
+                        metrics: ProcMetrics::measure(tree, block),
+                    });
+                }
+            }
+        }
+    }
+    result.sort_by(|a, b| b.metrics.complexity.cmp(&a.metrics.complexity)
+        .then(b.metrics.statements.cmp(&a.metrics.statements)));
+    result
+}
@@ -1,7 +1,9 @@
 //! The object tree representation, used as a parsing target.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
 
 pub use petgraph::graph::NodeIndex;
 use petgraph::graph::Graph;
@@ -9,10 +11,13 @@ use petgraph::visit::EdgeRef;
 use petgraph::Direction;
 use linked_hash_map::LinkedHashMap;
 
-use super::ast::{Expression, VarType, PathOp, Prefab, Parameter};
+use super::ast::{Expression, VarType, PathOp, Prefab, Parameter, Statement, Spanned, Block, SettingMode};
 use super::constants::Constant;
 use super::docs::DocCollection;
-use super::{DMError, Location, Context};
+use super::lexer::LocatedToken;
+use super::rewrite::format_block;
+use super::{DMError, FileId, Location, Context, Severity};
+use super::builtins::{self, BuiltinSignature, CustomProc};
 
 // ----------------------------------------------------------------------------
 // Variables
@@ -20,12 +25,16 @@ use super::{DMError, Location, Context};
 pub type Vars = LinkedHashMap<String, Constant>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct VarDeclaration {
     pub var_type: VarType,
     pub location: Location,
+    /// The note of a `@deprecated` doc comment tag, if the var has one.
+    pub deprecated: Option<String>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct VarValue {
     pub location: Location,
     /// Syntactic value, as specified in the source.
@@ -37,25 +46,308 @@ pub struct VarValue {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct TypeVar {
     pub value: VarValue,
     pub declaration: Option<VarDeclaration>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct ProcDeclaration {
     pub location: Location,
     pub is_verb: bool,
+    /// The note of a `@deprecated` doc comment tag or a
+    /// `SpacemanDMM_deprecated` directive, if the proc has either.
+    pub deprecated: Option<String>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct ProcValue {
     pub location: Location,
     pub parameters: Vec<Parameter>,
     pub docs: DocCollection,
+    pub kind: ProcKind,
+    pub settings: ProcSettings,
+    pub code: Code,
+}
+
+impl ProcValue {
+    /// Get the statements of this proc's body, parsing it first if it was
+    /// collected lazily.
+    ///
+    /// A lazy body is parsed only once, with any errors registered to the
+    /// given `Context`. Returns `None` if the body did not parse or was not
+    /// kept.
+    pub fn body(&self, context: &Context) -> Option<Body> {
+        match self.code {
+            Code::Present(ref block) => Some(Body::Parsed(block)),
+            Code::Lazy(ref lazy) => lazy.parse(context).map(Body::Lazy),
+            Code::Invalid | Code::Disabled => None,
+        }
+    }
+}
+
+/// The statements of a proc body, as returned by `ProcValue::body`.
+#[derive(Debug, Clone)]
+pub enum Body<'a> {
+    /// A body which was parsed along with the object tree.
+    Parsed(&'a [Spanned<Statement>]),
+    /// A lazily-collected body, parsed on demand.
+    Lazy(Arc<Block>),
+}
+
+impl<'a> Deref for Body<'a> {
+    type Target = [Spanned<Statement>];
+
+    fn deref(&self) -> &[Spanned<Statement>] {
+        match *self {
+            Body::Parsed(block) => block,
+            Body::Lazy(ref block) => block,
+        }
+    }
+}
+
+/// How a proc definition was introduced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum ProcKind {
+    /// Declared under `/proc/`.
+    Proc,
+    /// Declared under `/verb/`.
+    Verb,
+    /// Overrides a proc or verb declared elsewhere.
+    Override,
+}
+
+/// The directive marking a proc as deprecated, optionally with a note such
+/// as `set SpacemanDMM_deprecated = "use foo() instead"`.
+pub const DEPRECATED_DIRECTIVE: &str = "SpacemanDMM_deprecated";
+
+/// The `set` directives at the top level of a proc body.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ProcSettings {
+    pub name: Option<String>,
+    pub desc: Option<String>,
+    /// `Some(None)` if the category was explicitly set to null.
+    pub category: Option<Option<String>>,
+    pub hidden: Option<bool>,
+    pub instant: Option<bool>,
+    pub popup_menu: Option<bool>,
+    pub background: Option<bool>,
+    pub waitfor: Option<bool>,
+    pub invisibility: Option<i32>,
+    /// The `set src` specifier, such as `set src in view(1)`.
+    pub src: Option<(SettingMode, Expression)>,
+    /// Settings named `SpacemanDMM_*`, which direct this tool's checks
+    /// rather than BYOND.
+    pub directives: Vec<(String, Expression)>,
+}
+
+impl ProcSettings {
+    /// The prefix of settings which are directives to this tool.
+    pub const DIRECTIVE_PREFIX: &'static str = "SpacemanDMM_";
+
+    /// Check whether `name` is a directive to this tool.
+    pub fn is_directive(name: &str) -> bool {
+        name.starts_with(ProcSettings::DIRECTIVE_PREFIX)
+    }
+
+    /// The note of the `SpacemanDMM_deprecated` directive, which is empty
+    /// if the directive is set to a true value other than a string.
+    pub fn deprecation(&self) -> Option<String> {
+        self.directives.iter()
+            .rev()
+            .find(|&&(ref each, _)| each == DEPRECATED_DIRECTIVE)
+            .and_then(|&(_, ref value)| super::constants::simple_evaluate(Default::default(), value.clone()).ok())
+            .and_then(|constant| match constant.as_str() {
+                Some(note) => Some(note.to_owned()),
+                None if constant.to_bool() => Some(String::new()),
+                None => None,
+            })
+    }
+
+    /// Check whether a directive is set to a true value.
+    pub fn directive_enabled(&self, name: &str) -> bool {
+        self.directives.iter()
+            .rev()
+            .find(|&&(ref each, _)| each == name)
+            .and_then(|&(_, ref value)| super::constants::simple_evaluate(Default::default(), value.clone()).ok())
+            .map_or(false, |constant| constant.to_bool())
+    }
+
+    /// Check whether `name` is a setting BYOND understands.
+    pub fn is_known(name: &str) -> bool {
+        match name {
+            "name" | "desc" | "category" | "hidden" | "instant" | "popup_menu" |
+            "background" | "waitfor" | "invisibility" | "src" => true,
+            _ => false,
+        }
+    }
+
+    /// Record a `set` directive, describing any problem with its value.
+    ///
+    /// Unknown settings are ignored.
+    pub fn apply(&mut self, name: &str, mode: &SettingMode, value: &Expression) -> Result<(), String> {
+        let duplicate = match name {
+            "src" => {
+                let old = ::std::mem::replace(&mut self.src, Some((mode.clone(), value.clone())));
+                return if old.is_some() { Err("set more than once".to_owned()) } else { Ok(()) };
+            }
+            _ if ProcSettings::is_directive(name) => {
+                let duplicate = self.directives.iter().any(|&(ref each, _)| each == name);
+                self.directives.push((name.to_owned(), value.clone()));
+                return if duplicate { Err("set more than once".to_owned()) } else { Ok(()) };
+            }
+            _ if !ProcSettings::is_known(name) => return Ok(()),
+            _ if *mode != SettingMode::Assign => return Err("only `set src` may use `in`".to_owned()),
+            "name" => self.name.is_some(),
+            "desc" => self.desc.is_some(),
+            "category" => self.category.is_some(),
+            "hidden" => self.hidden.is_some(),
+            "instant" => self.instant.is_some(),
+            "popup_menu" => self.popup_menu.is_some(),
+            "background" => self.background.is_some(),
+            "waitfor" => self.waitfor.is_some(),
+            "invisibility" => self.invisibility.is_some(),
+            _ => false,
+        };
+
+        let constant = match super::constants::simple_evaluate(Default::default(), value.clone()) {
+            Ok(constant) => constant,
+            Err(_) => return Err("value must be a constant".to_owned()),
+        };
+        match name {
+            "name" | "desc" => {
+                let text = match constant.as_str() {
+                    Some(text) => text.to_owned(),
+                    None => return Err(format!("expected a string, got {}", constant)),
+                };
+                if name == "name" {
+                    self.name = Some(text);
+                } else {
+                    self.desc = Some(text);
+                }
+            }
+            "category" => self.category = Some(match constant {
+                Constant::Null(_) => None,
+                ref other => match other.as_str() {
+                    Some(text) => Some(text.to_owned()),
+                    None => return Err(format!("expected a string or null, got {}", constant)),
+                },
+            }),
+            "invisibility" => match constant.to_int() {
+                Some(value) => self.invisibility = Some(value),
+                None => return Err(format!("expected a number, got {}", constant)),
+            },
+            _ => {
+                if constant.to_float().is_none() {
+                    return Err(format!("expected a number, got {}", constant));
+                }
+                let flag = Some(constant.to_bool());
+                match name {
+                    "hidden" => self.hidden = flag,
+                    "instant" => self.instant = flag,
+                    "popup_menu" => self.popup_menu = flag,
+                    "background" => self.background = flag,
+                    _ => self.waitfor = flag,
+                }
+            }
+        }
+
+        if duplicate {
+            Err("set more than once".to_owned())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The body of a proc, if it was parsed.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum Code {
+    /// The body was parsed successfully.
+    Present(Block),
+    /// The body failed to parse.
+    Invalid,
+    /// Proc bodies were not parsed.
+    Disabled,
+    /// The body was collected as token trees, to be parsed on demand.
+    ///
+    /// Kept last so that skipping it does not renumber the other variants.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Lazy(LazyCode),
+}
+
+impl Default for Code {
+    fn default() -> Code {
+        Code::Disabled
+    }
+}
+
+/// A proc body whose parsing has been deferred until it is needed.
+pub struct LazyCode {
+    tokens: Vec<LocatedToken>,
+    /// `None` until parsed, then `Some(None)` if the body was invalid.
+    parsed: Mutex<Option<Option<Arc<Block>>>>,
+}
+
+impl LazyCode {
+    pub(crate) fn new(tokens: Vec<LocatedToken>) -> LazyCode {
+        LazyCode {
+            tokens,
+            parsed: Mutex::new(None),
+        }
+    }
+
+    /// The token trees making up the body.
+    pub fn tokens(&self) -> &[LocatedToken] {
+        &self.tokens
+    }
+
+    /// Whether the body has been parsed yet.
+    pub fn is_parsed(&self) -> bool {
+        self.parsed.lock().unwrap().is_some()
+    }
+
+    fn parse(&self, context: &Context) -> Option<Arc<Block>> {
+        let mut parsed = self.parsed.lock().unwrap();
+        if parsed.is_none() {
+            *parsed = Some(match super::parser::parse_proc_body(context, self.tokens.clone()) {
+                Ok(block) => Some(Arc::new(block)),
+                Err(err) => {
+                    context.register_error(err);
+                    None
+                }
+            });
+        }
+        parsed.as_ref().and_then(|block| block.clone())
+    }
+}
+
+impl Clone for LazyCode {
+    fn clone(&self) -> LazyCode {
+        LazyCode {
+            tokens: self.tokens.clone(),
+            parsed: Mutex::new(self.parsed.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl fmt::Debug for LazyCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LazyCode")
+            .field("tokens", &self.tokens.len())
+            .field("parsed", &self.is_parsed())
+            .finish()
+    }
 }
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct TypeProc {
     pub value: Vec<ProcValue>,
     pub declaration: Option<ProcDeclaration>,
@@ -67,13 +359,17 @@ pub struct TypeProc {
 const BAD_NODE_INDEX: usize = ::std::usize::MAX;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct Type {
     pub name: String,
     pub path: String,
     pub location: Location,
     location_specificity: usize,
+    #[cfg_attr(feature = "serde", serde(with = "::serde_helpers::linked_hash_map"))]
     pub vars: LinkedHashMap<String, TypeVar>,
+    #[cfg_attr(feature = "serde", serde(with = "::serde_helpers::linked_hash_map"))]
     pub procs: LinkedHashMap<String, TypeProc>,
+    #[cfg_attr(feature = "serde", serde(with = "::serde_helpers::node_index"))]
     parent_type: NodeIndex,
     pub docs: DocCollection,
 }
@@ -152,7 +448,7 @@ pub struct TypeRef<'a> {
 
 impl<'a> TypeRef<'a> {
     #[inline]
-    fn new(tree: &'a ObjectTree, idx: NodeIndex) -> TypeRef<'a> {
+    pub(crate) fn new(tree: &'a ObjectTree, idx: NodeIndex) -> TypeRef<'a> {
         TypeRef { tree, idx }
     }
 
@@ -269,6 +565,18 @@ impl<'a> TypeRef<'a> {
         }
         None
     }
+
+    /// Find the declaration of a proc on this type or its parents.
+    pub fn get_proc_declaration(self, name: &str) -> Option<&'a ProcDeclaration> {
+        let mut current: Option<TypeRef<'a>> = Some(self);
+        while let Some(ty) = current {
+            if let Some(decl) = ty.get().procs.get(name).and_then(|proc| proc.declaration.as_ref()) {
+                return Some(decl);
+            }
+            current = ty.parent_type();
+        }
+        None
+    }
 }
 
 impl<'a> ::std::ops::Deref for TypeRef<'a> {
@@ -292,13 +600,40 @@ impl<'a> ::std::cmp::PartialEq for TypeRef<'a> {
 
 impl<'a> ::std::cmp::Eq for TypeRef<'a> {}
 
+/// A type's own entry for a var, as found by `ObjectTree::var_overrides`.
+#[derive(Debug, Clone, Copy)]
+pub struct VarOverride<'a> {
+    pub ty: TypeRef<'a>,
+    pub var: &'a TypeVar,
+}
+
+impl<'a> VarOverride<'a> {
+    /// Whether this entry declares the var rather than only overriding it.
+    pub fn is_declaration(&self) -> bool {
+        self.var.declaration.is_some()
+    }
+
+    /// The constant-folded value, if the value is a constant.
+    pub fn constant(&self) -> Option<&'a Constant> {
+        self.var.value.constant.as_ref()
+    }
+}
+
 // ----------------------------------------------------------------------------
 // The object tree itself
 
+/// Warns when a var is set to the same value, or a proc defined with the
+/// same parameters and body, twice on the same type.
+pub const LINT_DUPLICATE_DEFINITIONS: &str = "duplicate-definitions";
+
 #[derive(Debug)]
 pub struct ObjectTree {
     pub graph: Graph<Type, ()>,
     pub types: BTreeMap<String, NodeIndex>,
+    /// The natives added by the codebase, by path. These are not serialized.
+    custom_procs: HashMap<String, CustomProc>,
+    /// Vars set to the same value twice, reported when finalized.
+    redefinitions: Vec<DMError>,
 }
 
 impl Default for ObjectTree {
@@ -306,6 +641,8 @@ impl Default for ObjectTree {
         let mut tree = ObjectTree {
             graph: Default::default(),
             types: Default::default(),
+            custom_procs: Default::default(),
+            redefinitions: Vec::new(),
         };
         tree.graph.add_node(Type {
             name: String::new(),
@@ -321,11 +658,89 @@ impl Default for ObjectTree {
     }
 }
 
+/// Object trees are serialized as a list of their types, in creation order.
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for ObjectTree {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.graph.raw_nodes().iter().map(|node| &node.weight))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for ObjectTree {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let types: Vec<Type> = ::serde::Deserialize::deserialize(deserializer)?;
+        let mut tree = ObjectTree {
+            graph: Graph::with_capacity(types.len(), types.len()),
+            types: Default::default(),
+            custom_procs: Default::default(),
+            redefinitions: Vec::new(),
+        };
+        for ty in types {
+            if ty.path.is_empty() {
+                if tree.graph.node_count() != 0 {
+                    return Err(D::Error::custom("root type must come first"));
+                }
+                tree.graph.add_node(ty);
+                continue;
+            }
+            // parents are always created before their children
+            let parent = match ty.path.len().checked_sub(ty.name.len() + 1) {
+                Some(0) => NodeIndex::new(0),
+                Some(len) => match tree.types.get(&ty.path[..len]) {
+                    Some(&idx) => idx,
+                    None => return Err(D::Error::custom(format!("parent of {} missing", ty.path))),
+                },
+                None => return Err(D::Error::custom(format!("malformed path {}", ty.path))),
+            };
+            if tree.graph.node_count() == 0 {
+                return Err(D::Error::custom("root type must come first"));
+            }
+            let path = ty.path.clone();
+            let idx = tree.graph.add_node(ty);
+            tree.graph.add_edge(parent, idx, ());
+            tree.types.insert(path, idx);
+        }
+        if tree.graph.node_count() == 0 {
+            return Err(D::Error::custom("missing root type"));
+        }
+        Ok(tree)
+    }
+}
+
 impl ObjectTree {
     pub fn register_builtins(&mut self) {
         super::builtins::register_builtins(self).expect("register_builtins failed");
     }
 
+    /// Register the natives which the context's settings add to the
+    /// builtins.
+    pub fn register_custom_builtins(&mut self, context: &Context) {
+        super::builtins::register_custom_builtins(self, context);
+        for proc in context.custom_builtins().procs.iter() {
+            let path = format!("/{}", builtins::path_elements(&proc.path).join("/"));
+            self.custom_procs.insert(path, proc.clone());
+        }
+    }
+
+    /// Look up the signature of a builtin global proc, including natives
+    /// added by the codebase.
+    pub fn builtin_signature(&self, name: &str) -> Option<BuiltinSignature> {
+        match self.custom_procs.get(&format!("/proc/{}", name)) {
+            Some(proc) => Some(proc.signature()),
+            None => builtins::builtin_signature(name),
+        }
+    }
+
+    /// Look up the declared return type of a native global proc added by
+    /// the codebase.
+    pub fn builtin_return_type(&self, name: &str) -> Option<TypeRef> {
+        let path = self.custom_procs.get(&format!("/proc/{}", name))?.return_type.as_ref()?;
+        self.find(path)
+    }
+
     // ------------------------------------------------------------------------
     // Access
 
@@ -389,12 +804,71 @@ impl ObjectTree {
         }
     }
 
+    /// Find every type which declares or overrides the named var, in tree
+    /// order.
+    pub fn var_overrides(&self, name: &str) -> Vec<VarOverride> {
+        let mut result: Vec<VarOverride> = self.graph.node_indices()
+            .filter_map(|idx| {
+                let ty = TypeRef::new(self, idx);
+                ty.get().vars.get(name).map(|var| VarOverride { ty, var })
+            })
+            .collect();
+        result.sort_by(|a, b| a.ty.path.split('/').cmp(b.ty.path.split('/')));
+        result
+    }
+
+    /// Estimate the memory used by each part of this tree.
+    pub fn memory_report(&self) -> super::memory::MemoryReport {
+        super::memory::MemoryReport::of_tree(self)
+    }
+
     // ------------------------------------------------------------------------
     // Finalization
 
     pub(crate) fn finalize(&mut self, context: &Context, sloppy: bool) {
+        self.report_redefinitions(context);
         self.assign_parent_types(context);
+        self.assign_deprecations();
         super::constants::evaluate_all(context, self, sloppy);
+        super::analysis::check_all(context, self);
+    }
+
+    fn report_redefinitions(&mut self, context: &Context) {
+        let redefinitions = ::std::mem::replace(&mut self.redefinitions, Vec::new());
+        if !context.lint_enabled(LINT_DUPLICATE_DEFINITIONS) {
+            return;
+        }
+        for error in redefinitions {
+            context.register_error(error);
+        }
+
+        // procs keep every definition, so they are compared only now that
+        // their bodies are all known
+        for node in self.graph.raw_nodes() {
+            for (name, proc) in node.weight.procs.iter() {
+                if proc.value.len() < 2 {
+                    continue;
+                }
+                let bodies: Vec<Option<String>> = proc.value.iter()
+                    .map(|value| value.body(context).map(|body| format_block(&body, "\t", 0)))
+                    .collect();
+                for (i, value) in proc.value.iter().enumerate().skip(1) {
+                    if value.location.is_builtins() || bodies[i].is_none() {
+                        continue;
+                    }
+                    let previous = proc.value[..i].iter().zip(bodies.iter()).find(|&(previous, body)| {
+                        *body == bodies[i] && same_parameters(&previous.parameters, &value.parameters)
+                    });
+                    if let Some((previous, _)) = previous {
+                        context.register_error(redefinition(
+                            value.location,
+                            previous.location,
+                            format!("proc {} redefined with the same body", name),
+                        ));
+                    }
+                }
+            }
+        }
     }
 
     fn assign_parent_types(&mut self, context: &Context) {
@@ -464,9 +938,139 @@ impl ObjectTree {
         }
     }
 
+    /// Record which declarations are deprecated, now that their docs and
+    /// proc settings are complete.
+    fn assign_deprecations(&mut self) {
+        for idx in self.graph.node_indices() {
+            let ty = self.graph.node_weight_mut(idx).unwrap();
+            for (_, var) in ty.vars.iter_mut() {
+                if let Some(ref mut decl) = var.declaration {
+                    decl.deprecated = var.value.docs.deprecation();
+                }
+            }
+            for (_, proc) in ty.procs.iter_mut() {
+                if let Some(ref mut decl) = proc.declaration {
+                    decl.deprecated = proc.value.iter()
+                        .filter_map(|value| value.settings.deprecation().or_else(|| value.docs.deprecation()))
+                        .next();
+                }
+            }
+        }
+    }
+
     // ------------------------------------------------------------------------
     // Parsing
 
+    /// Merge an unfinalized tree into this one, as if its source had been
+    /// parsed immediately after this tree's source.
+    pub(crate) fn merge(&mut self, other: ObjectTree) {
+        self.redefinitions.extend(other.redefinitions);
+        let (nodes, _) = other.graph.into_nodes_edges();
+        // nodes are created parents-first, so each type's parent path will
+        // already have been merged by the time it is reached
+        for (i, node) in nodes.into_iter().enumerate() {
+            let ty = node.weight;
+            let idx = if i == 0 {
+                NodeIndex::new(0)
+            } else {
+                let parent = match self.types.get(&ty.path[..ty.path.len() - ty.name.len() - 1]) {
+                    Some(&idx) => idx,
+                    None => NodeIndex::new(0),
+                };
+                self.subtype_or_add(ty.location, parent, &ty.name, ty.location_specificity)
+            };
+
+            let node = self.graph.node_weight_mut(idx).unwrap();
+            node.docs.extend(ty.docs);
+            for (name, var) in ty.vars {
+                // repeats keep the original declaration, but take the new value
+                if let Some(existing) = node.vars.get_mut(&name) {
+                    if var.value.expression.is_some() && existing.value.expression == var.value.expression {
+                        self.redefinitions.push(redefinition(
+                            var.value.location,
+                            existing.value.location,
+                            format!("var {} redefined with the same value", name),
+                        ));
+                    }
+                    if var.value.expression.is_some() {
+                        existing.value.location = var.value.location;
+                        existing.value.expression = var.value.expression;
+                    }
+                    continue;
+                }
+                node.vars.insert(name, var);
+            }
+            for (name, proc) in ty.procs {
+                let existing = node.procs.entry(name).or_insert_with(Default::default);
+                if existing.declaration.is_none() {
+                    existing.declaration = proc.declaration;
+                }
+                existing.value.extend(proc.value);
+            }
+        }
+    }
+
+    /// Take the declarations of one file from a fragment parsed from its
+    /// new contents, as long as its vars, procs and types are unchanged apart
+    /// from their locations and proc bodies.
+    ///
+    /// Returns `false`, leaving the tree untouched, if the whole environment
+    /// must be parsed again instead.
+    pub fn replace_file(&mut self, file: FileId, fragment: ObjectTree) -> bool {
+        if fragment.types.keys().any(|path| !self.types.contains_key(path)) {
+            return false;
+        }
+        let removed_type = self.types.iter().any(|(path, &idx)| {
+            self.graph[idx].location.file == file && !fragment.types.contains_key(path)
+        });
+        if removed_type || file_outline(self, file) != file_outline(&fragment, file) {
+            return false;
+        }
+
+        let (nodes, _) = fragment.graph.into_nodes_edges();
+        for node in nodes.into_iter().skip(1) {
+            let ty = node.weight;
+            let existing = &mut self.graph[self.types[&ty.path]];
+            if existing.location.file == file {
+                existing.location = ty.location;
+            }
+            for (name, var) in ty.vars {
+                let old = match existing.vars.get_mut(&name) {
+                    Some(old) => old,
+                    None => continue,
+                };
+                if let (Some(decl), Some(new_decl)) = (old.declaration.as_mut(), var.declaration) {
+                    if decl.location.file == file {
+                        decl.location = new_decl.location;
+                    }
+                }
+                // the constant is kept, as the expression is the same
+                if old.value.location.file == file {
+                    old.value.location = var.value.location;
+                    old.value.expression = var.value.expression;
+                    old.value.docs = var.value.docs;
+                }
+            }
+            for (name, proc) in ty.procs {
+                let old = match existing.procs.get_mut(&name) {
+                    Some(old) => old,
+                    None => continue,
+                };
+                if let (Some(decl), Some(new_decl)) = (old.declaration.as_mut(), proc.declaration) {
+                    if decl.location.file == file {
+                        decl.location = new_decl.location;
+                    }
+                }
+                let old_values = old.value.iter_mut().filter(|value| value.location.file == file);
+                for (old, new) in old_values.zip(proc.value) {
+                    *old = new;
+                }
+            }
+        }
+        self.assign_deprecations();
+        true
+    }
+
     fn subtype_or_add(&mut self, location: Location, parent: NodeIndex, child: &str, len: usize) -> NodeIndex {
         let mut neighbors = self.graph.neighbors(parent).detach();
         while let Some(target) = neighbors.next_node(&self.graph) {
@@ -529,11 +1133,17 @@ impl ObjectTree {
         mut prev: &'a str,
         mut rest: I,
         comment: DocCollection,
-    ) -> Result<Option<&mut TypeVar>, DMError>
+    ) -> Result<Option<(&'a str, &mut TypeVar)>, DMError>
     where
         I: Iterator<Item=&'a str>,
     {
-        let (mut is_declaration, mut is_static, mut is_const, mut is_tmp) = (false, false, false, false);
+        let mut is_declaration = false;
+        let mut var_type = VarType {
+            is_static: false,
+            is_const: false,
+            is_tmp: false,
+            type_path: Vec::new(),
+        };
 
         if is_var_decl(prev) {
             is_declaration = true;
@@ -541,11 +1151,9 @@ impl ObjectTree {
                 Some(name) => name,
                 None => return Ok(None), // var{} block, children will be real vars
             };
-            while prev == "global" || prev == "static" || prev == "tmp" || prev == "const" {
+            while VarType::is_modifier(prev) {
                 if let Some(name) = rest.next() {
-                    is_static |= prev == "global" || prev == "static";
-                    is_const |= prev == "const";
-                    is_tmp |= prev == "tmp";
+                    var_type.apply_modifier(prev);
                     prev = name;
                 } else {
                     return Ok(None); // var/const{} block, children will be real vars
@@ -555,14 +1163,13 @@ impl ObjectTree {
             return Err(DMError::new(location, "proc looks like a var"));
         }
 
-        let mut type_path = Vec::new();
         for each in rest {
-            type_path.push(prev.to_owned());
+            var_type.type_path.push(prev.to_owned());
             prev = each;
         }
         let node = self.graph.node_weight_mut(parent).unwrap();
         // TODO: warn and merge docs for repeats
-        Ok(Some(node.vars.entry(prev.to_owned()).or_insert_with(|| TypeVar {
+        Ok(Some((prev, node.vars.entry(prev.to_owned()).or_insert_with(|| TypeVar {
             value: VarValue {
                 location,
                 expression: None,
@@ -572,18 +1179,14 @@ impl ObjectTree {
             },
             declaration: if is_declaration {
                 Some(VarDeclaration {
-                    var_type: VarType {
-                        is_static,
-                        is_const,
-                        is_tmp,
-                        type_path,
-                    },
+                    var_type,
                     location,
+                    deprecated: None,
                 })
             } else {
                 None
             },
-        })))
+        }))))
     }
 
     fn register_proc(
@@ -600,6 +1203,7 @@ impl ObjectTree {
             proc.declaration = is_verb.map(|is_verb| ProcDeclaration {
                 location,
                 is_verb,
+                deprecated: None,
             });
         }
 
@@ -608,6 +1212,13 @@ impl ObjectTree {
             location,
             parameters,
             docs: Default::default(),
+            kind: match is_verb {
+                Some(true) => ProcKind::Verb,
+                Some(false) => ProcKind::Proc,
+                None => ProcKind::Override,
+            },
+            settings: Default::default(),
+            code: Code::Disabled,
         });
         Ok((len, proc.value.last_mut().unwrap()))
     }
@@ -642,13 +1253,28 @@ impl ObjectTree {
         comment: DocCollection,
     ) -> Result<(), DMError> {
         let (parent, initial) = self.get_from_path(location, &mut path, len)?;
-        if let Some(type_var) = self.register_var(location, parent, initial, path, comment)? {
-            type_var.value.location = location;
-            type_var.value.expression = Some(expr);
-            Ok(())
-        } else {
-            Err(DMError::new(location, "var must have a name"))
+        let previous = match self.register_var(location, parent, initial, path, comment)? {
+            Some((name, type_var)) => {
+                let previous = match type_var.value.expression {
+                    Some(ref previous) if *previous == expr && !location.is_builtins() => {
+                        Some((name, type_var.value.location))
+                    }
+                    _ => None,
+                };
+                type_var.value.location = location;
+                type_var.value.expression = Some(expr);
+                previous
+            }
+            None => return Err(DMError::new(location, "var must have a name")),
+        };
+        if let Some((name, previous)) = previous {
+            self.redefinitions.push(redefinition(
+                location,
+                previous,
+                format!("var {} redefined with the same value", name),
+            ));
         }
+        Ok(())
     }
 
     // an entry which is definitely a proc because an argument list is specified
@@ -695,3 +1321,57 @@ fn is_proc_decl(s: &str) -> bool {
 fn is_decl(s: &str) -> bool {
     is_var_decl(s) || is_proc_decl(s)
 }
+
+/// Describe the vars and procs which a file declares or sets on each type,
+/// leaving out locations and proc bodies.
+fn file_outline(tree: &ObjectTree, file: FileId) -> Vec<String> {
+    let mut outline = Vec::new();
+    for node in tree.graph.raw_nodes() {
+        let ty = &node.weight;
+        for (name, var) in ty.vars.iter() {
+            if let Some(ref decl) = var.declaration {
+                if decl.location.file == file {
+                    outline.push(format!("{}/var{}/{}", ty.path, decl.var_type, name));
+                }
+            }
+            if var.value.location.file == file {
+                match var.value.expression {
+                    Some(ref expr) => outline.push(format!("{}/{} = {}", ty.path, name, expr)),
+                    None => outline.push(format!("{}/{}", ty.path, name)),
+                }
+            }
+        }
+        for (name, proc) in ty.procs.iter() {
+            if let Some(ref decl) = proc.declaration {
+                if decl.location.file == file {
+                    let kind = if decl.is_verb { "verb" } else { "proc" };
+                    outline.push(format!("{}/{}/{}", ty.path, kind, name));
+                }
+            }
+            for value in proc.value.iter().filter(|value| value.location.file == file) {
+                let parameters: Vec<String> = value.parameters.iter().map(|p| p.to_string()).collect();
+                outline.push(format!("{}/{}({})", ty.path, name, parameters.join(", ")));
+            }
+        }
+    }
+    outline.sort();
+    outline
+}
+
+/// A warning that something was defined again just as it was before.
+fn redefinition(location: Location, previous: Location, message: String) -> DMError {
+    DMError::new(location, message)
+        .set_severity(Severity::Warning)
+        .with_note(previous, "previous definition here")
+}
+
+/// Whether two parameter lists are the same, wherever they are written.
+fn same_parameters(a: &[Parameter], b: &[Parameter]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| {
+        a.path == b.path
+            && a.name == b.name
+            && a.default == b.default
+            && a.input_type == b.input_type
+            && a.in_list == b.in_list
+    })
+}
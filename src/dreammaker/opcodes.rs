@@ -0,0 +1,171 @@
+//! DM bytecode opcodes, for disassemblers and runtime debuggers.
+//!
+//! A compiled proc is a sequence of 32-bit words, each instruction being an
+//! opcode followed by its operands. The numbering is that established by
+//! community reverse engineering of the runtime. As with the builtins,
+//! opcodes added after `BASE_BYOND_VERSION` record the version which added
+//! them.
+
+/// What an operand of an instruction encodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    /// A literal integer.
+    Int,
+    /// A literal number, stored as the bits of an `f32`.
+    Float,
+    /// An index into the string table.
+    String,
+    /// An index into the proc table.
+    Proc,
+    /// The offset of another instruction in the same proc.
+    Label,
+    /// The number of arguments taken from the stack.
+    ArgCount,
+    /// A chain of var accesses such as `src.loc.name`, of varying length.
+    Var,
+    /// A tagged value: a type tag followed by one or two words of data.
+    Value,
+}
+
+impl OperandKind {
+    /// Whether the operand always occupies exactly one word.
+    pub fn is_fixed_width(self) -> bool {
+        match self {
+            OperandKind::Var | OperandKind::Value => false,
+            _ => true,
+        }
+    }
+}
+
+/// One opcode and the operands which follow it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    pub code: u32,
+    /// The mnemonic, such as `PushInt`.
+    pub name: &'static str,
+    pub operands: &'static [OperandKind],
+    /// The BYOND version which added the opcode, if newer than
+    /// `BASE_BYOND_VERSION`.
+    pub since: Option<u32>,
+}
+
+impl Opcode {
+    /// Whether the opcode exists in the given BYOND version.
+    pub fn exists_in(&self, version: u32) -> bool {
+        self.since.map_or(true, |since| version >= since)
+    }
+
+    /// Whether execution may continue somewhere other than the next
+    /// instruction.
+    pub fn is_branch(&self) -> bool {
+        self.operands.contains(&OperandKind::Label)
+    }
+}
+
+macro_rules! opcodes {
+    ($($code:expr => $name:ident($($operand:ident),*) $(since $version:expr)*;)*) => {
+        &[$(Opcode {
+            code: $code,
+            name: stringify!($name),
+            operands: &[$(OperandKind::$operand),*],
+            since: opcodes!(@since $($version)*),
+        },)*]
+    };
+    (@since) => { None };
+    (@since $version:expr) => { Some($version) };
+}
+
+const OPCODES: &[Opcode] = opcodes! {
+    0x00 => End();
+    0x01 => New(ArgCount);
+    0x02 => Format(String, ArgCount);
+    0x03 => Output();
+    0x04 => OutputFormat(String, ArgCount);
+    0x05 => Read();
+    0x06 => Stat();
+    0x07 => Link();
+    0x08 => Ftp();
+    0x09 => Run();
+    0x0A => Del();
+    0x0B => Browse();
+    0x0C => BrowseRsc();
+    0x0D => Test();
+    0x0E => Not();
+    0x0F => Jmp(Label);
+    0x10 => Jnz(Label);
+    0x11 => Jz(Label);
+    0x12 => Ret();
+    0x13 => IsLoc();
+    0x14 => IsMob();
+    0x15 => IsObj();
+    0x16 => IsArea();
+    0x17 => IsTurf();
+    0x18 => Alert();
+    0x19 => EmptyList();
+    0x1A => NewList(ArgCount);
+    0x1B => View();
+    0x1C => OView();
+    0x1D => ViewTarget();
+    0x1E => OViewTarget();
+    0x1F => Block();
+    0x21 => Prob();
+    0x22 => Rand();
+    0x23 => RandRange();
+    0x24 => Sleep();
+    0x25 => Spawn(Label);
+    0x27 => Call(Var, ArgCount);
+    0x28 => CallNoReturn(Var, ArgCount);
+    0x29 => CallPath(ArgCount);
+    0x2A => CallParent();
+    0x2B => CallParentArgs(ArgCount);
+    0x2C => CallSelf();
+    0x2D => CallSelfArgs(ArgCount);
+    0x2E => CallGlobal(ArgCount, Proc);
+    0x2F => Log10();
+    0x30 => Log();
+    0x31 => GetVar(Var);
+    0x32 => SetVar(Var);
+    0x34 => Teq();
+    0x35 => Tne();
+    0x36 => Tl();
+    0x37 => Tg();
+    0x38 => Tle();
+    0x39 => Tge();
+    0x3B => Add();
+    0x3C => Sub();
+    0x3D => Mul();
+    0x3E => Div();
+    0x3F => Mod();
+    0x40 => Round();
+    0x41 => RoundN();
+    0x42 => AugAdd(Var);
+    0x43 => AugSub(Var);
+    0x44 => AugMul(Var);
+    0x45 => AugDiv(Var);
+    0x46 => AugMod(Var);
+    0x47 => AugBand(Var);
+    0x48 => AugBor(Var);
+    0x49 => AugXor(Var);
+    0x4A => AugLShift(Var);
+    0x4B => AugRShift(Var);
+    0x4C => PushInt(Int);
+    0x4D => Pop();
+    0x4E => IterLoad(Int, Int);
+    0x4F => IterNext();
+    0x50 => PushVal(Value);
+};
+
+/// All opcodes which exist in the given BYOND version, in numeric order.
+pub fn opcodes(version: u32) -> Vec<&'static Opcode> {
+    OPCODES.iter().filter(|op| op.exists_in(version)).collect()
+}
+
+/// Look up an opcode by its number.
+pub fn by_code(code: u32, version: u32) -> Option<&'static Opcode> {
+    OPCODES.iter().find(|op| op.code == code && op.exists_in(version))
+}
+
+/// Look up an opcode by its mnemonic.
+pub fn by_name(name: &str, version: u32) -> Option<&'static Opcode> {
+    OPCODES.iter().find(|op| op.name == name && op.exists_in(version))
+}
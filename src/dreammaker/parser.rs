@@ -1,19 +1,23 @@
 //! Minimalist parser which turns a token stream into an object tree.
 
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::ops::Range;
 use std::fmt;
 
 use linked_hash_map::LinkedHashMap;
 
-use super::{DMError, Location, HasLocation, Context, Severity, FileId};
+use super::{DMError, Location, HasLocation, Context, Severity, FileId, Fix};
 use super::lexer::{LocatedToken, Token, Punctuation};
-use super::objtree::ObjectTree;
+use super::objtree::{ObjectTree, Code, LazyCode, ProcSettings};
 use super::annotation::*;
 use super::ast::*;
 use super::docs::*;
 
+/// Warns when the left-hand side of `in` binds differently than it reads,
+/// as in `a && x in list` or `!x in list`.
+pub const LINT_IN_PRECEDENCE: &str = "in-precedence";
+
 /// Parse a token stream, in the form emitted by the indent processor, into
 /// an object tree.
 ///
@@ -26,6 +30,95 @@ where
     Parser::new(context, iter.into_iter()).parse_object_tree()
 }
 
+/// Parse a proc body which was collected as token trees by a parser with
+/// lazy proc bodies enabled.
+pub(crate) fn parse_proc_body(context: &Context, tokens: Vec<LocatedToken>) -> Result<Block, DMError> {
+    let mut parser = Parser::new(context, tokens.into_iter());
+    let block = parser.block(&LoopContext::None);
+    parser.require(block)
+}
+
+/// Parse a token stream into an object tree, dividing it at top-level file
+/// boundaries and parsing the pieces in parallel.
+///
+/// Only parsing is spread across threads. The whole stream is read before
+/// any piece is parsed, so preprocessing still runs serially on the calling
+/// thread; it cannot be split, as each file sees the macros of the files
+/// included before it.
+///
+/// The input should already have passed through the `IndentProcessor`.
+/// Module-level doc comments are not collected.
+pub fn parse_parallel<I>(context: &Context, iter: I, procs: bool) -> ObjectTree
+where
+    I: IntoIterator<Item=LocatedToken>,
+{
+    #[cfg(not(target_arch = "wasm32"))]
+    use rayon::prelude::*;
+
+    // there are no threads to spread the work over on the web
+    #[cfg(not(target_arch = "wasm32"))]
+    let pieces = split_by_file(iter).into_par_iter();
+    #[cfg(target_arch = "wasm32")]
+    let pieces = split_by_file(iter).into_iter();
+
+    let fragments: Vec<(ObjectTree, u64, u64)> = pieces
+        .map(|tokens| {
+            let mut parser = Parser::new(context, tokens.into_iter());
+            parser.procs = procs;
+            let root = parser.root();
+            if let Err(e) = parser.require(root) {
+                context.register_error(e);
+            }
+            (parser.tree, parser.procs_good, parser.procs_bad)
+        })
+        .collect();
+
+    let mut parser = Parser::new(context, ::std::iter::empty());
+    parser.tree.register_builtins();
+    parser.tree.register_custom_builtins(context);
+    for (tree, good, bad) in fragments {
+        parser.tree.merge(tree);
+        parser.procs_good += good;
+        parser.procs_bad += bad;
+    }
+    parser.finalize_object_tree()
+}
+
+/// Split a token stream wherever it moves to another file between two
+/// top-level statements.
+fn split_by_file<I>(iter: I) -> Vec<Vec<LocatedToken>>
+where
+    I: IntoIterator<Item=LocatedToken>,
+{
+    let mut chunks = Vec::new();
+    let mut current: Vec<LocatedToken> = Vec::new();
+    let mut file = None;
+    let mut depth = 0usize;
+    for token in iter {
+        let at_boundary = depth == 0 && match current.last() {
+            Some(&LocatedToken { token: Token::Punct(Punctuation::Semicolon), .. }) |
+            Some(&LocatedToken { token: Token::Punct(Punctuation::RBrace), .. }) => true,
+            _ => false,
+        };
+        if at_boundary && file != Some(token.location.file) {
+            chunks.push(::std::mem::replace(&mut current, Vec::new()));
+        }
+        if current.is_empty() {
+            file = Some(token.location.file);
+        }
+        match token.token {
+            Token::Punct(Punctuation::LBrace) => depth += 1,
+            Token::Punct(Punctuation::RBrace) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        current.push(token);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
 type Ident = String;
 
 // ----------------------------------------------------------------------------
@@ -164,6 +257,13 @@ impl Op {
             Op::AssignOp(op) => Expression::AssignOp { op, lhs, rhs },
         }
     }
+
+    fn name(self) -> String {
+        match self {
+            Op::BinaryOp(op) => op.to_string(),
+            Op::AssignOp(op) => op.to_string(),
+        }
+    }
 }
 
 macro_rules! oper_table {
@@ -205,11 +305,12 @@ oper_table! { BINARY_OPS;
     Pow {
         (BinaryOp, Pow),
     }
-    // * / %
+    // * / % %%
     Mul {
         (BinaryOp, Mul), //
         (BinaryOp, Div = Slash), //
         (BinaryOp, Mod),
+        (BinaryOp, FloatMod),
     }
     // + -
     Add {
@@ -251,7 +352,10 @@ oper_table! { BINARY_OPS;
         (BinaryOp, Or),
     }
     // ?               // ternary a ? b : c
-    // = += -= -= *= /= %= &= |= ^= <<= >>=
+    Ternary {
+        // handled specially by `ternary`
+    }
+    // = += -= -= *= /= %= %%= &= |= ^= <<= >>= :=
     Assign {
         (AssignOp, Assign),
         (AssignOp, AddAssign),
@@ -259,11 +363,13 @@ oper_table! { BINARY_OPS;
         (AssignOp, MulAssign),
         (AssignOp, DivAssign),
         (AssignOp, ModAssign),
+        (AssignOp, FloatModAssign),
         (AssignOp, BitAndAssign),
         (AssignOp, BitOrAssign),
         (AssignOp, BitXorAssign),
         (AssignOp, LShiftAssign),
         (AssignOp, RShiftAssign),
+        (AssignOp, AssignInto),
     }
     // "in" is special and has different precedence in different contexts
     In {
@@ -296,7 +402,8 @@ impl TTKind {
         match *token {
             Token::Punct(Punctuation::LParen) => Some(TTKind::Paren),
             Token::Punct(Punctuation::LBrace) => Some(TTKind::Brace),
-            Token::Punct(Punctuation::LBracket) => Some(TTKind::Bracket),
+            Token::Punct(Punctuation::LBracket) |
+            Token::Punct(Punctuation::SafeLBracket) => Some(TTKind::Bracket),
             _ => None,
         }
     }
@@ -335,7 +442,9 @@ pub struct Parser<'ctx, 'an, I> {
 
     input: I,
     eof: bool,
-    next: Option<Token>,
+    /// Tokens which have been put back or peeked at, with whether each is
+    /// fresh from the input rather than put back.
+    lookahead: VecDeque<(LocatedToken, bool)>,
     location: Location,
     expected: Vec<Cow<'static, str>>,
 
@@ -345,8 +454,12 @@ pub struct Parser<'ctx, 'an, I> {
     in_docs: usize,
 
     procs: bool,
+    lazy_procs: bool,
     procs_bad: u64,
     procs_good: u64,
+
+    /// How many blocks and expressions currently enclose the position.
+    depth: usize,
 }
 
 impl<'ctx, 'an, I> HasLocation for Parser<'ctx, 'an, I> {
@@ -368,7 +481,7 @@ where
 
             input,
             eof: false,
-            next: None,
+            lookahead: VecDeque::new(),
             location: Default::default(),
             expected: Vec::new(),
 
@@ -378,8 +491,11 @@ where
             in_docs: 0,
 
             procs: false,
+            lazy_procs: false,
             procs_bad: 0,
             procs_good: 0,
+
+            depth: 0,
         }
     }
 
@@ -387,6 +503,16 @@ where
         self.procs = true;
     }
 
+    /// Keep proc bodies as token trees to be parsed on demand by
+    /// `ProcValue::body`, rather than parsing them now.
+    ///
+    /// Has no effect if proc bodies are parsed eagerly. Syntax errors in
+    /// lazy bodies are only reported once they are parsed, and `set`
+    /// statements in them do not apply to the proc's `settings`.
+    pub fn enable_lazy_procs(&mut self) {
+        self.lazy_procs = true;
+    }
+
     pub fn annotate_to(&mut self, annotations: &'an mut AnnotationTree) {
         self.annotations = Some(annotations);
         self.procs = true;
@@ -404,10 +530,22 @@ where
 
     pub fn run(&mut self) {
         self.tree.register_builtins();
+        self.tree.register_custom_builtins(self.context);
+        let root = self.root();
+        if let Err(e) = self.require(root) {
+            self.context.register_error(e);
+        }
+    }
+
+    /// Parse the input into a fragment of an object tree for
+    /// `ObjectTree::replace_file`, without registering the builtins or
+    /// finalizing it.
+    pub fn parse_fragment(mut self) -> ObjectTree {
         let root = self.root();
         if let Err(e) = self.require(root) {
             self.context.register_error(e);
         }
+        self.tree
     }
 
     pub fn take_module_docs(&mut self) -> BTreeMap<FileId, Vec<(u32, DocComment)>> {
@@ -425,7 +563,7 @@ where
             );
         }
 
-        let sloppy = self.context.errors().iter().any(|p| p.severity() == Severity::Error);
+        let sloppy = self.context.has_errors_at(Severity::Error);
         self.tree.finalize(self.context, sloppy);
         self.tree
     }
@@ -436,14 +574,18 @@ where
     // Call this to get a DMError in the event of an entry point returning None
     fn describe_parse_error(&mut self) -> DMError {
         let expected = self.expected.join(", ");
-        if self.eof {
+        if self.eof && self.lookahead.iter().all(|&(ref entry, _)| entry.token == Token::Eof) {
             return self.error(format!("got EOF, expected one of: {}", expected));
         }
         match self.next("") {
             Ok(got) => {
                 let message = format!("got '{}', expected one of: {}", got, expected);
+                let error = match got.source_len() {
+                    Some(len) => self.error_spanning(len, message),
+                    None => self.error(message),
+                };
                 self.put_back(got);
-                self.error(message)
+                error
             }
             Err(err) => self
                 .error(format!("i/o error, expected one of: {}", expected))
@@ -451,6 +593,11 @@ where
         }
     }
 
+    /// An error spanning `len` bytes from the start of the last token read.
+    fn error_spanning<S: Into<String>>(&self, len: usize, message: S) -> DMError {
+        self.error(message).set_end(self.location.add_columns(len))
+    }
+
     fn parse_error<T>(&mut self) -> Result<T, DMError> {
         Err(self.describe_parse_error())
     }
@@ -463,12 +610,19 @@ where
         }
     }
 
-    fn next<S: Into<Cow<'static, str>>>(&mut self, expected: S) -> Result<Token, DMError> {
-        let tok = loop {
-            if let Some(next) = self.next.take() {
-                break Ok(next);
-            }
-            match self.input.next() {
+    /// Read the next token from the input, collecting any doc comments.
+    ///
+    /// Returns the token and whether it is real, as opposed to a marker for
+    /// the end of the input.
+    fn read_input(&mut self) -> Result<(LocatedToken, bool), DMError> {
+        loop {
+            // a cancelled parse ends as if the input ended here
+            let next = if self.context.is_cancelled() {
+                None
+            } else {
+                self.input.next()
+            };
+            match next {
                 Some(LocatedToken {
                     location,
                     token: Token::DocComment(dc),
@@ -482,33 +636,59 @@ where
                     DocTarget::EnclosingItem => self.docs_enclosing.push(dc),
                     DocTarget::FollowingItem => self.docs_following.push(dc),
                 },
-                Some(token) => {
-                    self.expected.clear();
-                    self.location = token.location;
-                    break Ok(token.token);
-                }
+                Some(token) => return Ok((token, true)),
                 None => {
                     if !self.eof {
                         self.eof = true;
-                        break Ok(Token::Eof);
+                        return Ok((LocatedToken::new(self.location, Token::Eof), false));
                     } else {
-                        break self.parse_error();
+                        return self.parse_error();
                     }
                 }
             }
+        }
+    }
+
+    fn next<S: Into<Cow<'static, str>>>(&mut self, expected: S) -> Result<Token, DMError> {
+        let (token, fresh) = match self.lookahead.pop_front() {
+            Some(entry) => entry,
+            None => self.read_input()?,
         };
+        if fresh {
+            self.expected.clear();
+            self.location = token.location;
+        }
         let what = expected.into();
         if !what.is_empty() && !self.expected.contains(&what) {
             self.expected.push(what);
         }
-        tok
+        Ok(token.token)
     }
 
     fn put_back(&mut self, tok: Token) {
-        if self.next.is_some() {
-            panic!("cannot put_back twice")
+        self.lookahead.push_front((LocatedToken::new(self.location, tok), false));
+    }
+
+    /// Look at the next token without consuming it.
+    fn peek(&mut self) -> Result<&Token, DMError> {
+        self.peek_nth(0)
+    }
+
+    /// Look at the token after the next one without consuming either.
+    fn peek2(&mut self) -> Result<&Token, DMError> {
+        self.peek_nth(1)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Result<&Token, DMError> {
+        while self.lookahead.len() <= n {
+            let entry = if self.eof {
+                (LocatedToken::new(self.location, Token::Eof), false)
+            } else {
+                self.read_input()?
+            };
+            self.lookahead.push_back(entry);
         }
-        self.next = Some(tok);
+        Ok(&self.lookahead[n].0.token)
     }
 
     fn updated_location(&mut self) -> Location {
@@ -609,8 +789,9 @@ where
             Token::Punct(p @ Punctuation::CloseColon) |
             Token::Punct(p @ Punctuation::Colon) => {
                 spurious_lead = true;
-                self.context.register_error(self.error(format!("path started by '{}', should be unprefixed", p))
-                    .set_severity(Severity::Warning));
+                self.context.register_error(self.error_spanning(p.to_string().len(), format!("path started by '{}', should be unprefixed", p))
+                    .set_severity(Severity::Warning)
+                    .add_fix(Fix::replace_char(self.location, "", format!("remove '{}'", p))));
             }
             t => { self.put_back(t); }
         }
@@ -623,7 +804,7 @@ where
             Some(i) => parts.push(i),
             None if !(absolute || spurious_lead) => return Ok(None),
             None => {
-                slash_loc.column += 1;
+                slash_loc = slash_loc.add_columns(1);
                 self.annotate_precise(slash_loc..slash_loc, || {
                     Annotation::IncompleteTreePath(absolute, parts.clone())
                 });
@@ -638,8 +819,9 @@ where
                 Token::Punct(p @ Punctuation::Dot) |
                 Token::Punct(p @ Punctuation::CloseColon) |
                 Token::Punct(p @ Punctuation::Colon) => {
-                    self.context.register_error(self.error(format!("path separated by '{}', should be '/'", p))
-                        .set_severity(Severity::Warning));
+                    self.context.register_error(self.error_spanning(p.to_string().len(), format!("path separated by '{}', should be '/'", p))
+                        .set_severity(Severity::Warning)
+                        .add_fix(Fix::replace_char(self.location, "/", format!("replace '{}' with '/'", p))));
                 }
                 t => { self.put_back(t); break; }
             }
@@ -647,7 +829,7 @@ where
             if let Some(i) = self.ident_in_seq(parts.len())? {
                 parts.push(i);
             } else {
-                slash_loc.column += 1;
+                slash_loc = slash_loc.add_columns(1);
                 self.annotate_precise(slash_loc..slash_loc, || {
                     Annotation::IncompleteTreePath(absolute, parts.clone())
                 });
@@ -744,9 +926,51 @@ where
                     SUCCESS
                 }));
 
+                let code = if self.procs {
+                    let result = {
+                        let mut subparser: Parser<'ctx, '_, _> = Parser::new(self.context, body_tt.into_iter());
+                        if let Some(a) = self.annotations.as_mut() {
+                            subparser.annotations = Some(&mut *a);
+                        }
+                        subparser.depth = self.depth;
+                        let block = subparser.block(&LoopContext::None);
+                        subparser.require(block)
+                    };
+                    match result {
+                        Ok(block) => {
+                            self.procs_good += 1;
+                            Code::Present(block)
+                        }
+                        Err(err) => {
+                            self.procs_bad += 1;
+                            self.context.register_error(err);
+                            Code::Invalid
+                        }
+                    }
+                } else if self.lazy_procs {
+                    Code::Lazy(LazyCode::new(body_tt))
+                } else {
+                    Code::Disabled
+                };
+
                 match self.tree.add_proc(location, new_stack.iter(), new_stack.len(), parameters) {
                     Ok((idx, proc)) => {
                         proc.docs.extend(comment);
+                        if let Code::Present(ref block) = code {
+                            for stmt in block.iter() {
+                                if let Statement::Setting(ref name, ref mode, ref value) = stmt.elem {
+                                    // invalid modes are reported while parsing the statement
+                                    if *mode == SettingMode::In && name != "src" {
+                                        continue;
+                                    }
+                                    if let Err(problem) = proc.settings.apply(name, mode, value) {
+                                        self.context.register_error(DMError::new(location, format!("bad 'set {}': {}", name, problem))
+                                            .set_severity(Severity::Warning));
+                                    }
+                                }
+                            }
+                        }
+                        proc.code = code;
                         // manually performed for borrowck reasons
                         if let Some(dest) = self.annotations.as_mut() {
                             dest.insert(entry_start..body_start, Annotation::ProcHeader(new_stack.to_vec(), idx));
@@ -756,31 +980,15 @@ where
                     Err(e) => self.context.register_error(e),
                 };
 
-                if self.procs {
-                    let result = {
-                        let mut subparser: Parser<'ctx, '_, _> = Parser::new(self.context, body_tt.into_iter());
-                        if let Some(a) = self.annotations.as_mut() {
-                            subparser.annotations = Some(&mut *a);
-                        }
-                        let block = subparser.block(&LoopContext::None);
-                        subparser.require(block)
-                    };
-                    if result.is_ok() {
-                        self.procs_good += 1;
-                    } else {
-                        self.procs_bad += 1;
-                    }
-                    if let Err(err) = result {
-                        self.context.register_error(err);
-                    }
-                }
                 SUCCESS
             }
             other => {
                 // usually `thing;` - a contentless declaration
                 // TODO: allow enclosing-targeting docs here somehow?
                 let comment = ::std::mem::replace(&mut self.docs_following, Default::default());
-                if let Err(e) = self.tree.add_entry(self.location, new_stack.iter(), new_stack.len(), comment) {
+                // the token after the path may be in another file if this is
+                // the last line of an include, so use where the path began
+                if let Err(e) = self.tree.add_entry(entry_start, new_stack.iter(), new_stack.len(), comment) {
                     self.context.register_error(e);
                 }
                 self.put_back(other);
@@ -828,6 +1036,7 @@ where
             None
         };
         let (input_type, in_list) = require!(self.input_specifier());
+        self.annotate(leading_loc, || Annotation::ProcParameter(name.clone()));
         success(Parameter {
             path,
             name,
@@ -851,16 +1060,54 @@ where
                 continue;
             }
             self.put_back(next);
-            require!(self.tree_entry(parent));
+            let result = self.tree_entry(parent);
+            if let Err(e) = self.require(result) {
+                // record the error and carry on with the next entry, so that
+                // one bad definition does not hide the rest of the file
+                self.context.register_error(e);
+                self.skip_tree_entry();
+            }
         }
         SUCCESS
     }
 
+    /// Skip the remainder of a tree entry which failed to parse, stopping at
+    /// the start of the next entry at the same level.
+    fn skip_tree_entry(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            let token = match self.next("") {
+                Ok(token) => token,
+                Err(_) => return,
+            };
+            match token {
+                Token::Eof => {
+                    self.put_back(token);
+                    return;
+                }
+                Token::Punct(Punctuation::Semicolon) if depth == 0 => return,
+                Token::Punct(Punctuation::LBrace) => depth += 1,
+                Token::Punct(Punctuation::RBrace) => {
+                    if depth == 0 {
+                        // the end of the enclosing block
+                        self.put_back(token);
+                        return;
+                    }
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
     fn tree_block(&mut self, parent: PathStack) -> Status<()> {
         leading!(self.exact(Token::Punct(Punctuation::LBrace)));
-        Ok(Some(require!(
-            self.tree_entries(parent, Token::Punct(Punctuation::RBrace))
-        )))
+        Ok(Some(require!(self.nested(|this| {
+            this.tree_entries(parent, Token::Punct(Punctuation::RBrace))
+        }))))
     }
 
     fn root(&mut self) -> Status<()> {
@@ -879,8 +1126,7 @@ where
         use super::lexer::Token::Punct;
         use super::lexer::Punctuation::*;
         // TODO: parse the declarations as expressions rather than giving up
-        while let Some(()) = self.exact(Punct(LBracket))? {
-            self.put_back(Punct(LBracket));
+        while *self.peek()? == Punct(LBracket) {
             require!(self.ignore_group(LBracket, RBracket));
         }
         SUCCESS
@@ -909,7 +1155,7 @@ where
         let mut as_what = match InputType::from_str(&ident) {
             Some(what) => what,
             None => {
-                self.context.register_error(self.error(format!("bad input type: '{}'", ident)));
+                self.context.register_error(self.error_spanning(ident.len(), format!("bad input type: '{}'", ident)));
                 InputType::default()
             }
         };
@@ -918,7 +1164,7 @@ where
             match InputType::from_str(&ident) {
                 Some(what) => as_what |= what,
                 None => {
-                    self.context.register_error(self.error(format!("bad input type: '{}'", ident)));
+                    self.context.register_error(self.error_spanning(ident.len(), format!("bad input type: '{}'", ident)));
                 }
             }
         }
@@ -926,7 +1172,11 @@ where
     }
 
     /// Parse a block
-    fn block(&mut self, loop_ctx: &LoopContext) -> Status<Vec<Statement>> {
+    fn block(&mut self, loop_ctx: &LoopContext) -> Status<Block> {
+        self.nested(|this| this.block_inner(loop_ctx))
+    }
+
+    fn block_inner(&mut self, loop_ctx: &LoopContext) -> Status<Block> {
         let mut vars = Vec::new();
         let result = if let Some(()) = self.exact(Token::Punct(Punctuation::LBrace))? {
             let mut statements = Vec::new();
@@ -936,7 +1186,9 @@ where
                 } else if let Some(()) = self.exact(Token::Punct(Punctuation::Semicolon))? {
                     continue;
                 } else {
-                    statements.push(require!(self.statement(loop_ctx, &mut vars)));
+                    let start = self.updated_location();
+                    let statement = require!(self.statement(loop_ctx, &mut vars));
+                    statements.push(Spanned::new(start, statement));
                 }
             }
             statements
@@ -945,8 +1197,9 @@ where
             Vec::new()
         } else {
             // and one-line blocks: if(1) neat();
+            let start = self.updated_location();
             let statement = require!(self.statement(loop_ctx, &mut vars));
-            vec![statement]
+            vec![Spanned::new(start, statement)]
         };
         for (loc, var_type, name) in vars {
             self.annotate(loc, || Annotation::LocalVarScope(var_type, name));
@@ -1002,14 +1255,64 @@ where
             // for (Init, Test, Inc) Statement
             // for (Var in Low to High)
             // for (Var = Low to High)
+            // for () Statement
             require!(self.exact(Token::Punct(Punctuation::LParen)));
+            if let Some(()) = self.exact(Token::Punct(Punctuation::RParen))? {
+                // an infinite loop
+                return success(Statement::ForLoop {
+                    init: None,
+                    test: None,
+                    inc: None,
+                    block: require!(self.block(&LoopContext::ForLoop)),
+                });
+            }
             let init = self.simple_statement(true, vars)?;
-            if let Some(()) = self.comma_or_semicolon()? {
+            if let Some(first_sep) = self.for_separator()? {
                 // three-pronged loop form ("for loop")
-                let test = self.expression()?;
-                require!(self.comma_or_semicolon());
-                let inc = self.simple_statement(false, vars)?;
-                require!(self.exact(Token::Punct(Punctuation::RParen)));
+                let mut clauses = Vec::new();
+                let mut seps = vec![first_sep];
+                loop {
+                    clauses.push((self.location, self.simple_statement(false, vars)?));
+                    if let Some(()) = self.exact(Token::Punct(Punctuation::RParen))? {
+                        break;
+                    }
+                    seps.push(require!(self.for_separator()));
+                }
+
+                // for(var/a = 1, b = 2; a < b; a++)
+                // When the clauses are separated by semicolons, any commas
+                // before the first one continue the declaration.
+                let mut init = init;
+                if let Some(first_semi) = seps.iter().position(|&p| p == Punctuation::Semicolon) {
+                    if first_semi > 0 {
+                        let mut var_stmts = match init {
+                            Some(Statement::Var(var)) => vec![var],
+                            Some(Statement::Vars(var_stmts)) => var_stmts,
+                            _ => return Err(self.error("for-loop initializer may only contain ',' when declaring vars")),
+                        };
+                        for (location, clause) in clauses.drain(..first_semi) {
+                            let var = match clause {
+                                Some(clause) => self.for_declarator(clause, location)?,
+                                None => return Err(self.error("expected a var name")),
+                            };
+                            if self.annotations.is_some() {
+                                vars.push((location, var.var_type.clone(), var.name.clone()));
+                            }
+                            var_stmts.push(var);
+                        }
+                        init = Some(Statement::Vars(var_stmts));
+                    }
+                }
+                if clauses.len() != 2 {
+                    return Err(self.error("for-loop must have exactly three clauses"));
+                }
+
+                let inc = clauses.pop().unwrap().1;
+                let test = match clauses.pop().unwrap().1 {
+                    Some(Statement::Expr(expr)) => Some(expr),
+                    None => None,
+                    Some(_) => return Err(self.error("for-loop condition must be an expression")),
+                };
                 success(Statement::ForLoop {
                     init: init.map(Box::new),
                     test,
@@ -1024,6 +1327,7 @@ where
                         var_type,
                         name,
                         value: Some(value),
+                        ..
                     }) => {
                         // for(var/a = 1 to
                         require!(self.exact_ident("to"));
@@ -1034,7 +1338,22 @@ where
                         var_type,
                         name,
                         value: None,
+                        ..
                     }) => (Some(var_type), name),
+                    Statement::Expr(Expression::AssignOp {
+                        op: AssignOp::Assign,
+                        lhs,
+                        rhs,
+                    }) => {
+                        // for(a = 1 to
+                        let name = match lhs.into_term() {
+                            Some(Term::Ident(name)) => name,
+                            _ => return Err(self.error("for-list must start with variable")),
+                        };
+                        require!(self.exact_ident("to"));
+                        let end = require!(self.expression());
+                        return success(require!(self.for_range(None, name, *rhs, end)));
+                    }
                     Statement::Expr(Expression::BinaryOp {
                         op: BinaryOp::In,
                         lhs,
@@ -1137,19 +1456,29 @@ where
             let try_block = require!(self.block(loop_ctx));
             self.skip_phantom_semicolons()?;
             require!(self.exact_ident("catch"));
-            let catch_params;
+            // catch :: 'catch' ('(' ('var' type_path | ident)? ')')?
+            let mut catch_param = None;
             if let Some(()) = self.exact(Token::Punct(Punctuation::LParen))? {
-                catch_params = require!(self.separated(Punctuation::Comma, Punctuation::RParen, None, |this| {
-                    // TODO: improve upon this cheap approximation
-                    success(leading!(this.tree_path()).1)
-                }));
-            } else {
-                catch_params = Vec::new();
+                if let Some(()) = self.exact_ident("var")? {
+                    let (_, mut tree_path) = require!(self.tree_path());
+                    let name = match tree_path.pop() {
+                        Some(name) => name,
+                        None => return Err(self.error("'var' must be followed by a name")),
+                    };
+                    let var_type = tree_path.into_iter().collect::<VarType>();
+                    if self.annotations.is_some() {
+                        vars.push((self.location, var_type.clone(), name.clone()));
+                    }
+                    catch_param = Some((Some(var_type), name));
+                } else if let Some(name) = self.ident()? {
+                    catch_param = Some((None, name));
+                }
+                require!(self.exact(Token::Punct(Punctuation::RParen)));
             }
             let catch_block = require!(self.block(loop_ctx));
             success(Statement::TryCatch {
                 try_block,
-                catch_params,
+                catch_param,
                 catch_block,
             })
         // SINGLE-LINE STATEMENTS
@@ -1164,7 +1493,13 @@ where
             };
             let value = require!(self.expression());
             require!(self.statement_terminator());
-            // TODO: warn on weird values for these
+            if !ProcSettings::is_known(&name) && !ProcSettings::is_directive(&name) {
+                self.context.register_error(self.error(format!("unknown setting 'set {}'", name))
+                    .set_severity(Severity::Warning));
+            } else if mode == SettingMode::In && name != "src" {
+                self.context.register_error(self.error(format!("'set {} in' is only meaningful for src", name))
+                    .set_severity(Severity::Warning));
+            }
             success(Statement::Setting(name, mode, value))
         } else if let Some(()) = self.exact_ident("break")? {
             let label = self.ident()?;
@@ -1174,19 +1509,27 @@ where
             let label = self.ident()?;
             require!(self.statement_terminator());
             success(Statement::Continue(label))
+        } else if let Some(()) = self.exact_ident("goto")? {
+            // statement :: 'goto' ident ';'
+            let label = require!(self.ident());
+            require!(self.statement_terminator());
+            success(Statement::Goto(label))
         } else {
-            let result = leading!(self.simple_statement(false, vars));
-
-            // check for a label `ident:`
-            if let Statement::Expr(ref expr) = result {
-                if let Some(Term::Ident(ref name)) = expr.as_term() {
-                    if let Some(()) = self.exact(Token::Punct(Punctuation::Colon))? {
-                        // it's a label! check for a block
-                        return success(Statement::Label(name.to_owned(), require!(self.block(loop_ctx))));
-                    }
-                }
+            // statement :: ident ':' block
+            // Only a colon directly after a lone identifier makes a label;
+            // one inside a ternary is consumed as part of the expression.
+            let starts_ident = match *self.peek()? {
+                Token::Ident(..) => true,
+                _ => false,
+            };
+            let is_label = starts_ident && *self.peek2()? == Token::Punct(Punctuation::Colon);
+            if is_label {
+                let name = require!(self.ident());
+                require!(self.exact(Token::Punct(Punctuation::Colon)));
+                return success(Statement::Label(name, require!(self.block(loop_ctx))));
             }
 
+            let result = leading!(self.simple_statement(false, vars));
             require!(self.statement_terminator());
             success(result)
         }
@@ -1242,8 +1585,9 @@ where
                         .set_severity(Severity::Warning));
                 }
 
+                let location = self.location;
                 if self.annotations.is_some() {
-                    vars.push((self.location, var_type.clone(), name.clone()));
+                    vars.push((location, var_type.clone(), name.clone()));
                 }
 
                 let value = if let Some(()) = self.exact(Token::Punct(Punctuation::Assign))? {
@@ -1261,7 +1605,7 @@ where
                         .set_severity(Severity::Warning));
                 }
 
-                var_stmts.push(VarStatement { var_type, name, value });
+                var_stmts.push(VarStatement { var_type, name, value, location });
                 if in_for || self.exact(Token::Punct(Punctuation::Comma))?.is_none() {
                     break;
                 }
@@ -1280,6 +1624,28 @@ where
             let expression = require!(self.expression());
             success(Statement::Throw(expression))
         // EXPRESSION STATEMENTS
+        } else if in_for {
+            // statement :: expression ('in' expression ('to' expression)?)?
+            // Here `in` introduces the list being iterated rather than acting
+            // as an operator, so its right side is a whole expression.
+            let lhs = leading!(self.expression_weakest(false, Strength::Assign));
+            if let Some(()) = self.exact(Token::Punct(Punctuation::In))? {
+                let mut rhs = require!(self.expression());
+                if let Some(()) = self.exact_ident("to")? {
+                    rhs = Expression::BinaryOp {
+                        op: BinaryOp::To,
+                        lhs: Box::new(rhs),
+                        rhs: Box::new(require!(self.expression())),
+                    };
+                }
+                success(Statement::Expr(Expression::BinaryOp {
+                    op: BinaryOp::In,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                }))
+            } else {
+                success(Statement::Expr(lhs))
+            }
         } else {
             // statement :: expression ';'
             let expr = leading!(self.expression());
@@ -1315,16 +1681,36 @@ where
         })
     }
 
-    fn comma_or_semicolon(&mut self) -> Status<()> {
+    fn for_separator(&mut self) -> Status<Punctuation> {
         if let Some(()) = self.exact(Token::Punct(Punctuation::Comma))? {
-            SUCCESS
+            success(Punctuation::Comma)
         } else if let Some(()) = self.exact(Token::Punct(Punctuation::Semicolon))? {
-            SUCCESS
+            success(Punctuation::Semicolon)
         } else {
             Ok(None)
         }
     }
 
+    /// Reinterpret a clause following `var/a,` in a for-loop initializer as
+    /// the declaration of another var.
+    fn for_declarator(&self, clause: Statement, location: Location) -> Result<VarStatement, DMError> {
+        let (name, value) = match clause {
+            Statement::Var(var) => return Ok(var),
+            Statement::Expr(Expression::AssignOp { op: AssignOp::Assign, lhs, rhs }) => {
+                match lhs.into_term() {
+                    Some(Term::Ident(name)) => (name, Some(*rhs)),
+                    _ => return Err(self.error("expected a var name")),
+                }
+            }
+            Statement::Expr(expr) => match expr.into_term() {
+                Some(Term::Ident(name)) => (name, None),
+                _ => return Err(self.error("expected a var name")),
+            },
+            _ => return Err(self.error("expected a var name")),
+        };
+        Ok(VarStatement { var_type: VarType::default(), name, value, location })
+    }
+
     fn case(&mut self) -> Status<Case> {
         let first = require!(self.expression());
         if let Some(()) = self.exact_ident("to")? {
@@ -1352,6 +1738,14 @@ where
         self.prefab_ex(Vec::new())
     }
 
+    /// Parse a prefab, annotating its whole span.
+    fn annotated_prefab(&mut self) -> Status<Prefab> {
+        let start = self.updated_location();
+        let prefab = leading!(self.prefab());
+        self.annotate(start, || Annotation::Prefab(prefab.clone()));
+        success(prefab)
+    }
+
     fn prefab_ex(&mut self, mut parts: TypePath) -> Status<Prefab> {
         // path :: path_sep ident (path_sep ident?)*
         // path_sep :: '/' | '.' | ':'
@@ -1367,7 +1761,7 @@ where
         if let Some(ident) = self.ident_in_seq(parts.len())? {
             parts.push((sep, ident));
         } else {
-            separator_loc.column += 1;
+            separator_loc = separator_loc.add_columns(1);
             self.annotate_precise(separator_loc..separator_loc, || {
                 Annotation::IncompleteTypePath(parts.clone(), sep)
             });
@@ -1379,7 +1773,7 @@ where
             if let Some(ident) = self.ident_in_seq(parts.len())? {
                 parts.push((sep, ident));
             } else {
-                separator_loc.column += 1;
+                separator_loc = separator_loc.add_columns(1);
                 self.annotate_precise(separator_loc..separator_loc, || {
                     Annotation::IncompleteTypePath(parts.clone(), sep)
                 });
@@ -1414,39 +1808,82 @@ where
     }
 
     fn expression_ex(&mut self, in_ternary: bool) -> Status<Expression> {
+        self.expression_weakest(in_ternary, Strength::In)
+    }
+
+    /// Parse an expression containing no operators weaker than `weakest`.
+    fn expression_weakest(&mut self, in_ternary: bool, weakest: Strength) -> Status<Expression> {
         let mut expr = leading!(self.group(in_ternary));
+        // the operator at the top of `expr`, if it binds loosely
+        let mut loose = None;
         loop {
             // try to read the next operator
             let next = self.next("operator")?;
+            if next == Token::Punct(Punctuation::QuestionMark) && Strength::Ternary <= weakest {
+                expr = require!(self.ternary(expr, in_ternary));
+                loose = Some("? :".to_owned());
+                continue;
+            }
             let &info = match BINARY_OPS.iter().find(|op| op.matches(&next)) {
-                Some(info) => info,
-                None => {
+                Some(info) if info.strength <= weakest => info,
+                _ => {
                     self.put_back(next);
                     break;
                 }
             };
+            if info.token == Punctuation::In {
+                self.check_in_lhs(&expr, loose.take());
+            }
 
             // trampoline high-strength expression parts as the lhs of the newly found op
             expr = require!(self.expression_part(expr, info, in_ternary));
+            loose = if info.strength >= Strength::Compare {
+                Some(info.oper.name())
+            } else {
+                None
+            };
         }
 
-        // TODO: this needs to be worked into the precedence table somehow
-        if let Some(()) = self.exact(Token::Punct(Punctuation::QuestionMark))? {
-            let if_ = require!(self.expression_ex(true));
-            match self.next("':'")? {
-                Token::Punct(Punctuation::Colon) |
-                Token::Punct(Punctuation::CloseColon) => {}
-                _ => return self.parse_error(),
+        success(expr)
+    }
+
+    /// Warn about the left-hand side of an `in` when BYOND's parse differs
+    /// from the intuitive one.
+    fn check_in_lhs(&self, lhs: &Expression, loose: Option<String>) {
+        if !self.context.lint_enabled(LINT_IN_PRECEDENCE) {
+            return;
+        }
+        if let Some(op) = loose {
+            self.context.register_error(self.error(format!(
+                "`in` binds more loosely than `{}`, so `a {} x in b` means `(a {} x) in b`; add parentheses to clarify",
+                op, op, op)).set_severity(Severity::Warning));
+        } else if let Expression::Base { ref unary, .. } = *lhs {
+            if unary.contains(&UnaryOp::Not) {
+                self.context.register_error(self.error(
+                    "`!x in y` means `(!x) in y`; use `!(x in y)` to negate the result"
+                ).set_severity(Severity::Warning));
             }
-            let else_ = require!(self.expression());
-            expr = Expression::TernaryOp {
-                cond: Box::new(expr),
-                if_: Box::new(if_),
-                else_: Box::new(else_),
-            };
         }
+    }
 
-        success(expr)
+    /// Parse the remainder of a ternary `cond ? if_ : else_` after the `?`.
+    ///
+    /// The `else_` branch may itself be a ternary, making the operator
+    /// right-associative. Within the `if_` branch, a `:` ends the branch
+    /// rather than being read as a path operator.
+    fn ternary(&mut self, cond: Expression, in_ternary: bool) -> Status<Expression> {
+        let if_ = require!(self.expression_ex(true));
+        match self.next("':'")? {
+            Token::Punct(Punctuation::Colon) |
+            Token::Punct(Punctuation::CloseColon) => {}
+            _ => return self.parse_error(),
+        }
+        let else_ = require!(self.expression_weakest(in_ternary, Strength::Ternary));
+        success(Expression::TernaryOp {
+            cond: Box::new(cond),
+            if_: Box::new(if_),
+            else_: Box::new(else_),
+        })
     }
 
     fn expression_part(&mut self, lhs: Expression, prev_op: OpInfo, in_ternary: bool) -> Status<Expression> {
@@ -1455,9 +1892,21 @@ where
         let mut bits = vec![lhs];
         let mut ops = vec![prev_op.oper];
         let mut rhs = require!(self.group(in_ternary));
+        // the operator at the top of `rhs`, if it binds loosely
+        let mut loose = None;
         loop {
             // try to read the next operator...
             let next = self.next("operator")?;
+            if next == Token::Punct(Punctuation::QuestionMark) {
+                if prev_op.strength > Strength::Ternary {
+                    // the ternary is stronger than us... it takes the rhs
+                    rhs = require!(self.ternary(rhs, in_ternary));
+                    loose = Some("? :".to_owned());
+                    continue;
+                }
+                self.put_back(next);
+                break;
+            }
             let &info = match BINARY_OPS.iter().find(|op| op.matches(&next)) {
                 Some(info) => info,
                 None => {
@@ -1471,6 +1920,9 @@ where
                 Ordering::Less => {
                     // the operator is stronger than us... recurse down
                     rhs = require!(self.expression_part(rhs, info, in_ternary));
+                    if info.strength >= Strength::Compare {
+                        loose = Some(info.oper.name());
+                    }
                 }
                 Ordering::Greater => {
                     // the operator is weaker than us... return up
@@ -1482,13 +1934,18 @@ where
                     ops.push(info.oper);
                     bits.push(rhs);
                     rhs = require!(self.group(in_ternary));
+                    loose = None;
                 }
             }
         }
 
-        // Handle ternary ops... they should have their own precedence or else.
-        // TODO: A?B:C should probably be handled here as well.
+        // Handle ternary ops.
         if prev_op.token == Punctuation::In {
+            if let Some(op) = loose {
+                self.context.register_error(self.error(format!(
+                    "`in` binds more loosely than `{}`, so `x in a {} b` means `x in (a {} b)`; add parentheses to clarify",
+                    op, op, op)).set_severity(Severity::Warning));
+            }
             // "in" is optionally ternary: (x in 1 to 5)
             if let Some(()) = self.exact_ident("to")? {
                 rhs = Expression::BinaryOp {
@@ -1521,6 +1978,10 @@ where
 
     // parse an Expression::Base (unary ops, term, follows)
     fn group(&mut self, in_ternary: bool) -> Status<Expression> {
+        self.nested(|this| this.group_inner(in_ternary))
+    }
+
+    fn group_inner(&mut self, in_ternary: bool) -> Status<Expression> {
         // read unary ops
         let mut unary_ops = Vec::new();
         loop {
@@ -1585,7 +2046,7 @@ where
                 // try to read an ident or path
                 let t = if let Some(ident) = self.ident()? {
                     NewType::Ident(ident)
-                } else if let Some(path) = self.prefab()? {
+                } else if let Some(path) = self.annotated_prefab()? {
                     NewType::Prefab(path)
                 } else {
                     NewType::Implicit
@@ -1616,6 +2077,12 @@ where
                 require!(self.arguments(&[], "call*")),
             ),
 
+            // term :: 'call_ext' arglist arglist
+            Token::Ident(ref i, _) if i == "call_ext" => Term::ExternalCall(
+                require!(self.arguments(&[], "call_ext")),
+                require!(self.arguments(&[], "call_ext*")),
+            ),
+
             // term :: 'input' arglist input_specifier
             Token::Ident(ref i, _) if i == "input" => match self.arguments(&[], "input")? {
                 Some(args) => {
@@ -1663,6 +2130,7 @@ where
                 match self.arguments(&[], &i)? {
                     Some(args) => {
                         self.annotate_precise(start..first_token, || Annotation::UnscopedCall(i.clone()));
+                        self.annotate(start, || Annotation::ProcCall(Vec::new(), i.clone()));
                         Term::Call(i, args)
                     },
                     None => {
@@ -1676,7 +2144,9 @@ where
             // term :: '..' arglist
             Token::Punct(Punctuation::Super) => {
                 self.annotate(start, || Annotation::ParentCall);
-                Term::ParentCall(require!(self.arguments(&[], "..")))
+                let args = require!(self.arguments(&[], ".."));
+                self.annotate(start, || Annotation::ProcCall(Vec::new(), "..".to_owned()));
+                Term::ParentCall(args)
             },
 
             // term :: '.'
@@ -1685,13 +2155,15 @@ where
                 if let Some(ident) = self.ident()? {
                     // prefab
                     // TODO: arrange for this ident to end up in the prefab's annotation
-                    Term::Prefab(require!(self.prefab_ex(vec![(PathOp::Dot, ident)])))
+                    let prefab = require!(self.prefab_ex(vec![(PathOp::Dot, ident)]));
+                    self.annotate(start, || Annotation::Prefab(prefab.clone()));
+                    Term::Prefab(prefab)
                 } else if let Some(args) = self.arguments(&[], ".")? {
                     // .() call
                     Term::SelfCall(args)
                 } else {
                     // bare dot
-                    dot_loc.column += 1;
+                    dot_loc = dot_loc.add_columns(1);
                     self.annotate_precise(dot_loc..dot_loc, || {
                         Annotation::IncompleteTypePath(Vec::new(), PathOp::Dot)
                     });
@@ -1703,7 +2175,7 @@ where
             t @ Token::Punct(Punctuation::Slash) |
             t @ Token::Punct(Punctuation::CloseColon) => {
                 self.put_back(t);
-                Term::Prefab(require!(self.prefab()))
+                Term::Prefab(require!(self.annotated_prefab()))
             },
 
             // term :: str_lit | num_lit
@@ -1722,13 +2194,16 @@ where
             Token::InterpStringBegin(begin) => {
                 let mut parts = Vec::new();
                 loop {
+                    let expr_start = self.updated_location();
                     let expr = self.expression()?;
+                    let index = parts.len();
+                    self.annotate(expr_start, || Annotation::Interpolation(index));
                     match self.next("']'")? {
                         Token::InterpStringPart(part) => {
-                            parts.push((expr, part));
+                            parts.push((expr, part, expr_start));
                         },
                         Token::InterpStringEnd(end) => {
-                            parts.push((expr, end));
+                            parts.push((expr, end, expr_start));
                             break;
                         },
                         _ => return self.parse_error(),
@@ -1750,6 +2225,13 @@ where
                 require!(self.exact(Token::Punct(Punctuation::RBracket)));
                 success(Follow::Index(Box::new(expr)))
             }
+            // follow :: '?[' expression ']'
+            Token::Punct(Punctuation::SafeLBracket) => {
+                belongs_to.clear();
+                let expr = require!(self.expression());
+                require!(self.exact(Token::Punct(Punctuation::RBracket)));
+                success(Follow::SafeIndex(Box::new(expr)))
+            }
 
             // follow :: '.' ident arglist?
             // TODO: only apply these rules if there is no whitespace around the punctuation
@@ -1770,7 +2252,7 @@ where
         let ident = match self.ident()? {
             Some(ident) => ident,
             None => {
-                index_op_loc.column += kind.len() as u16;
+                index_op_loc = index_op_loc.add_columns(kind.len());
                 self.annotate_precise(index_op_loc..index_op_loc, || {
                     Annotation::ScopedMissingIdent(belongs_to.clone())
                 });
@@ -1785,6 +2267,7 @@ where
             Some(args) => {
                 if !belongs_to.is_empty() {
                     let past = ::std::mem::replace(belongs_to, Vec::new());
+                    self.annotate(start, || Annotation::ProcCall(past.clone(), ident.clone()));
                     self.annotate_precise(start..end, || Annotation::ScopedCall(past, ident.clone()));
                 }
                 Follow::Call(kind, ident, args)
@@ -1882,25 +2365,34 @@ where
 
     fn read_any_tt(&mut self, target: &mut Vec<LocatedToken>) -> Status<()> {
         // read a single arbitrary "token tree", either a group or a single token
-        let start = self.next("anything")?;
-        let kind = TTKind::from_token(&start);
-        target.push(LocatedToken::new(self.location(), start));
-        let kind = match kind {
-            Some(k) => k,
-            None => return SUCCESS,
-        };
+        let mut open = Vec::new();
         loop {
             let token = self.next("anything")?;
-            if kind.is_end(&token) {
-                target.push(LocatedToken::new(self.location(), token));
+            if open.last().map_or(false, |kind: &TTKind| kind.is_end(&token)) {
+                open.pop();
+            } else if let Some(kind) = TTKind::from_token(&token) {
+                open.push(kind);
+            }
+            target.push(LocatedToken::new(self.location(), token));
+            if open.is_empty() {
                 return SUCCESS;
-            } else {
-                self.put_back(token);
-                require!(self.read_any_tt(target));
             }
         }
     }
 
+    /// Run `f` one level deeper, failing if that would exceed the nesting
+    /// limit.
+    fn nested<R, F: FnOnce(&mut Self) -> Status<R>>(&mut self, f: F) -> Status<R> {
+        let limit = self.context.nesting_limit();
+        if self.depth >= limit {
+            return Err(self.error(format!("nesting exceeds the limit of {} levels", limit)));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
     fn ignore_group(&mut self, left: Punctuation, right: Punctuation) -> Status<()> {
         leading!(self.exact(Token::Punct(left)));
         let mut depth = 1;
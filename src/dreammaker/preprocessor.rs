@@ -1,14 +1,15 @@
 //! The preprocessor.
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::{io, fmt};
-use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::ops::Range;
 
 use interval_tree::{IntervalTree, range};
 
-use super::{DMError, Location, HasLocation, FileId, Context, Severity};
+use super::{DMError, Location, HasLocation, FileId, Context, Severity, Phase};
 use super::lexer::*;
 use super::docs::{DocComment, DocTarget, DocCollection};
+use super::annotation::{Annotation, AnnotationTree};
 
 // ----------------------------------------------------------------------------
 // Macro representation and predefined macros
@@ -43,6 +44,16 @@ impl fmt::Display for Define {
     }
 }
 
+/// Concatenate two tokens for the `##` operator, or return them unchanged if
+/// they cannot form a single token.
+fn paste_tokens(first: Token, second: Token) -> Result<Token, (Token, Token)> {
+    match (first, second) {
+        (Token::Ident(first, _), Token::Ident(second, ws)) => Ok(Token::Ident(format!("{}{}", first, second), ws)),
+        (Token::Ident(first, _), Token::Int(second)) if second >= 0 => Ok(Token::Ident(format!("{}{}", first, second), false)),
+        (first, second) => Err((first, second)),
+    }
+}
+
 /// An interval tree representing historic macro definitions.
 pub type DefineHistory = IntervalTree<Location, (String, Define)>;
 
@@ -72,6 +83,12 @@ impl DefineMap {
         self.inner.get(key).and_then(|v| v.last())
     }
 
+    /// Returns every definition of the key which is in effect, oldest
+    /// first.
+    pub fn get_all(&self, key: &str) -> &[(Location, Define)] {
+        self.inner.get(key).map_or(&[], |v| &v[..])
+    }
+
     /// Inserts a key-value pair into the map.
     ///
     /// Returns `None` if the key was not present, or its most recent location
@@ -126,9 +143,107 @@ impl DefineMap {
     }
 }
 
+// ----------------------------------------------------------------------------
+// The graph of #include relationships
+
+/// The `#include` relationships between files, as seen by the preprocessor.
+#[derive(Debug, Clone, Default)]
+pub struct IncludeGraph {
+    // (includer, included, location of the directive)
+    edges: Vec<(FileId, FileId, Location)>,
+}
+
+impl IncludeGraph {
+    /// Record that the file at `location` includes `included`.
+    pub fn add_include(&mut self, location: Location, included: FileId) {
+        self.edges.push((location.file, included, location));
+    }
+
+    /// Iterate over every `#include` as includer, included, and the location
+    /// of the directive.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item=(FileId, FileId, Location)> + 'a {
+        self.edges.iter().cloned()
+    }
+
+    /// The files directly included by the given file.
+    pub fn includes<'a>(&'a self, file: FileId) -> impl Iterator<Item=FileId> + 'a {
+        self.edges.iter().filter(move |e| e.0 == file).map(|e| e.1)
+    }
+
+    /// The files which directly include the given file.
+    pub fn included_by<'a>(&'a self, file: FileId) -> impl Iterator<Item=FileId> + 'a {
+        self.edges.iter().filter(move |e| e.1 == file).map(|e| e.0)
+    }
+
+    /// The files which include the given file directly or indirectly, and so
+    /// must be reprocessed when it changes.
+    pub fn dependents(&self, file: FileId) -> Vec<FileId> {
+        let mut included_by: HashMap<FileId, Vec<FileId>> = HashMap::new();
+        for &(includer, included, _) in self.edges.iter() {
+            included_by.entry(included).or_insert_with(Vec::new).push(includer);
+        }
+
+        let mut seen = HashSet::new();
+        seen.insert(file);
+        let mut result = Vec::new();
+        let mut queue = vec![file];
+        while let Some(next) = queue.pop() {
+            for &includer in included_by.get(&next).map_or(&[][..], |v| &v[..]) {
+                if seen.insert(includer) {
+                    result.push(includer);
+                    queue.push(includer);
+                }
+            }
+        }
+        result
+    }
+
+    /// Order the files so that each comes before the files it includes.
+    ///
+    /// If the graph contains a cycle, the files forming it are returned
+    /// instead.
+    pub fn topological_order(&self) -> Result<Vec<FileId>, Vec<FileId>> {
+        let mut nodes = Vec::new();
+        for &(includer, included, _) in self.edges.iter() {
+            for &file in &[includer, included] {
+                if !nodes.contains(&file) {
+                    nodes.push(file);
+                }
+            }
+        }
+
+        let mut finished = Vec::new();
+        let mut stack = Vec::new();
+        for &node in nodes.iter() {
+            self.visit(node, &mut stack, &mut finished)?;
+        }
+        finished.reverse();
+        Ok(finished)
+    }
+
+    fn visit(&self, node: FileId, stack: &mut Vec<FileId>, finished: &mut Vec<FileId>) -> Result<(), Vec<FileId>> {
+        if finished.contains(&node) {
+            return Ok(());
+        }
+        if let Some(pos) = stack.iter().position(|&f| f == node) {
+            return Err(stack[pos..].to_vec());
+        }
+        stack.push(node);
+        for child in self.includes(node) {
+            self.visit(child, stack, finished)?;
+        }
+        stack.pop();
+        finished.push(node);
+        Ok(())
+    }
+}
+
 // ----------------------------------------------------------------------------
 // The stack of currently #included files
 
+/// How many macro expansions may be nested before expansion is abandoned.
+pub const MAX_EXPANSION_DEPTH: usize = 64;
+
 #[derive(Debug)]
 enum Include<'ctx> {
     File {
@@ -139,14 +254,16 @@ enum Include<'ctx> {
     Expansion {
         name: String,
         location: Location,
+        /// Where the expanded macro was defined.
+        define_loc: Location,
         tokens: VecDeque<Token>,
     },
 }
 
 impl<'ctx> Include<'ctx> {
     fn from_file(context: &'ctx Context, path: PathBuf) -> io::Result<Include> {
-        let reader = io::BufReader::new(File::open(&path)?);
-        Ok(Include::from_read(context, path, Box::new(reader)))
+        let read = context.open_file(&path)?;
+        Ok(Include::from_read(context, path, read))
     }
 
     fn from_read(context: &'ctx Context, path: PathBuf, read: Box<io::Read>) -> Include {
@@ -171,6 +288,8 @@ impl<'ctx> HasLocation for Include<'ctx> {
 #[derive(Debug, Default)]
 struct IncludeStack<'ctx> {
     stack: Vec<Include<'ctx>>,
+    /// How many files have been read to the end.
+    files_done: usize,
 }
 
 impl<'ctx> IncludeStack<'ctx> {
@@ -182,6 +301,18 @@ impl<'ctx> IncludeStack<'ctx> {
         }
         "".as_ref()
     }
+    fn contains_file(&self, file: FileId) -> bool {
+        self.stack.iter().any(|each| match *each {
+            Include::File { file: f, .. } => f == file,
+            _ => false,
+        })
+    }
+    fn expansion_names(&self) -> Vec<&str> {
+        self.stack.iter().filter_map(|each| match *each {
+            Include::Expansion { ref name, .. } => Some(&name[..]),
+            _ => None,
+        }).collect()
+    }
     fn top_no_expand(&self) -> &str {
         for each in self.stack.iter().rev() {
             if let &Include::Expansion { ref name, .. } = each {
@@ -223,7 +354,9 @@ impl<'ctx> Iterator for IncludeStack<'ctx> {
                 },
                 None => return None,
             }
-            self.stack.pop();
+            if let Some(Include::File { .. }) = self.stack.pop() {
+                self.files_done += 1;
+            }
         }
     }
 }
@@ -269,7 +402,11 @@ pub struct Preprocessor<'ctx> {
     env_file: PathBuf,
 
     include_stack: IncludeStack<'ctx>,
+    /// How many files the environment is expected to include.
+    files_total: usize,
     last_input_loc: Location,
+    /// Where the macro which produced the last input token was defined.
+    last_input_expanded_from: Option<Location>,
     output: VecDeque<Token>,
     ifdef_stack: Vec<Ifdef>,
     ifdef_history: IntervalTree<Location, bool>,
@@ -286,6 +423,9 @@ pub struct Preprocessor<'ctx> {
 
     docs_in: VecDeque<(Location, DocComment)>,
     docs_out: VecDeque<(Location, DocComment)>,
+
+    include_graph: IncludeGraph,
+    macro_uses: Vec<(Range<Location>, String, Location)>,
 }
 
 impl<'ctx> HasLocation for Preprocessor<'ctx> {
@@ -302,19 +442,25 @@ impl<'ctx> Preprocessor<'ctx> {
         let mut buffer = Vec::new();
         {
             use std::io::Read;
-            let mut file = File::open(&env_file)?;
-            file.read_to_end(&mut buffer)?;
+            context.open_file(&env_file)?.read_to_end(&mut buffer)?;
         }
+        // Count the includes for progress reporting, plus the environment itself.
+        let files_total = 1 + String::from_utf8_lossy(&buffer)
+            .lines()
+            .filter(|line| line.trim_left().starts_with("#include"))
+            .count();
         let include = Include::from_read(context, env_file.clone(), Box::new(io::Cursor::new(buffer)));
 
         // Load the built-in macros.
         let mut defines = DefineMap::default();
-        super::builtins::default_defines(&mut defines);
+        let version = context.byond_version().unwrap_or(super::builtins::BASE_BYOND_VERSION);
+        super::builtins::default_defines(&mut defines, version);
 
         Ok(Preprocessor {
             context,
             env_file,
-            include_stack: IncludeStack { stack: vec![include] },
+            include_stack: IncludeStack { stack: vec![include], files_done: 0 },
+            files_total,
             history: Default::default(),
             defines,
             maps: Default::default(),
@@ -328,10 +474,42 @@ impl<'ctx> Preprocessor<'ctx> {
             danger_idents: Default::default(),
             docs_in: Default::default(),
             docs_out: Default::default(),
+            include_graph: Default::default(),
+            macro_uses: Default::default(),
+            last_input_expanded_from: None,
             in_interp_string: 0,
         })
     }
 
+    /// Create a preprocessor with no input, starting from the given defines.
+    ///
+    /// Files to process may be added with `push_file`.
+    pub fn from_defines(context: &'ctx Context, defines: DefineMap) -> Self {
+        Preprocessor {
+            context,
+            env_file: PathBuf::new(),
+            include_stack: Default::default(),
+            files_total: 0,
+            history: Default::default(),
+            defines,
+            maps: Default::default(),
+            skins: Default::default(),
+            scripts: Default::default(),
+            ifdef_stack: Default::default(),
+            ifdef_history: Default::default(),
+            last_input_loc: Default::default(),
+            last_printable_input_loc: Default::default(),
+            output: Default::default(),
+            danger_idents: Default::default(),
+            docs_in: Default::default(),
+            docs_out: Default::default(),
+            include_graph: Default::default(),
+            macro_uses: Default::default(),
+            last_input_expanded_from: None,
+            in_interp_string: 0,
+        }
+    }
+
     /// Move all active defines to the define history.
     pub fn finalize(&mut self) {
         let mut i = 0;
@@ -355,11 +533,55 @@ impl<'ctx> Preprocessor<'ctx> {
         &self.history
     }
 
+    /// Access the defines active at the current position.
+    pub fn defines(&self) -> &DefineMap {
+        &self.defines
+    }
+
     /// Access currently active defines.
     pub fn defines_at(&self, location: Location) -> DefineMap {
         DefineMap::from_history(&self.history, location)
     }
 
+    /// Access the `#include` graph of the files seen so far.
+    pub fn include_graph(&self) -> &IncludeGraph {
+        &self.include_graph
+    }
+
+    /// Access the map (`.dmm`) files included so far.
+    pub fn maps(&self) -> &[PathBuf] {
+        &self.maps
+    }
+
+    /// Access the interface (`.dmf`) files included so far.
+    pub fn skins(&self) -> &[PathBuf] {
+        &self.skins
+    }
+
+    /// Access the client script (`.dms`) files included so far.
+    pub fn scripts(&self) -> &[PathBuf] {
+        &self.scripts
+    }
+
+    /// Access the macro invocations seen so far, as the span of the use, the
+    /// name of the macro, and the location of its definition.
+    pub fn macro_uses(&self) -> &[(Range<Location>, String, Location)] {
+        &self.macro_uses
+    }
+
+    /// If the token last returned came from a macro expansion, the location
+    /// of that macro's `#define`.
+    pub fn expanded_from(&self) -> Option<Location> {
+        self.last_input_expanded_from
+    }
+
+    /// Add annotations for the macro invocations seen so far.
+    pub fn annotate_macro_uses(&self, annotations: &mut AnnotationTree) {
+        for &(ref span, ref name, location) in self.macro_uses.iter() {
+            annotations.insert(span.clone(), Annotation::MacroUse(name.clone(), location));
+        }
+    }
+
     /// Access the ifdef history.
     pub fn ifdef_history(&self) -> &IntervalTree<Location, bool> {
         &self.ifdef_history
@@ -375,6 +597,7 @@ impl<'ctx> Preprocessor<'ctx> {
             context: context,
             env_file: self.env_file.clone(),
             include_stack: Default::default(),
+            files_total: 0,
             history: Default::default(),  // TODO: support branching a second time
             defines,
             maps: Default::default(),
@@ -388,6 +611,9 @@ impl<'ctx> Preprocessor<'ctx> {
             danger_idents: Default::default(),
             docs_in: Default::default(),
             docs_out: Default::default(),
+            include_graph: Default::default(),
+            macro_uses: Default::default(),
+            last_input_expanded_from: None,
             in_interp_string: 0,
         }
     }
@@ -398,6 +624,7 @@ impl<'ctx> Preprocessor<'ctx> {
             context: context,
             env_file: self.env_file.clone(),
             include_stack: Default::default(),
+            files_total: 0,
             history: Default::default(),  // TODO: support branching a second time
             defines: self.defines.clone(),
             maps: Default::default(),
@@ -411,6 +638,9 @@ impl<'ctx> Preprocessor<'ctx> {
             danger_idents: Default::default(),
             docs_in: Default::default(),
             docs_out: Default::default(),
+            include_graph: Default::default(),
+            macro_uses: Default::default(),
+            last_input_expanded_from: None,
             in_interp_string: 0,
         }
     }
@@ -528,7 +758,14 @@ impl<'ctx> Preprocessor<'ctx> {
     }
 
     fn inner_next(&mut self) -> Option<LocatedToken> {
-        self.include_stack.next()
+        let files_done = self.include_stack.files_done;
+        let next = self.include_stack.next();
+        if self.include_stack.files_done != files_done {
+            let files_done = self.include_stack.files_done;
+            self.files_total = ::std::cmp::max(self.files_total, files_done);
+            self.context.report_progress(Phase::Parsing, files_done, self.files_total);
+        }
+        next
     }
 
     #[allow(unreachable_code)]
@@ -611,7 +848,7 @@ impl<'ctx> Preprocessor<'ctx> {
                             self.include_stack.top_file_path().parent().unwrap().join(&path),
                             path,
                         ].into_iter().rev() {
-                            if !candidate.exists() {
+                            if !self.context.file_exists(&candidate) {
                                 continue;
                             }
                             // Double-match is used to let go of the borrow of
@@ -644,17 +881,26 @@ impl<'ctx> Preprocessor<'ctx> {
                                 FileType::DMS => self.scripts.push(candidate),
                                 // TODO: warn if a file is double-included, and
                                 // don't include it a second time
-                                FileType::DM => match Include::from_file(self.context, candidate) {
-                                    Ok(include) => {
-                                        // A phantom newline keeps the include
-                                        // directive being indented from making
-                                        // the first line of the file indented.
-                                        self.output.push_back(Token::Punct(Punctuation::Newline));
-                                        self.include_stack.stack.push(include);
+                                FileType::DM => {
+                                    let included = self.context.register_file(&candidate);
+                                    self.include_graph.add_include(self.last_input_loc, included);
+                                    if self.include_stack.contains_file(included) {
+                                        self.context.register_error(DMError::new(self.last_input_loc,
+                                            format!("recursive #include of {}", candidate.display())));
+                                        return Ok(());
                                     }
-                                    Err(e) => self.context.register_error(DMError::new(self.last_input_loc,
-                                        "failed to open file").set_cause(e)),
-                                },
+                                    match Include::from_file(self.context, candidate) {
+                                        Ok(include) => {
+                                            // A phantom newline keeps the include
+                                            // directive being indented from making
+                                            // the first line of the file indented.
+                                            self.output.push_back(Token::Punct(Punctuation::Newline));
+                                            self.include_stack.stack.push(include);
+                                        }
+                                        Err(e) => self.context.register_error(DMError::new(self.last_input_loc,
+                                            "failed to open file").set_cause(e)),
+                                    }
+                                }
                             }
                             return Ok(());
                         }
@@ -742,9 +988,9 @@ impl<'ctx> Preprocessor<'ctx> {
                                 // FILE_DIR is handled specially and sometimes makes sense to define multiple times.
                                 if define_name != "FILE_DIR" {
                                     self.context.register_error(DMError::new(define_name_loc,
-                                        format!("macro redefined: {}", define_name)).set_severity(Severity::Warning));
-                                    self.context.register_error(DMError::new(previous_loc,
-                                        format!("previous definition of {}", define_name)).set_severity(Severity::Info));
+                                        format!("macro redefined: {}", define_name))
+                                        .set_severity(Severity::Warning)
+                                        .with_note(previous_loc, "previous definition here"));
                                 }
                             }
                         }
@@ -814,20 +1060,40 @@ impl<'ctx> Preprocessor<'ctx> {
                 } else if ident == "__LINE__" {
                     self.output.push_back(Token::Int(self.last_input_loc.line as i32));
                     return Ok(());
+                } else if ident == "__MAIN__" && self.in_environment() {
+                    self.output.push_back(Token::Int(1));
+                    return Ok(());
                 }
 
                 // if it's a define, perform the substitution
-                match self.defines.get(ident).cloned() { // TODO
-                    Some((_, Define::Constant { subst, docs: _ })) => {
+                let use_span = self.last_input_loc..self.last_input_loc.add_columns(ident.len());
+                let define = match self.defines.get(ident) {
+                    Some(_) if self.include_stack.expansion_names().len() >= MAX_EXPANSION_DEPTH => {
+                        let names = self.include_stack.expansion_names();
+                        let cycle = names.iter().rposition(|name| name == ident).unwrap_or(0);
+                        self.context.register_error(DMError::new(self.last_input_loc, format!(
+                            "macro expansion exceeded depth limit of {}: {} -> {}",
+                            MAX_EXPANSION_DEPTH,
+                            names[cycle..].join(" -> "),
+                            ident,
+                        )));
+                        None
+                    }
+                    other => other.cloned(),
+                };
+                match define { // TODO
+                    Some((define_loc, Define::Constant { subst, docs: _ })) => {
+                        self.macro_uses.push((use_span, ident.to_owned(), define_loc));
                         let e = Include::Expansion {
                             name: ident.to_owned(),
                             tokens: subst.into_iter().collect(),
                             location: self.last_input_loc,
+                            define_loc,
                         };
                         self.include_stack.stack.push(e);
                         return Ok(());
                     }
-                    Some((_, Define::Function { ref params, ref subst, variadic, docs: _ })) => {
+                    Some((define_loc, Define::Function { ref params, ref subst, variadic, docs: _ })) => {
                         // if it's not followed by an LParen, it isn't really a function call
                         match next!() {
                             Token::Punct(Punctuation::LParen) => {}
@@ -843,6 +1109,8 @@ impl<'ctx> Preprocessor<'ctx> {
                             }
                         }
 
+                        self.macro_uses.push((use_span, ident.to_owned(), define_loc));
+
                         // read arguments
                         let mut args = Vec::new();
                         let mut this_arg = Vec::new();
@@ -893,44 +1161,30 @@ impl<'ctx> Preprocessor<'ctx> {
                                     Some(i) => expansion.extend(args[i].iter().cloned()),
                                     None => expansion.push_back(Token::Ident(ident, ws)),
                                 },
-                                // token paste = concat two idents together, if at all possible
+                                // token paste = concat two tokens together, if at all possible
                                 Token::Punct(Punctuation::TokenPaste) => {
-                                    match (expansion.pop_back(), input.next()) {
-                                        (Some(Token::Ident(first, ws1)), Some(Token::Ident(second, ws))) => {
-                                            match params.iter().position(|x| *x == second) {
-                                                Some(i) => {
-                                                    let mut arg = args[i].iter().cloned();
-                                                    match arg.next() {
-                                                        Some(Token::Ident(second, ws)) => {
-                                                            expansion.push_back(Token::Ident(
-                                                                format!("{}{}", first, second),
-                                                                ws,
-                                                            ));
-                                                        }
-                                                        Some(other) => {
-                                                            expansion.push_back(Token::Ident(first, ws1));
-                                                            expansion.push_back(other);
-                                                        }
-                                                        None => {}
-                                                    }
-                                                    expansion.extend(arg);
-                                                }
-                                                None => expansion.push_back(Token::Ident(format!("{}{}", first, second), ws)),
-                                            }
-                                        }
-                                        (non_ident_first, Some(Token::Ident(second, ws))) => {
-                                            expansion.extend(non_ident_first);
-                                            match params.iter().position(|x| *x == second) {
-                                                Some(i) => expansion.extend(args[i].iter().cloned()),
-                                                None => expansion.push_back(Token::Ident(second, ws)),
+                                    // the right-hand side may be an argument of several tokens
+                                    let mut rhs: VecDeque<Token> = match input.next() {
+                                        Some(Token::Ident(second, ws)) => match params.iter().position(|x| *x == second) {
+                                            Some(i) => args[i].iter().cloned().collect(),
+                                            None => Some(Token::Ident(second, ws)).into_iter().collect(),
+                                        },
+                                        other => other.into_iter().collect(),
+                                    };
+                                    match (expansion.pop_back(), rhs.pop_front()) {
+                                        (Some(first), Some(second)) => match paste_tokens(first, second) {
+                                            Ok(pasted) => expansion.push_back(pasted),
+                                            Err((first, second)) => {
+                                                expansion.push_back(first);
+                                                expansion.push_back(second);
                                             }
-                                        }
-                                        (non_ident_first, non_ident_second) => {
-                                            expansion.extend(non_ident_first);
-                                            expansion.extend(non_ident_second);
+                                        },
+                                        (first, second) => {
+                                            expansion.extend(first);
+                                            expansion.extend(second);
                                         }
                                     }
-                                    // read the next ident and concat it into the previous ident
+                                    expansion.extend(rhs);
                                 },
                                 // hash = must be followed by a param name, stringify the whole argument
                                 Token::Punct(Punctuation::Hash) => {
@@ -938,11 +1192,13 @@ impl<'ctx> Preprocessor<'ctx> {
                                         Some(Token::Ident(argname, _)) => match params.iter().position(|x| *x == argname) {
                                             Some(i) => {
                                                 let mut string = String::new();
+                                                let mut prev = None;
                                                 for each in &args[i] {
                                                     use std::fmt::Write;
-                                                    if !string.is_empty() {
+                                                    if prev.map_or(false, |prev| each.separate_from(prev)) {
                                                         string.push(' ');
                                                     }
+                                                    prev = Some(each);
                                                     let _e = write!(string, "{}", each);
                                                     #[cfg(debug_assertions)] {
                                                         _e.unwrap();
@@ -963,6 +1219,7 @@ impl<'ctx> Preprocessor<'ctx> {
                             name: ident.to_owned(),
                             tokens: expansion,
                             location: self.last_input_loc,
+                            define_loc,
                         };
                         self.include_stack.stack.push(e);
                         return Ok(());
@@ -1007,6 +1264,10 @@ impl<'ctx> Iterator for Preprocessor<'ctx> {
                 });
             }
 
+            if self.context.is_cancelled() {
+                return None;
+            }
+
             if let Some(tok) = self.inner_next() {
                 // linting for https://secure.byond.com/forum/?post=2072419
                 if !tok.token.is_whitespace() && tok.token != Token::Punct(Punctuation::Hash) {
@@ -1020,6 +1281,10 @@ impl<'ctx> Iterator for Preprocessor<'ctx> {
 
                 // update last_input_loc and attempt to process the input token
                 self.last_input_loc = tok.location;
+                self.last_input_expanded_from = match self.include_stack.stack.last() {
+                    Some(&Include::Expansion { define_loc, .. }) => Some(define_loc),
+                    _ => None,
+                };
                 if let Err(e) = self.real_next(tok.token, false) {
                     self.context.register_error(e);
                 }
@@ -1027,6 +1292,12 @@ impl<'ctx> Iterator for Preprocessor<'ctx> {
                 while let Some(ifdef) = self.pop_ifdef() {
                     self.context.register_error(DMError::new(ifdef.location, "unterminated #if/#ifdef"));
                 }
+                // some counted includes may have been skipped by #if
+                let files_done = self.include_stack.files_done;
+                if files_done < self.files_total {
+                    self.files_total = files_done;
+                    self.context.report_progress(Phase::Parsing, files_done, files_done);
+                }
                 return None;
             }
         }
@@ -0,0 +1,153 @@
+//! Import of BYOND profiler output, for finding the source of hot procs.
+//!
+//! The profiler can export its results as CSV or JSON. Each entry names a
+//! proc by its full path, such as `/mob/proc/Life`, which is matched against
+//! the object tree to find where the proc is defined.
+use objtree::{ObjectTree, TypeRef, ProcValue, Code};
+use analysis;
+
+/// One row of profiler output.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ProfileEntry {
+    /// The full path of the proc, such as `/mob/proc/Life`.
+    pub name: String,
+    /// CPU time spent in the proc itself, in seconds.
+    pub self_cpu: f64,
+    /// CPU time spent in the proc and everything it called, in seconds.
+    pub total_cpu: f64,
+    /// Real time from the proc's start to its return, including sleeps.
+    pub real_time: f64,
+    /// CPU time spent past the end of a tick.
+    pub overtime: f64,
+    /// The number of times the proc was called.
+    pub calls: u64,
+}
+
+impl ProfileEntry {
+    /// Split the name into the type path and proc name.
+    ///
+    /// Global procs have an empty type path.
+    pub fn split_name(&self) -> Option<(&str, &str)> {
+        let name = self.name.trim();
+        for keyword in &["/proc/", "/verb/"] {
+            if let Some(idx) = name.rfind(keyword) {
+                return Some((&name[..idx], &name[idx + keyword.len()..]));
+            }
+        }
+        // older versions omit the keyword for overrides
+        let idx = name.rfind('/')?;
+        Some((&name[..idx], &name[idx + 1..]))
+    }
+}
+
+/// Parse the CSV export of the profiler.
+///
+/// The header row determines which column holds which figure, so columns
+/// may appear in any order and unknown columns are ignored.
+pub fn parse_csv(text: &str) -> Result<Vec<ProfileEntry>, String> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = match lines.next() {
+        Some(line) => line.split(',').map(|col| col.trim().to_lowercase()).collect(),
+        None => return Ok(Vec::new()),
+    };
+    let column = |names: &[&str]| header.iter().position(|col| names.contains(&col.as_str()));
+    let name_col = column(&["name", "proc name", "proc"]).ok_or("missing proc name column")?;
+    let self_col = column(&["self", "self cpu"]);
+    let total_col = column(&["total", "total cpu"]);
+    let real_col = column(&["real", "real time"]);
+    let over_col = column(&["over", "overtime"]);
+    let calls_col = column(&["calls"]);
+
+    let mut entries = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let number = |col: Option<usize>| -> Result<f64, String> {
+            match col.and_then(|col| fields.get(col)) {
+                Some(field) => field.parse().map_err(|_| format!("row {}: bad number {:?}", i + 1, field)),
+                None => Ok(0.),
+            }
+        };
+        entries.push(ProfileEntry {
+            name: fields.get(name_col).ok_or_else(|| format!("row {}: missing proc name", i + 1))?.to_string(),
+            self_cpu: number(self_col)?,
+            total_cpu: number(total_col)?,
+            real_time: number(real_col)?,
+            overtime: number(over_col)?,
+            calls: number(calls_col)? as u64,
+        });
+    }
+    Ok(entries)
+}
+
+/// Parse the JSON export of the profiler, a list of objects with the keys
+/// `name`, `self`, `total`, `real`, `over`, and `calls`.
+///
+/// Missing figures are taken to be zero.
+#[cfg(feature = "profile")]
+pub fn parse_json(text: &str) -> Result<Vec<ProfileEntry>, String> {
+    use serde_json::{Map, Value};
+
+    let rows: Vec<Map<String, Value>> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    Ok(rows.iter().map(|row| {
+        let number = |key: &str| row.get(key).and_then(|v| v.as_f64()).unwrap_or(0.);
+        ProfileEntry {
+            name: row.get("name").and_then(|v| v.as_str()).unwrap_or("").to_owned(),
+            self_cpu: number("self"),
+            total_cpu: number("total"),
+            real_time: number("real"),
+            overtime: number("over"),
+            calls: number("calls") as u64,
+        }
+    }).collect())
+}
+
+/// Parse either export of the profiler, telling JSON from CSV by its
+/// opening bracket.
+#[cfg(feature = "profile")]
+pub fn parse(text: &str) -> Result<Vec<ProfileEntry>, String> {
+    if text.trim_left().starts_with('[') {
+        parse_json(text)
+    } else {
+        parse_csv(text)
+    }
+}
+
+/// A profiled proc joined against its definition.
+#[derive(Debug, Clone)]
+pub struct HotProc<'a> {
+    pub entry: &'a ProfileEntry,
+    /// The type the proc is defined on, if found.
+    pub ty: Option<TypeRef<'a>>,
+    /// The most specific definition of the proc, if found.
+    pub proc: Option<&'a ProcValue>,
+    /// Builtins which the proc calls that may sleep.
+    pub sleeps: Vec<&'a str>,
+}
+
+impl<'a> HotProc<'a> {
+    /// Check whether the analysis considers this proc worth a closer look:
+    /// it sleeps, yet spends long enough on the CPU to be profiled.
+    pub fn is_suspicious(&self) -> bool {
+        !self.sleeps.is_empty() && self.entry.self_cpu > 0.
+    }
+}
+
+/// Join profiler entries against the object tree, hottest first.
+pub fn hot_procs<'a>(tree: &'a ObjectTree, entries: &'a [ProfileEntry]) -> Vec<HotProc<'a>> {
+    let mut result: Vec<_> = entries.iter().map(|entry| {
+        let (ty, proc) = match entry.split_name() {
+            Some((path, name)) => {
+                let ty = if path.is_empty() { Some(tree.root()) } else { tree.find(path) };
+                (ty, ty.and_then(|ty| ty.get_proc(name)))
+            }
+            None => (None, None),
+        };
+        let sleeps = match proc.map(|proc| &proc.code) {
+            Some(&Code::Present(ref block)) => analysis::sleeping_calls(tree, block),
+            _ => Vec::new(),
+        };
+        HotProc { entry, ty, proc, sleeps }
+    }).collect();
+    result.sort_by(|a, b| b.entry.self_cpu.partial_cmp(&a.entry.self_cpu).unwrap_or(::std::cmp::Ordering::Equal));
+    result
+}
@@ -0,0 +1,219 @@
+//! An index of where procs and types are referred to.
+//!
+//! As in the dead code check, procs are matched by name alone, since DM
+//! dispatches them by name. Expressions do not carry locations, so each
+//! reference is recorded at the proc or var in which it appears.
+use std::collections::HashMap;
+
+use ast::*;
+use objtree::{ObjectTree, TypeRef};
+use analysis;
+use {Context, FileId, Location};
+
+/// A proc or var which refers to a proc or type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub location: Location,
+    /// The path of the proc or var, such as `/mob/proc/Login`.
+    pub from: String,
+}
+
+/// The references to each proc name and type path in an environment.
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceIndex {
+    procs: HashMap<String, Vec<Reference>>,
+    types: HashMap<String, Vec<Reference>>,
+    /// The direct subtypes of each type, following `parent_type`.
+    subtypes: HashMap<String, Vec<String>>,
+}
+
+impl ReferenceIndex {
+    /// Index every var value and proc body in the tree, parsing lazily
+    /// collected bodies as needed.
+    pub fn build(context: &Context, tree: &ObjectTree) -> ReferenceIndex {
+        let mut index = ReferenceIndex::default();
+        index.index_subtypes(tree);
+        for idx in tree.graph.node_indices() {
+            index.add_type(context, TypeRef::new(tree, idx), None);
+        }
+        index
+    }
+
+    /// Re-index the vars and procs declared in one file, replacing the
+    /// references previously recorded there.
+    ///
+    /// The tree may have been parsed again since the index was built, so
+    /// long as the other files have not changed.
+    pub fn update_file(&mut self, context: &Context, tree: &ObjectTree, file: FileId) {
+        for list in self.procs.values_mut().chain(self.types.values_mut()) {
+            list.retain(|each| each.location.file != file);
+        }
+        self.index_subtypes(tree);
+        for idx in tree.graph.node_indices() {
+            self.add_type(context, TypeRef::new(tree, idx), Some(file));
+        }
+    }
+
+    fn index_subtypes(&mut self, tree: &ObjectTree) {
+        self.subtypes.clear();
+        for idx in tree.graph.node_indices() {
+            let ty = TypeRef::new(tree, idx);
+            if let Some(parent) = ty.parent_type() {
+                self.subtypes.entry(parent.get().path.clone())
+                    .or_insert_with(Vec::new)
+                    .push(ty.get().path.clone());
+            }
+        }
+    }
+
+    /// Find the other files whose checks may change when the given file
+    /// does: those which call its procs or refer to its types, and those
+    /// which declare subtypes of its types.
+    pub fn dependents(&self, tree: &ObjectTree, file: FileId) -> Vec<FileId> {
+        let mut files = Vec::new();
+        {
+            let mut add = |each: FileId| if each != file && each != FileId::builtins() && !files.contains(&each) {
+                files.push(each);
+            };
+            for idx in tree.graph.node_indices() {
+                let ty = TypeRef::new(tree, idx);
+                let path = &ty.get().path;
+                let declares_type = ty.get().location.file == file;
+                if declares_type {
+                    for each in self.type_references(path).iter() {
+                        add(each.location.file);
+                    }
+                    for sub in self.subtypes(tree, path) {
+                        add(sub.get().location.file);
+                        for proc in sub.get().procs.values() {
+                            for value in proc.value.iter() {
+                                add(value.location.file);
+                            }
+                        }
+                    }
+                }
+                for (name, proc) in ty.get().procs.iter() {
+                    if proc.value.iter().any(|value| value.location.file == file) {
+                        for each in self.proc_references(name).iter() {
+                            add(each.location.file);
+                        }
+                    }
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    fn add_type(&mut self, context: &Context, ty: TypeRef, only_file: Option<FileId>) {
+        let wanted = |location: Location| location.file != FileId::builtins() && only_file.map_or(true, |file| location.file == file);
+        for (name, var) in ty.get().vars.iter() {
+            if !wanted(var.value.location) {
+                continue;
+            }
+            if let Some(ref expr) = var.value.expression {
+                let from = format!("{}/var/{}", ty.get().path, name);
+                let mut found = Found::default();
+                analysis::each_subexpr(expr, &mut |expr: &Expression| found.visit(expr));
+                self.record(found, var.value.location, &from);
+            }
+        }
+        for (name, proc) in ty.get().procs.iter() {
+            for value in proc.value.iter() {
+                if !wanted(value.location) {
+                    continue;
+                }
+                if let Some(body) = value.body(context) {
+                    let from = format!("{}/proc/{}", ty.get().path, name);
+                    let mut found = Found::default();
+                    analysis::each_expr(&body, true, &mut |expr: &Expression| found.visit(expr));
+                    self.record(found, value.location, &from);
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, found: Found, location: Location, from: &str) {
+        for (names, map) in vec![(found.procs, &mut self.procs), (found.types, &mut self.types)] {
+            for name in names {
+                let list = map.entry(name).or_insert_with(Vec::new);
+                // one reference per proc or var is enough
+                if !list.iter().any(|each| each.location == location) {
+                    list.push(Reference { location, from: from.to_owned() });
+                }
+            }
+        }
+    }
+
+    /// The references to procs of the given name.
+    pub fn proc_references(&self, name: &str) -> &[Reference] {
+        self.procs.get(name).map_or(&[], |list| &list[..])
+    }
+
+    /// The references to the given type path, not counting its subtypes.
+    pub fn type_references(&self, path: &str) -> &[Reference] {
+        self.types.get(path).map_or(&[], |list| &list[..])
+    }
+
+    /// Every subtype of the given type, following `parent_type`.
+    pub fn subtypes<'a>(&self, tree: &'a ObjectTree, path: &str) -> Vec<TypeRef<'a>> {
+        let mut result = Vec::new();
+        let mut stack = vec![path];
+        while let Some(next) = stack.pop() {
+            for child in self.subtypes.get(next).into_iter().flat_map(|list| list.iter()) {
+                if let Some(ty) = tree.find(child) {
+                    result.push(ty);
+                }
+                stack.push(child.as_str());
+            }
+        }
+        result
+    }
+
+    /// The subtypes of the given type which override one of its procs.
+    pub fn overrides<'a>(&self, tree: &'a ObjectTree, path: &str, proc_name: &str) -> Vec<TypeRef<'a>> {
+        let mut result = self.subtypes(tree, path);
+        result.retain(|ty| ty.get().procs.contains_key(proc_name));
+        result
+    }
+}
+
+/// The proc names and type paths referred to by one proc or var.
+#[derive(Default)]
+struct Found {
+    procs: Vec<String>,
+    types: Vec<String>,
+}
+
+impl Found {
+    fn visit(&mut self, expr: &Expression) {
+        if let Expression::Base { ref term, ref follow, .. } = *expr {
+            match *term {
+                Term::Call(ref name, _) => self.procs.push(name.clone()),
+                Term::Prefab(ref prefab) => self.prefab(prefab),
+                Term::New { type_: NewType::Prefab(ref prefab), .. } => self.prefab(prefab),
+                _ => {}
+            }
+            for each in follow.iter() {
+                if let Follow::Call(_, ref name, _) = *each {
+                    self.procs.push(name.clone());
+                }
+            }
+        }
+    }
+
+    fn prefab(&mut self, prefab: &Prefab) {
+        let len = prefab.path.len();
+        // `.proc/foo` and `/mob/verb/bar` refer to procs
+        if len >= 2 && (prefab.path[len - 2].1 == "proc" || prefab.path[len - 2].1 == "verb") {
+            self.procs.push(prefab.path[len - 1].1.clone());
+        } else if !prefab.path.is_empty() && prefab.path.iter().all(|&(op, _)| op == PathOp::Slash) {
+            let mut path = String::new();
+            for &(_, ref each) in prefab.path.iter() {
+                path.push('/');
+                path.push_str(each);
+            }
+            self.types.push(path);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Checking that resource literals refer to files which exist.
+//!
+//! DM looks for a resource such as `'icons/obj/foo.dmi'` relative to the
+//! directory of the `.dme`, then relative to each directory named by a
+//! `FILE_DIR` define. Optionally, constant icon states are also checked
+//! against the metadata of their `.dmi`.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{Context, DMError, Location, FileId, Severity};
+use ast::*;
+use analysis;
+use constants::{self, Constant};
+use dmi::{Metadata, Dirs, NORTH, SOUTH, EAST, WEST, NORTHEAST, NORTHWEST, SOUTHEAST, SOUTHWEST};
+use lexer::Token;
+use objtree::{ObjectTree, TypeRef};
+use preprocessor::{DefineMap, Define};
+
+/// Read the directories named by `FILE_DIR` defines, in the order they were
+/// defined.
+pub fn file_dirs(defines: &DefineMap) -> Vec<PathBuf> {
+    defines.get_all("FILE_DIR").iter().filter_map(|&(_, ref define)| match *define {
+        Define::Constant { ref subst, .. } => {
+            let mut text = String::new();
+            for token in subst.iter() {
+                match *token {
+                    Token::String(ref s) | Token::Resource(ref s) => text.push_str(s),
+                    ref other => text.push_str(&other.to_string()),
+                }
+            }
+            Some(PathBuf::from(text.replace("\\", "/")))
+        }
+        Define::Function { .. } => None,
+    }).collect()
+}
+
+/// Finds the files referred to by resource literals.
+pub struct ResourceChecker {
+    search_dirs: Vec<PathBuf>,
+    check_icon_states: bool,
+    found: HashMap<String, Option<PathBuf>>,
+    icons: HashMap<PathBuf, Option<Metadata>>,
+}
+
+impl ResourceChecker {
+    /// Look for resources in `root`, the directory of the `.dme`, and then
+    /// in each of `file_dirs` relative to it.
+    pub fn new(root: &Path, file_dirs: &[PathBuf]) -> ResourceChecker {
+        let mut search_dirs = vec![root.to_owned()];
+        for dir in file_dirs.iter() {
+            let dir = root.join(dir);
+            if !search_dirs.contains(&dir) {
+                search_dirs.push(dir);
+            }
+        }
+        ResourceChecker {
+            search_dirs,
+            check_icon_states: false,
+            found: HashMap::new(),
+            icons: HashMap::new(),
+        }
+    }
+
+    /// Also check that constant `icon_state` values exist in the `.dmi`
+    /// named by the `icon` of the same type, or in `icon()` and `image()`
+    /// calls, and that the `dir` and frame used are present in the state.
+    pub fn check_icon_states(&mut self, enabled: bool) {
+        self.check_icon_states = enabled;
+    }
+
+    /// Find the file a resource refers to, if it exists.
+    pub fn resolve(&mut self, resource: &str) -> Option<PathBuf> {
+        if let Some(found) = self.found.get(resource) {
+            return found.clone();
+        }
+        let relative = resource.replace("\\", "/");
+        let found = self.search_dirs.iter()
+            .map(|dir| dir.join(&relative))
+            .find(|path| path.is_file());
+        self.found.insert(resource.to_owned(), found.clone());
+        found
+    }
+
+    /// Check every resource in the tree's var values and parsed proc
+    /// bodies, reporting those which do not exist to the context.
+    pub fn check(&mut self, context: &Context, tree: &ObjectTree) {
+        for idx in tree.graph.node_indices() {
+            let ty = TypeRef::new(tree, idx);
+            for var in ty.get().vars.values() {
+                if var.value.location.file == FileId::builtins() {
+                    continue;
+                }
+                if let Some(ref expr) = var.value.expression {
+                    for resource in resources_in(|f| analysis::each_subexpr(expr, f)) {
+                        self.check_exists(context, var.value.location, None, &resource);
+                    }
+                }
+            }
+            for (name, proc) in ty.get().procs.iter() {
+                for value in proc.value.iter() {
+                    if let Some(body) = value.body(context) {
+                        let proc_path = format!("{}/proc/{}", ty.get().path, name);
+                        for resource in resources_in(|f| analysis::each_expr(&body, true, f)) {
+                            self.check_exists(context, value.location, Some(&proc_path), &resource);
+                        }
+                        if self.check_icon_states {
+                            self.check_proc_icon_states(context, ty, name, value.location, &body);
+                        }
+                    }
+                }
+            }
+            if self.check_icon_states {
+                self.check_icon_state(context, ty);
+            }
+        }
+    }
+
+    fn check_exists(&mut self, context: &Context, location: Location, proc_path: Option<&str>, resource: &str) {
+        if self.resolve(resource).is_some() {
+            return;
+        }
+        let message = match proc_path {
+            Some(path) => format!("{}: missing resource '{}'", path, resource),
+            None => format!("missing resource '{}'", resource),
+        };
+        context.register_error(DMError::new(location, message));
+    }
+
+    fn check_icon_state(&mut self, context: &Context, ty: TypeRef) {
+        // only where the type itself sets one of these
+        let vars = &ty.get().vars;
+        let location = match (vars.get("icon_state"), vars.get("icon"), vars.get("dir")) {
+            (Some(var), _, _) | (None, Some(var), _) | (None, None, Some(var)) => var.value.location,
+            (None, None, None) => return,
+        };
+        if location.file == FileId::builtins() {
+            return;
+        }
+        let icon = match ty.get_value("icon").and_then(|v| v.constant.as_ref()) {
+            Some(&Constant::Resource(ref icon)) => icon,
+            _ => return,
+        };
+        let state = match ty.get_value("icon_state").and_then(|v| v.constant.as_ref()) {
+            Some(&Constant::String(ref state)) => state,
+            _ => return,
+        };
+        let dir = ty.get_value("dir").and_then(|v| v.constant.as_ref()).and_then(Constant::to_int);
+        self.check_state_use(context, location, ty.pretty_path(), &StateUse { icon, state, dir, frame: None });
+    }
+
+    /// Check the `icon()` and `image()` calls in a proc body whose icon and
+    /// state are constants, and in `update_icon` procs, assignments of
+    /// constant states to `icon_state`.
+    fn check_proc_icon_states(&mut self, context: &Context, ty: TypeRef, name: &str, location: Location, block: &[Spanned<Statement>]) {
+        let proc_path = format!("{}/proc/{}", ty.get().path, name);
+        let own_icon = match ty.get_value("icon").and_then(|v| v.constant.as_ref()) {
+            Some(&Constant::Resource(ref icon)) => Some(icon.as_str()),
+            _ => None,
+        };
+        let in_update_icon = name.starts_with("update_icon");
+
+        let mut uses = Vec::new();
+        analysis::each_expr(block, true, &mut |expr: &Expression| match *expr {
+            Expression::Base { term: Term::Call(ref called, ref args), ref follow, .. } if follow.is_empty() => {
+                // icon(icon, icon_state, dir, frame) and
+                // image(icon, loc, icon_state, layer, dir)
+                let (state_idx, dir_idx, frame_idx) = match called.as_str() {
+                    "icon" => (1, Some(2), Some(3)),
+                    "image" => (2, Some(4), None),
+                    _ => return,
+                };
+                if let (Some(Constant::Resource(icon)), Some(Constant::String(state))) = (const_arg(args, 0), const_arg(args, state_idx)) {
+                    let dir = dir_idx.and_then(|i| const_arg(args, i)).and_then(|c| c.to_int());
+                    let frame = frame_idx.and_then(|i| const_arg(args, i)).and_then(|c| c.to_int());
+                    uses.push(StateUse { icon, state, dir, frame });
+                }
+            }
+            Expression::AssignOp { op: AssignOp::Assign, ref lhs, ref rhs } if in_update_icon => {
+                if let (Some(icon), true) = (own_icon, is_own_icon_state(lhs)) {
+                    if let Some(Constant::String(state)) = evaluate(rhs) {
+                        uses.push(StateUse { icon: icon.to_owned(), state, dir: None, frame: None });
+                    }
+                }
+            }
+            _ => {}
+        });
+
+        for each in uses.iter() {
+            self.check_state_use(context, location, &proc_path, each);
+        }
+    }
+
+    /// Check that an icon has a state, and that the state has the given dir
+    /// and frame, reporting problems both where the state is used and at
+    /// the icon file.
+    fn check_state_use<S: AsRef<str>>(&mut self, context: &Context, location: Location, subject: &str, usage: &StateUse<S>) {
+        let (icon, state) = (usage.icon.as_ref(), usage.state.as_ref());
+        if !icon.ends_with(".dmi") {
+            return;
+        }
+        let path = match self.resolve(icon) {
+            Some(path) => path,
+            None => return,  // reported as a missing resource instead
+        };
+        if !self.icons.contains_key(&path) {
+            let metadata = Metadata::from_file(&path).ok();
+            self.icons.insert(path.clone(), metadata);
+        }
+        let metadata = match self.icons[&path] {
+            Some(ref metadata) => metadata,
+            None => {
+                context.register_error(DMError::new(location, format!("{}: could not read icon '{}'", subject, icon))
+                    .set_severity(Severity::Warning));
+                return;
+            }
+        };
+
+        let icon_location = Location {
+            file: context.register_file(&path),
+            line: 1,
+            column: 1,
+        };
+        let report = |problem: String, note: String| {
+            context.register_error(DMError::new(location, format!("{}: {}", subject, problem))
+                .set_severity(Severity::Warning));
+            context.register_error(DMError::new(icon_location, note)
+                .set_severity(Severity::Info));
+        };
+
+        let found = match metadata.state_names.get(state) {
+            Some(&idx) => &metadata.states[idx],
+            None => return report(
+                format!("icon_state \"{}\" not found in '{}'", state, icon),
+                format!("icon '{}' has no state \"{}\"", icon, state),
+            ),
+        };
+        if let Some(dir) = usage.dir {
+            if !has_dir(found.dirs, dir) {
+                report(
+                    format!("icon_state \"{}\" in '{}' has no dir {}", state, icon, dir),
+                    format!("state \"{}\" has {} dirs", state, found.dirs.len()),
+                );
+            }
+        }
+        if let Some(frame) = usage.frame {
+            if frame < 1 || frame as usize > found.frames.len() {
+                report(
+                    format!("icon_state \"{}\" in '{}' has no frame {}", state, icon, frame),
+                    format!("state \"{}\" has {} frames", state, found.frames.len()),
+                );
+            }
+        }
+    }
+}
+
+/// A use of an icon state, with the dir and frame if they are known.
+struct StateUse<S> {
+    icon: S,
+    state: S,
+    dir: Option<i32>,
+    frame: Option<i32>,
+}
+
+/// Whether a state with the given dirs can be drawn facing `dir`. States
+/// with one dir are drawn the same way facing any direction.
+fn has_dir(dirs: Dirs, dir: i32) -> bool {
+    const CARDINALS: &[i32] = &[NORTH, SOUTH, EAST, WEST];
+    const DIAGONALS: &[i32] = &[NORTHEAST, NORTHWEST, SOUTHEAST, SOUTHWEST];
+    match dirs {
+        _ if dir == 0 => true,
+        Dirs::One => true,
+        Dirs::Four => CARDINALS.contains(&dir),
+        Dirs::Eight => CARDINALS.contains(&dir) || DIAGONALS.contains(&dir),
+    }
+}
+
+/// Whether an assignment target is `icon_state` or `src.icon_state`.
+fn is_own_icon_state(lhs: &Expression) -> bool {
+    match *lhs {
+        Expression::Base { term: Term::Ident(ref name), ref follow, .. } => match follow.len() {
+            0 => name == "icon_state",
+            1 => name == "src" && match follow[0] {
+                Follow::Field(_, ref field) => field == "icon_state",
+                _ => false,
+            },
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn evaluate(expr: &Expression) -> Option<Constant> {
+    constants::simple_evaluate(Location::default(), expr.clone()).ok()
+}
+
+/// Evaluate a positional argument, if it is present and constant.
+fn const_arg(args: &[Expression], idx: usize) -> Option<Constant> {
+    args.get(idx).and_then(evaluate)
+}
+
+/// Collect the resource literals visited by `each`.
+fn resources_in<'a, F>(each: F) -> Vec<String>
+    where F: FnOnce(&mut FnMut(&'a Expression))
+{
+    let mut result = Vec::new();
+    each(&mut |expr: &'a Expression| {
+        if let Expression::Base { term: Term::Resource(ref resource), .. } = *expr {
+            result.push(resource.clone());
+        }
+    });
+    result
+}
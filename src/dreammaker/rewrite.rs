@@ -0,0 +1,615 @@
+//! Re-emitting modified proc bodies as source edits.
+//!
+//! A `VisitorMut` is run over a copy of each proc body. Each statement which
+//! it changed is printed back out and compared against the file's lossless
+//! tokens, and only the tokens which differ are replaced, so comments, blank
+//! lines, and macro uses elsewhere are kept. Statements whose blocks changed
+//! but whose headers did not are compared block by block. The edits are
+//! returned as `Fix`es, which can be written with `apply_fixes`.
+use std::mem;
+use std::ops::Range;
+use std::slice;
+
+use super::{Context, FileId, Fix, Location};
+use super::ast::*;
+use super::indents::IndentProcessor;
+use super::lexer::{Lexer, LocatedToken, RawToken, Token, Punctuation};
+use super::objtree::{ObjectTree, TypeRef, Code};
+use super::visit::VisitorMut;
+
+/// Run a visitor over the procs defined in a file, returning edits for the
+/// parts of each proc body which it changed.
+///
+/// Procs whose body is not a block, such as one-line procs, are skipped.
+pub fn rewrite_procs<V: VisitorMut + ?Sized>(tree: &ObjectTree, file: FileId, source: &str, visitor: &mut V) -> Vec<Fix> {
+    let mut source_tokens = None;
+    let mut fixes = Vec::new();
+    for idx in tree.graph.node_indices() {
+        let ty = TypeRef::new(tree, idx);
+        for (name, proc) in ty.get().procs.iter() {
+            for value in proc.value.iter() {
+                if value.location.file != file {
+                    continue;
+                }
+                let block = match value.code {
+                    Code::Present(ref block) => block,
+                    _ => continue,
+                };
+                let mut new_block = block.clone();
+                visitor.visit_block(&mut new_block);
+                if new_block == *block {
+                    continue;
+                }
+                let tokens = source_tokens.get_or_insert_with(|| SourceTokens::new(file, source));
+                let body = match tokens.find_body(value.location) {
+                    Some(body) => body,
+                    None => continue,
+                };
+                let mut edits = Vec::new();
+                tokens.diff_block(block, &new_block, None, body.depth, &mut edits);
+                // statements from macros defined elsewhere can't be rewritten
+                if edits.iter().any(|edit| edit.range.start < body.tokens.start || edit.range.end > body.tokens.end) {
+                    continue;
+                }
+                let description = format!("rewrite {}/proc/{}", ty.path, name);
+                fixes.extend(edits.into_iter().map(|edit| tokens.fix(edit, &description)));
+            }
+        }
+    }
+    fixes
+}
+
+/// The extent of a proc body among the tokens of its file.
+struct Body {
+    /// The raw tokens between the braces of the body.
+    tokens: Range<usize>,
+    /// The brace depth of the body's statements.
+    depth: usize,
+}
+
+/// A replacement of some raw tokens with printed ones.
+struct Edit {
+    /// The raw tokens to replace, which may be empty.
+    range: Range<usize>,
+    /// Whether an empty `range` inserts after the token before it, rather
+    /// than before the token after it.
+    after: bool,
+    text: String,
+}
+
+/// A file's lossless tokens, and those tokens after indent processing, with
+/// the brace depth at each.
+struct SourceTokens {
+    file: FileId,
+    raw: Vec<RawToken>,
+    processed: Vec<(LocatedToken, usize)>,
+    /// A single level of indentation, as used by the file.
+    indent: String,
+}
+
+impl SourceTokens {
+    fn new(file: FileId, source: &str) -> SourceTokens {
+        let context = Context::default();
+        let raw = lex(&context, file, source);
+        let mut processed = Vec::new();
+        let mut depth = 0usize;
+        for token in IndentProcessor::new(&context, raw.iter().map(|t| LocatedToken::new(t.location, t.token.clone()))) {
+            let next_depth = match token.token {
+                Token::Punct(Punctuation::LBrace) => depth + 1,
+                Token::Punct(Punctuation::RBrace) => depth.saturating_sub(1),
+                _ => depth,
+            };
+            processed.push((token, depth));
+            depth = next_depth;
+        }
+
+        let mut tokens = SourceTokens {
+            file,
+            raw,
+            processed,
+            indent: "\t".to_owned(),
+        };
+        // the leading whitespace of the first line one level deep
+        let first_indented = (0..tokens.processed.len())
+            .filter(|&i| tokens.processed[i].1 == 1 && tokens.is_real(i))
+            .map(|i| tokens.raw_index(tokens.processed[i].0.location))
+            .find(|&i| i > 0 && is_whitespace(&tokens.raw[i - 1].token));
+        if let Some(i) = first_indented {
+            let start = tokens.raw[..i].iter().rposition(|t| !is_whitespace(&t.token)).map_or(0, |j| j + 1);
+            let indent: Vec<u8> = tokens.raw[start..i].iter().flat_map(|t| t.text.iter().cloned()).collect();
+            tokens.indent = String::from_utf8_lossy(&indent).into_owned();
+        }
+        tokens
+    }
+
+    /// The index of the first raw token at or after a location.
+    fn raw_index(&self, location: Location) -> usize {
+        lower_bound(&self.raw, location, |t| t.location)
+    }
+
+    /// Whether a processed token is in the source, rather than one the indent
+    /// processor inserted.
+    fn is_real(&self, index: usize) -> bool {
+        let token = &self.processed[index].0;
+        match self.raw.get(self.raw_index(token.location)) {
+            Some(raw) => raw.location == token.location && raw.token == token.token,
+            None => false,
+        }
+    }
+
+    /// Find the body of the proc whose parameter list starts at `header`.
+    fn find_body(&self, header: Location) -> Option<Body> {
+        let mut i = lower_bound(&self.processed, header, |t| t.0.location);
+        let mut parens = 0;
+        loop {
+            match self.processed.get(i)?.0.token {
+                Token::Punct(Punctuation::LParen) => parens += 1,
+                Token::Punct(Punctuation::RParen) => {
+                    parens -= 1;
+                    if parens == 0 {
+                        break;
+                    }
+                }
+                _ if parens == 0 => return None,
+                _ => {}
+            }
+            i += 1;
+        }
+
+        let (ref open, depth) = *self.processed.get(i + 1)?;
+        if open.token != Token::Punct(Punctuation::LBrace) {
+            return None;
+        }
+        let close = self.processed[i + 1..].iter()
+            .find(|t| t.0.token == Token::Punct(Punctuation::RBrace) && t.1 == depth + 1)?;
+        // the first raw token after the brace, whether it was a real brace
+        // or the newline before an indented body
+        let start = self.raw_index(open.location) + 1;
+        Some(Body {
+            tokens: start..self.raw_index(close.0.location),
+            depth: depth + 1,
+        })
+    }
+
+    /// The raw tokens of the statement starting at `start`, which ends
+    /// before `next` if given.
+    fn statement_tokens(&self, start: Location, next: Option<Location>) -> Range<usize> {
+        let mut first = lower_bound(&self.processed, start, |t| t.0.location);
+        while first + 1 < self.processed.len() && !self.is_real(first) {
+            first += 1;
+        }
+        let mut last = first;
+        let mut nesting = 0usize;
+        for (i, &(ref token, _)) in self.processed.iter().enumerate().skip(first) {
+            if next.map_or(false, |next| token.location >= next) {
+                break;
+            }
+            match token.token {
+                Token::Punct(Punctuation::LParen) |
+                Token::Punct(Punctuation::LBracket) |
+                Token::Punct(Punctuation::SafeLBracket) |
+                Token::Punct(Punctuation::LBrace) => nesting += 1,
+                Token::Punct(Punctuation::RParen) |
+                Token::Punct(Punctuation::RBracket) => nesting = nesting.saturating_sub(1),
+                Token::Punct(Punctuation::RBrace) if nesting == 0 => break,
+                Token::Punct(Punctuation::RBrace) => nesting -= 1,
+                Token::Punct(Punctuation::Semicolon) if nesting == 0 => break,
+                _ => {}
+            }
+            if self.is_real(i) {
+                last = i;
+            }
+        }
+        self.raw_index(self.processed[first].0.location)..self.raw_index(self.processed[last].0.location) + 1
+    }
+
+    /// Compare two versions of a block, recursing into statements whose
+    /// headers are unchanged.
+    fn diff_block(&self, old: &[Spanned<Statement>], new: &[Spanned<Statement>], next: Option<Location>, depth: usize, edits: &mut Vec<Edit>) {
+        if old.len() != new.len() {
+            if let (Some(first), Some(last)) = (old.first(), old.last()) {
+                let range = self.statement_tokens(first.location, None).start..self.statement_tokens(last.location, next).end;
+                let starts_line = self.raw[..range.start].iter().rev()
+                    .find(|t| !is_whitespace(&t.token))
+                    .map_or(true, |t| t.token == Token::Punct(Punctuation::Newline));
+                let one_line = !starts_line && self.raw[range.start].location.line == self.raw[range.end - 1].location.line;
+                let lines: Vec<String> = new.iter()
+                    .map(|statement| format_block(slice::from_ref(statement), "", 0).trim_right().to_owned())
+                    .collect();
+                // keep blocks such as `{ a; b }` on one line
+                let text = if one_line && lines.iter().all(|line| !line.contains('\n')) {
+                    lines.join("; ")
+                } else {
+                    format_block(new, &self.indent, depth)
+                };
+                self.diff_tokens(range, &text, edits);
+            }
+            return;
+        }
+
+        for (i, (old_stmt, new_stmt)) in old.iter().zip(new.iter()).enumerate() {
+            if old_stmt.elem == new_stmt.elem {
+                continue;
+            }
+            let next = old.get(i + 1).map(|s| s.location).or(next);
+            let (mut old_header, mut new_header) = (old_stmt.elem.clone(), new_stmt.elem.clone());
+            let old_blocks = take_blocks(&mut old_header);
+            let new_blocks = take_blocks(&mut new_header);
+            if old_header == new_header && old_blocks.len() == new_blocks.len() && !old_blocks.is_empty() {
+                for (old_block, new_block) in old_blocks.iter().zip(new_blocks.iter()) {
+                    self.diff_block(old_block, new_block, next, depth + 1, edits);
+                }
+            } else {
+                let range = self.statement_tokens(old_stmt.location, next);
+                self.diff_tokens(range, &format_block(slice::from_ref(new_stmt), &self.indent, depth), edits);
+            }
+        }
+    }
+
+    /// Replace some raw tokens with printed text, leaving out the tokens at
+    /// either end which are unchanged.
+    fn diff_tokens(&self, range: Range<usize>, text: &str, edits: &mut Vec<Edit>) {
+        let context = Context::default();
+        let printed = lex(&context, self.file, text);
+        let old: Vec<usize> = range.filter(|&i| !is_trivial(&self.raw[i].token)).collect();
+        let new: Vec<usize> = (0..printed.len()).filter(|&i| !is_trivial(&printed[i].token)).collect();
+        let same = |a: usize, b: usize| same_token(&self.raw[old[a]].token, &printed[new[b]].token);
+
+        let mut prefix = 0;
+        while prefix < old.len() && prefix < new.len() && same(prefix, prefix) {
+            prefix += 1;
+        }
+        let mut suffix = 0;
+        while suffix < old.len() - prefix && suffix < new.len() - prefix && same(old.len() - 1 - suffix, new.len() - 1 - suffix) {
+            suffix += 1;
+        }
+        let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+        if prefix == old_end && prefix == new_end {
+            return;
+        }
+
+        if prefix == old_end {
+            // pure insertion, next to whichever side has an unchanged token
+            let (range, after, text) = if prefix > 0 {
+                let from = new[prefix - 1] + 1;
+                let limit = old.get(prefix).cloned().unwrap_or(self.raw.len());
+                let newline = (old[prefix - 1] + 1..limit)
+                    .find(|&i| self.raw[i].token == Token::Punct(Punctuation::Newline) && !self.raw[i].text.is_empty());
+                match newline {
+                    // new lines go after the end of the line, past any comment
+                    Some(newline) if printed[from].token == Token::Punct(Punctuation::Newline) => {
+                        let mut text = write_tokens(&printed[from + 1..new[new_end - 1] + 1], false);
+                        text.push_str(&String::from_utf8_lossy(&self.raw[newline].text));
+                        (newline + 1..newline + 1, true, text)
+                    }
+                    _ => {
+                        let at = old[prefix - 1] + 1;
+                        (at..at, true, write_tokens(&printed[from..new[new_end - 1] + 1], false))
+                    }
+                }
+            } else {
+                let at = old[prefix];
+                let mut text = write_tokens(&printed[new[prefix]..new[new_end]], true);
+                text.push_str(&String::from_utf8_lossy(&printed[new[new_end]].leading));
+                (at..at, false, text)
+            };
+            edits.push(Edit { range, after, text });
+        } else {
+            let text = if prefix == new_end {
+                String::new()
+            } else {
+                write_tokens(&printed[new[prefix]..new[new_end - 1] + 1], true)
+            };
+            edits.push(Edit {
+                range: old[prefix]..old[old_end - 1] + 1,
+                // a deletion also takes the whitespace before it
+                after: prefix == new_end,
+                text,
+            });
+        }
+    }
+
+    /// Convert an edit to a fix against the source text.
+    fn fix(&self, edit: Edit, description: &str) -> Fix {
+        let Edit { range, after, text } = edit;
+        let start = if range.start == range.end {
+            if after {
+                end_of(&self.raw[range.start - 1])
+            } else {
+                self.raw[range.start].location
+            }
+        } else if after && range.start > 0 && is_blank(&self.raw[range.start].leading) {
+            end_of(&self.raw[range.start - 1])
+        } else {
+            self.raw[range.start].location
+        };
+        let end = if range.start == range.end {
+            start
+        } else {
+            end_of(&self.raw[range.end - 1])
+        };
+        Fix::new(start..end, text, description)
+    }
+}
+
+fn lex(context: &Context, file: FileId, source: &str) -> Vec<RawToken> {
+    Lexer::new(context, file, source.bytes().map(Ok)).lossless().collect()
+}
+
+/// The index of the first item at or after a location, in items sorted by
+/// location.
+fn lower_bound<T, F: Fn(&T) -> Location>(items: &[T], location: Location, key: F) -> usize {
+    let (mut low, mut high) = (0, items.len());
+    while low < high {
+        let mid = (low + high) / 2;
+        if key(&items[mid]) < location {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+fn is_whitespace(token: &Token) -> bool {
+    match *token {
+        Token::Punct(Punctuation::Tab) | Token::Punct(Punctuation::Space) => true,
+        _ => false,
+    }
+}
+
+/// Whether a token only separates or lays out statements.
+fn is_trivial(token: &Token) -> bool {
+    match *token {
+        Token::Punct(Punctuation::Newline) |
+        Token::Punct(Punctuation::Semicolon) |
+        Token::DocComment(_) => true,
+        ref other => is_whitespace(other),
+    }
+}
+
+fn same_token(a: &Token, b: &Token) -> bool {
+    match (a, b) {
+        // whether whitespace follows is layout
+        (&Token::Ident(ref a, _), &Token::Ident(ref b, _)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn is_blank(text: &[u8]) -> bool {
+    text.iter().all(|&b| b == b' ' || b == b'\t')
+}
+
+/// Write out raw tokens, optionally without the leading trivia of the first.
+fn write_tokens(tokens: &[RawToken], skip_leading: bool) -> String {
+    let mut out = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 || !skip_leading {
+            out.extend_from_slice(&token.leading);
+        }
+        out.extend_from_slice(&token.text);
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The location just past the end of a raw token's text.
+fn end_of(token: &RawToken) -> Location {
+    let mut location = token.location;
+    for &b in token.text.iter() {
+        if b == b'\n' {
+            location.line += 1;
+            location.column = 1;
+        } else {
+            location = location.add_columns(1);
+        }
+    }
+    location
+}
+
+/// Take the blocks nested directly in a statement, in source order, leaving
+/// them empty.
+fn take_blocks(statement: &mut Statement) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    match *statement {
+        Statement::While(_, ref mut block) |
+        Statement::DoWhile(ref mut block, _) |
+        Statement::ForLoop { ref mut block, .. } |
+        Statement::ForList { ref mut block, .. } |
+        Statement::ForRange { ref mut block, .. } |
+        Statement::Spawn(_, ref mut block) |
+        Statement::Label(_, ref mut block) => blocks.push(mem::replace(block, Vec::new())),
+        Statement::If(ref mut arms, ref mut else_arm) => {
+            for &mut (_, ref mut block) in arms.iter_mut() {
+                blocks.push(mem::replace(block, Vec::new()));
+            }
+            if let Some(ref mut block) = *else_arm {
+                blocks.push(mem::replace(block, Vec::new()));
+            }
+        }
+        Statement::Switch(_, ref mut cases, ref mut default) => {
+            for &mut (_, ref mut block) in cases.iter_mut() {
+                blocks.push(mem::replace(block, Vec::new()));
+            }
+            if let Some(ref mut block) = *default {
+                blocks.push(mem::replace(block, Vec::new()));
+            }
+        }
+        Statement::TryCatch { ref mut try_block, ref mut catch_block, .. } => {
+            blocks.push(mem::replace(try_block, Vec::new()));
+            blocks.push(mem::replace(catch_block, Vec::new()));
+        }
+        _ => {}
+    }
+    blocks
+}
+
+/// Print a block of statements as source code, one line per statement, with
+/// each line indented `depth` times.
+pub fn format_block(block: &[Spanned<Statement>], indent: &str, depth: usize) -> String {
+    let mut out = String::new();
+    write_block(&mut out, block, indent, depth);
+    out
+}
+
+fn write_block(out: &mut String, block: &[Spanned<Statement>], indent: &str, depth: usize) {
+    for statement in block.iter() {
+        write_statement(out, &statement.elem, indent, depth);
+    }
+}
+
+fn write_line(out: &mut String, indent: &str, depth: usize, line: &str) {
+    for _ in 0..depth {
+        out.push_str(indent);
+    }
+    out.push_str(line);
+    out.push('\n');
+}
+
+fn write_statement(out: &mut String, statement: &Statement, indent: &str, depth: usize) {
+    match *statement {
+        Statement::While(ref cond, ref block) => {
+            write_line(out, indent, depth, &format!("while({})", cond));
+            write_block(out, block, indent, depth + 1);
+        }
+        Statement::DoWhile(ref block, ref cond) => {
+            write_line(out, indent, depth, "do");
+            write_block(out, block, indent, depth + 1);
+            write_line(out, indent, depth, &format!("while({})", cond));
+        }
+        Statement::If(ref arms, ref else_arm) => {
+            for (i, &(ref cond, ref block)) in arms.iter().enumerate() {
+                let keyword = if i == 0 { "if" } else { "else if" };
+                write_line(out, indent, depth, &format!("{}({})", keyword, cond));
+                write_block(out, block, indent, depth + 1);
+            }
+            if let Some(ref block) = *else_arm {
+                write_line(out, indent, depth, "else");
+                write_block(out, block, indent, depth + 1);
+            }
+        }
+        Statement::ForLoop { ref init, ref test, ref inc, ref block } => {
+            let clause = |statement: &Option<Box<Statement>>| match *statement {
+                Some(ref statement) => simple_statement(statement),
+                None => String::new(),
+            };
+            let test = match *test {
+                Some(ref test) => test.to_string(),
+                None => String::new(),
+            };
+            write_line(out, indent, depth, &format!("for({}; {}; {})", clause(init), test, clause(inc)));
+            write_block(out, block, indent, depth + 1);
+        }
+        Statement::ForList { ref var_type, ref name, input_type, ref in_list, ref block } => {
+            let mut header = format!("for({}", declared(var_type, name));
+            if !input_type.is_empty() {
+                header.push_str(&format!(" as {}", input_type));
+            }
+            if let Some(ref in_list) = *in_list {
+                header.push_str(&format!(" in {}", in_list));
+            }
+            header.push(')');
+            write_line(out, indent, depth, &header);
+            write_block(out, block, indent, depth + 1);
+        }
+        Statement::ForRange { ref var_type, ref name, ref start, ref end, ref step, ref block } => {
+            let mut header = format!("for({} = {} to {}", declared(var_type, name), start, end);
+            if let Some(ref step) = *step {
+                header.push_str(&format!(" step {}", step));
+            }
+            header.push(')');
+            write_line(out, indent, depth, &header);
+            write_block(out, block, indent, depth + 1);
+        }
+        Statement::Spawn(ref delay, ref block) => {
+            match *delay {
+                Some(ref delay) => write_line(out, indent, depth, &format!("spawn({})", delay)),
+                None => write_line(out, indent, depth, "spawn"),
+            }
+            write_block(out, block, indent, depth + 1);
+        }
+        Statement::Switch(ref expr, ref cases, ref default) => {
+            write_line(out, indent, depth, &format!("switch({})", expr));
+            for &(ref case, ref block) in cases.iter() {
+                let case: Vec<String> = case.iter().map(|each| match *each {
+                    Case::Exact(ref expr) => expr.to_string(),
+                    Case::Range(ref start, ref end) => format!("{} to {}", start, end),
+                }).collect();
+                write_line(out, indent, depth + 1, &format!("if({})", case.join(", ")));
+                write_block(out, block, indent, depth + 2);
+            }
+            if let Some(ref block) = *default {
+                write_line(out, indent, depth + 1, "else");
+                write_block(out, block, indent, depth + 2);
+            }
+        }
+        Statement::TryCatch { ref try_block, ref catch_param, ref catch_block } => {
+            write_line(out, indent, depth, "try");
+            write_block(out, try_block, indent, depth + 1);
+            match *catch_param {
+                Some((ref var_type, ref name)) => write_line(out, indent, depth, &format!("catch({})", declared(var_type, name))),
+                None => write_line(out, indent, depth, "catch"),
+            }
+            write_block(out, catch_block, indent, depth + 1);
+        }
+        Statement::Label(ref name, ref block) => {
+            write_line(out, indent, depth, &format!("{}:", name));
+            write_block(out, block, indent, depth + 1);
+        }
+        _ => write_line(out, indent, depth, &simple_statement(statement)),
+    }
+}
+
+/// Print a statement which fits on one line, such as a for-loop clause.
+fn simple_statement(statement: &Statement) -> String {
+    match *statement {
+        Statement::Expr(ref expr) => expr.to_string(),
+        Statement::Return(None) => "return".to_owned(),
+        Statement::Return(Some(ref expr)) => format!("return {}", expr),
+        Statement::Throw(ref expr) => format!("throw {}", expr),
+        Statement::Var(ref var) => format!("var{}", var_statement(var)),
+        Statement::Vars(ref vars) => {
+            // later declarations continue the `var` of the first
+            let mut text = "var".to_owned();
+            for (i, var) in vars.iter().enumerate() {
+                let each = var_statement(var);
+                if i == 0 {
+                    text.push_str(&each);
+                } else {
+                    text.push_str(", ");
+                    text.push_str(&each[1..]);
+                }
+            }
+            text
+        }
+        Statement::Setting(ref name, SettingMode::Assign, ref value) => format!("set {} = {}", name, value),
+        Statement::Setting(ref name, SettingMode::In, ref value) => format!("set {} in {}", name, value),
+        Statement::Continue(None) => "continue".to_owned(),
+        Statement::Continue(Some(ref label)) => format!("continue {}", label),
+        Statement::Break(None) => "break".to_owned(),
+        Statement::Break(Some(ref label)) => format!("break {}", label),
+        Statement::Goto(ref label) => format!("goto {}", label),
+        ref other => {
+            let mut text = String::new();
+            write_statement(&mut text, other, "", 0);
+            text.pop();
+            text
+        }
+    }
+}
+
+/// Print the part of a var statement after `var`, starting with a slash.
+fn var_statement(var: &VarStatement) -> String {
+    match var.value {
+        Some(ref value) => format!("{}/{} = {}", var.var_type, var.name, value),
+        None => format!("{}/{}", var.var_type, var.name),
+    }
+}
+
+/// Print a loop variable or catch parameter, which is declared in place if
+/// it has a type.
+fn declared(var_type: &Option<VarType>, name: &str) -> String {
+    match *var_type {
+        Some(ref var_type) => format!("var{}/{}", var_type, name),
+        None => name.to_owned(),
+    }
+}
@@ -0,0 +1,200 @@
+//! Mapping of BYOND runtime errors back to the source.
+//!
+//! Runtime errors are written to `world.log` with a call stack in which each
+//! frame names the type of `src` and the proc being run, such as
+//! `Guy (/mob): Attack(null)`. These are matched against the object tree to
+//! find where each proc is defined.
+use std::path::Path;
+
+use objtree::{ObjectTree, TypeRef, ProcValue};
+use {Context, Location};
+
+/// One runtime error read from the log.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RuntimeError {
+    /// The message following `runtime error:`.
+    pub message: String,
+    /// The full path of the proc in which the error occurred, such as
+    /// `/mob/proc/Attack`.
+    pub proc_path: Option<String>,
+    /// The file and line of the error, reported when the environment was
+    /// compiled in debug mode.
+    pub source: Option<(String, u32)>,
+    /// The call stack, innermost first.
+    pub frames: Vec<Frame>,
+}
+
+/// One entry in the call stack of a runtime error.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Frame {
+    /// The line as it appeared in the log.
+    pub text: String,
+    /// The type of `src`, or `None` for global procs.
+    pub src_type: Option<String>,
+    /// The type on which the proc is defined, if known.
+    pub proc_type: Option<String>,
+    /// The name of the proc.
+    pub proc_name: String,
+}
+
+/// Split a proc path such as `/mob/proc/Attack` into the type path and proc
+/// name. Global procs have an empty type path.
+fn split_proc_path(path: &str) -> Option<(&str, &str)> {
+    for keyword in &["/proc/", "/verb/"] {
+        if let Some(idx) = path.rfind(keyword) {
+            return Some((&path[..idx], &path[idx + keyword.len()..]));
+        }
+    }
+    None
+}
+
+/// Parse one line of a call stack, or `None` if it is not a frame.
+fn parse_frame(line: &str) -> Option<Frame> {
+    if !line.ends_with(')') {
+        return None;
+    }
+
+    // `Guy (/mob): Attack(null)`, where the name may itself contain parens
+    let mut src_type = None;
+    let mut call = line;
+    for (idx, _) in line.match_indices("): ") {
+        if let Some(open) = line[..idx].rfind(" (") {
+            let path = &line[open + 2..idx];
+            if path.starts_with('/') && !path.contains(char::is_whitespace) {
+                src_type = Some(path.to_owned());
+                call = &line[idx + 3..];
+                break;
+            }
+        }
+    }
+    if src_type.is_none() && line.starts_with("world: ") {
+        src_type = Some("/world".to_owned());
+        call = &line["world: ".len()..];
+    }
+
+    let name = call[..call.find('(')?].trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    let (proc_type, proc_name) = match split_proc_path(name) {
+        Some((path, name)) => (Some(path.to_owned()), name),
+        None => (None, name),
+    };
+    Some(Frame {
+        text: line.to_owned(),
+        src_type,
+        proc_type,
+        proc_name: proc_name.to_owned(),
+    })
+}
+
+/// Parse the runtime errors out of a log, ignoring any other output.
+pub fn parse_log(text: &str) -> Vec<RuntimeError> {
+    let mut result: Vec<RuntimeError> = Vec::new();
+    let mut in_stack = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(idx) = line.find("runtime error: ") {
+            result.push(RuntimeError {
+                message: line[idx + "runtime error: ".len()..].to_owned(),
+                .. RuntimeError::default()
+            });
+            in_stack = false;
+            continue;
+        }
+        let current = match result.last_mut() {
+            Some(current) => current,
+            None => continue,
+        };
+
+        if in_stack {
+            match parse_frame(line) {
+                Some(frame) => current.frames.push(frame),
+                None if line == "..." => {}
+                None => in_stack = false,
+            }
+        } else if line.starts_with("proc name: ") {
+            // `proc name: Attack (/mob/proc/Attack)`
+            if let (Some(open), true) = (line.rfind(" ("), line.ends_with(')')) {
+                current.proc_path = Some(line[open + 2..line.len() - 1].to_owned());
+            }
+        } else if line.starts_with("source file: ") {
+            // `source file: code/mob.dm,12`
+            let rest = &line["source file: ".len()..];
+            if let Some(comma) = rest.rfind(',') {
+                if let Ok(number) = rest[comma + 1..].trim().parse() {
+                    current.source = Some((rest[..comma].to_owned(), number));
+                }
+            }
+        } else if line == "call stack:" {
+            in_stack = true;
+        }
+    }
+
+    // the innermost frame is in the proc named above the stack, which may be
+    // a parent's definition reached through `..()`
+    for each in result.iter_mut() {
+        if let (Some(path), Some(first)) = (each.proc_path.as_ref(), each.frames.first_mut()) {
+            if let Some((ty, name)) = split_proc_path(path) {
+                if first.proc_name == name {
+                    first.proc_type = Some(ty.to_owned());
+                }
+            }
+        }
+    }
+    result
+}
+
+/// A frame of a runtime error joined against the object tree.
+#[derive(Debug, Clone)]
+pub struct ResolvedFrame<'a> {
+    pub frame: &'a Frame,
+    /// The type on which the proc is defined, if found.
+    pub ty: Option<TypeRef<'a>>,
+    /// The most specific definition of the proc, if found.
+    pub proc: Option<&'a ProcValue>,
+    /// Where the frame was executing: the line of the error for the
+    /// innermost frame if it was reported, otherwise the proc's definition
+    /// unless it is a builtin.
+    pub location: Option<Location>,
+}
+
+/// Resolve each frame of a runtime error against the object tree.
+pub fn resolve<'a>(context: &Context, tree: &'a ObjectTree, error: &'a RuntimeError) -> Vec<ResolvedFrame<'a>> {
+    error.frames.iter().enumerate().map(|(i, frame)| {
+        let ty = match frame.proc_type.as_ref().or(frame.src_type.as_ref()) {
+            Some(path) if !path.is_empty() => tree.find(path),
+            _ => Some(tree.root()),
+        }.and_then(|ty| defining_type(ty, &frame.proc_name));
+        let proc = ty.and_then(|ty| ty.get_proc(&frame.proc_name));
+        let mut location = proc.map(|proc| proc.location).and_then(|location| {
+            if location.is_builtins() { None } else { Some(location) }
+        });
+        if i == 0 {
+            if let Some((ref path, line)) = error.source {
+                if let Some(file) = context.get_file(Path::new(path)) {
+                    location = Some(Location { file, line, column: 1 });
+                }
+            }
+        }
+        ResolvedFrame {
+            frame,
+            ty,
+            proc,
+            location,
+        }
+    }).collect()
+}
+
+/// The type on which a proc is defined, searching upwards from the given
+/// type as `get_proc` does.
+fn defining_type<'a>(ty: TypeRef<'a>, name: &str) -> Option<TypeRef<'a>> {
+    let mut current = Some(ty);
+    while let Some(ty) = current {
+        if ty.get().procs.get(name).map_or(false, |proc| !proc.value.is_empty()) {
+            return Some(ty);
+        }
+        current = ty.parent_type();
+    }
+    None
+}
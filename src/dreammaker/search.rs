@@ -0,0 +1,413 @@
+//! Structural search over proc bodies.
+//!
+//! A pattern describes the shape of an expression rather than its text:
+//!
+//! * `call("name", args...)` matches a call to a proc of that name, whether
+//!   unscoped as in `name()` or on a value as in `src.name()`.
+//! * `assign("name")` matches an assignment to a var of that name, including
+//!   compound assignments such as `+=`. A second argument matches the value
+//!   being assigned, as in `assign("health", CONST)`.
+//! * `_` matches any expression, and `CONST` any constant expression.
+//! * Strings, numbers, and `null` match themselves.
+//!
+//! In place of a name, `_` matches any name. At the end of an argument list,
+//! `..` matches any number of further arguments. A pattern may end with
+//! `in /path` to search only the procs of that type and its subtypes.
+//!
+//! For example, `call("addtimer", _, CONST)` finds two-argument `addtimer`
+//! calls whose delay is a constant, and `assign("health") in /mob` finds
+//! every assignment to `health` in procs of `/mob` and its subtypes.
+use ast::*;
+use constants::{self, Constant};
+use objtree::{ObjectTree, TypeRef};
+use visit::*;
+use super::{Context, DMError, Location, FileId};
+
+/// A parsed search pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    expr: ExprPattern,
+    scope: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ExprPattern {
+    Any,
+    Const,
+    Literal(Constant),
+    Call {
+        name: Option<String>,
+        args: Vec<ExprPattern>,
+        rest: bool,
+    },
+    Assign {
+        name: Option<String>,
+        value: Option<Box<ExprPattern>>,
+    },
+}
+
+/// An expression matching a pattern.
+#[derive(Debug, Clone)]
+pub struct Match<'a> {
+    /// The type whose proc contains the expression.
+    pub ty: TypeRef<'a>,
+    /// The name of the proc containing the expression.
+    pub proc: &'a str,
+    /// The location of the proc definition containing the expression.
+    pub location: Location,
+    /// The matching expression, as source code.
+    pub text: String,
+}
+
+impl Pattern {
+    /// Parse a pattern. Errors are located by their column in `text`.
+    pub fn parse(text: &str) -> Result<Pattern, DMError> {
+        let mut parser = PatternParser { text, pos: 0 };
+        let expr = parser.expr()?;
+        let scope = if parser.keyword("in") {
+            Some(parser.path()?)
+        } else {
+            None
+        };
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("unexpected input after pattern"));
+        }
+        Ok(Pattern { expr, scope })
+    }
+
+    /// The type path to which the search is limited, if any.
+    pub fn scope(&self) -> Option<&str> {
+        self.scope.as_ref().map(|s| s.as_str())
+    }
+
+    /// Check whether an expression matches this pattern, not counting its
+    /// subexpressions.
+    pub fn matches(&self, expr: &Expression) -> bool {
+        self.expr.matches(expr)
+    }
+
+    /// Find the expressions in a block which match this pattern, in source
+    /// order. The scope of the pattern is not considered.
+    pub fn find_in<'ast>(&self, block: &'ast [Spanned<Statement>]) -> Vec<&'ast Expression> {
+        let mut finder = Finder { pattern: &self.expr, found: Vec::new() };
+        finder.visit_block(block);
+        finder.found
+    }
+
+    /// Search every proc in the tree, parsing lazily-collected bodies as
+    /// needed. Matches are sorted by the location of their proc.
+    ///
+    /// If the pattern's scope names a type which does not exist, nothing
+    /// matches.
+    pub fn search<'a>(&self, context: &Context, tree: &'a ObjectTree) -> Vec<Match<'a>> {
+        let scope = match self.scope {
+            Some(ref path) => match tree.find(path) {
+                Some(ty) => Some(ty),
+                None => return Vec::new(),
+            },
+            None => None,
+        };
+
+        let mut result = Vec::new();
+        for idx in tree.graph.node_indices() {
+            let ty = TypeRef::new(tree, idx);
+            if let Some(scope) = scope {
+                if !ty.is_subtype_of(scope.get()) {
+                    continue;
+                }
+            }
+            for (name, proc) in ty.get().procs.iter() {
+                for value in proc.value.iter() {
+                    if let Some(body) = value.body(context) {
+                        for expr in self.find_in(&body) {
+                            result.push(Match {
+                                ty,
+                                proc: name,
+                                location: value.location,
+                                text: expr.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        // stable, so matches within a proc keep their order
+        result.sort_by_key(|each| each.location);
+        result
+    }
+}
+
+impl ExprPattern {
+    fn matches(&self, expr: &Expression) -> bool {
+        match *self {
+            ExprPattern::Any => true,
+            ExprPattern::Const => evaluate(expr).is_some(),
+            ExprPattern::Literal(ref literal) => match evaluate(expr) {
+                Some(value) => same_literal(literal, &value),
+                None => false,
+            },
+            ExprPattern::Call { ref name, ref args, rest } => calls(expr).iter().any(|&(called, actual)| {
+                same_name(name, called)
+                    && (actual.len() == args.len() || (rest && actual.len() > args.len()))
+                    && args.iter().zip(actual.iter()).all(|(pattern, arg)| pattern.matches(arg))
+            }),
+            ExprPattern::Assign { ref name, ref value } => match *expr {
+                Expression::AssignOp { ref lhs, ref rhs, .. } => {
+                    assigned_name(lhs).map_or(false, |assigned| same_name(name, assigned))
+                        && value.as_ref().map_or(true, |value| value.matches(rhs))
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+struct Finder<'p, 'ast> {
+    pattern: &'p ExprPattern,
+    found: Vec<&'ast Expression>,
+}
+
+impl<'p, 'ast> Visitor<'ast> for Finder<'p, 'ast> {
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        if self.pattern.matches(expr) {
+            self.found.push(expr);
+        }
+        walk_expression(self, expr);
+    }
+}
+
+fn evaluate(expr: &Expression) -> Option<Constant> {
+    constants::simple_evaluate(Location::default(), expr.clone()).ok()
+}
+
+fn same_literal(pattern: &Constant, value: &Constant) -> bool {
+    match (pattern, value) {
+        (&Constant::Null(_), &Constant::Null(_)) => true,
+        (&Constant::String(ref a), &Constant::String(ref b)) => a == b,
+        _ => match (pattern.to_float(), value.to_float()) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        },
+    }
+}
+
+fn same_name(pattern: &Option<String>, name: &str) -> bool {
+    pattern.as_ref().map_or(true, |pattern| pattern == name)
+}
+
+/// The calls made directly by an expression, including each call in a
+/// chain such as `a.b().c()`.
+fn calls(expr: &Expression) -> Vec<(&str, &[Expression])> {
+    let mut result = Vec::new();
+    if let Expression::Base { ref term, ref follow, .. } = *expr {
+        if let Term::Call(ref name, ref args) = *term {
+            result.push((name.as_str(), &args[..]));
+        }
+        for each in follow.iter() {
+            if let Follow::Call(_, ref name, ref args) = *each {
+                result.push((name.as_str(), &args[..]));
+            }
+        }
+    }
+    result
+}
+
+/// The name of the var assigned to by the left side of an assignment, as
+/// in `health = 1` or `M.health = 1`.
+fn assigned_name(lhs: &Expression) -> Option<&str> {
+    match *lhs {
+        Expression::Base { ref term, ref follow, .. } => match follow.last() {
+            Some(&Follow::Field(_, ref name)) => Some(name.as_str()),
+            Some(_) => None,
+            None => match *term {
+                Term::Ident(ref name) => Some(name.as_str()),
+                Term::Expr(ref inner) => assigned_name(inner),
+                _ => None,
+            },
+        },
+        _ => None,
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Pattern syntax
+
+struct PatternParser<'s> {
+    text: &'s str,
+    pos: usize,
+}
+
+impl<'s> PatternParser<'s> {
+    fn error<S: Into<String>>(&self, desc: S) -> DMError {
+        DMError::new(Location {
+            file: FileId::default(),
+            line: 1,
+            column: self.pos as u16 + 1,
+        }, desc)
+    }
+
+    fn rest(&self) -> &'s str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_left().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), DMError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", token)))
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'s str> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        if len == 0 {
+            return None;
+        }
+        self.pos += len;
+        Some(&rest[..len])
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let start = self.pos;
+        if self.ident() == Some(keyword) {
+            true
+        } else {
+            self.pos = start;
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<ExprPattern, DMError> {
+        self.skip_whitespace();
+        match self.rest().chars().next() {
+            Some('"') => return Ok(ExprPattern::Literal(Constant::String(self.string()?))),
+            Some(c) if c == '-' || c.is_digit(10) => return self.number().map(ExprPattern::Literal),
+            _ => {}
+        }
+
+        let start = self.pos;
+        match self.ident() {
+            Some("_") => Ok(ExprPattern::Any),
+            Some("CONST") => Ok(ExprPattern::Const),
+            Some("null") => Ok(ExprPattern::Literal(Constant::Null(None))),
+            Some("call") => {
+                self.expect("(")?;
+                let name = self.name()?;
+                let mut args = Vec::new();
+                let mut rest = false;
+                while self.eat(",") {
+                    if self.eat("..") {
+                        rest = true;
+                        break;
+                    }
+                    args.push(self.expr()?);
+                }
+                self.expect(")")?;
+                Ok(ExprPattern::Call { name, args, rest })
+            }
+            Some("assign") => {
+                self.expect("(")?;
+                let name = self.name()?;
+                let value = if self.eat(",") {
+                    Some(Box::new(self.expr()?))
+                } else {
+                    None
+                };
+                self.expect(")")?;
+                Ok(ExprPattern::Assign { name, value })
+            }
+            Some(other) => {
+                self.pos = start;
+                Err(self.error(format!("unknown pattern '{}'", other)))
+            }
+            None => Err(self.error("expected a pattern")),
+        }
+    }
+
+    /// A quoted name, or `_` for any name.
+    fn name(&mut self) -> Result<Option<String>, DMError> {
+        self.skip_whitespace();
+        if self.rest().starts_with('"') {
+            return self.string().map(Some);
+        }
+        let start = self.pos;
+        if self.ident() == Some("_") {
+            return Ok(None);
+        }
+        self.pos = start;
+        Err(self.error("expected a quoted name or '_'"))
+    }
+
+    fn string(&mut self) -> Result<String, DMError> {
+        self.expect("\"")?;
+        let mut result = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(result);
+                }
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => result.push(escaped),
+                    None => break,
+                },
+                other => result.push(other),
+            }
+        }
+        self.pos = self.text.len();
+        Err(self.error("unterminated string"))
+    }
+
+    fn number(&mut self) -> Result<Constant, DMError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest.char_indices()
+            .find(|&(i, c)| !(c.is_digit(10) || c == '.' || (i == 0 && c == '-')))
+            .map_or(rest.len(), |(i, _)| i);
+        let text = &rest[..len];
+        let result = if text.contains('.') {
+            text.parse::<f32>().ok().map(Constant::from)
+        } else {
+            text.parse::<i32>().ok().map(Constant::Int)
+        };
+        match result {
+            Some(constant) => {
+                self.pos += len;
+                Ok(constant)
+            }
+            None => Err(self.error(format!("invalid number '{}'", text))),
+        }
+    }
+
+    /// A type path such as `/mob/living`.
+    fn path(&mut self) -> Result<String, DMError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        if !rest.starts_with('/') {
+            return Err(self.error("expected a type path"));
+        }
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        self.pos += len;
+        let path = rest[..len].trim_right_matches('/');
+        Ok(path.to_owned())
+    }
+}
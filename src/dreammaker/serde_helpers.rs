@@ -0,0 +1,85 @@
+//! Serialization for foreign types which lack `serde` support.
+
+/// `LinkedHashMap`s as order-preserving maps.
+pub mod linked_hash_map {
+    use std::fmt;
+    use std::hash::Hash;
+    use std::marker::PhantomData;
+
+    use linked_hash_map::LinkedHashMap;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use serde::de::{Visitor, MapAccess};
+
+    pub fn serialize<K, V, S>(map: &LinkedHashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize + Hash + Eq,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_map(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<LinkedHashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Hash + Eq,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(MapVisitor(PhantomData))
+    }
+
+    struct MapVisitor<K, V>(PhantomData<(K, V)>);
+
+    impl<'de, K, V> Visitor<'de> for MapVisitor<K, V>
+    where
+        K: Deserialize<'de> + Hash + Eq,
+        V: Deserialize<'de>,
+    {
+        type Value = LinkedHashMap<K, V>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a map")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+            let mut map = LinkedHashMap::new();
+            while let Some((key, value)) = access.next_entry()? {
+                map.insert(key, value);
+            }
+            Ok(map)
+        }
+    }
+}
+
+/// `N32`s as plain floats.
+pub mod n32 {
+    use noisy_float::prelude::*;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(value: &N32, serializer: S) -> Result<S::Ok, S::Error> {
+        value.raw().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<N32, D::Error> {
+        let value = f32::deserialize(deserializer)?;
+        if value.is_nan() {
+            return Err(D::Error::custom("NaN is not a valid constant"));
+        }
+        Ok(n32(value))
+    }
+}
+
+/// `NodeIndex`es as plain integers.
+pub mod node_index {
+    use petgraph::graph::NodeIndex;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+    pub fn serialize<S: Serializer>(value: &NodeIndex, serializer: S) -> Result<S::Ok, S::Error> {
+        (value.index() as u64).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NodeIndex, D::Error> {
+        Ok(NodeIndex::new(u64::deserialize(deserializer)? as usize))
+    }
+}
@@ -0,0 +1,389 @@
+//! Structured parsing of escape sequences and text macros in strings.
+//!
+//! The lexer leaves escape sequences in string literals as-is. A
+//! `StringFormat` splits such a string into literal text, escapes, text
+//! macros, and the positions of embedded expressions, and can reproduce the
+//! original source text exactly.
+//!
+//! A `StringTable` collects the plain string literals of a token stream to
+//! find strings which are repeated throughout an environment.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use ast::Term;
+use lexer::{LocatedToken, Token};
+use preprocessor::Preprocessor;
+use Location;
+
+/// Text macros which BYOND recognizes after a backslash.
+pub const TEXT_MACROS: &[&str] = &[
+    // articles and proper nouns
+    "the", "The", "a", "an", "A", "An", "proper", "improper",
+    // pronouns
+    "he", "He", "she", "She", "his", "His", "him", "himself", "herself", "hers", "Hers",
+    // numbers
+    "th", "s", "roman", "Roman",
+    // special
+    "ref", "icon", "...",
+    // colors
+    "black", "blue", "green", "red",
+];
+
+/// Text macros which apply to the embedded expression after them.
+pub const FOLLOWING_MACROS: &[&str] = &["the", "The", "a", "an", "A", "An", "roman", "Roman", "ref", "icon"];
+
+/// Text macros which apply to the last embedded expression before them.
+pub const PRECEDING_MACROS: &[&str] = &[
+    "he", "He", "she", "She", "his", "His", "him", "himself", "herself", "hers", "Hers", "th", "s",
+];
+
+/// Text macros which only take effect at the start of a string.
+pub const LEADING_MACROS: &[&str] = &["proper", "improper"];
+
+/// Text macros which expect a number rather than an atom.
+pub const NUMBER_MACROS: &[&str] = &["th", "s", "roman", "Roman"];
+
+/// Characters which form escape sequences on their own.
+pub const ESCAPES: &[char] = &['n', 't', '"', '\'', '\\', '[', ']', '<', '>', ' '];
+
+/// One piece of a string literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatPiece {
+    /// Literal text, containing no escapes.
+    Text(String),
+    /// A single-character escape sequence such as `\n` or `\"`.
+    Escape(char),
+    /// A text macro such as `\the` or `\ref`, without the backslash.
+    Macro(String),
+    /// An embedded expression, by its index among the string's expressions.
+    Embed(usize),
+}
+
+impl FormatPiece {
+    /// Check whether this piece is a recognized escape or macro.
+    ///
+    /// Text and embedded expressions are always recognized.
+    pub fn is_known(&self) -> bool {
+        match *self {
+            FormatPiece::Escape(ch) => ESCAPES.contains(&ch),
+            FormatPiece::Macro(ref name) => TEXT_MACROS.contains(&name.as_str()),
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for FormatPiece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatPiece::Text(ref text) => f.write_str(text),
+            FormatPiece::Escape(ch) => write!(f, "\\{}", ch),
+            FormatPiece::Macro(ref name) => write!(f, "\\{}", name),
+            FormatPiece::Embed(_) => f.write_str("[]"),
+        }
+    }
+}
+
+/// The structure of a string literal or interpolated string.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StringFormat {
+    pub pieces: Vec<FormatPiece>,
+}
+
+impl StringFormat {
+    /// Parse the literal segments of a string, as produced by the lexer.
+    ///
+    /// Between each consecutive pair of segments is an embedded expression.
+    pub fn parse<S: AsRef<str>>(segments: &[S]) -> StringFormat {
+        let mut format = StringFormat::default();
+        for (i, segment) in segments.iter().enumerate() {
+            if i != 0 {
+                format.pieces.push(FormatPiece::Embed(i - 1));
+            }
+            format.parse_segment(segment.as_ref());
+        }
+        format
+    }
+
+    fn parse_segment(&mut self, segment: &str) {
+        let mut text = String::new();
+        let mut chars = segment.char_indices().peekable();
+        while let Some((_, ch)) = chars.next() {
+            if ch != '\\' {
+                text.push(ch);
+                continue;
+            }
+            let start = match chars.peek() {
+                Some(&(idx, _)) => idx,
+                None => {
+                    // a trailing backslash is left as text
+                    text.push(ch);
+                    break;
+                }
+            };
+            if !text.is_empty() {
+                self.pieces.push(FormatPiece::Text(::std::mem::replace(&mut text, String::new())));
+            }
+
+            // take the longest macro the following text starts with
+            let rest = &segment[start..];
+            let found = TEXT_MACROS.iter()
+                .filter(|name| rest.starts_with(*name))
+                .max_by_key(|name| name.len());
+            match found {
+                Some(name) if name.len() > 1 || !ESCAPES.contains(&name.chars().next().unwrap()) => {
+                    for _ in 0..name.chars().count() {
+                        chars.next();
+                    }
+                    self.pieces.push(FormatPiece::Macro(name.to_string()));
+                }
+                _ => {
+                    let (_, escaped) = chars.next().unwrap();
+                    self.pieces.push(FormatPiece::Escape(escaped));
+                }
+            }
+        }
+        if !text.is_empty() {
+            self.pieces.push(FormatPiece::Text(text));
+        }
+    }
+
+    /// Reproduce the literal segments this format was parsed from.
+    pub fn segments(&self) -> Vec<String> {
+        let mut result = vec![String::new()];
+        for piece in self.pieces.iter() {
+            match *piece {
+                FormatPiece::Embed(_) => result.push(String::new()),
+                ref other => {
+                    use std::fmt::Write;
+                    let _ = write!(result.last_mut().unwrap(), "{}", other);
+                }
+            }
+        }
+        result
+    }
+
+    /// Iterate over the text macros used in this string.
+    pub fn macros<'a>(&'a self) -> impl Iterator<Item=&'a str> + 'a {
+        self.pieces.iter().filter_map(|piece| match *piece {
+            FormatPiece::Macro(ref name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Iterate over any unrecognized escapes or macros in this string.
+    pub fn unknown<'a>(&'a self) -> impl Iterator<Item=&'a FormatPiece> + 'a {
+        self.pieces.iter().filter(|piece| !piece.is_known())
+    }
+
+    /// Determine what each text macro in this string applies to.
+    pub fn macro_uses(&self) -> Vec<MacroUse> {
+        let mut result = Vec::new();
+        let mut last_embed = None;
+        for (i, piece) in self.pieces.iter().enumerate() {
+            let name = match *piece {
+                FormatPiece::Embed(idx) => {
+                    last_embed = Some(idx);
+                    continue;
+                }
+                FormatPiece::Macro(ref name) => name.as_str(),
+                _ => continue,
+            };
+            let target = if FOLLOWING_MACROS.contains(&name) {
+                // only whitespace may come between the macro and its target
+                let next = self.pieces[i + 1..].iter().find(|piece| match **piece {
+                    FormatPiece::Text(ref text) => !text.trim().is_empty(),
+                    _ => true,
+                });
+                match next {
+                    Some(&FormatPiece::Embed(idx)) => MacroTarget::Embed(idx),
+                    _ => MacroTarget::Ignored,
+                }
+            } else if PRECEDING_MACROS.contains(&name) {
+                last_embed.map_or(MacroTarget::Ignored, MacroTarget::Embed)
+            } else if LEADING_MACROS.contains(&name) {
+                if i == 0 { MacroTarget::Whole } else { MacroTarget::Ignored }
+            } else {
+                MacroTarget::Whole
+            };
+            result.push(MacroUse { name, target });
+        }
+        result
+    }
+}
+
+/// A text macro in a string and what it applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacroUse<'a> {
+    /// The macro's name, without the backslash.
+    pub name: &'a str,
+    pub target: MacroTarget,
+}
+
+impl<'a> MacroUse<'a> {
+    /// Explain why BYOND ignores this macro, if it does.
+    pub fn ignored_reason(&self) -> Option<String> {
+        if self.target != MacroTarget::Ignored {
+            return None;
+        }
+        Some(if FOLLOWING_MACROS.contains(&self.name) {
+            format!("\\{} is not followed by an embedded expression and is ignored", self.name)
+        } else if PRECEDING_MACROS.contains(&self.name) {
+            format!("\\{} has no embedded expression before it and is ignored", self.name)
+        } else {
+            format!("\\{} is ignored except at the start of a string", self.name)
+        })
+    }
+
+    /// Whether this macro expects a number rather than an atom.
+    pub fn wants_number(&self) -> bool {
+        NUMBER_MACROS.contains(&self.name)
+    }
+}
+
+/// What a text macro applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroTarget {
+    /// The embedded expression with the given index.
+    Embed(usize),
+    /// The string as a whole, or nothing in particular.
+    Whole,
+    /// Nothing, as the macro is placed where BYOND ignores it.
+    Ignored,
+}
+
+impl Term {
+    /// Parse the escape sequences of a string or interpolated string term.
+    pub fn string_format(&self) -> Option<StringFormat> {
+        match *self {
+            Term::String(ref text) => Some(StringFormat::parse(&[text])),
+            Term::InterpString(ref begin, ref parts) => {
+                let mut segments = vec![begin];
+                segments.extend(parts.iter().map(|&(_, ref text, _)| text));
+                Some(StringFormat::parse(&segments))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The plain string literals of a token stream and where each appears.
+#[derive(Debug, Clone, Default)]
+pub struct StringTable {
+    strings: BTreeMap<String, Vec<Location>>,
+}
+
+/// A string literal which appears more than once.
+#[derive(Debug, Clone, Copy)]
+pub struct Duplicate<'a> {
+    pub text: &'a str,
+    pub locations: &'a [Location],
+}
+
+impl<'a> Duplicate<'a> {
+    /// Estimate the bytes of source saved by defining this string once.
+    pub fn savings(&self) -> usize {
+        // each repeat spends the literal and its quotes
+        (self.locations.len() - 1) * (self.text.len() + 2)
+    }
+}
+
+impl StringTable {
+    /// Collect every plain string literal in a token stream.
+    ///
+    /// Interpolated strings are skipped, as their text is not a constant.
+    pub fn from_tokens<I: IntoIterator<Item=LocatedToken>>(tokens: I) -> StringTable {
+        let mut table = StringTable::default();
+        for token in tokens {
+            if let Token::String(text) = token.token {
+                table.add(token.location, text);
+            }
+        }
+        table
+    }
+
+    /// Collect every plain string literal which a preprocessor produces.
+    ///
+    /// A string which comes from a macro is recorded once, at the macro's
+    /// `#define`, rather than at each place the macro is used.
+    pub fn from_preprocessor(preprocessor: &mut Preprocessor) -> StringTable {
+        let mut table = StringTable::default();
+        let mut expanded = BTreeSet::new();
+        while let Some(token) = preprocessor.next() {
+            if let Token::String(text) = token.token {
+                match preprocessor.expanded_from() {
+                    Some(define_loc) => if expanded.insert((define_loc, text.clone())) {
+                        table.add(define_loc, text);
+                    },
+                    None => table.add(token.location, text),
+                }
+            }
+        }
+        table
+    }
+
+    /// Record a string literal at the given location.
+    pub fn add(&mut self, location: Location, text: String) {
+        self.strings.entry(text).or_insert_with(Vec::new).push(location);
+    }
+
+    /// The number of distinct strings.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Check whether no strings were found.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// The number of string literals, counting repeats.
+    pub fn total(&self) -> usize {
+        self.strings.values().map(|locations| locations.len()).sum()
+    }
+
+    /// The locations at which a string appears.
+    pub fn locations(&self, text: &str) -> &[Location] {
+        self.strings.get(text).map_or(&[], |locations| &locations[..])
+    }
+
+    /// Find strings which appear more than once, greatest savings first.
+    ///
+    /// The empty string is excluded, as there is nothing to save.
+    pub fn duplicates(&self) -> Vec<Duplicate> {
+        let mut result: Vec<_> = self.strings.iter()
+            .filter(|&(text, locations)| !text.is_empty() && locations.len() > 1)
+            .map(|(text, locations)| Duplicate { text, locations })
+            .collect();
+        result.sort_by(|a, b| b.savings().cmp(&a.savings()).then(a.text.cmp(b.text)));
+        result
+    }
+
+    /// Find groups of distinct strings which differ only in case,
+    /// whitespace, or trailing punctuation.
+    pub fn near_duplicates(&self) -> Vec<Vec<&str>> {
+        let mut groups: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        for text in self.strings.keys() {
+            let key = normalize(text);
+            if !key.is_empty() {
+                groups.entry(key).or_insert_with(Vec::new).push(text);
+            }
+        }
+        groups.into_iter()
+            .map(|(_, group)| group)
+            .filter(|group| group.len() > 1)
+            .collect()
+    }
+}
+
+/// Reduce a string to the form compared by `near_duplicates`.
+fn normalize(text: &str) -> String {
+    let trimmed = text.trim().trim_right_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace());
+    let mut result = String::with_capacity(trimmed.len());
+    for word in trimmed.split_whitespace() {
+        if !result.is_empty() {
+            result.push(' ');
+        }
+        result.extend(word.chars().flat_map(char::to_lowercase));
+    }
+    result
+}
@@ -0,0 +1,619 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::ast::VarType;
+
+fn check(code: &str) -> Vec<String> {
+    check_with_lints(code, &[])
+}
+
+fn check_with_lints(code: &str, lints: &[&str]) -> Vec<String> {
+    let context = dm::Context::default();
+    for lint in lints {
+        context.enable_lint(lint);
+    }
+    check_in(context, code)
+}
+
+fn check_in(context: dm::Context, code: &str) -> Vec<String> {
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.parse_object_tree();
+    let errors = context.errors();
+    errors.iter().map(|e| e.description().to_owned()).collect()
+}
+
+#[test]
+fn var_type_flags() {
+    let var_type: VarType = ["static", "const", "list", "list", "obj"].iter().map(|s| s.to_string()).collect();
+    assert!(var_type.is_static && var_type.is_const && !var_type.is_tmp);
+    assert_eq!(var_type.list_depth(), 2);
+    assert!(var_type.is_list());
+    assert_eq!(var_type.element_type(), &["obj".to_owned()]);
+    assert_eq!(var_type.to_string(), "/static/const/list/list/obj");
+
+    let var_type: VarType = ["global", "tmp", "mob"].iter().map(|s| s.to_string()).collect();
+    assert!(var_type.is_static && var_type.is_tmp);
+    assert!(!var_type.is_list());
+    assert!(VarType::is_modifier("global") && !VarType::is_modifier("list"));
+}
+
+#[test]
+fn const_assignment() {
+    let errors = check(r##"
+/obj
+    var/const/MAX = 5
+    var/health = 10
+
+/obj/proc/test(MAX)
+    MAX = 1
+
+/obj/proc/test2()
+    var/const/LIMIT = 3
+    MAX = 1
+    MAX += 1
+    MAX++
+    LIMIT = 4
+    health = MAX
+    var/list/L = list(MAX = 2)
+    for(var/x in L)
+        x = 1
+"##);
+    assert_eq!(errors, vec![
+        "/obj/proc/test2: cannot assign to const var 'MAX'",
+        "/obj/proc/test2: cannot assign to const var 'MAX'",
+        "/obj/proc/test2: cannot assign to const var 'MAX'",
+        "/obj/proc/test2: cannot assign to const var 'LIMIT'",
+    ]);
+}
+
+#[test]
+fn const_assignment_location() {
+    let code = r##"
+/obj
+    var/const/MAX = 5
+
+/obj/proc/test()
+    if(MAX)
+        MAX = 1
+    for(MAX in world)
+        world << MAX
+"##;
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.parse_object_tree();
+
+    let errors = context.errors();
+    let found: Vec<_> = errors.iter()
+        .map(|e| (e.location().line, e.location().column, e.description()))
+        .collect();
+    assert_eq!(found, vec![
+        (6, 9, "/obj/proc/test: cannot assign to const var 'MAX'"),
+        (7, 5, "/obj/proc/test: cannot assign to const var 'MAX'"),
+    ]);
+}
+
+#[test]
+fn const_override() {
+    let errors = check(r##"
+/obj
+    var/const/MAX = 5
+/obj/item
+    MAX = 6
+"##);
+    assert_eq!(errors, vec!["cannot override const var 'MAX' on /obj/item"]);
+}
+
+#[test]
+fn parent_type_override() {
+    let errors = check(r##"
+/obj/item
+/obj/special
+    parent_type = /obj/item
+"##);
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[test]
+fn global_shadowing() {
+    let code = r##"
+var/global_count = 0
+
+/proc/test()
+    var/global_count = 1
+    for(var/i in 1 to global_count)
+        var/other = i
+        world << other
+"##;
+    assert!(check(code).is_empty());
+    assert_eq!(check_with_lints(code, &[dm::analysis::LINT_SHADOWED_GLOBAL_VARS]), vec![
+        "/proc/test: local var 'global_count' shadows global var",
+    ]);
+}
+
+#[test]
+fn redundant_safe_navigation() {
+    let errors = check(r##"
+/proc/test(list/L)
+    var/a = L?[1]?.name
+    var/b = list(1, 2)?[1]
+    var/c = "text"?.len
+    var/d = world?.name
+    var/e = L[1]?.name
+    return list(a, b, c, d, e)
+"##);
+    assert_eq!(errors, vec![
+        "/proc/test: redundant '?[' on a value which is never null",
+        "/proc/test: redundant '?.' on a value which is never null",
+        "/proc/test: redundant '?.' on a value which is never null",
+    ]);
+}
+
+#[test]
+fn goto_labels() {
+    let errors = check(r##"
+/proc/test(x)
+    start:
+    x = x ? x - 1 : 0
+    if(x)
+        goto start
+    outer:
+        for(var/i in 1 to 10)
+            if(i == x)
+                goto done
+            if(i > 5)
+                goto finish
+    done:
+    return x
+"##);
+    assert_eq!(errors, vec!["/proc/test: goto targets missing label 'finish'"]);
+}
+
+#[test]
+fn implicit_src() {
+    let code = r##"
+/obj
+    var/health = 10
+    proc/update_icon()
+
+/obj/proc/hurt(amount)
+    var/health = 5
+    health -= amount
+    src.health -= health
+    update_icon()
+    missing_proc()
+    usr << "ouch"
+
+/proc/global_thing()
+    var/health = 1
+    update_icon()
+    return health
+"##;
+    assert!(check(code).is_empty());
+    assert_eq!(check_with_lints(code, &[
+        dm::analysis::LINT_SHADOWED_SRC_VARS,
+        dm::analysis::LINT_UNDEFINED_PROCS,
+    ]), vec![
+        "/proc/global_thing: undefined proc 'update_icon'",
+        "/obj/proc/hurt: local var 'health' shadows src.health",
+        "/obj/proc/hurt: undefined proc 'missing_proc'",
+    ]);
+}
+
+#[test]
+fn builtin_versions() {
+    let code = r##"
+/obj/test
+    proc/go()
+        var/x = clamp(5, 1, 10)
+        render_target = "x"
+        new /particles
+        world << abs(x)
+"##;
+    let targeting = |version| {
+        let mut context = dm::Context::default();
+        context.set_byond_version(version);
+        check_in(context, code)
+    };
+    assert_eq!(targeting(512), vec![
+        "/obj/test/proc/go: /proc/clamp requires BYOND 513, but 512 is targeted".to_owned(),
+        "/obj/test/proc/go: /atom/var/render_target requires BYOND 513, but 512 is targeted".to_owned(),
+        "/obj/test/proc/go: /particles requires BYOND 514, but 512 is targeted".to_owned(),
+    ]);
+    assert_eq!(targeting(513), vec![
+        "/obj/test/proc/go: /particles requires BYOND 514, but 513 is targeted".to_owned(),
+    ]);
+    assert!(targeting(514).is_empty());
+    assert!(check(code).is_empty());
+}
+
+#[test]
+fn builtin_calls() {
+    let errors = check(r##"
+/proc/test()
+    var/x = istype(src, /obj, /mob)
+    x = copytext()
+    x = sqrt("four")
+    x = max(1, 2, 3, 4)
+    x = copytext(arglist(list("abc", 1)))
+    x = findtext("abc", "b", 1, 0)
+"##);
+    assert_eq!(errors, vec![
+        "/proc/test: 'istype' takes at most 2 arguments, got 3".to_owned(),
+        "/proc/test: 'copytext' takes at least 1 argument, got 0".to_owned(),
+        "/proc/test: 'sqrt' argument 1 should be a number, got text".to_owned(),
+    ]);
+}
+
+#[test]
+fn istype_narrowing() {
+    let code = r##"
+/obj/item
+    var/force = 1
+    proc/attack()
+
+/proc/test(atom/movable/x)
+    x.force = 2
+    if(istype(x, /obj/item))
+        x.force = 3
+        x.attack()
+    x.attack()
+    if(!istype(x, /obj/item))
+        return
+    x.force = 4
+    x:bogus = 5
+"##;
+    assert!(check(code).is_empty());
+    assert_eq!(check_with_lints(code, &[dm::analysis::LINT_UNDEFINED_FIELDS]), vec![
+        "/proc/test: undefined field 'force' on /atom/movable",
+        "/proc/test: undefined proc 'attack' on /atom/movable",
+    ]);
+}
+
+#[test]
+fn narrowing_after_assignment() {
+    let code = r##"
+/obj/item
+    var/force = 1
+
+/proc/test(atom/movable/x, atom/movable/y)
+    if(istype(x, /obj/item))
+        if(istype(y, /obj/item))
+            x = new /mob
+            y.force = 2
+            x.force = 3
+        y.force = 4
+        x.force = 5
+"##;
+    assert_eq!(check_with_lints(code, &[dm::analysis::LINT_UNDEFINED_FIELDS]), vec![
+        "/proc/test: undefined field 'force' on /atom/movable",
+        "/proc/test: undefined field 'force' on /atom/movable",
+        "/proc/test: undefined field 'force' on /atom/movable",
+    ]);
+}
+
+#[test]
+fn isnull_narrowing() {
+    let code = r##"
+/obj/item
+    var/force = 1
+
+/proc/test(atom/movable/x, atom/movable/y, datum/d)
+    if(!isnull(x))
+        world << x?.name
+    world << x?.name
+    if(isnull(y))
+        return
+    world << y?.name
+    if(ispath(d, /obj/item))
+        d.force = 2
+"##;
+    assert_eq!(check(code), vec![
+        "/proc/test: redundant '?.' on a value which is never null",
+        "/proc/test: redundant '?.' on a value which is never null",
+    ]);
+    // a path is not an instance of the type it names
+    assert_eq!(check_with_lints(code, &[dm::analysis::LINT_UNDEFINED_FIELDS]).last().unwrap(),
+        "/proc/test: undefined field 'force' on /datum");
+}
+
+#[test]
+fn typed_lists() {
+    let code = r##"
+/obj/item
+    var/force = 1
+    var/list/obj/item/contents_list
+
+/proc/test(list/obj/item/L)
+    for(var/x in L)
+        x.force = 2
+        x.bogus = 3
+    L[1].force = 4
+    L[1].contents_list[2].bogus = 5
+    L += new /obj/item
+    L += new /mob
+    L.Add(new /mob)
+"##;
+    assert!(check(code).is_empty());
+    assert_eq!(check_with_lints(code, &[dm::analysis::LINT_UNDEFINED_FIELDS]), vec![
+        "/proc/test: undefined field 'bogus' on /obj/item",
+        "/proc/test: undefined field 'bogus' on /obj/item",
+        "/proc/test: adding /mob to a list of /obj/item",
+        "/proc/test: adding /mob to a list of /obj/item",
+    ]);
+}
+
+#[test]
+fn assignment_conditions() {
+    let code = r##"
+/proc/test(x)
+    if(x = 1)
+        return
+    while(x = 2)
+        x++
+    for(var/i = 0, i = 3, i++)
+        x++
+    if(x == 5)
+        x = 6
+"##;
+    assert!(check(code).is_empty());
+    assert_eq!(check_with_lints(code, &[dm::analysis::LINT_ASSIGNMENT_CONDITIONS]), vec![
+        "/proc/test: assignment used as a condition, did you mean '=='?",
+        "/proc/test: assignment used as a condition, did you mean '=='?",
+        "/proc/test: assignment used as a condition, did you mean '=='?",
+    ]);
+}
+
+#[test]
+fn redundant_conditions() {
+    assert_eq!(check(r##"
+/obj/item
+/obj/item/tool
+
+/proc/test(x)
+    if(istype(x, /obj) || istype(x, /obj/item/tool))
+        return 1
+    if(istype(x, /obj/item) || istype(x, /mob) || istype(x, /obj/item))
+        return 2
+    if(istype(x, /obj/item) || istype(x, /obj/item/tool) || istype(x, /obj))
+        return 3
+    if(x == 1)
+        return 4
+    else if(x == 2)
+        return 5
+    else if(x == 1)
+        return 6
+"##), vec![
+        "/proc/test: istype(x, /obj/item/tool) is redundant with istype(x, /obj)",
+        "/proc/test: istype(x, /obj/item) is redundant with istype(x, /obj/item)",
+        "/proc/test: istype(x, /obj/item) is redundant with istype(x, /obj)",
+        "/proc/test: istype(x, /obj/item/tool) is redundant with istype(x, /obj/item)",
+        "/proc/test: duplicate condition in if/else if chain",
+    ]);
+}
+
+#[test]
+fn hard_deletes() {
+    let code = r##"
+/obj/item
+
+/proc/test(obj/item/I, list/L)
+    del(I)
+    del(L)
+
+/obj/item/proc/vanish()
+    del(src)
+    world << "gone"
+
+/proc/typo(obj/item/I)
+    del(I, I)
+"##;
+    assert_eq!(check(code), vec![
+        "/proc/typo: 'del' takes at most 1 argument, got 2",
+    ]);
+
+    let context = dm::Context::default();
+    context.enable_lint(dm::analysis::LINT_HARD_DELETES);
+    context.enable_lint(dm::analysis::LINT_CODE_AFTER_DEL_SRC);
+    context.set_lint_option(dm::analysis::OPTION_DEL_REPLACEMENT, "QDEL_NULL");
+    assert_eq!(check_in(context, code), vec![
+        "/proc/test: del() of /obj/item, use QDEL_NULL() instead",
+        "/proc/typo: 'del' takes at most 1 argument, got 2",
+        "/obj/item/proc/vanish: del(src) ends the proc, so the statements after it never run",
+        "/obj/item/proc/vanish: del() of /obj/item, use QDEL_NULL() instead",
+    ]);
+}
+
+struct SignalHandlerSleeps;
+
+impl dm::analysis::Lint for SignalHandlerSleeps {
+    fn name(&self) -> &str {
+        "signal-handler-sleeps"
+    }
+
+    fn check_proc(&self, cx: &dm::analysis::ProcLintContext, block: &[dm::ast::Spanned<dm::ast::Statement>]) {
+        if !cx.proc.settings.directive_enabled("SpacemanDMM_signal_handler") {
+            return;
+        }
+        for call in dm::analysis::sleeping_calls(cx.tree, block) {
+            cx.warning(format!("signal handlers must not sleep, but {}() may", call));
+        }
+    }
+}
+
+#[test]
+fn registered_lints() {
+    let code = r##"
+/datum/proc/on_signal()
+    set SpacemanDMM_signal_handler = 1
+    sleep(1)
+
+/datum/proc/elsewhere()
+    sleep(1)
+"##;
+    let mut context = dm::Context::default();
+    context.register_lint(Box::new(SignalHandlerSleeps));
+    assert_eq!(check_in(context, code), vec![
+        "/datum/proc/on_signal: signal handlers must not sleep, but sleep() may",
+    ]);
+}
+
+#[test]
+fn deprecations() {
+    assert_eq!(check(r##"
+/obj
+    /// @deprecated use new_health instead
+    var/health = 10
+    var/new_health = 10
+
+/obj/proc/old_hit()
+    set SpacemanDMM_deprecated = "use hit() instead"
+
+/obj/proc/hit()
+
+/// Does nothing.
+/// @deprecated
+/proc/old_global()
+
+/proc/test(obj/O)
+    O.old_hit()
+    O.health = 5
+    O.hit()
+    old_global()
+
+/obj/proc/self_test()
+    health = new_health
+    hit()
+"##), vec![
+        "/proc/test: use of deprecated proc 'old_hit': use hit() instead",
+        "/proc/test: use of deprecated var 'health': use new_health instead",
+        "/proc/test: use of deprecated proc 'old_global'",
+        "/obj/proc/self_test: use of deprecated var 'health': use new_health instead",
+    ]);
+}
+
+#[test]
+fn text_macros() {
+    let code = r##"
+/datum/thing
+/obj/item
+    name = "big \improper item"
+
+/proc/test(obj/item/I, datum/thing/T, n)
+    world << "\the [I] hits \the [T]."
+    world << "[n]\th place, \the [5]"
+    world << "\his \improper name"
+    world << "\The [I] and [I]\th"
+"##;
+    assert!(check(code).is_empty());
+    assert_eq!(check_with_lints(code, &[dm::analysis::LINT_TEXT_MACROS]), vec![
+        "/proc/test: \\the applied to /datum/thing, which is not an atom",
+        "/proc/test: \\the applied to a number",
+        "/proc/test: \\his has no embedded expression before it and is ignored",
+        "/proc/test: \\improper is ignored except at the start of a string",
+        "/proc/test: \\th applied to /obj/item, which is not a number",
+        "\\improper is ignored except at the start of a string on /obj/item",
+    ]);
+}
+
+#[test]
+fn embedded_expressions() {
+    let code = r##"
+/obj/item
+    var/force = 1
+
+/proc/test(obj/item/I)
+    world << "hits for [I.bogus] damage"
+    world << "[I.force] and [I.missing]"
+    I.other = 2
+"##;
+    assert!(check(code).is_empty());
+
+    let context = dm::Context::default();
+    context.enable_lint(dm::analysis::LINT_UNDEFINED_FIELDS);
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.parse_object_tree();
+
+    let errors = context.errors();
+    let found: Vec<_> = errors.iter()
+        .map(|e| (e.location().line, e.location().column, e.description()))
+        .collect();
+    assert_eq!(found, vec![
+        (5, 25, "/proc/test: undefined field 'bogus' on /obj/item"),
+        (6, 30, "/proc/test: undefined field 'missing' on /obj/item"),
+        (7, 5, "/proc/test: undefined field 'other' on /obj/item"),
+    ]);
+}
+
+#[test]
+fn inferred_local_types() {
+    let code = r##"
+/obj/item
+    var/obj/item/held
+    var/list/obj/item/stored
+
+/obj/item/proc/test()
+    var/a = new /obj/item
+    var/b = held
+    var/c = src.stored
+    var/d = 5
+    var/obj/e = held
+    var/f = held.held
+"##;
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    context.assert_success();
+
+    let ty = tree.find("/obj/item").unwrap();
+    let proc = &ty.get().procs["test"].value[0];
+    let body = match proc.code {
+        dm::objtree::Code::Present(ref body) => body,
+        _ => panic!("body not parsed"),
+    };
+    let found: Vec<_> = dm::analysis::inferred_local_types(&tree, ty, "test", proc, body).into_iter()
+        .map(|(location, path)| (location.line, location.column, path))
+        .collect();
+    assert_eq!(found, vec![
+        (6, 11, "/obj/item".to_owned()),
+        (7, 11, "/obj/item".to_owned()),
+        (8, 11, "/list/obj/item".to_owned()),
+        (11, 11, "/obj/item".to_owned()),
+    ]);
+}
+
+#[test]
+fn external_calls() {
+    let context = dm::Context::default();
+    context.set_custom_builtins(dm::CustomBuiltins {
+        external: vec![dm::ExternalFunction {
+            library: "rust_g".to_owned(),
+            name: "file_read".to_owned(),
+            args: 1,
+        }],
+        .. Default::default()
+    });
+    let errors = check_in(context, r##"
+/proc/test(lib)
+    call_ext("rust_g", "file_read")("a.txt")
+    call_ext("librust_g.so", "file_read")("a.txt", "b.txt")
+    call("rust_g.dll", "file_raed")("a.txt")
+    call_ext(lib, "file_raed")()
+    call_ext("other", "anything")()
+    call(src, "Topic")("x", "y")
+"##);
+    assert_eq!(errors, vec![
+        "/proc/test: external function 'file_read' takes 1 argument, got 2",
+        "/proc/test: unknown external function 'file_raed'",
+        "/proc/test: unknown external function 'file_raed'",
+    ]);
+}
@@ -1,10 +1,12 @@
 extern crate dreammaker as dm;
 
-use dm::Location;
+use dm::{Location, FileId};
 use dm::lexer::*;
+use dm::ast::PathOp;
 use dm::annotation::*;
 use dm::parser::Parser;
 use dm::indents::IndentProcessor;
+use dm::preprocessor::{Preprocessor, DefineMap};
 
 #[test]
 fn annotation_basic() {
@@ -43,3 +45,144 @@ fn annotation_basic() {
         }
     }
 }
+
+fn annotate(code: &str) -> (FileId, AnnotationTree) {
+    let context = Default::default();
+    let mut preprocessor = Preprocessor::from_defines(&context, DefineMap::default());
+    let file = preprocessor.push_file("test.dm".into(), ::std::io::Cursor::new(code.to_owned().into_bytes()));
+    let mut annotations = AnnotationTree::default();
+    {
+        let indent = IndentProcessor::new(&context, &mut preprocessor);
+        let mut parser = Parser::new(&context, indent);
+        parser.annotate_to(&mut annotations);
+        parser.run();
+    }
+    preprocessor.annotate_macro_uses(&mut annotations);
+    context.assert_success();
+    (file, annotations)
+}
+
+#[test]
+fn expression_annotations() {
+    let (_, annotations) = annotate(r##"
+#define GREETING "Hello"
+/proc/test()
+    var/x = /obj/item
+    src.foo(1)
+    bar(GREETING)
+    world.log << "[x] and [GREETING]"
+"##.trim());
+
+    let mut prefabs = Vec::new();
+    let mut calls = Vec::new();
+    let mut interpolations = Vec::new();
+    let mut macros = Vec::new();
+    for (_, annotation) in annotations.iter() {
+        match *annotation {
+            Annotation::Prefab(ref prefab) => prefabs.push(prefab.path.clone()),
+            Annotation::ProcCall(ref scope, ref name) => calls.push((scope.clone(), name.clone())),
+            Annotation::Interpolation(idx) => interpolations.push(idx),
+            Annotation::MacroUse(ref name, location) => macros.push((name.clone(), location.line)),
+            _ => {}
+        }
+    }
+
+    assert_eq!(prefabs, vec![vec![
+        (PathOp::Slash, "obj".to_owned()),
+        (PathOp::Slash, "item".to_owned()),
+    ]]);
+    calls.sort();
+    assert_eq!(calls, vec![
+        (vec![], "bar".to_owned()),
+        (vec!["src".to_owned()], "foo".to_owned()),
+    ]);
+    interpolations.sort();
+    assert_eq!(interpolations, vec![0, 1]);
+    assert_eq!(macros, vec![("GREETING".to_owned(), 1), ("GREETING".to_owned(), 1)]);
+}
+
+#[test]
+fn semantic_tokens() {
+    let (file, annotations) = annotate(r##"
+#define LIMIT 5
+/obj/proc/test(mob/M, count)
+    var/list/L = list()
+    L += M
+    if (count > LIMIT)
+        src.foo()
+"##.trim());
+
+    let tokens: Vec<_> = annotations.semantic_tokens(file).into_iter()
+        .map(|(range, class)| (range.start.line, range.start.column, range.end.column, class))
+        .collect();
+    assert_eq!(tokens, vec![
+        (2, 2, 5, TokenClass::Type),
+        (2, 6, 10, TokenClass::Keyword),
+        (2, 11, 15, TokenClass::Proc),
+        (2, 16, 19, TokenClass::Type),
+        (2, 20, 21, TokenClass::Parameter),
+        (2, 23, 28, TokenClass::Parameter),
+        (3, 9, 13, TokenClass::Type),
+        (3, 14, 15, TokenClass::Var),
+        (4, 5, 6, TokenClass::Var),
+        (4, 10, 11, TokenClass::Parameter),
+        (5, 9, 14, TokenClass::Parameter),
+        (5, 17, 22, TokenClass::Macro),
+        (6, 9, 12, TokenClass::Keyword),
+        (6, 13, 16, TokenClass::Proc),
+    ]);
+}
+
+#[test]
+fn call_arguments() {
+    let (file, annotations) = annotate(r##"
+/proc/test()
+    foo(1, bar(2, 3), 4)
+"##.trim());
+
+    let at = |column| {
+        annotations.call_arguments_at(Location { file, line: 2, column })
+            .map(|(priors, name, idx)| (priors.len(), name.to_owned(), idx))
+    };
+    assert_eq!(at(9), Some((0, "foo".to_owned(), 0)));
+    assert_eq!(at(12), Some((0, "foo".to_owned(), 1)));
+    assert_eq!(at(16), Some((0, "bar".to_owned(), 0)));
+    assert_eq!(at(19), Some((0, "bar".to_owned(), 1)));
+    assert_eq!(at(23), Some((0, "foo".to_owned(), 2)));
+    assert_eq!(at(2), None);
+}
+
+#[test]
+fn long_lines() {
+    // past the column limit, locations saturate rather than overflowing
+    let pad = format!("/*{}*/", " ".repeat(70000));
+    let code = format!(r##"
+/obj/proc/test()
+    var/x = {0}src.foo()
+    x = {0}/obj/
+    x = {0}.
+    x = {0}src?.
+{0}/obj/
+"##, pad);
+
+    let context = Default::default();
+    let mut preprocessor = Preprocessor::from_defines(&context, DefineMap::default());
+    let file = preprocessor.push_file("test.dm".into(), ::std::io::Cursor::new(code.trim().to_owned().into_bytes()));
+    let mut annotations = AnnotationTree::default();
+    {
+        let indent = IndentProcessor::new(&context, &mut preprocessor);
+        let mut parser = Parser::new(&context, indent);
+        parser.annotate_to(&mut annotations);
+        parser.run();
+    }
+
+    assert!(annotations.iter().any(|(_, annotation)| match *annotation {
+        Annotation::IncompleteTreePath(..) => true,
+        _ => false,
+    }));
+    let saturated: Vec<_> = annotations.semantic_tokens(file).into_iter()
+        .filter(|&(ref range, _)| range.start.column == u16::max_value())
+        .collect();
+    assert!(!saturated.is_empty());
+    assert!(saturated.iter().all(|&(ref range, _)| range.end.column == u16::max_value()));
+}
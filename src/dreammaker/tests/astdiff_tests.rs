@@ -0,0 +1,69 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::objtree::ObjectTree;
+use dm::astdiff::{compare, Change};
+use dm::constants::Constant;
+
+fn parse(code: &str) -> ObjectTree {
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.parse_object_tree()
+}
+
+#[test]
+fn changes() {
+    let old = parse(r##"
+/obj/thing
+    var/health = 10
+    var/name2 = "thing"
+    proc/hit(amount)
+        health -= amount
+    proc/stay()
+        return 1
+    proc/go()
+        return
+
+/obj/old
+"##);
+    let new = parse(r##"
+// a comment moves everything down a line
+
+/obj/thing
+    var/health = 20
+    var/color2 = "red"
+    proc/hit(amount, source)
+        health -= amount
+    proc/stay()
+        return 1
+    proc/come()
+        return
+
+/obj/new
+"##);
+
+    let changes = compare(&old, &new);
+    let strings: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+    assert_eq!(strings, vec![
+        "+ /obj/new",
+        "- /obj/old",
+        "~ /obj/thing/var/health: 10 -> 20",
+        "- /obj/thing/var/name2 = \"thing\"",
+        "+ /obj/thing/var/color2 = \"red\"",
+        "~ /obj/thing/proc/hit",
+        "- /obj/thing/proc/go",
+        "+ /obj/thing/proc/come",
+    ]);
+    assert_eq!(changes[2], Change::VarChanged {
+        path: "/obj/thing".to_owned(),
+        name: "health".to_owned(),
+        old: Some(Constant::Int(10)),
+        new: Some(Constant::Int(20)),
+    });
+
+    assert!(compare(&old, &old).is_empty());
+}
@@ -8,3 +8,37 @@ fn check_builtins() {
     tree.register_builtins();
     println!("{:?}", tree);
 }
+
+#[test]
+fn custom_builtins() {
+    use dm::lexer::Lexer;
+    use dm::indents::IndentProcessor;
+
+    let context = dm::Context::default();
+    context.set_custom_builtins(dm::CustomBuiltins {
+        procs: vec![dm::CustomProc {
+            path: "/proc/rustg_http_request".to_owned(),
+            params: vec!["method".to_owned(), "url".to_owned(), "body".to_owned()],
+            min_args: Some(2),
+            return_type: None,
+            sleeps: true,
+        }],
+        vars: vec!["/datum/var/native_id".to_owned()],
+        external: Vec::new(),
+    });
+    let code = r##"
+/datum/proc/fetch()
+    native_id = rustg_http_request("GET")
+"##;
+    let tree = {
+        let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+        let mut parser = dm::parser::Parser::new(&context, IndentProcessor::new(&context, lexer));
+        parser.enable_procs();
+        parser.parse_object_tree()
+    };
+
+    assert!(tree.find("/datum").unwrap().get().vars.contains_key("native_id"));
+    assert!(tree.builtin_signature("rustg_http_request").unwrap().sleeps);
+    let errors: Vec<_> = context.errors().iter().map(|e| e.description().to_owned()).collect();
+    assert_eq!(errors, vec!["/datum/proc/fetch: 'rustg_http_request' takes at least 2 arguments, got 1".to_owned()]);
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "capi")]
+
+extern crate dreammaker as dm;
+extern crate serde_json;
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::ptr;
+
+use serde_json::Value;
+
+use dm::capi::*;
+
+#[test]
+fn parse_through_c_abi() {
+    let dir = std::env::temp_dir().join("sdmm_capi_test");
+    fs::create_dir_all(&dir).unwrap();
+    let dme = dir.join("test.dme");
+    fs::write(&dme, "/mob/proc/Attack()\n\treturn\n#error oops\n").unwrap();
+
+    unsafe {
+        let path = CString::new(dme.to_str().unwrap()).unwrap();
+        let env = sdmm_parse_environment(path.as_ptr());
+        assert!(!env.is_null());
+
+        let errors = sdmm_get_errors(env);
+        assert!(!errors.is_null());
+        let json: Value = serde_json::from_str(CStr::from_ptr(errors).to_str().unwrap()).unwrap();
+        sdmm_free_string(errors);
+        assert_eq!(json[0]["severity"], "error");
+        assert_eq!(json[0]["line"], 3);
+        assert!(json[0]["message"].as_str().unwrap().contains("oops"));
+
+        sdmm_free(env);
+
+        let missing = CString::new(dir.join("missing.dme").to_str().unwrap()).unwrap();
+        assert!(sdmm_parse_environment(missing.as_ptr()).is_null());
+        assert!(sdmm_parse_environment(ptr::null()).is_null());
+        assert!(sdmm_get_errors(ptr::null()).is_null());
+    }
+}
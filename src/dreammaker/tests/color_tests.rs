@@ -0,0 +1,112 @@
+extern crate dreammaker as dm;
+
+use dm::constants::{Color, ColorMatrix, Constant, Matrix};
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+
+fn parse(code: &str) -> (dm::Context, dm::objtree::ObjectTree) {
+    let context = dm::Context::default();
+    let tree = {
+        let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+        dm::parser::parse(&context, IndentProcessor::new(&context, lexer))
+    };
+    (context, tree)
+}
+
+fn constant(tree: &dm::objtree::ObjectTree, path: &str, var: &str) -> Constant {
+    tree.find(path).unwrap().get().vars[var].value.constant.clone().unwrap()
+}
+
+#[test]
+fn parse_colors() {
+    let rgb = |r, g, b| Some(Color { r, g, b, a: 255 });
+    assert_eq!(Color::parse("#ff8000"), rgb(255, 128, 0));
+    assert_eq!(Color::parse("#F80"), rgb(255, 136, 0));
+    assert_eq!(Color::parse("#ff800080"), Some(Color { r: 255, g: 128, b: 0, a: 128 }));
+    assert_eq!(Color::parse("#f808"), Some(Color { r: 255, g: 136, b: 0, a: 136 }));
+    assert_eq!(Color::parse("Green"), rgb(0, 192, 0));
+    assert_eq!(Color::parse("#ff80"), Some(Color { r: 255, g: 255, b: 136, a: 0 }));
+    assert_eq!(Color::parse("#ff800"), None);
+    assert_eq!(Color::parse("#gg0000"), None);
+    assert_eq!(Color::parse("#+f0000"), None);
+    assert_eq!(Color::parse("chartreuse"), None);
+    assert_eq!(Color::parse("#ff800080").unwrap().to_string(), "#ff800080");
+    assert_eq!(Color::parse("red").unwrap().to_string(), "#ff0000");
+}
+
+#[test]
+fn rgb_and_matrix() {
+    let (context, tree) = parse(r##"
+/obj
+    var/a = rgb(255, 128, 0)
+    var/b = rgb(300, -5, 127.6, 64)
+    var/c = list(0.3,0.3,0.3, 0.59,0.59,0.59, 0.11,0.11,0.11)
+    var/d = list("#ff0000", null, "#0000ff", "#00000080", "#101010")
+    var/e = matrix(90, 5) // MATRIX_ROTATE
+    var/f = matrix(2, 3, 6) // MATRIX_SCALE
+    var/g = matrix(1, 2, 3, 4, 5, 6)
+    var/h = matrix(16, -8, 7) // MATRIX_TRANSLATE
+"##);
+    context.assert_success();
+
+    assert_eq!(constant(&tree, "/obj", "a"), Constant::string("#ff8000"));
+    assert_eq!(constant(&tree, "/obj", "b"), Constant::string("#ff008040"));
+    assert_eq!(constant(&tree, "/obj", "a").to_color(), Some(Color { r: 255, g: 128, b: 0, a: 255 }));
+
+    let gray = constant(&tree, "/obj", "c").to_color_matrix().unwrap();
+    assert_eq!(gray.0[1], [0.59, 0.59, 0.59, 0.]);
+    assert_eq!(gray.0[3], [0., 0., 0., 1.]);
+    let out = gray.apply([1., 0., 0., 1.]);
+    assert!((out[0] - 0.3).abs() < 1e-6 && (out[2] - 0.3).abs() < 1e-6 && out[3] == 1.);
+
+    let rows = constant(&tree, "/obj", "d").to_color_matrix().unwrap();
+    assert_eq!(rows.0[0], [1., 0., 0., 0.]);
+    assert_eq!(rows.0[1], ColorMatrix::IDENTITY.0[1]);
+    assert!((rows.0[3][3] - 128. / 255.).abs() < 1e-6);
+    assert!((rows.0[4][0] - 16. / 255.).abs() < 1e-6);
+    assert_eq!(Constant::string("#808080").to_color_matrix().unwrap().0[3][3], 1.);
+
+    let rotate = constant(&tree, "/obj", "e").to_matrix().unwrap();
+    let (x, y) = rotate.apply((0., 1.));
+    assert!((x - 1.).abs() < 1e-6 && y.abs() < 1e-6);
+    assert_eq!(constant(&tree, "/obj", "f").to_matrix().unwrap().apply((1., 1.)), (2., 3.));
+    assert_eq!(constant(&tree, "/obj", "g").to_matrix().unwrap(), Matrix { a: 1., b: 2., c: 3., d: 4., e: 5., f: 6. });
+    assert_eq!(constant(&tree, "/obj", "h").to_matrix().unwrap().apply((0., 0.)), (16., -8.));
+    assert!(Constant::Null(None).to_matrix().unwrap().is_identity());
+    assert_eq!(Constant::string("#fff").to_matrix(), None);
+}
+
+#[test]
+fn malformed_colors_lint() {
+    let code = r##"
+/obj
+    color = "#ff00ff"
+/obj/a
+    color = "#ff00f"
+/obj/b
+    color = "white"
+
+/obj/proc/test(obj/other)
+    color = "#12345"
+    other.color = rgb(1, 2, 3)
+    other.color = "#xyz"
+    name = "#xyz"
+"##;
+    let (context, _) = parse(code);
+    assert!(context.errors().is_empty());
+
+    let context = dm::Context::default();
+    context.enable_lint(dm::analysis::LINT_MALFORMED_COLORS);
+    {
+        let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+        let mut parser = dm::parser::Parser::new(&context, IndentProcessor::new(&context, lexer));
+        parser.enable_procs();
+        parser.parse_object_tree();
+    }
+    let errors: Vec<_> = context.errors().iter().map(|e| e.description().to_owned()).collect();
+    assert_eq!(errors, vec![
+        "/obj/proc/test: malformed color \"#12345\"",
+        "/obj/proc/test: malformed color \"#xyz\"",
+        "malformed color \"#ff00f\" on /obj/a",
+    ]);
+}
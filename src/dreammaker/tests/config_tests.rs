@@ -0,0 +1,55 @@
+#![cfg(feature = "config")]
+
+extern crate dreammaker as dm;
+
+use std::fs;
+
+use dm::config::{self, Config};
+
+#[test]
+fn load_and_apply() {
+    let dir = std::env::temp_dir().join("dm_config_tests_load_and_apply");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(config::FILE_NAME), r#"
+[lints]
+enable = ["hard-deletes"]
+
+[lints.options]
+del_replacement = "qdel"
+
+[builtins]
+vars = ["/datum/var/native_id"]
+
+[[builtins.procs]]
+path = "/proc/rustg_file_read"
+params = ["fname"]
+
+[editor]
+theme = "dark"
+"#).unwrap();
+
+    let context = dm::Context::default();
+    Config::load(&dir).apply(&context);
+    assert!(context.lint_enabled("hard-deletes"));
+    assert!(!context.lint_enabled("undefined-fields"));
+    assert_eq!(context.lint_option("del_replacement"), Some("qdel".to_owned()));
+    assert_eq!(context.custom_builtins().vars, vec!["/datum/var/native_id".to_owned()]);
+    assert_eq!(context.custom_builtins().procs[0].params, vec!["fname".to_owned()]);
+
+    // other tools' sections are left alone
+    #[derive(serde::Deserialize, Default)]
+    #[serde(default)]
+    struct Editor {
+        editor: Theme,
+    }
+    #[derive(serde::Deserialize, Default)]
+    #[serde(default)]
+    struct Theme {
+        theme: String,
+    }
+    let editor: Editor = config::load_file(&dir);
+    assert_eq!(editor.editor.theme, "dark");
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(Config::load(&dir).lints.enable.is_empty());
+}
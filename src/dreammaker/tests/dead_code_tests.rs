@@ -0,0 +1,59 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::dead_code::*;
+
+#[test]
+fn reports_unused_procs() {
+    let code = r##"
+/world/New()
+    setup()
+    ..()
+
+/proc/setup()
+    spawn(10)
+        new /obj/thing
+
+/proc/never_called()
+
+/proc/by_reflection()
+    set SpacemanDMM_called_by_reflection = 1
+
+/proc/by_name()
+
+/proc/custom_root()
+
+/obj/thing
+    var/callback = .proc/on_signal
+    proc/on_signal()
+    proc/unused_thing()
+    proc/ui_act()
+    verb/examine_it()
+
+/obj/thing/New()
+    call(src, "by_name")()
+"##;
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+
+    let describe = |roots: &[&str]| -> Vec<String> {
+        unused_procs(&tree, roots).iter()
+            .map(|each| format!("{}/proc/{}", each.ty.get().path, each.name))
+            .collect()
+    };
+    assert_eq!(describe(DEFAULT_ROOTS), vec![
+        "/proc/never_called",
+        "/proc/custom_root",
+        "/obj/thing/proc/unused_thing",
+    ]);
+    // roots on a type also cover its subtypes
+    assert_eq!(describe(&["custom_root", "/obj/unused_thing"]), vec![
+        "/proc/never_called",
+        "/obj/thing/proc/ui_act",
+    ]);
+}
@@ -0,0 +1,177 @@
+#![cfg(feature = "debugger")]
+
+extern crate dreammaker as dm;
+#[macro_use] extern crate serde_json;
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::debugger::*;
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    fn messages(&self) -> Vec<Value> {
+        let bytes = ::std::mem::replace(&mut *self.0.lock().unwrap(), Vec::new());
+        let mut input = &bytes[..];
+        let mut result = Vec::new();
+        while let Some(message) = read_message(&mut input).unwrap() {
+            result.push(serde_json::from_str(&message).unwrap());
+        }
+        result
+    }
+}
+
+/// Pretends each line of a proc is four bytes of code.
+#[derive(Default)]
+struct FakeBackend {
+    events: Option<EventSink>,
+    breakpoints: Vec<(ProcRef, u32)>,
+}
+
+impl Backend for FakeBackend {
+    fn attach(&mut self, events: EventSink, _: &Value) -> Result<(), String> {
+        self.events = Some(events);
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        self.events = None;
+    }
+
+    fn offset_of_line(&mut self, _: &ProcRef, line: u32) -> Option<u32> {
+        Some(line * 4)
+    }
+
+    fn line_of_offset(&mut self, _: &ProcRef, offset: u32) -> Option<u32> {
+        Some(offset / 4)
+    }
+
+    fn set_breakpoint(&mut self, proc: &ProcRef, offset: u32) -> Result<(), String> {
+        self.breakpoints.push((proc.clone(), offset));
+        Ok(())
+    }
+
+    fn clear_breakpoint(&mut self, proc: &ProcRef, offset: u32) {
+        self.breakpoints.retain(|each| each != &(proc.clone(), offset));
+    }
+
+    fn threads(&mut self) -> Vec<(i64, String)> {
+        vec![(1, "main".to_owned())]
+    }
+
+    fn stack_frames(&mut self, _: i64) -> Vec<StackFrame> {
+        vec![StackFrame {
+            proc: ProcRef { type_path: "/mob".to_owned(), name: "Attack".to_owned(), override_id: 0 },
+            offset: 12,
+        }]
+    }
+
+    fn scopes(&mut self, _: i64, frame: usize) -> Vec<Scope> {
+        vec![Scope { name: format!("Locals {}", frame), variables: 7 }]
+    }
+
+    fn variables(&mut self, _: i64) -> Vec<Variable> {
+        vec![Variable { name: "target".to_owned(), value: "null".to_owned(), variables: 0 }]
+    }
+
+    fn resume(&mut self, _: i64, _: Resume) {}
+
+    fn pause(&mut self, thread: i64) {
+        if let Some(ref events) = self.events {
+            events.send(BackendEvent::Stopped { thread, reason: StopReason::Pause });
+        }
+    }
+}
+
+fn request(seq: i64, command: &str, arguments: Value) -> String {
+    serde_json::to_string(&json!({
+        "seq": seq,
+        "type": "request",
+        "command": command,
+        "arguments": arguments,
+    })).unwrap()
+}
+
+#[test]
+fn debug_session() {
+    let code = r##"
+/mob/proc/Attack(target)
+    var/damage = 5
+    world.log << damage
+
+/mob/proc/Defend()
+    return
+"##;
+    let context = dm::Context::default();
+    let file = context.register_file(Path::new("mob.dm"));
+    let lexer = Lexer::new(&context, file, code.trim().bytes().map(Ok));
+    let tree = dm::parser::Parser::new(&context, IndentProcessor::new(&context, lexer)).parse_object_tree();
+
+    let output = Buffer::default();
+    let root = PathBuf::from("/game");
+    let mut debugger = Debugger::new(&context, &tree, root, FakeBackend::default(), output.clone());
+
+    assert!(debugger.handle(&request(1, "initialize", Value::Null)));
+    let messages = output.messages();
+    assert_eq!(messages[0]["success"], true);
+    assert_eq!(messages[1]["event"], "initialized");
+
+    debugger.handle(&request(2, "attach", Value::Null));
+    debugger.handle(&request(3, "setBreakpoints", json!({
+        "source": { "path": "/game/mob.dm" },
+        "breakpoints": [{ "line": 3 }, { "line": 5 }],
+    })));
+    let messages = output.messages();
+    assert_eq!(messages[1]["body"]["breakpoints"][0]["verified"], true);
+    assert_eq!(messages[1]["body"]["breakpoints"][1]["verified"], true);
+    {
+        let procs: Vec<_> = debugger.backend().breakpoints.iter().map(|&(ref proc, offset)| (proc.name.as_str(), offset)).collect();
+        assert_eq!(procs, vec![("Attack", 12), ("Defend", 20)]);
+    }
+
+    // breakpoints sent again for the same file replace the old ones
+    debugger.handle(&request(4, "setBreakpoints", json!({
+        "source": { "path": "/game/mob.dm" },
+        "breakpoints": [],
+    })));
+    assert!(debugger.backend().breakpoints.is_empty());
+    output.messages();
+
+    debugger.handle(&request(5, "pause", json!({ "threadId": 1 })));
+    debugger.handle(&request(6, "stackTrace", json!({ "threadId": 1 })));
+    let messages = output.messages();
+    assert_eq!(messages[0]["event"], "stopped");
+    assert_eq!(messages[0]["body"]["reason"], "pause");
+    let frame = &messages[2]["body"]["stackFrames"][0];
+    assert_eq!(frame["name"], "/mob/proc/Attack");
+    assert_eq!(frame["line"], 3);
+    assert_eq!(frame["source"]["path"], "/game/mob.dm");
+
+    let frame_id = frame["id"].clone();
+    debugger.handle(&request(7, "scopes", json!({ "frameId": frame_id })));
+    debugger.handle(&request(8, "variables", json!({ "variablesReference": 7 })));
+    let messages = output.messages();
+    assert_eq!(messages[0]["body"]["scopes"][0]["name"], "Locals 0");
+    assert_eq!(messages[1]["body"]["variables"][0]["name"], "target");
+
+    debugger.handle(&request(9, "evaluate", Value::Null));
+    assert_eq!(output.messages()[0]["success"], false);
+    assert!(!debugger.handle(&request(10, "disconnect", Value::Null)));
+}
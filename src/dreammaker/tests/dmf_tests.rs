@@ -0,0 +1,76 @@
+extern crate dreammaker as dm;
+
+use dm::FileId;
+use dm::dmf::*;
+
+const SKIN: &str = "
+macro \"default\"
+\telem 
+\t\tname = \"NORTH+REP\"
+\t\tcommand = \".north\"
+
+window \"mainwindow\"
+\telem \"mainwindow\"
+\t\ttype = MAIN
+\t\tsize = 640x480
+\t\ticon = 'icons\\\\ss13.png'
+\t\tmacro = \"default\"
+\telem \"output\"
+\t\ttype = OUTPUT
+\t\ttext = \"say \\\"hi\\\"\"
+";
+
+#[test]
+fn parse_skin() {
+    let context = dm::Context::default();
+    let skin = Skin::parse(&context, FileId::default(), SKIN);
+    context.assert_success();
+
+    assert_eq!(skin.sections.len(), 2);
+    let macros: Vec<_> = skin.sections_of(SectionKind::Macro).collect();
+    assert_eq!(macros[0].name, "default");
+    assert_eq!(macros[0].elements[0].name, None);
+    assert_eq!(macros[0].elements[0].get("command"), Some(&Value::String(".north".to_owned())));
+
+    let window = skin.windows().next().unwrap();
+    assert_eq!(window.elements[0].control_type(), Some("MAIN"));
+    assert_eq!(window.elements[0].get("icon"), Some(&Value::Resource("icons\\ss13.png".to_owned())));
+    assert_eq!(window.elements[0].get("macro").map(Value::as_str), Some("default"));
+    assert_eq!(window.controls().count(), 1);
+
+    let output = skin.find_control("output").unwrap();
+    assert_eq!(output.location.line, 13);
+    assert_eq!(output.get("text").map(Value::as_str), Some("say \"hi\""));
+}
+
+#[test]
+fn malformed_skin() {
+    let context = dm::Context::default();
+    let skin = Skin::parse(&context, FileId::default(), "\tsize = 1x1\nbogus \"x\"\n\telem \"y\"\nwindow \"w\"\n\telem \"w\"\n\t\ttext = \"open");
+    assert_eq!(context.errors().len(), 3);
+    assert_eq!(skin.sections.len(), 1);
+    assert!(skin.sections[0].elements[0].attributes.is_empty());
+}
+
+#[test]
+fn parse_script() {
+    let script = "
+#define FOO
+macro
+\tCTRL+Z return \"undo\"
+\tF1 \"help\"
+<STYLE>
+body { color: red; }
+</STYLE>
+";
+    let context = dm::Context::default();
+    let script = Script::parse(&context, FileId::default(), script);
+    context.assert_success();
+
+    assert_eq!(script.macros.len(), 2);
+    assert_eq!(script.macros[0].key, "CTRL+Z");
+    assert_eq!(script.macros[0].command, "undo");
+    assert_eq!(script.macros[1].command, "help");
+    assert_eq!(script.styles.len(), 1);
+    assert_eq!(script.styles[0].1, "\nbody { color: red; }\n");
+}
@@ -0,0 +1,104 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::duplicates::*;
+
+#[test]
+fn reports_duplicate_procs() {
+    let code = r##"
+/mob/proc/heal(amount)
+    var/total = amount * 2
+    for(var/i in 1 to total)
+        world << i
+    return total
+
+/obj/proc/repair(n) // same as heal, renamed
+    var/sum = n * 2
+
+    for(var/j in 1 to sum)
+        world << j
+    return sum
+
+/obj/proc/different(n)
+    var/sum = n * 3
+    for(var/j in 1 to sum)
+        world << j
+    return sum
+
+/obj/proc/uses_global(n)
+    var/sum = n * 2
+    for(var/j in 1 to sum)
+        world << j
+    return total
+
+/proc/tiny()
+    return 1
+
+/proc/also_tiny()
+    return 1
+"##.trim();
+
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+
+    let groups = duplicate_procs(&context, &tree, 2);
+    let names: Vec<Vec<String>> = groups.iter()
+        .map(|group| group.procs.iter().map(|each| format!("{}/{}", each.ty.get().path, each.name)).collect())
+        .collect();
+    assert_eq!(names, vec![vec!["/mob/heal", "/obj/repair"]]);
+    assert_eq!(groups[0].statements, 4);
+
+    assert_eq!(duplicate_procs(&context, &tree, 1).len(), 2);
+}
+
+fn redefinitions(lint: bool) -> Vec<(String, u32, u32)> {
+    let code = r##"
+/obj
+    var/health = 10
+    var/name = "thing"
+
+/obj/proc/break_apart(force = 1)
+    health -= force
+    return health
+
+/obj
+    health = 10
+    name = "other thing"
+
+/obj/proc/break_apart(force = 1)
+    health -= force
+    return health
+
+/obj/break_apart(force = 1)
+    health -= force * 2
+    return ..()
+"##.trim();
+
+    let context = dm::Context::default();
+    if lint {
+        context.enable_lint(dm::objtree::LINT_DUPLICATE_DEFINITIONS);
+    }
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.parse_object_tree();
+
+    let errors = context.errors();
+    errors.iter()
+        .map(|e| (e.description().to_owned(), e.location().line, e.notes()[0].location.line))
+        .collect()
+}
+
+#[test]
+fn exact_redefinitions() {
+    assert_eq!(redefinitions(true), vec![
+        ("var health redefined with the same value".to_owned(), 10, 2),
+        ("proc break_apart redefined with the same body".to_owned(), 13, 5),
+    ]);
+    assert!(redefinitions(false).is_empty());
+}
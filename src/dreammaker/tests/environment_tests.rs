@@ -0,0 +1,27 @@
+extern crate dreammaker as dm;
+
+use std::fs;
+
+#[test]
+fn detect_and_list() {
+    let root = std::env::temp_dir().join("spacemandmm_environment_tests");
+    let _ = fs::remove_dir_all(&root);
+    for dir in &["code/sub", "other", ".git"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    for file in &["a.dme", "tgstation.dme", "other/b.dme", ".git/c.dme", "code/sub/thing.dm"] {
+        fs::write(root.join(file), "").unwrap();
+    }
+
+    // found by walking up, preferring the non-default environment
+    assert_eq!(dm::detect_environment(&root.join("code/sub")), Some(root.join("a.dme")));
+    assert_eq!(dm::detect_environment(&root.join("other")), Some(root.join("other/b.dme")));
+
+    assert_eq!(dm::list_environments(&root), vec![
+        root.join("a.dme"),
+        root.join("other/b.dme"),
+        root.join("tgstation.dme"),
+    ]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,95 @@
+extern crate dreammaker as dm;
+
+use dm::constants::{evaluate_str, Constant};
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+
+#[test]
+fn eval_against_type() {
+    let code = r##"
+/obj/foo
+    var/health = 10
+    var/title = "foo"
+
+/obj/foo/bar
+    health = 25
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let mut tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    context.assert_success();
+
+    let eval = |tree: &mut _, ty, src| evaluate_str(&context, tree, ty, None, src).unwrap();
+    assert_eq!(eval(&mut tree, "/", "2 ** 3 + 1"), Constant::Int(9));
+    assert_eq!(eval(&mut tree, "/obj/foo", "health * 2"), Constant::Int(20));
+    assert_eq!(eval(&mut tree, "/obj/foo/bar", "initial(health)"), Constant::Int(25));
+    assert_eq!(eval(&mut tree, "/obj/foo/bar", "title + \"!\""), Constant::string("foo!"));
+    assert_eq!(eval(&mut tree, "/", "7.5 %% 2"), Constant::from(1.5));
+    assert_eq!(eval(&mut tree, "/", "-1 %% 3"), Constant::from(2.));
+    assert_eq!(eval(&mut tree, "/", "1 %% -3"), Constant::from(-2.));
+    assert!(evaluate_str(&context, &mut tree, "/", None, "1 %% 0").is_err());
+    assert!(evaluate_str(&context, &mut tree, "/obj/nonexistent", None, "1").is_err());
+}
+
+#[test]
+fn precision_warnings() {
+    let code = r##"
+/obj/foo
+    var/big = 4096 * 4096 + 1
+    var/huge = 65536 * 65536
+    var/fine = 4096 * 4096
+    var/flags = 0x1000000 | 1
+    var/shifted = 1 << 24
+    var/ok = (1 << 23) | 0xFF
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    let foo = tree.find("/obj/foo").unwrap();
+    assert_eq!(foo.get().vars["huge"].value.constant, Some(Constant::from(4294967296.)));
+
+    let errors = context.errors();
+    let warnings: Vec<_> = errors.iter().map(|e| e.description()).collect();
+    assert_eq!(warnings, vec![
+        "precision loss in constant 16777216 + 1: 16777217 exceeds 16777216 in magnitude",
+        "precision loss in constant 65536 * 65536: 4294967296 exceeds 16777216 in magnitude",
+        "bitwise operation 16777216 | 1 truncates 16777216 to 24 bits",
+        "bitwise operation 1 << 24 truncates 16777216 to 24 bits",
+    ]);
+}
+
+#[test]
+fn var_overrides() {
+    let code = r##"
+/obj
+    var/armor = 1 + 1
+
+/obj/item/shield
+    armor = 5 * 2
+
+/obj/item
+    armor = 4
+
+/mob
+    var/armor = 0
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    context.assert_success();
+
+    let overrides = tree.var_overrides("armor");
+    let found: Vec<_> = overrides.iter()
+        .map(|o| (o.ty.path.as_str(), o.is_declaration(), o.constant().cloned()))
+        .collect();
+    assert_eq!(found, vec![
+        ("/mob", true, Some(Constant::Int(0))),
+        ("/obj", true, Some(Constant::Int(2))),
+        ("/obj/item", false, Some(Constant::Int(4))),
+        ("/obj/item/shield", false, Some(Constant::Int(10))),
+    ]);
+    assert!(tree.var_overrides("missing").is_empty());
+}
@@ -0,0 +1,188 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::parser::Parser;
+use dm::ast::*;
+
+fn parse_expr(code: &str) -> Expression {
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let expr = Parser::new(&context, lexer).expression()
+        .expect("parse error")
+        .expect("no expression");
+    context.assert_success();
+    expr
+}
+
+// Render an expression fully parenthesized, to make its structure visible.
+fn show(expr: &Expression) -> String {
+    match *expr {
+        Expression::Base { ref unary, ref term, ref follow } => {
+            let mut out = match *term {
+                Term::Ident(ref name) => name.clone(),
+                Term::Int(value) => value.to_string(),
+                Term::Expr(ref expr) => show(expr),
+                Term::Call(ref name, ref args) => {
+                    format!("{}({})", name, args.iter().map(show).collect::<Vec<_>>().join(", "))
+                }
+                Term::Pick(ref args) => {
+                    let args: Vec<_> = args.iter().map(|&(ref weight, ref value)| match *weight {
+                        Some(ref weight) => format!("{}; {}", show(weight), show(value)),
+                        None => show(value),
+                    }).collect();
+                    format!("pick({})", args.join(", "))
+                }
+                ref other => format!("{:?}", other),
+            };
+            for each in follow.iter() {
+                match *each {
+                    Follow::Field(kind, ref name) => out = format!("{}{}{}", out, kind, name),
+                    Follow::Index(ref expr) => out = format!("{}[{}]", out, show(expr)),
+                    Follow::SafeIndex(ref expr) => out = format!("{}?[{}]", out, show(expr)),
+                    ref other => out = format!("{}{:?}", out, other),
+                }
+            }
+            for op in unary.iter() {
+                out = format!("{:?}({})", op, out);
+            }
+            out
+        }
+        Expression::BinaryOp { op, ref lhs, ref rhs } => format!("({} {} {})", show(lhs), op, show(rhs)),
+        Expression::AssignOp { op, ref lhs, ref rhs } => format!("({} {} {})", show(lhs), op, show(rhs)),
+        Expression::TernaryOp { ref cond, ref if_, ref else_ } => format!("({} ? {} : {})", show(cond), show(if_), show(else_)),
+    }
+}
+
+fn check(code: &str, expected: &str) {
+    assert_eq!(show(&parse_expr(code)), expected, "{}", code);
+}
+
+#[test]
+fn ternary_precedence() {
+    check("a ? b : c", "(a ? b : c)");
+    check("a || b ? c : d", "((a || b) ? c : d)");
+    check("a ? b : c || d", "(a ? b : (c || d))");
+    check("x = a ? b : c", "(x = (a ? b : c))");
+    check("x += a ? b : c", "(x += (a ? b : c))");
+    check("x = y = a ? b : c", "(x = (y = (a ? b : c)))");
+    check("a ? x = 1 : 2", "(a ? (x = 1) : 2)");
+}
+
+#[test]
+fn nested_ternaries() {
+    check("a ? b : c ? d : e", "(a ? b : (c ? d : e))");
+    check("a ? b ? c : d : e", "(a ? (b ? c : d) : e)");
+    check("a ? b ? c : d : e ? f : g", "(a ? (b ? c : d) : (e ? f : g))");
+    check("a ? b : c ? d : e ? f : g", "(a ? b : (c ? d : (e ? f : g)))");
+    check("(a ? b : c) ? d : e", "((a ? b : c) ? d : e)");
+    check("a ? (b ? c : d) : (e ? f : g)", "(a ? (b ? c : d) : (e ? f : g))");
+}
+
+#[test]
+fn ternaries_with_colon_paths() {
+    check("a ? b:c : d", "(a ? b:c : d)");
+    check("a ? b : c:d", "(a ? b : c:d)");
+    check("a ? b:c : d:e", "(a ? b:c : d:e)");
+    check("x = a ? b:c : d ? e:f : g", "(x = (a ? b:c : (d ? e:f : g)))");
+}
+
+#[test]
+fn newer_operators() {
+    check("a %% b * c", "((a %% b) * c)");
+    check("a + b %% c", "(a + (b %% c))");
+    check("a % b %% c", "((a % b) %% c)");
+    check("x %%= y + 1", "(x %%= (y + 1))");
+    check("x := y || z", "(x := (y || z))");
+    check("x := y = z", "(x := (y = z))");
+}
+
+#[test]
+fn safe_navigation() {
+    check("a?[b]", "a?[b]");
+    check("a?.b?.c[1]", "a?.b?.c[1]");
+    check("a?[b + 1]?:c", "a?[(b + 1)]?:c");
+    check("a?[b]?[c] || d", "(a?[b]?[c] || d)");
+    check("x ? a?[1] : b", "(x ? a?[1] : b)");
+}
+
+fn parse_expr_warnings(code: &str) -> (String, usize) {
+    let context = dm::Context::default();
+    context.enable_lint(dm::parser::LINT_IN_PRECEDENCE);
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let expr = Parser::new(&context, lexer).expression()
+        .expect("parse error")
+        .expect("no expression");
+    let warnings = context.errors().iter()
+        .filter(|e| e.severity() == dm::Severity::Warning)
+        .count();
+    (show(&expr), warnings)
+}
+
+#[test]
+fn in_precedence() {
+    check("x in list", "(x in list)");
+    check("x in list + 1", "(x in (list + 1))");
+    check("(x in list) && y", "((x in list) && y)");
+    check("x in (a && b)", "(x in (a && b))");
+    check("!(x in list)", "Not((x in list))");
+    check("x in 1 to 5", "(x in (1 to 5))");
+
+    // BYOND's `in` binds loosest, which is not what most readers expect
+    assert_eq!(parse_expr_warnings("x in list && y"), ("(x in (list && y))".to_owned(), 1));
+    assert_eq!(parse_expr_warnings("a && x in list"), ("((a && x) in list)".to_owned(), 1));
+    assert_eq!(parse_expr_warnings("x in a == b"), ("(x in (a == b))".to_owned(), 1));
+    assert_eq!(parse_expr_warnings("x in a ? b : c"), ("(x in (a ? b : c))".to_owned(), 1));
+    assert_eq!(parse_expr_warnings("!x in list"), ("(Not(x) in list)".to_owned(), 1));
+    // nothing is reported without the lint
+    check("a && x in list", "((a && x) in list)");
+}
+
+#[test]
+fn in_contexts() {
+    let code = r##"
+/proc/test(list/L)
+    for(var/x in L || list())
+        world << x
+    for(x in L && L.len ? L : list())
+        world << x
+    for(x in 1 to 10)
+        world << x
+    var/y = input("Pick one") in L || list()
+    if(locate(/obj) in world)
+        world << y
+"##.trim();
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let mut parser = Parser::new(&context, dm::indents::IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.run();
+    context.assert_success();
+}
+
+#[test]
+fn weighted_pick() {
+    check("pick(a, b, c)", "pick(a, b, c)");
+    check("pick(50; a, 30; b)", "pick(50; a, 30; b)");
+    check("pick(prob(x); a, b)", "pick(prob(x); a, b)");
+    check("pick(x + 1; a, 2 * y; b + c,)", "pick((x + 1); a, (2 * y); (b + c))");
+    check("pick(list)", "pick(list)");
+}
+
+#[test]
+fn weighted_pick_in_proc() {
+    let code = r##"
+/proc/test()
+    var/x = pick(
+        50; "a",
+        prob(30); "b",
+        "c")
+    if(pick(1; TRUE, 2; FALSE))
+        return x
+"##.trim();
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let mut parser = Parser::new(&context, dm::indents::IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.run();
+    context.assert_success();
+}
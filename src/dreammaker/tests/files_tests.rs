@@ -0,0 +1,53 @@
+extern crate dreammaker as dm;
+
+use std::path::Path;
+
+use dm::files::MemoryFiles;
+
+#[test]
+fn parse_from_memory() {
+    let mut files = MemoryFiles::default();
+    files.insert("game.dme", "#include \"code/mob.dm\"\n#include \"code/missing.dm\"\n");
+    files.insert("code/mob.dm", "/mob/player\n\tvar/health = 100\n");
+
+    let mut context = dm::Context::default();
+    context.set_file_provider(files);
+    let tree = context.parse_environment(Path::new("game.dme")).unwrap();
+
+    assert!(tree.find("/mob/player").is_some());
+    let errors = context.errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].description(), "failed to find file");
+    assert_eq!(errors[0].location().line, 2);
+}
+
+#[test]
+fn missing_environment() {
+    let mut context = dm::Context::default();
+    context.set_file_provider(MemoryFiles::default());
+    assert!(context.parse_environment(Path::new("game.dme")).is_err());
+}
+
+#[test]
+fn override_file_contents() {
+    let mut files = MemoryFiles::default();
+    files.insert("game.dme", "#include \"mob.dm\"\n");
+    files.insert("mob.dm", "/mob/saved\n");
+    let mut context = dm::Context::default();
+    context.set_file_provider(files);
+
+    let tree = context.parse_environment(Path::new("game.dme")).unwrap();
+    assert!(tree.find("/mob/saved").is_some());
+    let file = context.get_file(Path::new("mob.dm")).unwrap();
+
+    context.set_file_contents(Path::new("mob.dm"), b"/mob/typing\n".to_vec());
+    let tree = context.parse_environment(Path::new("game.dme")).unwrap();
+    assert!(tree.find("/mob/typing").is_some());
+    assert!(tree.find("/mob/saved").is_none());
+    assert_eq!(context.get_file(Path::new("mob.dm")), Some(file));
+    assert_eq!(tree.find("/mob/typing").unwrap().get().location.file, file);
+
+    context.clear_file_contents(Path::new("mob.dm"));
+    let tree = context.parse_environment(Path::new("game.dme")).unwrap();
+    assert!(tree.find("/mob/saved").is_some());
+}
@@ -0,0 +1,33 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::parser::Parser;
+use dm::indents::IndentProcessor;
+
+fn fix(code: &str) -> String {
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let indent = IndentProcessor::new(&context, lexer);
+    Parser::new(&context, indent).run();
+
+    let errors = context.errors();
+    let fixes: Vec<&dm::Fix> = errors.iter().flat_map(|e| e.fixes()).collect();
+    let (output, _) = dm::apply_fixes(code.as_bytes(), &fixes);
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn path_separator_fixes() {
+    assert_eq!(fix("/obj:foo.bar\n"), "/obj/foo/bar\n");
+    assert_eq!(fix(".obj/foo\n"), "obj/foo\n");
+}
+
+#[test]
+fn overlapping_fixes_skipped() {
+    let loc = |column| dm::Location { file: Default::default(), line: 1, column };
+    let a = dm::Fix::new(loc(1)..loc(3), "x", "first");
+    let b = dm::Fix::new(loc(2)..loc(4), "y", "second");
+    let (output, applied) = dm::apply_fixes(b"abcd", &[&a, &b]);
+    assert_eq!(output, b"xcd");
+    assert_eq!(applied, 1);
+}
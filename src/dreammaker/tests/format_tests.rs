@@ -28,4 +28,5 @@ fn floats() {
 
     assert_eq!(FormatFloat(std::f32::INFINITY).to_string(), "1.#INF");
     assert_eq!(FormatFloat(-std::f32::INFINITY).to_string(), "-1.#INF");
+    assert_eq!(FormatFloat(std::f32::NAN).to_string(), "1.#IND");
 }
@@ -0,0 +1,76 @@
+extern crate dreammaker as dm;
+
+use dm::formatting::*;
+
+fn format(source: &str, options: &FormatOptions, lines: std::ops::Range<u32>) -> String {
+    let fixes = format_lines(Default::default(), source, options, lines);
+    let fixes: Vec<_> = fixes.iter().collect();
+    String::from_utf8(dm::apply_fixes(source.as_bytes(), &fixes).0).unwrap()
+}
+
+fn spaces() -> FormatOptions {
+    FormatOptions { indent: IndentStyle::Spaces(4), .. Default::default() }
+}
+
+#[test]
+fn reindent_keeps_comments() {
+    let source = "\
+/obj
+\tname = \"thing\"   
+\t// what it does
+\tproc/use()  \t
+\t\t/* unused */ return 1
+\t
+";
+    assert_eq!(format(source, &spaces(), 1..100), "\
+/obj
+    name = \"thing\"
+    // what it does
+    proc/use()
+        /* unused */ return 1
+
+");
+    // tabs back again
+    let spaced = format(source, &spaces(), 1..100);
+    assert_eq!(format(&spaced, &FormatOptions::default(), 1..100), source
+        .replace("   \n", "\n")
+        .replace("  \t\n", "\n")
+        .replace("\n\t\n", "\n\n"));
+}
+
+#[test]
+fn continued_lines_untouched() {
+    let source = "\
+#define FOO 1
+/proc/test()
+\tvar/x = list(1,
+\t          2)
+\tworld << {\"first  
+\t  second\"}  
+";
+    assert_eq!(format(source, &spaces(), 1..100), "\
+#define FOO 1
+/proc/test()
+    var/x = list(1,
+\t          2)
+    world << {\"first  
+\t  second\"}
+");
+}
+
+#[test]
+fn format_range() {
+    let source = "\
+/obj
+\tname = \"a\" 
+\tdesc = \"b\" 
+\tgender = MALE 
+";
+    assert_eq!(format(source, &spaces(), 3..4), "\
+/obj
+\tname = \"a\" 
+    desc = \"b\"
+\tgender = MALE 
+");
+    assert!(format_file(Default::default(), "/obj\n\tname = 1\n", &Default::default()).is_empty());
+}
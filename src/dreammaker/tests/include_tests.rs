@@ -0,0 +1,81 @@
+extern crate dreammaker as dm;
+
+use std::path::Path;
+
+use dm::{Context, Location, FileId};
+use dm::files::MemoryFiles;
+use dm::preprocessor::{IncludeGraph, Preprocessor};
+
+fn at(file: FileId) -> Location {
+    Location { file, line: 1, column: 1 }
+}
+
+#[test]
+fn dependents_and_order() {
+    let context = Context::default();
+    let env = context.register_file(Path::new("test.dme"));
+    let defines = context.register_file(Path::new("code/__defines.dm"));
+    let mob = context.register_file(Path::new("code/mob.dm"));
+    let helpers = context.register_file(Path::new("code/helpers.dm"));
+
+    let mut graph = IncludeGraph::default();
+    graph.add_include(at(env), defines);
+    graph.add_include(at(env), mob);
+    graph.add_include(at(mob), helpers);
+    graph.add_include(at(defines), helpers);
+
+    assert_eq!(graph.includes(env).collect::<Vec<_>>(), vec![defines, mob]);
+    let mut dependents = graph.dependents(helpers);
+    dependents.sort();
+    assert_eq!(dependents, vec![env, defines, mob]);
+    assert_eq!(graph.dependents(env), vec![]);
+
+    let order = graph.topological_order().expect("unexpected cycle");
+    let pos = |file| order.iter().position(|&f| f == file).unwrap();
+    assert_eq!(order.len(), 4);
+    assert!(pos(env) < pos(defines));
+    assert!(pos(env) < pos(mob));
+    assert!(pos(mob) < pos(helpers));
+    assert!(pos(defines) < pos(helpers));
+}
+
+#[test]
+fn cycles() {
+    let context = Context::default();
+    let env = context.register_file(Path::new("test.dme"));
+    let a = context.register_file(Path::new("a.dm"));
+    let b = context.register_file(Path::new("b.dm"));
+
+    let mut graph = IncludeGraph::default();
+    graph.add_include(at(env), a);
+    graph.add_include(at(a), b);
+    graph.add_include(at(b), a);
+
+    assert_eq!(graph.topological_order(), Err(vec![a, b]));
+    let mut dependents = graph.dependents(a);
+    dependents.sort();
+    assert_eq!(dependents, vec![env, b]);
+}
+
+#[test]
+fn recursive_include_is_reported() {
+    let mut files = MemoryFiles::default();
+    files.insert("test.dme", "#include \"a.dm\"\n");
+    files.insert("a.dm", "#include \"b.dm\"\n/obj/a\n");
+    files.insert("b.dm", "#include \"a.dm\"\n/obj/b\n");
+    let mut context = Context::default();
+    context.set_file_provider(files);
+
+    let mut preprocessor = Preprocessor::new(&context, "test.dme".into()).unwrap();
+    preprocessor.by_ref().count();
+
+    let errors: Vec<_> = context.errors().iter()
+        .map(|e| (e.location().line, e.description().to_owned()))
+        .collect();
+    assert_eq!(errors, vec![(1, "recursive #include of a.dm".to_owned())]);
+    assert_eq!(context.file_path(context.errors()[0].location().file), Path::new("b.dm"));
+
+    let a = context.get_file(Path::new("a.dm")).unwrap();
+    let b = context.get_file(Path::new("b.dm")).unwrap();
+    assert_eq!(preprocessor.include_graph().topological_order(), Err(vec![a, b]));
+}
@@ -0,0 +1,50 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+
+fn diagnostics(code: &str) -> Vec<(u32, String)> {
+    let context = dm::Context::default();
+    context.enable_lint(dm::indents::LINT_MIXED_INDENTATION);
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    IndentProcessor::new(&context, lexer).for_each(drop);
+    let errors = context.errors();
+    errors.iter().map(|e| (e.location().line, e.description().to_owned())).collect()
+}
+
+#[test]
+fn consistent_indentation() {
+    assert!(diagnostics("/obj\n\tvar/x\n\tproc/f()\n\t\treturn\n").is_empty());
+    assert!(diagnostics("/obj\n    var/x\n    proc/f()\n        return\n").is_empty());
+}
+
+#[test]
+fn dedent_to_missing_level() {
+    assert_eq!(diagnostics("/obj\n    proc/f()\n        return\n      var/x\n"), vec![
+        (4, "inconsistent indentation: found 6 spaces, expected 0, 4, 8, or 12".to_owned()),
+    ]);
+}
+
+#[test]
+fn multiple_indentation() {
+    assert_eq!(diagnostics("/obj\n\tvar/x\n\t\t\tvar/y\n"), vec![
+        (3, "inconsistent multiple indentation: found 3 tabs, expected at most 2".to_owned()),
+    ]);
+}
+
+#[test]
+fn mixed_whitespace() {
+    assert_eq!(diagnostics("/obj\n\tvar/x\n\t    var/y\n"), vec![
+        (3, "indentation mixes tabs and spaces".to_owned()),
+        (3, "inconsistent multiple indentation: found 5 whitespace characters, expected at most 2".to_owned()),
+    ]);
+    assert_eq!(diagnostics("/obj\n\tvar/x\n/mob\n    var/y\n"), vec![
+        (4, "indented with spaces, but earlier lines are indented with tabs".to_owned()),
+    ]);
+
+    // both are off unless the lint is enabled
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), "/obj\n\tvar/x\n/mob\n    var/y\n".bytes().map(Ok));
+    IndentProcessor::new(&context, lexer).for_each(drop);
+    context.assert_success();
+}
@@ -32,3 +32,202 @@ fn nested_interpolation() {
         ]
     );
 }
+
+fn round_trip(f: &str, edits: &[(std::ops::Range<usize>, std::string::String)]) -> std::string::String {
+    let context = Default::default();
+    let tokens: Vec<_> = Lexer::new(&context, Default::default(), f.bytes().map(Ok))
+        .lossless()
+        .collect();
+    let mut output = Vec::new();
+    reemit(&mut output, &tokens, edits).unwrap();
+    std::string::String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn lossless_round_trip() {
+    let source = "/obj/foo  // comment\r\n\tvar/bar = \"A[B \\\n]C\" /* block */\n\tname = {\"x\"} ; \\\n  x\n";
+    assert_eq!(round_trip(source, &[]), source);
+}
+
+#[test]
+fn lossless_edits() {
+    // tokens: `a`, `=`, `1`, Newline, synthetic Newline
+    assert_eq!(round_trip("a = 1 // one\n", &[(2..3, "2".to_owned())]), "a = 2 // one\n");
+    assert_eq!(round_trip("a = 1\n", &[(0..0, "var/".to_owned())]), "var/a = 1\n");
+}
+
+fn lex_encoded(f: &[u8], encoding: Encoding) -> Vec<Token> {
+    let mut context = dm::Context::default();
+    context.set_encoding(encoding);
+    let result = Lexer::new(&context, Default::default(), f.iter().cloned().map(Ok))
+        .map(|t| t.token)
+        .collect();
+    context.assert_success();
+    result
+}
+
+#[test]
+fn encodings() {
+    let utf8 = "\"caf\u{e9}\"".as_bytes();
+    let latin1 = b"\"caf\xe9\"";
+    assert_eq!(lex_encoded(utf8, Encoding::Latin1), vec![String("caf\u{c3}\u{a9}".into()), Punct(Newline)]);
+    assert_eq!(lex_encoded(utf8, Encoding::Utf8), vec![String("caf\u{e9}".into()), Punct(Newline)]);
+    assert_eq!(lex_encoded(latin1, Encoding::Utf8), vec![String("caf\u{fffd}".into()), Punct(Newline)]);
+    assert_eq!(lex_encoded(utf8, Encoding::Autodetect), vec![String("caf\u{e9}".into()), Punct(Newline)]);
+    assert_eq!(lex_encoded(latin1, Encoding::Autodetect), vec![String("caf\u{e9}".into()), Punct(Newline)]);
+    assert_eq!("utf-8".parse(), Ok(Encoding::Utf8));
+}
+
+#[test]
+fn byte_order_mark() {
+    let source = b"\xef\xbb\xbf\"caf\xc3\xa9\"";
+    assert_eq!(lex_encoded(source, Encoding::Autodetect), vec![String("caf\u{e9}".into()), Punct(Newline)]);
+    assert_eq!(lex_encoded(source, Encoding::Latin1), vec![String("caf\u{c3}\u{a9}".into()), Punct(Newline)]);
+}
+
+#[test]
+fn partial_byte_order_mark() {
+    for &(source, ref expected) in &[
+        (&b"\xef\"a\""[..], String("a".into())),
+        (&b"\xefx"[..], Ident("x".into(), false)),
+    ] {
+        let context = dm::Context::default();
+        let tokens: Vec<_> = Lexer::new(&context, Default::default(), source.iter().cloned().map(Ok))
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(tokens, vec![expected.clone(), Punct(Newline)]);
+        let errors: Vec<_> = context.errors().iter().map(|e| e.description().to_owned()).collect();
+        assert_eq!(errors, vec!["illegal byte 0xef"]);
+    }
+}
+
+fn lex_number(f: &str) -> (Token, usize) {
+    let context = dm::Context::default();
+    let mut tokens: Vec<_> = Lexer::new(&context, Default::default(), f.bytes().map(Ok))
+        .map(|t| t.token)
+        .collect();
+    assert_eq!(tokens.pop(), Some(Punct(Newline)));
+    assert_eq!(tokens.len(), 1, "{:?} lexed as {:?}", f, tokens);
+    let errors = context.errors().len();
+    (tokens.pop().unwrap(), errors)
+}
+
+#[test]
+fn numeric_forms() {
+    for &(source, ref expected) in &[
+        ("0", Int(0)),
+        ("123", Int(123)),
+        ("1_000", Int(1000)),
+        ("017", Int(15)),
+        ("0x1F", Int(31)),
+        ("0xe5", Int(229)),
+        ("1.5", Float(1.5)),
+        ("1.", Float(1.0)),
+        ("1e5", Float(1e5)),
+        ("1E+5", Float(1e5)),
+        ("2.5e-3", Float(2.5e-3)),
+        ("1.#INF", Float(std::f32::INFINITY)),
+    ] {
+        assert_eq!(lex_number(source), (expected.clone(), 0), "{}", source);
+    }
+
+    match lex_number("1.#IND") {
+        (Float(val), 0) => assert!(val.is_nan()),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn malformed_numbers() {
+    assert_eq!(lex_number("1e5e"), (Float(1e5), 1));
+    assert_eq!(lex_number("1e"), (Float(1.0), 1));
+    assert_eq!(lex_number("1.5e+"), (Float(1.5), 1));
+    assert_eq!(lex_number("0x"), (Int(0), 1));
+    assert_eq!(lex_number("0x1g"), (Int(1), 1));
+    assert_eq!(lex_number("1.2.3"), (Float(1.2), 1));
+    assert_eq!(lex_number("1.#INX"), (Float(0.0), 1));
+}
+
+#[test]
+fn newer_operators() {
+    assert_eq!(lex("a%%b%%=c%=d%e"), vec![
+        Ident("a".into(), false),
+        Punct(FloatMod),
+        Ident("b".into(), false),
+        Punct(FloatModAssign),
+        Ident("c".into(), false),
+        Punct(ModAssign),
+        Ident("d".into(), false),
+        Punct(Mod),
+        Ident("e".into(), false),
+        Punct(Newline),
+    ]);
+    assert_eq!(lex("a := b:c"), vec![
+        Ident("a".into(), true),
+        Punct(AssignInto),
+        Ident("b".into(), false),
+        Punct(CloseColon),
+        Ident("c".into(), false),
+        Punct(Newline),
+    ]);
+}
+
+fn lex_errors(input: &[u8]) -> Vec<std::string::String> {
+    let context = dm::Context::default();
+    let _: Vec<_> = Lexer::new(&context, Default::default(), input.iter().cloned().map(Ok)).collect();
+    let errors = context.errors().iter().map(|e| e.description().to_owned()).collect();
+    errors
+}
+
+#[test]
+fn error_spans() {
+    for &(input, start, end) in &[
+        (&b"a = 0x1g"[..], 5, 9),
+        (&b"a = 1e"[..], 5, 7),
+        (&b"\"abc"[..], 1, 5),
+    ] {
+        let context = dm::Context::default();
+        let _: Vec<_> = Lexer::new(&context, Default::default(), input.iter().cloned().map(Ok)).collect();
+        let span = context.errors()[0].span();
+        assert_eq!((span.start.column, span.end.column), (start, end), "{:?}", std::str::from_utf8(input));
+    }
+}
+
+#[test]
+fn adversarial_input() {
+    // none of these may panic, only report errors
+    let inputs: &[&[u8]] = &[
+        b"\"unterminated",
+        b"\"[[[[[[",
+        b"\"]]]]\"]]]",
+        b"{\"",
+        b"@",
+        b"0x",
+        b"1e",
+        b"1.#",
+        b"/* /* unterminated",
+        b"\\",
+        b"#define",
+        b"'unterminated resource",
+        b"\xff\xfe\x00\x80",
+    ];
+    for input in inputs {
+        lex_errors(input);
+    }
+}
+
+#[test]
+fn location_limits() {
+    let long_line = vec![b'a'; 70000];
+    let errors = lex_errors(&long_line);
+    assert_eq!(errors, vec!["per-line column limit of 65535 exceeded, later locations are inaccurate"]);
+}
+
+#[test]
+fn throughput_counters() {
+    let context = dm::Context::default();
+    let tokens = Lexer::new(&context, Default::default(), b"var/a = 1\n".iter().cloned().map(Ok)).count();
+    let throughput = context.throughput();
+    assert_eq!(throughput.bytes, 10);
+    assert_eq!(throughput.tokens, tokens as u64);
+}
@@ -0,0 +1,45 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::annotation::AnnotationTree;
+
+#[test]
+fn memory_report() {
+    let code = r##"
+/datum
+    var/name = "datum"
+    var/list/things = list(1, 2, 3)
+
+/// Does the thing.
+/datum/proc/thing(a, b = 2)
+    for(var/i in 1 to a)
+        world.log << "[i]: [b]"
+    return things[1]
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let mut annotations = AnnotationTree::default();
+    let tree = {
+        let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+        parser.enable_procs();
+        parser.annotate_to(&mut annotations);
+        parser.run();
+        parser.finalize_object_tree()
+    };
+
+    let mut report = tree.memory_report();
+    assert!(report.types > 0);
+    assert!(report.vars > 0);
+    assert!(report.procs > 0);
+    assert!(report.proc_bodies > 0);
+    assert!(report.docs > 0);
+    assert_eq!(report.annotations, 0);
+
+    report.add_annotations(&annotations);
+    assert!(report.annotations > 0);
+    assert_eq!(report.total(), report.types + report.vars + report.procs + report.proc_bodies + report.docs + report.annotations);
+    assert!(report.to_string().contains("proc bodies"));
+}
@@ -0,0 +1,53 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::metrics::*;
+
+#[test]
+fn proc_metrics() {
+    let code = r##"
+/proc/simple()
+    return 1
+
+/proc/branchy(a, b)
+    if(a && b)
+        for(var/i in 1 to 10)
+            if(i == 5)
+                sleep(1)
+    else if(a)
+        return a ? 1 : 2
+    switch(b)
+        if(1)
+            world << "one"
+        if(2, 3)
+            world << "two or three"
+    spawn(10)
+        if(a || b)
+            sleep(1)
+"##;
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+
+    let reports = measure_all(&tree);
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].path(), "/proc/branchy");
+    assert_eq!(reports[0].metrics, ProcMetrics {
+        statements: 11,
+        // if/else-if, for, inner if, switch cases, spawned if, &&, ?:, ||
+        complexity: 11,
+        max_depth: 3,
+        sleeps: 1,
+    });
+    assert_eq!(reports[1].path(), "/proc/simple");
+    assert_eq!(reports[1].metrics, ProcMetrics {
+        statements: 1,
+        complexity: 1,
+        max_depth: 0,
+        sleeps: 0,
+    });
+}
@@ -0,0 +1,21 @@
+extern crate dreammaker as dm;
+
+use dm::opcodes::*;
+
+#[test]
+fn opcode_table() {
+    let all = opcodes(512);
+    for pair in all.windows(2) {
+        assert!(pair[0].code < pair[1].code, "{} and {} out of order", pair[0].name, pair[1].name);
+    }
+    for op in all.iter() {
+        assert_eq!(by_name(op.name, 512), Some(*op));
+    }
+
+    let jz = by_code(0x11, 512).unwrap();
+    assert_eq!(jz.name, "Jz");
+    assert!(jz.is_branch());
+    assert_eq!(by_name("CallGlobal", 512).unwrap().operands, &[OperandKind::ArgCount, OperandKind::Proc]);
+    assert!(!OperandKind::Var.is_fixed_width());
+    assert!(by_code(0xFFFF, 512).is_none());
+}
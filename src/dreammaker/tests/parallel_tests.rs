@@ -0,0 +1,57 @@
+extern crate dreammaker as dm;
+
+use std::path::Path;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+
+#[test]
+fn parallel_matches_sequential() {
+    let first = "/obj/foo\n\tvar/health = 10\n\tproc/hit()\n/obj/foo/bar\n";
+    let second = "/obj/foo\n\thealth = 20\n\thit()\n/obj/foo/bar/var/armor = 5\n";
+
+    let parse = |parallel| {
+        let context = dm::Context::default();
+        let a = context.register_file(Path::new("a.dm"));
+        let b = context.register_file(Path::new("b.dm"));
+        let tokens = Lexer::new(&context, a, first.bytes().map(Ok))
+            .chain(Lexer::new(&context, b, second.bytes().map(Ok)));
+        let indents = IndentProcessor::new(&context, tokens);
+        let tree = if parallel {
+            dm::parser::parse_parallel(&context, indents, true)
+        } else {
+            dm::parser::parse(&context, indents)
+        };
+        context.assert_success();
+        tree
+    };
+
+    for &parallel in [false, true].iter() {
+        let tree = parse(parallel);
+        let foo = tree.find("/obj/foo").unwrap();
+        assert_eq!(foo.get_value("health").unwrap().constant, Some(dm::constants::Constant::Int(20)));
+        assert_eq!(foo.procs["hit"].value.len(), 2);
+        assert!(foo.get_declaration("health").is_some());
+        let bar = tree.find("/obj/foo/bar").unwrap();
+        assert_eq!(bar.get_value("armor").unwrap().constant, Some(dm::constants::Constant::Int(5)));
+    }
+}
+
+#[test]
+fn register_errors_while_reading_them() {
+    let context = dm::Context::default();
+    context.register_error(dm::DMError::new(Default::default(), "first"));
+    for error in context.errors().iter() {
+        context.register_error(dm::DMError::new(error.location(), "second"));
+    }
+    assert_eq!(context.errors().len(), 2);
+}
+
+#[test]
+fn has_errors_at_severity() {
+    let context = dm::Context::default();
+    context.register_error(dm::DMError::new(Default::default(), "unused").set_severity(dm::Severity::Warning));
+    assert!(!context.has_errors_at(dm::Severity::Error));
+    assert!(context.has_errors_at(dm::Severity::Warning));
+    assert!(context.has_errors_at(dm::Severity::Hint));
+}
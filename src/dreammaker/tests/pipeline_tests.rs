@@ -1,9 +1,12 @@
 extern crate dreammaker as dm;
 
+use std::io::Cursor;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use dm::*;
-use dm::preprocessor::Preprocessor;
+use dm::preprocessor::{Preprocessor, DefineMap};
 
 fn with_test_dme<F: FnOnce(Preprocessor)>(context: &Context, f: F) {
     let dme = match std::env::var_os("TEST_DME") {
@@ -50,3 +53,49 @@ fn check_parser() {
         println!("\n--------\nSuccess!\n--------");
     });
 }
+
+#[test]
+fn cancellation() {
+    let code = "/datum/foo\n\tvar/bar = 1\n#ifdef FOO\n";
+    let parse = |cancelled: bool| {
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut context = Context::default();
+        context.set_cancel_flag(flag.clone());
+        flag.store(cancelled, Ordering::Relaxed);
+
+        let mut preprocessor = Preprocessor::from_defines(&context, DefineMap::default());
+        preprocessor.push_file("test.dm".into(), Cursor::new(code.as_bytes().to_vec()));
+        let tree = parser::parse(&context, indents::IndentProcessor::new(&context, preprocessor));
+        let errors = context.errors().len();
+        (tree.find("/datum/foo").is_some(), errors)
+    };
+
+    assert!(!Context::default().is_cancelled());
+    // the unterminated #ifdef is reported
+    assert_eq!(parse(false), (true, 1));
+    // nothing is parsed or analyzed once cancelled
+    assert_eq!(parse(true), (false, 0));
+}
+
+#[test]
+fn progress() {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let mut context = Context::default();
+    {
+        let reports = reports.clone();
+        context.set_progress_handler(move |phase, done, total| reports.lock().unwrap().push((phase, done, total)));
+    }
+
+    let mut preprocessor = Preprocessor::from_defines(&context, DefineMap::default());
+    preprocessor.push_file("test.dm".into(), Cursor::new(b"/datum/foo\n/datum/bar\n".to_vec()));
+    let tree = parser::parse(&context, indents::IndentProcessor::new(&context, preprocessor));
+    let types = tree.graph.node_count();
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports[0], (Phase::Parsing, 1, 1));
+    for &phase in [Phase::Evaluating, Phase::Analyzing].iter() {
+        let phase_reports: Vec<_> = reports.iter().filter(|r| r.0 == phase).collect();
+        assert_eq!(phase_reports[0], &(phase, 0, types));
+        assert_eq!(phase_reports.last().unwrap(), &&(phase, types, types));
+    }
+}
@@ -0,0 +1,179 @@
+extern crate dreammaker as dm;
+
+use std::io::Cursor;
+use std::path::Path;
+
+use dm::Context;
+use dm::lexer::Token;
+use dm::lexer::Token::*;
+use dm::lexer::Punctuation::*;
+use dm::preprocessor::{Preprocessor, DefineMap};
+
+fn preprocess(context: &Context, code: &str) -> Vec<Token> {
+    let mut preprocessor = Preprocessor::from_defines(context, DefineMap::default());
+    preprocessor.push_file("test.dm".into(), Cursor::new(code.to_owned().into_bytes()));
+    preprocessor
+        .filter_map(|t| match t.token {
+            Punct(Newline) | Punct(Space) | Punct(Tab) => None,
+            // whitespace-following doesn't matter here
+            Ident(name, _) => Some(Ident(name, false)),
+            other => Some(other),
+        })
+        .collect()
+}
+
+#[test]
+fn special_macros() {
+    let context = Context::default();
+    let tokens = preprocess(&context, r#"
+#define HERE __FILE__, __LINE__
+#define NAME(x) #x
+HERE
+NAME(foo)
+"#.trim());
+    context.assert_success();
+    assert_eq!(tokens, vec![
+        String("test.dm".to_owned()), Punct(Comma), Int(3),
+        String("foo".to_owned()),
+    ]);
+}
+
+#[test]
+fn recursive_expansion() {
+    let context = Context::default();
+    let tokens = preprocess(&context, r#"
+#define A B
+#define B A
+A
+#define C C + 1
+C
+"#.trim());
+    assert_eq!(tokens, vec![
+        Ident("A".to_owned(), false),
+        Ident("C".to_owned(), false), Punct(Add), Int(1),
+    ]);
+
+    let errors = context.errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].description(), "macro expansion exceeded depth limit of 64: A -> B -> A");
+}
+
+#[test]
+fn token_pasting() {
+    let context = Context::default();
+    let tokens = preprocess(&context, r#"
+#define CAT(a, b) a##b
+CAT(foo, bar) CAT(item, 5) CAT(x, ) CAT(, y)
+"#.trim());
+    context.assert_success();
+    assert_eq!(tokens, vec![
+        Ident("foobar".to_owned(), false),
+        Ident("item5".to_owned(), false),
+        Ident("x".to_owned(), false),
+        Ident("y".to_owned(), false),
+    ]);
+}
+
+#[test]
+fn stringizing() {
+    let context = Context::default();
+    let tokens = preprocess(&context, r#"
+#define STR(x) #x
+#define GREET(name) "Hello, [name]!"
+STR(foo.bar + 1)
+GREET(usr)
+"#.trim());
+    context.assert_success();
+    assert_eq!(tokens, vec![
+        String("foo.bar + 1".to_owned()),
+        InterpStringBegin("Hello, ".to_owned()),
+        Ident("usr".to_owned(), false),
+        InterpStringEnd("!".to_owned()),
+    ]);
+}
+
+#[test]
+fn real_world_macros() {
+    let context = Context::default();
+    let tokens = preprocess(&context, r#"
+#define NAMEOF(datum, X) (#X || ##datum.##X)
+#define VARSET_CALLBACK(datum, var, var_value) CALLBACK(GLOBAL_PROC, /proc/___callbackvarset, ##datum, NAMEOF(##datum, ##var), ##var_value)
+#define SHOULD_NOT_SLEEP(X) set SpacemanDMM_should_not_sleep = X
+#define SIGNAL_HANDLER SHOULD_NOT_SLEEP(TRUE)
+VARSET_CALLBACK(src, name, "bob")
+SIGNAL_HANDLER
+"#.trim());
+    context.assert_success();
+
+    let ident = |s: &str| Ident(s.to_owned(), false);
+    assert_eq!(tokens, vec![
+        ident("CALLBACK"), Punct(LParen),
+        ident("GLOBAL_PROC"), Punct(Comma),
+        Punct(Slash), ident("proc"), Punct(Slash), ident("___callbackvarset"), Punct(Comma),
+        ident("src"), Punct(Comma),
+        Punct(LParen), String("name".to_owned()), Punct(Or), ident("src"), Punct(Dot), ident("name"), Punct(RParen),
+        Punct(Comma),
+        String("bob".to_owned()),
+        Punct(RParen),
+        ident("set"), ident("SpacemanDMM_should_not_sleep"), Punct(Assign), ident("TRUE"),
+    ]);
+}
+
+#[test]
+fn redefinition_notes_previous() {
+    let context = Context::default();
+    preprocess(&context, "#define A 1\n\n#define A 2\n");
+    let errors = context.errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].description(), "macro redefined: A");
+    assert_eq!(errors[0].location().line, 3);
+    let notes = errors[0].notes();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].location.line, 1);
+    assert_eq!(notes[0].description, "previous definition here");
+
+    let mut printed = Vec::new();
+    context.pretty_print_error(&mut printed, &errors[0]).unwrap();
+    assert_eq!(::std::str::from_utf8(&printed).unwrap(), "\
+warning: macro redefined: A
+ --> test.dm:3:9
+  = note: test.dm:1:9: previous definition here
+
+");
+}
+
+#[test]
+fn diagnostic_snippets() {
+    let context = Context::default();
+    let code = "#define A 1\n\n#define A 2\n";
+    context.set_file_contents(Path::new("test.dm"), code.as_bytes().to_vec());
+    preprocess(&context, code);
+    let errors = context.errors();
+
+    let mut printed = Vec::new();
+    context.pretty_print_error(&mut printed, &errors[0]).unwrap();
+    assert_eq!(::std::str::from_utf8(&printed).unwrap(), "\
+warning: macro redefined: A
+ --> test.dm:3:9
+  |
+3 | #define A 2
+  |         ^
+  = note: test.dm:1:9: previous definition here
+
+");
+}
+
+#[test]
+fn macro_use_on_long_line() {
+    let context = Context::default();
+    let mut preprocessor = Preprocessor::from_defines(&context, DefineMap::default());
+    let code = format!("#define FOO 1\n{}FOO\n", " ".repeat(70000));
+    preprocessor.push_file("test.dm".into(), Cursor::new(code.into_bytes()));
+    let tokens = preprocessor.by_ref().filter(|t| t.token == Int(1)).count();
+    assert_eq!(tokens, 1);
+
+    // columns past the limit saturate rather than overflowing
+    let uses = preprocessor.macro_uses();
+    assert_eq!(uses.len(), 1);
+    assert_eq!(uses[0].0.end.column, u16::max_value());
+}
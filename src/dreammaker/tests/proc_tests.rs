@@ -0,0 +1,335 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::objtree::{ObjectTree, ProcKind, Code};
+use dm::ast::{Block, SettingMode, Statement, VarType};
+
+fn parse(code: &str) -> (ObjectTree, Vec<String>) {
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    let errors = context.errors().iter().map(|e| e.description().to_owned()).collect();
+    (tree, errors)
+}
+
+#[test]
+fn proc_kinds() {
+    let (tree, errors) = parse(r##"
+/mob/proc/think()
+/mob/verb/say()
+/mob/think()
+/mob/player/say()
+"##);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let mob = tree.find("/mob").unwrap();
+    let kinds: Vec<_> = mob.get().procs["think"].value.iter().map(|p| p.kind).collect();
+    assert_eq!(kinds, vec![ProcKind::Proc, ProcKind::Override]);
+    assert_eq!(mob.get().procs["say"].value[0].kind, ProcKind::Verb);
+
+    let player = tree.find("/mob/player").unwrap();
+    assert_eq!(player.get().procs["say"].value[0].kind, ProcKind::Override);
+}
+
+#[test]
+fn settings() {
+    let (tree, errors) = parse(r##"
+/mob/verb/look()
+    set name = "Look Around"
+    set category = null
+    set hidden = 1
+    set src in view(1)
+    set invisibility = 101
+    world << "hi"
+"##);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let settings = &tree.find("/mob").unwrap().get_proc("look").unwrap().settings;
+    assert_eq!(settings.name, Some("Look Around".to_owned()));
+    assert_eq!(settings.desc, None);
+    assert_eq!(settings.category, Some(None));
+    assert_eq!(settings.hidden, Some(true));
+    assert_eq!(settings.instant, None);
+    assert_eq!(settings.invisibility, Some(101));
+    assert_eq!(settings.src.as_ref().map(|s| s.0.clone()), Some(SettingMode::In));
+}
+
+#[test]
+fn bad_settings() {
+    let (_, errors) = parse(r##"
+/mob/verb/look()
+    set nmae = "Look"
+    set name in view()
+    set desc = 5
+    set hidden = "yes"
+    set category = "One"
+    set category = "Two"
+"##);
+    assert_eq!(errors, vec![
+        "unknown setting 'set nmae'",
+        "'set name in' is only meaningful for src",
+        "bad 'set desc': expected a string, got 5",
+        "bad 'set hidden': expected a number, got \"yes\"",
+        "bad 'set category': set more than once",
+    ]);
+}
+
+#[test]
+fn try_catch() {
+    let (tree, errors) = parse(r##"
+/proc/risky()
+    try
+        throw EXCEPTION("oops")
+    catch(var/exception/e)
+        world.log << e.name
+    try
+        throw "bare"
+    catch(existing)
+        return existing
+    try
+        risky()
+    catch
+        return
+"##);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let proc = tree.root().get_proc("risky").unwrap();
+    let block = match proc.code {
+        Code::Present(ref block) => block,
+        ref other => panic!("{:?}", other),
+    };
+    let params: Vec<_> = block.iter().map(|stmt| match stmt.elem {
+        Statement::TryCatch { ref try_block, ref catch_param, .. } => {
+            match try_block[0].elem {
+                Statement::Throw(_) | Statement::Expr(_) => {}
+                ref other => panic!("{:?}", other),
+            }
+            catch_param.clone()
+        }
+        ref other => panic!("{:?}", other),
+    }).collect();
+    assert_eq!(params, vec![
+        Some((Some(vec!["exception".to_owned()].into_iter().collect::<VarType>()), "e".to_owned())),
+        Some((None, "existing".to_owned())),
+        None,
+    ]);
+}
+
+fn proc_body(tree: &ObjectTree, name: &str) -> Block {
+    match tree.root().get_proc(name).unwrap().code {
+        Code::Present(ref block) => block.clone(),
+        ref other => panic!("{:?}", other),
+    }
+}
+
+fn var_names(stmt: &Statement) -> Vec<(String, bool)> {
+    match *stmt {
+        Statement::Var(ref var) => vec![(var.name.clone(), var.value.is_some())],
+        Statement::Vars(ref vars) => vars.iter().map(|var| (var.name.clone(), var.value.is_some())).collect(),
+        ref other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn multiple_vars() {
+    let (tree, errors) = parse(r##"
+/proc/decls()
+    var/a, b = 2, c
+    var/obj/item/x, y
+    var/single = 1
+"##);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let block = proc_body(&tree, "decls");
+    assert_eq!(var_names(&block[0].elem), vec![
+        ("a".to_owned(), false),
+        ("b".to_owned(), true),
+        ("c".to_owned(), false),
+    ]);
+    assert_eq!(var_names(&block[1].elem), vec![("x".to_owned(), false), ("y".to_owned(), false)]);
+    match block[1].elem {
+        Statement::Vars(ref vars) => assert_eq!(vars[0].var_type.type_path, vec!["obj", "item"]),
+        ref other => panic!("{:?}", other),
+    }
+    assert_eq!(var_names(&block[2].elem), vec![("single".to_owned(), true)]);
+}
+
+#[test]
+fn for_loop_declarations() {
+    let (tree, errors) = parse(r##"
+/proc/loops()
+    for(var/i = 1, i <= 10, i++)
+        world << i
+    for(var/a = 1, b = 10; a < b; a++)
+        world << a
+    for(var/x, y = 2, var/z = 3; x < z;)
+        world << y
+"##);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let block = proc_body(&tree, "loops");
+    let inits: Vec<_> = block.iter().map(|stmt| match stmt.elem {
+        Statement::ForLoop { ref init, ref test, .. } => {
+            assert!(test.is_some());
+            var_names(init.as_ref().unwrap())
+        }
+        ref other => panic!("{:?}", other),
+    }).collect();
+    assert_eq!(inits, vec![
+        vec![("i".to_owned(), true)],
+        vec![("a".to_owned(), true), ("b".to_owned(), true)],
+        vec![("x".to_owned(), false), ("y".to_owned(), true), ("z".to_owned(), true)],
+    ]);
+}
+
+#[test]
+fn for_loop_forms() {
+    let (tree, errors) = parse(r##"
+/proc/loops(list/L)
+    for()
+        break
+    for(,,)
+        break
+    for(;;)
+        break
+    for(var/i = 1; ; i += 2)
+        if(i > 10)
+            break
+    for(var/x in 1 to 10 step 2)
+        world << x
+    for(var/y = 10 to 1 step -1)
+        world << y
+    var/z
+    for(z = 1 to 5)
+        world << z
+    for(z in 1 to 5 step 2)
+        world << z
+    for(var/obj/O as obj in L)
+        world << O
+    for(z in L)
+        world << z
+"##);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let shapes: Vec<_> = proc_body(&tree, "loops").iter().map(|stmt| match stmt.elem {
+        Statement::ForLoop { ref init, ref test, ref inc, .. } => {
+            format!("loop {} {} {}", init.is_some(), test.is_some(), inc.is_some())
+        }
+        Statement::ForRange { ref var_type, ref step, .. } => {
+            format!("range {} {}", var_type.is_some(), step.is_some())
+        }
+        Statement::ForList { ref var_type, ref in_list, .. } => {
+            format!("list {} {}", var_type.is_some(), in_list.is_some())
+        }
+        Statement::Var(_) => "var".to_owned(),
+        ref other => panic!("{:?}", other),
+    }).collect();
+    assert_eq!(shapes, vec![
+        "loop false false false",
+        "loop false false false",
+        "loop false false false",
+        "loop true false true",
+        "range true true",
+        "range true true",
+        "var",
+        "range false false",
+        "range false true",
+        "list true true",
+        "list false true",
+    ]);
+}
+
+#[test]
+fn labels_and_ternaries() {
+    let (tree, errors) = parse(r##"
+/proc/labels(a, b)
+    start:
+    a ? b : a
+    a = b ? 1 : 2
+    loop:
+        for(var/i in 1 to 3)
+            continue loop
+    b:
+    goto b
+"##);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let kinds: Vec<_> = proc_body(&tree, "labels").iter().map(|stmt| match stmt.elem {
+        Statement::Label(ref name, ref block) => format!("label {} {}", name, block.len()),
+        Statement::Expr(_) => "expr".to_owned(),
+        Statement::Goto(ref name) => format!("goto {}", name),
+        ref other => panic!("{:?}", other),
+    }).collect();
+    assert_eq!(kinds, vec!["label start 0", "expr", "expr", "label loop 1", "label b 0", "goto b"]);
+}
+
+fn nesting_errors(context: dm::Context, parens: usize) -> Vec<String> {
+    let code = format!("/proc/deep()\n\treturn {}1{}\n", "(".repeat(parens), ")".repeat(parens));
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.parse_object_tree();
+    let errors = context.errors().iter().map(|e| e.description().to_owned()).collect();
+    errors
+}
+
+#[test]
+fn nesting_limit() {
+    let mut context = dm::Context::default();
+    context.set_nesting_limit(16);
+    assert!(nesting_errors(context, 10).is_empty());
+
+    let mut context = dm::Context::default();
+    context.set_nesting_limit(16);
+    assert_eq!(nesting_errors(context, 20), vec!["nesting exceeds the limit of 16 levels"]);
+
+    // the default limit is reached well before a main thread's stack runs out
+    let errors = ::std::thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(|| nesting_errors(dm::Context::default(), 10000))
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(errors, vec![format!("nesting exceeds the limit of {} levels", dm::DEFAULT_NESTING_LIMIT)]);
+}
+
+#[test]
+fn lazy_bodies() {
+    let code = r##"
+/proc/good()
+    var/x = 1
+    return x + 1
+
+/proc/bad()
+    return 1 2
+"##;
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_lazy_procs();
+    let tree = parser.parse_object_tree();
+    assert!(context.errors().is_empty());
+
+    let good = tree.root().get_proc("good").unwrap();
+    match good.code {
+        Code::Lazy(ref lazy) => assert!(!lazy.is_parsed()),
+        ref other => panic!("{:?}", other),
+    }
+    let body = good.body(&context).unwrap();
+    assert_eq!(body.len(), 2);
+    match good.code {
+        Code::Lazy(ref lazy) => assert!(lazy.is_parsed()),
+        ref other => panic!("{:?}", other),
+    }
+    assert_eq!(*good.body(&context).unwrap(), *body);
+
+    // errors are reported once, on first parse
+    let bad = tree.root().get_proc("bad").unwrap();
+    assert!(bad.body(&context).is_none());
+    assert!(bad.body(&context).is_none());
+    assert_eq!(context.errors().len(), 1);
+}
@@ -0,0 +1,90 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::profile::*;
+
+const PROFILE: &str = "
+Proc Name,Self CPU,Total CPU,Real Time,Overtime,Calls
+/mob/proc/Life,0.5,1.25,3.0,0.1,400
+/proc/helper,1.5,1.5,1.5,0,1000
+/datum/controller/Fire,0.25,0.25,0.25,0,10
+";
+
+#[test]
+fn parse_profile() {
+    let entries = parse_csv(PROFILE).unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0], ProfileEntry {
+        name: "/mob/proc/Life".to_owned(),
+        self_cpu: 0.5,
+        total_cpu: 1.25,
+        real_time: 3.0,
+        overtime: 0.1,
+        calls: 400,
+    });
+    assert_eq!(entries[0].split_name(), Some(("/mob", "Life")));
+    assert_eq!(entries[1].split_name(), Some(("", "helper")));
+    assert_eq!(entries[2].split_name(), Some(("/datum/controller", "Fire")));
+
+    assert!(parse_csv("Proc Name,Calls\n/proc/x,many").is_err());
+}
+
+#[test]
+#[cfg(feature = "profile")]
+fn parse_json_profile() {
+    let entries = parse(r#"[
+        {"name": "/mob/proc/Life", "self": 0.5, "total": 1.25, "real": 3.0, "over": 0.1, "calls": 400},
+        {"name": "/proc/helper", "calls": 1000}
+    ]"#).unwrap();
+    assert_eq!(entries, vec![
+        ProfileEntry {
+            name: "/mob/proc/Life".to_owned(),
+            self_cpu: 0.5,
+            total_cpu: 1.25,
+            real_time: 3.0,
+            overtime: 0.1,
+            calls: 400,
+        },
+        ProfileEntry {
+            name: "/proc/helper".to_owned(),
+            calls: 1000,
+            .. Default::default()
+        },
+    ]);
+
+    assert_eq!(parse(PROFILE).unwrap().len(), 3);
+    assert!(parse_json("{}").is_err());
+}
+
+#[test]
+fn join_hot_procs() {
+    let code = r##"
+/mob/proc/Life()
+    sleep(1)
+    spawn(5)
+        sleep(10)
+
+/proc/helper()
+    spawn(5)
+        sleep(10)
+    return 1
+"##;
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+
+    let entries = parse_csv(PROFILE).unwrap();
+    let hot = hot_procs(&tree, &entries);
+    let names: Vec<_> = hot.iter().map(|each| each.entry.name.as_str()).collect();
+    assert_eq!(names, vec!["/proc/helper", "/mob/proc/Life", "/datum/controller/Fire"]);
+
+    assert!(hot[0].proc.is_some());
+    assert!(!hot[0].is_suspicious());
+    assert_eq!(hot[1].sleeps, vec!["sleep"]);
+    assert!(hot[1].is_suspicious());
+    assert!(hot[2].proc.is_none());
+}
@@ -0,0 +1,43 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+
+#[test]
+fn bad_entries_are_skipped() {
+    let code = r##"
+/obj/first
+    var/x = 1
+
+/obj/broken
+    var/y = 1 +
+    var/z = 2
+
+/obj/second
+    var/w = 3
+    name = )
+    var/v = 4
+    proc/after()
+
+/obj/second/child
+/mob/last
+"##.trim();
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let tree = Parser::new(&context, IndentProcessor::new(&context, lexer)).parse_object_tree();
+
+    let errors = context.errors().len();
+    assert_eq!(errors, 2, "{:?}", context.errors());
+
+    for path in &["/obj/first", "/obj/broken", "/obj/second", "/obj/second/child", "/mob/last"] {
+        assert!(tree.find(path).is_some(), "missing {}", path);
+    }
+    // declarations after the bad line are still in the tree
+    let broken = tree.find("/obj/broken").unwrap();
+    assert!(broken.get_declaration("z").is_some());
+    let second = tree.find("/obj/second").unwrap();
+    assert!(second.get().vars.contains_key("w"));
+    assert!(second.get_declaration("v").is_some());
+    assert!(second.get_proc("after").is_some());
+}
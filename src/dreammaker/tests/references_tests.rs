@@ -0,0 +1,106 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::references::ReferenceIndex;
+
+fn parse(context: &dm::Context, code: &str) -> dm::objtree::ObjectTree {
+    let lexer = Lexer::new(context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = dm::parser::Parser::new(context, IndentProcessor::new(context, lexer));
+    parser.enable_lazy_procs();
+    parser.parse_object_tree()
+}
+
+#[test]
+fn references_and_overrides() {
+    let context = dm::Context::default();
+    let tree = parse(&context, r#"
+/obj/proc/use(mob/user)
+    return
+
+/obj/item/use(mob/user)
+    ..()
+
+/obj/item/tool/use(mob/user)
+    return
+
+/mob/var/obj/held
+/mob/var/item_type = /obj/item
+
+/mob/proc/act()
+    held.use(src)
+    use()
+    addtimer(CALLBACK(src, .proc/act), 10)
+    var/obj/item/I = new /obj/item/tool
+    I.use(src)
+    held = new item_type
+"#);
+    context.assert_success();
+    let index = ReferenceIndex::build(&context, &tree);
+
+    let uses: Vec<_> = index.proc_references("use").iter().map(|each| each.from.as_str()).collect();
+    assert_eq!(uses, vec!["/mob/proc/act"]);
+    let acts: Vec<_> = index.proc_references("act").iter().map(|each| each.from.as_str()).collect();
+    assert_eq!(acts, vec!["/mob/proc/act"]);
+    assert!(index.proc_references("missing").is_empty());
+
+    let items: Vec<_> = index.type_references("/obj/item").iter().map(|each| each.from.as_str()).collect();
+    assert_eq!(items, vec!["/mob/var/item_type"]);
+    assert_eq!(index.type_references("/obj/item/tool").len(), 1);
+
+    let mut subtypes: Vec<_> = index.subtypes(&tree, "/obj").iter().map(|ty| ty.get().path.clone()).collect();
+    subtypes.sort();
+    assert_eq!(subtypes, vec!["/obj/item", "/obj/item/tool"]);
+    let overrides: Vec<_> = index.overrides(&tree, "/obj", "use").iter().map(|ty| ty.get().path.clone()).collect();
+    assert_eq!(overrides.len(), 2);
+    assert!(index.overrides(&tree, "/obj/item/tool", "use").is_empty());
+
+    // re-indexing a file records its references once
+    let mut updated = index.clone();
+    updated.update_file(&context, &tree, Default::default());
+    assert_eq!(updated.proc_references("use"), index.proc_references("use"));
+    assert_eq!(updated.type_references("/obj/item").len(), 1);
+}
+
+#[test]
+fn dependents_and_file_checks() {
+    use std::path::Path;
+
+    let context = dm::Context::default();
+    let a = context.register_file(Path::new("a.dm"));
+    let b = context.register_file(Path::new("b.dm"));
+    let c = context.register_file(Path::new("c.dm"));
+    let sources = [(a, r#"
+/obj/item/proc/use(mob/user)
+    var/const/x = 1
+    x = 2
+"#), (b, r#"
+/obj/item/tool
+
+/mob/proc/act(obj/item/I)
+    I.use(src)
+    var/const/y = 1
+    y = 2
+"#), (c, r#"
+/turf/proc/unrelated()
+    return
+"#)];
+    let tree = {
+        let lexers = sources.iter().flat_map(|&(file, code)| {
+            Lexer::new(&context, file, code.trim_left().bytes().map(Ok)).collect::<Vec<_>>()
+        });
+        let mut parser = dm::parser::Parser::new(&context, IndentProcessor::new(&context, lexers));
+        parser.enable_lazy_procs();
+        parser.parse_object_tree()
+    };
+    // lazily collected bodies are not checked along with the tree
+    context.assert_success();
+
+    let index = ReferenceIndex::build(&context, &tree);
+    assert_eq!(index.dependents(&tree, a), vec![b]);
+    assert!(index.dependents(&tree, c).is_empty());
+
+    dm::analysis::check_file(&context, &tree, a);
+    let errors: Vec<_> = context.errors().iter().map(|e| (e.location().file, e.description().to_owned())).collect();
+    assert_eq!(errors, vec![(a, "/obj/item/proc/use: cannot assign to const var 'x'".to_owned())]);
+}
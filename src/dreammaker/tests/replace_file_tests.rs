@@ -0,0 +1,78 @@
+extern crate dreammaker as dm;
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use dm::files::MemoryFiles;
+use dm::indents::IndentProcessor;
+use dm::objtree::ObjectTree;
+use dm::parser::Parser;
+use dm::preprocessor::Preprocessor;
+
+const MOB: &str = "/mob/player\n\tvar/health = 100\n\tproc/hit()\n\t\thealth--\n";
+
+fn context() -> dm::Context {
+    let mut files = MemoryFiles::default();
+    files.insert("game.dme", "#define MAX_HEALTH 100\n#include \"code/mob.dm\"\n");
+    files.insert("code/mob.dm", MOB);
+    let mut context = dm::Context::default();
+    context.set_file_provider(files);
+    context
+}
+
+fn parse(context: &dm::Context) -> (Preprocessor, ObjectTree) {
+    let mut pp = Preprocessor::new(context, PathBuf::from("game.dme")).unwrap();
+    let tree = {
+        let mut parser = Parser::new(context, IndentProcessor::new(context, &mut pp));
+        parser.enable_lazy_procs();
+        parser.parse_object_tree()
+    };
+    pp.finalize();
+    (pp, tree)
+}
+
+/// Parse new contents for `code/mob.dm` and try to replace it in the tree.
+fn replace(context: &dm::Context, pp: &Preprocessor, tree: &mut ObjectTree, code: &'static str) -> bool {
+    let file = context.get_file(Path::new("code/mob.dm")).unwrap();
+    let mut branch = pp.branch_at_file(file, context);
+    branch.push_file(PathBuf::from("code/mob.dm"), Cursor::new(code));
+    let fragment = {
+        let mut parser = Parser::new(context, IndentProcessor::new(context, &mut branch));
+        parser.enable_lazy_procs();
+        parser.parse_fragment()
+    };
+    pp.matches_end_of_file(file, &branch) && tree.replace_file(file, fragment)
+}
+
+#[test]
+fn changed_bodies() {
+    let context = context();
+    let (pp, mut tree) = parse(&context);
+    context.assert_success();
+
+    assert!(replace(&context, &pp, &mut tree, "\n/mob/player\n\tvar/health = 100\n\tproc/hit()\n\t\thealth -= 2\n\t\treturn\n"));
+    context.assert_success();
+    let player = tree.find("/mob/player").unwrap();
+    let hit = player.get_proc("hit").unwrap();
+    assert_eq!(hit.location.line, 4);
+    assert_eq!(hit.body(&context).unwrap().len(), 2);
+    assert_eq!(player.get().vars["health"].value.location.line, 3);
+}
+
+#[test]
+fn changed_outline() {
+    let context = context();
+    let (pp, mut tree) = parse(&context);
+
+    // a new value, var, proc or type needs the whole environment
+    assert!(!replace(&context, &pp, &mut tree, "/mob/player\n\tvar/health = 50\n\tproc/hit()\n\t\thealth--\n"));
+    assert!(!replace(&context, &pp, &mut tree, "/mob/player\n\tvar/health = 100\n\tvar/armor\n\tproc/hit()\n\t\thealth--\n"));
+    assert!(!replace(&context, &pp, &mut tree, "/mob/player\n\tvar/health = 100\n\tproc/hit(amount)\n\t\thealth--\n"));
+    assert!(!replace(&context, &pp, &mut tree, "/mob/player\n\tvar/health = 100\n\tproc/hit()\n\t\thealth--\n/mob/monster\n"));
+    // as do new macros
+    assert!(!replace(&context, &pp, &mut tree, "#define ARMOR 5\n/mob/player\n\tvar/health = 100\n\tproc/hit()\n\t\thealth--\n"));
+
+    let player = tree.find("/mob/player").unwrap();
+    assert_eq!(player.get().vars["health"].value.expression.as_ref().unwrap().to_string(), "100");
+    assert!(player.get().vars.get("armor").is_none());
+}
@@ -0,0 +1,197 @@
+extern crate dreammaker as dm;
+
+use std::io::Cursor;
+
+use dm::lexer::Lexer;
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::objtree::{ObjectTree, Code};
+use dm::ast::{Block, Spanned, Statement, Term};
+use dm::visit::{VisitorMut, walk_term_mut};
+use dm::rewrite::*;
+
+fn parse(code: &str) -> ObjectTree {
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    parser.parse_object_tree()
+}
+
+#[test]
+fn format_round_trip() {
+    let body = "\
+var/x = -(a + b) * 2
+for(var/i = 1 to 10 step 2)
+\tx += i
+for(var/obj/O in world)
+\tdel(O)
+switch(a)
+\tif(1, 2 to 3)
+\t\tworld << \"low [a]\"
+\telse
+\t\treturn
+while(x && a && b)
+\tx--
+";
+    let mut code = "/proc/test(a, b)\n".to_owned();
+    for line in body.lines() {
+        code.push('\t');
+        code.push_str(line);
+        code.push('\n');
+    }
+    let tree = parse(&code);
+    let block = match tree.root().get_proc("test").unwrap().code {
+        Code::Present(ref block) => block.clone(),
+        ref other => panic!("{:?}", other),
+    };
+    assert_eq!(format_block(&block, "\t", 0), body);
+}
+
+struct Rename;
+
+impl VisitorMut for Rename {
+    fn visit_term(&mut self, term: &mut Term) {
+        if let Term::Call(ref mut name, _) = *term {
+            if name == "old_proc" {
+                *name = "new_proc".to_owned();
+            }
+        }
+        walk_term_mut(self, term);
+    }
+}
+
+fn rewrite<V: VisitorMut>(code: &str, visitor: &mut V) -> (usize, String) {
+    let context = dm::Context::default();
+    let mut preprocessor = Preprocessor::from_defines(&context, Default::default());
+    let file = preprocessor.push_file("test.dm".into(), Cursor::new(code.as_bytes().to_vec()));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, preprocessor));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+
+    let fixes = rewrite_procs(&tree, file, code, visitor);
+    let fixes: Vec<&dm::Fix> = fixes.iter().collect();
+    let (output, applied) = dm::apply_fixes(code.as_bytes(), &fixes);
+    assert_eq!(applied, fixes.len());
+    (fixes.len(), String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn rewrite_calls() {
+    let code = "\
+/proc/old_proc()
+
+/mob
+\tproc/test(a)
+\t\t// keep this
+\t\tif(a)
+\t\t\told_proc(a+1) // and this
+
+\t\treturn old_proc()
+
+\tproc/keep()
+\t\treturn 1
+";
+    let tree = parse(code);
+    let fixes = rewrite_procs(&tree, Default::default(), code, &mut Rename);
+    assert_eq!(fixes.len(), 2);
+    assert!(fixes.iter().all(|fix| fix.description == "rewrite /mob/proc/test"));
+
+    assert_eq!(rewrite(code, &mut Rename).1, "\
+/proc/old_proc()
+
+/mob
+\tproc/test(a)
+\t\t// keep this
+\t\tif(a)
+\t\t\tnew_proc(a+1) // and this
+
+\t\treturn new_proc()
+
+\tproc/keep()
+\t\treturn 1
+");
+}
+
+#[test]
+fn rewrite_braced_bodies() {
+    let code = "\
+/proc/test(a) { if(a) { old_proc(a) } else { return old_proc( ) } }
+/mob
+    proc/test()
+        spawn(1)
+            old_proc()
+";
+    assert_eq!(rewrite(code, &mut Rename), (3, "\
+/proc/test(a) { if(a) { new_proc(a) } else { return new_proc( ) } }
+/mob
+    proc/test()
+        spawn(1)
+            new_proc()
+".to_owned()));
+}
+
+struct AddArgument;
+
+impl VisitorMut for AddArgument {
+    fn visit_term(&mut self, term: &mut Term) {
+        if let Term::Call(ref name, ref mut args) = *term {
+            if name == "old_proc" {
+                args.push(Term::Null.into());
+            }
+        }
+        walk_term_mut(self, term);
+    }
+}
+
+#[test]
+fn rewrite_arguments() {
+    let code = "\
+/proc/test(a)
+\t#define TWICE(x) (x * 2)
+\tvar/b = TWICE(a) // unchanged
+\told_proc(TWICE(a))
+\told_proc()
+";
+    assert_eq!(rewrite(code, &mut AddArgument).1, "\
+/proc/test(a)
+\t#define TWICE(x) (x * 2)
+\tvar/b = TWICE(a) // unchanged
+\told_proc(a * 2, null)
+\told_proc(null)
+");
+}
+
+struct AddReturn;
+
+impl VisitorMut for AddReturn {
+    fn visit_block(&mut self, block: &mut Block) {
+        let location = block[0].location;
+        block.push(Spanned::new(location, Statement::Return(None)));
+    }
+}
+
+#[test]
+fn rewrite_new_statements() {
+    let code = "\
+/proc/test(a)
+\tif(a)
+\t\tx()
+\ty() // keep this
+
+/proc/braced() { x(); y() }
+/proc/last()
+\tx()";
+    assert_eq!(rewrite(code, &mut AddReturn).1, "\
+/proc/test(a)
+\tif(a)
+\t\tx()
+\ty() // keep this
+\treturn
+
+/proc/braced() { x(); y(); return }
+/proc/last()
+\tx()
+\treturn");
+}
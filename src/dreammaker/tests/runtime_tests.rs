@@ -0,0 +1,83 @@
+extern crate dreammaker as dm;
+
+use std::path::Path;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::runtime::*;
+
+const LOG: &str = "
+World opened
+[12:00:01] runtime error: Cannot read null.name
+proc name: Attack (/mob/proc/Attack)
+  source file: mob.dm,5
+  usr: Guy (/mob/living)
+  src: Guy (/mob/living)
+  src.loc: the floor (1,1,1) (/turf)
+  call stack:
+Guy (/mob/living): Attack(null)
+Guy (/mob/living): Attack(null)
+the floor (1,1,1) (/turf): Entered(Guy (/mob/living), null)
+helper(1)
+...
+world: New()
+World rebooting
+runtime error: list index out of bounds
+proc name: Broken (/datum/proc/Broken)
+  call stack:
+/datum (/datum): Broken()
+";
+
+#[test]
+fn parse_runtimes() {
+    let errors = parse_log(LOG);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].message, "Cannot read null.name");
+    assert_eq!(errors[0].proc_path, Some("/mob/proc/Attack".to_owned()));
+    assert_eq!(errors[0].source, Some(("mob.dm".to_owned(), 5)));
+
+    let frames: Vec<_> = errors[0].frames.iter().map(|frame| (
+        frame.src_type.as_ref().map(|s| s.as_str()),
+        frame.proc_type.as_ref().map(|s| s.as_str()),
+        frame.proc_name.as_str(),
+    )).collect();
+    assert_eq!(frames, vec![
+        (Some("/mob/living"), Some("/mob"), "Attack"),
+        (Some("/mob/living"), None, "Attack"),
+        (Some("/turf"), None, "Entered"),
+        (None, None, "helper"),
+        (Some("/world"), None, "New"),
+    ]);
+
+    assert_eq!(errors[1].source, None);
+    assert_eq!(errors[1].frames.len(), 1);
+}
+
+#[test]
+fn resolve_runtimes() {
+    let code = r##"
+/mob/proc/Attack(target)
+    world.log << target
+
+/mob/living/Attack(target)
+    ..()
+
+/proc/helper(x)
+    return x
+"##;
+    let context = dm::Context::default();
+    let file = context.register_file(Path::new("mob.dm"));
+    let lexer = Lexer::new(&context, file, code.trim().bytes().map(Ok));
+    let tree = Parser::new(&context, IndentProcessor::new(&context, lexer)).parse_object_tree();
+
+    let errors = parse_log(LOG);
+    let frames = resolve(&context, &tree, &errors[0]);
+    let types: Vec<_> = frames[..4].iter().map(|each| each.ty.map(|ty| ty.get().path.as_str())).collect();
+    assert_eq!(types, vec![Some("/mob"), Some("/mob/living"), Some("/atom"), Some("")]);
+
+    // the innermost frame is at the reported line, the rest at definitions
+    let lines: Vec<_> = frames[..4].iter().map(|each| each.location.map(|loc| loc.line)).collect();
+    assert_eq!(lines, vec![Some(5), Some(4), None, Some(7)]);
+    assert!(frames[0].proc.is_some());
+}
@@ -0,0 +1,69 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::search::Pattern;
+
+const CODE: &str = r##"
+/proc/addtimer(callback, wait)
+
+/mob
+    var/health = 100
+    proc/hurt(amount)
+        health -= amount
+        addtimer("heal", 20)
+        addtimer("heal", amount)
+    proc/heal()
+        src.health = 100
+
+/obj/proc/hurt()
+    var/health = 5
+    health = 0
+    addtimer("hurt", 5, "extra")
+"##;
+
+fn search(pattern: &str) -> Vec<String> {
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), CODE.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    let pattern = Pattern::parse(pattern).unwrap();
+    pattern.search(&context, &tree).iter()
+        .map(|each| format!("{}/{}: {}", each.ty.get().path, each.proc, each.text))
+        .collect()
+}
+
+#[test]
+fn calls() {
+    assert_eq!(search(r#"call("addtimer", _, CONST)"#), vec![
+        r#"/mob/hurt: addtimer("heal", 20)"#,
+    ]);
+    assert_eq!(search(r#"call("addtimer", _, ..)"#).len(), 3);
+    assert_eq!(search(r#"call("addtimer", _, 5, "extra")"#).len(), 1);
+    assert_eq!(search(r#"call(_, _, _, _)"#).len(), 1);
+}
+
+#[test]
+fn assignments() {
+    assert_eq!(search(r#"assign("health") in /mob"#), vec![
+        "/mob/hurt: health -= amount",
+        "/mob/heal: src.health = 100",
+    ]);
+    assert_eq!(search(r#"assign("health", 0)"#), vec![
+        "/obj/hurt: health = 0",
+    ]);
+    assert!(search(r#"assign("health") in /nonexistent"#).is_empty());
+}
+
+#[test]
+fn syntax_errors() {
+    assert!(Pattern::parse("call(").is_err());
+    assert!(Pattern::parse("frobnicate()").is_err());
+    assert!(Pattern::parse(r#"call("foo") in mob"#).is_err());
+    assert!(Pattern::parse(r#"call("foo" trailing"#).is_err());
+    let err = Pattern::parse(r#"call("foo", ?)"#).unwrap_err();
+    assert_eq!(err.location().column, 13);
+    assert_eq!(Pattern::parse(r#"assign("x") in /mob/"#).unwrap().scope(), Some("/mob"));
+}
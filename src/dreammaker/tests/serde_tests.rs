@@ -0,0 +1,37 @@
+#![cfg(feature = "serde")]
+
+extern crate dreammaker as dm;
+extern crate serde_json;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::objtree::ObjectTree;
+
+#[test]
+fn objtree_json_round_trip() {
+    let code = r##"
+/obj/foo
+    var/health = 10
+    var/list/things = list("a" = 1, /obj)
+    proc/hit(amount = 5)
+        health -= amount
+
+/obj/foo/bar
+    parent_type = /datum
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    context.assert_success();
+
+    let json = serde_json::to_string(&tree).unwrap();
+    let loaded: ObjectTree = serde_json::from_str(&json).unwrap();
+
+    let foo = loaded.find("/obj/foo").unwrap();
+    assert_eq!(foo.get_value("health").unwrap().constant, Some(dm::constants::Constant::Int(10)));
+    assert_eq!(foo.get_proc("hit").unwrap().parameters[0].name, "amount");
+    let bar = loaded.find("/obj/foo/bar").unwrap();
+    assert_eq!(bar.parent_type().unwrap().path, "/datum");
+    assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+}
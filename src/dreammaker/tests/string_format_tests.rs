@@ -0,0 +1,143 @@
+extern crate dreammaker as dm;
+
+use std::io::Cursor;
+
+use dm::ast::Term;
+use dm::lexer::Lexer;
+use dm::preprocessor::{Preprocessor, DefineMap};
+use dm::strings::{StringFormat, FormatPiece, StringTable, MacroTarget};
+
+fn text(s: &str) -> FormatPiece {
+    FormatPiece::Text(s.to_owned())
+}
+
+fn mac(s: &str) -> FormatPiece {
+    FormatPiece::Macro(s.to_owned())
+}
+
+#[test]
+fn escapes_and_macros() {
+    let format = StringFormat::parse(&["\\The cat says \\\"hi\\\"\\n\\ttab"]);
+    assert_eq!(format.pieces, vec![
+        mac("The"),
+        text(" cat says "),
+        FormatPiece::Escape('"'),
+        text("hi"),
+        FormatPiece::Escape('"'),
+        FormatPiece::Escape('n'),
+        FormatPiece::Escape('t'),
+        text("tab"),
+    ]);
+    assert_eq!(format.unknown().count(), 0);
+
+    // the longest macro wins
+    let format = StringFormat::parse(&["\\himself\\hisx\\..."]);
+    assert_eq!(format.macros().collect::<Vec<_>>(), vec!["himself", "his", "..."]);
+}
+
+#[test]
+fn embedded_expressions() {
+    let format = StringFormat::parse(&["\\ref", " and \\icon", ""]);
+    assert_eq!(format.pieces, vec![
+        mac("ref"),
+        FormatPiece::Embed(0),
+        text(" and "),
+        mac("icon"),
+        FormatPiece::Embed(1),
+    ]);
+}
+
+#[test]
+fn unknown_escapes() {
+    let format = StringFormat::parse(&["\\q\\z"]);
+    assert_eq!(format.unknown().cloned().collect::<Vec<_>>(), vec![
+        FormatPiece::Escape('q'),
+        FormatPiece::Escape('z'),
+    ]);
+}
+
+#[test]
+fn round_trip() {
+    for segments in &[
+        vec!["plain"],
+        vec!["\\the ", "\\s \\improper\\red\\\\ \\[x\\] end\\"],
+        vec!["", "", "\\Roman"],
+    ] {
+        let format = StringFormat::parse(segments);
+        assert_eq!(format.segments(), *segments);
+    }
+}
+
+#[test]
+fn macro_targets() {
+    let format = StringFormat::parse(&["\\improper \\the ", " and \\his hat, \\a thing"]);
+    let uses = format.macro_uses();
+    assert_eq!(uses.iter().map(|each| (each.name, each.target)).collect::<Vec<_>>(), vec![
+        ("improper", MacroTarget::Whole),
+        ("the", MacroTarget::Embed(0)),
+        ("his", MacroTarget::Embed(0)),
+        ("a", MacroTarget::Ignored),
+    ]);
+    assert_eq!(uses[3].ignored_reason().unwrap(), "\\a is not followed by an embedded expression and is ignored");
+
+    let format = StringFormat::parse(&["\\his ", " \\proper"]);
+    let reasons: Vec<_> = format.macro_uses().iter().filter_map(|each| each.ignored_reason()).collect();
+    assert_eq!(reasons, vec![
+        "\\his has no embedded expression before it and is ignored",
+        "\\proper is ignored except at the start of a string",
+    ]);
+}
+
+#[test]
+fn terms() {
+    let term = Term::InterpString("\\a ".to_owned(), vec![(None, "!".to_owned(), Default::default())]);
+    let format = term.string_format().unwrap();
+    assert_eq!(format.pieces, vec![mac("a"), text(" "), FormatPiece::Embed(0), text("!")]);
+    assert!(Term::Null.string_format().is_none());
+}
+
+#[test]
+fn string_table() {
+    let context = Default::default();
+    let code = r#"
+var/a = "Hello there"
+var/b = "Hello there"
+var/c = "hello  there!"
+var/d = "unique [a]"
+var/e = "Hello there"
+var/f = ""
+var/g = ""
+"#;
+    let table = StringTable::from_tokens(Lexer::new(&context, Default::default(), code.bytes().map(Ok)));
+    assert_eq!(table.len(), 3);
+    assert_eq!(table.total(), 6);
+    assert_eq!(table.locations("Hello there").len(), 3);
+    assert_eq!(table.locations("hello  there!")[0].line, 4);
+
+    let duplicates = table.duplicates();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].text, "Hello there");
+    assert_eq!(duplicates[0].savings(), 26);
+
+    assert_eq!(table.near_duplicates(), vec![vec!["Hello there", "hello  there!"]]);
+}
+
+#[test]
+fn string_table_macros() {
+    let context = Default::default();
+    let code = r#"
+#define GREETING "Hello there"
+#define GREET(who) "Hello there", who
+var/a = GREETING
+var/b = GREETING
+var/c = list(GREET("you"), GREET("you"))
+var/d = "Hello there"
+"#;
+    let mut preprocessor = Preprocessor::from_defines(&context, DefineMap::default());
+    preprocessor.push_file("test.dm".into(), Cursor::new(code.trim().to_owned().into_bytes()));
+    let table = StringTable::from_preprocessor(&mut preprocessor);
+
+    // each macro's string counts once, at its #define
+    let lines: Vec<_> = table.locations("Hello there").iter().map(|loc| loc.line).collect();
+    assert_eq!(lines, vec![1, 2, 6]);
+}
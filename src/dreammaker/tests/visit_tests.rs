@@ -0,0 +1,84 @@
+extern crate dreammaker as dm;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::objtree::Code;
+use dm::ast::*;
+use dm::visit::*;
+
+fn parse_proc(code: &str, name: &str) -> Block {
+    let context = dm::Context::default();
+    let lexer = Lexer::new(&context, Default::default(), code.trim().bytes().map(Ok));
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, lexer));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    match tree.root().get_proc(name).unwrap().code {
+        Code::Present(ref block) => block.clone(),
+        ref other => panic!("{:?}", other),
+    }
+}
+
+const CODE: &str = r##"
+/proc/test(a, b)
+    var/x = foo(a)
+    if(x)
+        for(var/i in 1 to 10)
+            bar(i, b[foo(i)])
+    spawn(1)
+        foo(x ? a : b)
+    switch(a)
+        if(1 to 2)
+            return "[foo(b)]"
+"##;
+
+#[derive(Default)]
+struct CallNames {
+    calls: Vec<String>,
+    statements: usize,
+}
+
+impl<'ast> Visitor<'ast> for CallNames {
+    fn visit_statement(&mut self, statement: &'ast Statement) {
+        self.statements += 1;
+        walk_statement(self, statement);
+    }
+
+    fn visit_term(&mut self, term: &'ast Term) {
+        if let Term::Call(ref name, _) = *term {
+            self.calls.push(name.clone());
+        }
+        walk_term(self, term);
+    }
+}
+
+#[test]
+fn visit_in_order() {
+    let block = parse_proc(CODE, "test");
+    let mut visitor = CallNames::default();
+    visitor.visit_block(&block);
+    assert_eq!(visitor.calls, vec!["foo", "bar", "foo", "foo", "foo"]);
+    assert_eq!(visitor.statements, 8);
+}
+
+struct Rename;
+
+impl VisitorMut for Rename {
+    fn visit_term(&mut self, term: &mut Term) {
+        if let Term::Call(ref mut name, _) = *term {
+            if name == "foo" {
+                *name = "baz".to_owned();
+            }
+        }
+        walk_term_mut(self, term);
+    }
+}
+
+#[test]
+fn visit_mut() {
+    let mut block = parse_proc(CODE, "test");
+    Rename.visit_block(&mut block);
+    let mut visitor = CallNames::default();
+    visitor.visit_block(&block);
+    assert_eq!(visitor.calls, vec!["baz", "bar", "baz", "baz", "baz"]);
+}
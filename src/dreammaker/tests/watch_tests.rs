@@ -0,0 +1,27 @@
+#![cfg(feature = "notify")]
+extern crate dreammaker as dm;
+
+use std::fs;
+use std::time::Duration;
+
+#[test]
+fn reports_changed_files() {
+    let root = std::env::temp_dir().join("spacemandmm_watch_tests");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("a.dm"), "/datum/a\n").unwrap();
+    fs::write(root.join("b.dm"), "/datum/b\n").unwrap();
+
+    let context = dm::Context::default();
+    let a = context.register_file(&root.join("a.dm"));
+    context.register_file(&root.join("b.dm"));
+
+    let watch = dm::watch::Watch::new(&context, Duration::from_millis(100)).unwrap();
+    fs::write(root.join("a.dm"), "/datum/a/changed\n").unwrap();
+    fs::write(root.join("unrelated.txt"), "").unwrap();
+
+    let dirty = watch.wait().unwrap();
+    assert_eq!(dirty.into_iter().collect::<Vec<_>>(), vec![a]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,449 @@
+//! Traversal of proc bodies.
+//!
+//! A `Visitor` is called on each node of the AST in source order. Each of its
+//! methods defaults to calling the matching `walk_*` function, which visits
+//! the children of that node; an implementation which overrides a method can
+//! call the `walk_*` function itself to continue into the children, or not
+//! to skip them. `VisitorMut` and the `walk_*_mut` functions are the same,
+//! but over mutable references.
+use super::ast::*;
+
+/// A traversal over a proc body.
+pub trait Visitor<'ast> {
+    fn visit_block(&mut self, block: &'ast [Spanned<Statement>]) {
+        walk_block(self, block)
+    }
+
+    fn visit_statement(&mut self, statement: &'ast Statement) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_case(&mut self, case: &'ast Case) {
+        walk_case(self, case)
+    }
+
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        walk_expression(self, expr)
+    }
+
+    fn visit_term(&mut self, term: &'ast Term) {
+        walk_term(self, term)
+    }
+
+    fn visit_follow(&mut self, follow: &'ast Follow) {
+        walk_follow(self, follow)
+    }
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, block: &'ast [Spanned<Statement>]) {
+    for statement in block.iter() {
+        visitor.visit_statement(&statement.elem);
+    }
+}
+
+pub fn walk_statement<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, statement: &'ast Statement) {
+    match *statement {
+        Statement::Expr(ref expr) |
+        Statement::Throw(ref expr) |
+        Statement::Setting(_, _, ref expr) => visitor.visit_expression(expr),
+        Statement::Return(ref expr) => if let Some(ref expr) = *expr {
+            visitor.visit_expression(expr);
+        },
+        Statement::While(ref cond, ref block) => {
+            visitor.visit_expression(cond);
+            visitor.visit_block(block);
+        }
+        Statement::DoWhile(ref block, ref cond) => {
+            visitor.visit_block(block);
+            visitor.visit_expression(cond);
+        }
+        Statement::If(ref arms, ref else_arm) => {
+            for &(ref cond, ref block) in arms.iter() {
+                visitor.visit_expression(cond);
+                visitor.visit_block(block);
+            }
+            if let Some(ref block) = *else_arm {
+                visitor.visit_block(block);
+            }
+        }
+        Statement::ForLoop { ref init, ref test, ref inc, ref block } => {
+            if let Some(ref init) = *init {
+                visitor.visit_statement(init);
+            }
+            if let Some(ref test) = *test {
+                visitor.visit_expression(test);
+            }
+            if let Some(ref inc) = *inc {
+                visitor.visit_statement(inc);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::ForList { ref in_list, ref block, .. } => {
+            if let Some(ref in_list) = *in_list {
+                visitor.visit_expression(in_list);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::ForRange { ref start, ref end, ref step, ref block, .. } => {
+            visitor.visit_expression(start);
+            visitor.visit_expression(end);
+            if let Some(ref step) = *step {
+                visitor.visit_expression(step);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::Var(ref var) => if let Some(ref value) = var.value {
+            visitor.visit_expression(value);
+        },
+        Statement::Vars(ref vars) => for var in vars.iter() {
+            if let Some(ref value) = var.value {
+                visitor.visit_expression(value);
+            }
+        },
+        Statement::Spawn(ref delay, ref block) => {
+            if let Some(ref delay) = *delay {
+                visitor.visit_expression(delay);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::Switch(ref expr, ref cases, ref default) => {
+            visitor.visit_expression(expr);
+            for &(ref case, ref block) in cases.iter() {
+                for each in case.iter() {
+                    visitor.visit_case(each);
+                }
+                visitor.visit_block(block);
+            }
+            if let Some(ref block) = *default {
+                visitor.visit_block(block);
+            }
+        }
+        Statement::TryCatch { ref try_block, ref catch_block, .. } => {
+            visitor.visit_block(try_block);
+            visitor.visit_block(catch_block);
+        }
+        Statement::Label(_, ref block) => visitor.visit_block(block),
+        Statement::Continue(_) |
+        Statement::Break(_) |
+        Statement::Goto(_) => {}
+    }
+}
+
+pub fn walk_case<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, case: &'ast Case) {
+    match *case {
+        Case::Exact(ref expr) => visitor.visit_expression(expr),
+        Case::Range(ref start, ref end) => {
+            visitor.visit_expression(start);
+            visitor.visit_expression(end);
+        }
+    }
+}
+
+pub fn walk_expression<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, expr: &'ast Expression) {
+    match *expr {
+        Expression::Base { ref term, ref follow, .. } => {
+            visitor.visit_term(term);
+            for each in follow.iter() {
+                visitor.visit_follow(each);
+            }
+        }
+        Expression::BinaryOp { ref lhs, ref rhs, .. } |
+        Expression::AssignOp { ref lhs, ref rhs, .. } => {
+            visitor.visit_expression(lhs);
+            visitor.visit_expression(rhs);
+        }
+        Expression::TernaryOp { ref cond, ref if_, ref else_ } => {
+            visitor.visit_expression(cond);
+            visitor.visit_expression(if_);
+            visitor.visit_expression(else_);
+        }
+    }
+}
+
+pub fn walk_term<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, term: &'ast Term) {
+    match *term {
+        Term::New { ref args, ref type_ } => {
+            if let NewType::Prefab(ref prefab) = *type_ {
+                for value in prefab.vars.values() {
+                    visitor.visit_expression(value);
+                }
+            }
+            if let Some(ref args) = *args {
+                for arg in args.iter() {
+                    visitor.visit_expression(arg);
+                }
+            }
+        }
+        Term::List(ref args) |
+        Term::Call(_, ref args) |
+        Term::ParentCall(ref args) |
+        Term::SelfCall(ref args) => for arg in args.iter() {
+            visitor.visit_expression(arg);
+        },
+        Term::Input { ref args, ref in_list, .. } |
+        Term::Locate { ref args, ref in_list } => {
+            for arg in args.iter() {
+                visitor.visit_expression(arg);
+            }
+            if let Some(ref in_list) = *in_list {
+                visitor.visit_expression(in_list);
+            }
+        }
+        Term::Pick(ref args) => for &(ref weight, ref value) in args.iter() {
+            if let Some(ref weight) = *weight {
+                visitor.visit_expression(weight);
+            }
+            visitor.visit_expression(value);
+        },
+        Term::Prefab(ref prefab) => for value in prefab.vars.values() {
+            visitor.visit_expression(value);
+        },
+        Term::Expr(ref expr) => visitor.visit_expression(expr),
+        Term::DynamicCall(ref first, ref second) |
+        Term::ExternalCall(ref first, ref second) => for arg in first.iter().chain(second.iter()) {
+            visitor.visit_expression(arg);
+        },
+        Term::InterpString(_, ref parts) => for &(ref expr, _, _) in parts.iter() {
+            if let Some(ref expr) = *expr {
+                visitor.visit_expression(expr);
+            }
+        },
+        Term::Ident(_) |
+        Term::Null |
+        Term::String(_) |
+        Term::Resource(_) |
+        Term::Int(_) |
+        Term::Float(_) => {}
+    }
+}
+
+pub fn walk_follow<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, follow: &'ast Follow) {
+    match *follow {
+        Follow::Index(ref expr) |
+        Follow::SafeIndex(ref expr) => visitor.visit_expression(expr),
+        Follow::Field(..) => {}
+        Follow::Call(_, _, ref args) => for arg in args.iter() {
+            visitor.visit_expression(arg);
+        },
+    }
+}
+
+/// A traversal over a proc body which may modify it.
+pub trait VisitorMut {
+    fn visit_block(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_statement(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement)
+    }
+
+    fn visit_case(&mut self, case: &mut Case) {
+        walk_case_mut(self, case)
+    }
+
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr)
+    }
+
+    fn visit_term(&mut self, term: &mut Term) {
+        walk_term_mut(self, term)
+    }
+
+    fn visit_follow(&mut self, follow: &mut Follow) {
+        walk_follow_mut(self, follow)
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut Block) {
+    for statement in block.iter_mut() {
+        visitor.visit_statement(&mut statement.elem);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut Statement) {
+    match *statement {
+        Statement::Expr(ref mut expr) |
+        Statement::Throw(ref mut expr) |
+        Statement::Setting(_, _, ref mut expr) => visitor.visit_expression(expr),
+        Statement::Return(ref mut expr) => if let Some(ref mut expr) = *expr {
+            visitor.visit_expression(expr);
+        },
+        Statement::While(ref mut cond, ref mut block) => {
+            visitor.visit_expression(cond);
+            visitor.visit_block(block);
+        }
+        Statement::DoWhile(ref mut block, ref mut cond) => {
+            visitor.visit_block(block);
+            visitor.visit_expression(cond);
+        }
+        Statement::If(ref mut arms, ref mut else_arm) => {
+            for &mut (ref mut cond, ref mut block) in arms.iter_mut() {
+                visitor.visit_expression(cond);
+                visitor.visit_block(block);
+            }
+            if let Some(ref mut block) = *else_arm {
+                visitor.visit_block(block);
+            }
+        }
+        Statement::ForLoop { ref mut init, ref mut test, ref mut inc, ref mut block } => {
+            if let Some(ref mut init) = *init {
+                visitor.visit_statement(init);
+            }
+            if let Some(ref mut test) = *test {
+                visitor.visit_expression(test);
+            }
+            if let Some(ref mut inc) = *inc {
+                visitor.visit_statement(inc);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::ForList { ref mut in_list, ref mut block, .. } => {
+            if let Some(ref mut in_list) = *in_list {
+                visitor.visit_expression(in_list);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::ForRange { ref mut start, ref mut end, ref mut step, ref mut block, .. } => {
+            visitor.visit_expression(start);
+            visitor.visit_expression(end);
+            if let Some(ref mut step) = *step {
+                visitor.visit_expression(step);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::Var(ref mut var) => if let Some(ref mut value) = var.value {
+            visitor.visit_expression(value);
+        },
+        Statement::Vars(ref mut vars) => for var in vars.iter_mut() {
+            if let Some(ref mut value) = var.value {
+                visitor.visit_expression(value);
+            }
+        },
+        Statement::Spawn(ref mut delay, ref mut block) => {
+            if let Some(ref mut delay) = *delay {
+                visitor.visit_expression(delay);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::Switch(ref mut expr, ref mut cases, ref mut default) => {
+            visitor.visit_expression(expr);
+            for &mut (ref mut case, ref mut block) in cases.iter_mut() {
+                for each in case.iter_mut() {
+                    visitor.visit_case(each);
+                }
+                visitor.visit_block(block);
+            }
+            if let Some(ref mut block) = *default {
+                visitor.visit_block(block);
+            }
+        }
+        Statement::TryCatch { ref mut try_block, ref mut catch_block, .. } => {
+            visitor.visit_block(try_block);
+            visitor.visit_block(catch_block);
+        }
+        Statement::Label(_, ref mut block) => visitor.visit_block(block),
+        Statement::Continue(_) |
+        Statement::Break(_) |
+        Statement::Goto(_) => {}
+    }
+}
+
+pub fn walk_case_mut<V: VisitorMut + ?Sized>(visitor: &mut V, case: &mut Case) {
+    match *case {
+        Case::Exact(ref mut expr) => visitor.visit_expression(expr),
+        Case::Range(ref mut start, ref mut end) => {
+            visitor.visit_expression(start);
+            visitor.visit_expression(end);
+        }
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expression) {
+    match *expr {
+        Expression::Base { ref mut term, ref mut follow, .. } => {
+            visitor.visit_term(term);
+            for each in follow.iter_mut() {
+                visitor.visit_follow(each);
+            }
+        }
+        Expression::BinaryOp { ref mut lhs, ref mut rhs, .. } |
+        Expression::AssignOp { ref mut lhs, ref mut rhs, .. } => {
+            visitor.visit_expression(lhs);
+            visitor.visit_expression(rhs);
+        }
+        Expression::TernaryOp { ref mut cond, ref mut if_, ref mut else_ } => {
+            visitor.visit_expression(cond);
+            visitor.visit_expression(if_);
+            visitor.visit_expression(else_);
+        }
+    }
+}
+
+pub fn walk_term_mut<V: VisitorMut + ?Sized>(visitor: &mut V, term: &mut Term) {
+    match *term {
+        Term::New { ref mut args, ref mut type_ } => {
+            if let NewType::Prefab(ref mut prefab) = *type_ {
+                for (_, value) in prefab.vars.iter_mut() {
+                    visitor.visit_expression(value);
+                }
+            }
+            if let Some(ref mut args) = *args {
+                for arg in args.iter_mut() {
+                    visitor.visit_expression(arg);
+                }
+            }
+        }
+        Term::List(ref mut args) |
+        Term::Call(_, ref mut args) |
+        Term::ParentCall(ref mut args) |
+        Term::SelfCall(ref mut args) => for arg in args.iter_mut() {
+            visitor.visit_expression(arg);
+        },
+        Term::Input { ref mut args, ref mut in_list, .. } |
+        Term::Locate { ref mut args, ref mut in_list } => {
+            for arg in args.iter_mut() {
+                visitor.visit_expression(arg);
+            }
+            if let Some(ref mut in_list) = *in_list {
+                visitor.visit_expression(in_list);
+            }
+        }
+        Term::Pick(ref mut args) => for &mut (ref mut weight, ref mut value) in args.iter_mut() {
+            if let Some(ref mut weight) = *weight {
+                visitor.visit_expression(weight);
+            }
+            visitor.visit_expression(value);
+        },
+        Term::Prefab(ref mut prefab) => for (_, value) in prefab.vars.iter_mut() {
+            visitor.visit_expression(value);
+        },
+        Term::Expr(ref mut expr) => visitor.visit_expression(expr),
+        Term::DynamicCall(ref mut first, ref mut second) |
+        Term::ExternalCall(ref mut first, ref mut second) => for arg in first.iter_mut().chain(second.iter_mut()) {
+            visitor.visit_expression(arg);
+        },
+        Term::InterpString(_, ref mut parts) => for &mut (ref mut expr, _, _) in parts.iter_mut() {
+            if let Some(ref mut expr) = *expr {
+                visitor.visit_expression(expr);
+            }
+        },
+        Term::Ident(_) |
+        Term::Null |
+        Term::String(_) |
+        Term::Resource(_) |
+        Term::Int(_) |
+        Term::Float(_) => {}
+    }
+}
+
+pub fn walk_follow_mut<V: VisitorMut + ?Sized>(visitor: &mut V, follow: &mut Follow) {
+    match *follow {
+        Follow::Index(ref mut expr) |
+        Follow::SafeIndex(ref mut expr) => visitor.visit_expression(expr),
+        Follow::Field(..) => {}
+        Follow::Call(_, _, ref mut args) => for arg in args.iter_mut() {
+            visitor.visit_expression(arg);
+        },
+    }
+}
@@ -0,0 +1,80 @@
+//! JavaScript bindings, for linting DM entirely in the browser.
+//!
+//! Build for the web with:
+//!
+//! ```text
+//! cargo rustc -p dreammaker --release --target wasm32-unknown-unknown --features wasm -- --crate-type cdylib
+//! wasm-bindgen --target web target/wasm32-unknown-unknown/release/dreammaker.wasm --out-dir pkg
+//! ```
+//!
+//! Source files are passed in from JavaScript, as the browser offers no
+//! filesystem. Structured results are returned as JSON strings.
+use std::collections::HashMap;
+use std::path::Path;
+
+use wasm_bindgen::prelude::*;
+
+use files::MemoryFiles;
+use objtree::ObjectTree;
+use Context;
+
+/// A parsed environment.
+#[wasm_bindgen]
+pub struct Environment {
+    context: Context,
+    tree: ObjectTree,
+}
+
+#[wasm_bindgen]
+impl Environment {
+    /// Parse an environment starting at the `.dme` at the path `dme`.
+    ///
+    /// `files` is a JSON object mapping the path of each file to its
+    /// contents. Throws if it is malformed or `dme` is not among the files.
+    #[wasm_bindgen(constructor)]
+    pub fn new(dme: &str, files: &str) -> Result<Environment, JsValue> {
+        let files: HashMap<String, String> = ::serde_json::from_str(files)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let mut provider = MemoryFiles::default();
+        for (path, contents) in files {
+            provider.insert(path, contents);
+        }
+
+        let mut context = Context::default();
+        context.set_file_provider(provider);
+        let tree = context.parse_environment(Path::new(dme))
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(Environment { context, tree })
+    }
+
+    /// The diagnostics produced while parsing, as a JSON array of objects
+    /// with `file`, `line`, `column`, `end_line`, `end_column`, `severity`,
+    /// `message`, and `notes`, each with a `file`, `line`, `column`, and
+    /// `message`.
+    pub fn errors(&self) -> String {
+        self.context.errors_json()
+    }
+
+    /// The paths of all types in the environment, as a JSON array.
+    pub fn types(&self) -> String {
+        let types: Vec<&str> = self.tree.types.keys()
+            .map(|path| path.as_str())
+            .filter(|path| !path.is_empty())
+            .collect();
+        json!(types).to_string()
+    }
+}
+
+/// Check a single file of DM code, returning its diagnostics as `errors`
+/// does.
+#[wasm_bindgen]
+pub fn lint(code: &str) -> String {
+    let mut files = MemoryFiles::default();
+    files.insert("code.dme", code);
+
+    let mut context = Context::default();
+    context.set_file_provider(files);
+    // the file was just provided, so it will be found
+    let _ = context.parse_environment(Path::new("code.dme"));
+    context.errors_json()
+}
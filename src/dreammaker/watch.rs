@@ -0,0 +1,110 @@
+//! Watching the files of an environment for changes.
+//!
+//! Requires the `notify` feature. The directories containing each file
+//! registered with a `Context` are watched, rather than the files
+//! themselves, so that editors which save by replacing a file are noticed.
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::{self, Watcher, RecommendedWatcher, RecursiveMode, RawEvent};
+
+use super::{Context, FileId};
+
+/// A watch over the files loaded by a context.
+pub struct Watch {
+    _watcher: RecommendedWatcher,
+    events: Receiver<RawEvent>,
+    files: HashMap<PathBuf, FileId>,
+    delay: Duration,
+}
+
+impl Watch {
+    /// Begin watching every file registered with the context, including the
+    /// `.dme` and each file it includes.
+    ///
+    /// Changes are reported once no further changes have occurred for
+    /// `delay`.
+    pub fn new(context: &Context, delay: Duration) -> notify::Result<Watch> {
+        let (tx, events) = channel();
+        let mut watcher = notify::raw_watcher(tx)?;
+
+        let mut files = HashMap::new();
+        let mut dirs = BTreeSet::new();
+        for path in context.file_list() {
+            let file = match context.get_file(&path) {
+                Some(file) => file,
+                None => continue,
+            };
+            // files which were never on disk cannot change
+            if let Some(normal) = normalize(&path) {
+                if let Some(parent) = normal.parent() {
+                    dirs.insert(parent.to_owned());
+                }
+                files.insert(normal, file);
+            }
+        }
+        for dir in dirs.iter() {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+
+        Ok(Watch {
+            _watcher: watcher,
+            events,
+            files,
+            delay,
+        })
+    }
+
+    /// Block until at least one watched file changes, then until changes
+    /// settle, and return the files which changed.
+    ///
+    /// Returns `None` if the watch has stopped.
+    pub fn wait(&self) -> Option<BTreeSet<FileId>> {
+        let mut dirty = BTreeSet::new();
+        while dirty.is_empty() {
+            let event = self.events.recv().ok()?;
+            self.handle(event, &mut dirty);
+        }
+        loop {
+            match self.events.recv_timeout(self.delay) {
+                Ok(event) => self.handle(event, &mut dirty),
+                Err(RecvTimeoutError::Timeout) => return Some(dirty),
+                Err(RecvTimeoutError::Disconnected) => return Some(dirty),
+            }
+        }
+    }
+
+    /// Call `callback` with each set of changed files, until it returns
+    /// `false` or the watch stops.
+    pub fn run<F: FnMut(&BTreeSet<FileId>) -> bool>(&self, mut callback: F) {
+        while let Some(dirty) = self.wait() {
+            if !callback(&dirty) {
+                break;
+            }
+        }
+    }
+
+    fn handle(&self, event: RawEvent, dirty: &mut BTreeSet<FileId>) {
+        if let Some(path) = event.path.as_ref().and_then(|p| normalize(p)) {
+            if let Some(&file) = self.files.get(&path) {
+                dirty.insert(file);
+            }
+        }
+    }
+}
+
+/// Resolve a path to its canonical form, even if the file itself has been
+/// removed, as happens partway through some editors' saves.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    if let Ok(canonical) = path.canonicalize() {
+        return Some(canonical);
+    }
+    let parent = match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => Path::new("."),
+        Some(parent) => parent,
+        None => return None,
+    };
+    Some(parent.canonicalize().ok()?.join(path.file_name()?))
+}
@@ -222,7 +222,7 @@ impl EditorScene {
             } else if path.extension() == Some("dmm".as_ref()) {
                 // determine the corresponding DME
                 if !loading_env {
-                    if let Some(env) = detect_environment(&path) {
+                    if let Some(env) = path.parent().and_then(dm::detect_environment) {
                         self.load_environment(env);
                         loading_env = true;
                     }
@@ -232,10 +232,13 @@ impl EditorScene {
             }
         }
 
-        // Open most recent `.dme` by default.
+        // Open most recent `.dme` by default, or one near the working
+        // directory if there is none.
         if !loading_env {
             if let Some(env_path) = self.config.recent.first().cloned() {
                 self.load_environment(env_path);
+            } else if let Some(env_path) = dm::detect_environment_default() {
+                self.load_environment(env_path);
             }
         }
     }
@@ -1505,28 +1508,6 @@ fn file_name(path: &Path) -> Cow<str> {
     path.file_name().map_or("".into(), |s| s.to_string_lossy())
 }
 
-fn detect_environment(path: &Path) -> Option<PathBuf> {
-    let mut current = path.parent();
-    while let Some(dir) = current {
-        let read_dir = match std::fs::read_dir(dir) {
-            Ok(r) => r,
-            Err(_) => return None,
-        };
-        for entry in read_dir {
-            let entry = match entry {
-                Ok(e) => e,
-                Err(_) => return None,
-            };
-            let path = entry.path();
-            if path.extension() == Some("dme".as_ref()) {
-                return Some(path);
-            }
-        }
-        current = dir.parent();
-    }
-    None
-}
-
 fn prepare_tool_icon(
     renderer: &mut ImRenderer,
     environment: Option<&Environment>,
@@ -12,11 +12,11 @@ use dm::objtree::ObjectTree;
 use dm::constants::Constant;
 use dmm_tools::dmm::Prefab;
 use dmm_tools::minimap::{self, GetVar};
+use dmm_tools::coords::{PixelOffset, TILE_SIZE};
 
 use dmi::*;
 use map_repr::AtomMap;
 
-const TILE_SIZE: u32 = 32;
 
 gfx_defines! {
     #[derive(Default)]
@@ -287,12 +287,7 @@ impl RenderPop {
             color[3] as f32 / 255.0,
         ];
 
-        let pixel_x = fab.get_var("pixel_x", objtree).to_int().unwrap_or(0);
-        let pixel_y = fab.get_var("pixel_y", objtree).to_int().unwrap_or(0);
-        let pixel_w = fab.get_var("pixel_w", objtree).to_int().unwrap_or(0);
-        let pixel_z = fab.get_var("pixel_z", objtree).to_int().unwrap_or(0);
-        let step_x = fab.get_var("step_x", objtree).to_int().unwrap_or(0);
-        let step_y = fab.get_var("step_y", objtree).to_int().unwrap_or(0);
+        let offset = PixelOffset::of(objtree, fab);
 
         Some(RenderPop {
             category: category_of(&fab.path) as u32,
@@ -300,9 +295,9 @@ impl RenderPop {
             uv,
             color,
             size: [width, height],
-            ofs_x: pixel_x + pixel_w + step_x,
-            ofs_y: pixel_y + pixel_z + step_y,
-            plane: minimap::plane_of(objtree, fab),
+            ofs_x: offset.x,
+            ofs_y: offset.y,
+            plane: offset.plane,
             layer: minimap::layer_of(objtree, fab),
         })
     }
@@ -0,0 +1,67 @@
+//! Editor settings, read from `SpacemanDMM.toml` beside the environment
+//! along with the lint and builtin settings in `dm::config`.
+//!
+//! ```toml
+//! [format]
+//! indent = "spaces"
+//! indent_width = 4
+//!
+//! [inlay_hints]
+//! enable = true
+//! ```
+use std::path::Path;
+
+use dm::config::FILE_NAME;
+use dm::formatting::{FormatOptions, IndentStyle};
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub format: Format,
+    pub inlay_hints: InlayHints,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Format {
+    /// Either "tabs" or "spaces". If unset, the editor's setting is used.
+    pub indent: Option<String>,
+    /// The number of spaces in one indent. If unset, the editor's tab size
+    /// is used.
+    pub indent_width: Option<usize>,
+    /// Whether to keep trailing whitespace.
+    pub keep_trailing_whitespace: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct InlayHints {
+    /// Whether to show inferred types and omitted arguments. Off by
+    /// default, as large procs can have a great many.
+    pub enable: bool,
+}
+
+impl Config {
+    /// Read the settings in the given directory, if there are any.
+    pub fn load(dir: &Path) -> Config {
+        dm::config::load_file(dir)
+    }
+
+    /// Combine these settings with the editor's formatting options.
+    pub fn format_options(&self, editor: &::langserver::FormattingOptions) -> FormatOptions {
+        let width = self.format.indent_width.unwrap_or(editor.tab_size as usize);
+        let spaces = match self.format.indent.as_ref().map(String::as_str) {
+            Some("tabs") => false,
+            Some("spaces") => true,
+            Some(other) => {
+                eprintln!("unknown indent style in {}: {:?}", FILE_NAME, other);
+                editor.insert_spaces
+            }
+            None => editor.insert_spaces,
+        };
+        FormatOptions {
+            indent: if spaces { IndentStyle::Spaces(width) } else { IndentStyle::Tabs },
+            trim_trailing_whitespace: !self.format.keep_trailing_whitespace,
+        }
+    }
+}
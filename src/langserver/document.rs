@@ -22,10 +22,10 @@ pub struct DocumentStore {
 }
 
 impl DocumentStore {
-    pub fn open(&mut self, doc: TextDocumentItem) -> Result<(), jsonrpc::Error> {
+    pub fn open(&mut self, doc: TextDocumentItem) -> Result<PathBuf, jsonrpc::Error> {
         let path = url_to_path(doc.uri)?;
-        match self.map.insert(path, Document::new(doc.version, doc.text)) {
-            None => Ok(()),
+        match self.map.insert(path.clone(), Document::new(doc.version, doc.text)) {
+            None => Ok(path),
             Some(_) => Err(invalid_request("opened a document a second time")),
         }
     }
@@ -1,6 +1,8 @@
 //! Extensions to the language server protocol.
 
+use langserver::{Range, SymbolKind, TextDocumentIdentifier, TextDocumentPositionParams};
 use langserver::notification::*;
+use langserver::request::Request;
 
 pub enum WindowStatus {}
 impl Notification for WindowStatus {
@@ -12,3 +14,65 @@ pub struct WindowStatusParams {
     pub environment: Option<String>,
     pub tasks: Vec<String>,
 }
+
+/// The client command run by clicking a code lens, with the arguments of
+/// VS Code's `editor.action.showReferences`: a document URI, a position, and
+/// a list of locations.
+pub const SHOW_REFERENCES: &str = "dreammaker.showReferences";
+
+pub enum InlayHints {}
+impl Request for InlayHints {
+    const METHOD: &'static str = "experimental/inlayHints";
+    type Params = InlayHintsParams;
+    type Result = Vec<InlayHint>;
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintsParams {
+    pub text_document: TextDocumentIdentifier,
+}
+#[derive(Debug, Serialize)]
+pub struct InlayHint {
+    pub range: Range,
+    pub kind: InlayKind,
+    pub label: String,
+}
+#[derive(Debug, Serialize)]
+pub enum InlayKind {
+    TypeHint,
+    ParameterHint,
+}
+
+pub enum PrepareTypeHierarchy {}
+impl Request for PrepareTypeHierarchy {
+    const METHOD: &'static str = "textDocument/prepareTypeHierarchy";
+    type Params = TextDocumentPositionParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+}
+pub enum TypeHierarchySupertypes {}
+impl Request for TypeHierarchySupertypes {
+    const METHOD: &'static str = "typeHierarchy/supertypes";
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+}
+pub enum TypeHierarchySubtypes {}
+impl Request for TypeHierarchySubtypes {
+    const METHOD: &'static str = "typeHierarchy/subtypes";
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+}
+#[derive(Debug, Deserialize)]
+pub struct TypeHierarchyParams {
+    pub item: TypeHierarchyItem,
+}
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeHierarchyItem {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The full path of the type.
+    pub detail: Option<String>,
+    pub uri: String,
+    pub range: Range,
+    pub selection_range: Range,
+}
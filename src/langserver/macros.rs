@@ -35,8 +35,8 @@ macro_rules! handle_method_call {
 
             $(
                 #[allow(non_snake_case)]
-                fn $what(&mut $self, $p: <langserver::request::$what as langserver::request::Request>::Params)
-                -> Result<<langserver::request::$what as langserver::request::Request>::Result, jsonrpc::Error>
+                fn $what(&mut $self, $p: <$what as langserver::request::Request>::Params)
+                -> Result<<$what as langserver::request::Request>::Result, jsonrpc::Error>
                 {
                     #[allow(unused_imports)]
                     use langserver::*;
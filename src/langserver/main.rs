@@ -23,6 +23,7 @@ mod document;
 mod symbol_search;
 mod extras;
 mod completion;
+mod config;
 
 use std::path::{PathBuf, Path};
 use std::collections::{HashMap, VecDeque};
@@ -34,6 +35,10 @@ use jsonrpc::{Request, Call, Response, Output};
 use langserver::MessageType;
 use petgraph::visit::IntoNodeReferences;
 
+// requests are named unqualified by `handle_method_call!`
+use langserver::request::*;
+use extras::{InlayHints, PrepareTypeHierarchy, TypeHierarchySupertypes, TypeHierarchySubtypes};
+
 use dm::FileId;
 use dm::annotation::{Annotation, AnnotationTree};
 use dm::objtree::TypeRef;
@@ -83,10 +88,16 @@ struct Engine<'a, R: 'a, W: 'a> {
     status: InitStatus,
     parent_pid: u64,
     root: PathBuf,
+    config: config::Config,
+    environment: Option<PathBuf>,
 
     context: &'a dm::Context,
     preprocessor: Option<dm::preprocessor::Preprocessor<'a>>,
     objtree: dm::objtree::ObjectTree,
+    references: Option<dm::references::ReferenceIndex>,
+    /// The diagnostics of the last parse, kept to be published again along
+    /// with the results of checking a file.
+    parse_errors: Vec<dm::DMError>,
 
     annotations: HashMap<PathBuf, (FileId, FileId, Rc<AnnotationTree>)>,
 }
@@ -101,10 +112,14 @@ impl<'a, R: io::RequestRead, W: io::ResponseWrite> Engine<'a, R, W> {
             status: InitStatus::Starting,
             parent_pid: 0,
             root: Default::default(),
+            config: Default::default(),
+            environment: None,
 
             context,
             preprocessor: None,
             objtree: Default::default(),
+            references: None,
+            parse_errors: Vec::new(),
 
             annotations: Default::default(),
         }
@@ -174,6 +189,34 @@ impl<'a, R: io::RequestRead, W: io::ResponseWrite> Engine<'a, R, W> {
         })
     }
 
+    fn convert_error(&self, error: &dm::DMError) -> langserver::Diagnostic {
+        let position = |loc: dm::Location| langserver::Position {
+            line: loc.line.saturating_sub(1) as u64,
+            character: loc.column.saturating_sub(1) as u64,
+        };
+        let span = error.span();
+        let related: Vec<_> = error.notes().iter()
+            .filter(|note| !note.location.is_builtins())
+            .filter_map(|note| Some(langserver::DiagnosticRelatedInformation {
+                location: langserver::Location {
+                    uri: self.file_url(note.location.file).ok()?,
+                    range: langserver::Range::new(position(note.location), position(note.location)),
+                },
+                message: note.description.clone(),
+            }))
+            .collect();
+        langserver::Diagnostic {
+            message: error.description().to_owned(),
+            severity: Some(convert_severity(error.severity())),
+            range: langserver::Range {
+                start: position(span.start),
+                end: position(span.end),
+            },
+            related_information: if related.is_empty() { None } else { Some(related) },
+            .. Default::default()
+        }
+    }
+
     // ------------------------------------------------------------------------
     // Environment tracking
 
@@ -209,7 +252,9 @@ impl<'a, R: io::RequestRead, W: io::ResponseWrite> Engine<'a, R, W> {
             }
         };
 
-        self.objtree = dm::parser::parse(ctx, dm::indents::IndentProcessor::new(ctx, &mut pp));
+        self.objtree = parse_tree(ctx, &mut pp);
+        self.environment = Some(environment);
+        self.references = None;
         pp.finalize();
         self.preprocessor = Some(pp);
         self.issue_notification::<extras::WindowStatus>(Default::default());
@@ -221,25 +266,12 @@ impl<'a, R: io::RequestRead, W: io::ResponseWrite> Engine<'a, R, W> {
         );
 
         // initial diagnostics pump
+        self.parse_errors = self.context.take_errors();
         let mut map: HashMap<_, Vec<_>> = HashMap::new();
-        for error in self.context.errors().iter() {
-            let loc = error.location();
-            let pos = langserver::Position {
-                line: loc.line.saturating_sub(1) as u64,
-                character: loc.column.saturating_sub(1) as u64,
-            };
-            let diag = langserver::Diagnostic {
-                message: error.description().to_owned(),
-                severity: Some(convert_severity(error.severity())),
-                range: langserver::Range {
-                    start: pos,
-                    end: pos,
-                },
-                .. Default::default()
-            };
-            map.entry(self.context.file_path(loc.file))
+        for error in self.parse_errors.iter() {
+            map.entry(self.context.file_path(error.location().file))
                 .or_insert_with(Default::default)
-                .push(diag);
+                .push(self.convert_error(error));
         }
 
         for (path, diagnostics) in map {
@@ -255,6 +287,109 @@ impl<'a, R: io::RequestRead, W: io::ResponseWrite> Engine<'a, R, W> {
         Ok(())
     }
 
+    /// Parse a file again after it is saved, then check only that file and
+    /// the files which depend on it, publishing their diagnostics.
+    ///
+    /// The whole environment is parsed again only if the file's macros,
+    /// vars, procs or types have changed.
+    fn revalidate(&mut self, path: &Path) -> Result<(), jsonrpc::Error> {
+        let environment = match self.environment {
+            Some(ref environment) => environment.clone(),
+            None => return Ok(()),
+        };
+        let stripped = match path.strip_prefix(&self.root) {
+            Ok(stripped) => stripped.to_owned(),
+            Err(_) => return Ok(()),
+        };
+        let file_id = match self.context.get_file(&stripped) {
+            Some(id) => id,
+            None => return Ok(()),  // not part of the environment
+        };
+        let start = std::time::Instant::now();
+
+        // anything left over, such as errors in proc bodies parsed for a
+        // hover, would otherwise pile up in the context
+        let ctx = self.context;
+        ctx.take_errors();
+
+        let contents = self.docs.read(path).map_err(invalid_request)?;
+        let replaced = match self.preprocessor {
+            Some(ref pp) => {
+                let mut branch = pp.branch_at_file(file_id, ctx);
+                branch.push_file(stripped, contents);
+                let fragment = {
+                    let mut parser = dm::parser::Parser::new(ctx, dm::indents::IndentProcessor::new(ctx, &mut branch));
+                    parser.enable_lazy_procs();
+                    parser.parse_fragment()
+                };
+                pp.matches_end_of_file(file_id, &branch) && self.objtree.replace_file(file_id, fragment)
+            }
+            None => false,
+        };
+        if replaced {
+            self.parse_errors.retain(|error| error.location().file != file_id);
+            self.parse_errors.extend(ctx.take_errors());
+        } else {
+            ctx.take_errors();
+            let mut pp = dm::preprocessor::Preprocessor::new(ctx, environment).map_err(invalid_request)?;
+            self.objtree = parse_tree(ctx, &mut pp);
+            pp.finalize();
+            self.preprocessor = Some(pp);
+            self.parse_errors = ctx.take_errors();
+        }
+        self.annotations.clear();
+
+        // other files have not changed, so their references still stand
+        let references = match self.references.take() {
+            Some(mut references) => {
+                references.update_file(ctx, &self.objtree, file_id);
+                references
+            }
+            None => dm::references::ReferenceIndex::build(ctx, &self.objtree),
+        };
+        let mut affected = vec![file_id];
+        affected.extend(references.dependents(&self.objtree, file_id));
+        self.references = Some(references);
+        for &file in affected.iter() {
+            dm::analysis::check_file(ctx, &self.objtree, file);
+        }
+        let checked = ctx.take_errors();
+
+        let mut map: HashMap<FileId, Vec<_>> = affected.iter().map(|&file| (file, Vec::new())).collect();
+        for error in self.parse_errors.iter().chain(checked.iter()) {
+            if let Some(list) = map.get_mut(&error.location().file) {
+                // the type-level checks also ran when the environment was parsed
+                let diagnostic = self.convert_error(error);
+                if !list.contains(&diagnostic) {
+                    list.push(diagnostic);
+                }
+            }
+        }
+        for (file, diagnostics) in map {
+            let uri = self.file_url(file)?;
+            self.issue_notification::<langserver::notification::PublishDiagnostics>(
+                langserver::PublishDiagnosticsParams { uri, diagnostics },
+            );
+        }
+
+        let elapsed = start.elapsed();
+        eprintln!(
+            "revalidated {} files{} in {}.{:03}s",
+            affected.len(),
+            if replaced { "" } else { " after parsing the environment" },
+            elapsed.as_secs(),
+            elapsed.subsec_nanos() / 1_000_000
+        );
+        Ok(())
+    }
+
+    /// Let later parses see the unsaved contents of an open document.
+    fn overlay_document(&self, path: &Path) {
+        if let Ok(contents) = self.docs.get_contents(path) {
+            self.context.set_file_contents(path, contents.into_owned().into_bytes());
+        }
+    }
+
     fn get_annotations(&mut self, path: &Path) -> Result<(FileId, FileId, Rc<AnnotationTree>), jsonrpc::Error> {
         Ok(match self.annotations.entry(path.to_owned()) {
             Entry::Occupied(o) => o.get().clone(),
@@ -274,13 +409,14 @@ impl<'a, R: io::RequestRead, W: io::ResponseWrite> Engine<'a, R, W> {
                 };
                 let contents = self.docs.read(path).map_err(invalid_request)?;
                 let file_id = preprocessor.push_file(stripped.to_owned(), contents);
-                let indent = dm::indents::IndentProcessor::new(&context, preprocessor);
                 let mut annotations = AnnotationTree::default();
                 {
+                    let indent = dm::indents::IndentProcessor::new(&context, &mut preprocessor);
                     let mut parser = dm::parser::Parser::new(&context, indent);
                     parser.annotate_to(&mut annotations);
                     parser.run();
                 }
+                preprocessor.annotate_macro_uses(&mut annotations);
                 v.insert((real_file_id, file_id, Rc::new(annotations))).clone()
             }
         })
@@ -411,6 +547,23 @@ impl<'a, R: io::RequestRead, W: io::ResponseWrite> Engine<'a, R, W> {
         next
     }
 
+    fn type_hierarchy_item(&self, ty: TypeRef) -> Result<extras::TypeHierarchyItem, jsonrpc::Error> {
+        let location = self.convert_location(ty.location, &ty.path, "", "")?;
+        Ok(extras::TypeHierarchyItem {
+            name: ty.name.clone(),
+            kind: langserver::SymbolKind::Class,
+            detail: Some(ty.path.clone()),
+            uri: location.uri.to_string(),
+            range: location.range,
+            selection_range: location.range,
+        })
+    }
+
+    /// Find the type a type hierarchy item describes.
+    fn type_of_item(&self, item: &extras::TypeHierarchyItem) -> Option<TypeRef> {
+        item.detail.as_ref().and_then(|path| self.objtree.find(path))
+    }
+
     // ------------------------------------------------------------------------
     // Driver
 
@@ -486,6 +639,9 @@ handle_method_call! {
                 text_document_sync: Some(TextDocumentSyncCapability::Options(TextDocumentSyncOptions {
                     open_close: Some(true),
                     change: Some(TextDocumentSyncKind::Incremental),
+                    save: Some(SaveOptions {
+                        include_text: Some(false),
+                    }),
                     .. Default::default()
                 })),
                 completion_provider: Some(CompletionOptions {
@@ -495,6 +651,11 @@ handle_method_call! {
                 signature_help_provider: Some(SignatureHelpOptions {
                     trigger_characters: Some(vec!["(".to_owned(), ",".to_owned()]),
                 }),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: None,
+                }),
+                document_formatting_provider: Some(true),
+                document_range_formatting_provider: Some(true),
                 .. Default::default()
             }
         }
@@ -914,12 +1075,7 @@ handle_method_call! {
         let iter = annotations.get_location(location);
         let mut result = None;
 
-        if_annotation! { Annotation::ProcArguments(priors, proc_name, mut idx) in iter; {
-            // take the specific argument we're working on
-            if_annotation! { Annotation::ProcArgument(i) in iter; {
-                idx = *i;
-            }}
-
+        if let Some((priors, proc_name, idx)) = annotations.call_arguments_at(location) {
             let mut next = self.find_scoped_type(&iter, priors);
             while let Some(ty) = next {
                 if let Some(proc) = ty.procs.get(proc_name) {
@@ -929,16 +1085,23 @@ handle_method_call! {
                     let mut label = format!("{}/{}(", ty.path, proc_name);
                     let mut sep = "";
                     for param in proc.value.last().unwrap().parameters.iter() {
-                        params.push(ParameterInformation {
-                            label: param.name.clone(),
-                            documentation: None,
-                        });
+                        let mut param_label = String::new();
                         for each in param.path.iter() {
-                            let _ = write!(label, "{}{}", sep, each);
-                            sep = "/";
+                            let _ = write!(param_label, "{}/", each);
                         }
-                        let _ = write!(label, "{}{}", sep, param.name);
+                        param_label.push_str(&param.name);
+                        if let Some(ref default) = param.default {
+                            match dm::constants::simple_evaluate(param.location, default.clone()) {
+                                Ok(constant) => { let _ = write!(param_label, " = {}", constant); }
+                                Err(_) => param_label.push_str(" = ..."),
+                            }
+                        }
+                        let _ = write!(label, "{}{}", sep, param_label);
                         sep = ", ";
+                        params.push(ParameterInformation {
+                            label: param_label,
+                            documentation: None,
+                        });
                     }
                     let _ = write!(label, ")");
 
@@ -960,10 +1123,218 @@ handle_method_call! {
                     }
                 }
             }
-        }}
+        }
 
         result
     }
+
+    on CodeLensRequest(&mut self, params) {
+        let uri = params.text_document.uri;
+        let path = url_to_path(uri.clone())?;
+        let file_id = match path.strip_prefix(&self.root).ok().and_then(|path| self.context.get_file(path)) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        if self.references.is_none() {
+            self.references = Some(dm::references::ReferenceIndex::build(self.context, &self.objtree));
+        }
+        let references = self.references.as_ref().unwrap();
+
+        let mut lenses = Vec::new();
+        {
+            let mut lens = |location: dm::Location, noun: &str, targets: Vec<dm::Location>| -> Result<(), jsonrpc::Error> {
+                let position = Position {
+                    line: location.line.saturating_sub(1) as u64,
+                    character: location.column.saturating_sub(1) as u64,
+                };
+                let mut locations = Vec::new();
+                for target in targets {
+                    locations.push(serde_json::to_value(self.convert_location(target, "", "", "")?).expect("encode problem"));
+                }
+                lenses.push(CodeLens {
+                    range: Range::new(position, position),
+                    command: Some(Command {
+                        title: format!("{} {}{}", locations.len(), noun, if locations.len() == 1 { "" } else { "s" }),
+                        command: extras::SHOW_REFERENCES.to_owned(),
+                        arguments: Some(vec![
+                            serde_json::to_value(uri.as_str()).expect("encode problem"),
+                            serde_json::to_value(position).expect("encode problem"),
+                            serde_json::Value::Array(locations),
+                        ]),
+                    }),
+                    data: None,
+                });
+                Ok(())
+            };
+
+            for (idx, ty) in self.objtree.graph.node_references() {
+                if idx.index() != 0 && ty.location.file == file_id {
+                    let subtypes = references.subtypes(&self.objtree, &ty.path);
+                    lens(ty.location, "subtype", subtypes.iter().map(|sub| sub.get().location).collect())?;
+                    let uses = references.type_references(&ty.path);
+                    lens(ty.location, "reference", uses.iter().map(|each| each.location).collect())?;
+                }
+                for (name, proc) in ty.procs.iter() {
+                    for value in proc.value.iter().filter(|value| value.location.file == file_id) {
+                        let overrides = references.overrides(&self.objtree, &ty.path, name);
+                        lens(value.location, "override", overrides.iter().filter_map(|sub| {
+                            sub.get().procs.get(name).and_then(|p| p.value.first()).map(|p| p.location)
+                        }).collect())?;
+                        let uses = references.proc_references(name);
+                        lens(value.location, "reference", uses.iter().map(|each| each.location).collect())?;
+                    }
+                }
+            }
+        }
+        Some(lenses)
+    }
+
+    on PrepareTypeHierarchy(&mut self, params) {
+        let path = url_to_path(params.text_document.uri)?;
+        let (_, file_id, annotations) = self.get_annotations(&path)?;
+        let location = dm::Location {
+            file: file_id,
+            line: params.position.line as u32 + 1,
+            column: params.position.character as u16 + 1,
+        };
+        let mut found = None;
+
+        let iter = annotations.get_location(location);
+        match_annotation! { iter;
+        Annotation::TreePath(absolute, parts) => {
+            found = self.objtree.type_by_path(completion::combine_tree_path(&iter, *absolute, parts));
+        },
+        Annotation::TypePath(parts) => {
+            if let Some(completion::TypePathResult { ty, decl: None, proc: None }) = self.follow_type_path(&iter, parts) {
+                found = Some(ty);
+            }
+        },
+        }
+
+        match found {
+            Some(ty) if !ty.is_root() => Some(vec![self.type_hierarchy_item(ty)?]),
+            _ => None,
+        }
+    }
+
+    on TypeHierarchySupertypes(&mut self, params) {
+        let ty = match self.type_of_item(&params.item) {
+            Some(ty) => ty,
+            None => return Ok(None),
+        };
+        let mut result = Vec::new();
+        if let Some(parent) = ignore_root(ty.parent_type()) {
+            result.push(self.type_hierarchy_item(parent)?);
+        }
+        Some(result)
+    }
+
+    on TypeHierarchySubtypes(&mut self, params) {
+        let ty = match self.type_of_item(&params.item) {
+            Some(ty) => ty,
+            None => return Ok(None),
+        };
+        let mut children = Vec::new();
+        self.objtree.root().recurse(&mut |each| {
+            if each.parent_type().map_or(false, |parent| parent.path == ty.path) {
+                children.push(each);
+            }
+        });
+        children.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut result = Vec::new();
+        for child in children {
+            result.push(self.type_hierarchy_item(child)?);
+        }
+        Some(result)
+    }
+
+    on InlayHints(&mut self, params) {
+        if !self.config.inlay_hints.enable {
+            return Ok(Vec::new());
+        }
+        let path = url_to_path(params.text_document.uri)?;
+        let (real_file_id, file_id, annotations) = self.get_annotations(&path)?;
+        let hint = |location: dm::Location, kind: extras::InlayKind, label: String| {
+            let position = Position {
+                line: location.line.saturating_sub(1) as u64,
+                character: location.column.saturating_sub(1) as u64,
+            };
+            extras::InlayHint { range: Range::new(position, position), kind, label }
+        };
+        let mut hints = Vec::new();
+
+        // inferred types of locals, as of the last time the environment
+        // was parsed
+        {
+            let context = self.context;
+            let objtree = &self.objtree;
+            objtree.root().recurse(&mut |ty| {
+                for (name, proc) in ty.get().procs.iter() {
+                    for value in proc.value.iter().filter(|value| value.location.file == real_file_id) {
+                        if let Some(body) = value.body(context) {
+                            for (location, path) in dm::analysis::inferred_local_types(objtree, ty, name, value, &body) {
+                                hints.push(hint(location, extras::InlayKind::TypeHint, format!(": {}", path)));
+                            }
+                        }
+                    }
+                }
+            });
+        }
+
+        // default values of omitted arguments
+        for (span, annotation) in annotations.iter() {
+            let (priors, proc_name, count) = match *annotation {
+                Annotation::ProcArguments(ref priors, ref proc_name, count) if span.start.file == file_id => (priors, proc_name, count),
+                _ => continue,
+            };
+            let iter = annotations.get_location(span.start);
+            let mut next = self.find_scoped_type(&iter, priors);
+            while let Some(ty) = next {
+                if let Some(proc) = ty.procs.get(proc_name) {
+                    let mut label = String::new();
+                    for param in proc.value.last().unwrap().parameters.iter().skip(count) {
+                        if let Some(ref default) = param.default {
+                            let value = match dm::constants::simple_evaluate(param.location, default.clone()) {
+                                Ok(constant) => constant.to_string(),
+                                Err(_) => "...".to_owned(),
+                            };
+                            let sep = if label.is_empty() && count == 0 { "" } else { ", " };
+                            label.push_str(&format!("{}{} = {}", sep, param.name, value));
+                        }
+                    }
+                    if !label.is_empty() {
+                        // the closing parenthesis
+                        let end = span.end.add_columns(1);
+                        hints.push(hint(end, extras::InlayKind::ParameterHint, label));
+                    }
+                    break;
+                }
+                next = ty.parent_type();
+                if next.map_or(false, |n| n.is_root()) && !priors.is_empty() {
+                    break;
+                }
+            }
+        }
+        hints
+    }
+
+    on Formatting(&mut self, params) {
+        let path = url_to_path(params.text_document.uri)?;
+        let options = self.config.format_options(&params.options);
+        let contents = self.docs.get_contents(&path).map_err(invalid_request)?;
+        let fixes = dm::formatting::format_file(FileId::default(), &contents, &options);
+        Some(fixes.iter().map(fix_to_edit).collect::<Vec<_>>())
+    }
+
+    on RangeFormatting(&mut self, params) {
+        let path = url_to_path(params.text_document.uri)?;
+        let options = self.config.format_options(&params.options);
+        let contents = self.docs.get_contents(&path).map_err(invalid_request)?;
+        // LSP lines are zero-based and the range's end is inclusive
+        let lines = params.range.start.line as u32 + 1 .. params.range.end.line as u32 + 2;
+        let fixes = dm::formatting::format_lines(FileId::default(), &contents, &options, lines);
+        Some(fixes.iter().map(fix_to_edit).collect::<Vec<_>>())
+    }
 }
 
 handle_notification! {
@@ -975,9 +1346,19 @@ handle_notification! {
 
     on Initialized(&mut self, _) {
         eprintln!("workspace root: {}", self.root.display());
-        let environment = dm::detect_environment(&self.root, dm::DEFAULT_ENV)
-            .map_err(invalid_request)?;
+        // prefer an environment above the workspace root, then one within it
+        let environment = dm::detect_environment(&self.root)
+            .or_else(|| dm::list_environments(&self.root).into_iter().next());
         if let Some(environment) = environment {
+            // file paths are relative to the directory of the environment
+            if let Some(dir) = environment.parent() {
+                if dir != self.root {
+                    eprintln!("environment root: {}", dir.display());
+                    self.root = dir.to_owned();
+                }
+            }
+            self.config = config::Config::load(&self.root);
+            dm::config::Config::load(&self.root).apply(self.context);
             self.parse_environment(environment)?;
         } else {
             self.show_status("no .dme file");
@@ -987,17 +1368,25 @@ handle_notification! {
     // ------------------------------------------------------------------------
     // document content management
     on DidOpenTextDocument(&mut self, params) {
-        self.docs.open(params.text_document)?;
+        let path = self.docs.open(params.text_document)?;
+        self.overlay_document(&path);
     }
 
     on DidCloseTextDocument(&mut self, params) {
         let path = self.docs.close(params.text_document)?;
         self.annotations.remove(&path);
+        self.context.clear_file_contents(&path);
     }
 
     on DidChangeTextDocument(&mut self, params) {
         let path = self.docs.change(params.text_document, params.content_changes)?;
         self.annotations.remove(&path);
+        self.overlay_document(&path);
+    }
+
+    on DidSaveTextDocument(&mut self, params) {
+        let path = url_to_path(params.text_document.uri)?;
+        self.revalidate(&path)?;
     }
 }
 
@@ -1022,6 +1411,24 @@ fn value_to_params(value: serde_json::Value) -> jsonrpc::Params {
     }
 }
 
+fn parse_tree<'ctx>(context: &'ctx dm::Context, pp: &mut dm::preprocessor::Preprocessor<'ctx>) -> dm::objtree::ObjectTree {
+    let mut parser = dm::parser::Parser::new(context, dm::indents::IndentProcessor::new(context, pp));
+    // bodies are parsed when they are first needed
+    parser.enable_lazy_procs();
+    parser.parse_object_tree()
+}
+
+fn fix_to_edit(fix: &dm::Fix) -> langserver::TextEdit {
+    let position = |loc: dm::Location| langserver::Position {
+        line: loc.line.saturating_sub(1) as u64,
+        character: loc.column.saturating_sub(1) as u64,
+    };
+    langserver::TextEdit {
+        range: langserver::Range::new(position(fix.replacement_range.start), position(fix.replacement_range.end)),
+        new_text: fix.replacement_text.clone(),
+    }
+}
+
 fn invalid_request<S: ToString>(message: S) -> jsonrpc::Error {
     jsonrpc::Error {
         code: jsonrpc::ErrorCode::InvalidRequest,
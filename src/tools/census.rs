@@ -0,0 +1,173 @@
+//! Counting the contents of maps.
+use std::collections::{BTreeMap, HashMap};
+
+use dm::objtree::*;
+use dmm::Map;
+use minimap::GetVar;
+
+/// Counts of what a z-level, or a whole map, contains.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Census {
+    /// The number of tiles counted.
+    pub tiles: usize,
+    /// How many atoms of each exact type were found.
+    pub types: BTreeMap<String, usize>,
+    /// How many tiles each area covers.
+    pub areas: BTreeMap<String, usize>,
+    /// The number of power cable pieces.
+    pub cables: usize,
+    /// The number of separate networks the cable pieces form.
+    pub powernets: usize,
+}
+
+impl Census {
+    /// Count the contents of one z-level of a map.
+    pub fn of_z_level(objtree: &ObjectTree, map: &Map, z: usize) -> Census {
+        let mut census = Census::default();
+        let mut cables = Vec::new();
+
+        let grid = map.z_level(z);
+        for ((y, x), key) in grid.indexed_iter() {
+            census.tiles += 1;
+            let mut area = None;
+            for fab in map.dictionary[key].iter() {
+                *census.types.entry(fab.path.clone()).or_insert(0) += 1;
+                if subpath(&fab.path, "/area/") {
+                    area = Some(&fab.path);
+                } else if subpath(&fab.path, "/obj/structure/cable/") {
+                    if let Some(dirs) = cable_dirs(fab.get_var("icon_state", objtree).as_str()) {
+                        cables.push(Cable { x, y, dirs });
+                    }
+                }
+            }
+            if let Some(area) = area {
+                *census.areas.entry(area.clone()).or_insert(0) += 1;
+            }
+        }
+
+        census.cables = cables.len();
+        census.powernets = count_powernets(&cables);
+        census
+    }
+
+    /// Count the contents of each z-level of a map.
+    pub fn of_map(objtree: &ObjectTree, map: &Map) -> Vec<Census> {
+        (0..map.dim_z()).map(|z| Census::of_z_level(objtree, map, z)).collect()
+    }
+
+    /// Add the counts of another census to this one. Networks are assumed
+    /// not to cross between the two.
+    pub fn merge(&mut self, other: &Census) {
+        self.tiles += other.tiles;
+        for (path, &count) in other.types.iter() {
+            *self.types.entry(path.clone()).or_insert(0) += count;
+        }
+        for (path, &count) in other.areas.iter() {
+            *self.areas.entry(path.clone()).or_insert(0) += count;
+        }
+        self.cables += other.cables;
+        self.powernets += other.powernets;
+    }
+
+    /// Total the type counts so that each type also includes the counts of
+    /// its subtypes, following parent types in the object tree.
+    ///
+    /// Types missing from the object tree are counted only by their own path.
+    pub fn grouped(&self, objtree: &ObjectTree) -> BTreeMap<String, usize> {
+        let mut output = BTreeMap::new();
+        for (path, &count) in self.types.iter() {
+            let mut current = match objtree.find(path) {
+                Some(ty) => ty,
+                None => {
+                    *output.entry(path.clone()).or_insert(0) += count;
+                    continue;
+                }
+            };
+            while !current.is_root() {
+                *output.entry(current.get().path.clone()).or_insert(0) += count;
+                current = match current.parent_type() {
+                    Some(parent) => parent,
+                    None => break,
+                };
+            }
+        }
+        output
+    }
+}
+
+struct Cable {
+    x: usize,
+    y: usize,
+    dirs: [u8; 2],
+}
+
+/// Parse a cable's `icon_state` of the form "d1-d2".
+fn cable_dirs(icon_state: Option<&str>) -> Option<[u8; 2]> {
+    let mut parts = icon_state?.splitn(2, '-');
+    let d1 = parts.next()?.parse().ok()?;
+    let d2 = parts.next()?.parse().ok()?;
+    Some([d1, d2])
+}
+
+/// The grid offset of a direction, where rows count down from the north.
+fn offset(dir: u8) -> (isize, isize) {
+    let mut dx = 0;
+    let mut dy = 0;
+    if dir & 1 != 0 { dy -= 1; }  // NORTH
+    if dir & 2 != 0 { dy += 1; }  // SOUTH
+    if dir & 4 != 0 { dx += 1; }  // EAST
+    if dir & 8 != 0 { dx -= 1; }  // WEST
+    (dx, dy)
+}
+
+fn opposite(dir: u8) -> u8 {
+    ((dir & 5) << 1) | ((dir & 10) >> 1)
+}
+
+/// Count the networks formed by cables, which connect when they share a
+/// direction on the same tile or point toward each other from adjacent
+/// tiles.
+fn count_powernets(cables: &[Cable]) -> usize {
+    let mut by_tile: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (i, cable) in cables.iter().enumerate() {
+        by_tile.entry((cable.x, cable.y)).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut parent: Vec<usize> = (0..cables.len()).collect();
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    for (i, cable) in cables.iter().enumerate() {
+        // pieces on the same tile sharing a direction are joined
+        for &j in by_tile[&(cable.x, cable.y)].iter() {
+            if cable.dirs.iter().any(|d| cables[j].dirs.contains(d)) {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+        // as are pieces pointing toward each other
+        for &dir in cable.dirs.iter().filter(|&&dir| dir != 0) {
+            let (dx, dy) = offset(dir);
+            let x = cable.x as isize + dx;
+            let y = cable.y as isize + dy;
+            if x < 0 || y < 0 {
+                continue;
+            }
+            if let Some(others) = by_tile.get(&(x as usize, y as usize)) {
+                for &j in others.iter() {
+                    if cables[j].dirs.contains(&opposite(dir)) {
+                        let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                        parent[a] = b;
+                    }
+                }
+            }
+        }
+    }
+
+    (0..cables.len()).filter(|&i| find(&mut parent, i) == i).count()
+}
@@ -0,0 +1,154 @@
+//! Coordinates on maps and rendered images.
+//!
+//! Game coordinates are 1-indexed and measured from the bottom-left corner
+//! of the map, as in BYOND. Raw coordinates index `Map::grid` from zero,
+//! starting at the top row. Pixel positions on rendered images are measured
+//! from the image's top-left corner.
+use std::fmt;
+
+use dm::objtree::ObjectTree;
+use minimap::GetVar;
+
+/// The width and height of a tile, in pixels.
+pub const TILE_SIZE: u32 = 32;
+
+/// A tile position on one z-level, in game coordinates.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Coord2 {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A tile position on a map, in game coordinates.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Coord3 {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Coord2 {
+    #[inline]
+    pub fn new(x: i32, y: i32) -> Coord2 {
+        Coord2 { x, y }
+    }
+
+    /// Place this position on the given z-level.
+    #[inline]
+    pub fn z(self, z: i32) -> Coord3 {
+        Coord3 { x: self.x, y: self.y, z }
+    }
+
+    /// Convert from raw `(x, y)` grid coordinates on a map `dim_y` tiles
+    /// tall.
+    pub fn from_raw((x, y): (usize, usize), dim_y: usize) -> Coord2 {
+        Coord2 {
+            x: x as i32 + 1,
+            y: dim_y as i32 - y as i32,
+        }
+    }
+
+    /// Convert to raw `(x, y)` grid coordinates, or `None` if this position
+    /// is outside a map of the given size.
+    pub fn to_raw(self, (dim_x, dim_y): (usize, usize)) -> Option<(usize, usize)> {
+        if self.x < 1 || self.y < 1 || self.x > dim_x as i32 || self.y > dim_y as i32 {
+            return None;
+        }
+        Some(((self.x - 1) as usize, (dim_y as i32 - self.y) as usize))
+    }
+
+    /// The position one tile away in the given direction. Diagonals move
+    /// along both axes; zero stays in place.
+    pub fn step(self, dir: i32) -> Coord2 {
+        use dmi::*;
+        let mut result = self;
+        if dir & NORTH != 0 { result.y += 1; }
+        if dir & SOUTH != 0 { result.y -= 1; }
+        if dir & EAST != 0 { result.x += 1; }
+        if dir & WEST != 0 { result.x -= 1; }
+        result
+    }
+
+    /// The pixel position of this tile's top-left corner on an image whose
+    /// top-left tile is `top_left`.
+    pub fn to_pixel(self, top_left: Coord2) -> (i32, i32) {
+        (
+            (self.x - top_left.x) * TILE_SIZE as i32,
+            (top_left.y - self.y) * TILE_SIZE as i32,
+        )
+    }
+}
+
+impl Coord3 {
+    #[inline]
+    pub fn new(x: i32, y: i32, z: i32) -> Coord3 {
+        Coord3 { x, y, z }
+    }
+
+    /// This position without its z-level.
+    #[inline]
+    pub fn xy(self) -> Coord2 {
+        Coord2 { x: self.x, y: self.y }
+    }
+
+    /// Convert from raw `(x, y, z)` grid coordinates on a map `dim_y` tiles
+    /// tall.
+    pub fn from_raw((x, y, z): (usize, usize, usize), dim_y: usize) -> Coord3 {
+        Coord2::from_raw((x, y), dim_y).z(z as i32 + 1)
+    }
+
+    /// Convert to raw `(x, y, z)` grid coordinates, or `None` if this
+    /// position is outside a map of the given size.
+    pub fn to_raw(self, (dim_x, dim_y, dim_z): (usize, usize, usize)) -> Option<(usize, usize, usize)> {
+        if self.z < 1 || self.z > dim_z as i32 {
+            return None;
+        }
+        let (x, y) = self.xy().to_raw((dim_x, dim_y))?;
+        Some((x, y, (self.z - 1) as usize))
+    }
+}
+
+impl fmt::Display for Coord2 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+impl fmt::Display for Coord3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+    }
+}
+
+/// Where an atom is drawn relative to its tile.
+///
+/// `x` and `y` are in pixels, with y counting up. `plane` does not move the
+/// icon, but atoms on a higher plane are drawn above those on a lower one
+/// regardless of `layer`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PixelOffset {
+    pub x: i32,
+    pub y: i32,
+    pub plane: i32,
+}
+
+impl PixelOffset {
+    /// Read the offset of an atom, combining `pixel_x`/`pixel_y`,
+    /// `pixel_w`/`pixel_z`, and `step_x`/`step_y` as BYOND does.
+    pub fn of<T: GetVar + ?Sized>(objtree: &ObjectTree, atom: &T) -> PixelOffset {
+        let var = |name: &str| atom.get_var(name, objtree).to_int().unwrap_or(0);
+        PixelOffset {
+            x: var("pixel_x") + var("pixel_w") + var("step_x"),
+            y: var("pixel_y") + var("pixel_z") + var("step_y"),
+            plane: var("plane"),
+        }
+    }
+
+    /// Where to draw an icon `icon_height` pixels tall on an image whose
+    /// top-left tile is `top_left`, so that its bottom-left corner is this
+    /// offset from the bottom-left corner of `tile`.
+    pub fn draw_position(self, tile: Coord2, top_left: Coord2, icon_height: u32) -> (i32, i32) {
+        let (x, y) = tile.to_pixel(top_left);
+        (x + self.x, y + TILE_SIZE as i32 - icon_height as i32 - self.y)
+    }
+}
@@ -3,7 +3,6 @@ use std::path::Path;
 use std::collections::BTreeMap;
 
 use ndarray::Array3;
-use lodepng::{self, RGBA};
 use lodepng::ffi::{State as PngState, ColorType};
 
 pub const NORTH: i32 = 1;
@@ -31,18 +30,11 @@ impl IconFile {
     pub fn from_file(path: &Path) -> io::Result<IconFile> {
         let path = &::dm::fix_case(path);
         let mut decoder = PngState::new();
-        decoder.info_raw.colortype = ColorType::RGBA;
-        decoder.info_raw.set_bitdepth(8);
-        decoder.remember_unknown_chunks(false);
-        let bitmap = match decoder.decode_file(path) {
-            Ok(::lodepng::Image::RGBA(bitmap)) => bitmap,
-            Ok(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "not RGBA")),
-            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
-        };
+        let image = decode_file(&mut decoder, path)?;
 
         let mut metadata = Metadata {
-            width: bitmap.width as u32,
-            height: bitmap.height as u32,
+            width: image.width,
+            height: image.height,
             states: Vec::new(),
             state_names: BTreeMap::new(),
         };
@@ -57,7 +49,7 @@ impl IconFile {
 
         Ok(IconFile {
             metadata: metadata,
-            image: Image::from_rgba(bitmap),
+            image,
         })
     }
 
@@ -67,28 +59,246 @@ impl IconFile {
             None => return None,
         };
         let state = &self.metadata.states[state_index];
+        Some(self.rect_of_index(state.offset + dir_index(state.dirs, dir)))
+    }
 
-        let dir_idx = match (state.dirs, dir) {
-            (Dirs::One, _) => 0,
-            (Dirs::Eight, NORTHWEST) => 7,
-            (Dirs::Eight, NORTHEAST) => 6,
-            (Dirs::Eight, SOUTHWEST) => 5,
-            (Dirs::Eight, SOUTHEAST) => 4,
-            (_, WEST) => 3,
-            (_, EAST) => 2,
-            (_, NORTH) => 1,
-            (_, _) => 0,
+    /// Get the frame sequence of an icon state in the given direction.
+    ///
+    /// Each frame is given as its rectangle within the image and its delay
+    /// in ticks. Rewinding states include their backwards frames.
+    pub fn frames_of(&self, icon_state: &str, dir: i32) -> Option<Vec<(Rect, f32)>> {
+        let state_index = match self.metadata.state_names.get(icon_state) {
+            Some(&i) => i,
+            None => return None,
         };
+        let state = &self.metadata.states[state_index];
 
-        let icon_index = state.offset as u32 + dir_idx;
+        let dir_idx = dir_index(state.dirs, dir);
+        let frame = |i: usize| (
+            self.rect_of_index(state.offset + i * state.dirs.len() + dir_idx),
+            state.frames.delay(i),
+        );
+        let count = state.frames.len();
+        let mut frames: Vec<_> = (0..count).map(&frame).collect();
+        if state.rewind && count > 2 {
+            frames.extend((1..count - 1).rev().map(&frame));
+        }
+        Some(frames)
+    }
+
+    fn rect_of_index(&self, icon_index: usize) -> Rect {
+        let icon_index = icon_index as u32;
         let icon_count = self.image.width / self.metadata.width;
         let (icon_x, icon_y) = (icon_index % icon_count, icon_index / icon_count);
-        Some((
+        (
             icon_x * self.metadata.width,
             icon_y * self.metadata.height,
             self.metadata.width,
             self.metadata.height,
-        ))
+        )
+    }
+
+    /// Write an icon state in the given direction as an animated PNG.
+    ///
+    /// Delays are converted from ticks, and the state's `loop` setting is
+    /// preserved. Single-frame states produce a one-frame animation.
+    pub fn write_apng<W: io::Write>(&self, w: &mut W, icon_state: &str, dir: i32) -> io::Result<()> {
+        let frames = match self.frames_of(icon_state, dir) {
+            Some(frames) => frames,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("no such icon state: {:?}", icon_state))),
+        };
+        let loop_ = self.metadata.states[self.metadata.state_names[icon_state]].loop_;
+        let (width, height) = (self.metadata.width, self.metadata.height);
+
+        w.write_all(PNG_SIGNATURE)?;
+        write_ihdr(w, width, height)?;
+
+        let mut actl = Vec::with_capacity(8);
+        push_u32(&mut actl, frames.len() as u32);
+        push_u32(&mut actl, loop_);
+        write_chunk(w, b"acTL", &actl)?;
+
+        let mut sequence = 0;
+        for (i, &(rect, delay)) in frames.iter().enumerate() {
+            let mut fctl = Vec::with_capacity(26);
+            push_u32(&mut fctl, sequence);
+            push_u32(&mut fctl, width);
+            push_u32(&mut fctl, height);
+            push_u32(&mut fctl, 0);  // x offset
+            push_u32(&mut fctl, 0);  // y offset
+            // ticks are tenths of a second, so express the delay in hundredths
+            let delay = (delay * 10.).round().max(0.).min(65535.) as u16;
+            fctl.extend_from_slice(&[(delay >> 8) as u8, delay as u8, 0, 100]);
+            fctl.extend_from_slice(&[0, 0]);  // no disposal, replace contents
+            write_chunk(w, b"fcTL", &fctl)?;
+            sequence += 1;
+
+            let data = encode_idat(&self.image.crop(rect))?;
+            if i == 0 {
+                write_chunk(w, b"IDAT", &data)?;
+            } else {
+                let mut fdat = Vec::with_capacity(4 + data.len());
+                push_u32(&mut fdat, sequence);
+                fdat.extend_from_slice(&data);
+                write_chunk(w, b"fdAT", &fdat)?;
+                sequence += 1;
+            }
+        }
+
+        write_chunk(w, b"IEND", &[])
+    }
+}
+
+fn dir_index(dirs: Dirs, dir: i32) -> usize {
+    match (dirs, dir) {
+        (Dirs::One, _) => 0,
+        (Dirs::Eight, NORTHWEST) => 7,
+        (Dirs::Eight, NORTHEAST) => 6,
+        (Dirs::Eight, SOUTHWEST) => 5,
+        (Dirs::Eight, SOUTHEAST) => 4,
+        (_, WEST) => 3,
+        (_, EAST) => 2,
+        (_, NORTH) => 1,
+        (_, _) => 0,
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Icon file creation
+
+/// A new icon state, with images given per frame and then per direction.
+pub struct NewState {
+    pub name: String,
+    pub dirs: Dirs,
+    /// Each entry holds one image per direction, in the order south,
+    /// north, east, west, southeast, southwest, northeast, northwest.
+    pub frames: Vec<Vec<Image>>,
+    /// The delay of each frame in ticks, or `None` for one tick each.
+    pub delays: Option<Vec<f32>>,
+    pub loop_: u32,
+    pub rewind: bool,
+    pub movement: bool,
+}
+
+impl NewState {
+    /// A single-direction, single-frame state.
+    pub fn still<S: Into<String>>(name: S, image: Image) -> NewState {
+        NewState {
+            name: name.into(),
+            dirs: Dirs::One,
+            frames: vec![vec![image]],
+            delays: None,
+            loop_: 0,
+            rewind: false,
+            movement: false,
+        }
+    }
+}
+
+/// Assembles icon states into a new `IconFile`.
+pub struct IconBuilder {
+    width: u32,
+    height: u32,
+    states: Vec<NewState>,
+}
+
+impl IconBuilder {
+    pub fn new(width: u32, height: u32) -> IconBuilder {
+        IconBuilder {
+            width,
+            height,
+            states: Vec::new(),
+        }
+    }
+
+    /// Add a state, checking that its images and delays are consistent.
+    pub fn add_state(&mut self, state: NewState) -> io::Result<()> {
+        let invalid = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+
+        if state.name.contains('"') || state.name.contains('\\') {
+            return invalid(format!("unsupported characters in state name {:?}", state.name));
+        }
+        if state.frames.is_empty() {
+            return invalid(format!("state {:?} has no frames", state.name));
+        }
+        if let Some(ref delays) = state.delays {
+            if delays.len() != state.frames.len() {
+                return invalid(format!("state {:?} has {} frames but {} delays", state.name, state.frames.len(), delays.len()));
+            }
+        }
+        for frame in state.frames.iter() {
+            if frame.len() != state.dirs.len() {
+                return invalid(format!("state {:?} has {} dirs but a frame has {} images", state.name, state.dirs.len(), frame.len()));
+            }
+            for image in frame.iter() {
+                if image.width != self.width || image.height != self.height {
+                    return invalid(format!(
+                        "state {:?} has a {}x{} image in a {}x{} icon",
+                        state.name, image.width, image.height, self.width, self.height,
+                    ));
+                }
+            }
+        }
+        self.states.push(state);
+        Ok(())
+    }
+
+    /// Lay out the states into a sprite sheet with matching metadata.
+    pub fn build(self) -> IconFile {
+        let total: usize = self.states.iter().map(|s| s.frames.len() * s.dirs.len()).sum();
+        let per_row = ((total as f64).sqrt().ceil() as u32).max(1);
+        let rows = (total as u32 + per_row - 1) / per_row;
+
+        let mut image = Image::new_rgba(per_row * self.width, rows.max(1) * self.height);
+        let mut metadata = Metadata {
+            width: self.width,
+            height: self.height,
+            states: Vec::new(),
+            state_names: BTreeMap::new(),
+        };
+
+        let mut index = 0;
+        for state in self.states {
+            metadata.state_names.entry(state.name.clone()).or_insert(metadata.states.len());
+            metadata.states.push(State {
+                offset: index as usize,
+                name: state.name,
+                loop_: state.loop_,
+                rewind: state.rewind,
+                movement: state.movement,
+                dirs: state.dirs,
+                frames: match state.delays {
+                    Some(ref delays) if delays.iter().any(|&d| d != 1.) => Frames::Delays(delays.clone()),
+                    _ if state.frames.len() == 1 => Frames::One,
+                    _ => Frames::Count(state.frames.len()),
+                },
+            });
+            for each in state.frames.iter().flat_map(|frame| frame.iter()) {
+                image.blit(each, ((index % per_row) * self.width, (index / per_row) * self.height));
+                index += 1;
+            }
+        }
+
+        IconFile { metadata, image }
+    }
+}
+
+impl IconFile {
+    /// Write this icon as a `.dmi` file, including its metadata.
+    pub fn write<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut text = b"Description\0".to_vec();
+        text.extend_from_slice(self.metadata.to_string().as_bytes());
+
+        w.write_all(PNG_SIGNATURE)?;
+        write_ihdr(w, self.image.width, self.image.height)?;
+        write_chunk(w, b"tEXt", &text)?;
+        write_chunk(w, b"IDAT", &encode_idat(&self.image)?)?;
+        write_chunk(w, b"IEND", &[])
+    }
+
+    pub fn to_file(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(::std::fs::File::create(path)?);
+        self.write(&mut file)
     }
 }
 
@@ -110,22 +320,6 @@ impl Image {
         }
     }
 
-    fn from_rgba(bitmap: lodepng::Bitmap<RGBA>) -> Image {
-        Image {
-            width: bitmap.width as u32,
-            height: bitmap.height as u32,
-            data: Array3::from_shape_fn((bitmap.height, bitmap.width, 4), |(y, x, c)| {
-                let rgba = bitmap.buffer[y * bitmap.width + x];
-                match c {
-                    0 => rgba.r,
-                    1 => rgba.g,
-                    2 => rgba.b,
-                    3 => rgba.a,
-                    _ => unreachable!(),
-                }
-            }),
-        }
-    }
 
     /// Read an `Image` from a file.
     ///
@@ -134,17 +328,8 @@ impl Image {
     pub fn from_file(path: &Path) -> io::Result<Image> {
         let path = &::dm::fix_case(path);
         let mut decoder = PngState::new();
-        decoder.info_raw.colortype = ColorType::RGBA;
-        decoder.info_raw.set_bitdepth(8);
         decoder.read_text_chunks(false);
-        decoder.remember_unknown_chunks(false);
-        let bitmap = match decoder.decode_file(path) {
-            Ok(::lodepng::Image::RGBA(bitmap)) => bitmap,
-            Ok(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "not RGBA")),
-            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
-        };
-
-        Ok(Image::from_rgba(bitmap))
+        decode_file(&mut decoder, path)
     }
 
     #[cfg(feature="png")]
@@ -162,6 +347,40 @@ impl Image {
         Ok(())
     }
 
+    /// Create an image from tightly packed 8-bit RGBA pixel data.
+    ///
+    /// Panics if the buffer is not `4 * width * height` bytes long.
+    pub fn from_rgba_bytes(width: u32, height: u32, data: Vec<u8>) -> Image {
+        Image {
+            width,
+            height,
+            data: Array3::from_shape_vec((height as usize, width as usize, 4), data)
+                .expect("RGBA buffer does not match image size"),
+        }
+    }
+
+    /// Replace the contents of a region of this image with another image.
+    pub fn blit(&mut self, other: &Image, pos: (u32, u32)) {
+        self.data.slice_mut(s![
+            pos.1 as isize..(pos.1 + other.height) as isize,
+            pos.0 as isize..(pos.0 + other.width) as isize,
+            ..
+        ]).assign(&other.data);
+    }
+
+    /// Copy a rectangular region out of this image.
+    pub fn crop(&self, rect: Rect) -> Image {
+        Image {
+            width: rect.2,
+            height: rect.3,
+            data: self.data.slice(s![
+                rect.1 as isize..(rect.1 + rect.3) as isize,
+                rect.0 as isize..(rect.0 + rect.2) as isize,
+                ..
+            ]).to_owned(),
+        }
+    }
+
     pub fn composite(&mut self, other: &Image, pos: (u32, u32), crop: Rect, color: [u8; 4]) {
         use ndarray::Axis;
 
@@ -211,3 +430,94 @@ impl Image {
 fn mul255(x: u8, y: u8) -> u8 {
     (x as u16 * y as u16 / 255) as u8
 }
+
+/// Decode a PNG file to an `Image`.
+///
+/// lodepng is asked for 16-bit output even though icons are 8-bit: when the
+/// requested color mode matches the file's, lodepng compares their palettes
+/// by slicing null pointers.
+fn decode_file(decoder: &mut PngState, path: &Path) -> io::Result<Image> {
+    decoder.info_raw.colortype = ColorType::RGBA;
+    decoder.info_raw.set_bitdepth(16);
+    decoder.remember_unknown_chunks(false);
+    let bitmap = match decoder.decode_file(path) {
+        Ok(::lodepng::Image::RGBA16(bitmap)) => bitmap,
+        Ok(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "not RGBA")),
+        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    };
+
+    Ok(Image {
+        width: bitmap.width as u32,
+        height: bitmap.height as u32,
+        data: Array3::from_shape_fn((bitmap.height, bitmap.width, 4), |(y, x, c)| {
+            let rgba = bitmap.buffer[y * bitmap.width + x];
+            let value = match c {
+                0 => rgba.r,
+                1 => rgba.g,
+                2 => rgba.b,
+                3 => rgba.a,
+                _ => unreachable!(),
+            };
+            (value >> 8) as u8
+        }),
+    })
+}
+
+// ----------------------------------------------------------------------------
+// PNG chunk writing
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+}
+
+fn write_chunk<W: io::Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut header = Vec::with_capacity(8);
+    push_u32(&mut header, data.len() as u32);
+    header.extend_from_slice(kind);
+    w.write_all(&header)?;
+    w.write_all(data)?;
+
+    let mut crc = Vec::with_capacity(4);
+    push_u32(&mut crc, crc32(crc32(!0, kind), data) ^ !0);
+    w.write_all(&crc)
+}
+
+fn write_ihdr<W: io::Write>(w: &mut W, width: u32, height: u32) -> io::Result<()> {
+    let mut ihdr = Vec::with_capacity(13);
+    push_u32(&mut ihdr, width);
+    push_u32(&mut ihdr, height);
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);  // 8-bit RGBA, no interlace
+    write_chunk(w, b"IHDR", &ihdr)
+}
+
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// Compress an image as 8-bit RGBA, returning the contents of its IDAT chunk.
+///
+/// Rows are stored unfiltered. lodepng's encoder is avoided because it
+/// builds slices from its null palette pointer when cloning color modes.
+fn encode_idat(image: &Image) -> io::Result<Vec<u8>> {
+    let stride = image.width as usize * 4;
+    let data = image.data.iter().cloned().collect::<Vec<u8>>();
+    if data.len() != stride * image.height as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+            "{}x{} image has {} bytes of RGBA data", image.width, image.height, data.len())));
+    }
+
+    let mut filtered = Vec::with_capacity(data.len() + image.height as usize);
+    for y in 0..image.height as usize {
+        filtered.push(0);  // filter type: none
+        filtered.extend_from_slice(&data[y * stride..(y + 1) * stride]);
+    }
+    Ok(deflate::deflate_bytes_zlib(&filtered))
+}
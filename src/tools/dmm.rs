@@ -10,6 +10,9 @@ use linked_hash_map::LinkedHashMap;
 use dm::{DMError, Location, HasLocation};
 use dm::lexer::{LocationTracker, from_latin1, from_latin1_borrowed};
 use dm::constants::Constant;
+use dm::objtree::subpath;
+
+use coords::{Coord2, Coord3};
 
 const MAX_KEY_LENGTH: u8 = 3;
 
@@ -75,6 +78,11 @@ impl Map {
         save_tgm(self, File::create(path)?)
     }
 
+    /// Write the map in TGM format to any output, such as a `Vec<u8>`.
+    pub fn to_writer<W: io::Write>(&self, output: W) -> io::Result<()> {
+        save_tgm(self, output)
+    }
+
     pub fn adjust_key_length(&mut self) {
         if self.dictionary.len() > 2704 {
             self.key_length = 3;
@@ -85,6 +93,51 @@ impl Map {
         }
     }
 
+    /// Find the key for the given tile contents, adding it to the dictionary
+    /// with the lowest unused key if it is not yet present.
+    ///
+    /// The key length is increased if necessary.
+    pub fn key_for(&mut self, tile: Vec<Prefab>) -> Key {
+        if let Some((&key, _)) = self.dictionary.iter().find(|&(_, v)| *v == tile) {
+            return key;
+        }
+        self.insert_key(tile)
+    }
+
+    /// Add the given tile contents to the dictionary with the lowest unused
+    /// key, without checking whether they are already present.
+    ///
+    /// The key length is increased if necessary.
+    pub fn insert_key(&mut self, tile: Vec<Prefab>) -> Key {
+        let mut key = Key::default();
+        while self.dictionary.contains_key(&key) {
+            key = key.next();
+        }
+        assert!(key != Key::invalid(), "map has too many unique tiles");
+        self.dictionary.insert(key, tile);
+        while !self.key_fits(key) {
+            self.key_length += 1;
+        }
+        key
+    }
+
+    /// Remove all dictionary entries which are not used by any tile.
+    pub fn remove_unused_keys(&mut self) {
+        let mut used = ::std::collections::BTreeSet::new();
+        for &key in self.grid.iter() {
+            used.insert(key);
+        }
+        self.dictionary = take(&mut self.dictionary)
+            .into_iter()
+            .filter(|&(key, _)| used.contains(&key))
+            .collect();
+    }
+
+    /// Check whether the given key can be formatted at the current key length.
+    pub fn key_fits(&self, key: Key) -> bool {
+        self.key_length >= MAX_KEY_LENGTH || (key.0 as usize) < 52usize.pow(self.key_length as u32)
+    }
+
     #[inline]
     pub fn dim_xyz(&self) -> (usize, usize, usize) {
         let dim = self.grid.dim();
@@ -106,12 +159,198 @@ impl Map {
         FormatKey(self.key_length, key)
     }
 
-    pub fn zero_to_one(&self, (x, y, z): (usize, usize, usize)) -> (usize, usize, usize) {
-        (x + 1, self.grid.dim().1 - y, z + 1)
+    /// Convert raw `(x, y, z)` grid coordinates to game coordinates.
+    pub fn zero_to_one(&self, raw: (usize, usize, usize)) -> Coord3 {
+        Coord3::from_raw(raw, self.grid.dim().1)
+    }
+
+    /// Convert game coordinates to raw `(x, y, z)` grid coordinates, failing
+    /// if they are outside the map.
+    pub fn raw_coord(&self, coord: Coord3) -> Result<(usize, usize, usize), String> {
+        let (dim_x, dim_y, dim_z) = self.dim_xyz();
+        coord.to_raw((dim_x, dim_y, dim_z)).ok_or_else(|| {
+            format!("{} is outside the {}x{}x{} map", coord, dim_x, dim_y, dim_z)
+        })
+    }
+
+    /// Convert game coordinates to raw `(x, y, z)` grid coordinates.
+    ///
+    /// Panics if the coordinates are outside the map.
+    pub fn one_to_zero(&self, coord: Coord3) -> (usize, usize, usize) {
+        match self.raw_coord(coord) {
+            Ok(raw) => raw,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Tile editing
+    //
+    // Coordinates here are 1-indexed and inclusive, as in game. Edits fail
+    // without changing anything if any coordinates are outside the map.
+    // Replaced tiles may leave keys unused, which `remove_unused_keys` cleans
+    // up.
+
+    /// Get the contents of the tile at the given coordinates.
+    ///
+    /// Panics if the coordinates are outside the map.
+    pub fn tile(&self, coord: Coord3) -> &[Prefab] {
+        let (x, y, z) = self.one_to_zero(coord);
+        &self.dictionary[&self.grid[(z, y, x)]]
+    }
+
+    /// Replace the contents of the tile at the given coordinates.
+    pub fn set_tile(&mut self, coord: Coord3, tile: Vec<Prefab>) -> Result<(), String> {
+        let (x, y, z) = self.raw_coord(coord)?;
+        self.grid[(z, y, x)] = self.key_for(tile);
+        Ok(())
+    }
+
+    /// Replace the contents of every tile in a box between two corners.
+    pub fn fill(&mut self, min: Coord3, max: Coord3, tile: Vec<Prefab>) -> Result<(), String> {
+        self.raw_coord(min)?;
+        self.raw_coord(max)?;
+        let key = self.key_for(tile);
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let (x, y, z) = self.one_to_zero(Coord3::new(x, y, z));
+                    self.grid[(z, y, x)] = key;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a prefab to the tile at the given coordinates, as with
+    /// `stamp_tile`.
+    pub fn stamp(&mut self, coord: Coord3, prefab: Prefab) -> Result<(), String> {
+        let (x, y, z) = self.raw_coord(coord)?;
+        let mut tile = self.dictionary[&self.grid[(z, y, x)]].clone();
+        stamp_tile(&mut tile, prefab);
+        self.grid[(z, y, x)] = self.key_for(tile);
+        Ok(())
+    }
+
+    /// Add a prefab to every tile in a box between two corners, as with
+    /// `stamp_tile`.
+    pub fn stamp_rect(&mut self, min: Coord3, max: Coord3, prefab: Prefab) -> Result<(), String> {
+        self.raw_coord(min)?;
+        self.raw_coord(max)?;
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    self.stamp(Coord3::new(x, y, z), prefab.clone())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------------
+    // Z-level and size manipulation
+    //
+    // Z indices here are zero-based. X and Y sizes are in tiles, and tiles
+    // keep their in-game coordinates, measured from the bottom-left corner.
+
+    /// Insert a new z-level filled with the given tile before `index`.
+    pub fn insert_z_level(&mut self, index: usize, tile: Vec<Prefab>) -> Result<(), String> {
+        let (dim_z, dim_y, dim_x) = self.grid.dim();
+        if index > dim_z {
+            return Err(format!("cannot insert z-level {} into a map with {} z-levels", index, dim_z));
+        }
+        let key = self.key_for(tile);
+        let old = take(&mut self.grid);
+        self.grid = Array3::from_shape_fn((dim_z + 1, dim_y, dim_x), |(z, y, x)| {
+            if z < index {
+                old[(z, y, x)]
+            } else if z == index {
+                key
+            } else {
+                old[(z - 1, y, x)]
+            }
+        });
+        Ok(())
+    }
+
+    /// Remove the z-level at `index`, discarding any keys it alone used.
+    ///
+    /// Fails if this would leave the map with no z-levels.
+    pub fn remove_z_level(&mut self, index: usize) -> Result<(), String> {
+        let (dim_z, dim_y, dim_x) = self.grid.dim();
+        if index >= dim_z {
+            return Err(format!("cannot remove z-level {} from a map with {} z-levels", index, dim_z));
+        }
+        if dim_z == 1 {
+            return Err("cannot remove the only z-level".to_owned());
+        }
+        let old = take(&mut self.grid);
+        self.grid = Array3::from_shape_fn((dim_z - 1, dim_y, dim_x), |(z, y, x)| {
+            if z < index {
+                old[(z, y, x)]
+            } else {
+                old[(z + 1, y, x)]
+            }
+        });
+        self.remove_unused_keys();
+        Ok(())
     }
 
-    pub fn one_to_zero(&self, (x, y, z): (usize, usize, usize)) -> (usize, usize, usize) {
-        (x - 1, self.grid.dim().1 - y, z - 1)
+    /// Reorder the z-levels so that new level `i` is old level `order[i]`.
+    ///
+    /// Fails if `order` is not a permutation of the existing z-levels.
+    pub fn reorder_z_levels(&mut self, order: &[usize]) -> Result<(), String> {
+        let (dim_z, dim_y, dim_x) = self.grid.dim();
+        let mut sorted = order.to_owned();
+        sorted.sort();
+        if !sorted.iter().cloned().eq(0..dim_z) {
+            return Err(format!("{:?} is not a permutation of the {} z-levels", order, dim_z));
+        }
+        let old = take(&mut self.grid);
+        self.grid = Array3::from_shape_fn((dim_z, dim_y, dim_x), |(z, y, x)| old[(order[z], y, x)]);
+        Ok(())
+    }
+
+    /// Resize every z-level, filling any new space with the given tile.
+    ///
+    /// Growing or shrinking happens on the top and right edges.
+    pub fn resize(&mut self, new_x: usize, new_y: usize, tile: Vec<Prefab>) -> Result<(), String> {
+        if new_x == 0 || new_y == 0 {
+            return Err(format!("cannot resize the map to {}x{}", new_x, new_y));
+        }
+        let (dim_z, dim_y, dim_x) = self.grid.dim();
+        let key = self.key_for(tile);
+        let old = take(&mut self.grid);
+        self.grid = Array3::from_shape_fn((dim_z, new_y, new_x), |(z, y, x)| {
+            // convert to the distance from the bottom edge
+            let from_bottom = new_y - 1 - y;
+            if x < dim_x && from_bottom < dim_y {
+                old[(z, dim_y - 1 - from_bottom, x)]
+            } else {
+                key
+            }
+        });
+        self.remove_unused_keys();
+        Ok(())
+    }
+
+    /// Crop every z-level to the given inclusive x and y bounds.
+    ///
+    /// The tile at `min` becomes the new (1, 1).
+    pub fn crop(&mut self, min: Coord2, max: Coord2) -> Result<(), String> {
+        let (dim_z, dim_y, dim_x) = self.grid.dim();
+        let (raw_min, raw_max) = match (min.to_raw((dim_x, dim_y)), max.to_raw((dim_x, dim_y))) {
+            // the raw grid is stored top row first
+            (Some((min_x, max_y)), Some((max_x, min_y))) if min.x <= max.x && min.y <= max.y => ((min_x, min_y), (max_x, max_y)),
+            _ => return Err(format!("cannot crop a {}x{} map to {} through {}", dim_x, dim_y, min, max)),
+        };
+        let (new_x, new_y) = (raw_max.0 - raw_min.0 + 1, raw_max.1 - raw_min.1 + 1);
+        let old = take(&mut self.grid);
+        self.grid = Array3::from_shape_fn((dim_z, new_y, new_x), |(z, y, x)| {
+            old[(z, raw_min.1 + y, raw_min.0 + x)]
+        });
+        self.remove_unused_keys();
+        Ok(())
     }
 }
 
@@ -122,6 +361,46 @@ impl Prefab {
             vars: Default::default(),
         }
     }
+
+    fn kind(&self) -> PrefabKind {
+        if subpath(&self.path, "/turf/") {
+            PrefabKind::Turf
+        } else if subpath(&self.path, "/area/") {
+            PrefabKind::Area
+        } else {
+            PrefabKind::Movable
+        }
+    }
+
+    /// Set a var on this prefab, for chaining.
+    pub fn with_var<S: Into<String>>(mut self, name: S, value: Constant) -> Prefab {
+        self.vars.insert(name.into(), value);
+        self
+    }
+}
+
+/// Add a prefab to the contents of a tile.
+///
+/// Turfs replace the existing turf and areas replace the existing area.
+/// Other prefabs are placed after any already present, beneath the turf.
+pub fn stamp_tile(tile: &mut Vec<Prefab>, prefab: Prefab) {
+    let kind = prefab.kind();
+    if kind == PrefabKind::Movable {
+        let i = tile.iter().position(|fab| fab.kind() != PrefabKind::Movable).unwrap_or(tile.len());
+        tile.insert(i, prefab);
+    } else {
+        match tile.iter().position(|fab| fab.kind() == kind) {
+            Some(i) => tile[i] = prefab,
+            None => tile.push(prefab),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum PrefabKind {
+    Movable,
+    Turf,
+    Area,
 }
 
 impl fmt::Display for Prefab {
@@ -195,7 +474,7 @@ const BASE_52: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
 
 const TGM_HEADER: &str = "//MAP CONVERTED BY dmm2tgm.py THIS HEADER COMMENT PREVENTS RECONVERSION, DO NOT REMOVE";
 
-fn save_tgm(map: &Map, f: File) -> io::Result<()> {
+fn save_tgm<W: io::Write>(map: &Map, f: W) -> io::Result<()> {
     use std::io::Write;
 
     let mut f = BufWriter::new(f);
@@ -501,7 +780,7 @@ fn advance_key(current: KeyType, next_digit: KeyType) -> Result<KeyType, DMError
     })
 }
 
-fn parse_constant(location: Location, input: Vec<u8>) -> Result<Constant, DMError> {
+pub(crate) fn parse_constant(location: Location, input: Vec<u8>) -> Result<Constant, DMError> {
     use dm::Context;
     use dm::lexer::Lexer;
     use dm::parser::Parser;
@@ -0,0 +1,304 @@
+//! In-memory map editing operations with an undo/redo history.
+//!
+//! Coordinates are in-game, as with the tile editing methods on `Map`.
+//! Operations fail without changing anything if given coordinates outside
+//! the map.
+use std::fmt;
+use std::collections::HashMap;
+
+use ndarray::Array3;
+
+use dm::objtree::subpath;
+use dmm::{self, Map, Key, Prefab};
+use coords::Coord3;
+
+/// An inclusive rectangular selection of tiles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub min: Coord3,
+    pub max: Coord3,
+}
+
+impl Region {
+    /// Create a region spanning the two given corners, in any order.
+    pub fn new(a: Coord3, b: Coord3) -> Region {
+        use std::cmp::{min, max};
+        Region {
+            min: Coord3::new(min(a.x, b.x), min(a.y, b.y), min(a.z, b.z)),
+            max: Coord3::new(max(a.x, b.x), max(a.y, b.y), max(a.z, b.z)),
+        }
+    }
+
+    /// A region containing only the given tile.
+    pub fn tile(coord: Coord3) -> Region {
+        Region { min: coord, max: coord }
+    }
+
+    /// The size of this region in x, y, z order.
+    pub fn dim_xyz(&self) -> (usize, usize, usize) {
+        (
+            (self.max.x - self.min.x + 1) as usize,
+            (self.max.y - self.min.y + 1) as usize,
+            (self.max.z - self.min.z + 1) as usize,
+        )
+    }
+
+    /// Iterate over the coordinates in this region.
+    pub fn coords(&self) -> Vec<Coord3> {
+        let mut result = Vec::new();
+        for z in self.min.z..=self.max.z {
+            for y in self.min.y..=self.max.y {
+                for x in self.min.x..=self.max.x {
+                    result.push(Coord3::new(x, y, z));
+                }
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{} to {}", self.min, self.max)
+        }
+    }
+}
+
+/// The contents of a copied region, independent of any map's keys.
+#[derive(Debug, Clone)]
+pub struct Clipboard {
+    // Z/Y/X order, relative to the region's minimum corner
+    tiles: Array3<Vec<Prefab>>,
+}
+
+impl Clipboard {
+    pub fn dim_xyz(&self) -> (usize, usize, usize) {
+        let dim = self.tiles.dim();
+        (dim.2, dim.1, dim.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Change {
+    coord: Coord3,
+    before: Key,
+    after: Key,
+}
+
+#[derive(Debug, Clone)]
+struct Edit {
+    description: String,
+    changes: Vec<Change>,
+}
+
+/// A map under editing, tracking history for undo and redo.
+///
+/// Dictionary entries are never removed while editing, so that history
+/// remains valid. Call `finish` to prune unused keys before saving.
+#[derive(Debug, Clone)]
+pub struct Editor {
+    map: Map,
+    // reverse of the map's dictionary, so edits don't scan it for every tile
+    keys: HashMap<Vec<Prefab>, Key>,
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl Editor {
+    pub fn new(map: Map) -> Editor {
+        let keys = map.dictionary.iter()
+            .map(|(&key, tile)| (tile.clone(), key))
+            .collect();
+        Editor {
+            map,
+            keys,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    /// Stop editing, removing unused dictionary entries from the map.
+    pub fn finish(mut self) -> Map {
+        self.map.remove_unused_keys();
+        self.map
+    }
+
+    /// Get the contents of a tile.
+    ///
+    /// Panics if the coordinates are outside the map.
+    pub fn tile(&self, coord: Coord3) -> &[Prefab] {
+        self.map.tile(coord)
+    }
+
+    // ------------------------------------------------------------------------
+    // Operations
+    //
+    // Each returns whether the map changed, or an error if the coordinates
+    // are outside the map.
+
+    /// Place a prefab on a tile as `Map::stamp` does, above any existing
+    /// movables.
+    pub fn place(&mut self, coord: Coord3, prefab: Prefab) -> Result<bool, String> {
+        let description = format!("place {} at {}", prefab, coord);
+        self.edit(description, Region::tile(coord), |_, tile| {
+            dmm::stamp_tile(tile, prefab.clone());
+        })
+    }
+
+    /// Remove the first instance of a prefab from a tile.
+    pub fn remove(&mut self, coord: Coord3, prefab: &Prefab) -> Result<bool, String> {
+        let description = format!("remove {} at {}", prefab, coord);
+        self.edit(description, Region::tile(coord), |_, tile| {
+            if let Some(pos) = tile.iter().position(|fab| fab == prefab) {
+                tile.remove(pos);
+            }
+        })
+    }
+
+    /// Replace the turf on every tile in a region with the given turf.
+    pub fn set_turf(&mut self, region: Region, turf: Prefab) -> Result<bool, String> {
+        if !subpath(&turf.path, "/turf/") {
+            return Err(format!("{} is not a turf", turf.path));
+        }
+        let description = format!("set turf {} at {}", turf, region);
+        self.edit(description, region, |_, tile| {
+            dmm::stamp_tile(tile, turf.clone());
+        })
+    }
+
+    /// Replace the entire contents of every tile in a region.
+    pub fn fill(&mut self, region: Region, contents: &[Prefab]) -> Result<bool, String> {
+        let description = format!("fill {}", region);
+        self.edit(description, region, |_, tile| {
+            *tile = contents.to_owned();
+        })
+    }
+
+    /// Copy the contents of a region.
+    pub fn copy(&self, region: Region) -> Result<Clipboard, String> {
+        self.check(region)?;
+        let (dx, dy, dz) = region.dim_xyz();
+        let min = region.min;
+        Ok(Clipboard {
+            tiles: Array3::from_shape_fn((dz, dy, dx), |(z, y, x)| {
+                self.tile(Coord3::new(min.x + x as i32, min.y + y as i32, min.z + z as i32)).to_owned()
+            }),
+        })
+    }
+
+    /// Paste copied tiles with their minimum corner at the given origin.
+    ///
+    /// Tiles which would fall outside the map are discarded.
+    pub fn paste(&mut self, origin: Coord3, clipboard: &Clipboard) -> Result<bool, String> {
+        use std::cmp::min;
+
+        self.map.raw_coord(origin)?;
+        let (mx, my, mz) = self.map.dim_xyz();
+        let (cx, cy, cz) = clipboard.dim_xyz();
+        let region = Region::new(origin, Coord3::new(
+            min(origin.x + cx as i32 - 1, mx as i32),
+            min(origin.y + cy as i32 - 1, my as i32),
+            min(origin.z + cz as i32 - 1, mz as i32),
+        ));
+        let description = format!("paste at {}", origin);
+        self.edit(description, region, |coord, tile| {
+            let offset = (
+                (coord.z - origin.z) as usize,
+                (coord.y - origin.y) as usize,
+                (coord.x - origin.x) as usize,
+            );
+            *tile = clipboard.tiles[offset].clone();
+        })
+    }
+
+    // ------------------------------------------------------------------------
+    // History
+
+    /// Undo the most recent edit, returning false if there was none.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(edit) => {
+                for change in edit.changes.iter().rev() {
+                    let (x, y, z) = self.map.one_to_zero(change.coord);
+                    self.map.grid[(z, y, x)] = change.before;
+                }
+                self.redo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redo the most recently undone edit, returning false if there was none.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(edit) => {
+                for change in edit.changes.iter() {
+                    let (x, y, z) = self.map.one_to_zero(change.coord);
+                    self.map.grid[(z, y, x)] = change.after;
+                }
+                self.undo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Descriptions of the edits which can be undone, oldest first.
+    pub fn undo_history(&self) -> Vec<&str> {
+        self.undo.iter().map(|e| e.description.as_str()).collect()
+    }
+
+    /// Descriptions of the edits which can be redone, next first.
+    pub fn redo_history(&self) -> Vec<&str> {
+        self.redo.iter().rev().map(|e| e.description.as_str()).collect()
+    }
+
+    /// Check that a region lies entirely within the map.
+    fn check(&self, region: Region) -> Result<(), String> {
+        self.map.raw_coord(region.min)?;
+        self.map.raw_coord(region.max)?;
+        Ok(())
+    }
+
+    fn edit<F>(&mut self, description: String, region: Region, mut f: F) -> Result<bool, String> where
+        F: FnMut(Coord3, &mut Vec<Prefab>),
+    {
+        self.check(region)?;
+        let mut changes = Vec::new();
+        for coord in region.coords() {
+            let (x, y, z) = self.map.one_to_zero(coord);
+            let before = self.map.grid[(z, y, x)];
+            let mut tile = self.map.dictionary[&before].clone();
+            f(coord, &mut tile);
+            let after = self.key_for(tile);
+            if after != before {
+                self.map.grid[(z, y, x)] = after;
+                changes.push(Change { coord, before, after });
+            }
+        }
+        if changes.is_empty() {
+            return Ok(false);
+        }
+        self.undo.push(Edit { description, changes });
+        self.redo.clear();
+        Ok(true)
+    }
+
+    fn key_for(&mut self, tile: Vec<Prefab>) -> Key {
+        if let Some(&key) = self.keys.get(&tile) {
+            return key;
+        }
+        let key = self.map.insert_key(tile.clone());
+        self.keys.insert(tile, key);
+        key
+    }
+}
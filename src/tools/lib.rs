@@ -6,6 +6,7 @@ extern crate dreammaker as dm;
 #[cfg(feature="png")] extern crate png;
 extern crate lodepng;
 extern crate inflate;
+extern crate deflate;
 
 #[macro_use] extern crate ndarray;
 extern crate linked_hash_map;
@@ -13,8 +14,13 @@ extern crate rand;
 
 #[macro_use] mod utils;
 pub mod dmm;
+pub mod coords;
 pub mod icon_cache;
 pub mod minimap;
 pub mod render_passes;
 pub mod lint;
+pub mod census;
 pub mod dmi;
+pub mod merge;
+pub mod editor;
+pub mod search;
@@ -1,9 +1,11 @@
 //! Simplistic linting tools for maps, to automatically fix certain issues.
+use std::collections::BTreeMap;
 use std::fmt;
 
 use dm::objtree::*;
 use dm::constants::Constant;
-use dmm::Map;
+use dmm::{Map, Prefab, Key};
+use coords::Coord3;
 
 macro_rules! lints {
     ($($ident:ident = $desc:expr;)*) => {
@@ -55,7 +57,7 @@ pub fn check(
                     }
                 }
                 if found_turf != 1 {
-                    println!("    at {:?}: found {} turfs", map.zero_to_one((x, y, z)), found_turf);
+                    println!("    at {}: found {} turfs", map.zero_to_one((x, y, z)), found_turf);
                 }
             }
         }
@@ -99,6 +101,116 @@ pub fn check(
     lints
 }
 
+/// A problem with the prefabs used by a map, as found by `validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// The first tile with the problem.
+    pub coords: Coord3,
+    /// The number of tiles with the problem, including the first.
+    pub tiles: usize,
+    /// The prefab with the problem.
+    pub prefab: Prefab,
+    pub kind: ProblemKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProblemKind {
+    /// The prefab's type does not exist.
+    UnknownType,
+    /// The prefab sets a var which its type does not have.
+    UnknownVar(String),
+    /// The prefab sets a var to the value it already has on its type.
+    DefaultValue(String),
+    /// The tile holds another, identical prefab.
+    Duplicate,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.coords)?;
+        match self.kind {
+            ProblemKind::UnknownType => write!(f, "unknown type {}", self.prefab.path)?,
+            ProblemKind::UnknownVar(ref var) => write!(f, "unknown var {:?} on {}", var, self.prefab.path)?,
+            ProblemKind::DefaultValue(ref var) => write!(f, "{} sets {:?} to its default value", self.prefab.path, var)?,
+            ProblemKind::Duplicate => write!(f, "duplicate {}", self.prefab)?,
+        }
+        if self.tiles > 1 {
+            write!(f, " (and {} more tiles)", self.tiles - 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the prefabs used by a map against the object tree, without making
+/// any changes.
+///
+/// Each problem is reported once per dictionary key, at the first tile using
+/// that key. Problems are sorted by z, then y, then x.
+pub fn validate(objtree: &ObjectTree, map: &Map) -> Vec<Problem> {
+    let mut by_key: BTreeMap<Key, Vec<(Prefab, ProblemKind)>> = BTreeMap::new();
+    for (&key, prefabs) in map.dictionary.iter() {
+        let mut problems = Vec::new();
+        for (i, fab) in prefabs.iter().enumerate() {
+            if prefabs[..i].contains(fab) {
+                problems.push((fab.clone(), ProblemKind::Duplicate));
+            }
+
+            let ty = match objtree.find(&fab.path) {
+                Some(ty) => ty,
+                None => {
+                    problems.push((fab.clone(), ProblemKind::UnknownType));
+                    continue;
+                }
+            };
+            for (name, value) in fab.vars.iter() {
+                let var = match ty.get_value(name) {
+                    Some(var) => var,
+                    None => {
+                        problems.push((fab.clone(), ProblemKind::UnknownVar(name.clone())));
+                        continue;
+                    }
+                };
+                // vars whose initial value is not constant can't be compared
+                let default = match (&var.constant, &var.expression) {
+                    (&Some(ref constant), _) => constant,
+                    (&None, &None) => Constant::null(),
+                    (&None, &Some(_)) => continue,
+                };
+                if value == default {
+                    problems.push((fab.clone(), ProblemKind::DefaultValue(name.clone())));
+                }
+            }
+        }
+        if !problems.is_empty() {
+            by_key.insert(key, problems);
+        }
+    }
+
+    // locate the problems in the grid
+    let mut first_seen: BTreeMap<Key, (Coord3, usize)> = BTreeMap::new();
+    let (dim_x, dim_y, dim_z) = map.dim_xyz();
+    for z in 0..dim_z {
+        for y in (0..dim_y).rev() {
+            for x in 0..dim_x {
+                let key = map.grid[(z, y, x)];
+                if by_key.contains_key(&key) {
+                    let coords = map.zero_to_one((x, y, z));
+                    first_seen.entry(key).or_insert((coords, 0)).1 += 1;
+                }
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    for (key, (coords, tiles)) in first_seen {
+        for (prefab, kind) in by_key.remove(&key).unwrap_or_default() {
+            output.push(Problem { coords, tiles, prefab, kind });
+        }
+    }
+    output.sort_by_key(|problem| (problem.coords.z, problem.coords.y, problem.coords.x));
+    output
+}
+
 pub fn retain_mut<T, F>(v: &mut Vec<T>, mut f: F)
 where
     F: FnMut(&mut T) -> bool,
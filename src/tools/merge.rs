@@ -0,0 +1,118 @@
+//! Three-way merging of maps, suitable for use as a git merge driver.
+use std::collections::{BTreeMap, HashMap};
+
+use ndarray::Array3;
+
+use dm::{DMError, Location};
+use dmm::{Map, Key, Prefab};
+use coords::Coord3;
+
+/// A tile which was changed differently on both sides of a merge.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub coord: Coord3,
+    pub base: Vec<Prefab>,
+    pub ours: Vec<Prefab>,
+    pub theirs: Vec<Prefab>,
+}
+
+/// The outcome of a three-way merge.
+///
+/// Conflicting tiles retain the contents from `ours` in the merged map.
+#[derive(Debug, Clone)]
+pub struct Merged {
+    pub map: Map,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Merged {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merge the tile edits made in `ours` and `theirs` relative to `base`.
+///
+/// Tiles changed on only one side take that side's contents. The key
+/// dictionary is regenerated deterministically, preferring the keys already
+/// used by `ours` and then by `theirs`, so that the merged map differs as
+/// little as possible from either parent.
+pub fn merge(base: &Map, ours: &Map, theirs: &Map) -> Result<Merged, DMError> {
+    let dim = base.grid.dim();
+    if ours.grid.dim() != dim || theirs.grid.dim() != dim {
+        return Err(DMError::new(Location::default(), format!(
+            "cannot merge maps of different sizes: base {:?}, ours {:?}, theirs {:?}",
+            base.dim_xyz(), ours.dim_xyz(), theirs.dim_xyz(),
+        )));
+    }
+
+    // Decide the contents of each tile.
+    let mut conflicts = Vec::new();
+    let mut tiles: Vec<&Vec<Prefab>> = Vec::with_capacity(base.grid.len());
+    for ((z, y, x), base_key) in base.grid.indexed_iter() {
+        let base_tile = &base.dictionary[base_key];
+        let our_tile = &ours.dictionary[&ours.grid[(z, y, x)]];
+        let their_tile = &theirs.dictionary[&theirs.grid[(z, y, x)]];
+
+        tiles.push(if our_tile == their_tile || their_tile == base_tile {
+            our_tile
+        } else if our_tile == base_tile {
+            their_tile
+        } else {
+            conflicts.push(Conflict {
+                coord: base.zero_to_one((x, y, z)),
+                base: base_tile.clone(),
+                ours: our_tile.clone(),
+                theirs: their_tile.clone(),
+            });
+            our_tile
+        });
+    }
+
+    // Regenerate the dictionary, reusing existing keys where possible.
+    let mut assigned: HashMap<&Vec<Prefab>, Key> = tiles.iter().map(|&t| (t, Key::invalid())).collect();
+    let mut dictionary: BTreeMap<Key, Vec<Prefab>> = BTreeMap::new();
+    for parent in &[ours, theirs] {
+        for (&key, tile) in parent.dictionary.iter() {
+            if dictionary.contains_key(&key) {
+                continue;
+            }
+            if let Some(slot) = assigned.get_mut(tile) {
+                if *slot == Key::invalid() {
+                    *slot = key;
+                    dictionary.insert(key, tile.clone());
+                }
+            }
+        }
+    }
+    let mut next_free = Key::default();
+    for tile in tiles.iter() {
+        let slot = assigned.get_mut(tile).unwrap();
+        if *slot != Key::invalid() {
+            continue;
+        }
+        while dictionary.contains_key(&next_free) {
+            next_free = next_free.next();
+        }
+        if next_free == Key::invalid() {
+            return Err(DMError::new(Location::default(), "merged map has too many unique tiles"));
+        }
+        *slot = next_free;
+        dictionary.insert(next_free, (*tile).clone());
+    }
+
+    let mut tiles = tiles.into_iter();
+    let grid = Array3::from_shape_fn(dim, |_| assigned[tiles.next().unwrap()]);
+
+    let max_key = dictionary.keys().next_back().cloned().unwrap_or_default();
+    let mut map = Map {
+        key_length: ours.key_length,
+        dictionary,
+        grid,
+    };
+    while !map.key_fits(max_key) {
+        map.key_length += 1;
+    }
+
+    Ok(Merged { map, conflicts })
+}
@@ -1,17 +1,16 @@
 use std::path::Path;
 
-use ndarray::{self, Axis};
+use ndarray::Axis;
 
 use dm::objtree::*;
 use dm::objtree::subpath as subtype;
 use dm::constants::Constant;
 use dmm::{Map, Grid, Prefab};
+use coords::{Coord2, PixelOffset, TILE_SIZE};
 use dmi::Image;
 use render_passes::RenderPass;
 use icon_cache::IconCache;
 
-const TILE_SIZE: u32 = 32;
-
 // ----------------------------------------------------------------------------
 // Main minimap code
 
@@ -20,8 +19,10 @@ pub struct Context<'a> {
     pub objtree: &'a ObjectTree,
     pub map: &'a Map,
     pub grid: Grid<'a>,
-    pub min: (usize, usize),
-    pub max: (usize, usize),
+    /// The bottom-left corner of the area to render, in game coordinates.
+    pub min: Coord2,
+    /// The top-right corner of the area to render, in game coordinates.
+    pub max: Coord2,
     pub render_passes: &'a [Box<RenderPass>],
 }
 
@@ -34,25 +35,21 @@ pub fn generate(ctx: Context, icon_cache: &IconCache) -> Result<Image, ()> {
         ..
     } = ctx;
 
-    // transform min/max from bottom-left-based to top-left-based
-    // probably doesn't belong here
-    let (len_y, _) = ctx.grid.dim();
-    let (min_y, max_y) = (len_y - ctx.max.1 - 1, len_y - ctx.min.1 - 1);
-    let (len_x, len_y) = (ctx.max.0 - ctx.min.0 + 1, ctx.max.1 - ctx.min.1 + 1);
+    let (dim_y, _) = ctx.grid.dim();
+    let top_left = Coord2::new(ctx.min.x, ctx.max.y);
+    let (len_x, len_y) = (ctx.max.x - ctx.min.x + 1, ctx.max.y - ctx.min.y + 1);
 
     // loads atoms from the prefabs on the map and adds overlays and smoothing
     let mut atoms = Vec::new();
     let mut overlays = Vec::new();
 
     for (y, row) in grid.axis_iter(Axis(0)).enumerate() {
-        if y < min_y || y > max_y {
-            continue;
-        }
         for (x, e) in row.iter().enumerate() {
-            if x < ctx.min.0 || x > ctx.max.0 {
+            let loc = Coord2::from_raw((x, y), dim_y);
+            if loc.x < ctx.min.x || loc.x > ctx.max.x || loc.y < ctx.min.y || loc.y > ctx.max.y {
                 continue;
             }
-            for mut atom in get_atom_list(objtree, &map.dictionary[e], (x as u32, y as u32), render_passes) {
+            for mut atom in get_atom_list(objtree, &map.dictionary[e], loc, render_passes) {
                 // icons which differ from their map states
                 let p = &atom.type_.path;
                 if p == "/obj/structure/table/wood/fancy/black" {
@@ -173,7 +170,9 @@ pub fn generate(ctx: Context, icon_cache: &IconCache) -> Result<Image, ()> {
                 }
 
                 // smoothing time
-                handle_smooth(&mut atoms, ctx, atom, !0);
+                if !render_passes.iter().any(|pass| pass.smooth(&atom, ctx, &mut atoms)) {
+                    atoms.push(atom);
+                }
                 atoms.extend(overlays.drain(..));
             }
         }
@@ -210,13 +209,8 @@ pub fn generate(ctx: Context, icon_cache: &IconCache) -> Result<Image, ()> {
         };
 
         if let Some(mut rect) = icon_file.rect_of(&icon_state, dir) {
-            let pixel_x = atom.get_var("pixel_x", ctx.objtree).to_int().unwrap_or(0);
-            let pixel_y = atom.get_var("pixel_y", ctx.objtree).to_int().unwrap_or(0) +
-                icon_file.metadata.height as i32;
-            let mut loc = (
-                ((atom.loc.0 - ctx.min.0 as u32) * TILE_SIZE) as i32 + pixel_x,
-                ((atom.loc.1 + 1 - min_y as u32) * TILE_SIZE) as i32 - pixel_y,
-            );
+            let offset = PixelOffset::of(objtree, &atom);
+            let mut loc = offset.draw_position(atom.loc, top_left, icon_file.metadata.height);
 
             // OOB handling
             if loc.0 < 0 {
@@ -265,7 +259,7 @@ pub fn generate(ctx: Context, icon_cache: &IconCache) -> Result<Image, ()> {
 pub fn get_atom_list<'a>(
     objtree: &'a ObjectTree,
     prefabs: &'a [Prefab],
-    loc: (u32, u32),
+    loc: Coord2,
     render_passes: &[Box<RenderPass>],
 ) -> Vec<Atom<'a>> {
     let mut result = Vec::new();
@@ -312,11 +306,11 @@ pub struct Atom<'a> {
     type_: &'a Type,
     prefab: Option<&'a Vars>,
     vars: Vars,
-    pub loc: (u32, u32),
+    pub loc: Coord2,
 }
 
 impl<'a> Atom<'a> {
-    pub fn from_prefab(objtree: &'a ObjectTree, fab: &'a Prefab, loc: (u32, u32)) -> Option<Self> {
+    pub fn from_prefab(objtree: &'a ObjectTree, fab: &'a Prefab, loc: Coord2) -> Option<Self> {
         objtree.find(&fab.path).map(|type_| Atom {
             type_: type_.get(),
             prefab: Some(&fab.vars),
@@ -325,7 +319,7 @@ impl<'a> Atom<'a> {
         })
     }
 
-    pub fn from_type(objtree: &'a ObjectTree, path: &str, loc: (u32, u32)) -> Option<Self> {
+    pub fn from_type(objtree: &'a ObjectTree, path: &str, loc: Coord2) -> Option<Self> {
         objtree.find(path).map(|type_| Atom {
             type_: type_.get(),
             prefab: None,
@@ -334,7 +328,7 @@ impl<'a> Atom<'a> {
         })
     }
 
-    pub fn from_type_ref(type_: &'a Type, loc: (u32, u32)) -> Self {
+    pub fn from_type_ref(type_: &'a Type, loc: Coord2) -> Self {
         Atom {
             type_: type_,
             prefab: None,
@@ -480,344 +474,8 @@ pub fn color_of<T: GetVar + ?Sized>(objtree: &ObjectTree, atom: &T) -> [u8; 4] {
         _ => 255,
     };
 
-    match atom.get_var("color", objtree) {
-        &Constant::String(ref color) if color.starts_with("#") => {
-            let mut sum = 0;
-            for ch in color[1..color.len()].chars() {
-                sum = 16 * sum + ch.to_digit(16).unwrap();
-            }
-            if color.len() == 7 {  // #rrggbb
-                [(sum >> 16) as u8, (sum >> 8) as u8, sum as u8, alpha]
-            } else if color.len() == 4 {  // #rgb
-                [
-                    (0x11 * ((sum >> 8) & 0xf)) as u8,
-                    (0x11 * ((sum >> 4) & 0xf)) as u8,
-                    (0x11 * (sum & 0xf)) as u8,
-                    alpha,
-                ]
-            } else {
-                [255, 255, 255, alpha]  // invalid
-            }
-        }
-        _ => [255, 255, 255, alpha],
-    }
-}
-
-// ----------------------------------------------------------------------------
-// Icon smoothing subsystem
-
-// (1 << N) where N is the usual value
-const N_NORTH: i32 = 2;
-const N_SOUTH: i32 = 4;
-const N_EAST: i32 = 16;
-const N_WEST: i32 = 256;
-const N_NORTHEAST: i32 = 32;
-const N_NORTHWEST: i32 = 512;
-const N_SOUTHEAST: i32 = 64;
-const N_SOUTHWEST: i32 = 1024;
-
-const SMOOTH_TRUE: i32 = 1;  // smooth with exact specified types or just itself
-const SMOOTH_MORE: i32 = 2;  // smooth with all subtypes thereof
-const SMOOTH_DIAGONAL: i32 = 4;  // smooth diagonally
-const SMOOTH_BORDER: i32 = 8;  // smooth with the borders of the map
-
-fn handle_smooth<'a>(output: &mut Vec<Atom<'a>>, ctx: Context<'a>, atom: Atom<'a>, mask: i32) {
-    let smooth_flags = mask & atom.get_var("smooth", ctx.objtree).to_int().unwrap_or(0);
-    if smooth_flags & (SMOOTH_TRUE | SMOOTH_MORE) != 0 {
-        let adjacencies = calculate_adjacencies(ctx, &atom, smooth_flags);
-        if smooth_flags & SMOOTH_DIAGONAL != 0 {
-            diagonal_smooth(output, ctx, &atom, adjacencies);
-        } else {
-            cardinal_smooth(output, ctx, &atom, adjacencies);
-        }
-    } else {
-        output.push(atom);
-    }
-}
-
-fn calculate_adjacencies(ctx: Context, atom: &Atom, flags: i32) -> i32 {
-    use dmi::*;
-    // TODO: anchored check
-
-    let mut adjacencies = 0;
-    let check_one = |direction, flag| {
-        if find_type_in_direction(ctx, atom, direction, flags) {
-            flag
-        } else {
-            0
-        }
-    };
-
-    for &dir in &[SOUTH, NORTH, EAST, WEST] {
-        adjacencies |= check_one(dir, 1 << dir);
-    }
-
-    if adjacencies & N_NORTH != 0 {
-        if adjacencies & N_WEST != 0 {
-            adjacencies |= check_one(NORTHWEST, N_NORTHWEST);
-        }
-        if adjacencies & N_EAST != 0 {
-            adjacencies |= check_one(NORTHEAST, N_NORTHEAST);
-        }
-    }
-    if adjacencies & N_SOUTH != 0 {
-        if adjacencies & N_WEST != 0 {
-            adjacencies |= check_one(SOUTHWEST, N_SOUTHWEST);
-        }
-        if adjacencies & N_EAST != 0 {
-            adjacencies |= check_one(SOUTHEAST, N_SOUTHEAST);
-        }
-    }
-
-    adjacencies
-}
-
-fn find_type_in_direction<'a>(ctx: Context, source: &Atom, direction: i32, flags: i32) -> bool {
-    use std::ptr::eq;
-
-    let (dx, dy) = offset(direction);
-    let new_loc = (source.loc.0 as i32 + dx, source.loc.1 as i32 + dy);
-    let (dim_y, dim_x) = ctx.grid.dim();
-    if new_loc.0 < 0 || new_loc.1 < 0 || new_loc.0 >= dim_x as i32 || new_loc.1 >= dim_y as i32 {
-        return flags & SMOOTH_BORDER != 0;
-    }
-    let new_loc = (new_loc.0 as u32, new_loc.1 as u32);
-
-    // TODO: make this not call get_atom_list way too many times
-    let atom_list = get_atom_list(
-        ctx.objtree,
-        &ctx.map.dictionary[&ctx.grid[ndarray::Dim([new_loc.1 as usize, new_loc.0 as usize])]],
-        new_loc,
-        ctx.render_passes,
-    );
-    match source.get_var("canSmoothWith", ctx.objtree) {
-        &Constant::List(ref elements) => if flags & SMOOTH_MORE != 0 {
-            // smooth with canSmoothWith + subtypes
-            for atom in atom_list {
-                let mut path = &atom.type_.path[..];
-                while !path.is_empty() {
-                    if smoothlist_contains(elements, path) {
-                        return true;
-                    }
-                    path = &path[..path.rfind("/").unwrap()];
-                }
-            }
-        } else {
-            // smooth only with exact types in canSmoothWith
-            for atom in atom_list {
-                if smoothlist_contains(elements, &atom.type_.path) {
-                    return true;
-                }
-            }
-        },
-        _ => {
-            // smooth only with the same type
-            for atom in atom_list {
-                if eq(atom.type_, source.type_) {
-                    return true;
-                }
-            }
-        },
-    }
-    false
-}
-
-fn smoothlist_contains(list: &[(Constant, Option<Constant>)], desired: &str) -> bool {
-    for &(ref key, _) in list {
-        // TODO: be more specific than to_string
-        if key.to_string() == desired {
-            return true;
-        }
-    }
-    false
-}
-
-fn cardinal_smooth<'a>(output: &mut Vec<Atom<'a>>, ctx: Context<'a>, source: &Atom<'a>, adjacencies: i32) {
-    for &(what, f1, n1, f2, n2, f3) in &[
-        ("1", N_NORTH, "n", N_WEST, "w", N_NORTHWEST),
-        ("2", N_NORTH, "n", N_EAST, "e", N_NORTHEAST),
-        ("3", N_SOUTH, "s", N_WEST, "w", N_SOUTHWEST),
-        ("4", N_SOUTH, "s", N_EAST, "e", N_SOUTHEAST),
-    ] {
-        let name = if (adjacencies & f1 != 0) && (adjacencies & f2 != 0) {
-            if (adjacencies & f3) != 0 {
-                format!("{}-f", what)
-            } else {
-                format!("{}-{}{}", what, n1, n2)
-            }
-        } else if adjacencies & f1 != 0 {
-            format!("{}-{}", what, n1)
-        } else if adjacencies & f2 != 0 {
-            format!("{}-{}", what, n2)
-        } else {
-            format!("{}-i", what)
-        };
-
-        let mut copy = source.clone();
-        copy.set_var("icon_state", Constant::string(name));
-        if let Some(icon) = source.get_var_notnull("smooth_icon", ctx.objtree) {
-            copy.set_var("icon", icon.clone());
-        }
-        output.push(copy);
-    }
-}
-
-fn diagonal_smooth<'a>(output: &mut Vec<Atom<'a>>, ctx: Context<'a>, source: &Atom<'a>, adjacencies: i32) {
-    let presets = if adjacencies == N_NORTH | N_WEST {
-        ["d-se", "d-se-0"]
-    } else if adjacencies == N_NORTH | N_EAST {
-        ["d-sw", "d-sw-0"]
-    } else if adjacencies == N_SOUTH | N_WEST {
-        ["d-ne", "d-ne-0"]
-    } else if adjacencies == N_SOUTH | N_EAST {
-        ["d-nw", "d-nw-0"]
-    } else if adjacencies == N_NORTH | N_WEST | N_NORTHWEST {
-        ["d-se", "d-se-1"]
-    } else if adjacencies == N_NORTH | N_EAST | N_NORTHEAST {
-        ["d-sw", "d-sw-1"]
-    } else if adjacencies == N_SOUTH | N_WEST | N_SOUTHWEST {
-        ["d-ne", "d-ne-1"]
-    } else if adjacencies == N_SOUTH | N_EAST | N_SOUTHEAST {
-        ["d-nw", "d-nw-1"]
-    } else {
-        return cardinal_smooth(output, ctx, source, adjacencies);
-    };
-
-    // turf underneath
-    if subtype(&source.type_.path, "/turf/closed/wall/") {
-        // BYOND memes
-        if source
-            .get_var("fixed_underlay", ctx.objtree)
-            .index(&Constant::string("space"))
-            .is_some()
-        {
-            output.push(Atom::from_type(ctx.objtree, "/turf/open/space/basic", source.loc).unwrap());
-        } else {
-            let dir = flip(reverse_ndir(adjacencies));
-            let mut needs_plating = true;
-            // check direct, then 45deg left, then 45deg right
-            'dirs: for &each in &[dir, left_45(dir), right_45(dir)] {
-                let (dx, dy) = offset(each);
-                let new_loc = (source.loc.0 as i32 + dx, source.loc.1 as i32 + dy);
-                let (dim_y, dim_x) = ctx.grid.dim();
-                if !(new_loc.0 < 0 || new_loc.1 < 0 || new_loc.0 >= dim_x as i32 || new_loc.1 >= dim_y as i32) {
-                    let new_loc = (new_loc.0 as u32, new_loc.1 as u32);
-                    // TODO: make this not call get_atom_list way too many times
-                    let atom_list = get_atom_list(
-                        ctx.objtree,
-                        &ctx.map.dictionary[&ctx.grid[ndarray::Dim([new_loc.1 as usize, new_loc.0 as usize])]],
-                        new_loc,
-                        ctx.render_passes,
-                    );
-                    for mut atom in atom_list {
-                        if subtype(&atom.type_.path, "/turf/open/") {
-                            atom.loc = source.loc;
-                            output.push(atom);
-                            needs_plating = false;
-                            break 'dirs;
-                        }
-                    }
-                }
-            }
-            if needs_plating {
-                output.push(Atom::from_type(ctx.objtree, "/turf/open/floor/plating", source.loc).unwrap());
-            }
-        }
-    }
-
-    // the diagonal overlay
-    for &each in presets.iter() {
-        let mut copy = source.clone();
-        copy.set_var("icon_state", Constant::string(each));
-        if let Some(icon) = source.get_var_notnull("smooth_icon", ctx.objtree) {
-            copy.set_var("icon", icon.clone());
-        }
-        output.push(copy);
-    }
-}
-
-fn offset(direction: i32) -> (i32, i32) {
-    use dmi::*;
-    match direction {
-        0 => (0, 0),
-        SOUTH => (0, 1),
-        NORTH => (0, -1),
-        EAST => (1, 0),
-        WEST => (-1, 0),
-        SOUTHEAST => (1, 1),
-        SOUTHWEST => (-1, 1),
-        NORTHEAST => (1, -1),
-        NORTHWEST => (-1, -1),
-        _ => panic!(),
-    }
-}
-
-fn flip(direction: i32) -> i32 {
-    use dmi::*;
-    match direction {
-        0 => 0,
-        SOUTH => NORTH,
-        NORTH => SOUTH,
-        EAST => WEST,
-        WEST => EAST,
-        SOUTHEAST => NORTHWEST,
-        SOUTHWEST => NORTHEAST,
-        NORTHEAST => SOUTHWEST,
-        NORTHWEST => SOUTHEAST,
-        _ => panic!(),
-    }
-}
-
-fn reverse_ndir(ndir: i32) -> i32 {
-    use dmi::*;
-    const NW1: i32 = N_NORTH | N_WEST;
-    const NW2: i32 = NW1 | N_NORTHWEST;
-    const NE1: i32 = N_NORTH | N_EAST;
-    const NE2: i32 = NE1 | N_NORTHEAST;
-    const SW1: i32 = N_SOUTH | N_WEST;
-    const SW2: i32 = SW1 | N_SOUTHWEST;
-    const SE1: i32 = N_SOUTH | N_EAST;
-    const SE2: i32 = SE1 | N_SOUTHEAST;
-
-    match ndir {
-        N_NORTH => NORTH,
-        N_SOUTH => SOUTH,
-        N_WEST => WEST,
-        N_EAST => EAST,
-        N_SOUTHEAST | SE1 | SE2 => SOUTHEAST,
-        N_SOUTHWEST | SW1 | SW2 => SOUTHWEST,
-        N_NORTHEAST | NE1 | NE2 => NORTHEAST,
-        N_NORTHWEST | NW1 | NW2 => NORTHWEST,
-        _ => panic!(),
-    }
-}
-
-fn left_45(dir: i32) -> i32 {
-    use dmi::*;
-    match dir {
-        NORTH => NORTHWEST,
-        NORTHEAST => NORTH,
-        EAST => NORTHEAST,
-        SOUTHEAST => EAST,
-        SOUTH => SOUTHEAST,
-        SOUTHWEST => SOUTH,
-        WEST => SOUTHWEST,
-        NORTHWEST => WEST,
-        e => e,
-    }
-}
-
-fn right_45(dir: i32) -> i32 {
-    use dmi::*;
-    match dir {
-        NORTH => NORTHEAST,
-        NORTHEAST => EAST,
-        EAST => SOUTHEAST,
-        SOUTHEAST => SOUTH,
-        SOUTH => SOUTHWEST,
-        SOUTHWEST => WEST,
-        WEST => NORTHWEST,
-        NORTHWEST => NORTH,
-        e => e,
+    match atom.get_var("color", objtree).to_color() {
+        Some(color) => [color.r, color.g, color.b, (color.a as u16 * alpha as u16 / 255) as u8],
+        None => [255, 255, 255, alpha],  // invalid, or a color matrix
     }
 }
@@ -0,0 +1,117 @@
+//! Render passes built from callbacks, for codebase-specific fixups.
+use super::*;
+
+/// A callback which adjusts the vars of an atom.
+pub type AdjustVarsFn = for<'a> Fn(&mut Atom<'a>, &'a ObjectTree) + Send + Sync;
+
+/// A callback which adds underlays and overlays to an atom.
+pub type OverlaysFn = for<'a> Fn(&mut Atom<'a>, &'a ObjectTree, &mut Vec<Atom<'a>>, &mut Vec<Atom<'a>>) + Send + Sync;
+
+/// A callback which decides whether to keep an atom.
+pub type FilterFn = Fn(&Atom, &ObjectTree) -> bool + Send + Sync;
+
+/// A render pass which dispatches to callbacks registered per type.
+///
+/// Each callback applies to atoms of the given type and its subtypes, and
+/// callbacks run in the order they were registered. Atoms expose both their
+/// prefab's vars and the object tree's defaults through `GetVar`.
+///
+/// ```ignore
+/// let hooks = Hooks::new()
+///     .on_adjust_vars("/obj/machinery/light", |atom, _| {
+///         atom.set_var("icon_state", Constant::string("tube1"));
+///     });
+/// render_passes.push(Box::new(hooks));
+/// ```
+#[derive(Default)]
+pub struct Hooks {
+    early_filters: Vec<(String, Box<FilterFn>)>,
+    adjust_vars: Vec<(String, Box<AdjustVarsFn>)>,
+    overlays: Vec<(String, Box<OverlaysFn>)>,
+    late_filters: Vec<(String, Box<FilterFn>)>,
+}
+
+impl Hooks {
+    pub fn new() -> Hooks {
+        Hooks::default()
+    }
+
+    /// Register a filter run before any other processing.
+    ///
+    /// Return `false` to discard the atom.
+    pub fn on_early_filter<F>(mut self, path: &str, f: F) -> Self
+        where F: Fn(&Atom, &ObjectTree) -> bool + Send + Sync + 'static
+    {
+        self.early_filters.push((normalize(path), Box::new(f)));
+        self
+    }
+
+    /// Register a callback to adjust the vars of an atom.
+    pub fn on_adjust_vars<F>(mut self, path: &str, f: F) -> Self
+        where F: for<'a> Fn(&mut Atom<'a>, &'a ObjectTree) + Send + Sync + 'static
+    {
+        self.adjust_vars.push((normalize(path), Box::new(f)));
+        self
+    }
+
+    /// Register a callback to add underlays and overlays to an atom.
+    pub fn on_overlays<F>(mut self, path: &str, f: F) -> Self
+        where F: for<'a> Fn(&mut Atom<'a>, &'a ObjectTree, &mut Vec<Atom<'a>>, &mut Vec<Atom<'a>>) + Send + Sync + 'static
+    {
+        self.overlays.push((normalize(path), Box::new(f)));
+        self
+    }
+
+    /// Register a filter run on adjusted atoms and pseudo-atoms at the end.
+    ///
+    /// Return `false` to discard the atom.
+    pub fn on_late_filter<F>(mut self, path: &str, f: F) -> Self
+        where F: Fn(&Atom, &ObjectTree) -> bool + Send + Sync + 'static
+    {
+        self.late_filters.push((normalize(path), Box::new(f)));
+        self
+    }
+}
+
+impl RenderPass for Hooks {
+    fn early_filter(&self, atom: &Atom, objtree: &ObjectTree) -> bool {
+        self.early_filters.iter()
+            .filter(|&&(ref path, _)| atom.istype(path))
+            .all(|&(_, ref f)| f(atom, objtree))
+    }
+
+    fn adjust_vars<'a>(&self, atom: &mut Atom<'a>, objtree: &'a ObjectTree) {
+        for &(ref path, ref f) in self.adjust_vars.iter() {
+            if atom.istype(path) {
+                f(atom, objtree);
+            }
+        }
+    }
+
+    fn overlays<'a>(&self,
+        atom: &mut Atom<'a>,
+        objtree: &'a ObjectTree,
+        underlays: &mut Vec<Atom<'a>>,
+        overlays: &mut Vec<Atom<'a>>,
+    ) {
+        for &(ref path, ref f) in self.overlays.iter() {
+            if atom.istype(path) {
+                f(atom, objtree, underlays, overlays);
+            }
+        }
+    }
+
+    fn late_filter(&self, atom: &Atom, objtree: &ObjectTree) -> bool {
+        self.late_filters.iter()
+            .filter(|&&(ref path, _)| atom.istype(path))
+            .all(|&(_, ref f)| f(atom, objtree))
+    }
+}
+
+fn normalize(path: &str) -> String {
+    if path.ends_with("/") {
+        path.to_owned()
+    } else {
+        format!("{}/", path)
+    }
+}
@@ -1,10 +1,12 @@
 use dm::objtree::*;
 use dm::constants::Constant;
-use minimap::{Atom, GetVar};
+use minimap::{Atom, GetVar, Context};
 
 pub mod transit_tube;
 pub mod random;
 pub mod structures;
+pub mod smoothing;
+pub mod hooks;
 
 /// A map rendering pass.
 ///
@@ -48,6 +50,16 @@ pub trait RenderPass: Sync {
         overlays: &mut Vec<Atom<'a>>,
     ) {}
 
+    /// Emulate icon smoothing, pushing the resulting atoms to `output`.
+    ///
+    /// Return `true` to consume the original atom. Only the first pass to do
+    /// so is applied, and atoms no pass consumes are rendered unsmoothed.
+    fn smooth<'a>(&self,
+        atom: &Atom<'a>,
+        ctx: Context<'a>,
+        output: &mut Vec<Atom<'a>>,
+    ) -> bool { false }
+
     /// Filter atoms at the end of the process.
     ///
     /// Will act on adjusted atoms and pseudo-atoms from `adjust_vars` and
@@ -84,10 +96,21 @@ pub const RENDER_PASSES: &[RenderPassInfo] = &[
     pass!(FakeGlass, "fake-glass", "Add underlays to fake glass turfs.", true),
     pass!(transit_tube::TransitTube, "transit-tube", "Add overlays to connect transit tubes together.", true),
     pass!(structures::GravityGen, "gravity-gen", "Expand the gravity generator to the full structure.", true),
+    pass!(smoothing::IconSmoothing, "icon-smoothing", "Emulate corner-based icon smoothing using the `smooth` var.", true),
+    pass!(smoothing::BitmaskSmoothing, "bitmask-smoothing", "Emulate junction bitmask icon smoothing using smoothing groups.", true),
     pass!(Wires, "only-powernet", "Render only power cables.", false),
     pass!(Pipes, "only-pipenet", "Render only atmospheric pipes.", false),
 ];
 
+/// Find the names in a comma-separated list of render passes which are not
+/// recognized by `configure`.
+pub fn unknown_names(list: &str) -> Vec<&str> {
+    list.split(",")
+        .filter(|&name| !name.is_empty() && name != "all")
+        .filter(|&name| !RENDER_PASSES.iter().any(|pass| pass.name == name))
+        .collect()
+}
+
 pub fn configure(include: &str, exclude: &str) -> Vec<Box<RenderPass>> {
     let include: Vec<&str> = include.split(",").collect();
     let exclude: Vec<&str> = exclude.split(",").collect();
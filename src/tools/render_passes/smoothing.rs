@@ -0,0 +1,406 @@
+//! Emulation of the icon smoothing subsystems used by various codebases.
+use ndarray;
+
+use super::*;
+use minimap::get_atom_list;
+
+// ----------------------------------------------------------------------------
+// Corner smoothing
+
+// (1 << N) where N is the usual value
+const N_NORTH: i32 = 2;
+const N_SOUTH: i32 = 4;
+const N_EAST: i32 = 16;
+const N_WEST: i32 = 256;
+const N_NORTHEAST: i32 = 32;
+const N_NORTHWEST: i32 = 512;
+const N_SOUTHEAST: i32 = 64;
+const N_SOUTHWEST: i32 = 1024;
+
+const SMOOTH_TRUE: i32 = 1;  // smooth with exact specified types or just itself
+const SMOOTH_MORE: i32 = 2;  // smooth with all subtypes thereof
+const SMOOTH_DIAGONAL: i32 = 4;  // smooth diagonally
+const SMOOTH_BORDER: i32 = 8;  // smooth with the borders of the map
+
+/// Corner-based smoothing driven by the `smooth` and `canSmoothWith` vars.
+#[derive(Default)]
+pub struct IconSmoothing;
+impl RenderPass for IconSmoothing {
+    fn smooth<'a>(&self,
+        atom: &Atom<'a>,
+        ctx: Context<'a>,
+        output: &mut Vec<Atom<'a>>,
+    ) -> bool {
+        let smooth_flags = atom.get_var("smooth", ctx.objtree).to_int().unwrap_or(0);
+        if smooth_flags & (SMOOTH_TRUE | SMOOTH_MORE) == 0 {
+            return false;
+        }
+        let adjacencies = calculate_adjacencies(ctx, atom, smooth_flags);
+        if smooth_flags & SMOOTH_DIAGONAL != 0 {
+            diagonal_smooth(output, ctx, atom, adjacencies);
+        } else {
+            cardinal_smooth(output, ctx, atom, adjacencies);
+        }
+        true
+    }
+}
+
+fn calculate_adjacencies(ctx: Context, atom: &Atom, flags: i32) -> i32 {
+    use dmi::*;
+    // TODO: anchored check
+
+    let mut adjacencies = 0;
+    let check_one = |direction, flag| {
+        if find_type_in_direction(ctx, atom, direction, flags) {
+            flag
+        } else {
+            0
+        }
+    };
+
+    for &dir in &[SOUTH, NORTH, EAST, WEST] {
+        adjacencies |= check_one(dir, 1 << dir);
+    }
+
+    if adjacencies & N_NORTH != 0 {
+        if adjacencies & N_WEST != 0 {
+            adjacencies |= check_one(NORTHWEST, N_NORTHWEST);
+        }
+        if adjacencies & N_EAST != 0 {
+            adjacencies |= check_one(NORTHEAST, N_NORTHEAST);
+        }
+    }
+    if adjacencies & N_SOUTH != 0 {
+        if adjacencies & N_WEST != 0 {
+            adjacencies |= check_one(SOUTHWEST, N_SOUTHWEST);
+        }
+        if adjacencies & N_EAST != 0 {
+            adjacencies |= check_one(SOUTHEAST, N_SOUTHEAST);
+        }
+    }
+
+    adjacencies
+}
+
+fn find_type_in_direction<'a>(ctx: Context, source: &Atom, direction: i32, flags: i32) -> bool {
+    let atom_list = match atoms_in_direction(ctx, source, direction) {
+        Some(list) => list,
+        None => return flags & SMOOTH_BORDER != 0,
+    };
+    match source.get_var("canSmoothWith", ctx.objtree) {
+        &Constant::List(ref elements) => if flags & SMOOTH_MORE != 0 {
+            // smooth with canSmoothWith + subtypes
+            for atom in atom_list {
+                let mut path = atom.path();
+                while !path.is_empty() {
+                    if smoothlist_contains(elements, path) {
+                        return true;
+                    }
+                    path = &path[..path.rfind("/").unwrap()];
+                }
+            }
+        } else {
+            // smooth only with exact types in canSmoothWith
+            for atom in atom_list {
+                if smoothlist_contains(elements, atom.path()) {
+                    return true;
+                }
+            }
+        },
+        _ => {
+            // smooth only with the same type
+            for atom in atom_list {
+                if atom.path() == source.path() {
+                    return true;
+                }
+            }
+        },
+    }
+    false
+}
+
+fn smoothlist_contains(list: &[(Constant, Option<Constant>)], desired: &str) -> bool {
+    for &(ref key, _) in list {
+        // TODO: be more specific than to_string
+        if key.to_string() == desired {
+            return true;
+        }
+    }
+    false
+}
+
+fn cardinal_smooth<'a>(output: &mut Vec<Atom<'a>>, ctx: Context<'a>, source: &Atom<'a>, adjacencies: i32) {
+    for &(what, f1, n1, f2, n2, f3) in &[
+        ("1", N_NORTH, "n", N_WEST, "w", N_NORTHWEST),
+        ("2", N_NORTH, "n", N_EAST, "e", N_NORTHEAST),
+        ("3", N_SOUTH, "s", N_WEST, "w", N_SOUTHWEST),
+        ("4", N_SOUTH, "s", N_EAST, "e", N_SOUTHEAST),
+    ] {
+        let name = if (adjacencies & f1 != 0) && (adjacencies & f2 != 0) {
+            if (adjacencies & f3) != 0 {
+                format!("{}-f", what)
+            } else {
+                format!("{}-{}{}", what, n1, n2)
+            }
+        } else if adjacencies & f1 != 0 {
+            format!("{}-{}", what, n1)
+        } else if adjacencies & f2 != 0 {
+            format!("{}-{}", what, n2)
+        } else {
+            format!("{}-i", what)
+        };
+
+        let mut copy = source.clone();
+        copy.set_var("icon_state", Constant::string(name));
+        if let Some(icon) = source.get_var_notnull("smooth_icon", ctx.objtree) {
+            copy.set_var("icon", icon.clone());
+        }
+        output.push(copy);
+    }
+}
+
+fn diagonal_smooth<'a>(output: &mut Vec<Atom<'a>>, ctx: Context<'a>, source: &Atom<'a>, adjacencies: i32) {
+    let presets = if adjacencies == N_NORTH | N_WEST {
+        ["d-se", "d-se-0"]
+    } else if adjacencies == N_NORTH | N_EAST {
+        ["d-sw", "d-sw-0"]
+    } else if adjacencies == N_SOUTH | N_WEST {
+        ["d-ne", "d-ne-0"]
+    } else if adjacencies == N_SOUTH | N_EAST {
+        ["d-nw", "d-nw-0"]
+    } else if adjacencies == N_NORTH | N_WEST | N_NORTHWEST {
+        ["d-se", "d-se-1"]
+    } else if adjacencies == N_NORTH | N_EAST | N_NORTHEAST {
+        ["d-sw", "d-sw-1"]
+    } else if adjacencies == N_SOUTH | N_WEST | N_SOUTHWEST {
+        ["d-ne", "d-ne-1"]
+    } else if adjacencies == N_SOUTH | N_EAST | N_SOUTHEAST {
+        ["d-nw", "d-nw-1"]
+    } else {
+        return cardinal_smooth(output, ctx, source, adjacencies);
+    };
+
+    // turf underneath
+    if source.istype("/turf/closed/wall/") {
+        // BYOND memes
+        if source
+            .get_var("fixed_underlay", ctx.objtree)
+            .index(&Constant::string("space"))
+            .is_some()
+        {
+            output.push(Atom::from_type(ctx.objtree, "/turf/open/space/basic", source.loc).unwrap());
+        } else {
+            let dir = flip(reverse_ndir(adjacencies));
+            let mut needs_plating = true;
+            // check direct, then 45deg left, then 45deg right
+            'dirs: for &each in &[dir, left_45(dir), right_45(dir)] {
+                if let Some(atom_list) = atoms_in_direction(ctx, source, each) {
+                    for mut atom in atom_list {
+                        if atom.istype("/turf/open/") {
+                            atom.loc = source.loc;
+                            output.push(atom);
+                            needs_plating = false;
+                            break 'dirs;
+                        }
+                    }
+                }
+            }
+            if needs_plating {
+                output.push(Atom::from_type(ctx.objtree, "/turf/open/floor/plating", source.loc).unwrap());
+            }
+        }
+    }
+
+    // the diagonal overlay
+    for &each in presets.iter() {
+        let mut copy = source.clone();
+        copy.set_var("icon_state", Constant::string(each));
+        if let Some(icon) = source.get_var_notnull("smooth_icon", ctx.objtree) {
+            copy.set_var("icon", icon.clone());
+        }
+        output.push(copy);
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Bitmask smoothing
+
+// bits of the junction, as in the `smoothing_junction` var
+const J_NORTH: i32 = 1;
+const J_SOUTH: i32 = 2;
+const J_EAST: i32 = 4;
+const J_WEST: i32 = 8;
+const J_NORTHEAST: i32 = 16;
+const J_SOUTHEAST: i32 = 32;
+const J_SOUTHWEST: i32 = 64;
+const J_NORTHWEST: i32 = 128;
+
+const SMOOTH_BITMASK: i32 = 2;  // smooth using junction bitmask states
+const SMOOTH_BITMASK_BORDER: i32 = 8;  // smooth with the borders of the map
+
+/// Junction bitmask smoothing driven by the `smoothing_flags`,
+/// `smoothing_groups`, and `canSmoothWith` vars.
+///
+/// Atoms smooth with neighbors having any of their `canSmoothWith` groups
+/// in their `smoothing_groups`, or with the same type if `canSmoothWith` is
+/// null. The icon state becomes `"{base_icon_state}-{junction}"`.
+#[derive(Default)]
+pub struct BitmaskSmoothing;
+impl RenderPass for BitmaskSmoothing {
+    fn smooth<'a>(&self,
+        atom: &Atom<'a>,
+        ctx: Context<'a>,
+        output: &mut Vec<Atom<'a>>,
+    ) -> bool {
+        use dmi::*;
+
+        let flags = atom.get_var("smoothing_flags", ctx.objtree).to_int().unwrap_or(0);
+        if flags & SMOOTH_BITMASK == 0 {
+            return false;
+        }
+
+        let can_smooth_with = smoothing_groups(atom.get_var("canSmoothWith", ctx.objtree));
+        let smooths_with = |direction| match atoms_in_direction(ctx, atom, direction) {
+            Some(list) => list.iter().any(|other| match can_smooth_with {
+                Some(ref wanted) => smoothing_groups(other.get_var("smoothing_groups", ctx.objtree))
+                    .map_or(false, |groups| groups.iter().any(|g| wanted.contains(g))),
+                None => other.path() == atom.path(),
+            }),
+            None => flags & SMOOTH_BITMASK_BORDER != 0,
+        };
+
+        let mut junction = 0;
+        for &(dir, bit) in &[(NORTH, J_NORTH), (SOUTH, J_SOUTH), (EAST, J_EAST), (WEST, J_WEST)] {
+            if smooths_with(dir) {
+                junction |= bit;
+            }
+        }
+        // diagonals only count when both adjacent cardinals do
+        for &(dir, bit, first, second) in &[
+            (NORTHEAST, J_NORTHEAST, J_NORTH, J_EAST),
+            (SOUTHEAST, J_SOUTHEAST, J_SOUTH, J_EAST),
+            (SOUTHWEST, J_SOUTHWEST, J_SOUTH, J_WEST),
+            (NORTHWEST, J_NORTHWEST, J_NORTH, J_WEST),
+        ] {
+            if junction & first != 0 && junction & second != 0 && smooths_with(dir) {
+                junction |= bit;
+            }
+        }
+
+        let base = match atom.get_var_notnull("base_icon_state", ctx.objtree)
+            .or_else(|| atom.get_var_notnull("icon_state", ctx.objtree))
+        {
+            Some(&Constant::String(ref base)) => base.clone(),
+            _ => String::new(),
+        };
+        let mut copy = atom.clone();
+        copy.set_var("icon_state", Constant::string(format!("{}-{}", base, junction)));
+        output.push(copy);
+        true
+    }
+}
+
+/// Read a list of smoothing groups, given as a list or a comma-separated string.
+fn smoothing_groups(value: &Constant) -> Option<Vec<String>> {
+    match *value {
+        Constant::List(ref elements) => Some(elements.iter().map(|&(ref key, _)| match *key {
+            Constant::String(ref s) => s.clone(),
+            ref other => other.to_string(),
+        }).collect()),
+        Constant::String(ref s) => Some(s.split(',')
+            .map(|group| group.trim())
+            .filter(|group| !group.is_empty())
+            .map(|group| group.to_owned())
+            .collect()),
+        _ => None,
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Helpers
+
+/// Get the atoms on the adjacent tile in the given direction, or `None` if
+/// that would be beyond the edge of the map.
+fn atoms_in_direction<'a>(ctx: Context<'a>, source: &Atom, direction: i32) -> Option<Vec<Atom<'a>>> {
+    let new_loc = source.loc.step(direction);
+    let (dim_y, dim_x) = ctx.grid.dim();
+    let (x, y) = new_loc.to_raw((dim_x, dim_y))?;
+
+    // TODO: make this not call get_atom_list way too many times
+    Some(get_atom_list(
+        ctx.objtree,
+        &ctx.map.dictionary[&ctx.grid[ndarray::Dim([y, x])]],
+        new_loc,
+        ctx.render_passes,
+    ))
+}
+
+fn flip(direction: i32) -> i32 {
+    use dmi::*;
+    match direction {
+        0 => 0,
+        SOUTH => NORTH,
+        NORTH => SOUTH,
+        EAST => WEST,
+        WEST => EAST,
+        SOUTHEAST => NORTHWEST,
+        SOUTHWEST => NORTHEAST,
+        NORTHEAST => SOUTHWEST,
+        NORTHWEST => SOUTHEAST,
+        _ => panic!(),
+    }
+}
+
+fn reverse_ndir(ndir: i32) -> i32 {
+    use dmi::*;
+    const NW1: i32 = N_NORTH | N_WEST;
+    const NW2: i32 = NW1 | N_NORTHWEST;
+    const NE1: i32 = N_NORTH | N_EAST;
+    const NE2: i32 = NE1 | N_NORTHEAST;
+    const SW1: i32 = N_SOUTH | N_WEST;
+    const SW2: i32 = SW1 | N_SOUTHWEST;
+    const SE1: i32 = N_SOUTH | N_EAST;
+    const SE2: i32 = SE1 | N_SOUTHEAST;
+
+    match ndir {
+        N_NORTH => NORTH,
+        N_SOUTH => SOUTH,
+        N_WEST => WEST,
+        N_EAST => EAST,
+        N_SOUTHEAST | SE1 | SE2 => SOUTHEAST,
+        N_SOUTHWEST | SW1 | SW2 => SOUTHWEST,
+        N_NORTHEAST | NE1 | NE2 => NORTHEAST,
+        N_NORTHWEST | NW1 | NW2 => NORTHWEST,
+        _ => panic!(),
+    }
+}
+
+fn left_45(dir: i32) -> i32 {
+    use dmi::*;
+    match dir {
+        NORTH => NORTHWEST,
+        NORTHEAST => NORTH,
+        EAST => NORTHEAST,
+        SOUTHEAST => EAST,
+        SOUTH => SOUTHEAST,
+        SOUTHWEST => SOUTH,
+        WEST => SOUTHWEST,
+        NORTHWEST => WEST,
+        e => e,
+    }
+}
+
+fn right_45(dir: i32) -> i32 {
+    use dmi::*;
+    match dir {
+        NORTH => NORTHEAST,
+        NORTHEAST => EAST,
+        EAST => SOUTHEAST,
+        SOUTHEAST => SOUTH,
+        SOUTH => SOUTHWEST,
+        SOUTHWEST => WEST,
+        WEST => NORTHWEST,
+        NORTHWEST => NORTH,
+        e => e,
+    }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use coords::Coord2;
 
 #[derive(Default)]
 pub struct Spawners;
@@ -54,16 +55,16 @@ impl RenderPass for GravityGen {
         }
 
         for &(count, x, y) in &[
-            (1, 1, -2),
-            (2, 0, -2),
-            (3, -1, -2),
-            (4, 1, -1),
-            (5, 0, -1),
-            (6, -1, -1),
+            (1, 1, 2),
+            (2, 0, 2),
+            (3, -1, 2),
+            (4, 1, 1),
+            (5, 0, 1),
+            (6, -1, 1),
             (7, 1, 0),
             (9, -1, 0),
         ] {
-            let new_loc = ((atom.loc.0 as i32 + x) as u32, (atom.loc.1 as i32 + y) as u32);
+            let new_loc = Coord2::new(atom.loc.x + x, atom.loc.y + y);
             let mut new_atom = Atom::from_type(objtree, "/obj/machinery/gravity_generator/part", new_loc).unwrap();
             new_atom.set_var("icon_state", Constant::string(format!("on_{}", count)));
             if count <= 3 {
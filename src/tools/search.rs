@@ -0,0 +1,185 @@
+//! Searching maps for tiles matching type and var constraints.
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use dm::objtree::{ObjectTree, subpath};
+use dm::constants::Constant;
+use dmm::{self, Map, Prefab};
+use minimap::GetVar;
+use coords::Coord3;
+
+/// A comparison operator in a var constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn as_str(self) -> &'static str {
+        match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+/// A constraint on the value of a var, such as `pixel_x != 0`.
+#[derive(Debug, Clone)]
+pub struct VarConstraint {
+    pub var: String,
+    pub op: Op,
+    pub value: Constant,
+}
+
+impl VarConstraint {
+    /// Check whether the given value satisfies this constraint.
+    ///
+    /// Numbers are compared numerically. Other values only support equality.
+    pub fn test(&self, value: &Constant) -> bool {
+        if let (Some(lhs), Some(rhs)) = (value.to_float(), self.value.to_float()) {
+            return match self.op {
+                Op::Eq => lhs == rhs,
+                Op::Ne => lhs != rhs,
+                Op::Lt => lhs < rhs,
+                Op::Le => lhs <= rhs,
+                Op::Gt => lhs > rhs,
+                Op::Ge => lhs >= rhs,
+            };
+        }
+        match self.op {
+            Op::Eq => *value == self.value,
+            Op::Ne => *value != self.value,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for VarConstraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        // two-character operators must be tried first
+        const OPS: &[(&str, Op)] = &[
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+            ("=", Op::Eq),
+        ];
+        // the var name is read first, so that an operator inside the value
+        // is not taken for the comparison
+        let text = s.trim_left();
+        let len = text.find(|c: char| c != '_' && !c.is_alphanumeric()).unwrap_or(text.len());
+        let var = &text[..len];
+        if var.is_empty() {
+            return Err(format!("no var name in {:?}", s));
+        }
+        let rest = text[len..].trim_left();
+        let (op_text, op) = match OPS.iter().find(|&&(op_text, _)| rest.starts_with(op_text)) {
+            Some(&found) => found,
+            None => return Err(format!("no comparison operator after {:?} in {:?}", var, s)),
+        };
+        let value = rest[op_text.len()..].trim();
+        let value = dmm::parse_constant(Default::default(), value.as_bytes().to_owned())
+            .map_err(|e| e.description().to_owned())?;
+        Ok(VarConstraint {
+            var: var.to_owned(),
+            op,
+            value,
+        })
+    }
+}
+
+impl fmt::Display for VarConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.var, self.op.as_str(), self.value)
+    }
+}
+
+/// A query against the contents of map tiles.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// The type to search for. Subtypes also match unless `exact` is set.
+    pub path: Option<String>,
+    pub exact: bool,
+    /// Constraints which must all be satisfied by a matching prefab.
+    pub constraints: Vec<VarConstraint>,
+}
+
+impl Query {
+    /// Check whether a single prefab matches this query.
+    ///
+    /// Subtyping follows `parent_type` in the object tree, and unmodified
+    /// vars take their values from the type's declaration.
+    pub fn matches(&self, objtree: &ObjectTree, prefab: &Prefab) -> bool {
+        self.matches_grouped(&self.constraints_by_var(), objtree, prefab)
+    }
+
+    /// Group the constraints by var, so that each var is looked up once.
+    fn constraints_by_var(&self) -> BTreeMap<&str, Vec<&VarConstraint>> {
+        let mut grouped: BTreeMap<&str, Vec<&VarConstraint>> = BTreeMap::new();
+        for constraint in self.constraints.iter() {
+            grouped.entry(&constraint.var).or_insert_with(Vec::new).push(constraint);
+        }
+        grouped
+    }
+
+    fn matches_grouped(&self, grouped: &BTreeMap<&str, Vec<&VarConstraint>>, objtree: &ObjectTree, prefab: &Prefab) -> bool {
+        if let Some(ref path) = self.path {
+            if self.exact {
+                if prefab.path != *path {
+                    return false;
+                }
+            } else {
+                let is_subtype = match (objtree.find(&prefab.path), objtree.find(path)) {
+                    (Some(ty), Some(parent)) => ty.is_subtype_of(&parent),
+                    // fall back to path-based subtyping for unknown types
+                    _ => subpath(&prefab.path, path),
+                };
+                if !is_subtype {
+                    return false;
+                }
+            }
+        }
+
+        grouped.iter().all(|(var, constraints)| {
+            let value = prefab.get_var(var, objtree);
+            constraints.iter().all(|c| c.test(value))
+        })
+    }
+}
+
+/// Find all tiles containing a prefab matching the query.
+///
+/// Returns game coordinates, ordered by z-level, then by row from the bottom.
+pub fn find(objtree: &ObjectTree, map: &Map, query: &Query) -> Vec<Coord3> {
+    let grouped = query.constraints_by_var();
+    let matching_keys: HashSet<_> = map.dictionary.iter()
+        .filter(|&(_, prefabs)| prefabs.iter().any(|fab| query.matches_grouped(&grouped, objtree, fab)))
+        .map(|(&key, _)| key)
+        .collect();
+
+    let mut result = Vec::new();
+    if matching_keys.is_empty() {
+        return result;
+    }
+    for ((z, y, x), key) in map.grid.indexed_iter() {
+        if matching_keys.contains(key) {
+            result.push(map.zero_to_one((x, y, z)));
+        }
+    }
+    result.sort_by_key(|c| (c.z, c.y, c.x));
+    result
+}
@@ -0,0 +1,54 @@
+extern crate dreammaker as dm;
+extern crate dmm_tools;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::constants::Constant;
+use dmm_tools::dmm::{Map, Prefab};
+use dmm_tools::census::Census;
+
+fn cable(icon_state: &str) -> Prefab {
+    let mut fab = Prefab::from_path("/obj/structure/cable/yellow");
+    fab.vars.insert("icon_state".to_owned(), Constant::string(icon_state));
+    fab
+}
+
+#[test]
+fn census() {
+    let code = r##"
+/area/hallway
+/turf/floor
+/obj/structure/cable
+    icon_state = "0-1"
+/obj/structure/cable/yellow
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    context.assert_success();
+
+    // a north-south run of cable through a 1x3 strip
+    let mut map = Map::new(1, 3, 1, "/turf/floor".to_owned(), "/area/hallway".to_owned());
+    {
+        let tile = map.dictionary.values_mut().next().unwrap();
+        tile.push(cable("1-2"));
+    }
+    let census = Census::of_z_level(&tree, &map, 0);
+    assert_eq!(census.tiles, 3);
+    assert_eq!(census.areas["/area/hallway"], 3);
+    assert_eq!(census.types["/turf/floor"], 3);
+    assert_eq!(census.cables, 3);
+    assert_eq!(census.powernets, 1);
+
+    let grouped = census.grouped(&tree);
+    assert_eq!(grouped["/obj/structure/cable"], 3);
+    assert_eq!(grouped["/obj/structure"], 3);
+    assert_eq!(grouped["/atom"], 9);
+
+    let mut total = Census::default();
+    total.merge(&census);
+    total.merge(&census);
+    assert_eq!(total.tiles, 6);
+    assert_eq!(total.powernets, 2);
+}
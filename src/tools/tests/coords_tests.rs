@@ -0,0 +1,63 @@
+extern crate dreammaker as dm;
+extern crate dmm_tools;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::constants::Constant;
+use dmm_tools::coords::*;
+use dmm_tools::dmm::Prefab;
+
+#[test]
+fn raw_round_trip() {
+    // a map 5 wide, 4 tall, and 2 deep
+    let dim = (5, 4, 2);
+    assert_eq!(Coord3::from_raw((0, 0, 0), dim.1), Coord3::new(1, 4, 1));
+    assert_eq!(Coord3::from_raw((4, 3, 1), dim.1), Coord3::new(5, 1, 2));
+    assert_eq!(Coord3::new(1, 4, 1).to_raw(dim), Some((0, 0, 0)));
+    assert_eq!(Coord3::new(2, 1, 2).to_raw(dim), Some((1, 3, 1)));
+
+    assert_eq!(Coord3::new(0, 1, 1).to_raw(dim), None);
+    assert_eq!(Coord3::new(1, 5, 1).to_raw(dim), None);
+    assert_eq!(Coord3::new(1, 1, 3).to_raw(dim), None);
+
+    for &(x, y) in &[(0, 0), (3, 1), (4, 3)] {
+        assert_eq!(Coord2::from_raw((x, y), dim.1).to_raw((dim.0, dim.1)), Some((x, y)));
+    }
+}
+
+#[test]
+fn steps_and_pixels() {
+    let origin = Coord2::new(3, 3);
+    assert_eq!(origin.step(1), Coord2::new(3, 4));  // NORTH
+    assert_eq!(origin.step(10), Coord2::new(2, 2));  // SOUTHWEST
+    assert_eq!(origin.step(0), origin);
+    assert_eq!(origin.z(2).xy(), origin);
+
+    let top_left = Coord2::new(2, 5);
+    assert_eq!(top_left.to_pixel(top_left), (0, 0));
+    assert_eq!(origin.to_pixel(top_left), (32, 64));
+
+    // a 64px icon nudged up and right extends above its tile
+    let offset = PixelOffset { x: 4, y: 8, plane: 0 };
+    assert_eq!(offset.draw_position(origin, top_left, 64), (36, 24));
+}
+
+#[test]
+fn pixel_offset_vars() {
+    let code = r##"
+/obj/sign
+    pixel_x = 2
+    pixel_w = 3
+    pixel_y = -4
+    plane = -2
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    context.assert_success();
+
+    let fab = Prefab::from_path("/obj/sign").with_var("step_y", Constant::Int(5));
+    assert_eq!(PixelOffset::of(&tree, &fab), PixelOffset { x: 5, y: 1, plane: -2 });
+    assert_eq!(Coord3::new(1, 2, 3).to_string(), "(1, 2, 3)");
+}
@@ -0,0 +1,155 @@
+extern crate dmm_tools;
+extern crate inflate;
+
+use dmm_tools::dmi::*;
+
+const DESCRIPTION: &str = "# BEGIN DMI
+version = 4.0
+	width = 2
+	height = 2
+state = \"still\"
+	dirs = 1
+	frames = 1
+state = \"blink\"
+	dirs = 4
+	frames = 3
+	delay = 1,2,3
+	rewind = 1
+# END DMI
+";
+
+fn icon() -> IconFile {
+    IconFile {
+        metadata: Metadata::from_str(DESCRIPTION),
+        // 1 + 4 * 3 = 13 icons, in rows of 4
+        image: Image::new_rgba(8, 8),
+    }
+}
+
+fn count(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack.windows(needle.len()).filter(|w| *w == needle).count()
+}
+
+#[test]
+fn frame_sequence() {
+    let icon = icon();
+    assert_eq!(icon.frames_of("still", SOUTH).unwrap(), vec![((0, 0, 2, 2), 1.)]);
+
+    // frame-major: each frame holds every direction
+    let frames = icon.frames_of("blink", EAST).unwrap();
+    let rects: Vec<_> = frames.iter().map(|&(rect, _)| rect).collect();
+    let delays: Vec<_> = frames.iter().map(|&(_, delay)| delay).collect();
+    assert_eq!(rects, vec![(6, 0, 2, 2), (6, 2, 2, 2), (6, 4, 2, 2), (6, 2, 2, 2)]);
+    assert_eq!(delays, vec![1., 2., 3., 2.]);
+    assert!(icon.frames_of("missing", SOUTH).is_none());
+}
+
+#[test]
+fn apng_export() {
+    let icon = icon();
+    let mut output = Vec::new();
+    icon.write_apng(&mut output, "blink", NORTH).unwrap();
+
+    assert!(output.starts_with(b"\x89PNG\r\n\x1a\n"));
+    // four frames, looping forever
+    assert_eq!(count(&output, b"acTL\0\0\0\x04\0\0\0\0"), 1);
+    assert_eq!(count(&output, b"fcTL"), 4);
+    assert_eq!(count(&output, b"IDAT"), 1);
+    assert_eq!(count(&output, b"fdAT"), 3);
+    assert!(output.ends_with(b"IEND\xae\x42\x60\x82"));
+
+    assert!(icon.write_apng(&mut Vec::new(), "missing", NORTH).is_err());
+}
+
+/// Split a PNG stream into its chunks, skipping the signature.
+fn chunks(png: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut result = Vec::new();
+    let mut pos = 8;
+    while pos + 12 <= png.len() {
+        let len = png[pos..pos + 4].iter().fold(0, |acc, &b| (acc << 8) | b as usize);
+        result.push((&png[pos + 4..pos + 8], &png[pos + 8..pos + 8 + len]));
+        pos += 12 + len;
+    }
+    result
+}
+
+/// Decompress one frame's image data, checking that its rows are unfiltered.
+fn unfiltered_rgba(data: &[u8], width: usize) -> Vec<u8> {
+    let raw = inflate::inflate_bytes_zlib(data).unwrap();
+    let mut result = Vec::new();
+    for row in raw.chunks(1 + width * 4) {
+        assert_eq!(row[0], 0);
+        result.extend_from_slice(&row[1..]);
+    }
+    result
+}
+
+#[test]
+fn apng_round_trip() {
+    let mut icon = icon();
+    for y in 0..8 {
+        for x in 0..8 {
+            icon.image.data[(y, x, 0)] = (y * 8 + x) as u8;
+            icon.image.data[(y, x, 3)] = 255;
+        }
+    }
+
+    let mut output = Vec::new();
+    icon.write_apng(&mut output, "blink", NORTH).unwrap();
+    let chunks = chunks(&output);
+    let frames: Vec<&[u8]> = chunks.iter()
+        .filter_map(|&(kind, data)| match kind {
+            b"IDAT" => Some(data),
+            b"fdAT" => Some(&data[4..]),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(frames.len(), 4);
+
+    for (data, &(rect, _)) in frames.iter().zip(icon.frames_of("blink", NORTH).unwrap().iter()) {
+        let decoded = unfiltered_rgba(data, 2);
+        let expected: Vec<u8> = icon.image.crop(rect).data.iter().cloned().collect();
+        assert_eq!(decoded, expected);
+    }
+}
+
+fn solid(r: u8, g: u8, b: u8) -> Image {
+    Image::from_rgba_bytes(2, 2, [r, g, b, 255].iter().cloned().cycle().take(16).collect())
+}
+
+#[test]
+fn build_and_write() {
+    let mut builder = IconBuilder::new(2, 2);
+    builder.add_state(NewState::still("red", solid(255, 0, 0))).unwrap();
+    builder.add_state(NewState {
+        name: "spin".to_owned(),
+        dirs: Dirs::Four,
+        frames: vec![
+            vec![solid(1, 0, 0), solid(2, 0, 0), solid(3, 0, 0), solid(4, 0, 0)],
+            vec![solid(5, 0, 0), solid(6, 0, 0), solid(7, 0, 0), solid(8, 0, 0)],
+        ],
+        delays: Some(vec![1., 2.5]),
+        loop_: 3,
+        rewind: false,
+        movement: false,
+    }).unwrap();
+    assert!(builder.add_state(NewState::still("bad", Image::new_rgba(4, 4))).is_err());
+    assert!(builder.add_state(NewState::still("quo\"te", solid(0, 0, 0))).is_err());
+
+    let icon = builder.build();
+    assert_eq!(icon.image.width, 3 * 2);
+    assert_eq!(icon.image.height, 3 * 2);
+    let description = icon.metadata.to_string();
+    assert!(description.starts_with("# BEGIN DMI\nversion = 4.0\n"));
+    assert!(description.contains("state = \"spin\"\n\tdirs = 4\n\tframes = 2\n\tdelay = 1,2.5\n\tloop = 3\n"));
+
+    let path = std::env::temp_dir().join("spacemandmm_dmi_tests.dmi");
+    icon.to_file(&path).unwrap();
+    let loaded = IconFile::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.metadata.to_string(), description);
+    assert_eq!(loaded.image.data, icon.image.data);
+    let (x, y, _, _) = loaded.frames_of("spin", WEST).unwrap()[1].0;
+    assert_eq!(loaded.image.data[(y as usize, x as usize, 0)], 8);
+}
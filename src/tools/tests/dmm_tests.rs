@@ -0,0 +1,106 @@
+extern crate dreammaker as dm;
+extern crate dmm_tools;
+
+use dm::constants::Constant;
+use dmm_tools::coords::{Coord2, Coord3};
+use dmm_tools::dmm::{Map, Prefab};
+
+fn tile(path: &str) -> Vec<Prefab> {
+    vec![Prefab::from_path(path), Prefab::from_path("/area/space")]
+}
+
+fn path_at(map: &Map, coord: Coord3) -> &str {
+    let (x, y, z) = map.one_to_zero(coord);
+    &map.dictionary[&map.grid[(z, y, x)]][0].path
+}
+
+#[test]
+fn z_levels() {
+    let mut map = Map::new(2, 2, 1, "/turf/one".to_owned(), "/area/space".to_owned());
+    map.insert_z_level(1, tile("/turf/two")).unwrap();
+    map.insert_z_level(0, tile("/turf/zero")).unwrap();
+    assert_eq!(map.dim_xyz(), (2, 2, 3));
+    assert_eq!(path_at(&map, Coord3::new(1, 1, 1)), "/turf/zero");
+    assert_eq!(path_at(&map, Coord3::new(2, 2, 3)), "/turf/two");
+
+    map.reorder_z_levels(&[2, 0, 1]).unwrap();
+    assert_eq!(path_at(&map, Coord3::new(1, 1, 1)), "/turf/two");
+    assert_eq!(path_at(&map, Coord3::new(1, 1, 2)), "/turf/zero");
+    assert_eq!(path_at(&map, Coord3::new(1, 1, 3)), "/turf/one");
+
+    map.remove_z_level(1).unwrap();
+    assert_eq!(map.dim_xyz(), (2, 2, 2));
+    assert_eq!(map.dictionary.len(), 2);
+    assert_eq!(path_at(&map, Coord3::new(1, 1, 2)), "/turf/one");
+
+    assert!(map.insert_z_level(3, tile("/turf/far")).is_err());
+    assert!(map.reorder_z_levels(&[0, 0]).is_err());
+    assert!(map.reorder_z_levels(&[1]).is_err());
+    assert!(map.remove_z_level(2).is_err());
+    map.remove_z_level(0).unwrap();
+    assert_eq!(map.remove_z_level(0), Err("cannot remove the only z-level".to_owned()));
+    assert_eq!(map.dim_xyz(), (2, 2, 1));
+}
+
+#[test]
+fn resize_and_crop() {
+    let mut map = Map::new(2, 2, 1, "/turf/old".to_owned(), "/area/space".to_owned());
+    map.resize(3, 4, tile("/turf/new")).unwrap();
+    assert_eq!(map.dim_xyz(), (3, 4, 1));
+    assert_eq!(path_at(&map, Coord3::new(1, 1, 1)), "/turf/old");
+    assert_eq!(path_at(&map, Coord3::new(2, 2, 1)), "/turf/old");
+    assert_eq!(path_at(&map, Coord3::new(3, 1, 1)), "/turf/new");
+    assert_eq!(path_at(&map, Coord3::new(1, 3, 1)), "/turf/new");
+
+    map.crop(Coord2::new(2, 2), Coord2::new(3, 3)).unwrap();
+    assert_eq!(map.dim_xyz(), (2, 2, 1));
+    assert_eq!(path_at(&map, Coord3::new(1, 1, 1)), "/turf/old");
+    assert_eq!(path_at(&map, Coord3::new(2, 1, 1)), "/turf/new");
+    assert_eq!(path_at(&map, Coord3::new(1, 2, 1)), "/turf/new");
+
+    assert!(map.resize(0, 2, tile("/turf/new")).is_err());
+    assert!(map.crop(Coord2::new(1, 1), Coord2::new(3, 1)).is_err());
+    assert!(map.crop(Coord2::new(2, 1), Coord2::new(1, 1)).is_err());
+    assert_eq!(map.dim_xyz(), (2, 2, 1));
+}
+
+#[test]
+fn build_and_save() {
+    let mut map = Map::new(5, 4, 1, "/turf/floor".to_owned(), "/area/space".to_owned());
+    map.fill(Coord3::new(1, 1, 1), Coord3::new(5, 1, 1), vec![Prefab::from_path("/turf/wall"), Prefab::from_path("/area/space")]).unwrap();
+    map.stamp_rect(Coord3::new(2, 2, 1), Coord3::new(3, 3, 1), Prefab::from_path("/area/room")).unwrap();
+    map.stamp(Coord3::new(2, 2, 1), Prefab::from_path("/obj/table")).unwrap();
+    map.stamp(Coord3::new(2, 2, 1), Prefab::from_path("/obj/item").with_var("name", Constant::string("thing"))).unwrap();
+    map.remove_unused_keys();
+
+    assert_eq!(path_at(&map, Coord3::new(4, 1, 1)), "/turf/wall");
+    let tile: Vec<String> = map.tile(Coord3::new(2, 2, 1)).iter().map(|fab| fab.to_string()).collect();
+    assert_eq!(tile, vec![
+        "/obj/table".to_owned(),
+        "/obj/item {name = \"thing\"}".to_owned(),
+        "/turf/floor".to_owned(),
+        "/area/room".to_owned(),
+    ]);
+    assert_eq!(map.tile(Coord3::new(3, 3, 1))[1].path, "/area/room");
+    assert_eq!(map.tile(Coord3::new(4, 4, 1))[1].path, "/area/space");
+    assert_eq!(map.dictionary.len(), 4);
+
+    assert!(map.set_tile(Coord3::new(6, 1, 1), vec![Prefab::from_path("/turf/wall")]).is_err());
+    assert!(map.stamp(Coord3::new(1, 1, 0), Prefab::from_path("/obj/table")).is_err());
+    assert!(map.fill(Coord3::new(1, 1, 1), Coord3::new(5, 5, 1), vec![Prefab::from_path("/turf/wall")]).is_err());
+    assert!(map.stamp_rect(Coord3::new(5, 4, 1), Coord3::new(6, 4, 1), Prefab::from_path("/obj/table")).is_err());
+    assert_eq!(map.dictionary.len(), 4);
+
+    let mut saved = Vec::new();
+    map.to_writer(&mut saved).unwrap();
+    let path = std::env::temp_dir().join("dmm_tests_build_and_save.dmm");
+    std::fs::write(&path, &saved).unwrap();
+    let loaded = Map::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.dim_xyz(), (5, 4, 1));
+    for y in 1..5 {
+        for x in 1..6 {
+            assert_eq!(loaded.tile(Coord3::new(x, y, 1)), map.tile(Coord3::new(x, y, 1)));
+        }
+    }
+}
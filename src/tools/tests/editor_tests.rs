@@ -0,0 +1,77 @@
+extern crate dmm_tools;
+
+use dmm_tools::coords::Coord3;
+use dmm_tools::dmm::{Map, Prefab};
+use dmm_tools::editor::{Editor, Region};
+
+fn paths(editor: &Editor, coord: Coord3) -> Vec<&str> {
+    editor.tile(coord).iter().map(|fab| fab.path.as_str()).collect()
+}
+
+#[test]
+fn place_and_undo() {
+    let map = Map::new(3, 3, 1, "/turf/space".to_owned(), "/area/space".to_owned());
+    let mut editor = Editor::new(map);
+    let center = Coord3::new(2, 2, 1);
+
+    assert_eq!(editor.place(center, Prefab::from_path("/obj/table")), Ok(true));
+    assert_eq!(editor.place(center, Prefab::from_path("/obj/item/pen")), Ok(true));
+    assert_eq!(paths(&editor, center), vec!["/obj/table", "/obj/item/pen", "/turf/space", "/area/space"]);
+    assert_eq!(editor.undo_history(), vec!["place /obj/table at (2, 2, 1)", "place /obj/item/pen at (2, 2, 1)"]);
+
+    assert!(editor.undo());
+    assert_eq!(paths(&editor, center), vec!["/obj/table", "/turf/space", "/area/space"]);
+    assert!(editor.redo());
+    assert_eq!(paths(&editor, center).len(), 4);
+    assert!(!editor.redo());
+
+    assert_eq!(editor.remove(center, &Prefab::from_path("/obj/table")), Ok(true));
+    assert_eq!(paths(&editor, center), vec!["/obj/item/pen", "/turf/space", "/area/space"]);
+    // a new edit discards the redo history
+    assert!(editor.undo());
+    assert_eq!(editor.set_turf(Region::tile(Coord3::new(1, 3, 1)), Prefab::from_path("/turf/floor")), Ok(true));
+    assert!(!editor.redo());
+}
+
+#[test]
+fn out_of_bounds() {
+    let map = Map::new(3, 3, 1, "/turf/space".to_owned(), "/area/space".to_owned());
+    let mut editor = Editor::new(map);
+
+    assert!(editor.place(Coord3::new(0, 1, 1), Prefab::from_path("/obj/table")).is_err());
+    assert!(editor.place(Coord3::new(1, 1, 2), Prefab::from_path("/obj/table")).is_err());
+    let floor = vec![Prefab::from_path("/turf/floor"), Prefab::from_path("/area/station")];
+    assert_eq!(
+        editor.fill(Region::new(Coord3::new(1, 1, 1), Coord3::new(4, 3, 1)), &floor),
+        Err("(4, 3, 1) is outside the 3x3x1 map".to_owned()));
+    assert!(editor.copy(Region::new(Coord3::new(3, 3, 1), Coord3::new(3, 4, 1))).is_err());
+    assert_eq!(
+        editor.set_turf(Region::tile(Coord3::new(1, 1, 1)), Prefab::from_path("/obj/table")),
+        Err("/obj/table is not a turf".to_owned()));
+    assert!(editor.undo_history().is_empty());
+    assert_eq!(paths(&editor, Coord3::new(1, 1, 1)), vec!["/turf/space", "/area/space"]);
+}
+
+#[test]
+fn fill_copy_paste() {
+    let map = Map::new(4, 4, 1, "/turf/space".to_owned(), "/area/space".to_owned());
+    let mut editor = Editor::new(map);
+
+    let floor = vec![Prefab::from_path("/turf/floor"), Prefab::from_path("/area/station")];
+    assert_eq!(editor.fill(Region::new(Coord3::new(2, 2, 1), Coord3::new(1, 1, 1)), &floor), Ok(true));
+    assert_eq!(editor.fill(Region::new(Coord3::new(1, 1, 1), Coord3::new(2, 2, 1)), &floor), Ok(false));
+
+    let clipboard = editor.copy(Region::new(Coord3::new(1, 1, 1), Coord3::new(2, 2, 1))).unwrap();
+    assert_eq!(clipboard.dim_xyz(), (2, 2, 1));
+    // pasting partially off the edge clips to the map
+    assert_eq!(editor.paste(Coord3::new(4, 4, 1), &clipboard), Ok(true));
+    assert_eq!(paths(&editor, Coord3::new(4, 4, 1)), vec!["/turf/floor", "/area/station"]);
+    assert_eq!(paths(&editor, Coord3::new(3, 3, 1)), vec!["/turf/space", "/area/space"]);
+    // tiles matching existing contents reuse their keys
+    assert_eq!(editor.map().dictionary.len(), 2);
+
+    assert!(editor.undo());
+    assert!(editor.undo());
+    let map = editor.finish();
+    assert_eq!(map.dictionary.len(), 1);
+}
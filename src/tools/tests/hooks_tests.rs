@@ -0,0 +1,57 @@
+extern crate dreammaker as dm;
+extern crate dmm_tools;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::constants::Constant;
+use dmm_tools::coords::Coord2;
+use dmm_tools::minimap::{Atom, GetVar};
+use dmm_tools::render_passes::RenderPass;
+use dmm_tools::render_passes::hooks::Hooks;
+
+#[test]
+fn type_hooks() {
+    let code = r##"
+/obj/machinery/light
+    icon_state = "tube"
+    var/on = 1
+
+/obj/machinery/light/broken
+    on = 0
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    context.assert_success();
+
+    let hooks = Hooks::new()
+        .on_early_filter("/obj/machinery/light/broken", |_, _| false)
+        .on_adjust_vars("/obj/machinery/light", |atom, objtree| {
+            if atom.get_var("on", objtree).to_bool() {
+                atom.set_var("icon_state", Constant::string("tube1"));
+            }
+        })
+        .on_overlays("/obj/machinery", |atom, _, _, overlays| {
+            let mut copy = atom.clone();
+            copy.set_var("icon_state", Constant::string("glow"));
+            overlays.push(copy);
+        });
+
+    let mut light = Atom::from_type(&tree, "/obj/machinery/light", Coord2::new(1, 1)).unwrap();
+    let broken = Atom::from_type(&tree, "/obj/machinery/light/broken", Coord2::new(1, 1)).unwrap();
+    let other = Atom::from_type(&tree, "/obj", Coord2::new(1, 1)).unwrap();
+
+    assert!(hooks.early_filter(&light, &tree));
+    assert!(!hooks.early_filter(&broken, &tree));
+    assert!(hooks.early_filter(&other, &tree));
+
+    hooks.adjust_vars(&mut light, &tree);
+    assert!(light.get_var("icon_state", &tree).eq_string("tube1"));
+
+    let (mut underlays, mut overlays) = (Vec::new(), Vec::new());
+    hooks.overlays(&mut light, &tree, &mut underlays, &mut overlays);
+    assert!(underlays.is_empty());
+    assert_eq!(overlays.len(), 1);
+    assert!(overlays[0].get_var("icon_state", &tree).eq_string("glow"));
+}
@@ -0,0 +1,49 @@
+extern crate dreammaker as dm;
+extern crate dmm_tools;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::constants::Constant;
+use dmm_tools::coords::Coord3;
+use dmm_tools::dmm::{Map, Prefab};
+use dmm_tools::lint::{validate, ProblemKind};
+
+#[test]
+fn validate_prefabs() {
+    let code = r##"
+/turf/floor
+    var/broken = 0
+    icon_state = "floor"
+
+/obj/item
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    context.assert_success();
+
+    let mut map = Map::new(2, 2, 1, "/turf/floor".to_owned(), "/area".to_owned());
+    {
+        let tile = map.dictionary.values_mut().next().unwrap();
+        tile[0].vars.insert("broken".to_owned(), Constant::Int(0));
+        tile[0].vars.insert("icon_state".to_owned(), Constant::string("floor_broken"));
+        tile[0].vars.insert("missing".to_owned(), Constant::Int(1));
+        tile.push(Prefab::from_path("/obj/item"));
+        tile.push(Prefab::from_path("/obj/item"));
+        tile.push(Prefab::from_path("/obj/nonexistent"));
+    }
+
+    let problems = validate(&tree, &map);
+    let kinds: Vec<_> = problems.iter().map(|p| p.kind.clone()).collect();
+    assert_eq!(kinds, vec![
+        ProblemKind::DefaultValue("broken".to_owned()),
+        ProblemKind::UnknownVar("missing".to_owned()),
+        ProblemKind::Duplicate,
+        ProblemKind::UnknownType,
+    ]);
+    for problem in problems.iter() {
+        assert_eq!(problem.coords, Coord3::new(1, 1, 1));
+        assert_eq!(problem.tiles, 4);
+    }
+}
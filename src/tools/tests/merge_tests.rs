@@ -0,0 +1,65 @@
+extern crate dmm_tools;
+
+use dmm_tools::coords::Coord3;
+use dmm_tools::dmm::{Map, Key, Prefab};
+use dmm_tools::merge::merge;
+
+fn with_tile(map: &Map, (x, y): (usize, usize), path: &str) -> Map {
+    let mut map = map.clone();
+    let tile = vec![
+        Prefab::from_path(path),
+        Prefab::from_path("/area/space"),
+    ];
+    let key = match map.dictionary.iter().find(|&(_, v)| *v == tile) {
+        Some((&k, _)) => k,
+        None => {
+            let k = map.dictionary.keys().next_back().unwrap().next();
+            map.dictionary.insert(k, tile);
+            k
+        }
+    };
+    map.grid[(0, y, x)] = key;
+    map
+}
+
+fn tile_path(map: &Map, (x, y): (usize, usize)) -> &str {
+    &map.dictionary[&map.grid[(0, y, x)]][0].path
+}
+
+#[test]
+fn non_overlapping_edits() {
+    let base = Map::new(3, 3, 1, "/turf/space".to_owned(), "/area/space".to_owned());
+    let ours = with_tile(&base, (0, 0), "/turf/floor");
+    let theirs = with_tile(&base, (2, 2), "/turf/wall");
+
+    let merged = merge(&base, &ours, &theirs).unwrap();
+    assert!(merged.is_clean());
+    assert_eq!(tile_path(&merged.map, (0, 0)), "/turf/floor");
+    assert_eq!(tile_path(&merged.map, (2, 2)), "/turf/wall");
+    assert_eq!(tile_path(&merged.map, (1, 1)), "/turf/space");
+    assert_eq!(merged.map.dictionary.len(), 3);
+    // keys from our side are preserved
+    assert_eq!(merged.map.grid[(0, 0, 0)], ours.grid[(0, 0, 0)]);
+    assert_eq!(merged.map.grid[(0, 1, 1)], Key::default());
+}
+
+#[test]
+fn overlapping_edits() {
+    let base = Map::new(2, 2, 1, "/turf/space".to_owned(), "/area/space".to_owned());
+    let ours = with_tile(&base, (1, 0), "/turf/floor");
+    let theirs = with_tile(&base, (1, 0), "/turf/wall");
+
+    let merged = merge(&base, &ours, &theirs).unwrap();
+    assert_eq!(merged.conflicts.len(), 1);
+    assert_eq!(merged.conflicts[0].coord, Coord3::new(2, 2, 1));
+    assert_eq!(tile_path(&merged.map, (1, 0)), "/turf/floor");
+}
+
+#[test]
+fn identical_edits() {
+    let base = Map::new(2, 2, 1, "/turf/space".to_owned(), "/area/space".to_owned());
+    let ours = with_tile(&base, (0, 1), "/turf/floor");
+    let merged = merge(&base, &ours, &ours).unwrap();
+    assert!(merged.is_clean());
+    assert_eq!(tile_path(&merged.map, (0, 1)), "/turf/floor");
+}
@@ -0,0 +1,15 @@
+extern crate dmm_tools;
+
+use dmm_tools::render_passes::{configure, unknown_names, RENDER_PASSES};
+
+#[test]
+fn pass_names() {
+    assert!(unknown_names("").is_empty());
+    assert!(unknown_names("all,hide-space,random").is_empty());
+    assert_eq!(unknown_names("hide-space,hide_space,randoms"), vec!["hide_space", "randoms"]);
+
+    let defaults = RENDER_PASSES.iter().filter(|pass| pass.default).count();
+    assert_eq!(configure("", "").len(), defaults);
+    assert_eq!(configure("", "hide-space,random").len(), defaults - 2);
+    assert_eq!(configure("only-powernet", "all").len(), 1);
+}
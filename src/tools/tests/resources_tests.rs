@@ -0,0 +1,100 @@
+extern crate dreammaker as dm;
+extern crate dmm_tools;
+
+use std::fs;
+
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::resources::*;
+use dmm_tools::dmi::*;
+
+const DME: &str = r##"
+#define FILE_DIR .
+#define FILE_DIR "icons"
+
+/obj/thing
+	icon = 'thing.dmi'
+	icon_state = "red"
+
+/obj/thing/wrong
+	icon_state = "blue"
+
+/obj/thing/facing
+	icon_state = "arrow"
+	dir = 6
+
+/obj/thing/proc/update_icon()
+	icon_state = "green"
+	var/icon/I = icon('thing.dmi', "red", 2, 3)
+	I = icon('thing.dmi', "arrow", 4, 1)
+
+/obj/absent
+	icon = 'absent.dmi'
+
+/obj/proc/beep()
+	world << 'sound/beep.ogg'
+	world << 'sound/boop.ogg'
+"##;
+
+#[test]
+fn missing_resources_and_icon_states() {
+    let root = std::env::temp_dir().join("spacemandmm_resources_tests");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("icons")).unwrap();
+    fs::create_dir_all(root.join("sound")).unwrap();
+    fs::write(root.join("test.dme"), DME).unwrap();
+    fs::write(root.join("sound/beep.ogg"), "").unwrap();
+
+    let mut builder = IconBuilder::new(2, 2);
+    builder.add_state(NewState::still("red", Image::new_rgba(2, 2))).unwrap();
+    builder.add_state(NewState {
+        dirs: Dirs::Four,
+        frames: vec![(0..4).map(|_| Image::new_rgba(2, 2)).collect()],
+        ..NewState::still("arrow", Image::new_rgba(2, 2))
+    }).unwrap();
+    builder.build().to_file(&root.join("icons/thing.dmi")).unwrap();
+
+    let context = dm::Context::default();
+    let mut preprocessor = Preprocessor::new(&context, root.join("test.dme")).unwrap();
+    let tree = {
+        let mut parser = Parser::new(&context, IndentProcessor::new(&context, &mut preprocessor));
+        parser.enable_procs();
+        parser.parse_object_tree()
+    };
+    context.assert_success();
+
+    let file_dirs = file_dirs(preprocessor.defines());
+    assert_eq!(file_dirs, vec![std::path::PathBuf::from("."), "icons".into()]);
+
+    let mut checker = ResourceChecker::new(&root, &file_dirs);
+    assert_eq!(checker.resolve("thing.dmi"), Some(root.join("icons/thing.dmi")));
+    assert_eq!(checker.resolve("sound\\beep.ogg"), Some(root.join("sound/beep.ogg")));
+    checker.check_icon_states(true);
+    checker.check(&context, &tree);
+
+    let mut errors: Vec<String> = context.errors().iter().map(|e| e.description().to_owned()).collect();
+    errors.sort();
+    assert_eq!(errors, vec![
+        "/obj/proc/beep: missing resource 'sound/boop.ogg'",
+        "/obj/thing/facing: icon_state \"arrow\" in 'thing.dmi' has no dir 6",
+        "/obj/thing/proc/update_icon: icon_state \"green\" not found in 'thing.dmi'",
+        "/obj/thing/proc/update_icon: icon_state \"red\" in 'thing.dmi' has no frame 3",
+        "/obj/thing/wrong: icon_state \"blue\" not found in 'thing.dmi'",
+        "icon 'thing.dmi' has no state \"blue\"",
+        "icon 'thing.dmi' has no state \"green\"",
+        "missing resource 'absent.dmi'",
+        "state \"arrow\" has 4 dirs",
+        "state \"red\" has 1 frames",
+    ]);
+
+    // the notes point at the icon itself
+    let notes: Vec<_> = context.errors().iter()
+        .filter(|e| e.severity() == dm::Severity::Info)
+        .map(|e| context.file_path(e.location().file))
+        .collect();
+    assert_eq!(notes.len(), 4);
+    assert!(notes.iter().all(|path| *path == root.join("icons/thing.dmi")));
+
+    fs::remove_dir_all(&root).unwrap();
+}
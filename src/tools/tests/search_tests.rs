@@ -0,0 +1,86 @@
+extern crate dreammaker as dm;
+extern crate dmm_tools;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::constants::Constant;
+use dmm_tools::coords::Coord3;
+use dmm_tools::dmm::{Map, Prefab};
+use dmm_tools::search::{self, Op, Query, VarConstraint};
+
+#[test]
+fn find_subtypes_and_vars() {
+    let code = r##"
+/obj/machinery
+    var/pixel_x = 0
+
+/obj/machinery/light
+
+/obj/item
+    parent_type = /obj/machinery
+"##.trim();
+
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    context.assert_success();
+
+    let mut map = Map::new(2, 2, 1, "/turf".to_owned(), "/area".to_owned());
+    let mut light = Prefab::from_path("/obj/machinery/light");
+    light.vars.insert("pixel_x".to_owned(), Constant::Int(-32));
+    let key = map.dictionary.keys().next().unwrap().next();
+    map.dictionary.insert(key, vec![light, Prefab::from_path("/turf"), Prefab::from_path("/area")]);
+    map.grid[(0, 1, 0)] = key;
+    let key = key.next();
+    map.dictionary.insert(key, vec![Prefab::from_path("/obj/item"), Prefab::from_path("/turf"), Prefab::from_path("/area")]);
+    map.grid[(0, 0, 1)] = key;
+
+    let query = Query {
+        path: Some("/obj/machinery".to_owned()),
+        .. Default::default()
+    };
+    assert_eq!(search::find(&tree, &map, &query), vec![Coord3::new(1, 1, 1), Coord3::new(2, 2, 1)]);
+
+    let query = Query {
+        path: Some("/obj/machinery".to_owned()),
+        exact: false,
+        constraints: vec!["pixel_x != 0".parse::<VarConstraint>().unwrap()],
+    };
+    assert_eq!(search::find(&tree, &map, &query), vec![Coord3::new(1, 1, 1)]);
+
+    let query = Query {
+        constraints: vec!["pixel_x >= 0".parse::<VarConstraint>().unwrap()],
+        .. Default::default()
+    };
+    assert_eq!(search::find(&tree, &map, &query), vec![Coord3::new(2, 2, 1)]);
+
+    // several constraints on one var must all hold
+    let query = Query {
+        constraints: vec![
+            "pixel_x < 0".parse::<VarConstraint>().unwrap(),
+            "pixel_x > -40".parse::<VarConstraint>().unwrap(),
+        ],
+        .. Default::default()
+    };
+    assert_eq!(search::find(&tree, &map, &query), vec![Coord3::new(1, 1, 1)]);
+}
+
+#[test]
+fn parse_constraints() {
+    let c: VarConstraint = "dir == 4".parse().unwrap();
+    assert_eq!(c.var, "dir");
+    assert_eq!(c.value, Constant::Int(4));
+    assert!(c.test(&Constant::Int(4)));
+    assert!("== 4".parse::<VarConstraint>().is_err());
+    assert!("dir".parse::<VarConstraint>().is_err());
+    assert!("di-r == 4".parse::<VarConstraint>().is_err());
+
+    // operators inside the value are part of the value
+    let c: VarConstraint = r#"desc != "a==b""#.parse().unwrap();
+    assert_eq!(c.var, "desc");
+    assert_eq!(c.op, Op::Ne);
+    assert_eq!(c.value, Constant::String("a==b".into()));
+    let c: VarConstraint = "pixel_x<=-32".parse().unwrap();
+    assert_eq!((c.var.as_str(), c.op), ("pixel_x", Op::Le));
+    assert_eq!(c.value, Constant::Int(-32));
+}
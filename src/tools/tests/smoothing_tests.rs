@@ -0,0 +1,115 @@
+extern crate dreammaker as dm;
+extern crate dmm_tools;
+
+use dm::lexer::Lexer;
+use dm::indents::IndentProcessor;
+use dm::objtree::ObjectTree;
+use dmm_tools::coords::{Coord2, Coord3};
+use dmm_tools::dmm::{Map, Prefab};
+use dmm_tools::minimap::{Atom, Context, GetVar};
+use dmm_tools::render_passes::RenderPass;
+use dmm_tools::render_passes::smoothing::BitmaskSmoothing;
+
+const CODE: &str = r##"
+/atom
+    var/smoothing_flags = 0
+    var/smoothing_groups
+    var/canSmoothWith
+    var/base_icon_state
+
+/turf/floor
+
+/turf/wall
+    icon_state = "wall"
+    smoothing_flags = 2
+    smoothing_groups = "walls"
+
+/turf/wall/border
+    base_icon_state = "border"
+    smoothing_flags = 10
+
+/turf/wall/mixed
+    base_icon_state = "mixed"
+    canSmoothWith = "walls, windows"
+
+/obj/window
+    smoothing_groups = list("windows")
+
+/obj/grille
+    smoothing_groups = list("grilles")
+"##;
+
+fn parse() -> ObjectTree {
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), CODE.trim().bytes().map(Ok));
+    let tree = dm::parser::parse(&context, IndentProcessor::new(&context, lexer));
+    context.assert_success();
+    tree
+}
+
+fn smooth(tree: &ObjectTree, map: &Map, path: &str, loc: Coord2) -> String {
+    let render_passes: Vec<Box<RenderPass>> = Vec::new();
+    let (dim_x, dim_y, _) = map.dim_xyz();
+    let ctx = Context {
+        objtree: tree,
+        map,
+        grid: map.z_level(0),
+        min: Coord2::new(1, 1),
+        max: Coord2::new(dim_x as i32, dim_y as i32),
+        render_passes: &render_passes,
+    };
+
+    let atom = Atom::from_type(tree, path, loc).unwrap();
+    let mut output = Vec::new();
+    assert!(BitmaskSmoothing.smooth(&atom, ctx, &mut output));
+    assert_eq!(output.len(), 1);
+    match *output[0].get_var("icon_state", tree) {
+        dm::constants::Constant::String(ref state) => state.clone(),
+        ref other => panic!("bad icon_state {:?}", other),
+    }
+}
+
+#[test]
+fn junctions_at_map_edges() {
+    let tree = parse();
+    let mut map = Map::new(2, 2, 1, "/turf/wall".to_owned(), "/area".to_owned());
+
+    // the map edge only counts with the border flag
+    assert_eq!(smooth(&tree, &map, "/turf/wall", Coord2::new(1, 1)), "wall-21");
+    assert_eq!(smooth(&tree, &map, "/turf/wall", Coord2::new(2, 2)), "wall-74");
+
+    map.fill(Coord3::new(1, 1, 1), Coord3::new(2, 2, 1), vec![Prefab::from_path("/turf/wall/border"), Prefab::from_path("/area")]).unwrap();
+    map.set_tile(Coord3::new(2, 1, 1), vec![Prefab::from_path("/turf/floor"), Prefab::from_path("/area")]).unwrap();
+    // west and south are the edge and east is the floor; the diagonals
+    // need both of their cardinals
+    assert_eq!(smooth(&tree, &map, "/turf/wall/border", Coord2::new(1, 1)), "border-203");
+    // everything but the floor to the south smooths
+    assert_eq!(smooth(&tree, &map, "/turf/wall/border", Coord2::new(2, 2)), "border-157");
+}
+
+#[test]
+fn mixed_smoothing_groups() {
+    let tree = parse();
+    let mut map = Map::new(3, 3, 1, "/turf/floor".to_owned(), "/area".to_owned());
+    let tile = |path: &str| vec![
+        Prefab::from_path(path),
+        Prefab::from_path("/turf/floor"),
+        Prefab::from_path("/area"),
+    ];
+    // walls to the north, windows to the east and northeast, a grille to
+    // the west, and plain floor to the south
+    map.set_tile(Coord3::new(2, 3, 1), tile("/turf/wall")).unwrap();
+    map.set_tile(Coord3::new(3, 2, 1), tile("/obj/window")).unwrap();
+    map.set_tile(Coord3::new(3, 3, 1), tile("/obj/window")).unwrap();
+    map.set_tile(Coord3::new(1, 2, 1), tile("/obj/grille")).unwrap();
+    map.set_tile(Coord3::new(1, 3, 1), tile("/turf/wall")).unwrap();
+
+    // north + east + northeast; the grille is not in canSmoothWith, so the
+    // northwest wall does not count either
+    assert_eq!(smooth(&tree, &map, "/turf/wall/mixed", Coord2::new(2, 2)), "mixed-21");
+
+    // without canSmoothWith, only the exact same type smooths
+    assert_eq!(smooth(&tree, &map, "/turf/wall", Coord2::new(2, 2)), "wall-1");
+    map.set_tile(Coord3::new(2, 2, 1), tile("/turf/wall/mixed")).unwrap();
+    assert_eq!(smooth(&tree, &map, "/turf/wall", Coord2::new(2, 1)), "wall-0");
+}